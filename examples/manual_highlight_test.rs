@@ -1,6 +1,6 @@
-/// 手動測試語法高亮功能
-///
-/// 執行：cargo run --example manual_highlight_test
+//! 手動測試語法高亮功能
+//!
+//! 執行：cargo run --example manual_highlight_test
 
 #[cfg(feature = "syntax-highlighting")]
 use wedi::highlight::{supports_true_color, HighlightConfig, HighlightEngine};
@@ -75,9 +75,7 @@ fn test_language(lang_name: &str, file_path: &str) {
     for (i, line) in content.lines().enumerate() {
         // ⚠️ 重要：syntect 需要換行符才能正確解析語法狀態
         let line_with_newline = format!("{}\n", line);
-        let highlighted = highlighter.highlight_line(&line_with_newline);
-        // 移除尾部的換行符以避免多餘空行
-        let highlighted = highlighted.trim_end_matches('\n');
+        let highlighted = highlighter.highlight_line(&line_with_newline).to_ansi();
         println!("{:3}: {}", i + 1, highlighted);
     }
 }
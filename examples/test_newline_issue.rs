@@ -1,6 +1,6 @@
-/// 測試換行符對語法高亮的影響
-///
-/// 執行：cargo run --example test_newline_issue
+//! 測試換行符對語法高亮的影響
+//!
+//! 執行：cargo run --example test_newline_issue
 
 #[cfg(feature = "syntax-highlighting")]
 use wedi::highlight::{HighlightConfig, HighlightEngine};
@@ -23,7 +23,7 @@ fn main() {
     // 測試 Bash 語法
     engine.set_file(Some(Path::new("test.sh")));
 
-    let test_code = vec![
+    let test_code = [
         "#!/bin/bash",
         "# This is a comment",
         "echo \"Hello World\"",
@@ -36,7 +36,7 @@ fn main() {
     println!("{}", "-".repeat(60));
     let mut highlighter1 = engine.create_highlighter().unwrap();
     for (i, line) in test_code.iter().enumerate() {
-        let highlighted = highlighter1.highlight_line(line);
+        let highlighted = highlighter1.highlight_line(line).to_ansi();
         println!("{:2}: {}", i + 1, highlighted);
     }
 
@@ -45,7 +45,7 @@ fn main() {
     let mut highlighter2 = engine.create_highlighter().unwrap();
     for (i, line) in test_code.iter().enumerate() {
         let line_with_newline = format!("{}\n", line);
-        let highlighted = highlighter2.highlight_line(&line_with_newline);
+        let highlighted = highlighter2.highlight_line(&line_with_newline).to_ansi();
         println!("{:2}: {}", i + 1, highlighted);
     }
 
@@ -53,7 +53,7 @@ fn main() {
     println!("\n5. 測試跨行語法狀態（多行註解）...");
     let mut highlighter2 = HighlightLines::new(rust_syntax, theme);
 
-    let multiline = vec!["/* 開始註解", "   中間", "   結束 */", "fn test() {}"];
+    let multiline = ["/* 開始註解", "   中間", "   結束 */", "fn test() {}"];
 
     for (i, line) in multiline.iter().enumerate() {
         let ranges = highlighter2
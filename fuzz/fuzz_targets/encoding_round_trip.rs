@@ -0,0 +1,61 @@
+//! 編碼往返的 fuzz 目標
+//!
+//! 任意位元組經過 BOM/UTF-8 偵測、解碼與存檔編碼都不能 panic；
+//! 有效的 UTF-8（不論有沒有 BOM）存檔後必須與輸入完全相同，
+//! 以 Unicode 編碼存檔的內容重新讀取後文字必須不變
+//!
+//! 執行：`cargo +nightly fuzz run encoding_round_trip`
+
+#![no_main]
+
+use encoding_rs::Encoding;
+use libfuzzer_sys::fuzz_target;
+use wedi::{EncodingConfig, RopeBuffer};
+
+/// 沒有偵測到 BOM 或 UTF-8 時使用的讀取編碼，由第一個位元組選擇
+const READ_ENCODINGS: [&Encoding; 8] = [
+    encoding_rs::UTF_8,
+    encoding_rs::UTF_16LE,
+    encoding_rs::UTF_16BE,
+    encoding_rs::WINDOWS_1252,
+    encoding_rs::GBK,
+    encoding_rs::BIG5,
+    encoding_rs::SHIFT_JIS,
+    encoding_rs::EUC_KR,
+];
+
+fn is_unicode(encoding: &'static Encoding) -> bool {
+    encoding == encoding_rs::UTF_8
+        || encoding == encoding_rs::UTF_16LE
+        || encoding == encoding_rs::UTF_16BE
+}
+
+fuzz_target!(|data: &[u8]| {
+    let Some((&selector, bytes)) = data.split_first() else {
+        return;
+    };
+    let read_encoding = READ_ENCODINGS[selector as usize % READ_ENCODINGS.len()];
+    let mut config = EncodingConfig::default();
+    config.read_encoding = Some(read_encoding);
+
+    let buffer = RopeBuffer::from_bytes_with_encoding(bytes, &config);
+    let text = buffer.text();
+    let encoded = buffer.encode_contents("fuzz");
+
+    // 有效的 UTF-8 以 UTF-8 存檔時原樣寫回
+    // （指定的讀取編碼即使被偵測結果略過，仍會作為存檔編碼）
+    let save_encoding = buffer.save_encoding();
+    let without_bom = bytes.strip_prefix(b"\xEF\xBB\xBF").unwrap_or(bytes);
+    if save_encoding == encoding_rs::UTF_8 && std::str::from_utf8(without_bom).is_ok() {
+        assert_eq!(encoded, bytes);
+    }
+
+    // Unicode 編碼可以表示任何文字（包括解碼時的取代字元），重新讀取必須得到相同的文字
+    if is_unicode(save_encoding) {
+        let mut config = EncodingConfig::default();
+        config.read_encoding = Some(save_encoding);
+        let reloaded = RopeBuffer::from_bytes_with_encoding(&encoded, &config);
+        assert_eq!(reloaded.text(), text);
+        assert_eq!(reloaded.encode_contents("fuzz"), encoded);
+    }
+});
@@ -0,0 +1,248 @@
+// 文字分析
+//
+// `:analyze` 統計緩衝區中最常出現的單字與行，並標出完全相同的重複行，
+// 方便直接在編輯器中整理設定檔與字詞清單。結果以選單顯示，選取項目時跳到對應的行。
+// `:count`（Alt+S）則像 `wc` 一樣計算整個檔案與選擇範圍的行數、單字數、字符數與位元組數
+
+use std::collections::HashMap;
+
+/// 最多列出的常見單字數
+const TOP_WORDS: usize = 20;
+
+/// 出現多次的行（內容完全相同，忽略空白行）
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DuplicateLine {
+    pub text: String,
+    pub rows: Vec<usize>, // 所有出現的行（0-based，遞增）
+}
+
+/// 常見單字與第一次出現的行
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WordCount {
+    pub word: String,
+    pub count: usize,
+    pub first_row: usize,
+}
+
+/// 分析結果
+#[derive(Debug, Default)]
+pub struct Analysis {
+    pub line_count: usize,
+    pub word_total: usize,
+    pub duplicates: Vec<DuplicateLine>, // 依出現次數遞減，同次數時依第一次出現的位置
+    pub words: Vec<WordCount>,          // 依出現次數遞減，最多 TOP_WORDS 個
+}
+
+/// 選單中的一個項目；`row` 為 None 的是分組標題
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub label: String,
+    pub row: Option<usize>,
+}
+
+/// 分析各行內容（不含換行符號）
+pub fn analyze<I, S>(lines: I) -> Analysis
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    let mut analysis = Analysis::default();
+    let mut line_rows: HashMap<String, Vec<usize>> = HashMap::new();
+    let mut word_counts: HashMap<String, (usize, usize)> = HashMap::new();
+
+    for (row, line) in lines.into_iter().enumerate() {
+        let line = line.as_ref();
+        analysis.line_count += 1;
+        if !line.trim().is_empty() {
+            line_rows.entry(line.to_string()).or_default().push(row);
+        }
+        for word in words(line) {
+            analysis.word_total += 1;
+            word_counts.entry(word.to_string()).or_insert((0, row)).0 += 1;
+        }
+    }
+
+    analysis.duplicates = line_rows
+        .into_iter()
+        .filter(|(_, rows)| rows.len() > 1)
+        .map(|(text, rows)| DuplicateLine { text, rows })
+        .collect();
+    analysis.duplicates.sort_by(|a, b| {
+        b.rows
+            .len()
+            .cmp(&a.rows.len())
+            .then(a.rows[0].cmp(&b.rows[0]))
+    });
+
+    analysis.words = word_counts
+        .into_iter()
+        .map(|(word, (count, first_row))| WordCount {
+            word,
+            count,
+            first_row,
+        })
+        .collect();
+    analysis.words.sort_by(|a, b| {
+        b.count
+            .cmp(&a.count)
+            .then(a.first_row.cmp(&b.first_row))
+            .then(a.word.cmp(&b.word))
+    });
+    analysis.words.truncate(TOP_WORDS);
+    analysis
+}
+
+/// 一行中的單字：連續的字母、數字與底線
+fn words(line: &str) -> impl Iterator<Item = &str> {
+    line.split(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .filter(|word| !word.is_empty())
+}
+
+impl Analysis {
+    /// 重複行的多餘份數（刪除後只剩每組一行時會少掉的行數）
+    pub fn extra_copies(&self) -> usize {
+        self.duplicates.iter().map(|dup| dup.rows.len() - 1).sum()
+    }
+
+    /// 狀態列摘要
+    pub fn summary(&self) -> String {
+        format!(
+            "{} lines, {} words, {} duplicated lines ({} extra copies)",
+            self.line_count,
+            self.word_total,
+            self.duplicates.len(),
+            self.extra_copies()
+        )
+    }
+
+    /// 選單項目：重複行（每組之後列出其餘的出現位置）與常見單字
+    pub fn entries(&self) -> Vec<Entry> {
+        let header = |label: String| Entry { label, row: None };
+        let mut entries = vec![header(format!(
+            "Duplicate lines: {} ({} extra copies)",
+            self.duplicates.len(),
+            self.extra_copies()
+        ))];
+        for dup in &self.duplicates {
+            let first = dup.rows[0];
+            entries.push(Entry {
+                label: format!("  {:>4}x  line {}: {}", dup.rows.len(), first + 1, dup.text),
+                row: Some(first),
+            });
+            for &row in &dup.rows[1..] {
+                entries.push(Entry {
+                    label: format!("         line {}: duplicate of line {}", row + 1, first + 1),
+                    row: Some(row),
+                });
+            }
+        }
+        entries.push(header(format!(
+            "Most frequent words ({} total)",
+            self.word_total
+        )));
+        for word in &self.words {
+            entries.push(Entry {
+                label: format!("  {:>4}x  {}", word.count, word.word),
+                row: Some(word.first_row),
+            });
+        }
+        entries
+    }
+}
+
+/// `wc` 風格的計數；單字為以空白分隔的片段
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Counts {
+    pub lines: usize,
+    pub words: usize,
+    pub chars: usize,
+    in_word: bool,
+    line_open: bool, // 最後一行還沒有以換行結尾
+}
+
+impl Counts {
+    /// 計算分成多個片段的文字（例如 Rope 的 chunks），單字可以跨越片段
+    pub fn of<'a>(chunks: impl IntoIterator<Item = &'a str>) -> Self {
+        let mut counts = Self::default();
+        for chunk in chunks {
+            for ch in chunk.chars() {
+                counts.chars += 1;
+                if ch == '\n' {
+                    counts.lines += 1;
+                    counts.line_open = false;
+                } else {
+                    counts.line_open = true;
+                }
+                let in_word = !ch.is_whitespace();
+                if in_word && !counts.in_word {
+                    counts.words += 1;
+                }
+                counts.in_word = in_word;
+            }
+        }
+        // 沒有以換行結尾的最後一行也算一行
+        counts.lines += usize::from(counts.line_open);
+        counts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_analyze_duplicates_and_words() {
+        let text = "alpha\nbeta\n\nalpha\ngamma beta\n\nalpha\nbeta\n";
+        let analysis = analyze(text.lines());
+        assert_eq!(analysis.line_count, 8);
+        assert_eq!(analysis.word_total, 7);
+        // 空白行不算重複
+        assert_eq!(
+            analysis.duplicates,
+            vec![
+                DuplicateLine {
+                    text: "alpha".to_string(),
+                    rows: vec![0, 3, 6]
+                },
+                DuplicateLine {
+                    text: "beta".to_string(),
+                    rows: vec![1, 7]
+                },
+            ]
+        );
+        assert_eq!(analysis.extra_copies(), 3);
+        let words: Vec<_> = analysis
+            .words
+            .iter()
+            .map(|w| (w.word.as_str(), w.count, w.first_row))
+            .collect();
+        assert_eq!(
+            words,
+            vec![("alpha", 3, 0), ("beta", 3, 1), ("gamma", 1, 4)]
+        );
+
+        let entries = analysis.entries();
+        assert_eq!(entries[0].row, None);
+        assert_eq!(entries[1].row, Some(0));
+        assert_eq!(entries[2].label, "         line 4: duplicate of line 1");
+        assert_eq!(entries[2].row, Some(3));
+        assert!(entries
+            .iter()
+            .any(|e| e.row.is_none() && e.label.starts_with("Most frequent")));
+    }
+
+    #[test]
+    fn test_words_split_on_punctuation() {
+        let found: Vec<_> = words("key_1 = \"值\", other-key;").collect();
+        assert_eq!(found, vec!["key_1", "值", "other", "key"]);
+    }
+
+    #[test]
+    fn test_counts_across_chunks() {
+        let counts = Counts::of(["hello wo", "rld\n中文 ", "\t", "x"]);
+        assert_eq!((counts.lines, counts.words, counts.chars), (2, 4, 17));
+        let counts = Counts::of(["one\n", "two\n"]);
+        assert_eq!((counts.lines, counts.words, counts.chars), (2, 2, 8));
+        assert_eq!(Counts::of([""]).lines, 0);
+    }
+}
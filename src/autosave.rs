@@ -0,0 +1,166 @@
+// 自動存檔
+//
+// 停止編輯一段時間後自動存檔。可以直接寫回原檔，或只在同目錄寫入
+// `.檔名.wedi-recovery` 復原檔（原檔不變，正常存檔或離開時刪除）。
+// 異常結束留下的復原檔會在下次開啟同一檔案時提示。
+
+use anyhow::{bail, Result};
+use ropey::Rope;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// 最後一次修改後閒置多久才自動存檔
+pub const AUTOSAVE_DELAY: Duration = Duration::from_secs(2);
+
+/// 自動存檔的方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AutosavePolicy {
+    /// 不自動存檔
+    #[default]
+    Off,
+    /// 直接寫回原檔
+    File,
+    /// 只寫入復原檔
+    Recovery,
+}
+
+impl FromStr for AutosavePolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "off" | "no" | "false" => Ok(Self::Off),
+            "file" | "on" => Ok(Self::File),
+            "recovery" | "recover" => Ok(Self::Recovery),
+            _ => bail!("Invalid autosave policy: {} (use off, file or recovery)", s),
+        }
+    }
+}
+
+impl AutosavePolicy {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Off => "off",
+            Self::File => "file",
+            Self::Recovery => "recovery",
+        }
+    }
+}
+
+/// 取得檔案對應的復原檔路徑
+pub fn recovery_path_for(file_path: &Path) -> Option<PathBuf> {
+    let name = file_path.file_name()?.to_str()?;
+    let dir = file_path.parent().unwrap_or(Path::new(""));
+    Some(dir.join(format!(".{}.wedi-recovery", name)))
+}
+
+/// 寫入復原檔
+pub fn write_recovery(file_path: &Path, text: &str) -> Result<()> {
+    let Some(recovery_path) = recovery_path_for(file_path) else {
+        bail!("Invalid file path: {}", file_path.display());
+    };
+    fs::write(recovery_path, text)?;
+    Ok(())
+}
+
+/// 刪除復原檔（不存在時不做任何事）
+pub fn remove_recovery(file_path: &Path) {
+    if let Some(recovery_path) = recovery_path_for(file_path) {
+        let _ = fs::remove_file(recovery_path);
+    }
+}
+
+/// 之前留下的復原檔（存在時返回路徑）
+pub fn existing_recovery(file_path: &Path) -> Option<PathBuf> {
+    recovery_path_for(file_path).filter(|path| path.is_file())
+}
+
+/// 收到終止訊號時的緊急副本：有未存檔修改的檔案與內容
+///
+/// 主迴圈在每次按鍵後更新；終端掛斷後主迴圈可能卡在讀取按鍵，
+/// 這時由等待訊號的執行緒取走副本寫成復原檔。取走後（`Claimed`）不再更新
+enum EmergencyCopy {
+    Ready(Option<(PathBuf, Rope)>),
+    Claimed,
+}
+
+static EMERGENCY_COPY: Mutex<EmergencyCopy> = Mutex::new(EmergencyCopy::Ready(None));
+
+/// 更新緊急副本（沒有未存檔修改時為 None）
+pub fn set_emergency_copy(copy: Option<(PathBuf, Rope)>) {
+    let mut state = EMERGENCY_COPY.lock().unwrap_or_else(|e| e.into_inner());
+    if let EmergencyCopy::Ready(current) = &mut *state {
+        *current = copy;
+    }
+}
+
+/// 取走緊急副本，由呼叫端負責結束前的保存；已被取走時返回 None
+pub fn claim_emergency_copy() -> Option<Option<(PathBuf, Rope)>> {
+    let mut state = EMERGENCY_COPY.lock().unwrap_or_else(|e| e.into_inner());
+    match std::mem::replace(&mut *state, EmergencyCopy::Claimed) {
+        EmergencyCopy::Ready(copy) => Some(copy),
+        EmergencyCopy::Claimed => None,
+    }
+}
+
+/// 寫入緊急副本的復原檔，返回復原檔路徑
+pub fn write_emergency_copy(path: &Path, text: &str) -> Option<PathBuf> {
+    write_recovery(path, text).ok()?;
+    recovery_path_for(path)
+}
+
+/// 因訊號結束時顯示在終端的說明；沒有未存檔修改時為 None
+pub fn termination_message(signal: i32, recovery: Option<&Path>, modified: bool) -> Option<String> {
+    match recovery {
+        Some(path) => Some(format!(
+            "wedi: terminated by signal {}; unsaved changes were written to {}",
+            signal,
+            path.display()
+        )),
+        None if modified => Some(format!(
+            "wedi: terminated by signal {}; unsaved changes were lost",
+            signal
+        )),
+        None => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_policy_and_recovery_file() {
+        assert_eq!(
+            "Recovery".parse::<AutosavePolicy>().unwrap(),
+            AutosavePolicy::Recovery
+        );
+        assert_eq!(
+            "file".parse::<AutosavePolicy>().unwrap(),
+            AutosavePolicy::File
+        );
+        assert_eq!(
+            "off".parse::<AutosavePolicy>().unwrap(),
+            AutosavePolicy::Off
+        );
+        assert!("sometimes".parse::<AutosavePolicy>().is_err());
+
+        let temp_dir = TempDir::new().unwrap();
+        let file_path = temp_dir.path().join("notes.txt");
+        assert_eq!(
+            recovery_path_for(&file_path).unwrap(),
+            temp_dir.path().join(".notes.txt.wedi-recovery")
+        );
+
+        assert_eq!(existing_recovery(&file_path), None);
+        write_recovery(&file_path, "draft").unwrap();
+        let recovery_path = existing_recovery(&file_path).unwrap();
+        assert_eq!(fs::read_to_string(recovery_path).unwrap(), "draft");
+        remove_recovery(&file_path);
+        assert_eq!(existing_recovery(&file_path), None);
+    }
+}
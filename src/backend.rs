@@ -0,0 +1,626 @@
+// 終端後端
+//
+// View 透過 TerminalBackend 讀取按鍵、取得大小並輸出繪圖指令（crossterm 產生的 ANSI 序列），
+// 實際終端由 Terminal 實作；MemoryTerminal 在記憶體中解譯輸出並記錄每一幀，
+// 不需要真正的 TTY 就能測試渲染、捲動與選擇
+
+use crate::terminal::Terminal;
+use anyhow::{bail, Result};
+use crossterm::event::KeyEvent;
+use std::collections::{BTreeSet, VecDeque};
+use std::io::{self, Write};
+use std::time::Duration;
+use unicode_width::UnicodeWidthChar;
+
+/// 終端後端：按鍵輸入、終端大小與繪圖輸出
+///
+/// 繪圖指令以 [`Write`] 寫入（例如 `queue!(backend, cursor::MoveTo(0, 0))`），
+/// 一幀的輸出包在 [`TerminalBackend::begin_frame`] 與 [`TerminalBackend::end_frame`] 之間
+#[allow(dead_code)]
+pub trait TerminalBackend: Write {
+    /// 終端大小（欄數, 列數）
+    fn size(&self) -> (u16, u16);
+
+    /// 等待下一個按鍵
+    fn read_key(&mut self) -> Result<KeyEvent>;
+
+    /// 等待下一個按鍵，背景工作要求喚醒時返回 None（見 [`Terminal::wake`]）
+    fn read_key_or_wake(&mut self) -> Result<Option<KeyEvent>> {
+        self.read_key().map(Some)
+    }
+
+    /// 在 timeout 內等待按鍵，逾時返回 None
+    fn poll_key(&mut self, timeout: Duration) -> Result<Option<KeyEvent>>;
+
+    /// 開始輸出一幀
+    fn begin_frame(&mut self) -> Result<()>;
+
+    /// 結束一幀並送出，游標顯示在指定位置（None 保持隱藏）
+    fn end_frame(&mut self, cursor_position: Option<(u16, u16)>) -> Result<()>;
+
+    /// 取得並重置畫面覆寫標記，返回 true 時下一幀需完整重繪
+    fn take_screen_invalidated(&mut self) -> bool;
+}
+
+impl Write for Terminal {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        Terminal::output().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Terminal::output().flush()
+    }
+}
+
+impl TerminalBackend for Terminal {
+    fn size(&self) -> (u16, u16) {
+        Terminal::size(self)
+    }
+
+    fn read_key(&mut self) -> Result<KeyEvent> {
+        Terminal::read_key()
+    }
+
+    fn read_key_or_wake(&mut self) -> Result<Option<KeyEvent>> {
+        Terminal::read_key_or_wake()
+    }
+
+    fn poll_key(&mut self, timeout: Duration) -> Result<Option<KeyEvent>> {
+        Terminal::poll_key(timeout)
+    }
+
+    fn begin_frame(&mut self) -> Result<()> {
+        Terminal::begin_frame()
+    }
+
+    fn end_frame(&mut self, cursor_position: Option<(u16, u16)>) -> Result<()> {
+        Terminal::end_frame(cursor_position)
+    }
+
+    fn take_screen_invalidated(&mut self) -> bool {
+        Terminal::take_screen_invalidated()
+    }
+}
+
+/// 畫面上的一格
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Cell {
+    ch: char, // 寬字元的第二格為 '\0'
+    reverse: bool,
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        Self {
+            ch: ' ',
+            reverse: false,
+        }
+    }
+}
+
+/// MemoryTerminal 記錄的一幀畫面
+#[allow(dead_code)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    cells: Vec<Vec<Cell>>,
+    cursor: Option<(u16, u16)>,
+    updated_rows: Vec<usize>,
+}
+
+#[allow(dead_code)]
+impl Frame {
+    /// 指定螢幕列的文字（去掉行尾空白）
+    pub fn line(&self, row: usize) -> String {
+        self.cells
+            .get(row)
+            .map(|cells| {
+                let text: String = cells.iter().map(|c| c.ch).filter(|&c| c != '\0').collect();
+                text.trim_end().to_string()
+            })
+            .unwrap_or_default()
+    }
+
+    /// 所有螢幕列的文字
+    pub fn lines(&self) -> Vec<String> {
+        (0..self.cells.len()).map(|row| self.line(row)).collect()
+    }
+
+    /// 指定螢幕列中反白（選擇範圍）的文字
+    pub fn reversed_text(&self, row: usize) -> String {
+        self.cells
+            .get(row)
+            .map(|cells| {
+                cells
+                    .iter()
+                    .filter(|c| c.reverse && c.ch != '\0')
+                    .map(|c| c.ch)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// 一幀結束時顯示的游標位置（欄, 列），游標隱藏時為 None
+    pub fn cursor(&self) -> Option<(u16, u16)> {
+        self.cursor
+    }
+
+    /// 這一幀有輸出文字或清除過的螢幕列（由小到大）
+    pub fn updated_rows(&self) -> &[usize] {
+        &self.updated_rows
+    }
+}
+
+/// 記憶體中的終端，用於測試
+///
+/// 按鍵事先以 [`MemoryTerminal::push_key`] 排入；輸出的 ANSI 序列只解譯畫面內容需要的部分
+/// （游標移動、清除、反白），每次 `end_frame` 記錄一幀
+#[allow(dead_code)]
+pub struct MemoryTerminal {
+    size: (u16, u16),
+    keys: VecDeque<KeyEvent>,
+    screen: Vec<Vec<Cell>>,
+    position: (usize, usize), // 目前的輸出位置（欄, 列）
+    reverse: bool,
+    pending: Vec<u8>,              // 尚未解譯的輸出
+    updated_rows: BTreeSet<usize>, // 目前這一幀輸出過的列
+    frames: Vec<Frame>,
+    invalidated: bool,
+}
+
+#[allow(dead_code)]
+impl MemoryTerminal {
+    pub fn new(cols: u16, rows: u16) -> Self {
+        Self {
+            size: (cols, rows),
+            keys: VecDeque::new(),
+            screen: vec![vec![Cell::default(); cols as usize]; rows as usize],
+            position: (0, 0),
+            reverse: false,
+            pending: Vec::new(),
+            updated_rows: BTreeSet::new(),
+            frames: Vec::new(),
+            invalidated: false,
+        }
+    }
+
+    /// 排入一個按鍵，供 `read_key` 依序讀取
+    pub fn push_key(&mut self, key: KeyEvent) {
+        self.keys.push_back(key);
+    }
+
+    /// 改變終端大小並清除畫面（與實際終端相同，下一幀需完整重繪）
+    pub fn resize(&mut self, cols: u16, rows: u16) {
+        self.size = (cols, rows);
+        self.screen = vec![vec![Cell::default(); cols as usize]; rows as usize];
+        self.invalidated = true;
+    }
+
+    /// 已記錄的所有幀
+    pub fn frames(&self) -> &[Frame] {
+        &self.frames
+    }
+
+    /// 最後一幀
+    pub fn last_frame(&self) -> Option<&Frame> {
+        self.frames.last()
+    }
+
+    /// 解譯目前累積的輸出
+    fn process_output(&mut self) {
+        let bytes = std::mem::take(&mut self.pending);
+        // 最後可能是不完整的 UTF-8 序列，留到下次處理
+        let split = match std::str::from_utf8(&bytes) {
+            Err(e) if e.error_len().is_none() => e.valid_up_to(),
+            _ => bytes.len(),
+        };
+        let text = String::from_utf8_lossy(&bytes[..split]).into_owned();
+        let rest = bytes[split..].to_vec();
+
+        let mut chars = text.chars();
+        while let Some(ch) = chars.next() {
+            match ch {
+                // ESC 後面不是 '[' 的序列只有兩個字元，一併略過
+                '\x1b' => {
+                    if let Some('[') = chars.next() {
+                        let mut params = String::new();
+                        let mut final_byte = None;
+                        for c in chars.by_ref() {
+                            if ('\x40'..='\x7e').contains(&c) {
+                                final_byte = Some(c);
+                                break;
+                            }
+                            params.push(c);
+                        }
+                        if let Some(final_byte) = final_byte {
+                            self.apply_csi(&params, final_byte);
+                        }
+                    }
+                }
+                '\r' => self.position.0 = 0,
+                '\n' => self.position.1 += 1,
+                c if c.is_control() => {}
+                c => self.put_char(c),
+            }
+        }
+
+        self.pending = rest;
+    }
+
+    /// 處理 CSI 序列（其他序列不影響畫面內容，直接略過）
+    fn apply_csi(&mut self, params: &str, final_byte: char) {
+        let numbers: Vec<usize> = params.split(';').map(|p| p.parse().unwrap_or(0)).collect();
+        let (cols, rows) = (self.size.0 as usize, self.size.1 as usize);
+
+        match final_byte {
+            // MoveTo：1-based 的 列;欄
+            'H' if !params.starts_with('?') => {
+                let row = numbers.first().copied().unwrap_or(1).max(1) - 1;
+                let col = numbers.get(1).copied().unwrap_or(1).max(1) - 1;
+                self.position = (col.min(cols), row);
+            }
+            'K' => {
+                let (col, row) = self.position;
+                if let Some(line) = self.screen.get_mut(row) {
+                    let start = if numbers[0] == 2 { 0 } else { col.min(cols) };
+                    line[start..].fill(Cell::default());
+                    self.updated_rows.insert(row);
+                }
+            }
+            'J' if numbers[0] == 2 => {
+                self.screen = vec![vec![Cell::default(); cols]; rows];
+                self.updated_rows.extend(0..rows);
+            }
+            'm' => {
+                let mut iter = numbers.iter();
+                while let Some(&code) = iter.next() {
+                    match code {
+                        0 => self.reverse = false,
+                        7 => self.reverse = true,
+                        27 => self.reverse = false,
+                        // 38/48/58 後面接 256 色（5;n）或 24 位元色（2;r;g;b）
+                        38 | 48 | 58 => match iter.next() {
+                            Some(5) => {
+                                iter.next();
+                            }
+                            Some(2) => {
+                                iter.nth(2);
+                            }
+                            _ => {}
+                        },
+                        _ => {}
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// 在目前位置輸出一個字元；超出行尾時與終端相同自動換行
+    fn put_char(&mut self, ch: char) {
+        let width = UnicodeWidthChar::width(ch).unwrap_or(0);
+        if width == 0 {
+            return;
+        }
+        let cols = self.size.0 as usize;
+        if self.position.0 + width > cols {
+            self.position = (0, self.position.1 + 1);
+        }
+
+        let (col, row) = self.position;
+        let reverse = self.reverse;
+        if let Some(line) = self.screen.get_mut(row) {
+            if col < cols {
+                line[col] = Cell { ch, reverse };
+            }
+            if width == 2 && col + 1 < cols {
+                line[col + 1] = Cell { ch: '\0', reverse };
+            }
+            self.updated_rows.insert(row);
+        }
+        self.position.0 += width;
+    }
+}
+
+impl Write for MemoryTerminal {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.pending.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.process_output();
+        Ok(())
+    }
+}
+
+impl TerminalBackend for MemoryTerminal {
+    fn size(&self) -> (u16, u16) {
+        self.size
+    }
+
+    fn read_key(&mut self) -> Result<KeyEvent> {
+        match self.keys.pop_front() {
+            Some(key) => Ok(key),
+            None => bail!("No more keys"),
+        }
+    }
+
+    fn poll_key(&mut self, _timeout: Duration) -> Result<Option<KeyEvent>> {
+        Ok(self.keys.pop_front())
+    }
+
+    fn begin_frame(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn end_frame(&mut self, cursor_position: Option<(u16, u16)>) -> Result<()> {
+        self.process_output();
+        self.frames.push(Frame {
+            cells: self.screen.clone(),
+            cursor: cursor_position,
+            updated_rows: std::mem::take(&mut self.updated_rows).into_iter().collect(),
+        });
+        Ok(())
+    }
+
+    fn take_screen_invalidated(&mut self) -> bool {
+        std::mem::take(&mut self.invalidated)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::RopeBuffer;
+    use crate::editor_core::EditorCore;
+    use crate::input::Command;
+    use crate::view::LineNumberMode;
+    use crossterm::event::{KeyCode, KeyModifiers};
+    use crossterm::{cursor, queue, style};
+
+    fn core_with_text(text: &str, cols: u16, rows: u16) -> EditorCore {
+        let mut core = EditorCore::with_size(RopeBuffer::new(), cols, rows);
+        core.paste_text(text);
+        core.apply(Command::MoveHome);
+        core.go_to_line(1);
+        core.take_message();
+        core
+    }
+
+    #[test]
+    fn test_memory_terminal_interprets_output() {
+        let mut term = MemoryTerminal::new(10, 3);
+        queue!(term, cursor::MoveTo(2, 1), style::Print("中文ab")).unwrap();
+        queue!(term, style::SetAttribute(style::Attribute::Reverse)).unwrap();
+        queue!(term, style::Print("x")).unwrap();
+        queue!(term, style::SetAttribute(style::Attribute::NoReverse)).unwrap();
+        queue!(
+            term,
+            style::SetForegroundColor(style::Color::Rgb { r: 7, g: 7, b: 7 })
+        )
+        .unwrap();
+        queue!(
+            term,
+            style::Print("y"),
+            cursor::MoveTo(0, 0),
+            style::Print("top")
+        )
+        .unwrap();
+        term.end_frame(Some((3, 1))).unwrap();
+
+        let frame = term.last_frame().unwrap();
+        assert_eq!(frame.lines(), vec!["top", "  中文abxy", ""]);
+        assert_eq!(frame.reversed_text(1), "x");
+        assert_eq!(frame.cursor(), Some((3, 1)));
+
+        queue!(term, cursor::MoveTo(4, 1)).unwrap();
+        queue!(
+            term,
+            crossterm::terminal::Clear(crossterm::terminal::ClearType::UntilNewLine)
+        )
+        .unwrap();
+        term.end_frame(None).unwrap();
+        assert_eq!(term.last_frame().unwrap().line(1), "  中");
+        assert_eq!(term.frames().len(), 2);
+    }
+
+    #[test]
+    fn test_render_and_scroll() {
+        let text: String = (1..=10).map(|i| format!("line {}\n", i)).collect();
+        let mut core = core_with_text(&text, 40, 5);
+        let mut term = MemoryTerminal::new(40, 5);
+
+        core.render(&mut term).unwrap();
+        let frame = term.last_frame().unwrap();
+        assert_eq!(frame.line(0), " 1 line 1");
+        assert_eq!(frame.line(3), " 4 line 4");
+        assert!(frame.line(4).contains("Line 1/11"));
+        assert_eq!(frame.cursor(), Some((3, 0)));
+
+        // 光標接近底部時向下捲動，下方保留一行
+        for _ in 0..5 {
+            core.apply(Command::MoveDown);
+        }
+        core.render(&mut term).unwrap();
+        let frame = term.last_frame().unwrap();
+        assert_eq!(frame.line(0), " 4 line 4");
+        assert_eq!(frame.line(3), " 7 line 7");
+        assert_eq!(frame.cursor(), Some((3, 2)));
+    }
+
+    #[test]
+    fn test_render_with_ruler() {
+        let text: String = (1..=10).map(|i| format!("line {}\n", i)).collect();
+        let mut core = core_with_text(&text, 20, 5);
+        core.view.show_ruler = true;
+        core.view.ruler_columns = vec![3, 12];
+        let mut term = MemoryTerminal::new(20, 5);
+
+        // 尺規佔用第一行，文字與光標下移一行，標示的欄位反白
+        core.render(&mut term).unwrap();
+        let frame = term.last_frame().unwrap();
+        assert_eq!(frame.line(0), "   0123456789012345");
+        assert_eq!(frame.reversed_text(0), "21");
+        assert_eq!(frame.line(1), " 1 line 1");
+        assert_eq!(frame.cursor(), Some((3, 1)));
+
+        // 可用行數少一行，提早捲動
+        for _ in 0..2 {
+            core.apply(Command::MoveDown);
+        }
+        core.render(&mut term).unwrap();
+        let frame = term.last_frame().unwrap();
+        assert_eq!(frame.line(1), " 2 line 2");
+        assert_eq!(frame.cursor(), Some((3, 2)));
+    }
+
+    #[test]
+    fn test_render_error_row() {
+        let text: String = (1..=10).map(|i| format!("line {}\n", i)).collect();
+        let mut core = core_with_text(&text, 40, 5);
+        let mut term = MemoryTerminal::new(40, 5);
+        for _ in 0..2 {
+            core.apply(Command::MoveDown);
+        }
+
+        // 錯誤訊息佔用狀態列上方一行，文字區少一行並提早捲動
+        core.view
+            .set_error_message(Some("Save failed: denied".to_string()));
+        core.render(&mut term).unwrap();
+        let frame = term.last_frame().unwrap();
+        assert_eq!(frame.line(0), " 2 line 2");
+        assert_eq!(frame.line(3), " Save failed: denied");
+        assert!(frame.line(4).contains("Line 3/11"));
+        assert_eq!(frame.cursor(), Some((3, 1)));
+
+        // 清除後恢復原本的行數
+        core.view.set_error_message(None);
+        core.render(&mut term).unwrap();
+        let frame = term.last_frame().unwrap();
+        assert_eq!(frame.line(3), " 5 line 5");
+    }
+
+    #[test]
+    fn test_render_relative_line_numbers() {
+        let text: String = (1..=10).map(|i| format!("line {}\n", i)).collect();
+        let mut core = core_with_text(&text, 40, 5);
+        let mut term = MemoryTerminal::new(40, 5);
+        core.apply(Command::MoveDown);
+        core.apply(Command::MoveDown);
+
+        core.view.line_number_mode = LineNumberMode::Relative;
+        core.render(&mut term).unwrap();
+        let frame = term.last_frame().unwrap();
+        assert_eq!(frame.line(0), " 2 line 1");
+        assert_eq!(frame.line(2), " 0 line 3");
+        assert_eq!(frame.line(3), " 1 line 4");
+
+        // 混合模式：光標行顯示絕對行號，移動光標時行號跟著更新
+        core.view.line_number_mode = LineNumberMode::Hybrid;
+        core.apply(Command::MoveUp);
+        core.render(&mut term).unwrap();
+        let frame = term.last_frame().unwrap();
+        assert_eq!(frame.line(0), " 1 line 1");
+        assert_eq!(frame.line(1), " 2 line 2");
+        assert_eq!(frame.line(3), " 2 line 4");
+    }
+
+    #[test]
+    fn test_render_zero_based_and_hex_line_numbers() {
+        let text: String = (0..20).map(|i| format!("line {}\n", i)).collect();
+        let mut core = core_with_text(&text, 40, 20);
+        let mut term = MemoryTerminal::new(40, 20);
+
+        core.view.line_number_start = 0;
+        core.render(&mut term).unwrap();
+        let frame = term.last_frame().unwrap();
+        assert_eq!(frame.line(0), " 0 line 0");
+        assert_eq!(frame.line(10), "10 line 10");
+
+        // 十六進位：最大的行號 0x14 也是兩位數
+        core.view.hex_line_numbers = true;
+        core.render(&mut term).unwrap();
+        let frame = term.last_frame().unwrap();
+        assert_eq!(frame.line(10), " a line 10");
+        assert_eq!(frame.line(16), "10 line 16");
+    }
+
+    #[test]
+    fn test_render_selection_from_keys() {
+        let mut core = core_with_text("hello world\n", 30, 4);
+        let mut term = MemoryTerminal::new(30, 4);
+        for _ in 0..5 {
+            term.push_key(KeyEvent::new(KeyCode::Right, KeyModifiers::SHIFT));
+        }
+
+        while let Ok(Some(key)) = term.poll_key(Duration::ZERO) {
+            assert!(core.handle_key(key));
+        }
+        core.render(&mut term).unwrap();
+
+        let frame = term.last_frame().unwrap();
+        assert_eq!(frame.line(0), "1 hello world");
+        assert_eq!(frame.reversed_text(0), "hello");
+        assert!(term.read_key().is_err());
+    }
+
+    #[test]
+    fn test_redraws_only_damaged_rows() {
+        let text: String = (1..=10).map(|i| format!("line {}\n", i)).collect();
+        let mut core = core_with_text(&text, 40, 6);
+        let mut term = MemoryTerminal::new(40, 6);
+        core.render(&mut term).unwrap();
+        assert_eq!(
+            term.last_frame().unwrap().updated_rows(),
+            &[0, 1, 2, 3, 4, 5]
+        );
+
+        // 只移動光標：沒有任何行需要重新輸出
+        core.apply(Command::MoveRight);
+        core.render(&mut term).unwrap();
+        let frame = term.last_frame().unwrap();
+        assert!(frame.updated_rows().is_empty());
+        assert_eq!(frame.cursor(), Some((4, 0)));
+
+        // 換行後只有狀態列的行號改變
+        core.apply(Command::MoveDown);
+        core.render(&mut term).unwrap();
+        assert_eq!(term.last_frame().unwrap().updated_rows(), &[5]);
+
+        // 單行編輯只輸出該行
+        core.apply(Command::Insert('x'));
+        core.render(&mut term).unwrap();
+        let frame = term.last_frame().unwrap();
+        assert_eq!(frame.updated_rows(), &[1]);
+        assert_eq!(frame.line(1), " 2 lxine 2");
+
+        // 捲動時所有行的內容都改變
+        for _ in 0..4 {
+            core.apply(Command::MoveDown);
+        }
+        core.render(&mut term).unwrap();
+        assert_eq!(
+            term.last_frame().unwrap().updated_rows(),
+            &[0, 1, 2, 3, 4, 5]
+        );
+    }
+
+    #[test]
+    fn test_resize_redraws_everything() {
+        let mut core = core_with_text("a very long line that wraps\n", 40, 4);
+        let mut term = MemoryTerminal::new(40, 4);
+        core.render(&mut term).unwrap();
+        assert_eq!(
+            term.last_frame().unwrap().line(0),
+            "1 a very long line that wraps"
+        );
+
+        // 縮小後自動換行，清除過的畫面仍完整重繪
+        term.resize(16, 4);
+        core.render(&mut term).unwrap();
+        let frame = term.last_frame().unwrap();
+        assert_eq!(frame.line(0), "1 a very long l");
+        assert_eq!(frame.line(1), "  ine that wrap");
+        assert_eq!(frame.line(2), "  s");
+    }
+}
@@ -0,0 +1,237 @@
+// 目錄瀏覽
+//
+// 開啟的路徑是目錄時（`wedi /etc/nginx` 或 `:e DIR`）以全螢幕列表顯示目錄內容：
+// Enter 進入目錄或開啟檔案，Backspace 回到上一層
+
+use crate::dialog::truncate_to_width;
+use crate::terminal::Terminal;
+use crate::utils::visual_width;
+use anyhow::{Context, Result};
+use crossterm::{
+    cursor,
+    event::{self, Event, KeyCode, KeyEventKind},
+    execute, queue,
+    style::{self, Color},
+    terminal::{self, ClearType},
+};
+use std::path::{Path, PathBuf};
+
+/// 目錄中的一個項目
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Entry {
+    name: String,
+    is_dir: bool,
+}
+
+impl Entry {
+    fn label(&self) -> String {
+        if self.is_dir {
+            format!("{}/", self.name)
+        } else {
+            self.name.clone()
+        }
+    }
+}
+
+/// 列出目錄內容：目錄在前、再依名稱排序（不分大小寫）；不是根目錄時第一項為 `..`
+fn list(dir: &Path) -> Result<Vec<Entry>> {
+    let mut entries = Vec::new();
+    for entry in std::fs::read_dir(dir).with_context(|| format!("Cannot read {}", dir.display()))? {
+        let entry = entry?;
+        // 指向目錄的符號連結也當作目錄
+        let is_dir = std::fs::metadata(entry.path()).is_ok_and(|m| m.is_dir());
+        entries.push(Entry {
+            name: entry.file_name().to_string_lossy().into_owned(),
+            is_dir,
+        });
+    }
+    entries.sort_by_cached_key(|entry| (!entry.is_dir, entry.name.to_lowercase()));
+    if dir.parent().is_some() {
+        entries.insert(
+            0,
+            Entry {
+                name: "..".to_string(),
+                is_dir: true,
+            },
+        );
+    }
+    Ok(entries)
+}
+
+/// 在啟動編輯器之前瀏覽目錄；返回選擇的檔案，離開時返回 None
+pub fn open_directory(dir: &Path) -> Result<Option<PathBuf>> {
+    // terminal 離開作用域時恢復終端設定
+    let terminal = Terminal::new()?;
+    Terminal::enter_raw_mode()?;
+    browse(dir, terminal.size())
+}
+
+/// 全螢幕瀏覽目錄，返回選擇的檔案；ESC 或 q 返回 None
+pub fn browse(dir: &Path, terminal_size: (u16, u16)) -> Result<Option<PathBuf>> {
+    // 瀏覽畫面會覆寫編輯區，關閉後需完整重繪
+    Terminal::invalidate_screen();
+    let (cols, rows) = terminal_size;
+    let cols = cols as usize;
+    // 最後一行為標題列
+    let page = (rows as usize).saturating_sub(1).max(1);
+
+    let mut dir = std::fs::canonicalize(dir).unwrap_or_else(|_| dir.to_path_buf());
+    let mut entries = list(&dir)?;
+    let mut selected = 0;
+    let mut top = 0;
+    let mut error: Option<String> = None;
+
+    loop {
+        // 捲動讓選取的項目保持可見
+        if selected < top {
+            top = selected;
+        } else if selected >= top + page {
+            top = selected + 1 - page;
+        }
+
+        Terminal::begin_frame()?;
+        for screen_row in 0..page {
+            queue!(
+                Terminal::output(),
+                cursor::MoveTo(0, screen_row as u16),
+                terminal::Clear(ClearType::CurrentLine)
+            )?;
+            let Some(entry) = entries.get(top + screen_row) else {
+                continue;
+            };
+            let text = truncate_to_width(&format!(" {}", entry.label()), cols);
+            if top + screen_row == selected {
+                queue!(
+                    Terminal::output(),
+                    style::SetBackgroundColor(Color::Cyan),
+                    style::SetForegroundColor(Color::Black),
+                    style::Print(&text),
+                    style::Print(" ".repeat(cols.saturating_sub(visual_width(&text)))),
+                )?;
+            } else {
+                if entry.is_dir {
+                    queue!(Terminal::output(), style::SetForegroundColor(Color::Blue))?;
+                }
+                queue!(Terminal::output(), style::Print(&text))?;
+            }
+            queue!(Terminal::output(), style::ResetColor)?;
+        }
+
+        let status = match &error {
+            Some(error) => format!(" {}  {}", dir.display(), error),
+            None => format!(
+                " {}  {}/{}  Enter:Open  Backspace:Up  Esc/q:Close",
+                dir.display(),
+                (selected + 1).min(entries.len()),
+                entries.len()
+            ),
+        };
+        let status = truncate_to_width(&status, cols);
+        queue!(
+            Terminal::output(),
+            cursor::MoveTo(0, page as u16),
+            style::SetBackgroundColor(Color::DarkGrey),
+            style::SetForegroundColor(Color::White),
+            style::Print(&status),
+            style::Print(" ".repeat(cols.saturating_sub(visual_width(&status)))),
+            style::ResetColor
+        )?;
+        Terminal::end_frame(None)?;
+
+        let Event::Key(key_event) = event::read()? else {
+            continue;
+        };
+        if key_event.kind != KeyEventKind::Press && key_event.kind != KeyEventKind::Repeat {
+            continue;
+        }
+        let last = entries.len().saturating_sub(1);
+        // 要進入的目錄，以及進入後要選取的項目名稱（回到上一層時選取原本的目錄）
+        let target = match key_event.code {
+            KeyCode::Esc | KeyCode::Char('q') => {
+                execute!(Terminal::output(), cursor::Show)?;
+                return Ok(None);
+            }
+            KeyCode::Up | KeyCode::Char('k') => {
+                selected = selected.saturating_sub(1);
+                None
+            }
+            KeyCode::Down | KeyCode::Char('j') => {
+                selected = (selected + 1).min(last);
+                None
+            }
+            KeyCode::PageUp => {
+                selected = selected.saturating_sub(page);
+                None
+            }
+            KeyCode::PageDown => {
+                selected = (selected + page).min(last);
+                None
+            }
+            KeyCode::Home => {
+                selected = 0;
+                None
+            }
+            KeyCode::End => {
+                selected = last;
+                None
+            }
+            KeyCode::Backspace | KeyCode::Left | KeyCode::Char('-') => parent(&dir),
+            KeyCode::Enter | KeyCode::Right => match entries.get(selected) {
+                Some(entry) if entry.name == ".." => parent(&dir),
+                Some(entry) if entry.is_dir => Some((dir.join(&entry.name), None)),
+                Some(entry) => {
+                    execute!(Terminal::output(), cursor::Show)?;
+                    return Ok(Some(dir.join(&entry.name)));
+                }
+                None => None,
+            },
+            _ => None,
+        };
+
+        let Some((target, select)) = target else {
+            continue;
+        };
+        match list(&target) {
+            Ok(list) => {
+                selected = select
+                    .and_then(|name| list.iter().position(|entry| entry.name == name))
+                    .unwrap_or(0);
+                top = 0;
+                entries = list;
+                dir = target;
+                error = None;
+            }
+            Err(e) => error = Some(format!("{:#}", e)),
+        }
+    }
+}
+
+/// 上一層目錄，以及要選取的目前目錄名稱
+fn parent(dir: &Path) -> Option<(PathBuf, Option<String>)> {
+    let name = dir.file_name()?.to_string_lossy().into_owned();
+    Some((dir.parent()?.to_path_buf(), Some(name)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_list_directories_first() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("sites")).unwrap();
+        std::fs::write(dir.path().join("nginx.conf"), "").unwrap();
+        std::fs::write(dir.path().join("Mime.types"), "").unwrap();
+        std::fs::create_dir(dir.path().join("conf.d")).unwrap();
+
+        let labels: Vec<String> = list(dir.path()).unwrap().iter().map(Entry::label).collect();
+        assert_eq!(
+            labels,
+            ["../", "conf.d/", "sites/", "Mime.types", "nginx.conf"]
+        );
+        assert_eq!(
+            parent(&dir.path().join("sites")),
+            Some((dir.path().to_path_buf(), Some("sites".to_string())))
+        );
+    }
+}
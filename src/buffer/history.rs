@@ -15,11 +15,76 @@ pub enum Action {
         end: usize,
         text: String,
     },
+    /// 把 `start` 開始的 `old` 取代為 `new`（整批修改，一次復原）
+    Replace {
+        start: usize,
+        old: String,
+        new: String,
+    },
+}
+
+impl Action {
+    /// 預設的說明，例如 `insert "abc"`、`delete 3 lines`
+    pub fn description(&self) -> String {
+        match self {
+            Action::Insert { text, .. } => describe("insert", text),
+            Action::Delete { text, .. } | Action::DeleteRange { text, .. } => {
+                describe("delete", text)
+            }
+            Action::Replace { old, new, .. } => {
+                if old.contains('\n') || new.contains('\n') {
+                    describe_lines("change", old.lines().count().max(new.lines().count()))
+                } else {
+                    format!("replace {}", quote_short(old).unwrap_or("text".to_string()))
+                }
+            }
+        }
+    }
+}
+
+/// 以動詞描述一段文字：多行時為行數，短文字直接引用，否則為字元數
+pub fn describe(verb: &str, text: &str) -> String {
+    let breaks = text.matches('\n').count();
+    if breaks > 0 && text.trim_matches(['\r', '\n']).is_empty() {
+        let plural = if breaks == 1 { "" } else { "s" };
+        return format!("{} {} line break{}", verb, breaks, plural);
+    }
+    if breaks > 0 {
+        return describe_lines(verb, text.lines().count().max(1));
+    }
+    match quote_short(text) {
+        Some(quoted) => format!("{} {}", verb, quoted),
+        None => format!("{} {} chars", verb, text.chars().count()),
+    }
+}
+
+/// 以動詞描述行數，例如 `indent 5 lines`
+pub fn describe_lines(verb: &str, lines: usize) -> String {
+    format!(
+        "{} {} line{}",
+        verb,
+        lines,
+        if lines == 1 { "" } else { "s" }
+    )
+}
+
+/// 單行且不超過 20 個字元的文字加上引號，其他返回 None
+fn quote_short(text: &str) -> Option<String> {
+    (!text.is_empty() && !text.contains('\n') && text.chars().count() <= 20)
+        .then(|| format!("\"{}\"", text))
+}
+
+/// 歷史中的一個步驟：修改內容與顯示在撤銷/重做訊息中的說明
+#[derive(Debug, Clone)]
+struct Step {
+    action: Action,
+    description: String,
+    version: u64, // 修改後的緩衝區版本，用來確認說明對應的是剛才的修改
 }
 
 pub struct History {
-    undo_stack: Vec<Action>,
-    redo_stack: Vec<Action>,
+    undo_stack: Vec<Step>,
+    redo_stack: Vec<Step>,
     max_size: usize,
 }
 
@@ -32,30 +97,52 @@ impl History {
         }
     }
 
-    pub fn push(&mut self, action: Action) {
+    /// 記錄修改；`version` 是修改後的緩衝區版本
+    pub fn push(&mut self, action: Action, version: u64) {
         if self.undo_stack.len() >= self.max_size {
             self.undo_stack.remove(0);
         }
-        self.undo_stack.push(action);
+        self.undo_stack.push(Step {
+            description: action.description(),
+            action,
+            version,
+        });
         self.redo_stack.clear();
     }
 
-    pub fn undo(&mut self) -> Option<Action> {
-        if let Some(action) = self.undo_stack.pop() {
-            self.redo_stack.push(action.clone());
-            Some(action)
-        } else {
-            None
+    /// 更換最後一個步驟的說明；只有它正是 `version` 的修改時才更換，返回是否已更換
+    pub fn describe_last(&mut self, version: u64, description: String) -> bool {
+        match self.undo_stack.last_mut() {
+            Some(step) if step.version == version => {
+                step.description = description;
+                true
+            }
+            _ => false,
         }
     }
 
+    pub fn undo(&mut self) -> Option<Action> {
+        let step = self.undo_stack.pop()?;
+        let action = step.action.clone();
+        self.redo_stack.push(step);
+        Some(action)
+    }
+
     pub fn redo(&mut self) -> Option<Action> {
-        if let Some(action) = self.redo_stack.pop() {
-            self.undo_stack.push(action.clone());
-            Some(action)
-        } else {
-            None
-        }
+        let step = self.redo_stack.pop()?;
+        let action = step.action.clone();
+        self.undo_stack.push(step);
+        Some(action)
+    }
+
+    /// 剛才撤銷的步驟的說明（重做堆疊頂端）
+    pub fn last_undone(&self) -> Option<&str> {
+        self.redo_stack.last().map(|step| step.description.as_str())
+    }
+
+    /// 剛才重做（或最後一次修改）的步驟的說明
+    pub fn last_done(&self) -> Option<&str> {
+        self.undo_stack.last().map(|step| step.description.as_str())
     }
 
     #[allow(dead_code)]
@@ -73,6 +160,126 @@ impl History {
         self.undo_stack.clear();
         self.redo_stack.clear();
     }
+
+    /// 以文字格式輸出撤銷與重做堆疊（撤銷檔使用）
+    ///
+    /// 每個步驟一行：堆疊（`u`/`r`）、種類、位置與文字以 tab 分隔，文字中的 `\`、tab 與換行字元以跳脫表示
+    #[allow(dead_code)]
+    pub fn serialize(&self) -> String {
+        let mut out = String::new();
+        for (stack, steps) in [("u", &self.undo_stack), ("r", &self.redo_stack)] {
+            for step in steps {
+                let fields = match &step.action {
+                    Action::Insert { pos, text } => {
+                        ["i", &pos.to_string(), &escape(text)].join("\t")
+                    }
+                    Action::Delete { pos, text } => {
+                        ["d", &pos.to_string(), &escape(text)].join("\t")
+                    }
+                    Action::DeleteRange { start, end, text } => {
+                        ["D", &start.to_string(), &end.to_string(), &escape(text)].join("\t")
+                    }
+                    Action::Replace { start, old, new } => {
+                        ["r", &start.to_string(), &escape(old), &escape(new)].join("\t")
+                    }
+                };
+                out.push_str(&format!(
+                    "{}\t{}\t{}\n",
+                    stack,
+                    escape(&step.description),
+                    fields
+                ));
+            }
+        }
+        out
+    }
+
+    /// 解析 [`History::serialize`] 的輸出；格式不符時返回 None
+    #[allow(dead_code)]
+    pub fn deserialize(text: &str) -> Option<History> {
+        let mut history = History::default();
+        for line in text.lines() {
+            let fields: Vec<&str> = line.split('\t').collect();
+            let [stack, description, kind, rest @ ..] = fields.as_slice() else {
+                return None;
+            };
+            let step = Step {
+                action: parse_action(kind, rest)?,
+                description: unescape(description)?,
+                version: 0,
+            };
+            match *stack {
+                "u" => history.undo_stack.push(step),
+                "r" => history.redo_stack.push(step),
+                _ => return None,
+            }
+        }
+        let excess = history.undo_stack.len().saturating_sub(history.max_size);
+        history.undo_stack.drain(..excess);
+        Some(history)
+    }
+}
+
+/// 解析一個步驟的種類與欄位
+fn parse_action(kind: &str, fields: &[&str]) -> Option<Action> {
+    let number = |index: usize| fields.get(index)?.parse::<usize>().ok();
+    let text = |index: usize| unescape(fields.get(index)?);
+    Some(match (kind, fields.len()) {
+        ("i", 2) => Action::Insert {
+            pos: number(0)?,
+            text: text(1)?,
+        },
+        ("d", 2) => Action::Delete {
+            pos: number(0)?,
+            text: text(1)?,
+        },
+        ("D", 3) => Action::DeleteRange {
+            start: number(0)?,
+            end: number(1)?,
+            text: text(2)?,
+        },
+        ("r", 3) => Action::Replace {
+            start: number(0)?,
+            old: text(1)?,
+            new: text(2)?,
+        },
+        _ => return None,
+    })
+}
+
+/// 跳脫 `\`、tab 與換行字元
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '\t' => out.push_str("\\t"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// 還原 [`escape`]；不認得的跳脫返回 None
+fn unescape(text: &str) -> Option<String> {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        out.push(match chars.next()? {
+            '\\' => '\\',
+            't' => '\t',
+            'n' => '\n',
+            'r' => '\r',
+            _ => return None,
+        });
+    }
+    Some(out)
 }
 
 impl Default for History {
@@ -80,3 +287,89 @@ impl Default for History {
         Self::new(1000)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_step_descriptions() {
+        let mut history = History::default();
+        history.push(
+            Action::Insert {
+                pos: 0,
+                text: "a\nb\nc\n".to_string(),
+            },
+            1,
+        );
+        assert_eq!(history.last_done(), Some("insert 3 lines"));
+        history.push(
+            Action::Delete {
+                pos: 0,
+                text: "x".to_string(),
+            },
+            2,
+        );
+        assert_eq!(history.last_done(), Some("delete \"x\""));
+        // 版本不符（例如沒有實際修改）時不更換說明
+        assert!(!history.describe_last(1, "indent 5 lines".to_string()));
+        assert!(history.describe_last(2, "indent 5 lines".to_string()));
+
+        history.undo();
+        assert_eq!(history.last_undone(), Some("indent 5 lines"));
+        assert_eq!(history.last_done(), Some("insert 3 lines"));
+        assert_eq!(describe("paste", &"x".repeat(25)), "paste 25 chars");
+        assert_eq!(describe("delete", "\r\n"), "delete 1 line break");
+    }
+
+    #[test]
+    fn test_serialize_round_trip() {
+        let mut history = History::default();
+        history.push(
+            Action::Insert {
+                pos: 0,
+                text: "a\tb\\n\r\n中".to_string(),
+            },
+            1,
+        );
+        history.push(
+            Action::DeleteRange {
+                start: 2,
+                end: 4,
+                text: "\tb".to_string(),
+            },
+            2,
+        );
+        history.describe_last(2, "delete tab".to_string());
+        history.push(
+            Action::Replace {
+                start: 0,
+                old: "a".to_string(),
+                new: "".to_string(),
+            },
+            3,
+        );
+        history.undo();
+
+        let text = history.serialize();
+        assert_eq!(text.lines().count(), 3);
+        let mut restored = History::deserialize(&text).unwrap();
+        assert_eq!(restored.serialize(), text);
+        assert_eq!(restored.last_undone(), Some("replace \"a\""));
+        assert_eq!(restored.last_done(), Some("delete tab"));
+        match restored.undo() {
+            Some(Action::DeleteRange { start, end, text }) => {
+                assert_eq!((start, end, text.as_str()), (2, 4, "\tb"));
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        restored.undo();
+        match restored.redo() {
+            Some(Action::Insert { text, .. }) => assert_eq!(text, "a\tb\\n\r\n中"),
+            other => panic!("unexpected {:?}", other),
+        }
+
+        assert!(History::deserialize("u\tx\ti\t0\n").is_none());
+        assert!(History::deserialize("u\tx\ti\t0\tbad\\q").is_none());
+    }
+}
@@ -0,0 +1,143 @@
+// 書籤（標記）
+//
+// 標記以字元位置記錄；緩衝區每次插入或刪除文字時一併移動，
+// 讓標記在前方的文字改變後仍然指向同一段內容
+
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, Default)]
+pub struct Marks {
+    positions: BTreeMap<String, usize>, // 名稱 -> 字元位置
+}
+
+impl Marks {
+    pub fn set(&mut self, name: &str, pos: usize) {
+        self.positions.insert(name.to_string(), pos);
+    }
+
+    pub fn get(&self, name: &str) -> Option<usize> {
+        self.positions.get(name).copied()
+    }
+
+    pub fn remove(&mut self, name: &str) -> bool {
+        self.positions.remove(name).is_some()
+    }
+
+    pub fn clear(&mut self) {
+        self.positions.clear();
+    }
+
+    /// 依名稱排序的 (名稱, 位置)
+    pub fn iter(&self) -> impl Iterator<Item = (&str, usize)> {
+        self.positions
+            .iter()
+            .map(|(name, &pos)| (name.as_str(), pos))
+    }
+
+    /// 在 `pos` 插入 `len` 個字元：之後（含同一位置）的標記往後移
+    pub fn inserted(&mut self, pos: usize, len: usize) {
+        for mark in self.positions.values_mut() {
+            if *mark >= pos {
+                *mark += len;
+            }
+        }
+    }
+
+    /// 刪除 start..end：之後的標記往前移，被刪除範圍內的標記移到 `start`
+    pub fn removed(&mut self, start: usize, end: usize) {
+        for mark in self.positions.values_mut() {
+            if *mark >= end {
+                *mark -= end - start;
+            } else if *mark > start {
+                *mark = start;
+            }
+        }
+    }
+
+    /// 從 `start` 開始的 `old` 被取代為 `new`
+    ///
+    /// 範圍內的標記盡量留在同一行（例如整批縮排或加註解後）：新舊內容行數相同時
+    /// 保留行與欄位（欄位不超過新的行尾），否則保留位移但不超過新內容的結尾
+    pub fn replaced(&mut self, start: usize, old: &str, new: &str) {
+        let old_len = old.chars().count();
+        let new_len = new.chars().count();
+        let end = start + old_len;
+        let same_lines = old.matches('\n').count() == new.matches('\n').count();
+        for mark in self.positions.values_mut() {
+            if *mark >= end && (*mark > start || old_len == 0) {
+                *mark = *mark - old_len + new_len;
+            } else if *mark > start {
+                let offset = *mark - start;
+                *mark = start
+                    + if same_lines {
+                        map_offset(old, new, offset)
+                    } else {
+                        offset.min(new_len)
+                    };
+            }
+        }
+    }
+}
+
+/// 把 `old` 中的字元位移對應到行數相同的 `new` 中同一行的同一欄（不超過行尾）
+fn map_offset(old: &str, new: &str, offset: usize) -> usize {
+    let before: String = old.chars().take(offset).collect();
+    let line = before.matches('\n').count();
+    let col = before.chars().rev().take_while(|&ch| ch != '\n').count();
+    let mut pos = 0;
+    for (index, text) in new.split('\n').enumerate() {
+        if index == line {
+            let len = text.trim_end_matches('\r').chars().count();
+            return pos + col.min(len);
+        }
+        pos += text.chars().count() + 1;
+    }
+    new.chars().count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_marks_follow_edits() {
+        let mut marks = Marks::default();
+        marks.set("1", 10);
+        marks.set("a", 4);
+        marks.inserted(2, 3);
+        assert_eq!((marks.get("1"), marks.get("a")), (Some(13), Some(7)));
+        marks.inserted(13, 1);
+        assert_eq!(marks.get("1"), Some(14));
+        marks.inserted(20, 5);
+        assert_eq!(marks.get("1"), Some(14));
+
+        marks.removed(5, 9);
+        assert_eq!((marks.get("1"), marks.get("a")), (Some(10), Some(5)));
+        marks.removed(8, 12);
+        assert_eq!(marks.get("1"), Some(8));
+
+        let names: Vec<_> = marks.iter().map(|(name, _)| name).collect();
+        assert_eq!(names, vec!["1", "a"]);
+        assert!(marks.remove("a"));
+        assert!(!marks.remove("a"));
+    }
+
+    #[test]
+    fn test_marks_keep_their_line_when_replaced() {
+        // 整批縮排：第二行行首的標記仍在第二行
+        let mut marks = Marks::default();
+        marks.set("1", 2);
+        marks.set("2", 5);
+        marks.set("3", 7);
+        marks.replaced(0, "a\nbc\n", "    a\n    bc\n");
+        assert_eq!(marks.get("1"), Some(6));
+        assert_eq!(marks.get("2"), Some(13)); // 第三行行首
+        assert_eq!(marks.get("3"), Some(15)); // 範圍之後
+
+        // 行數改變時不超過新內容的結尾
+        let mut marks = Marks::default();
+        marks.set("1", 4);
+        marks.replaced(2, "xyz\n", "");
+        assert_eq!(marks.get("1"), Some(2));
+    }
+}
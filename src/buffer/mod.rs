@@ -1,12 +1,18 @@
 mod history;
+mod marks;
 mod rope_buffer;
 
+pub use history::{describe, describe_lines};
 pub use rope_buffer::RopeBuffer;
 
-#[derive(Debug, Clone)]
+/// 讀取與存檔的編碼設定；預設為自動偵測、沿用讀取編碼與原檔的 BOM
+#[derive(Debug, Clone, Default)]
+#[non_exhaustive]
 pub struct EncodingConfig {
     pub read_encoding: Option<&'static encoding_rs::Encoding>,
     pub save_encoding: Option<&'static encoding_rs::Encoding>,
+    /// 存檔時是否寫入 BOM（None 表示沿用原檔是否有 BOM）
+    pub bom: Option<bool>,
 }
 
 // #[derive(Debug, Clone)]
@@ -4,6 +4,7 @@ use std::fs;
 use std::path::{Path, PathBuf};
 
 use super::history::{Action, History};
+use super::marks::Marks;
 use super::EncodingConfig;
 use crate::debug_log;
 
@@ -11,10 +12,17 @@ pub struct RopeBuffer {
     rope: Rope,
     file_path: Option<PathBuf>,
     modified: bool,
+    version: u64,                // 每次修改遞增，用於判斷背景存檔期間是否有新的修改
+    saved_rope: Rope,            // 磁碟上的內容（用於標示修改過的行）
+    saving: Option<(u64, Rope)>, // 背景存檔中的快照版本與內容
     history: History,
+    marks: Marks,                                  // 書籤，隨插入與刪除移動
     in_undo_redo: bool,                            // 防止在撤銷/重做時記錄歷史
+    batch: Option<(u64, Rope)>,                    // 合併成一個撤銷步驟的修改開始前的版本與內容
     read_encoding: &'static encoding_rs::Encoding, // 讀取編碼
     save_encoding: &'static encoding_rs::Encoding, // 存檔編碼
+    has_bom: bool,                                 // 存檔時是否寫入 BOM
+    bom_choice: Option<bool>,                      // 使用者指定的 BOM（None 時依原檔與編碼決定）
 }
 
 impl RopeBuffer {
@@ -33,10 +41,17 @@ impl RopeBuffer {
             rope: Rope::new(),
             file_path: None,
             modified: false,
+            version: 0,
+            saved_rope: Rope::new(),
+            saving: None,
             history: History::default(),
+            marks: Marks::default(),
             in_undo_redo: false,
+            batch: None,
             read_encoding: system_enc,
             save_encoding: system_enc,
+            has_bom: false,
+            bom_choice: None,
         }
     }
 
@@ -251,64 +266,105 @@ impl RopeBuffer {
     //     let encoding_config = EncodingConfig {
     //         read_encoding: None,
     //         save_encoding: None,
+    //         bom: None,
     //     };
     //     Self::from_file_with_encoding(path, &encoding_config)
     // }
 
-    pub fn from_file_with_encoding(path: &Path, encoding_config: &EncodingConfig) -> Result<Self> {
-        // 如果文件存在，讀取內容；否則創建空緩衝區
-        let (rope, detected_encoding, modified) = if path.exists() {
-            let bytes = fs::read(path)
-                .with_context(|| format!("Failed to read file: {}", path.display()))?;
-
-            // 編碼處理邏輯 - 簡化版本
-            // 優先級：BOM > 用戶指定 > 系統預設
-            let (read_encoding, bom_length, detected_encoding_info) =
-                if let Some((bom_encoding, bom_len)) = Self::detect_unicode(&bytes) {
-                    // 檢測到 BOM 或 UTF-8，使用檢測到的編碼
-                    let detected_info = if bom_len > 0 {
-                        format!("BOM detected: {}", bom_encoding.name())
-                    } else {
-                        "UTF-8 detected (no BOM)".to_string()
-                    };
-                    (bom_encoding, bom_len, Some((detected_info, bom_encoding)))
-                } else if let Some(specified_enc) = encoding_config.read_encoding {
-                    // 沒有檢測到，使用用戶指定的編碼
-                    (specified_enc, 0, None)
+    /// 解碼位元組內容，返回 (內容, 讀取編碼, 是否有 BOM)
+    ///
+    /// `source` 僅用於警告訊息（檔名或 stdin）
+    fn decode_bytes(
+        bytes: &[u8],
+        encoding_config: &EncodingConfig,
+        source: &str,
+    ) -> (Rope, &'static encoding_rs::Encoding, bool) {
+        // 編碼處理邏輯 - 簡化版本
+        // 優先級：BOM > 用戶指定 > 系統預設
+        let (read_encoding, bom_length, detected_encoding_info) =
+            if let Some((bom_encoding, bom_len)) = Self::detect_unicode(bytes) {
+                // 檢測到 BOM 或 UTF-8，使用檢測到的編碼
+                let detected_info = if bom_len > 0 {
+                    format!("BOM detected: {}", bom_encoding.name())
                 } else {
-                    // 沒有檢測到也沒有用戶指定，使用系統編碼
-                    let system_enc = Self::get_system_ansi_encoding();
-                    (system_enc, 0, None)
+                    "UTF-8 detected (no BOM)".to_string()
                 };
-
-            // Debug 模式：顯示編碼選擇信息
-            // if cfg!(debug_assertions) {
-            debug_log!("  File: {}", path.display());
-            if let Some((detected_info, detected_enc)) = &detected_encoding_info {
-                debug_log!("  Detected: {}", detected_info);
-                if let Some(specified_enc) = encoding_config.read_encoding {
-                    if detected_enc.name() != specified_enc.name() {
-                        debug_log!("  User specified: {} (bypassed)", specified_enc.name());
-                    }
-                }
+                (bom_encoding, bom_len, Some((detected_info, bom_encoding)))
             } else if let Some(specified_enc) = encoding_config.read_encoding {
-                debug_log!("  User specified: {}", specified_enc.name());
+                // 沒有檢測到，使用用戶指定的編碼
+                (specified_enc, 0, None)
             } else {
-                debug_log!("  System default: {}", read_encoding.name());
-            }
-            debug_log!("  Using decoding: {}", read_encoding.name());
-            // }
+                // 沒有檢測到也沒有用戶指定，使用系統編碼
+                let system_enc = Self::get_system_ansi_encoding();
+                (system_enc, 0, None)
+            };
 
-            // 解碼為 UTF-8
-            let (decoded, _, had_errors) = read_encoding.decode(&bytes[bom_length..]);
-            if had_errors {
-                eprintln!(
-                    "[WARN] Encoding errors detected in file: {}",
-                    path.display()
-                );
+        // Debug 模式：顯示編碼選擇信息
+        // if cfg!(debug_assertions) {
+        if let Some((detected_info, detected_enc)) = &detected_encoding_info {
+            debug_log!("  Detected: {}", detected_info);
+            if let Some(specified_enc) = encoding_config.read_encoding {
+                if detected_enc.name() != specified_enc.name() {
+                    debug_log!("  User specified: {} (bypassed)", specified_enc.name());
+                }
             }
+        } else if let Some(specified_enc) = encoding_config.read_encoding {
+            debug_log!("  User specified: {}", specified_enc.name());
+        } else {
+            debug_log!("  System default: {}", read_encoding.name());
+        }
+        debug_log!("  Using decoding: {}", read_encoding.name());
+        // }
+
+        // 解碼為 UTF-8；BOM 已在上面處理，內容開頭即使像另一個 BOM 也屬於文字
+        let (decoded, had_errors) = read_encoding.decode_without_bom_handling(&bytes[bom_length..]);
+        if had_errors {
+            eprintln!("[WARN] Encoding errors detected in {}", source);
+        }
 
-            (Rope::from_str(&decoded), read_encoding, false)
+        (Rope::from_str(&decoded), read_encoding, bom_length > 0)
+    }
+
+    /// 從記憶體中的內容建立沒有檔案路徑的緩衝區（例如 stdin 管道輸入）
+    ///
+    /// 內容尚未存檔，因此標記為已修改
+    pub fn from_bytes_with_encoding(bytes: &[u8], encoding_config: &EncodingConfig) -> Self {
+        let (rope, read_encoding, had_bom) = Self::decode_bytes(bytes, encoding_config, "stdin");
+        let save_encoding = encoding_config
+            .save_encoding
+            .or(encoding_config.read_encoding)
+            .unwrap_or(read_encoding);
+
+        Self {
+            rope,
+            file_path: None,
+            modified: true,
+            version: 0,
+            saved_rope: Rope::new(),
+            saving: None,
+            history: History::default(),
+            marks: Marks::default(),
+            in_undo_redo: false,
+            batch: None,
+            read_encoding,
+            save_encoding,
+            has_bom: encoding_config
+                .bom
+                .unwrap_or(had_bom || is_utf16(save_encoding)),
+            bom_choice: encoding_config.bom,
+        }
+    }
+
+    pub fn from_file_with_encoding(path: &Path, encoding_config: &EncodingConfig) -> Result<Self> {
+        // 如果文件存在，讀取內容；否則創建空緩衝區
+        let (rope, detected_encoding, modified, had_bom) = if path.exists() {
+            let bytes = fs::read(path)
+                .with_context(|| format!("Failed to read file: {}", path.display()))?;
+
+            debug_log!("  File: {}", path.display());
+            let (rope, read_encoding, had_bom) =
+                Self::decode_bytes(&bytes, encoding_config, &path.display().to_string());
+            (rope, read_encoding, false, had_bom)
         } else {
             // 文件不存在，創建空緩衝區
             // 使用用戶指定編碼，否則使用系統默認編碼
@@ -331,7 +387,7 @@ impl RopeBuffer {
                 }
             }
 
-            (Rope::new(), encoding_to_use, true)
+            (Rope::new(), encoding_to_use, true, false)
         };
 
         // 確定存檔編碼：優先級 --en > --dec > 實際讀取編碼
@@ -340,19 +396,32 @@ impl RopeBuffer {
             .or(encoding_config.read_encoding)
             .unwrap_or(detected_encoding);
 
+        // BOM：用戶指定 > 沿用原檔（UTF-16 沒有 BOM 無法被辨識，預設加上）
+        let has_bom = encoding_config
+            .bom
+            .unwrap_or(had_bom || is_utf16(save_encoding));
+
         // Debug 模式：顯示存檔編碼選擇信息
         // if cfg!(debug_assertions) {
         debug_log!("  Using encoding: {}", save_encoding.name());
+        debug_log!("  BOM on save: {}", has_bom);
         // }
 
         Ok(Self {
+            saved_rope: if modified { Rope::new() } else { rope.clone() },
+            saving: None,
             rope,
             file_path: Some(path.to_path_buf()),
             modified,
+            version: 0,
             history: History::default(),
+            marks: Marks::default(),
             in_undo_redo: false,
+            batch: None,
             read_encoding: detected_encoding,
             save_encoding,
+            has_bom,
+            bom_choice: encoding_config.bom,
         })
     }
 
@@ -360,30 +429,27 @@ impl RopeBuffer {
         let pos = pos.min(self.rope.len_chars());
 
         // 記錄到歷史
-        if !self.in_undo_redo {
-            self.history.push(Action::Insert {
-                pos,
-                text: ch.to_string(),
-            });
-        }
+        self.record(Action::Insert {
+            pos,
+            text: ch.to_string(),
+        });
 
         self.rope.insert_char(pos, ch);
-        self.modified = true;
+        self.marks.inserted(pos, 1);
+        self.mark_modified();
     }
 
     pub fn insert(&mut self, pos: usize, text: &str) {
         let pos = pos.min(self.rope.len_chars());
 
         // 記錄到歷史
-        if !self.in_undo_redo {
-            self.history.push(Action::Insert {
-                pos,
-                text: text.to_string(),
-            });
-        }
+        self.record(Action::Insert {
+            pos,
+            text: text.to_string(),
+        });
 
-        self.rope.insert(pos, text);
-        self.modified = true;
+        self.rope_insert(pos, text);
+        self.mark_modified();
     }
 
     pub fn delete_char(&mut self, pos: usize) {
@@ -392,15 +458,13 @@ impl RopeBuffer {
             let deleted_char = self.rope.char(pos).to_string();
 
             // 記錄到歷史
-            if !self.in_undo_redo {
-                self.history.push(Action::Delete {
-                    pos,
-                    text: deleted_char,
-                });
-            }
+            self.record(Action::Delete {
+                pos,
+                text: deleted_char,
+            });
 
-            self.rope.remove(pos..pos + 1);
-            self.modified = true;
+            self.rope_remove(pos, pos + 1);
+            self.mark_modified();
         }
     }
 
@@ -412,19 +476,35 @@ impl RopeBuffer {
             let deleted_text = self.rope.slice(start..end).to_string();
 
             // 記錄到歷史
-            if !self.in_undo_redo {
-                self.history.push(Action::DeleteRange {
-                    start,
-                    end,
-                    text: deleted_text,
-                });
-            }
+            self.record(Action::DeleteRange {
+                start,
+                end,
+                text: deleted_text,
+            });
 
-            self.rope.remove(start..end);
-            self.modified = true;
+            self.rope_remove(start, end);
+            self.mark_modified();
         }
     }
 
+    /// 把 start..end 取代為 `text`，在歷史中只算一個步驟
+    #[allow(dead_code)]
+    pub fn replace_range(&mut self, start: usize, end: usize, text: &str) {
+        let end = end.min(self.rope.len_chars());
+        let start = start.min(end);
+        let old = self.rope.slice(start..end).to_string();
+
+        // 記錄到歷史
+        self.record(Action::Replace {
+            start,
+            old: old.clone(),
+            new: text.to_string(),
+        });
+
+        self.rope_replace(start, &old, text);
+        self.mark_modified();
+    }
+
     pub fn delete_line(&mut self, row: usize) {
         if row < self.line_count() {
             let start = self.rope.line_to_char(row);
@@ -438,16 +518,14 @@ impl RopeBuffer {
             let deleted_line = self.rope.slice(start..end).to_string();
 
             // 記錄到歷史
-            if !self.in_undo_redo {
-                self.history.push(Action::DeleteRange {
-                    start,
-                    end,
-                    text: deleted_line,
-                });
-            }
+            self.record(Action::DeleteRange {
+                start,
+                end,
+                text: deleted_line,
+            });
 
-            self.rope.remove(start..end);
-            self.modified = true;
+            self.rope_remove(start, end);
+            self.mark_modified();
         }
     }
 
@@ -471,6 +549,7 @@ impl RopeBuffer {
         self.rope.char_to_line(char_idx.min(self.rope.len_chars()))
     }
 
+    #[allow(dead_code)]
     pub fn save(&mut self) -> Result<()> {
         if let Some(path) = &self.file_path.clone() {
             if cfg!(debug_assertions) {
@@ -478,17 +557,10 @@ impl RopeBuffer {
                 eprintln!("[DEBUG]   save_encoding: {}", self.save_encoding.name());
             }
 
-            let contents = self.rope.to_string();
-            // 使用指定編碼編碼內容
-            let (encoded, _, had_errors) = self.save_encoding.encode(&contents);
-            if had_errors {
-                eprintln!(
-                    "[WARN] Encoding errors occurred while saving file: {}",
-                    path.display()
-                );
-            }
+            let encoded = self.encode_contents(&path.display().to_string());
             std::fs::write(path, encoded)?;
             self.modified = false;
+            self.saved_rope = self.rope.clone();
 
             if cfg!(debug_assertions) {
                 eprintln!(
@@ -505,44 +577,207 @@ impl RopeBuffer {
 
     #[allow(dead_code)]
     pub fn save_to(&mut self, path: &Path) -> Result<()> {
-        let contents = self.rope.to_string();
-        // 使用指定編碼編碼內容
-        let (encoded, _, had_errors) = self.save_encoding.encode(&contents);
-        if had_errors {
-            eprintln!(
-                "[WARN] Encoding errors occurred while saving file: {}",
-                path.display()
-            );
-        }
+        let encoded = self.encode_contents(&path.display().to_string());
         std::fs::write(path, encoded)?;
         self.modified = false;
+        self.saved_rope = self.rope.clone();
         self.file_path = Some(path.to_path_buf());
         Ok(())
     }
 
-    #[allow(dead_code)]
     pub fn save_as(&mut self, path: &Path) -> Result<()> {
-        let contents = self.rope.to_string();
-        // 使用指定編碼編碼內容
-        let (encoded, _, had_errors) = self.save_encoding.encode(&contents);
-        if had_errors {
-            eprintln!(
-                "[WARN] Encoding errors occurred while saving file: {}",
-                path.display()
-            );
-        }
+        let encoded = self.encode_contents(&path.display().to_string());
         fs::write(path, encoded)
             .with_context(|| format!("Failed to write file: {}", path.display()))?;
         self.file_path = Some(path.to_path_buf());
         self.modified = false;
+        self.saved_rope = self.rope.clone();
         Ok(())
     }
 
+    /// 以存檔編碼編碼內容，需要時在開頭加上 BOM
+    ///
+    /// `target` 僅用於警告訊息（檔名或 stdout）
+    pub fn encode_contents(&self, target: &str) -> Vec<u8> {
+        encode_text(
+            &self.rope.to_string(),
+            self.save_encoding,
+            self.has_bom,
+            target,
+        )
+    }
+
+    /// 建立存檔快照，讓編碼與寫檔可以在背景執行緒進行
+    ///
+    /// Rope 的複製只增加參考計數，即使是大檔案也不需要複製內容
+    pub fn save_snapshot(&mut self) -> Result<SaveSnapshot> {
+        let path = self
+            .file_path
+            .clone()
+            .ok_or_else(|| anyhow::anyhow!("No file path set"))?;
+        self.saving = Some((self.version, self.rope.clone()));
+        Ok(SaveSnapshot {
+            rope: self.rope.clone(),
+            path,
+            encoding: self.save_encoding,
+            has_bom: self.has_bom,
+            version: self.version,
+        })
+    }
+
+    /// 背景存檔完成後更新狀態
+    ///
+    /// 存檔期間若又有修改，緩衝區仍維持已修改
+    pub fn finish_save(&mut self, version: u64) {
+        if self.version == version {
+            self.modified = false;
+        }
+        if let Some((_, rope)) = self.saving.take().filter(|(v, _)| *v == version) {
+            self.saved_rope = rope;
+        }
+    }
+
+    /// 目前內容的快照；Rope 的複製只增加參考計數
+    #[allow(dead_code)]
+    pub fn rope_snapshot(&self) -> Rope {
+        self.rope.clone()
+    }
+
+    /// 目前的修改版本（每次修改遞增）
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    /// 最近一次讀取或存檔時磁碟上的內容
+    pub fn saved_text(&self) -> String {
+        self.saved_rope.to_string()
+    }
+
+    /// 找出無法以存檔編碼表示的字符
+    ///
+    /// 返回最多 `limit` 個 (行號, 字符)，行號為 0-based；空結果表示可無損存檔
+    pub fn find_unencodable_chars(&self, limit: usize) -> Vec<(usize, char)> {
+        let mut problems = Vec::new();
+        if limit == 0 || self.save_encoding == encoding_rs::UTF_8 || is_utf16(self.save_encoding) {
+            return problems;
+        }
+
+        let mut buf = [0u8; 4];
+        for (row, line) in self.rope.lines().enumerate() {
+            // 先整行檢查，只有出錯的行才逐字檢查
+            let line_str = line.to_string();
+            let (_, _, had_errors) = self.save_encoding.encode(&line_str);
+            if !had_errors {
+                continue;
+            }
+
+            for ch in line_str.chars() {
+                let (_, _, had_errors) = self.save_encoding.encode(ch.encode_utf8(&mut buf));
+                if had_errors {
+                    problems.push((row, ch));
+                    if problems.len() >= limit {
+                        return problems;
+                    }
+                }
+            }
+        }
+
+        problems
+    }
+
+    /// 字符範圍 `start..end` 的文字片段（不複製內容）
+    pub fn chunks(&self, start: usize, end: usize) -> ropey::iter::Chunks<'_> {
+        let len = self.rope.len_chars();
+        self.rope.slice(start.min(len)..end.min(len)).chunks()
+    }
+
+    /// 字符範圍 `start..end` 以存檔編碼存檔時的位元組數（不含 BOM）
+    ///
+    /// 無法表示的字符與存檔時一樣以 `&#NNNN;` 計算
+    pub fn encoded_len(&self, start: usize, end: usize) -> usize {
+        let encoding = self.save_encoding;
+        if encoding == encoding_rs::UTF_8 {
+            return self.chunks(start, end).map(str::len).sum();
+        }
+        if is_utf16(encoding) {
+            return self
+                .chunks(start, end)
+                .flat_map(str::chars)
+                .map(|ch| ch.len_utf16() * 2)
+                .sum();
+        }
+
+        let mut encoder = encoding.new_encoder();
+        let mut output = [0u8; 4096];
+        let mut total = 0;
+        for (chunk, last) in self
+            .chunks(start, end)
+            .map(|chunk| (chunk, false))
+            .chain([("", true)])
+        {
+            let mut input = chunk;
+            loop {
+                let (result, read, written, _) = encoder.encode_from_utf8(input, &mut output, last);
+                total += written;
+                input = &input[read..];
+                if result == encoding_rs::CoderResult::InputEmpty {
+                    break;
+                }
+            }
+        }
+        total
+    }
+
+    /// 存檔後的檔案大小（位元組，含 BOM）
+    pub fn encoded_file_len(&self) -> usize {
+        let bom = match self.save_encoding {
+            _ if !self.has_bom => 0,
+            e if e == encoding_rs::UTF_8 => 3,
+            e if is_utf16(e) => 2,
+            _ => 0,
+        };
+        bom + self.encoded_len(0, self.rope.len_chars())
+    }
+
+    /// 取得整個緩衝區的文字
+    pub fn text(&self) -> String {
+        self.rope.to_string()
+    }
+
+    /// 以目前的讀取編碼讀出磁碟上的檔案內容（沒有路徑或檔案不存在時返回 None）
+    pub fn read_disk_text(&self) -> Result<Option<String>> {
+        let Some(path) = self.file_path.as_deref().filter(|p| p.exists()) else {
+            return Ok(None);
+        };
+
+        let bytes =
+            fs::read(path).with_context(|| format!("Failed to read file: {}", path.display()))?;
+        Ok(Some(self.decode_text(&bytes, &path.display().to_string())))
+    }
+
+    /// 以目前的讀取編碼解碼其他來源的檔案內容（例如 git 的 HEAD 版本）
+    ///
+    /// `source` 僅用於警告訊息
+    pub fn decode_text(&self, bytes: &[u8], source: &str) -> String {
+        let encoding_config = EncodingConfig {
+            read_encoding: Some(self.read_encoding),
+            save_encoding: None,
+            bom: None,
+        };
+        let (rope, _, _) = Self::decode_bytes(bytes, &encoding_config, source);
+        rope.to_string()
+    }
+
     pub fn is_modified(&self) -> bool {
         self.modified
     }
 
-    #[allow(dead_code)]
+    /// 標記為已修改並遞增版本
+    fn mark_modified(&mut self) {
+        self.modified = true;
+        self.version += 1;
+    }
+
     pub fn file_path(&self) -> Option<&Path> {
         self.file_path.as_deref()
     }
@@ -579,6 +814,125 @@ impl RopeBuffer {
         self.rope.slice(line_start..line_end).to_string()
     }
 
+    /// 在 rope 中插入文字並移動之後的書籤
+    fn rope_insert(&mut self, pos: usize, text: &str) {
+        self.rope.insert(pos, text);
+        self.marks.inserted(pos, text.chars().count());
+    }
+
+    /// 從 rope 刪除 start..end 並移動書籤
+    fn rope_remove(&mut self, start: usize, end: usize) {
+        self.rope.remove(start..end);
+        self.marks.removed(start, end);
+    }
+
+    /// 把 rope 中從 `start` 開始的 `old` 取代為 `new`，書籤盡量留在原本的行
+    fn rope_replace(&mut self, start: usize, old: &str, new: &str) {
+        self.rope.remove(start..start + old.chars().count());
+        self.rope.insert(start, new);
+        self.marks.replaced(start, old, new);
+    }
+
+    /// 在字元位置設定書籤（同名的書籤會被取代）
+    pub fn set_mark(&mut self, name: &str, pos: usize) {
+        self.marks.set(name, pos.min(self.rope.len_chars()));
+    }
+
+    /// 書籤目前的字元位置
+    pub fn mark(&self, name: &str) -> Option<usize> {
+        self.marks.get(name)
+    }
+
+    /// 刪除書籤，返回是否存在
+    pub fn delete_mark(&mut self, name: &str) -> bool {
+        self.marks.remove(name)
+    }
+
+    /// 依名稱排序的所有書籤 (名稱, 字元位置)
+    pub fn marks(&self) -> impl Iterator<Item = (&str, usize)> {
+        self.marks.iter()
+    }
+
+    /// 記錄修改到歷史（撤銷/重做本身不記錄）；版本在修改後加一
+    fn record(&mut self, action: Action) {
+        if !self.in_undo_redo && self.batch.is_none() {
+            self.history.push(action, self.version + 1);
+        }
+    }
+
+    /// 開始把接下來的修改合併為歷史中的一個步驟，以 [`RopeBuffer::end_batch`] 結束
+    ///
+    /// 期間不可撤銷或重做
+    #[allow(dead_code)]
+    pub fn begin_batch(&mut self) {
+        if self.batch.is_none() {
+            self.batch = Some((self.version, self.rope.clone()));
+        }
+    }
+
+    /// 結束 [`RopeBuffer::begin_batch`]：期間的修改以前後內容的差異記錄為一個步驟
+    #[allow(dead_code)]
+    pub fn end_batch(&mut self) {
+        let Some((version, before)) = self.batch.take() else {
+            return;
+        };
+        if version == self.version {
+            return;
+        }
+        // 前後共同的開頭與結尾之間就是修改的範圍
+        let (before_len, after_len) = (before.len_chars(), self.rope.len_chars());
+        let prefix = before
+            .chars()
+            .zip(self.rope.chars())
+            .take_while(|(a, b)| a == b)
+            .count();
+        let suffix = before
+            .chars_at(before_len)
+            .reversed()
+            .zip(self.rope.chars_at(after_len).reversed())
+            .take(before_len.min(after_len) - prefix)
+            .take_while(|(a, b)| a == b)
+            .count();
+        let old = before.slice(prefix..before_len - suffix).to_string();
+        let new = self.rope.slice(prefix..after_len - suffix).to_string();
+        let action = match (old.is_empty(), new.is_empty()) {
+            (true, true) => return,
+            (true, false) => Action::Insert {
+                pos: prefix,
+                text: new,
+            },
+            (false, true) => Action::DeleteRange {
+                start: prefix,
+                end: before_len - suffix,
+                text: old,
+            },
+            (false, false) => Action::Replace {
+                start: prefix,
+                old,
+                new,
+            },
+        };
+        self.history.push(action, self.version);
+    }
+
+    /// 為剛才的修改加上撤銷/重做時顯示的說明，例如 `indent 5 lines`
+    ///
+    /// 剛才沒有實際修改（歷史中最後一步不是目前的版本）時不做任何事
+    #[allow(dead_code)]
+    pub fn describe_last_edit(&mut self, description: impl Into<String>) {
+        self.history.describe_last(self.version, description.into());
+    }
+
+    /// 剛才撤銷的修改的說明
+    pub fn last_undone_description(&self) -> Option<&str> {
+        self.history.last_undone()
+    }
+
+    /// 剛才重做的修改的說明
+    pub fn last_done_description(&self) -> Option<&str> {
+        self.history.last_done()
+    }
+
     // 撤銷/重做方法
     pub fn undo(&mut self) -> Option<usize> {
         if let Some(action) = self.history.undo() {
@@ -588,20 +942,26 @@ impl RopeBuffer {
                 Action::Insert { pos, text } => {
                     // 撤銷插入 = 刪除
                     let char_count = text.chars().count();
-                    self.rope.remove(pos..pos + char_count);
-                    self.modified = true;
+                    self.rope_remove(pos, pos + char_count);
+                    self.mark_modified();
                     Some(pos)
                 }
                 Action::Delete { pos, text } => {
                     // 撤銷刪除 = 插入
-                    self.rope.insert(pos, &text);
-                    self.modified = true;
+                    self.rope_insert(pos, &text);
+                    self.mark_modified();
                     Some(pos)
                 }
                 Action::DeleteRange { start, text, .. } => {
                     // 撤銷範圍刪除 = 插入
-                    self.rope.insert(start, &text);
-                    self.modified = true;
+                    self.rope_insert(start, &text);
+                    self.mark_modified();
+                    Some(start)
+                }
+                Action::Replace { start, old, new } => {
+                    // 撤銷取代 = 換回原本的文字
+                    self.rope_replace(start, &new, &old);
+                    self.mark_modified();
                     Some(start)
                 }
             };
@@ -620,21 +980,27 @@ impl RopeBuffer {
             let result_pos = match action {
                 Action::Insert { pos, text } => {
                     // 重做插入
-                    self.rope.insert(pos, &text);
-                    self.modified = true;
+                    self.rope_insert(pos, &text);
+                    self.mark_modified();
                     Some(pos + text.chars().count())
                 }
                 Action::Delete { pos, text } => {
                     // 重做刪除
                     let char_count = text.chars().count();
-                    self.rope.remove(pos..pos + char_count);
-                    self.modified = true;
+                    self.rope_remove(pos, pos + char_count);
+                    self.mark_modified();
                     Some(pos)
                 }
                 Action::DeleteRange { start, end, .. } => {
                     // 重做範圍刪除
-                    self.rope.remove(start..end);
-                    self.modified = true;
+                    self.rope_remove(start, end);
+                    self.mark_modified();
+                    Some(start)
+                }
+                Action::Replace { start, old, new } => {
+                    // 重做取代
+                    self.rope_replace(start, &old, &new);
+                    self.mark_modified();
                     Some(start)
                 }
             };
@@ -646,6 +1012,26 @@ impl RopeBuffer {
         }
     }
 
+    /// 撤銷歷史的文字格式（寫入撤銷檔）
+    #[allow(dead_code)]
+    pub fn history_text(&self) -> String {
+        self.history.serialize()
+    }
+
+    /// 以撤銷檔讀回的歷史取代目前的撤銷歷史；格式不符時不改變並返回 false
+    ///
+    /// 呼叫端需確認歷史對應的正是目前的內容
+    #[allow(dead_code)]
+    pub fn restore_history(&mut self, text: &str) -> bool {
+        match History::deserialize(text) {
+            Some(history) => {
+                self.history = history;
+                true
+            }
+            None => false,
+        }
+    }
+
     #[allow(dead_code)]
     pub fn can_undo(&self) -> bool {
         self.history.can_undo()
@@ -664,22 +1050,49 @@ impl RopeBuffer {
     /// 設置存檔編碼
     pub fn set_save_encoding(&mut self, encoding: &'static encoding_rs::Encoding) {
         self.save_encoding = encoding;
+        // UTF-16 沒有 BOM 無法被辨識，使用者沒有指定時預設加上
+        if is_utf16(encoding) && self.bom_choice.is_none() {
+            self.has_bom = true;
+        }
         // 設置編碼後標記為已修改，因為編碼改變了
-        self.modified = true;
+        self.mark_modified();
     }
 
     // 獲取存檔編碼
-    #[allow(dead_code)]
     pub fn save_encoding(&self) -> &'static encoding_rs::Encoding {
         self.save_encoding
     }
 
+    /// 存檔時是否寫入 BOM
+    pub fn has_bom(&self) -> bool {
+        self.has_bom
+    }
+
+    /// 設定存檔時是否寫入 BOM（之後改變編碼時保留這個選擇）
+    pub fn set_bom(&mut self, has_bom: bool) {
+        self.bom_choice = Some(has_bom);
+        if self.has_bom != has_bom {
+            self.has_bom = has_bom;
+            // BOM 改變會影響存檔內容，標記為已修改
+            self.mark_modified();
+        }
+    }
+
+    /// 設定新緩衝區一開始的 BOM（例如 `--bom`）；屬於初始狀態，不標記為已修改
+    pub fn set_initial_bom(&mut self, has_bom: bool) {
+        self.bom_choice = Some(has_bom);
+        self.has_bom = has_bom;
+    }
+
     /// 使用指定編碼重新載入檔案
+    ///
+    /// 會丟棄未存檔的修改與撤銷歷史，呼叫端需先向使用者確認
     pub fn reload_with_encoding(&mut self, encoding: &'static encoding_rs::Encoding) -> Result<()> {
         if let Some(path) = &self.file_path.clone() {
             let encoding_config = EncodingConfig {
                 read_encoding: Some(encoding),
                 save_encoding: Some(encoding),
+                bom: self.bom_choice,
             };
             let new_buffer = Self::from_file_with_encoding(path, &encoding_config)?;
 
@@ -687,8 +1100,11 @@ impl RopeBuffer {
             self.rope = new_buffer.rope;
             self.read_encoding = new_buffer.read_encoding;
             self.save_encoding = new_buffer.save_encoding;
+            self.has_bom = new_buffer.has_bom;
+            self.saved_rope = new_buffer.saved_rope;
             self.modified = false;
             self.history.clear(); // 清除 undo/redo 歷史
+            self.marks.clear(); // 內容以新的編碼重新解碼，書籤的位置不再可靠
 
             Ok(())
         } else {
@@ -700,6 +1116,9 @@ impl RopeBuffer {
     pub fn change_encoding(&mut self, encoding: &'static encoding_rs::Encoding) {
         self.read_encoding = encoding;
         self.save_encoding = encoding;
+        if is_utf16(encoding) && self.bom_choice.is_none() {
+            self.has_bom = true;
+        }
         // 不標記為已修改，因為只是改變未來的編碼設定
     }
 
@@ -709,9 +1128,101 @@ impl RopeBuffer {
     }
 }
 
+/// 背景存檔用的緩衝區快照
+pub struct SaveSnapshot {
+    rope: Rope,
+    path: PathBuf,
+    encoding: &'static encoding_rs::Encoding,
+    has_bom: bool,
+    version: u64,
+}
+
+impl SaveSnapshot {
+    /// 建立快照時的緩衝區版本，存檔完成後交給 `RopeBuffer::finish_save`
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    /// 編碼並寫入檔案（可在任意執行緒執行）
+    pub fn write(&self) -> Result<()> {
+        let target = self.path.display().to_string();
+        let encoded = encode_text(&self.rope.to_string(), self.encoding, self.has_bom, &target);
+        fs::write(&self.path, encoded)
+            .with_context(|| format!("Failed to write file: {}", self.path.display()))
+    }
+}
+
+/// 以指定編碼編碼文字，需要時在開頭加上 BOM
+fn encode_text(
+    contents: &str,
+    encoding: &'static encoding_rs::Encoding,
+    has_bom: bool,
+    target: &str,
+) -> Vec<u8> {
+    // 使用指定編碼編碼內容
+    // encoding_rs 的 encode 只會輸出 UTF-8 取代 UTF-16，因此 UTF-16 需自行編碼
+    let (encoded, output_encoding, had_errors) = if is_utf16(encoding) {
+        let big_endian = encoding == encoding_rs::UTF_16BE;
+        (
+            std::borrow::Cow::Owned(encode_utf16(contents, big_endian)),
+            encoding,
+            false,
+        )
+    } else {
+        encoding.encode(contents)
+    };
+    if had_errors {
+        eprintln!("[WARN] Encoding errors occurred while writing {}", target);
+    }
+
+    // BOM 依實際輸出的編碼決定，非 Unicode 編碼不寫 BOM
+    let bom: &[u8] = if !has_bom {
+        &[]
+    } else if output_encoding == encoding_rs::UTF_8 {
+        &[0xEF, 0xBB, 0xBF]
+    } else if output_encoding == encoding_rs::UTF_16LE {
+        &[0xFF, 0xFE]
+    } else if output_encoding == encoding_rs::UTF_16BE {
+        &[0xFE, 0xFF]
+    } else {
+        &[]
+    };
+
+    let mut bytes = Vec::with_capacity(bom.len() + encoded.len());
+    bytes.extend_from_slice(bom);
+    bytes.extend_from_slice(&encoded);
+    bytes
+}
+
+/// 是否為 UTF-16 編碼（encoding_rs 無法輸出，需自行編碼）
+fn is_utf16(encoding: &'static encoding_rs::Encoding) -> bool {
+    encoding == encoding_rs::UTF_16LE || encoding == encoding_rs::UTF_16BE
+}
+
+/// 將文字編碼為 UTF-16 位元組（不含 BOM）
+fn encode_utf16(text: &str, big_endian: bool) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(text.len() * 2);
+    for unit in text.encode_utf16() {
+        let pair = if big_endian {
+            unit.to_be_bytes()
+        } else {
+            unit.to_le_bytes()
+        };
+        bytes.extend_from_slice(&pair);
+    }
+    bytes
+}
+
+impl Default for RopeBuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use proptest::prelude::*;
     use std::fs;
     use tempfile::TempDir;
 
@@ -728,6 +1239,7 @@ mod tests {
             &EncodingConfig {
                 read_encoding: None,
                 save_encoding: None,
+                bom: None,
             },
         )
         .unwrap();
@@ -749,6 +1261,7 @@ mod tests {
             &EncodingConfig {
                 read_encoding: None,
                 save_encoding: None,
+                bom: None,
             },
         )
         .unwrap();
@@ -774,12 +1287,331 @@ mod tests {
             &EncodingConfig {
                 read_encoding: None,
                 save_encoding: None,
+                bom: None,
             },
         )
         .unwrap();
         assert_eq!(buffer.save_encoding().name(), "UTF-16LE");
     }
 
+    #[test]
+    fn test_bom_preserved_on_save() {
+        let temp_dir = TempDir::new().unwrap();
+        let file_path = temp_dir.path().join("test_bom_roundtrip.txt");
+
+        let mut content = vec![0xEF, 0xBB, 0xBF];
+        content.extend_from_slice("Hello".as_bytes());
+        fs::write(&file_path, &content).unwrap();
+
+        let config = EncodingConfig {
+            read_encoding: None,
+            save_encoding: None,
+            bom: None,
+        };
+        let mut buffer = RopeBuffer::from_file_with_encoding(&file_path, &config).unwrap();
+        assert!(buffer.has_bom());
+        assert_eq!(buffer.get_line_content(0), "Hello");
+
+        buffer.save().unwrap();
+        assert_eq!(fs::read(&file_path).unwrap(), content);
+
+        // 移除 BOM
+        buffer.set_bom(false);
+        assert!(buffer.is_modified());
+        buffer.save().unwrap();
+        assert_eq!(fs::read(&file_path).unwrap(), b"Hello");
+    }
+
+    #[test]
+    fn test_bom_only_stripped_once() {
+        let config = EncodingConfig {
+            read_encoding: None,
+            save_encoding: None,
+            bom: None,
+        };
+
+        // BOM 之後的 U+FEFF 是文字的一部分
+        let content = b"\xEF\xBB\xBF\xEF\xBB\xBFHi";
+        let buffer = RopeBuffer::from_bytes_with_encoding(content, &config);
+        assert_eq!(buffer.text(), "\u{FEFF}Hi");
+        assert_eq!(buffer.encode_contents("test"), content);
+
+        // UTF-16LE BOM 之後像 UTF-16BE BOM 的內容不能改變解碼的編碼
+        let content = b"\xFF\xFE\xFE\xFFa\x00";
+        let buffer = RopeBuffer::from_bytes_with_encoding(content, &config);
+        assert_eq!(buffer.save_encoding(), encoding_rs::UTF_16LE);
+        assert_eq!(buffer.text(), "\u{FFFE}a");
+        assert_eq!(buffer.encode_contents("test"), content);
+    }
+
+    #[test]
+    fn test_bom_config_override() {
+        let temp_dir = TempDir::new().unwrap();
+        let file_path = temp_dir.path().join("test_bom_override.txt");
+        fs::write(&file_path, "Hello").unwrap();
+
+        let mut buffer = RopeBuffer::from_file_with_encoding(
+            &file_path,
+            &EncodingConfig {
+                read_encoding: None,
+                save_encoding: None,
+                bom: Some(true),
+            },
+        )
+        .unwrap();
+        assert!(buffer.has_bom());
+        buffer.save().unwrap();
+        assert_eq!(fs::read(&file_path).unwrap(), b"\xEF\xBB\xBFHello");
+
+        // 非 Unicode 編碼不寫 BOM
+        buffer.set_save_encoding(encoding_rs::GBK);
+        buffer.save().unwrap();
+        assert_eq!(fs::read(&file_path).unwrap(), b"Hello");
+    }
+
+    #[test]
+    fn test_explicit_bom_kept_when_switching_to_utf16() {
+        let temp_dir = TempDir::new().unwrap();
+        let file_path = temp_dir.path().join("test_no_bom.txt");
+        fs::write(&file_path, "Hi").unwrap();
+
+        let mut buffer = RopeBuffer::from_file_with_encoding(
+            &file_path,
+            &EncodingConfig {
+                read_encoding: None,
+                save_encoding: None,
+                bom: Some(false),
+            },
+        )
+        .unwrap();
+        buffer.set_save_encoding(encoding_rs::UTF_16LE);
+        assert!(!buffer.has_bom());
+        buffer.save().unwrap();
+        assert_eq!(fs::read(&file_path).unwrap(), b"H\0i\0");
+
+        // `:set nobom` 之後改變新檔案的編碼
+        let mut buffer = RopeBuffer::new();
+        buffer.set_bom(false);
+        buffer.change_encoding(encoding_rs::UTF_16BE);
+        assert!(!buffer.has_bom());
+
+        // 沒有指定時 UTF-16 預設加上 BOM
+        let mut buffer = RopeBuffer::new();
+        buffer.change_encoding(encoding_rs::UTF_16BE);
+        assert!(buffer.has_bom());
+    }
+
+    #[test]
+    fn test_bom_option_on_new_file_is_initial_state() {
+        let temp_dir = TempDir::new().unwrap();
+        let file_path = temp_dir.path().join("new.txt");
+        let config = |bom| EncodingConfig {
+            read_encoding: None,
+            save_encoding: None,
+            bom,
+        };
+
+        // 不存在的檔案：--bom 只決定存檔時的 BOM，不另外算成修改
+        let plain = RopeBuffer::from_file_with_encoding(&file_path, &config(None)).unwrap();
+        let with_bom =
+            RopeBuffer::from_file_with_encoding(&file_path, &config(Some(true))).unwrap();
+        assert!(with_bom.has_bom());
+        assert_eq!(with_bom.is_modified(), plain.is_modified());
+        assert_eq!(with_bom.version(), plain.version());
+
+        let mut buffer = RopeBuffer::new();
+        buffer.set_initial_bom(true);
+        assert!(buffer.has_bom());
+        assert!(!buffer.is_modified());
+        buffer.change_encoding(encoding_rs::UTF_16LE);
+        assert!(!buffer.is_modified());
+    }
+
+    #[test]
+    fn test_utf16_save_roundtrip() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = EncodingConfig {
+            read_encoding: None,
+            save_encoding: None,
+            bom: None,
+        };
+
+        for (encoding, bom) in [
+            (encoding_rs::UTF_16LE, [0xFF, 0xFE]),
+            (encoding_rs::UTF_16BE, [0xFE, 0xFF]),
+        ] {
+            let file_path = temp_dir.path().join(format!("{}.txt", encoding.name()));
+
+            let mut buffer = RopeBuffer::new();
+            buffer.set_save_encoding(encoding);
+            buffer.insert(0, "Hello, 世界! 😀\n");
+            buffer.save_to(&file_path).unwrap();
+
+            let saved_bytes = fs::read(&file_path).unwrap();
+            assert_eq!(saved_bytes[..2], bom);
+            let (decoded, _, had_errors) = encoding.decode(&saved_bytes);
+            assert!(!had_errors);
+            assert_eq!(decoded, "Hello, 世界! 😀\n");
+
+            // 重新開啟後應以同樣編碼讀取並寫回相同內容
+            let mut reopened = RopeBuffer::from_file_with_encoding(&file_path, &config).unwrap();
+            assert_eq!(reopened.save_encoding(), encoding);
+            assert_eq!(reopened.get_line_content(0), "Hello, 世界! 😀\n");
+            reopened.save().unwrap();
+            assert_eq!(fs::read(&file_path).unwrap(), saved_bytes);
+        }
+    }
+
+    #[test]
+    fn test_from_bytes_without_path() {
+        let config = EncodingConfig {
+            read_encoding: None,
+            save_encoding: None,
+            bom: None,
+        };
+        let buffer = RopeBuffer::from_bytes_with_encoding("line 1\n世界\n".as_bytes(), &config);
+        assert!(!buffer.has_file_path());
+        assert!(buffer.is_modified());
+        assert_eq!(buffer.line_count(), 3);
+        assert_eq!(buffer.get_line_content(1), "世界\n");
+        assert_eq!(buffer.save_encoding(), encoding_rs::UTF_8);
+    }
+
+    #[test]
+    fn test_encoded_len_matches_saved_bytes() {
+        let config = EncodingConfig {
+            read_encoding: None,
+            save_encoding: None,
+            bom: None,
+        };
+        let mut buffer = RopeBuffer::from_bytes_with_encoding("a中文\n€😀 x\n".as_bytes(), &config);
+        let len = buffer.len_chars();
+        assert_eq!(
+            buffer.encoded_len(0, len),
+            buffer.encode_contents("test").len()
+        );
+        assert_eq!(buffer.encoded_len(1, 3), 6);
+
+        buffer.set_bom(true);
+        assert_eq!(
+            buffer.encoded_file_len(),
+            buffer.encode_contents("test").len()
+        );
+
+        for encoding in [
+            encoding_rs::GBK,
+            encoding_rs::UTF_16LE,
+            encoding_rs::SHIFT_JIS,
+        ] {
+            buffer.set_save_encoding(encoding);
+            assert_eq!(
+                buffer.encoded_file_len(),
+                buffer.encode_contents("test").len(),
+                "{}",
+                encoding.name()
+            );
+        }
+        buffer.set_save_encoding(encoding_rs::GBK);
+        assert_eq!(buffer.encoded_len(1, 3), 4);
+    }
+
+    #[test]
+    fn test_replace_range_is_one_undo_step() {
+        let config = EncodingConfig {
+            read_encoding: None,
+            save_encoding: None,
+            bom: None,
+        };
+        let mut buffer = RopeBuffer::from_bytes_with_encoding("a  \r\n世界\n".as_bytes(), &config);
+        buffer.replace_range(1, 5, "\n");
+        assert_eq!(buffer.text(), "a\n世界\n");
+        assert_eq!(buffer.undo(), Some(1));
+        assert_eq!(buffer.text(), "a  \r\n世界\n");
+        assert!(!buffer.can_undo());
+        assert_eq!(buffer.redo(), Some(1));
+        assert_eq!(buffer.text(), "a\n世界\n");
+    }
+
+    #[test]
+    fn test_batch_is_one_undo_step() {
+        let mut buffer = RopeBuffer::new();
+        buffer.insert(0, "1\n2\n3\n4\n");
+        buffer.begin_batch();
+        buffer.delete_line(1);
+        buffer.delete_line(1);
+        buffer.end_batch();
+        assert_eq!(buffer.text(), "1\n4\n");
+        assert_eq!(buffer.last_done_description(), Some("delete 2 lines"));
+        assert_eq!(buffer.undo(), Some(2));
+        assert_eq!(buffer.text(), "1\n2\n3\n4\n");
+        assert_eq!(buffer.redo(), Some(2));
+        assert_eq!(buffer.text(), "1\n4\n");
+
+        // 重複貼上：前後內容相同的部分不算在步驟中
+        buffer.begin_batch();
+        buffer.insert(2, "x\n");
+        buffer.insert(2, "x\n");
+        buffer.end_batch();
+        assert_eq!(buffer.text(), "1\nx\nx\n4\n");
+        assert_eq!(buffer.undo(), Some(2));
+        assert_eq!(buffer.text(), "1\n4\n");
+
+        // 沒有修改時不記錄
+        buffer.begin_batch();
+        buffer.end_batch();
+        assert_eq!(buffer.last_undone_description(), Some("insert 2 lines"));
+    }
+
+    #[test]
+    fn test_marks_move_with_edits_and_undo() {
+        let mut buffer = RopeBuffer::new();
+        buffer.insert(0, "one\ntwo\nthree\n");
+        buffer.set_mark("1", buffer.line_to_char(2));
+        buffer.insert(0, "zero\n");
+        assert_eq!(buffer.char_to_line(buffer.mark("1").unwrap()), 3);
+        buffer.delete_line(1);
+        assert_eq!(buffer.char_to_line(buffer.mark("1").unwrap()), 2);
+        buffer.undo();
+        buffer.undo();
+        assert_eq!(buffer.mark("1"), Some(buffer.line_to_char(2)));
+        assert!(buffer.delete_mark("1"));
+        assert_eq!(buffer.marks().count(), 0);
+    }
+
+    #[test]
+    fn test_snapshot_save_with_concurrent_edit() {
+        let temp_dir = TempDir::new().unwrap();
+        let file_path = temp_dir.path().join("test_snapshot.txt");
+        fs::write(&file_path, "Hello").unwrap();
+
+        let config = EncodingConfig {
+            read_encoding: None,
+            save_encoding: None,
+            bom: None,
+        };
+        let mut buffer = RopeBuffer::from_file_with_encoding(&file_path, &config).unwrap();
+        buffer.insert(5, " world");
+
+        let snapshot = buffer.save_snapshot().unwrap();
+        let version = snapshot.version();
+        // 快照建立後的修改不會寫入檔案，存檔完成後仍維持已修改
+        buffer.insert(11, "!");
+        std::thread::spawn(move || snapshot.write())
+            .join()
+            .unwrap()
+            .unwrap();
+        buffer.finish_save(version);
+        assert_eq!(fs::read_to_string(&file_path).unwrap(), "Hello world");
+        assert!(buffer.is_modified());
+        assert_eq!(buffer.saved_text(), "Hello world");
+
+        let snapshot = buffer.save_snapshot().unwrap();
+        snapshot.write().unwrap();
+        buffer.finish_save(snapshot.version());
+        assert_eq!(fs::read_to_string(&file_path).unwrap(), "Hello world!");
+        assert!(!buffer.is_modified());
+    }
+
     #[test]
     fn test_gbk_encoding_save() {
         let temp_dir = TempDir::new().unwrap();
@@ -813,6 +1645,7 @@ mod tests {
             &EncodingConfig {
                 read_encoding: Some(encoding_rs::GBK),
                 save_encoding: None,
+                bom: None,
             },
         )
         .unwrap();
@@ -845,6 +1678,24 @@ mod tests {
         assert_eq!(decoded, "Hello, world! ©");
     }
 
+    #[test]
+    fn test_find_unencodable_chars() {
+        let mut buffer = RopeBuffer::new();
+        buffer.change_encoding(encoding_rs::WINDOWS_1252);
+        buffer.insert(0, "café\nok\n世界 and ü\n");
+
+        // é 與 ü 可用 Windows-1252 表示，中文不行
+        let problems = buffer.find_unencodable_chars(10);
+        assert_eq!(problems, vec![(2, '世'), (2, '界')]);
+
+        // 數量上限
+        assert_eq!(buffer.find_unencodable_chars(1), vec![(2, '世')]);
+
+        // UTF-8 永遠可無損存檔
+        buffer.change_encoding(encoding_rs::UTF_8);
+        assert!(buffer.find_unencodable_chars(10).is_empty());
+    }
+
     #[test]
     fn test_big5_encoding_save() {
         let temp_dir = TempDir::new().unwrap();
@@ -867,10 +1718,117 @@ mod tests {
         // 注意：Big5 無法表示簡體中文字符，所以會有替換字符
         assert!(decoded.contains("Hello"));
     }
-}
 
-impl Default for RopeBuffer {
-    fn default() -> Self {
-        Self::new()
+    /// 隨機產生的編輯操作；位置以比例表示，套用時依當下的長度換算
+    #[derive(Debug, Clone)]
+    enum Edit {
+        InsertChar(usize, char),
+        Insert(usize, String),
+        DeleteChar(usize),
+        DeleteRange(usize, usize),
+        Replace(usize, usize, String),
+    }
+
+    /// 含多位元組字元、寬字元與各種換行的短文字
+    fn edit_text() -> impl Strategy<Value = String> {
+        "[ab\n\r\té中😀]{0,8}"
+    }
+
+    fn edit() -> impl Strategy<Value = Edit> {
+        prop_oneof![
+            (
+                any::<usize>(),
+                prop::sample::select(vec!['x', '\n', 'é', '中', '😀'])
+            )
+                .prop_map(|(pos, ch)| Edit::InsertChar(pos, ch)),
+            (any::<usize>(), edit_text()).prop_map(|(pos, text)| Edit::Insert(pos, text)),
+            any::<usize>().prop_map(Edit::DeleteChar),
+            (any::<usize>(), 0..12usize).prop_map(|(pos, len)| Edit::DeleteRange(pos, len)),
+            (any::<usize>(), 0..6usize, edit_text())
+                .prop_map(|(pos, len, text)| Edit::Replace(pos, len, text)),
+        ]
+    }
+
+    fn apply(buffer: &mut RopeBuffer, edit: &Edit) {
+        let pos = |p: usize| p % (buffer.len_chars() + 1);
+        match edit {
+            Edit::InsertChar(p, ch) => buffer.insert_char(pos(*p), *ch),
+            Edit::Insert(p, text) => buffer.insert(pos(*p), text),
+            Edit::DeleteChar(p) => buffer.delete_char(pos(*p)),
+            Edit::DeleteRange(p, len) => {
+                let start = pos(*p);
+                buffer.delete_range(start, start + len);
+            }
+            Edit::Replace(p, len, text) => {
+                let start = pos(*p);
+                buffer.replace_range(start, start + len, text);
+            }
+        }
+    }
+
+    proptest! {
+        // 每一步撤銷都回到該步之前的內容，全部撤銷後與原始文字完全相同，
+        // 重做則依序重播到同樣的狀態
+        #[test]
+        fn prop_undo_redo_round_trip(
+            initial in "[ab\n中😀]{0,20}",
+            edits in prop::collection::vec(edit(), 0..40),
+        ) {
+            let mut buffer = RopeBuffer::new();
+            buffer.insert(0, &initial);
+            // 初始內容不屬於要驗證的歷史
+            buffer.history.clear();
+
+            let mut states = vec![buffer.text()];
+            for edit in &edits {
+                let version = buffer.version();
+                apply(&mut buffer, edit);
+                // 沒有實際修改的操作（例如在結尾刪除）不會留下歷史
+                if buffer.version() != version {
+                    states.push(buffer.text());
+                }
+            }
+
+            for expected in states.iter().rev().skip(1) {
+                prop_assert!(buffer.undo().is_some());
+                prop_assert_eq!(&buffer.text(), expected);
+            }
+            prop_assert!(buffer.undo().is_none());
+            prop_assert_eq!(buffer.text(), initial);
+
+            for expected in states.iter().skip(1) {
+                prop_assert!(buffer.redo().is_some());
+                prop_assert_eq!(&buffer.text(), expected);
+            }
+            prop_assert!(buffer.redo().is_none());
+        }
+
+        // 撤銷一部分後再編輯會捨棄重做，之後全部撤銷仍能回到原始文字
+        #[test]
+        fn prop_edit_after_undo_discards_redo(
+            initial in "[ab\n中]{0,20}",
+            edits in prop::collection::vec(edit(), 1..20),
+            undo_count in 0..20usize,
+            extra in edit(),
+        ) {
+            let mut buffer = RopeBuffer::new();
+            buffer.insert(0, &initial);
+            buffer.history.clear();
+
+            for edit in &edits {
+                apply(&mut buffer, edit);
+            }
+            for _ in 0..undo_count {
+                buffer.undo();
+            }
+            let version = buffer.version();
+            apply(&mut buffer, &extra);
+            if buffer.version() != version {
+                prop_assert!(!buffer.can_redo());
+            }
+
+            while buffer.undo().is_some() {}
+            prop_assert_eq!(buffer.text(), initial);
+        }
     }
 }
@@ -0,0 +1,241 @@
+// 光標處字元的 Unicode 資訊
+//
+// 類似 vim 的 `ga`：顯示碼位、UTF-8 位元組、名稱或類別、所屬區塊與顯示寬度，
+// 用來找出破壞排版的不可見字元（零寬空白、BOM、方向控制字元）與模糊寬度符號。
+// 沒有內建完整的 Unicode 名稱表，只為常見的空白、控制與格式字元提供名稱，其他字元顯示類別
+
+use unicode_width::UnicodeWidthChar;
+
+/// 常見的不可見、空白與容易混淆的字元名稱
+fn known_name(ch: char) -> Option<&'static str> {
+    Some(match ch {
+        '\0' => "NULL",
+        '\x07' => "BELL",
+        '\x08' => "BACKSPACE",
+        '\t' => "CHARACTER TABULATION",
+        '\n' => "LINE FEED",
+        '\x0B' => "LINE TABULATION",
+        '\x0C' => "FORM FEED",
+        '\r' => "CARRIAGE RETURN",
+        '\x1B' => "ESCAPE",
+        ' ' => "SPACE",
+        '\x7F' => "DELETE",
+        '\u{85}' => "NEXT LINE",
+        '\u{A0}' => "NO-BREAK SPACE",
+        '\u{AD}' => "SOFT HYPHEN",
+        '\u{34F}' => "COMBINING GRAPHEME JOINER",
+        '\u{61C}' => "ARABIC LETTER MARK",
+        '\u{1680}' => "OGHAM SPACE MARK",
+        '\u{180E}' => "MONGOLIAN VOWEL SEPARATOR",
+        '\u{2000}' => "EN QUAD",
+        '\u{2001}' => "EM QUAD",
+        '\u{2002}' => "EN SPACE",
+        '\u{2003}' => "EM SPACE",
+        '\u{2004}' => "THREE-PER-EM SPACE",
+        '\u{2005}' => "FOUR-PER-EM SPACE",
+        '\u{2006}' => "SIX-PER-EM SPACE",
+        '\u{2007}' => "FIGURE SPACE",
+        '\u{2008}' => "PUNCTUATION SPACE",
+        '\u{2009}' => "THIN SPACE",
+        '\u{200A}' => "HAIR SPACE",
+        '\u{200B}' => "ZERO WIDTH SPACE",
+        '\u{200C}' => "ZERO WIDTH NON-JOINER",
+        '\u{200D}' => "ZERO WIDTH JOINER",
+        '\u{200E}' => "LEFT-TO-RIGHT MARK",
+        '\u{200F}' => "RIGHT-TO-LEFT MARK",
+        '\u{2010}' => "HYPHEN",
+        '\u{2011}' => "NON-BREAKING HYPHEN",
+        '\u{2012}' => "FIGURE DASH",
+        '\u{2013}' => "EN DASH",
+        '\u{2014}' => "EM DASH",
+        '\u{2018}' => "LEFT SINGLE QUOTATION MARK",
+        '\u{2019}' => "RIGHT SINGLE QUOTATION MARK",
+        '\u{201C}' => "LEFT DOUBLE QUOTATION MARK",
+        '\u{201D}' => "RIGHT DOUBLE QUOTATION MARK",
+        '\u{2026}' => "HORIZONTAL ELLIPSIS",
+        '\u{2028}' => "LINE SEPARATOR",
+        '\u{2029}' => "PARAGRAPH SEPARATOR",
+        '\u{202A}' => "LEFT-TO-RIGHT EMBEDDING",
+        '\u{202B}' => "RIGHT-TO-LEFT EMBEDDING",
+        '\u{202C}' => "POP DIRECTIONAL FORMATTING",
+        '\u{202D}' => "LEFT-TO-RIGHT OVERRIDE",
+        '\u{202E}' => "RIGHT-TO-LEFT OVERRIDE",
+        '\u{202F}' => "NARROW NO-BREAK SPACE",
+        '\u{205F}' => "MEDIUM MATHEMATICAL SPACE",
+        '\u{2060}' => "WORD JOINER",
+        '\u{2066}' => "LEFT-TO-RIGHT ISOLATE",
+        '\u{2067}' => "RIGHT-TO-LEFT ISOLATE",
+        '\u{2068}' => "FIRST STRONG ISOLATE",
+        '\u{2069}' => "POP DIRECTIONAL ISOLATE",
+        '\u{2212}' => "MINUS SIGN",
+        '\u{3000}' => "IDEOGRAPHIC SPACE",
+        '\u{FE0E}' => "VARIATION SELECTOR-15 (text style)",
+        '\u{FE0F}' => "VARIATION SELECTOR-16 (emoji style)",
+        '\u{FEFF}' => "ZERO WIDTH NO-BREAK SPACE (BOM)",
+        '\u{FFFC}' => "OBJECT REPLACEMENT CHARACTER",
+        '\u{FFFD}' => "REPLACEMENT CHARACTER",
+        _ => return None,
+    })
+}
+
+/// 字元的大致類別（依 Rust 標準函式庫與常見的碼位範圍判斷）
+fn category(ch: char) -> &'static str {
+    match ch {
+        _ if ch.is_control() => "control",
+        '\u{0300}'..='\u{036F}'
+        | '\u{1AB0}'..='\u{1AFF}'
+        | '\u{1DC0}'..='\u{1DFF}'
+        | '\u{20D0}'..='\u{20FF}'
+        | '\u{FE20}'..='\u{FE2F}' => "combining mark",
+        '\u{FE00}'..='\u{FE0F}' | '\u{E0100}'..='\u{E01EF}' => "variation selector",
+        '\u{E000}'..='\u{F8FF}' | '\u{F0000}'..='\u{10FFFF}' => "private use",
+        _ if ch.is_whitespace() => "space",
+        _ if ch.is_uppercase() => "uppercase letter",
+        _ if ch.is_lowercase() => "lowercase letter",
+        _ if ch.is_alphabetic() => "letter",
+        _ if ch.is_numeric() => "number",
+        _ if ch.is_ascii_punctuation() => "punctuation",
+        _ if UnicodeWidthChar::width(ch) == Some(0) => "format",
+        _ => "symbol or punctuation",
+    }
+}
+
+/// 常見的 Unicode 區塊
+fn block(ch: char) -> Option<&'static str> {
+    Some(match ch {
+        '\0'..='\x7F' => "Basic Latin",
+        '\u{80}'..='\u{FF}' => "Latin-1 Supplement",
+        '\u{100}'..='\u{24F}' => "Latin Extended",
+        '\u{250}'..='\u{2AF}' => "IPA Extensions",
+        '\u{2B0}'..='\u{2FF}' => "Spacing Modifier Letters",
+        '\u{300}'..='\u{36F}' => "Combining Diacritical Marks",
+        '\u{370}'..='\u{3FF}' => "Greek and Coptic",
+        '\u{400}'..='\u{52F}' => "Cyrillic",
+        '\u{590}'..='\u{5FF}' => "Hebrew",
+        '\u{600}'..='\u{6FF}' => "Arabic",
+        '\u{900}'..='\u{97F}' => "Devanagari",
+        '\u{E00}'..='\u{E7F}' => "Thai",
+        '\u{1100}'..='\u{11FF}' => "Hangul Jamo",
+        '\u{1E00}'..='\u{1EFF}' => "Latin Extended Additional",
+        '\u{2000}'..='\u{206F}' => "General Punctuation",
+        '\u{2070}'..='\u{209F}' => "Superscripts and Subscripts",
+        '\u{20A0}'..='\u{20CF}' => "Currency Symbols",
+        '\u{2100}'..='\u{214F}' => "Letterlike Symbols",
+        '\u{2150}'..='\u{218F}' => "Number Forms",
+        '\u{2190}'..='\u{21FF}' => "Arrows",
+        '\u{2200}'..='\u{22FF}' => "Mathematical Operators",
+        '\u{2300}'..='\u{23FF}' => "Miscellaneous Technical",
+        '\u{2460}'..='\u{24FF}' => "Enclosed Alphanumerics",
+        '\u{2500}'..='\u{257F}' => "Box Drawing",
+        '\u{2580}'..='\u{259F}' => "Block Elements",
+        '\u{25A0}'..='\u{25FF}' => "Geometric Shapes",
+        '\u{2600}'..='\u{26FF}' => "Miscellaneous Symbols",
+        '\u{2700}'..='\u{27BF}' => "Dingbats",
+        '\u{2E80}'..='\u{2FDF}' => "CJK Radicals",
+        '\u{3000}'..='\u{303F}' => "CJK Symbols and Punctuation",
+        '\u{3040}'..='\u{309F}' => "Hiragana",
+        '\u{30A0}'..='\u{30FF}' => "Katakana",
+        '\u{3100}'..='\u{312F}' | '\u{31A0}'..='\u{31BF}' => "Bopomofo",
+        '\u{3400}'..='\u{4DBF}' => "CJK Unified Ideographs Extension A",
+        '\u{4E00}'..='\u{9FFF}' => "CJK Unified Ideographs",
+        '\u{AC00}'..='\u{D7AF}' => "Hangul Syllables",
+        '\u{E000}'..='\u{F8FF}' => "Private Use Area",
+        '\u{F900}'..='\u{FAFF}' => "CJK Compatibility Ideographs",
+        '\u{FE00}'..='\u{FE0F}' => "Variation Selectors",
+        '\u{FE30}'..='\u{FE4F}' => "CJK Compatibility Forms",
+        '\u{FF00}'..='\u{FFEF}' => "Halfwidth and Fullwidth Forms",
+        '\u{FFF0}'..='\u{FFFF}' => "Specials",
+        '\u{1F300}'..='\u{1F5FF}' => "Miscellaneous Symbols and Pictographs",
+        '\u{1F600}'..='\u{1F64F}' => "Emoticons",
+        '\u{1F680}'..='\u{1F6FF}' => "Transport and Map Symbols",
+        '\u{1F900}'..='\u{1F9FF}' => "Supplemental Symbols and Pictographs",
+        '\u{20000}'..='\u{3FFFF}' => "CJK Unified Ideographs Extension",
+        _ => return None,
+    })
+}
+
+/// 一個字元的說明，例如 `'é' U+00E9 UTF-8 C3 A9, lowercase letter, width 1, Latin-1 Supplement`
+///
+/// `joined` 是緊接在後、畫在同一格的零寬字元（組合符號、變體選擇符），列在碼位之後。
+/// `ambiguous_wide` 為目前 `ambiwidth` 的設定；模糊寬度字元會標出兩種設定下的寬度。
+/// 訊息列放不下時會被截斷，所以較少用到的區塊名稱放在最後
+pub fn describe(ch: char, joined: &[char], ambiguous_wide: bool) -> String {
+    let mut utf8 = [0u8; 4];
+    let bytes: Vec<String> = ch
+        .encode_utf8(&mut utf8)
+        .bytes()
+        .map(|b| format!("{:02X}", b))
+        .collect();
+    let narrow = UnicodeWidthChar::width(ch);
+    let wide = UnicodeWidthChar::width_cjk(ch);
+
+    // 可以直接顯示的字元加上引號；不可見的字元只顯示碼位與名稱
+    let mut text = match ch {
+        _ if ch.is_control() || ch.is_whitespace() || narrow == Some(0) => String::new(),
+        _ => format!(
+            "'{}' ",
+            std::iter::once(&ch).chain(joined).collect::<String>()
+        ),
+    };
+    text.push_str(&format!("U+{:04X}", ch as u32));
+    for c in joined {
+        text.push_str(&format!(" + U+{:04X}", *c as u32));
+    }
+    text.push_str(&format!(
+        " UTF-8 {}, {}",
+        bytes.join(" "),
+        known_name(ch).unwrap_or_else(|| category(ch))
+    ));
+    match (narrow, wide) {
+        (None, _) => {}
+        (Some(narrow), Some(wide)) if narrow != wide => text.push_str(&format!(
+            ", width {} (ambiguous: {} narrow, {} wide)",
+            if ambiguous_wide { wide } else { narrow },
+            narrow,
+            wide
+        )),
+        (Some(width), _) => text.push_str(&format!(", width {}", width)),
+    }
+    if let Some(block) = block(ch) {
+        text.push_str(", ");
+        text.push_str(block);
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_describe_characters() {
+        assert_eq!(
+            describe('é', &[], false),
+            "'é' U+00E9 UTF-8 C3 A9, lowercase letter, width 1, Latin-1 Supplement"
+        );
+        assert_eq!(
+            describe('\u{200B}', &[], false),
+            "U+200B UTF-8 E2 80 8B, ZERO WIDTH SPACE, width 0, General Punctuation"
+        );
+        assert_eq!(
+            describe('中', &[], false),
+            "'中' U+4E2D UTF-8 E4 B8 AD, letter, width 2, CJK Unified Ideographs"
+        );
+        assert_eq!(
+            describe('\t', &[], false),
+            "U+0009 UTF-8 09, CHARACTER TABULATION, Basic Latin"
+        );
+        assert_eq!(
+            describe('e', &['\u{301}'], false),
+            "'e\u{301}' U+0065 + U+0301 UTF-8 65, lowercase letter, width 1, Basic Latin"
+        );
+    }
+
+    #[test]
+    fn test_describe_ambiguous_width() {
+        assert!(describe('±', &[], false).contains("width 1 (ambiguous: 1 narrow, 2 wide)"));
+        assert!(describe('±', &[], true).contains("width 2 (ambiguous: 1 narrow, 2 wide)"));
+        assert!(describe('😀', &[], false)
+            .contains("UTF-8 F0 9F 98 80, symbol or punctuation, width 2, Emoticons"));
+    }
+}
@@ -0,0 +1,550 @@
+// 子命令
+//
+// `wedi edit`（省略子命令時的預設）開啟編輯器；其餘子命令不進入全螢幕，
+// 處理檔案後直接結束：`wedi diff`、`wedi convert`、`wedi highlight`。
+// `--set KEY=VALUE` 為全域選項，可以放在子命令之前，效果與編輯器中的 `:set` 相同
+
+use crate::buffer::{EncodingConfig, RopeBuffer};
+use anyhow::{bail, Context, Result};
+use pico_args::Arguments;
+use std::ffi::OsString;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// 子命令
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Subcommand {
+    Edit,
+    Diff,
+    ConvertEncoding,
+    #[cfg(feature = "syntax-highlighting")]
+    HighlightExport,
+}
+
+impl Subcommand {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "edit" => Some(Self::Edit),
+            "diff" => Some(Self::Diff),
+            "convert" | "convert-encoding" => Some(Self::ConvertEncoding),
+            #[cfg(feature = "syntax-highlighting")]
+            "highlight" | "highlight-export" => Some(Self::HighlightExport),
+            _ => None,
+        }
+    }
+}
+
+/// 命令列參數：子命令、全域的 `--set` 與子命令的其餘參數
+#[derive(Debug)]
+pub struct Cli {
+    pub subcommand: Subcommand,
+    pub settings: Vec<String>,
+    pub args: Arguments,
+}
+
+impl Cli {
+    pub fn from_env() -> Result<Self> {
+        Self::from_args(std::env::args_os().skip(1).collect())
+    }
+
+    /// 開頭的 `--set` 之後第一個參數若為子命令名稱則取出；否則視為 `edit`，
+    /// 讓 `wedi [OPTIONS] FILE` 維持原本的用法（名稱與子命令相同的檔案以 `./diff` 開啟）
+    fn from_args(mut args: Vec<OsString>) -> Result<Self> {
+        let mut settings = Vec::new();
+        while let Some(first) = args.first().and_then(|arg| arg.to_str()) {
+            if let Some(setting) = first.strip_prefix("--set=") {
+                settings.push(setting.to_string());
+                args.remove(0);
+            } else if first == "--set" {
+                args.remove(0);
+                if args.is_empty() {
+                    bail!("--set requires KEY[=VALUE]");
+                }
+                settings.push(args.remove(0).to_string_lossy().into_owned());
+            } else {
+                break;
+            }
+        }
+
+        let named = args
+            .first()
+            .and_then(|arg| arg.to_str())
+            .and_then(Subcommand::from_name);
+        let subcommand = match named {
+            Some(subcommand) => {
+                args.remove(0);
+                subcommand
+            }
+            None => Subcommand::Edit,
+        };
+
+        let mut args = Arguments::from_vec(args);
+        settings.extend(args.values_from_str::<_, String>("--set")?);
+        Ok(Self {
+            subcommand,
+            settings,
+            args,
+        })
+    }
+}
+
+/// 非編輯子命令不接受 `--set`
+fn reject_settings(settings: &[String], name: &str) -> Result<()> {
+    if let Some(setting) = settings.first() {
+        bail!(
+            "--set {} only applies to `wedi edit`, not `wedi {}`",
+            setting,
+            name
+        );
+    }
+    Ok(())
+}
+
+/// 取出剩餘的位置參數；還有未處理的選項時失敗
+fn remaining(args: Arguments, usage: &str) -> Result<Vec<PathBuf>> {
+    let rest = args.finish();
+    if let Some(flag) = rest.iter().find(|arg| {
+        arg.to_str()
+            .is_some_and(|s| s.starts_with('-') && s.len() > 1)
+    }) {
+        bail!("Unknown option {:?}\n\nUsage: {}", flag, usage);
+    }
+    Ok(rest.into_iter().map(PathBuf::from).collect())
+}
+
+/// 取出剩餘的位置參數，數量必須符合
+fn positional(args: Arguments, names: &[&str], usage: &str) -> Result<Vec<PathBuf>> {
+    let paths = remaining(args, usage)?;
+    if paths.len() != names.len() {
+        bail!("Expected {}\n\nUsage: {}", names.join(" and "), usage);
+    }
+    Ok(paths)
+}
+
+/// 讀取檔案（編碼自動檢測或依 `encoding` 指定）
+fn read_file(path: &Path, encoding: &EncodingConfig) -> Result<RopeBuffer> {
+    if !path.is_file() {
+        bail!("No such file: {}", path.display());
+    }
+    RopeBuffer::from_file_with_encoding(path, encoding)
+}
+
+const DIFF_USAGE: &str = "wedi diff [-e ENCODING] OLD NEW";
+
+/// `wedi diff`：以 unified diff 格式輸出兩個檔案的差異
+///
+/// 與 `diff` 相同，返回值 0 表示相同、1 表示有差異
+pub fn run_diff(cli: Cli) -> Result<i32> {
+    let mut args = cli.args;
+    if args.contains(["-h", "--help"]) {
+        println!("Compare two files (decoded with their own encodings) as a unified diff");
+        println!();
+        println!("USAGE:");
+        println!("    {}", DIFF_USAGE);
+        println!();
+        println!("OPTIONS:");
+        println!("    -e, --encoding <ENCODING>    Read both files with this encoding");
+        println!("                                 (default: detect each file's encoding)");
+        return Ok(0);
+    }
+    reject_settings(&cli.settings, "diff")?;
+
+    let encoding: Option<String> = args.opt_value_from_str(["-e", "--encoding"])?;
+    let encoding = crate::parse_encoding(encoding.as_deref(), None, None)?;
+    let paths = positional(args, &["OLD", "NEW"], DIFF_USAGE)?;
+
+    let old = read_file(&paths[0], &encoding)?.text();
+    let new = read_file(&paths[1], &encoding)?.text();
+    let lines = crate::diff::unified_diff(
+        &old,
+        &new,
+        &paths[0].display().to_string(),
+        &paths[1].display().to_string(),
+    );
+
+    let mut stdout = std::io::stdout().lock();
+    for line in &lines {
+        writeln!(stdout, "{}", line)?;
+    }
+    Ok(if lines.is_empty() { 0 } else { 1 })
+}
+
+const CONVERT_USAGE: &str = "wedi convert [-f ENCODING] -t ENCODING [--bom|--no-bom] \
+[--eol lf|crlf] [--lossy] [-o OUTPUT | --in-place] FILE...";
+
+/// 轉換後的換行符
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Eol {
+    Lf,
+    Crlf,
+}
+
+impl std::str::FromStr for Eol {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "lf" | "unix" => Ok(Self::Lf),
+            "crlf" | "dos" | "windows" => Ok(Self::Crlf),
+            _ => bail!("Invalid line ending: {} (use lf or crlf)", s),
+        }
+    }
+}
+
+/// `wedi convert`（或 `convert-encoding`）：以另一種編碼重新寫出檔案
+///
+/// 單一檔案預設輸出到 stdout；多個檔案需要 `-o DIR`（寫到目錄中的同名檔案）或 `--in-place`。
+/// 某個檔案失敗時繼續處理其餘檔案，最後返回 1
+pub fn run_convert_encoding(cli: Cli) -> Result<i32> {
+    let mut args = cli.args;
+    if args.contains(["-h", "--help"]) {
+        println!("Re-encode files");
+        println!();
+        println!("USAGE:");
+        println!("    {}", CONVERT_USAGE);
+        println!();
+        println!("OPTIONS:");
+        println!("    -f, --from-encoding <ENCODING>    Encoding of FILE (default: detect)");
+        println!("    -t, --to-encoding <ENCODING>      Encoding to write");
+        println!("    --bom, --no-bom                   Add or strip the byte order mark");
+        println!("                                      (default: keep the file's original BOM)");
+        println!("    --eol <EOL>                       Convert line endings to lf or crlf");
+        println!("                                      (default: keep them)");
+        println!("    --lossy                           Replace characters the target encoding");
+        println!("                                      cannot represent instead of failing");
+        println!("    -o, --output <PATH>               Write to PATH instead of stdout; with");
+        println!("                                      several files, PATH is a directory");
+        println!("    -i, --in-place                    Overwrite each FILE");
+        return Ok(0);
+    }
+    reject_settings(&cli.settings, "convert")?;
+
+    let from: Option<String> = args.opt_value_from_str(["-f", "--from-encoding"])?;
+    let to: Option<String> = args.opt_value_from_str(["-t", "--to-encoding"])?;
+    let Some(to) = to else {
+        bail!("Missing --to-encoding\n\nUsage: {}", CONVERT_USAGE);
+    };
+    let bom = if args.contains("--bom") {
+        Some(true)
+    } else if args.contains("--no-bom") {
+        Some(false)
+    } else {
+        None
+    };
+    let eol: Option<Eol> = args.opt_value_from_str("--eol")?;
+    let lossy = args.contains("--lossy");
+    let in_place = args.contains(["-i", "--in-place"]);
+    let output: Option<PathBuf> = args.opt_value_from_os_str(["-o", "--output"], |s| {
+        Ok::<_, std::convert::Infallible>(PathBuf::from(s))
+    })?;
+    let paths = remaining(args, CONVERT_USAGE)?;
+    if paths.is_empty() {
+        bail!("Expected FILE\n\nUsage: {}", CONVERT_USAGE);
+    }
+
+    if in_place && output.is_some() {
+        bail!("--in-place and --output cannot be used together");
+    }
+    let batch = paths.len() > 1;
+    if batch && !in_place && !output.as_deref().is_some_and(Path::is_dir) {
+        bail!(
+            "Converting several files needs --in-place or an existing directory for --output\n\nUsage: {}",
+            CONVERT_USAGE
+        );
+    }
+
+    let encoding = crate::parse_encoding(from.as_deref(), Some(&to), bom)?;
+    let mut failed = false;
+    for path in &paths {
+        let target = match (&output, in_place) {
+            (_, true) => Some(path.clone()),
+            (Some(dir), false) if batch => Some(dir.join(path.file_name().unwrap_or_default())),
+            (output, false) => output.clone(),
+        };
+        let result = read_file(path, &encoding)
+            .and_then(|mut buffer| {
+                if let Some(eol) = eol {
+                    set_eol(&mut buffer, eol);
+                }
+                convert(&buffer, lossy)
+            })
+            .and_then(|bytes| write_output(target.as_deref(), &bytes));
+        if let Err(e) = result {
+            // 單一檔案時錯誤直接返回；批次時回報後繼續處理其餘檔案
+            if !batch {
+                return Err(e);
+            }
+            eprintln!("wedi: {}: {:#}", path.display(), e);
+            failed = true;
+        }
+    }
+    Ok(if failed { 1 } else { 0 })
+}
+
+/// 寫到檔案，None 時寫到 stdout
+fn write_output(path: Option<&Path>, bytes: &[u8]) -> Result<()> {
+    match path {
+        Some(path) => std::fs::write(path, bytes)
+            .with_context(|| format!("Failed to write {}", path.display())),
+        None => {
+            let mut stdout = std::io::stdout().lock();
+            stdout.write_all(bytes)?;
+            stdout.flush()?;
+            Ok(())
+        }
+    }
+}
+
+/// 將緩衝區中的換行符統一為 `eol`（單獨的 `\r` 保持不變）
+fn set_eol(buffer: &mut RopeBuffer, eol: Eol) {
+    let text = buffer.text();
+    let unix = text.replace("\r\n", "\n");
+    let converted = match eol {
+        Eol::Lf => unix,
+        Eol::Crlf => unix.replace('\n', "\r\n"),
+    };
+    if converted != text {
+        buffer.delete_range(0, buffer.len_chars());
+        buffer.insert(0, &converted);
+    }
+}
+
+/// 以緩衝區的存檔編碼編碼內容；有無法表示的字元且不允許取代時失敗
+fn convert(buffer: &RopeBuffer, lossy: bool) -> Result<Vec<u8>> {
+    if !lossy {
+        if let Some(&(row, ch)) = buffer.find_unencodable_chars(1).first() {
+            bail!(
+                "{:?} on line {} cannot be encoded in {} (use --lossy to replace such characters)",
+                ch,
+                row + 1,
+                buffer.save_encoding().name()
+            );
+        }
+    }
+    Ok(buffer.encode_contents("stdout"))
+}
+
+#[cfg(feature = "syntax-highlighting")]
+const EXPORT_USAGE: &str =
+    "wedi highlight [--theme THEME] [--format ansi|html] [--syntax SYNTAX] FILE|-";
+
+/// `wedi highlight`（或 `highlight-export`）：輸出語法高亮後的檔案（終端色碼或 HTML）
+///
+/// FILE 為 `-` 時從 stdin 讀取，語法由 `--syntax` 指定或依內容推斷
+#[cfg(feature = "syntax-highlighting")]
+pub fn run_highlight_export(cli: Cli) -> Result<i32> {
+    use crate::highlight::HighlightEngine;
+
+    let mut args = cli.args;
+    if args.contains(["-h", "--help"]) {
+        println!("Print a file with syntax highlighting");
+        println!();
+        println!("USAGE:");
+        println!("    {}", EXPORT_USAGE);
+        println!();
+        println!("OPTIONS:");
+        println!("    --theme <THEME>      Highlighting theme (see wedi --list-themes)");
+        println!("    --format <FORMAT>    ansi (terminal colors, default) or html");
+        println!("    --syntax <SYNTAX>    Syntax name or file extension (e.g. rust, py)");
+        println!("                         (default: detect from the file name and content)");
+        println!("    -e, --encoding <ENCODING>    Encoding of FILE (default: detect)");
+        return Ok(0);
+    }
+    reject_settings(&cli.settings, "highlight")?;
+
+    let theme: Option<String> = args.opt_value_from_str("--theme")?;
+    let format: Option<String> = args.opt_value_from_str("--format")?;
+    let html = match format.as_deref() {
+        None | Some("ansi") => false,
+        Some("html") => true,
+        Some(other) => bail!("Unknown format: {} (use ansi or html)", other),
+    };
+    let syntax: Option<String> = args.opt_value_from_str("--syntax")?;
+    let encoding: Option<String> = args.opt_value_from_str(["-e", "--encoding"])?;
+    let encoding = crate::parse_encoding(encoding.as_deref(), None, None)?;
+    let paths = positional(args, &["FILE"], EXPORT_USAGE)?;
+    let stdin = paths[0].as_os_str() == "-";
+    let buffer = if stdin {
+        let mut bytes = Vec::new();
+        std::io::Read::read_to_end(&mut std::io::stdin(), &mut bytes)?;
+        RopeBuffer::from_bytes_with_encoding(&bytes, &encoding)
+    } else {
+        read_file(&paths[0], &encoding)?
+    };
+
+    // HTML 需要 RGB 顏色；終端輸出依終端能力選擇
+    let true_color = html || crate::highlight::supports_true_color();
+    let mut engine = HighlightEngine::new(theme.as_deref(), true_color)?;
+    match &syntax {
+        Some(syntax) => engine.set_syntax(syntax)?,
+        None if stdin => engine.set_file(None),
+        None => engine.set_file(Some(&paths[0])),
+    }
+    if !engine.is_enabled() {
+        let head: String = (0..crate::filetype::SNIFF_LINES.min(buffer.line_count()))
+            .map(|row| buffer.get_line_full(row))
+            .collect();
+        if let Some(ext) = crate::filetype::detect_extension_from_content(&head) {
+            engine.set_fallback_extension(ext);
+        }
+    }
+    let mut highlighter = engine.create_highlighter();
+
+    let mut stdout = std::io::stdout().lock();
+    if html {
+        writeln!(stdout, "<pre>")?;
+    }
+    for row in 0..buffer.line_count() {
+        let mut line = buffer.get_line_full(row);
+        if line.is_empty() && row + 1 == buffer.line_count() {
+            break;
+        }
+        // syntect 需要換行符才能正確解析語法狀態
+        if !line.ends_with('\n') {
+            line.push('\n');
+        }
+        let styled = match highlighter.as_mut() {
+            Some(highlighter) => highlighter.highlight_line(&line),
+            None => crate::styled::StyledLine::plain(line.trim_end_matches(['\n', '\r'])),
+        };
+        if html {
+            writeln!(stdout, "{}", to_html(&styled))?;
+        } else {
+            writeln!(stdout, "{}", styled.to_ansi())?;
+        }
+    }
+    if html {
+        writeln!(stdout, "</pre>")?;
+    }
+    Ok(0)
+}
+
+/// 將一行轉為 HTML（特殊字元跳脫，顏色以 inline style 表示）
+#[cfg(feature = "syntax-highlighting")]
+fn to_html(line: &crate::styled::StyledLine) -> String {
+    use crossterm::style::Color;
+
+    let mut output = String::new();
+    for span in &line.spans {
+        let text = span
+            .text
+            .replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;");
+        let mut css = Vec::new();
+        if let Some(Color::Rgb { r, g, b }) = span.style.foreground {
+            css.push(format!("color:#{:02x}{:02x}{:02x}", r, g, b));
+        }
+        if span.style.underline {
+            css.push("text-decoration:underline".to_string());
+        }
+        if css.is_empty() {
+            output.push_str(&text);
+        } else {
+            output.push_str(&format!(
+                "<span style=\"{}\">{}</span>",
+                css.join(";"),
+                text
+            ));
+        }
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cli(args: &[&str]) -> Cli {
+        Cli::from_args(args.iter().map(OsString::from).collect()).unwrap()
+    }
+
+    #[test]
+    fn test_subcommand_and_global_settings() {
+        let parsed = cli(&["--set", "cc=80", "diff", "a", "b"]);
+        assert_eq!(parsed.subcommand, Subcommand::Diff);
+        assert_eq!(parsed.settings, ["cc=80"]);
+        assert_eq!(parsed.args.finish(), ["a", "b"]);
+
+        // 沒有子命令時為 edit，選項與檔名照舊；--set 也可以放在後面
+        let parsed = cli(&[
+            "--set=cursorline",
+            "--ruler",
+            "notes.txt",
+            "--set",
+            "nu=off",
+        ]);
+        assert_eq!(parsed.subcommand, Subcommand::Edit);
+        assert_eq!(parsed.settings, ["cursorline", "nu=off"]);
+        assert_eq!(parsed.args.finish(), ["--ruler", "notes.txt"]);
+
+        let parsed = cli(&["./diff"]);
+        assert_eq!(parsed.subcommand, Subcommand::Edit);
+        assert!(Cli::from_args(vec![OsString::from("--set")]).is_err());
+    }
+
+    #[test]
+    fn test_convert_rejects_unencodable_chars() {
+        let encoding = crate::parse_encoding(None, Some("gbk"), None).unwrap();
+        let buffer = RopeBuffer::from_bytes_with_encoding("中文\nok ✓\n".as_bytes(), &encoding);
+        let error = convert(&buffer, false).unwrap_err().to_string();
+        assert!(error.contains("line 2"), "{}", error);
+
+        let bytes = convert(&buffer, true).unwrap();
+        assert_eq!(&bytes[..4], &[0xD6, 0xD0, 0xCE, 0xC4]);
+    }
+
+    #[test]
+    fn test_encoding_labels() {
+        for label in crate::editor::ENCODING_LABELS {
+            assert!(
+                crate::parse_encoding(Some(label), None, None).is_ok(),
+                "Unknown encoding label: {}",
+                label
+            );
+        }
+
+        // 不認得的編碼列出可用的名稱
+        let error = crate::parse_encoding(Some("utf-9"), None, None)
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("utf-9"), "{}", error);
+        assert!(error.contains("shift-jis"), "{}", error);
+    }
+
+    #[test]
+    fn test_set_eol() {
+        let encoding = crate::parse_encoding(None, None, None).unwrap();
+        let mut buffer = RopeBuffer::from_bytes_with_encoding(b"a\r\nb\nc\rd", &encoding);
+        set_eol(&mut buffer, Eol::Crlf);
+        assert_eq!(buffer.text(), "a\r\nb\r\nc\rd");
+        set_eol(&mut buffer, Eol::Lf);
+        assert_eq!(buffer.text(), "a\nb\nc\rd");
+        assert!("mac".parse::<Eol>().is_err());
+    }
+
+    #[cfg(feature = "syntax-highlighting")]
+    #[test]
+    fn test_to_html_escapes_and_colors() {
+        use crate::styled::{SpanStyle, StyledLine};
+        use crossterm::style::Color;
+
+        let mut line = StyledLine::new();
+        line.push(
+            "if",
+            SpanStyle {
+                foreground: Some(Color::Rgb {
+                    r: 255,
+                    g: 0,
+                    b: 16,
+                }),
+                ..SpanStyle::default()
+            },
+        );
+        line.push(" a < b && c", SpanStyle::default());
+        assert_eq!(
+            to_html(&line),
+            "<span style=\"color:#ff0010\">if</span> a &lt; b &amp;&amp; c"
+        );
+    }
+}
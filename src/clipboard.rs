@@ -1,4 +1,6 @@
-use anyhow::{anyhow, Result};
+#[cfg(windows)]
+use anyhow::anyhow;
+use anyhow::Result;
 
 // ────────────────────────────────────────────────────────────────
 // Clipboard Manager
@@ -53,47 +55,22 @@ impl ClipboardManager {
             Ok(())
         }
 
-        #[cfg(target_os = "macos")]
+        #[cfg(all(unix, feature = "system-clipboard"))]
         {
-            let mut child = std::process::Command::new("pbcopy")
-                .stdin(std::process::Stdio::piped())
-                .spawn()?;
-
-            if let Some(stdin) = child.stdin.as_mut() {
-                std::io::Write::write_all(stdin, text.as_bytes())?;
+            let mut last_err = anyhow::anyhow!("No system clipboard available");
+            for (program, args) in copy_commands() {
+                match run_copy(program, args, text) {
+                    Ok(()) => return Ok(()),
+                    Err(e) => last_err = e,
+                }
             }
-
-            child.wait()?;
-            Ok(())
+            Err(last_err)
         }
 
-        #[cfg(all(unix, not(target_os = "macos")))]
+        #[cfg(all(unix, not(feature = "system-clipboard")))]
         {
-            // Try wl-copy first, then xclip
-            let result = std::process::Command::new("wl-copy")
-                .stdin(std::process::Stdio::piped())
-                .spawn()
-                .and_then(|mut child| {
-                    if let Some(stdin) = child.stdin.as_mut() {
-                        std::io::Write::write_all(stdin, text.as_bytes())?;
-                    }
-                    child.wait()
-                });
-
-            if result.is_err() {
-                // Fallback to xclip
-                let mut child = std::process::Command::new("xclip")
-                    .args(&["-selection", "clipboard"])
-                    .stdin(std::process::Stdio::piped())
-                    .spawn()?;
-
-                if let Some(stdin) = child.stdin.as_mut() {
-                    std::io::Write::write_all(stdin, text.as_bytes())?;
-                }
-
-                child.wait()?;
-            }
-            Ok(())
+            let _ = text;
+            anyhow::bail!("System clipboard support is disabled in this build")
         }
     }
 
@@ -139,33 +116,152 @@ impl ClipboardManager {
             }
         }
 
-        #[cfg(target_os = "macos")]
+        #[cfg(all(unix, feature = "system-clipboard"))]
         {
-            let output = std::process::Command::new("pbpaste").output()?;
-            Ok(String::from_utf8_lossy(&output.stdout).to_string())
+            let mut last_err = anyhow::anyhow!("No system clipboard available");
+            for (program, args) in paste_commands() {
+                match run_paste(program, args) {
+                    Ok(text) => return Ok(text),
+                    Err(e) => last_err = e,
+                }
+            }
+            Err(last_err)
         }
 
-        #[cfg(all(unix, not(target_os = "macos")))]
+        #[cfg(all(unix, not(feature = "system-clipboard")))]
+        {
+            anyhow::bail!("System clipboard support is disabled in this build")
+        }
+    }
+
+    /// 複製與貼上會嘗試的外部程式（不重複）；Windows 直接使用系統 API，沒有外部程式
+    pub fn programs(&self) -> Vec<&'static str> {
+        #[cfg(all(unix, feature = "system-clipboard"))]
         {
-            // Try wl-paste first, then xclip
-            let result = std::process::Command::new("wl-paste").output();
-
-            match result {
-                Ok(output) => Ok(String::from_utf8_lossy(&output.stdout).to_string()),
-                Err(_) => {
-                    // Fallback to xclip
-                    let output = std::process::Command::new("xclip")
-                        .args(&["-selection", "clipboard", "-o"])
-                        .output()?;
-                    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+            let mut programs = Vec::new();
+            for (program, _) in copy_commands().into_iter().chain(paste_commands()) {
+                if !programs.contains(&program) {
+                    programs.push(program);
                 }
             }
+            programs
+        }
+
+        #[cfg(not(all(unix, feature = "system-clipboard")))]
+        {
+            Vec::new()
         }
     }
 
+    /// 是否有可用的系統剪貼簿（不可用時編輯器改用內部剪貼簿）
     pub fn is_available(&self) -> bool {
-        true // 自製實現總是可用的
+        #[cfg(windows)]
+        {
+            true
+        }
+
+        #[cfg(all(unix, feature = "system-clipboard"))]
+        {
+            !copy_commands().is_empty()
+        }
+
+        #[cfg(all(unix, not(feature = "system-clipboard")))]
+        {
+            false
+        }
+    }
+}
+
+// ────────────────────────────────────────────────────────────────
+// 外部剪貼簿程式（macOS: pbcopy/pbpaste，Linux: wl-clipboard/xclip）
+// ────────────────────────────────────────────────────────────────
+
+/// 複製用的外部程式及參數，依序嘗試
+#[cfg(all(unix, feature = "system-clipboard"))]
+fn copy_commands() -> Vec<(&'static str, &'static [&'static str])> {
+    #[cfg(target_os = "macos")]
+    {
+        vec![("pbcopy", &[])]
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    {
+        // 沒有顯示伺服器時（SSH、救援環境）這些程式必定失敗，直接略過
+        let mut commands: Vec<(&'static str, &'static [&'static str])> = Vec::new();
+        if env_is_set("WAYLAND_DISPLAY") {
+            commands.push(("wl-copy", &[]));
+        }
+        if env_is_set("DISPLAY") {
+            commands.push(("xclip", &["-selection", "clipboard"]));
+        }
+        commands
+    }
+}
+
+/// 貼上用的外部程式及參數，依序嘗試
+#[cfg(all(unix, feature = "system-clipboard"))]
+fn paste_commands() -> Vec<(&'static str, &'static [&'static str])> {
+    #[cfg(target_os = "macos")]
+    {
+        vec![("pbpaste", &[])]
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    {
+        let mut commands: Vec<(&'static str, &'static [&'static str])> = Vec::new();
+        if env_is_set("WAYLAND_DISPLAY") {
+            commands.push(("wl-paste", &["--no-newline"]));
+        }
+        if env_is_set("DISPLAY") {
+            commands.push(("xclip", &["-selection", "clipboard", "-o"]));
+        }
+        commands
+    }
+}
+
+#[cfg(all(unix, not(target_os = "macos"), feature = "system-clipboard"))]
+fn env_is_set(name: &str) -> bool {
+    std::env::var_os(name).is_some_and(|v| !v.is_empty())
+}
+
+/// 將文字寫入外部程式的 stdin（stderr 丟棄，避免破壞編輯器畫面）
+#[cfg(all(unix, feature = "system-clipboard"))]
+fn run_copy(program: &str, args: &[&str], text: &str) -> Result<()> {
+    use std::process::{Command, Stdio};
+
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        std::io::Write::write_all(&mut stdin, text.as_bytes())?;
+    }
+
+    let status = child.wait()?;
+    if !status.success() {
+        anyhow::bail!("{} exited with {}", program, status);
+    }
+    Ok(())
+}
+
+/// 讀取外部程式的輸出作為剪貼簿內容
+#[cfg(all(unix, feature = "system-clipboard"))]
+fn run_paste(program: &str, args: &[&str]) -> Result<String> {
+    use std::process::{Command, Stdio};
+
+    let output = Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()?;
+
+    if !output.status.success() {
+        anyhow::bail!("{} exited with {}", program, output.status);
     }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
 }
 
 impl Default for ClipboardManager {
@@ -6,47 +6,57 @@ use std::path::Path;
 #[allow(dead_code)]
 #[derive(Debug, Clone, PartialEq)]
 pub enum CommentStyle {
-    Line(String), // 單行註解，如 "//"
+    Line(String),          // 單行註解，如 "//"
     Block(String, String), // 塊註解，如 "/*" 和 "*/"
 }
 
+/// 依檔案類型切換行註解
 #[allow(dead_code)]
 pub struct CommentHandler {
     style: Option<CommentStyle>,
+    guessed: bool, // 不認得的檔案類型，註解風格是預設的 #
 }
 
 #[allow(dead_code)]
 impl CommentHandler {
     pub fn new() -> Self {
-        Self { style: None }
+        Self {
+            style: None,
+            guessed: false,
+        }
     }
 
     pub fn detect_from_path(&mut self, path: &Path) {
         let extension = path.extension().and_then(|s| s.to_str());
+        self.detect_from_extension(extension);
+    }
 
-        self.style = match extension {
-            // C-style comments: //
-            Some("rs") | Some("c") | Some("cpp") | Some("cc") | Some("cxx") | Some("h")
-            | Some("hpp") | Some("java") | Some("js") | Some("ts") | Some("jsx") | Some("tsx")
-            | Some("go") | Some("cs") | Some("php") | Some("swift") | Some("kt") => {
-                Some(CommentStyle::Line("//".to_string()))
-            }
-            // Hash/Pound comments: #
-            Some("py") | Some("sh") | Some("bash") | Some("rb") | Some("pl") | Some("yaml")
-            | Some("yml") | Some("toml") | Some("ps1") | Some("r") => {
-                Some(CommentStyle::Line("#".to_string()))
-            }
-            // SQL-style comments: --
-            Some("sql") | Some("lua") | Some("hs") | Some("elm") => {
-                Some(CommentStyle::Line("--".to_string()))
-            }
-            // Batch/CMD comments: REM
-            Some("bat") | Some("cmd") => Some(CommentStyle::Line("REM".to_string())),
-            // Vim comments: "
-            Some("vim") | Some("vimrc") => Some(CommentStyle::Line("\"".to_string())),
-            // 默認使用 # 註解（適用於大多數腳本語言和配置文件）
-            _ => Some(CommentStyle::Line("#".to_string())),
-        };
+    /// 依副檔名設定註解風格（也用於內容檢測推斷出的等效副檔名）
+    pub fn detect_from_extension(&mut self, extension: Option<&str>) {
+        self.detect_from_extensions(extension);
+    }
+
+    /// 依序嘗試多個副檔名（例如手動指定的語言所對應的副檔名），使用第一個已知的註解風格
+    pub fn detect_from_extensions<'a>(&mut self, extensions: impl IntoIterator<Item = &'a str>) {
+        // 默認使用 # 註解（適用於大多數腳本語言和配置文件）
+        let style = extensions.into_iter().find_map(style_for_extension);
+        self.guessed = style.is_none();
+        self.style = style.unwrap_or_else(|| Some(CommentStyle::Line("#".to_string())));
+    }
+
+    /// 直接指定行註解前綴（例如 git 的 `core.commentChar`）
+    pub fn set_line_prefix(&mut self, prefix: &str) {
+        self.style = Some(CommentStyle::Line(prefix.to_string()));
+        self.guessed = false;
+    }
+
+    /// 已知檔案類型的行註解前綴（例如 `//`），用於自動換行時延續註解；
+    /// 不認得的檔案類型（例如 Markdown 的 `#` 是標題）返回 None
+    pub fn line_prefix(&self) -> Option<&str> {
+        match &self.style {
+            Some(CommentStyle::Line(prefix)) if !self.guessed => Some(prefix),
+            _ => None,
+        }
     }
 
     pub fn toggle_line_comment(&self, line: &str) -> Option<String> {
@@ -164,3 +174,27 @@ impl Default for CommentHandler {
         Self::new()
     }
 }
+
+/// 副檔名對應的註解風格；外層的 None 表示不認得這個副檔名
+fn style_for_extension(extension: &str) -> Option<Option<CommentStyle>> {
+    let style = match extension {
+        // C-style comments: //
+        "rs" | "c" | "cpp" | "cc" | "cxx" | "h" | "hpp" | "java" | "js" | "ts" | "jsx" | "tsx"
+        | "go" | "cs" | "php" | "swift" | "kt" | "groovy" | "gradle" => {
+            Some(CommentStyle::Line("//".to_string()))
+        }
+        // Hash/Pound comments: #
+        "py" | "sh" | "bash" | "rb" | "pl" | "yaml" | "yml" | "toml" | "ps1" | "r" | "mk"
+        | "mak" => Some(CommentStyle::Line("#".to_string())),
+        // 沒有行註解語法的格式
+        "json" | "xml" => None,
+        // SQL-style comments: --
+        "sql" | "lua" | "hs" | "elm" => Some(CommentStyle::Line("--".to_string())),
+        // Batch/CMD comments: REM
+        "bat" | "cmd" => Some(CommentStyle::Line("REM".to_string())),
+        // Vim comments: "
+        "vim" | "vimrc" => Some(CommentStyle::Line("\"".to_string())),
+        _ => return None,
+    };
+    Some(style)
+}
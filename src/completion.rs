@@ -0,0 +1,153 @@
+// 單字補全
+//
+// Ctrl+N（或接在單字後面的 Tab）以緩衝區中已經出現過的單字補全光標前的單字，
+// 候選項依出現次數排序。索引依緩衝區版本快取，內容沒有改變時不重新掃描
+
+use std::collections::HashMap;
+
+use crate::buffer::RopeBuffer;
+
+/// 選單最多列出的候選項數
+pub const MAX_CANDIDATES: usize = 50;
+
+/// 緩衝區中所有單字的出現次數
+#[derive(Debug, Default)]
+pub struct WordIndex {
+    counts: HashMap<String, usize>,
+    version: Option<u64>,      // 建立索引時的緩衝區版本，None 表示需要重建
+    extra_chars: &'static str, // 除了字母、數字與底線以外也算單字一部分的字元
+}
+
+impl WordIndex {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 依語言設定單字字元（例如 CSS 的 `-`、PHP 的 `$`），依序使用第一個已知的副檔名
+    pub fn set_language<'a>(&mut self, extensions: impl IntoIterator<Item = &'a str>) {
+        self.extra_chars = extensions
+            .into_iter()
+            .find_map(extra_chars_for_extension)
+            .unwrap_or("");
+        self.version = None;
+    }
+
+    pub fn is_word_char(&self, ch: char) -> bool {
+        ch.is_alphanumeric() || ch == '_' || self.extra_chars.contains(ch)
+    }
+
+    /// 緩衝區內容改變時重新掃描
+    pub fn refresh(&mut self, buffer: &RopeBuffer) {
+        if self.version == Some(buffer.version()) {
+            return;
+        }
+        self.counts.clear();
+        let mut word = String::new();
+        for row in 0..buffer.line_count() {
+            let Some(line) = buffer.line(row) else {
+                continue;
+            };
+            for ch in line.chars() {
+                if self.is_word_char(ch) {
+                    word.push(ch);
+                } else if !word.is_empty() {
+                    *self.counts.entry(std::mem::take(&mut word)).or_default() += 1;
+                }
+            }
+            if !word.is_empty() {
+                *self.counts.entry(std::mem::take(&mut word)).or_default() += 1;
+            }
+        }
+        self.version = Some(buffer.version());
+    }
+
+    /// 光標前的單字：返回 (開始的欄位, 單字)；光標前不是單字字元時返回 None
+    pub fn prefix_before(&self, line: &str, col: usize) -> Option<(usize, String)> {
+        let before: Vec<char> = line.chars().take(col).collect();
+        let start = before
+            .iter()
+            .rposition(|&ch| !self.is_word_char(ch))
+            .map_or(0, |i| i + 1);
+        (start < before.len()).then(|| (start, before[start..].iter().collect()))
+    }
+
+    /// 以 `prefix` 開頭的單字，依出現次數遞減（相同時依字母順序）
+    ///
+    /// `current` 是光標所在的整個單字，它本身的一次出現不算（只出現在這裡的單字不列出）；
+    /// 以數字開頭的單字（數值）不列出
+    pub fn candidates(&self, prefix: &str, current: &str) -> Vec<String> {
+        let mut found: Vec<(&str, usize)> = self
+            .counts
+            .iter()
+            .filter(|(word, _)| word.len() > prefix.len() && word.starts_with(prefix))
+            .filter(|(word, _)| !word.starts_with(|ch: char| ch.is_ascii_digit()))
+            .map(|(word, &count)| (word.as_str(), count - usize::from(word == current)))
+            .filter(|&(_, count)| count > 0)
+            .collect();
+        found.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        found
+            .into_iter()
+            .take(MAX_CANDIDATES)
+            .map(|(word, _)| word.to_string())
+            .collect()
+    }
+}
+
+/// 副檔名對應的額外單字字元；外層的 None 表示不認得這個副檔名
+fn extra_chars_for_extension(extension: &str) -> Option<&'static str> {
+    let chars = match extension {
+        // 識別字可以包含連字號
+        "css" | "scss" | "sass" | "less" | "html" | "htm" | "xml" | "lisp" | "el" | "clj"
+        | "cljs" | "scm" | "rkt" => "-",
+        // 變數的 $ 前綴
+        "php" | "sh" | "bash" | "zsh" | "ps1" | "pl" => "$",
+        "js" | "jsx" | "ts" | "tsx" | "mjs" => "$",
+        "rs" | "c" | "cpp" | "cc" | "h" | "hpp" | "java" | "go" | "cs" | "py" | "rb" | "lua"
+        | "swift" | "kt" => "",
+        _ => return None,
+    };
+    Some(chars)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn index_of(text: &str) -> WordIndex {
+        let mut buffer = RopeBuffer::new();
+        buffer.insert(0, text);
+        let mut index = WordIndex::new();
+        index.refresh(&buffer);
+        index
+    }
+
+    #[test]
+    fn test_candidates_ranked_by_frequency() {
+        let index = index_of("let value = values[0];\nvalue += valid(value);\nva 3va");
+        assert_eq!(
+            index.candidates("va", "va"),
+            vec!["value", "valid", "values"]
+        );
+        // 只出現在光標處的單字不算
+        assert_eq!(index.candidates("vali", "valid"), Vec::<String>::new());
+        assert_eq!(index.candidates("value", "value"), vec!["values"]);
+        assert_eq!(
+            index.prefix_before("x = val", 7),
+            Some((4, "val".to_string()))
+        );
+        assert_eq!(index.prefix_before("x = ", 4), None);
+    }
+
+    #[test]
+    fn test_language_word_chars() {
+        let mut buffer = RopeBuffer::new();
+        buffer.insert(0, ".nav-bar { }\n.nav-item { }\n");
+        let mut index = WordIndex::new();
+        index.refresh(&buffer);
+        assert_eq!(index.candidates("na", ""), vec!["nav"]);
+
+        index.set_language(["md", "css"]);
+        index.refresh(&buffer);
+        assert_eq!(index.candidates("nav", ""), vec!["nav-bar", "nav-item"]);
+    }
+}
@@ -1,26 +1,156 @@
-// 配置管理
-// 這個模組將在後續階段實現
+// 設定檔
+//
+// 每行一個選項，格式與 `:set` 相同（`KEY[=VALUE]`，可加上開頭的 `set`），`#` 開頭為註解：
+//
+//     colorcolumn=80,100
+//     cursorline
+//     uitheme=light
+//     ui.statusbar=white,dark_blue
+//
+// 編輯器啟動時在命令列選項之前套用，命令列的選項與 `--set` 可以覆蓋設定檔
 
+use anyhow::{Context, Result};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// 設定檔路徑：`WEDI_CONFIG` 環境變數，否則為設定目錄中的 `config`
+#[allow(dead_code)]
+pub fn path() -> Option<PathBuf> {
+    if let Some(path) = env::var_os("WEDI_CONFIG").filter(|p| !p.is_empty()) {
+        return Some(PathBuf::from(path));
+    }
+    config_dir().map(|dir| dir.join("config"))
+}
+
+/// 設定目錄：Windows 為 `%APPDATA%\wedi`，其他系統為 `$XDG_CONFIG_HOME/wedi` 或 `~/.config/wedi`
+#[allow(dead_code)]
+pub fn config_dir() -> Option<PathBuf> {
+    let non_empty = |name: &str| env::var_os(name).filter(|value| !value.is_empty());
+    let base = if cfg!(windows) {
+        PathBuf::from(non_empty("APPDATA")?)
+    } else if let Some(dir) = non_empty("XDG_CONFIG_HOME") {
+        PathBuf::from(dir)
+    } else {
+        PathBuf::from(non_empty("HOME")?).join(".config")
+    };
+    Some(base.join("wedi"))
+}
+
+/// 狀態目錄（撤銷檔等執行時產生的資料）：Windows 為 `%LOCALAPPDATA%\wedi`，
+/// 其他系統為 `$XDG_STATE_HOME/wedi` 或 `~/.local/state/wedi`
+#[allow(dead_code)]
+pub fn state_dir() -> Option<PathBuf> {
+    let non_empty = |name: &str| env::var_os(name).filter(|value| !value.is_empty());
+    let base = if cfg!(windows) {
+        PathBuf::from(non_empty("LOCALAPPDATA")?)
+    } else if let Some(dir) = non_empty("XDG_STATE_HOME") {
+        PathBuf::from(dir)
+    } else {
+        PathBuf::from(non_empty("HOME")?)
+            .join(".local")
+            .join("state")
+    };
+    Some(base.join("wedi"))
+}
+
+/// 設定檔中的選項與所在行號（1-based）
+#[allow(dead_code)]
+pub fn parse(text: &str) -> Vec<(usize, String)> {
+    text.lines()
+        .enumerate()
+        .filter_map(|(index, line)| Some((index + 1, setting(line)?.to_string())))
+        .collect()
+}
+
+/// 一行中的選項（去掉開頭的 `:` 與 `set`）；空行與註解返回 None
+fn setting(line: &str) -> Option<&str> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+    let line = line.strip_prefix(':').unwrap_or(line);
+    Some(line.strip_prefix("set ").unwrap_or(line).trim())
+}
+
+/// 在設定檔中寫入 `key=value`：取代既有的同名選項（重複的只保留第一個），沒有時加在檔尾
+///
+/// 其餘的行（包括註解）保持不變；設定檔或目錄不存在時建立
 #[allow(dead_code)]
-pub struct Config {
-    pub tab_width: usize,
-    pub line_numbers: bool,
-    pub auto_indent: bool,
-}
-
-impl Config {
-    #[allow(dead_code)]
-    pub fn new() -> Self {
-        Self {
-            tab_width: 4,
-            line_numbers: true,
-            auto_indent: true,
+pub fn save_setting(path: &Path, key: &str, value: &str) -> Result<()> {
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    };
+    let new_line = format!("{}={}", key, value);
+    let mut lines = Vec::new();
+    let mut replaced = false;
+    for line in text.lines() {
+        let existing = setting(line).map(|s| s.split('=').next().unwrap_or_default().trim());
+        if existing == Some(key) {
+            if !replaced {
+                lines.push(new_line.clone());
+                replaced = true;
+            }
+        } else {
+            lines.push(line.to_string());
         }
     }
+    if !replaced {
+        lines.push(new_line);
+    }
+
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    fs::write(path, lines.join("\n") + "\n")
+        .with_context(|| format!("Failed to write {}", path.display()))
+}
+
+/// 讀取設定檔；檔案不存在時返回空清單
+#[allow(dead_code)]
+pub fn load(path: &Path) -> Result<Vec<(usize, String)>> {
+    match fs::read_to_string(path) {
+        Ok(text) => Ok(parse(&text)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e).with_context(|| format!("Failed to read {}", path.display())),
+    }
 }
 
-impl Default for Config {
-    fn default() -> Self {
-        Self::new()
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_settings() {
+        let text = "# wedi\n\ncolorcolumn=80\n  set cursorline\n:set uitheme=light\n:nu\n";
+        assert_eq!(
+            parse(text),
+            vec![
+                (3, "colorcolumn=80".to_string()),
+                (4, "cursorline".to_string()),
+                (5, "uitheme=light".to_string()),
+                (6, "nu".to_string()),
+            ]
+        );
+        let missing = Path::new("/nonexistent/wedi/config");
+        assert!(load(missing).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_save_setting_replaces_or_appends() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let path = dir.path().join("wedi").join("config");
+        save_setting(&path, "theme", "InspiredGitHub").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "theme=InspiredGitHub\n");
+
+        fs::write(&path, "# colors\nset theme=a\ncursorline\ntheme=b\n").unwrap();
+        save_setting(&path, "theme", "Solarized (dark)").unwrap();
+        save_setting(&path, "uitheme", "light").unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "# colors\ntheme=Solarized (dark)\ncursorline\nuitheme=light\n"
+        );
     }
 }
@@ -1,5 +1,7 @@
 use crate::buffer::RopeBuffer;
-use crate::utils::visual_width;
+use crate::utils::{
+    floor_grapheme_boundary, next_grapheme_boundary, prev_grapheme_boundary, visual_width,
+};
 use crate::view::View;
 
 #[derive(Debug, Clone, Copy)]
@@ -54,9 +56,10 @@ impl Cursor {
         }
     }
 
+    /// 向左移動一個字素叢集（emoji 序列與組合字元視為一個字）
     pub fn move_left(&mut self, buffer: &RopeBuffer, view: &View) {
         if self.col > 0 {
-            self.col -= 1;
+            self.col = prev_grapheme_boundary(&self.line_text(buffer, self.row), self.col);
             self.update_visual_from_logical(buffer, view);
         } else if self.row > 0 {
             // 移動到上一行末尾
@@ -67,10 +70,11 @@ impl Cursor {
         self.sync_desired_visual_col(buffer, view);
     }
 
+    /// 向右移動一個字素叢集
     pub fn move_right(&mut self, buffer: &RopeBuffer, view: &View) {
-        let line_len = self.line_len(buffer, self.row);
-        if self.col < line_len {
-            self.col += 1;
+        let line = self.line_text(buffer, self.row);
+        if self.col < line.chars().count() {
+            self.col = next_grapheme_boundary(&line, self.col);
             self.update_visual_from_logical(buffer, view);
         } else if self.row + 1 < buffer.line_count() {
             // 移動到下一行開頭
@@ -112,7 +116,64 @@ impl Cursor {
         }
     }
 
-    #[allow(dead_code)]
+    /// 移動到下一個段落：越過目前的段落到下一個空白行，沒有時到文件末尾
+    pub fn move_paragraph_down(&mut self, buffer: &RopeBuffer, view: &View) {
+        let last = buffer.line_count().saturating_sub(1);
+        let mut row = self.row;
+        while row < last && is_blank_line(buffer, row) {
+            row += 1;
+        }
+        while row < last && !is_blank_line(buffer, row) {
+            row += 1;
+        }
+        if is_blank_line(buffer, row) && row != self.row {
+            self.set_position(buffer, view, row, 0);
+        } else {
+            self.move_to_file_end(buffer, view);
+        }
+    }
+
+    /// 移動到上一個段落：越過目前的段落到上一個空白行，沒有時到文件開頭
+    pub fn move_paragraph_up(&mut self, buffer: &RopeBuffer, view: &View) {
+        let mut row = self.row;
+        while row > 0 && is_blank_line(buffer, row) {
+            row -= 1;
+        }
+        while row > 0 && !is_blank_line(buffer, row) {
+            row -= 1;
+        }
+        self.set_position(buffer, view, row, 0);
+    }
+
+    /// 移動到目前縮排區塊的第一行（縮排不小於目前行的連續行）
+    ///
+    /// 已經在區塊第一行時移到外層（縮排較小）的上一行，重複按可以一層層往外
+    pub fn move_to_block_start(&mut self, buffer: &RopeBuffer, view: &View) {
+        let (start, _) = indent_block(buffer, view, self.row);
+        let row = if start == self.row {
+            outer_line(buffer, view, self.row, (0..self.row).rev())
+        } else {
+            Some(start)
+        };
+        if let Some(row) = row {
+            self.set_position(buffer, view, row, indent_len(buffer, row));
+        }
+    }
+
+    /// 移動到目前縮排區塊的最後一行；已經在最後一行時移到外層的下一行
+    pub fn move_to_block_end(&mut self, buffer: &RopeBuffer, view: &View) {
+        let (_, end) = indent_block(buffer, view, self.row);
+        let row = if end == self.row {
+            outer_line(buffer, view, self.row, self.row + 1..buffer.line_count())
+        } else {
+            Some(end)
+        };
+        if let Some(row) = row {
+            self.set_position(buffer, view, row, indent_len(buffer, row));
+        }
+    }
+
+    /// 移動到指定行，盡量保持期望的視覺列
     pub fn move_to_line(&mut self, buffer: &RopeBuffer, view: &View, line: usize) {
         self.row = line.min(buffer.line_count().saturating_sub(1));
         self.visual_line_index = 0;
@@ -145,9 +206,9 @@ impl Cursor {
         let visual_col = self.desired_visual_col;
         self.col = view.visual_to_logical_col(buffer, self.row, self.visual_line_index, visual_col);
 
-        // 確保不超出行長度
-        let line_len = self.line_len(buffer, self.row);
-        self.col = self.col.min(line_len);
+        // 確保不超出行長度，也不停在字素叢集中間
+        let line = self.line_text(buffer, self.row);
+        self.col = floor_grapheme_boundary(&line, self.col.min(line.chars().count()));
     }
 
     /// 從邏輯座標更新視覺座標
@@ -192,6 +253,13 @@ impl Cursor {
         }
     }
 
+    /// 指定行的內容（不包含換行符）
+    fn line_text(&self, buffer: &RopeBuffer, row: usize) -> String {
+        let mut text = buffer.get_line_content(row);
+        text.truncate(text.trim_end_matches(['\n', '\r']).len());
+        text
+    }
+
     /// 獲取指定行的長度（不包含換行符）
     fn line_len(&self, buffer: &RopeBuffer, row: usize) -> usize {
         if let Some(line) = buffer.line(row) {
@@ -204,6 +272,73 @@ impl Cursor {
     }
 }
 
+/// 只含空白字元的行
+fn is_blank_line(buffer: &RopeBuffer, row: usize) -> bool {
+    buffer
+        .line(row)
+        .is_none_or(|line| line.chars().all(char::is_whitespace))
+}
+
+/// 行首空白的字元數
+fn indent_len(buffer: &RopeBuffer, row: usize) -> usize {
+    buffer.line(row).map_or(0, |line| {
+        line.chars()
+            .take_while(|&ch| ch == ' ' || ch == '\t')
+            .count()
+    })
+}
+
+/// 行首空白的顯示寬度；空白行返回 None
+fn indent_width(buffer: &RopeBuffer, view: &View, row: usize) -> Option<usize> {
+    if is_blank_line(buffer, row) {
+        return None;
+    }
+    let line = buffer.line(row)?.to_string();
+    Some(view.logical_col_to_visual_col(&line, indent_len(buffer, row)))
+}
+
+/// `row` 所在縮排區塊的第一行與最後一行（都是非空白行）
+///
+/// 區塊是縮排不小於 `row` 的連續行，中間的空白行不會中斷區塊；
+/// `row` 是空白行時以下一個非空白行為準
+fn indent_block(buffer: &RopeBuffer, view: &View, row: usize) -> (usize, usize) {
+    let line_count = buffer.line_count();
+    let Some(base_row) = (row..line_count).find(|&r| !is_blank_line(buffer, r)) else {
+        return (row, row);
+    };
+    let base = indent_width(buffer, view, base_row).unwrap_or(0);
+    let inside = |r: usize| indent_width(buffer, view, r).is_none_or(|w| w >= base);
+
+    let mut start = base_row;
+    let mut r = base_row;
+    while r > 0 && inside(r - 1) {
+        r -= 1;
+        if !is_blank_line(buffer, r) {
+            start = r;
+        }
+    }
+    let mut end = base_row;
+    let mut r = base_row;
+    while r + 1 < line_count && inside(r + 1) {
+        r += 1;
+        if !is_blank_line(buffer, r) {
+            end = r;
+        }
+    }
+    (start, end)
+}
+
+/// 在 `rows` 中找第一個縮排小於 `row` 的非空白行
+fn outer_line(
+    buffer: &RopeBuffer,
+    view: &View,
+    row: usize,
+    mut rows: impl Iterator<Item = usize>,
+) -> Option<usize> {
+    let base = indent_width(buffer, view, row)?;
+    rows.find(|&r| indent_width(buffer, view, r).is_some_and(|w| w < base))
+}
+
 impl Default for Cursor {
     fn default() -> Self {
         Self::new()
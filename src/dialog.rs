@@ -1,33 +1,51 @@
 // 對話框模組 - 用於輸入框、確認框等
 
+use crate::terminal::Terminal;
 use anyhow::Result;
 use crossterm::{
     cursor,
-    event::{self, Event, KeyCode, KeyEventKind},
+    event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
     execute, queue,
-    style::{self, Color},
+    style::{self, Attribute, Color},
     terminal::{self, ClearType},
 };
-use std::io::{self, Write};
+
+use crate::utils::{char_width, visual_width};
 
 /// 顯示輸入對話框並獲取用戶輸入
 #[allow(dead_code)]
 pub fn prompt(prompt_text: &str, terminal_size: (u16, u16)) -> Result<Option<String>> {
+    prompt_with_completion(prompt_text, terminal_size, &[])
+}
+
+/// 顯示輸入對話框，並支援以 Tab 循環補全候選字串
+///
+/// 補全以目前輸入為前綴（不分大小寫）篩選候選項，連按 Tab 依序切換
+pub fn prompt_with_completion(
+    prompt_text: &str,
+    terminal_size: (u16, u16),
+    candidates: &[&str],
+) -> Result<Option<String>> {
+    // 對話框會覆寫編輯區，關閉後需完整重繪
+    Terminal::invalidate_screen();
     let mut input = String::new();
+    // 補全狀態：(使用者輸入的前綴, 目前選中的候選索引)
+    let mut completion: Option<(String, usize)> = None;
     let (cols, rows) = terminal_size;
     let dialog_row = rows.saturating_sub(2);
 
     loop {
-        // 清除對話框行
-        execute!(
-            io::stdout(),
+        // 清除對話框行（輸出期間隱藏游標）
+        Terminal::begin_frame()?;
+        queue!(
+            Terminal::output(),
             cursor::MoveTo(0, dialog_row),
             terminal::Clear(ClearType::CurrentLine)
         )?;
 
         // 顯示提示和當前輸入
         queue!(
-            io::stdout(),
+            Terminal::output(),
             style::SetBackgroundColor(Color::DarkBlue),
             style::SetForegroundColor(Color::White),
             cursor::MoveTo(0, dialog_row),
@@ -40,22 +58,19 @@ pub fn prompt(prompt_text: &str, terminal_size: (u16, u16)) -> Result<Option<Str
             &display
         };
 
-        queue!(io::stdout(), style::Print(display))?;
+        queue!(Terminal::output(), style::Print(display))?;
 
         // 填滿剩餘空間
         let remaining = cols as usize - display.len();
         if remaining > 0 {
-            queue!(io::stdout(), style::Print(" ".repeat(remaining)))?;
+            queue!(Terminal::output(), style::Print(" ".repeat(remaining)))?;
         }
 
-        queue!(io::stdout(), style::ResetColor)?;
+        queue!(Terminal::output(), style::ResetColor)?;
 
         // 設置光標位置
         let cursor_x = (prompt_text.len() + 2 + input.len()).min(cols as usize - 1) as u16;
-        execute!(io::stdout(), cursor::MoveTo(cursor_x, dialog_row))?;
-        execute!(io::stdout(), cursor::Show)?;
-
-        io::stdout().flush()?;
+        Terminal::end_frame(Some((cursor_x, dialog_row)))?;
 
         // 讀取按鍵,只處理 Press 和 Repeat 事件
         loop {
@@ -74,14 +89,30 @@ pub fn prompt(prompt_text: &str, terminal_size: (u16, u16)) -> Result<Option<Str
                         // 取消
                         return Ok(None);
                     }
+                    KeyCode::Tab if !candidates.is_empty() => {
+                        // 補全：以最初輸入的前綴篩選，連按 Tab 循環切換
+                        let (prefix, next) = match completion.take() {
+                            Some((prefix, idx)) => (prefix, idx + 1),
+                            None => (input.clone(), 0),
+                        };
+                        let matches = completion_matches(&prefix, candidates);
+                        if !matches.is_empty() {
+                            let idx = next % matches.len();
+                            input = matches[idx].to_string();
+                            completion = Some((prefix, idx));
+                        }
+                        break;
+                    }
                     KeyCode::Char(c) => {
                         // 添加字符
                         input.push(c);
+                        completion = None;
                         break;
                     }
                     KeyCode::Backspace => {
                         // 刪除字符
                         input.pop();
+                        completion = None;
                         break;
                     }
                     _ => {
@@ -93,23 +124,36 @@ pub fn prompt(prompt_text: &str, terminal_size: (u16, u16)) -> Result<Option<Str
     }
 }
 
+/// 找出以 prefix 開頭的候選項（不分大小寫）
+fn completion_matches<'a>(prefix: &str, candidates: &[&'a str]) -> Vec<&'a str> {
+    let prefix = prefix.to_lowercase();
+    candidates
+        .iter()
+        .copied()
+        .filter(|c| c.to_lowercase().starts_with(&prefix))
+        .collect()
+}
+
 /// 顯示確認對話框
 #[allow(dead_code)]
 pub fn confirm(message: &str, terminal_size: (u16, u16)) -> Result<bool> {
+    // 對話框會覆寫編輯區，關閉後需完整重繪
+    Terminal::invalidate_screen();
     let (cols, rows) = terminal_size;
     let dialog_row = rows.saturating_sub(2);
 
     loop {
-        // 清除對話框行
-        execute!(
-            io::stdout(),
+        // 清除對話框行（輸出期間隱藏游標）
+        Terminal::begin_frame()?;
+        queue!(
+            Terminal::output(),
             cursor::MoveTo(0, dialog_row),
             terminal::Clear(ClearType::CurrentLine)
         )?;
 
         // 顯示消息
         queue!(
-            io::stdout(),
+            Terminal::output(),
             style::SetBackgroundColor(Color::DarkYellow),
             style::SetForegroundColor(Color::Black),
             cursor::MoveTo(0, dialog_row),
@@ -122,16 +166,16 @@ pub fn confirm(message: &str, terminal_size: (u16, u16)) -> Result<bool> {
             &display
         };
 
-        queue!(io::stdout(), style::Print(display))?;
+        queue!(Terminal::output(), style::Print(display))?;
 
         // 填滿剩餘空間
         let remaining = cols as usize - display.len();
         if remaining > 0 {
-            queue!(io::stdout(), style::Print(" ".repeat(remaining)))?;
+            queue!(Terminal::output(), style::Print(" ".repeat(remaining)))?;
         }
 
-        queue!(io::stdout(), style::ResetColor)?;
-        io::stdout().flush()?;
+        queue!(Terminal::output(), style::ResetColor)?;
+        Terminal::end_frame(None)?;
 
         // 讀取按鍵,只處理 Press 事件
         loop {
@@ -152,3 +196,525 @@ pub fn confirm(message: &str, terminal_size: (u16, u16)) -> Result<bool> {
         }
     }
 }
+
+/// 顯示多選項對話框
+///
+/// `options` 為 (按鍵, 說明) 列表，返回使用者選擇的按鍵（小寫）；ESC 返回 None
+#[allow(dead_code)]
+pub fn choose(
+    message: &str,
+    options: &[(char, &str)],
+    terminal_size: (u16, u16),
+) -> Result<Option<char>> {
+    // 對話框會覆寫編輯區，關閉後需完整重繪
+    Terminal::invalidate_screen();
+    let (cols, rows) = terminal_size;
+    let dialog_row = rows.saturating_sub(2);
+
+    let choices = options
+        .iter()
+        .map(|(key, label)| format!("[{}] {}", key, label))
+        .collect::<Vec<_>>()
+        .join("  ");
+    let display = truncate_to_width(&format!(" {} {}", message, choices), cols as usize);
+
+    loop {
+        // 清除對話框行（輸出期間隱藏游標）
+        Terminal::begin_frame()?;
+        queue!(
+            Terminal::output(),
+            cursor::MoveTo(0, dialog_row),
+            terminal::Clear(ClearType::CurrentLine)
+        )?;
+
+        queue!(
+            Terminal::output(),
+            style::SetBackgroundColor(Color::DarkYellow),
+            style::SetForegroundColor(Color::Black),
+            cursor::MoveTo(0, dialog_row),
+            style::Print(&display),
+        )?;
+
+        // 填滿剩餘空間
+        let remaining = (cols as usize).saturating_sub(visual_width(&display));
+        if remaining > 0 {
+            queue!(Terminal::output(), style::Print(" ".repeat(remaining)))?;
+        }
+
+        queue!(Terminal::output(), style::ResetColor)?;
+        Terminal::end_frame(None)?;
+
+        // 讀取按鍵,只處理 Press 事件
+        loop {
+            if let Event::Key(key_event) = event::read()? {
+                if key_event.kind != KeyEventKind::Press && key_event.kind != KeyEventKind::Repeat {
+                    continue;
+                }
+
+                match key_event.code {
+                    KeyCode::Esc => return Ok(None),
+                    KeyCode::Char(c) => {
+                        let c = c.to_ascii_lowercase();
+                        if options.iter().any(|(key, _)| *key == c) {
+                            return Ok(Some(c));
+                        }
+                        break;
+                    }
+                    _ => break,
+                }
+            }
+        }
+    }
+}
+
+/// 在畫面下方顯示可捲動的選單，返回選擇的索引；ESC 返回 None
+///
+/// 上下鍵、PageUp/PageDown、Home/End 移動選取項目，Enter 確定。
+/// 每次選取項目改變時呼叫 `preview`，呼叫端可以立即套用並重繪畫面（選單隨後重新畫在上面）
+#[allow(dead_code)]
+pub fn pick(
+    title: &str,
+    items: &[String],
+    selected: usize,
+    terminal_size: (u16, u16),
+    preview: &mut dyn FnMut(usize) -> Result<()>,
+) -> Result<Option<usize>> {
+    Ok(
+        match pick_with_keys(title, items, selected, &[], terminal_size, preview)? {
+            Some(Picked::Item(index)) => Some(index),
+            _ => None,
+        },
+    )
+}
+
+/// [`pick_with_keys`] 的結果
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Picked {
+    /// 以 Enter 選擇的項目
+    Item(usize),
+    /// 按下 `keys` 中的按鍵（小寫），以及當時選取的項目
+    Key(char, usize),
+}
+
+/// 與 [`pick`] 相同，另外以 `keys` 中的按鍵對整個列表執行動作（標題列顯示按鍵說明）
+#[allow(dead_code)]
+pub fn pick_with_keys(
+    title: &str,
+    items: &[String],
+    selected: usize,
+    keys: &[(char, &str)],
+    terminal_size: (u16, u16),
+    preview: &mut dyn FnMut(usize) -> Result<()>,
+) -> Result<Option<Picked>> {
+    if items.is_empty() {
+        return Ok(None);
+    }
+    let key_help: String = keys
+        .iter()
+        .map(|(key, label)| format!("  [{}] {}", key, label))
+        .collect();
+    let (cols, rows) = terminal_size;
+    let cols = cols as usize;
+    // 選單佔畫面下半部（不含狀態列），第一行為標題
+    let height = items
+        .len()
+        .min((rows as usize / 2).saturating_sub(1))
+        .max(1);
+    let title_row = (rows as usize).saturating_sub(height + 2);
+    let mut selected = selected.min(items.len() - 1);
+    let mut top = selected.saturating_sub(height - 1);
+
+    loop {
+        // 選單會覆寫編輯區，下一次重繪必須完整輸出
+        Terminal::invalidate_screen();
+        Terminal::begin_frame()?;
+        let header = format!(" {} ({}/{}){}", title, selected + 1, items.len(), key_help);
+        queue!(
+            Terminal::output(),
+            cursor::MoveTo(0, title_row as u16),
+            style::SetBackgroundColor(Color::DarkBlue),
+            style::SetForegroundColor(Color::White),
+            style::Print(format!("{:<cols$}", truncate_to_width(&header, cols))),
+            style::ResetColor,
+        )?;
+        for (offset, item) in items.iter().enumerate().skip(top).take(height) {
+            let screen_row = (title_row + 1 + offset - top) as u16;
+            let (foreground, background) = if offset == selected {
+                (Color::Black, Color::Cyan)
+            } else {
+                (Color::White, Color::DarkGrey)
+            };
+            let text = truncate_to_width(&format!("   {}", item), cols);
+            let padding = cols.saturating_sub(visual_width(&text));
+            queue!(
+                Terminal::output(),
+                cursor::MoveTo(0, screen_row),
+                style::SetBackgroundColor(background),
+                style::SetForegroundColor(foreground),
+                style::Print(text),
+                style::Print(" ".repeat(padding)),
+                style::ResetColor,
+            )?;
+        }
+        Terminal::end_frame(None)?;
+
+        let previous = selected;
+        loop {
+            let Event::Key(key_event) = event::read()? else {
+                continue;
+            };
+            if key_event.kind != KeyEventKind::Press && key_event.kind != KeyEventKind::Repeat {
+                continue;
+            }
+            match key_event.code {
+                KeyCode::Esc => return Ok(None),
+                KeyCode::Enter => return Ok(Some(Picked::Item(selected))),
+                KeyCode::Char(c) if keys.iter().any(|(key, _)| *key == c.to_ascii_lowercase()) => {
+                    return Ok(Some(Picked::Key(c.to_ascii_lowercase(), selected)))
+                }
+                KeyCode::Up => selected = selected.saturating_sub(1),
+                KeyCode::Down => selected = (selected + 1).min(items.len() - 1),
+                KeyCode::PageUp => selected = selected.saturating_sub(height),
+                KeyCode::PageDown => selected = (selected + height).min(items.len() - 1),
+                KeyCode::Home => selected = 0,
+                KeyCode::End => selected = items.len() - 1,
+                _ => continue,
+            }
+            break;
+        }
+
+        // 捲動讓選取的項目保持可見
+        if selected < top {
+            top = selected;
+        } else if selected >= top + height {
+            top = selected + 1 - height;
+        }
+        if selected != previous {
+            preview(selected)?;
+        }
+    }
+}
+
+/// [`popup`] 的結果
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PopupResult {
+    Selected(usize),
+    Cancelled,
+    /// 選單不處理的按鍵：選單已關閉，由呼叫端照常處理（例如繼續輸入）
+    Key(KeyEvent),
+}
+
+/// 彈出選單的最大高度（項目數）
+const POPUP_HEIGHT: usize = 8;
+
+/// 在 `anchor`（螢幕座標）下方顯示小型選單，空間不足時改畫在上方
+///
+/// 上下鍵、Tab/Shift+Tab、Ctrl+N/Ctrl+P 移動選取項目，Enter 確定，ESC 取消；
+/// 其他按鍵關閉選單並返回給呼叫端
+#[allow(dead_code)]
+pub fn popup(
+    items: &[String],
+    anchor: (u16, u16),
+    terminal_size: (u16, u16),
+) -> Result<PopupResult> {
+    if items.is_empty() {
+        return Ok(PopupResult::Cancelled);
+    }
+    let (cols, rows) = (terminal_size.0 as usize, terminal_size.1 as usize);
+    let (x, y) = (anchor.0 as usize, anchor.1 as usize);
+    let text_rows = rows.saturating_sub(1); // 不蓋住狀態列
+    let below = text_rows.saturating_sub(y + 1);
+    let height = items.len().min(POPUP_HEIGHT).min(below.max(y)).max(1);
+    let top_row = if below >= height { y + 1 } else { y - height };
+    let width = items
+        .iter()
+        .map(|item| visual_width(item) + 2)
+        .max()
+        .unwrap_or(0)
+        .min(cols);
+    let left = x.min(cols.saturating_sub(width));
+    let mut selected = 0;
+    let mut top = 0;
+
+    loop {
+        // 選單會覆寫編輯區，下一次重繪必須完整輸出
+        Terminal::invalidate_screen();
+        Terminal::begin_frame()?;
+        for (offset, item) in items.iter().enumerate().skip(top).take(height) {
+            let screen_row = (top_row + offset - top) as u16;
+            let (foreground, background) = if offset == selected {
+                (Color::Black, Color::Cyan)
+            } else {
+                (Color::White, Color::DarkGrey)
+            };
+            let text = truncate_to_width(&format!(" {}", item), width);
+            let padding = width.saturating_sub(visual_width(&text));
+            queue!(
+                Terminal::output(),
+                cursor::MoveTo(left as u16, screen_row),
+                style::SetBackgroundColor(background),
+                style::SetForegroundColor(foreground),
+                style::Print(text),
+                style::Print(" ".repeat(padding)),
+                style::ResetColor,
+            )?;
+        }
+        Terminal::end_frame(Some(anchor))?;
+
+        loop {
+            let Event::Key(key_event) = event::read()? else {
+                continue;
+            };
+            if key_event.kind != KeyEventKind::Press && key_event.kind != KeyEventKind::Repeat {
+                continue;
+            }
+            let ctrl = key_event.modifiers.contains(KeyModifiers::CONTROL);
+            match key_event.code {
+                KeyCode::Esc => return Ok(PopupResult::Cancelled),
+                KeyCode::Enter => return Ok(PopupResult::Selected(selected)),
+                KeyCode::Up | KeyCode::BackTab => {
+                    selected = selected.checked_sub(1).unwrap_or(items.len() - 1)
+                }
+                KeyCode::Char('p') if ctrl => {
+                    selected = selected.checked_sub(1).unwrap_or(items.len() - 1)
+                }
+                KeyCode::Down | KeyCode::Tab => selected = (selected + 1) % items.len(),
+                KeyCode::Char('n') if ctrl => selected = (selected + 1) % items.len(),
+                _ => return Ok(PopupResult::Key(key_event)),
+            }
+            break;
+        }
+
+        // 捲動讓選取的項目保持可見
+        if selected < top {
+            top = selected;
+        } else if selected >= top + height {
+            top = selected + 1 - height;
+        }
+    }
+}
+
+/// 顯示可捲動的全螢幕文字視窗（例如 diff、按鍵說明），按 ESC 或 q 關閉
+///
+/// `/` 搜尋（不分大小寫），`n`/`N` 跳到下一個/上一個符合的行；
+/// `line_color` 決定每行的前景色，返回 None 使用預設顏色
+#[allow(dead_code)]
+pub fn pager(
+    title: &str,
+    lines: &[String],
+    terminal_size: (u16, u16),
+    line_color: fn(&str) -> Option<Color>,
+) -> Result<()> {
+    // 對話框會覆寫編輯區，關閉後需完整重繪
+    Terminal::invalidate_screen();
+    let (cols, rows) = terminal_size;
+    let cols = cols as usize;
+    // 最後一行為標題列
+    let page = (rows as usize).saturating_sub(1).max(1);
+    let max_top = lines.len().saturating_sub(page);
+    let mut top = 0;
+    // 搜尋字串與目前符合的行
+    let mut query = String::new();
+    let mut current_match: Option<usize> = None;
+    let mut search_message: Option<String> = None;
+
+    loop {
+        Terminal::begin_frame()?;
+        for screen_row in 0..page {
+            queue!(
+                Terminal::output(),
+                cursor::MoveTo(0, screen_row as u16),
+                terminal::Clear(ClearType::CurrentLine)
+            )?;
+            if let Some(line) = lines.get(top + screen_row) {
+                let text = truncate_to_width(&line.replace('\t', "    "), cols);
+                if let Some(color) = line_color(line) {
+                    queue!(Terminal::output(), style::SetForegroundColor(color))?;
+                }
+                // 符合搜尋的部分以反白顯示
+                let mut last = 0;
+                for (start, end) in match_ranges(&text, &query) {
+                    queue!(
+                        Terminal::output(),
+                        style::Print(&text[last..start]),
+                        style::SetAttribute(Attribute::Reverse),
+                        style::Print(&text[start..end]),
+                        style::SetAttribute(Attribute::NoReverse)
+                    )?;
+                    last = end;
+                }
+                queue!(
+                    Terminal::output(),
+                    style::Print(&text[last..]),
+                    style::ResetColor
+                )?;
+            }
+        }
+
+        let position = format!(
+            "{}-{}/{}",
+            (top + 1).min(lines.len()),
+            (top + page).min(lines.len()),
+            lines.len()
+        );
+        let status = match &search_message {
+            Some(message) => format!(" {}  {}  {}", title, position, message),
+            None => format!(
+                " {}  {}  Up/Down/PgUp/PgDn:Scroll  /:Search  Esc/q:Close",
+                title, position
+            ),
+        };
+        let status = truncate_to_width(&status, cols);
+        queue!(
+            Terminal::output(),
+            cursor::MoveTo(0, page as u16),
+            style::SetBackgroundColor(Color::DarkGrey),
+            style::SetForegroundColor(Color::White),
+            style::Print(&status),
+            style::Print(" ".repeat(cols.saturating_sub(visual_width(&status)))),
+            style::ResetColor
+        )?;
+        Terminal::end_frame(None)?;
+
+        // 讀取按鍵,只處理 Press 和 Repeat 事件
+        loop {
+            if let Event::Key(key_event) = event::read()? {
+                if key_event.kind != KeyEventKind::Press && key_event.kind != KeyEventKind::Repeat {
+                    continue;
+                }
+
+                // 搜尋：新的搜尋從畫面頂端開始，n/N 從目前符合的行接續
+                let search = match key_event.code {
+                    KeyCode::Esc | KeyCode::Char('q') => {
+                        execute!(Terminal::output(), cursor::Show)?;
+                        return Ok(());
+                    }
+                    KeyCode::Char('/') => {
+                        match prompt("/", terminal_size)? {
+                            Some(input) if !input.is_empty() => query = input,
+                            _ => break,
+                        }
+                        Some((top, true))
+                    }
+                    KeyCode::Char('n') if !query.is_empty() => {
+                        Some((current_match.map_or(top, |row| row + 1), true))
+                    }
+                    KeyCode::Char('N') if !query.is_empty() => {
+                        Some((current_match.unwrap_or(top), false))
+                    }
+                    KeyCode::Up | KeyCode::Char('k') => {
+                        top = top.saturating_sub(1);
+                        None
+                    }
+                    KeyCode::Down | KeyCode::Char('j') => {
+                        top = (top + 1).min(max_top);
+                        None
+                    }
+                    KeyCode::PageUp => {
+                        top = top.saturating_sub(page);
+                        None
+                    }
+                    KeyCode::PageDown | KeyCode::Char(' ') => {
+                        top = (top + page).min(max_top);
+                        None
+                    }
+                    KeyCode::Home => {
+                        top = 0;
+                        None
+                    }
+                    KeyCode::End => {
+                        top = max_top;
+                        None
+                    }
+                    _ => continue,
+                };
+
+                if let Some((from, forward)) = search {
+                    match find_line(lines, &query, from, forward) {
+                        Some(row) => {
+                            current_match = Some(row);
+                            top = row.min(max_top);
+                            search_message = Some(format!("/{}", query));
+                        }
+                        None => search_message = Some(format!("Not found: {}", query)),
+                    }
+                }
+                break;
+            }
+        }
+    }
+}
+
+/// 從 `from` 開始尋找包含 query 的行（不分大小寫，到尾端時從另一端繼續）
+///
+/// 向前搜尋包含 `from` 本身，向後搜尋從 `from` 的前一行開始
+fn find_line(lines: &[String], query: &str, from: usize, forward: bool) -> Option<usize> {
+    if query.is_empty() || lines.is_empty() {
+        return None;
+    }
+    let len = lines.len();
+    let from = from.min(len);
+    let matches = |row: &usize| !match_ranges(&lines[*row], query).is_empty();
+
+    if forward {
+        (from..len).chain(0..from).find(matches)
+    } else {
+        (0..from).rev().chain((from..len).rev()).find(matches)
+    }
+}
+
+/// 找出 text 中所有 query 出現的位元組範圍（ASCII 不分大小寫）
+fn match_ranges(text: &str, query: &str) -> Vec<(usize, usize)> {
+    if query.is_empty() {
+        return Vec::new();
+    }
+    let haystack = text.to_ascii_lowercase();
+    let needle = query.to_ascii_lowercase();
+    haystack
+        .match_indices(&needle)
+        .map(|(start, matched)| (start, start + matched.len()))
+        .collect()
+}
+
+/// 依視覺寬度截斷字串（避免在多字節字符中間切斷）
+#[allow(dead_code)]
+pub fn truncate_to_width(s: &str, max_width: usize) -> String {
+    let mut result = String::new();
+    let mut width = 0;
+    for ch in s.chars() {
+        let ch_width = char_width(ch);
+        if width + ch_width > max_width {
+            break;
+        }
+        result.push(ch);
+        width += ch_width;
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_line_wraps_around() {
+        let lines: Vec<String> = ["Save file", "Toggle line comment", "Find text", "comment"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        assert_eq!(find_line(&lines, "COMMENT", 0, true), Some(1));
+        assert_eq!(find_line(&lines, "comment", 2, true), Some(3));
+        assert_eq!(find_line(&lines, "save", 1, true), Some(0));
+        assert_eq!(find_line(&lines, "comment", 1, false), Some(3));
+        assert_eq!(find_line(&lines, "missing", 0, true), None);
+        assert_eq!(find_line(&lines, "", 0, true), None);
+    }
+
+    #[test]
+    fn test_match_ranges() {
+        assert_eq!(match_ranges("Ctrl+F  Find", "f"), vec![(5, 6), (8, 9)]);
+        assert!(match_ranges("abc", "").is_empty());
+    }
+}
@@ -0,0 +1,354 @@
+// 行差異比較（unified diff）
+//
+// 使用 Myers 演算法比較兩段文字的行，輸出與 `diff -u` 相同格式的結果，
+// 用於檢視緩衝區與磁碟檔案之間的修改，並產生行號欄的修改標記。
+
+use crate::view::LineMarker;
+use std::collections::HashMap;
+
+/// 每個區塊前後保留的上下文行數
+pub const CONTEXT_LINES: usize = 3;
+
+/// 編輯距離上限，超過時不再尋找最短差異，改為整段刪除後插入（避免大檔案耗用大量記憶體）
+const MAX_EDIT_DISTANCE: usize = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DiffOp {
+    Equal,
+    Delete,
+    Insert,
+}
+
+/// 產生 unified diff，兩者相同時返回空列表
+///
+/// 返回的每一行不含換行符，第一、二行為 `---`/`+++` 標頭
+pub fn unified_diff(old: &str, new: &str, old_label: &str, new_label: &str) -> Vec<String> {
+    let old_lines: Vec<&str> = old.split_inclusive('\n').collect();
+    let new_lines: Vec<&str> = new.split_inclusive('\n').collect();
+    let ops = diff_lines(&old_lines, &new_lines);
+
+    // 每個操作對應的 (操作, 行內容, 之前已消耗的舊行數, 之前已消耗的新行數)
+    let mut entries = Vec::with_capacity(ops.len());
+    let (mut old_idx, mut new_idx) = (0, 0);
+    for (op, line) in ops {
+        entries.push((op, line, old_idx, new_idx));
+        match op {
+            DiffOp::Equal => {
+                old_idx += 1;
+                new_idx += 1;
+            }
+            DiffOp::Delete => old_idx += 1,
+            DiffOp::Insert => new_idx += 1,
+        }
+    }
+
+    let changes: Vec<usize> = entries
+        .iter()
+        .enumerate()
+        .filter(|(_, (op, ..))| *op != DiffOp::Equal)
+        .map(|(i, _)| i)
+        .collect();
+    if changes.is_empty() {
+        return Vec::new();
+    }
+
+    let mut output = vec![format!("--- {}", old_label), format!("+++ {}", new_label)];
+
+    // 將相距不超過兩倍上下文的修改合併為同一區塊
+    let mut i = 0;
+    while i < changes.len() {
+        let mut j = i;
+        while j + 1 < changes.len() && changes[j + 1] - changes[j] <= 2 * CONTEXT_LINES + 1 {
+            j += 1;
+        }
+
+        let start = changes[i].saturating_sub(CONTEXT_LINES);
+        let end = (changes[j] + CONTEXT_LINES + 1).min(entries.len());
+        let hunk = &entries[start..end];
+
+        let old_count = hunk.iter().filter(|e| e.0 != DiffOp::Insert).count();
+        let new_count = hunk.iter().filter(|e| e.0 != DiffOp::Delete).count();
+        let (_, _, old_start, new_start) = hunk[0];
+        output.push(format!(
+            "@@ -{} +{} @@",
+            hunk_range(old_start, old_count),
+            hunk_range(new_start, new_count)
+        ));
+
+        for (op, line, ..) in hunk {
+            let prefix = match op {
+                DiffOp::Equal => ' ',
+                DiffOp::Delete => '-',
+                DiffOp::Insert => '+',
+            };
+            output.push(format!("{}{}", prefix, line.trim_end_matches(['\n', '\r'])));
+            if !line.ends_with('\n') {
+                output.push("\\ No newline at end of file".to_string());
+            }
+        }
+
+        i = j + 1;
+    }
+
+    output
+}
+
+/// 計算新內容每行相對於舊內容的修改標記（新內容的行號 -> 標記）
+///
+/// 每個修改區塊中，與刪除行配對的新行標為修改，多出的標為新增；
+/// 只有刪除時標在刪除位置之後的行（刪除在檔尾時標在最後一行）
+pub fn line_markers(old: &str, new: &str) -> HashMap<usize, LineMarker> {
+    let old_lines: Vec<&str> = old.split_inclusive('\n').collect();
+    let new_lines: Vec<&str> = new.split_inclusive('\n').collect();
+    let ops = diff_lines(&old_lines, &new_lines);
+
+    let mut markers = HashMap::new();
+    let mut new_idx = 0;
+    let mut i = 0;
+    while i < ops.len() {
+        if ops[i].0 == DiffOp::Equal {
+            new_idx += 1;
+            i += 1;
+            continue;
+        }
+
+        let (mut deleted, mut inserted) = (0, 0);
+        while i < ops.len() && ops[i].0 != DiffOp::Equal {
+            match ops[i].0 {
+                DiffOp::Delete => deleted += 1,
+                _ => inserted += 1,
+            }
+            i += 1;
+        }
+
+        for offset in 0..inserted {
+            let marker = if offset < deleted {
+                LineMarker::Modified
+            } else {
+                LineMarker::Added
+            };
+            markers.insert(new_idx + offset, marker);
+        }
+        if inserted == 0 {
+            let row = new_idx.min(new_lines.len().saturating_sub(1));
+            markers.entry(row).or_insert(LineMarker::Deleted);
+        }
+        new_idx += inserted;
+    }
+
+    markers
+}
+
+/// 區塊標頭的行範圍（1-based；沒有行時以前一行為起點）
+fn hunk_range(start: usize, count: usize) -> String {
+    match count {
+        0 => format!("{},0", start),
+        1 => format!("{}", start + 1),
+        _ => format!("{},{}", start + 1, count),
+    }
+}
+
+/// 計算兩組行之間的最短編輯序列
+fn diff_lines<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<(DiffOp, &'a str)> {
+    // 先去掉共同的開頭與結尾，縮小需要比較的範圍
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let old_mid = &old[prefix..old.len() - suffix];
+    let new_mid = &new[prefix..new.len() - suffix];
+
+    let mut ops: Vec<(DiffOp, &str)> = old[..prefix]
+        .iter()
+        .map(|line| (DiffOp::Equal, *line))
+        .collect();
+
+    match myers(old_mid, new_mid) {
+        Some(mid_ops) => ops.extend(mid_ops),
+        None => {
+            ops.extend(old_mid.iter().map(|line| (DiffOp::Delete, *line)));
+            ops.extend(new_mid.iter().map(|line| (DiffOp::Insert, *line)));
+        }
+    }
+
+    ops.extend(
+        old[old.len() - suffix..]
+            .iter()
+            .map(|line| (DiffOp::Equal, *line)),
+    );
+    ops
+}
+
+/// Myers O(ND) 差異演算法；編輯距離超過上限時返回 None
+fn myers<'a>(old: &[&'a str], new: &[&'a str]) -> Option<Vec<(DiffOp, &'a str)>> {
+    let n = old.len() as isize;
+    let m = new.len() as isize;
+    let max = (old.len() + new.len()).min(MAX_EDIT_DISTANCE) as isize;
+    let offset = max + 1;
+
+    // v[k + offset]：對角線 k 上目前能到達的最遠 x
+    let mut v = vec![0isize; 2 * offset as usize + 1];
+    let mut trace = Vec::new();
+    let mut found = false;
+
+    'search: for d in 0..=max {
+        trace.push(v.clone());
+        for k in (-d..=d).step_by(2) {
+            let idx = (k + offset) as usize;
+            let mut x = if k == -d || (k != d && v[idx - 1] < v[idx + 1]) {
+                v[idx + 1]
+            } else {
+                v[idx - 1] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && old[x as usize] == new[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[idx] = x;
+            if x >= n && y >= m {
+                found = true;
+                break 'search;
+            }
+        }
+    }
+
+    if !found {
+        return None;
+    }
+
+    // 從終點沿著記錄回溯出編輯序列
+    let mut ops = Vec::new();
+    let (mut x, mut y) = (n, m);
+    for (d, v) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let k = x - y;
+        let prev_k =
+            if k == -d || (k != d && v[(k - 1 + offset) as usize] < v[(k + 1 + offset) as usize]) {
+                k + 1
+            } else {
+                k - 1
+            };
+        let prev_x = v[(prev_k + offset) as usize];
+        let prev_y = prev_x - prev_k;
+
+        while x > prev_x && y > prev_y {
+            ops.push((DiffOp::Equal, old[(x - 1) as usize]));
+            x -= 1;
+            y -= 1;
+        }
+
+        if d > 0 {
+            if x == prev_x {
+                ops.push((DiffOp::Insert, new[(y - 1) as usize]));
+            } else {
+                ops.push((DiffOp::Delete, old[(x - 1) as usize]));
+            }
+        }
+
+        x = prev_x;
+        y = prev_y;
+    }
+
+    ops.reverse();
+    Some(ops)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_identical() {
+        assert!(unified_diff("a\nb\n", "a\nb\n", "old", "new").is_empty());
+        assert!(unified_diff("", "", "old", "new").is_empty());
+    }
+
+    #[test]
+    fn test_single_change() {
+        let diff = unified_diff("a\nb\nc\n", "a\nB\nc\n", "old", "new");
+        assert_eq!(
+            diff,
+            [
+                "--- old",
+                "+++ new",
+                "@@ -1,3 +1,3 @@",
+                " a",
+                "-b",
+                "+B",
+                " c"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_insert_into_empty() {
+        let diff = unified_diff("", "hello\n", "old", "new");
+        assert_eq!(diff, ["--- old", "+++ new", "@@ -0,0 +1 @@", "+hello"]);
+    }
+
+    #[test]
+    fn test_separate_hunks() {
+        let old: String = (1..=20).map(|i| format!("{}\n", i)).collect();
+        let new: String = (1..=20)
+            .map(|i| match i {
+                2 => "two\n".to_string(),
+                19 => "nineteen\n".to_string(),
+                _ => format!("{}\n", i),
+            })
+            .collect();
+        let diff = unified_diff(&old, &new, "old", "new");
+        let headers: Vec<_> = diff.iter().filter(|l| l.starts_with("@@")).collect();
+        assert_eq!(headers, ["@@ -1,5 +1,5 @@", "@@ -16,5 +16,5 @@"]);
+    }
+
+    #[test]
+    fn test_missing_trailing_newline() {
+        let diff = unified_diff("a\nb\n", "a\nb", "old", "new");
+        assert_eq!(
+            diff,
+            [
+                "--- old",
+                "+++ new",
+                "@@ -1,2 +1,2 @@",
+                " a",
+                "-b",
+                "+b",
+                "\\ No newline at end of file"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_line_markers() {
+        let markers = line_markers("a\nb\nc\nd\n", "a\nB\nc\nnew\nd\n");
+        assert_eq!(markers.len(), 2);
+        assert_eq!(markers[&1], LineMarker::Modified);
+        assert_eq!(markers[&3], LineMarker::Added);
+
+        // 刪除標在之後的行，檔尾的刪除標在最後一行
+        let markers = line_markers("a\nb\nc\n", "a\nc\n");
+        assert_eq!(markers, HashMap::from([(1, LineMarker::Deleted)]));
+        let markers = line_markers("a\nb\n", "a\n");
+        assert_eq!(markers, HashMap::from([(0, LineMarker::Deleted)]));
+
+        assert!(line_markers("same\n", "same\n").is_empty());
+    }
+
+    #[test]
+    fn test_minimal_edit_script() {
+        let old = "a\nb\nc\na\nb\nb\na\n";
+        let new = "c\nb\na\nb\na\nc\n";
+        let diff = unified_diff(old, new, "old", "new");
+        let changed = diff[3..]
+            .iter()
+            .filter(|l| l.starts_with('+') || l.starts_with('-'))
+            .count();
+        // 經典範例 ABCABBA -> CBABAC 的最短編輯距離為 5
+        assert_eq!(changed, 5);
+    }
+}
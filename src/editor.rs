@@ -1,34 +1,220 @@
+use crate::analysis::Counts;
+use crate::autosave::{AutosavePolicy, AUTOSAVE_DELAY};
+use crate::backend::TerminalBackend;
 use crate::buffer::{EncodingConfig, RopeBuffer};
 use crate::clipboard::ClipboardManager;
-use crate::comment::CommentHandler;
+use crate::completion::WordIndex;
 use crate::cursor::Cursor;
-use crate::input::{handle_key_event, Command, Direction};
+use crate::dialog::Picked;
+use crate::dialog::PopupResult;
+use crate::editor_core::EditorCore;
+use crate::ex::ExCommand;
+use crate::hooks::{Hook, HookContext, HookRegistry};
+use crate::input::{handle_key_event, Command};
+use crate::jumplist::JumpList;
+use crate::lock::{FileLock, LockInfo};
 use crate::search::Search;
-use crate::terminal::Terminal;
+use crate::styled::SpanStyle;
+use crate::template::{LocalTime, Template};
+use crate::terminal::{CursorStyle, Terminal};
 use crate::utils::visual_width;
-use crate::view::{Selection, View};
-use anyhow::Result;
-use std::path::Path;
+use crate::utils::AmbiguousWidth;
+use crate::view::{LineNumberMode, LineSpan, TabLine, View, WrapGutter};
+use crate::whitespace::Fix;
+use anyhow::{bail, Context, Result};
+use crossterm::event::{KeyCode, KeyModifiers, MouseButton, MouseEvent, MouseEventKind};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread::JoinHandle;
+use std::time::Instant;
 
 #[cfg(feature = "syntax-highlighting")]
-use crate::highlight::{HighlightCache, HighlightConfig, HighlightEngine};
+use crate::highlight::{HighlightCache, HighlightConfig, HighlightEngine, HighlightWorker};
+#[cfg(feature = "syntax-highlighting")]
+use crate::styled::StyledLine;
+
+/// 背景工作（語法集載入、存檔）進行期間檢查是否完成的間隔
+const BACKGROUND_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(50);
+
+/// 預設的最高重繪頻率（每秒幀數，`:set maxfps`）
+const DEFAULT_MAX_FPS: u32 = 60;
+
+/// 拖曳選擇時指標停在畫面邊緣，每隔多久自動捲動一步
+const AUTO_SCROLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(50);
+
+/// 滑鼠滾輪每格捲動的行數
+const MOUSE_WHEEL_LINES: usize = 3;
+
+/// Ctrl+U 次數的上限
+const MAX_COUNT: usize = 10_000;
+
+/// 超過此行數時不計算行號欄的修改標記（避免每次編輯都比對整個大檔案）
+const MAX_LINE_MARKER_LINES: usize = 100_000;
+
+// `:set colorcolumn` 未指定欄位時的參考線位置
+const DEFAULT_COLOR_COLUMN: usize = 80;
+
+/// 常用編碼名稱（供編碼切換對話框補全，以及命令列編碼錯誤時列出可用的名稱）
+///
+/// 名稱皆可被 `encoding_rs::Encoding::for_label` 解析
+pub const ENCODING_LABELS: &[&str] = &[
+    "utf-8",
+    "utf-16le",
+    "utf-16be",
+    "gbk",
+    "gb18030",
+    "big5",
+    "shift-jis",
+    "euc-jp",
+    "iso-2022-jp",
+    "euc-kr",
+    "windows-1250",
+    "windows-1251",
+    "windows-1252",
+    "windows-1253",
+    "windows-1254",
+    "windows-1255",
+    "windows-1256",
+    "windows-1257",
+    "windows-1258",
+    "windows-874",
+    "iso-8859-1",
+    "iso-8859-2",
+    "iso-8859-3",
+    "iso-8859-4",
+    "iso-8859-5",
+    "iso-8859-6",
+    "iso-8859-7",
+    "iso-8859-8",
+    "iso-8859-10",
+    "iso-8859-13",
+    "iso-8859-14",
+    "iso-8859-15",
+    "iso-8859-16",
+    "koi8-r",
+    "koi8-u",
+    "ibm866",
+    "macintosh",
+    "x-mac-cyrillic",
+];
+
+// 光標所在行的預設背景色（256 色中接近黑色的灰）
+pub const DEFAULT_CURSOR_LINE_COLOR: crossterm::style::Color =
+    crossterm::style::Color::AnsiValue(236);
+
+/// 啟動時讀取的設定檔（`:checkhealth` 回報）
+struct ConfigFile {
+    path: PathBuf,
+    applied: usize,      // 成功套用的選項數
+    errors: Vec<String>, // 無法套用的行（含行號）
+}
+
+/// 背景執行的檢查程式結果；None 表示檢查程式沒有安裝
+type LintResult = Result<Option<Vec<crate::lint::Diagnostic>>>;
+
+/// 切換到背景的緩衝區，以及切換回來時要恢復的狀態
+struct HiddenBuffer {
+    buffer: RopeBuffer, // 內容、復原歷史與書籤
+    cursor: (usize, usize),
+    offset_row: usize,
+    file_lock: Option<FileLock>,
+    filetype: Option<String>,
+    jumps: JumpList,
+    diagnostics: Vec<crate::lint::Diagnostic>,
+    recovery_written: Option<PathBuf>,
+}
 
+/// `:grep` 的結果，沒有參數的 `:grep` 重新列出
+struct GrepResults {
+    query: String,
+    matches: Vec<crate::grep::GrepMatch>,
+    selected: usize, // 上一次開啟的結果
+}
+
+/// 背景執行緒中進行的存檔
+struct PendingSave {
+    version: u64,                       // 快照時的緩衝區版本
+    result: mpsc::Receiver<Result<()>>, // 寫檔結果
+}
 
 pub struct Editor {
-    buffer: RopeBuffer,
-    cursor: Cursor,
-    view: View,
+    core: EditorCore, // 緩衝區、光標、選擇範圍與內部剪貼簿
     terminal: Terminal,
     clipboard: ClipboardManager,
-    internal_clipboard: String, // 內部剪貼簿作為後備
     search: Search,
-    comment_handler: CommentHandler,
     should_quit: bool,
-    selection: Option<Selection>,
-    selection_mode: bool, // F1 選擇模式開關
     message: Option<String>,
-    quit_times: u8, // 追蹤連續按 Ctrl+Q 的次數
+    error: Option<String>, // 錯誤訊息另外顯示在狀態列上方，直到按 ESC 或操作成功
     debug_mode: bool,
+    file_lock: Option<FileLock>,       // 本實例持有的檔案鎖
+    lock_conflict: Option<LockInfo>,   // 開啟時發現的其他實例的鎖
+    needs_redraw: bool,                // 畫面是否需要重繪（沒有事件時不重繪）
+    output_to_stdout: bool,            // 結束時將內容寫到 stdout（filter 模式）
+    git_message: bool,                 // 是否為 git 提交訊息（狀態列顯示摘要長度）
+    git_comment: String,               // git 檔案的註解前綴（core.commentChar，預設 #）
+    auto_text_width: bool,             // textwidth 是開啟提交訊息時自動設定的
+    pending_save: Option<PendingSave>, // 背景執行中的存檔
+    autosave: AutosavePolicy,
+    hooks: HookRegistry,                                      // 編譯時加入的外掛
+    autowrite: bool,                                          // 執行外部命令（:!）前先存檔
+    format_program: Option<String>, // `:set formatprg` 指定的格式化命令，None 依檔案類型選擇
+    format_on_save: bool,           // 存檔前以格式化程式整理內容
+    save_warning: Option<String>,   // 存檔前發生但不阻止存檔的錯誤（例如格式化失敗），存檔後顯示
+    lint_enabled: bool,             // 存檔後執行檢查程式
+    lint_programs: std::collections::HashMap<String, String>, // `:set lintprg.EXT=CMD` 覆寫的檢查命令（空字串為停用）
+    pending_lint: Option<mpsc::Receiver<LintResult>>,         // 背景執行中的檢查
+    diagnostics: Vec<crate::lint::Diagnostic>,                // 最近一次檢查的結果（依行號排序）
+    grep_results: Option<GrepResults>,                        // 上一次 `:grep` 的結果
+    buffers: Vec<Option<HiddenBuffer>>, // 開啟的緩衝區（分頁列的順序）；目前的緩衝區為 None，狀態在編輯器的欄位中
+    current_buffer: usize,              // 目前的緩衝區在 buffers 中的位置
+    tab_line: bool,                     // 開啟兩個以上的緩衝區時在頂端顯示分頁列
+    undo_file: bool,                    // 存檔後保存撤銷歷史，重新開啟時可以撤銷（`:set undofile`）
+    #[cfg(feature = "vi")]
+    modal: Option<crate::modal::Modal>, // vi 風格的模式輸入（`:set vi`）
+    #[cfg(feature = "lsp")]
+    lsp: Option<crate::lsp::LspClient>, // 目前檔案的語言伺服器
+    #[cfg(feature = "lsp")]
+    lsp_enabled: bool, // 開啟檔案時啟動語言伺服器
+    #[cfg(feature = "lsp")]
+    lsp_programs: std::collections::HashMap<String, String>, // `:set lspprg.EXT=CMD` 覆寫的伺服器命令
+    #[cfg(feature = "lsp")]
+    lsp_version: Option<u64>, // 已同步給語言伺服器的緩衝區版本
+    date_format: String,                              // `:insert date` 的格式
+    time_format: String,                              // `:insert time` 與 Alt+T 的格式
+    frame_interval: std::time::Duration,              // 兩次重繪之間的最短間隔（`:set maxfps`）
+    last_redraw: Instant,                             // 上一次重繪的時間
+    redraw_pending_since: Option<Instant>,            // 需要重繪、但為了合併連續輸入而延後的起點
+    terminated: Option<(i32, Option<PathBuf>)>,       // 收到的終止訊號與寫入的復原檔
+    emergency_copy_state: Option<(u64, bool)>,        // 緊急副本對應的緩衝區版本與是否已修改
+    key_debug: bool, // 在狀態列顯示收到的按鍵事件（`:set keydebug`）
+    autosave_change: Option<(u64, Instant)>, // 最後看到的緩衝區版本與時間，用來判斷閒置
+    autosaved_version: Option<u64>, // 上次自動存檔時的緩衝區版本
+    pending_recovery: Option<JoinHandle<Result<()>>>, // 背景寫入中的復原檔
+    recovery_written: Option<PathBuf>, // 本實例寫過復原檔的檔案（存檔或離開時刪除復原檔）
+    line_markers_version: Option<u64>, // 行號欄修改標記對應的緩衝區版本（None 表示需要重新計算）
+    whitespace_version: Option<u64>, // 行尾與空白檢查對應的緩衝區版本（None 表示需要重新檢查）
+    whitespace_hint: Option<String>, // 狀態列的行尾與空白提示，例如 `[noeol]`
+    filetype: Option<String>, // 手動指定的語言（`:set filetype`），None 為自動檢測
+    word_index: WordIndex, // 單字補全的索引（依緩衝區版本快取）
+    jumps: JumpList, // 跳轉歷史（Alt+Left/Right）
+    auto_scroll: Option<(u16, u16)>, // 拖曳選擇時停在畫面邊緣的指標位置，定時自動捲動
+    last_auto_scroll: std::time::Instant, // 上一次自動捲動的時間
+    saved: bool,     // 這次執行中是否成功存過檔（決定 --must-save 的結束碼）
+    show_search_matches: bool, // 是否在畫面上標示搜尋結果（ESC 關閉）
+    search_jump: Option<bool>, // 背景搜尋找到結果後要跳往的方向（true 為下一個）
+    cursor_style: Option<CursorStyle>, // 一般狀態的游標樣式（None 沿用終端預設）
+    selection_cursor_style: Option<CursorStyle>, // 選擇文字時的游標樣式（None 與一般狀態相同）
+    applied_cursor_style: Option<CursorStyle>, // 目前已套用到終端的游標樣式
+    probe_ambiguous_width: bool, // 啟動時測量終端顯示模糊寬度字元的欄數
+    config_file: Option<ConfigFile>, // 啟動時讀取的設定檔
+    #[cfg(feature = "git")]
+    git: Option<crate::git::GitFile>, // 檔案所在的 git 工作目錄
+    #[cfg(feature = "git")]
+    git_status: Option<crate::git::GitStatus>, // 分支與暫存狀態（顯示在狀態列）
+    #[cfg(feature = "git")]
+    git_head: Option<String>, // HEAD 版本的內容（行號欄以此標示修改的行）
+    #[cfg(debug_assertions)]
+    idle_frames: u32, // 自上次輸入後重繪的次數（用於檢查閒置時不重繪）
 
     // 語法高亮（可選功能）
     #[cfg(feature = "syntax-highlighting")]
@@ -36,6 +222,8 @@ pub struct Editor {
     #[cfg(feature = "syntax-highlighting")]
     pub(crate) highlight_cache: HighlightCache,
     #[cfg(feature = "syntax-highlighting")]
+    highlight_worker: HighlightWorker, // 高亮時間預算外的行與預先準備的行
+    #[cfg(feature = "syntax-highlighting")]
     #[allow(dead_code)]
     highlight_config: HighlightConfig,
     #[cfg(feature = "syntax-highlighting")]
@@ -54,6 +242,10 @@ impl Editor {
             RopeBuffer::from_file_with_encoding(path, encoding_config)?
         } else {
             let mut buffer = RopeBuffer::new();
+            // --bom/--no-bom 在設定存檔編碼之前記錄，UTF-16 才不會預設加上 BOM
+            if let Some(bom) = encoding_config.bom {
+                buffer.set_initial_bom(bom);
+            }
             // 如果指定了讀取編碼，設置編碼
             if let Some(enc) = encoding_config.read_encoding {
                 if cfg!(debug_assertions) {
@@ -91,14 +283,26 @@ impl Editor {
             buffer
         };
 
+        Self::with_buffer(
+            buffer,
+            debug_mode,
+            #[cfg(feature = "syntax-highlighting")]
+            theme,
+        )
+    }
+
+    /// 以已建立的緩衝區建立編輯器（例如 stdin 管道輸入的內容）
+    pub fn with_buffer(
+        buffer: RopeBuffer,
+        debug_mode: bool,
+        #[cfg(feature = "syntax-highlighting")] theme: Option<&str>,
+    ) -> Result<Self> {
         let terminal = Terminal::new()?;
         let view = View::new(&terminal);
         let clipboard = ClipboardManager::new()?;
 
-        let mut comment_handler = CommentHandler::new();
-        if let Some(path) = file_path {
-            comment_handler.detect_from_path(path);
-        }
+        // 檢查是否有其他 wedi 實例正在編輯同一檔案（鎖在進入 run() 後才建立）
+        let lock_conflict = buffer.file_path().and_then(FileLock::existing);
 
         // 語法高亮初始化
         #[cfg(feature = "syntax-highlighting")]
@@ -110,93 +314,155 @@ impl Editor {
                 config.theme = custom_theme.to_string();
             }
 
-            let mut engine = if config.enabled {
+            let engine = if config.enabled {
+                // 語法集在背景載入，檔案先以純文字顯示，載入完成後再補上高亮
+                crate::highlight::preload_syntax_set();
                 HighlightEngine::new(Some(&config.theme), config.true_color).ok()
             } else {
                 None
             };
 
-            // 如果有檔案，設定語法類型
-            if let (Some(path), Some(ref mut eng)) = (file_path, engine.as_mut()) {
-                eng.set_file(Some(path));
-            }
-
             (engine, HighlightCache::new(), config)
         };
 
-        Ok(Self {
-            buffer,
-            cursor: Cursor::new(),
-            view,
+        let mut core = EditorCore::with_view(buffer, view);
+        core.ruler = debug_mode; // 除錯模式在頂部顯示欄位尺規
+
+        let mut editor = Self {
+            core,
             terminal,
             clipboard,
-            internal_clipboard: String::new(), // 初始化內部剪貼簿
             search: Search::new(),
-            comment_handler,
             should_quit: false,
-            selection: None,
-            selection_mode: false, // 預設關閉選擇模式
             message: None,
-            quit_times: 0,
+            error: None,
             debug_mode,
+            file_lock: None,
+            lock_conflict,
+            needs_redraw: true,
+            output_to_stdout: false,
+            git_message: false,
+            git_comment: "#".to_string(),
+            auto_text_width: false,
+            pending_save: None,
+            autosave: AutosavePolicy::Off,
+            hooks: HookRegistry::builtin(),
+            autowrite: false,
+            format_program: None,
+            format_on_save: false,
+            save_warning: None,
+            lint_enabled: true,
+            lint_programs: std::collections::HashMap::new(),
+            pending_lint: None,
+            diagnostics: Vec::new(),
+            grep_results: None,
+            buffers: vec![None],
+            current_buffer: 0,
+            tab_line: true,
+            undo_file: false,
+            #[cfg(feature = "vi")]
+            modal: None,
+            #[cfg(feature = "lsp")]
+            lsp: None,
+            #[cfg(feature = "lsp")]
+            lsp_enabled: false,
+            #[cfg(feature = "lsp")]
+            lsp_programs: std::collections::HashMap::new(),
+            #[cfg(feature = "lsp")]
+            lsp_version: None,
+            date_format: crate::template::DEFAULT_DATE_FORMAT.to_string(),
+            time_format: crate::template::DEFAULT_TIME_FORMAT.to_string(),
+            frame_interval: std::time::Duration::from_secs(1) / DEFAULT_MAX_FPS,
+            last_redraw: Instant::now(),
+            redraw_pending_since: None,
+            terminated: None,
+            emergency_copy_state: None,
+            key_debug: false,
+            autosave_change: None,
+            autosaved_version: None,
+            pending_recovery: None,
+            recovery_written: None,
+            line_markers_version: None,
+            whitespace_version: None,
+            whitespace_hint: None,
+            filetype: None,
+            word_index: WordIndex::new(),
+            jumps: JumpList::new(),
+            auto_scroll: None,
+            last_auto_scroll: std::time::Instant::now(),
+            saved: false,
+            show_search_matches: false,
+            search_jump: None,
+            cursor_style: None,
+            selection_cursor_style: None,
+            applied_cursor_style: None,
+            probe_ambiguous_width: false,
+            config_file: None,
+            #[cfg(feature = "git")]
+            git: None,
+            #[cfg(feature = "git")]
+            git_status: None,
+            #[cfg(feature = "git")]
+            git_head: None,
+            #[cfg(debug_assertions)]
+            idle_frames: 0,
 
             #[cfg(feature = "syntax-highlighting")]
             highlight_engine,
             #[cfg(feature = "syntax-highlighting")]
             highlight_cache,
             #[cfg(feature = "syntax-highlighting")]
+            highlight_worker: HighlightWorker::new(),
+            #[cfg(feature = "syntax-highlighting")]
             highlight_config,
             #[cfg(feature = "syntax-highlighting")]
             highlight_enabled: true, // 預設啟用語法高亮
-        })
+        };
+
+        // 依檔名與內容設定註解風格和語法類型
+        editor.detect_file_type();
+        #[cfg(feature = "git")]
+        editor.refresh_git();
+
+        Ok(editor)
     }
 
     pub fn run(&mut self) -> Result<()> {
         Terminal::enter_raw_mode()?;
-        Terminal::clear_screen()?;
-
-        while !self.should_quit {
-            let debug_info = if self.debug_mode {
-                Some(self.get_debug_info())
-            } else {
-                None
-            };
-
-            // ⚠️ 重要：在計算高亮之前先更新 offset_row
-            // 避免跳頁後 highlighted_lines 使用舊的 offset_row
-            let has_debug_ruler = self.debug_mode;
-            self.view
-                .scroll_if_needed(&self.cursor, &self.buffer, has_debug_ruler);
-
-            // 獲取語法高亮行
-            #[cfg(feature = "syntax-highlighting")]
-            let highlighted_lines = {
-                if self.highlight_enabled {
-                    let start_row = self.view.offset_row;
-                    let end_row = start_row + self.view.screen_rows;
-                    self.get_highlighted_lines(start_row, end_row)
-                } else {
-                    std::collections::HashMap::new()
+        if self.probe_ambiguous_width {
+            match Terminal::probe_ambiguous_wide() {
+                Ok(wide) => crate::utils::set_ambiguous_wide(wide),
+                Err(e) => {
+                    self.error = Some(format!("Ambiguous width probe failed: {}", e));
                 }
-            };
+            }
+        }
+        Terminal::clear_screen()?;
+        Terminal::install_signal_handlers();
+        spawn_termination_watchdog();
 
-            self.view.render(
-                &self.buffer,
-                &self.cursor,
-                self.selection.as_ref(),
-                if self.debug_mode {
-                    debug_info.as_deref()
-                } else {
-                    self.message.as_deref()
-                },
-                #[cfg(feature = "syntax-highlighting")]
-                Some(&highlighted_lines),
-            )?;
+        self.resolve_lock_conflict()?;
+        if self.core.buffer.has_file_path() {
+            if let Err(e) = self.run_hooks(None, |hook, ctx| hook.on_open(ctx)) {
+                self.error = Some(format!("{:#}", e));
+            }
+        }
 
-            let key_event = Terminal::read_key()?;
+        let result = self.event_loop();
 
-            if let Some(command) = handle_key_event(key_event, self.selection_mode) {
-                self.handle_command(command)?;
+        // 收到終止訊號：保留未存檔的修改後結束（終端已掛斷時讀取按鍵會失敗，不回報迴圈的錯誤）
+        if let Some(signal) = Terminal::termination_signal() {
+            self.terminate(signal);
+            return Ok(());
+        }
+        result?;
+
+        // 結束前等待背景存檔寫完；正常離開時不再需要復原檔
+        self.poll_save(true);
+        self.remove_recovery();
+        for hidden in self.buffers.iter_mut().flatten() {
+            if let Some(path) = hidden.recovery_written.take() {
+                crate::autosave::remove_recovery(&path);
             }
         }
 
@@ -204,1055 +470,4178 @@ impl Editor {
         Ok(())
     }
 
-    fn handle_command(&mut self, command: Command) -> Result<()> {
-        // 任何非 Quit 的命令都重置 quit_times
-        if !matches!(command, Command::Quit) {
-            self.quit_times = 0;
-        }
+    /// 主迴圈：重繪、讀取按鍵並執行命令，直到離開或收到終止訊號
+    fn event_loop(&mut self) -> Result<()> {
+        while !self.should_quit && Terminal::termination_signal().is_none() {
+            // 收到 SIGTSTP：還原終端後暫停，返回時完整重繪
+            if Terminal::take_suspend_request() {
+                self.suspend()?;
+            }
 
-        match command {
-            // 字符輸入
-            Command::Insert(ch) => {
-                if self.has_selection() {
-                    self.delete_selection();
-                }
-
-                let pos = self.cursor.char_position(&self.buffer);
-                self.buffer.insert_char(pos, ch);
-
-                // 優化：僅失效當前行（除非是換行符，需要重建整個緩存）
-                if ch == '\n' {
-                    self.view.invalidate_cache(); // 換行影響多行佈局
-                    #[cfg(feature = "syntax-highlighting")]
-                    self.highlight_cache.clear(); // 語法高亮快取也需要清除
-                    self.cursor.row += 1;
-                    self.cursor.reset_to_line_start();
-                } else {
-                    self.view.invalidate_line(self.cursor.row); // 僅失效當前行
-                    #[cfg(feature = "syntax-highlighting")]
-                    self.invalidate_highlight_cache(self.cursor.row); // 語法高亮快取失效
-                    self.cursor.set_position(
-                        &self.buffer,
-                        &self.view,
-                        self.cursor.row,
-                        self.cursor.col + 1,
-                    );
+            // 子程序可能改變終端模式，發現時重新進入 raw mode 並完整重繪
+            if Terminal::ensure_raw_mode()? {
+                self.core.view.update_size();
+                self.core.view.invalidate_cache();
+                // 子程序可能改變了游標樣式，重新套用
+                self.applied_cursor_style = None;
+                // 子程序可能是 git 指令，重新讀取儲存庫狀態
+                #[cfg(feature = "git")]
+                self.refresh_git();
+                self.needs_redraw = true;
+                #[cfg(debug_assertions)]
+                {
+                    self.idle_frames = 0;
                 }
+            }
 
-                self.selection = None;
-                self.selection_mode = false; // 輸入後關閉選擇模式
-            }
-
-            // 刪除操作
-            Command::Backspace => {
-                if self.has_selection() {
-                    self.delete_selection();
-                } else if self.cursor.col > 0 {
-                    // 行內刪除
-                    let new_col = self.cursor.col - 1;
-                    let pos = self.buffer.line_to_char(self.cursor.row) + new_col;
-                    self.buffer.delete_char(pos);
-                    self.view.invalidate_line(self.cursor.row); // 僅失效當前行
-                    #[cfg(feature = "syntax-highlighting")]
-                    self.invalidate_highlight_cache(self.cursor.row);
-                    self.cursor
-                        .set_position(&self.buffer, &self.view, self.cursor.row, new_col);
-                } else if self.cursor.row > 0 {
-                    // 刪除換行符，合併到上一行
-                    let new_row = self.cursor.row - 1;
-                    let prev_line_len = self
-                        .buffer
-                        .get_line_content(new_row)
-                        .trim_end_matches(['\n', '\r'])
-                        .chars()
-                        .count();
-
-                    let pos = self.buffer.line_to_char(new_row) + prev_line_len;
-                    self.buffer.delete_char(pos);
-                    self.view.invalidate_cache(); // 行合併影響多行
-                    #[cfg(feature = "syntax-highlighting")]
-                    self.highlight_cache.clear();
-
-                    self.cursor
-                        .set_position(&self.buffer, &self.view, new_row, prev_line_len);
-                }
-                self.selection_mode = false; // 刪除後關閉選擇模式
+            // 同步鎖檔中的未存檔狀態，讓其他實例能看到
+            if let Some(lock) = self.file_lock.as_mut() {
+                lock.set_modified(self.core.buffer.is_modified());
             }
+            self.update_emergency_copy();
 
-            Command::Delete => {
-                if self.has_selection() {
-                    self.delete_selection();
-                } else {
-                    let pos = self.cursor.char_position(&self.buffer);
-                    let line_content = self.buffer.get_line_content(self.cursor.row);
-                    let at_line_end = self.cursor.col
-                        >= line_content.trim_end_matches(['\n', '\r']).chars().count();
-
-                    self.buffer.delete_char(pos);
-
-                    // 優化：如果在行尾刪除（會合併下一行），需要完全失效；否則僅失效當前行
-                    if at_line_end {
-                        self.view.invalidate_cache(); // 行合併影響多行
-                        #[cfg(feature = "syntax-highlighting")]
-                        self.highlight_cache.clear();
-                    } else {
-                        self.view.invalidate_line(self.cursor.row); // 僅失效當前行
-                        #[cfg(feature = "syntax-highlighting")]
-                        self.invalidate_highlight_cache(self.cursor.row);
-                    }
+            if self.needs_redraw {
+                // 連續輸入（按住方向鍵、慢速連線上一次到達的多個按鍵）先全部處理再重繪一次
+                if let Some(key_event) = self.coalesced_key()? {
+                    self.handle_key(key_event)?;
+                    continue;
                 }
-                self.selection_mode = false; // 刪除後關閉選擇模式
+                self.redraw()?;
+                self.needs_redraw = false;
+                self.last_redraw = Instant::now();
+                self.redraw_pending_since = None;
             }
 
-            Command::DeleteLine => {
-                if self.has_selection() {
-                    self.delete_selection();
-                } else {
-                    // 記錄是否在最後一行
-                    let was_last_line = self.cursor.row == self.buffer.line_count() - 1;
+            // 閒置時不做任何重繪或配置，維持 0% CPU
+            let Some(key_event) = self.next_key()? else {
+                continue;
+            };
+            self.handle_key(key_event)?;
+        }
+        Ok(())
+    }
 
-                    self.buffer.delete_line(self.cursor.row);
-                    self.view.invalidate_cache();
-                    #[cfg(feature = "syntax-highlighting")]
-                    self.highlight_cache.clear();
+    /// 重繪前取出已到達或在這一幀的間隔內到達的按鍵，讓連續的按鍵合併成一次重繪
+    ///
+    /// 距離上一次重繪已超過 `frame_interval` 時不等待，只取出已經到達的按鍵；
+    /// 重繪最多延後一個間隔，持續不斷的輸入也會定期更新畫面
+    fn coalesced_key(&mut self) -> Result<Option<crossterm::event::KeyEvent>> {
+        let pending_since = *self.redraw_pending_since.get_or_insert_with(Instant::now);
+        if pending_since.elapsed() >= self.frame_interval {
+            return Ok(None);
+        }
+        let wait = self
+            .frame_interval
+            .saturating_sub(self.last_redraw.elapsed());
+        self.terminal.poll_key(wait)
+    }
 
-                    // 如果刪除的是最後一行且不是唯一一行，光標上移
-                    if was_last_line && self.cursor.row > 0 {
-                        self.cursor.row -= 1;
-                    }
+    /// 執行按鍵對應的命令
+    fn handle_key(&mut self, key_event: crossterm::event::KeyEvent) -> Result<()> {
+        #[cfg(debug_assertions)]
+        {
+            self.idle_frames = 0;
+        }
 
-                    // 確保光標在有效範圍內
-                    if self.cursor.row >= self.buffer.line_count() && self.buffer.line_count() > 0 {
-                        self.cursor.row = self.buffer.line_count() - 1;
-                    }
+        // 按鍵（滑鼠以外的事件）結束拖曳時的自動捲動
+        if key_event.code != KeyCode::F(22) {
+            self.auto_scroll = None;
+        }
 
-                    self.cursor.reset_to_line_start();
-                }
-                self.selection_mode = false; // 刪除後關閉選擇模式
-            }
-
-            // 光標移動
-            Command::MoveUp => {
-                self.cursor.move_up(&self.buffer, &self.view);
-                self.selection = None;
-            }
-            Command::MoveDown => {
-                self.cursor.move_down(&self.buffer, &self.view);
-                self.selection = None;
-            }
-            Command::MoveLeft => {
-                self.cursor.move_left(&self.buffer, &self.view);
-                self.selection = None;
-            }
-            Command::MoveRight => {
-                self.cursor.move_right(&self.buffer, &self.view);
-                self.selection = None;
-            }
-            Command::MoveHome => {
-                self.cursor.move_to_line_start();
-                self.selection = None;
-            }
-            Command::MoveEnd => {
-                self.cursor.move_to_line_end(&self.buffer, &self.view);
-                self.selection = None;
-            }
-            Command::PageUp => {
-                let effective_rows = self.view.get_effective_screen_rows(self.debug_mode);
-                // 記錄光標在屏幕上的 Y 位置
-                let cursor_screen_y = self.view.get_cursor_screen_y(&self.cursor, &self.buffer);
-                // 翻頁並維持光標屏幕位置
-                let (new_row, new_visual_line_index) =
-                    self.view
-                        .scroll_page(-1, cursor_screen_y, &self.buffer, effective_rows);
-                // 更新光標位置
-                self.cursor.row = new_row;
-                self.cursor.visual_line_index = new_visual_line_index;
-                self.cursor
-                    .set_position(&self.buffer, &self.view, new_row, self.cursor.col);
-                self.selection = None;
-            }
-            Command::PageDown => {
-                let effective_rows = self.view.get_effective_screen_rows(self.debug_mode);
-                // 記錄光標在屏幕上的 Y 位置
-                let cursor_screen_y = self.view.get_cursor_screen_y(&self.cursor, &self.buffer);
-                // 翻頁並維持光標屏幕位置
-                let (new_row, new_visual_line_index) =
-                    self.view
-                        .scroll_page(1, cursor_screen_y, &self.buffer, effective_rows);
-                // 更新光標位置
-                self.cursor.row = new_row;
-                self.cursor.visual_line_index = new_visual_line_index;
-                self.cursor
-                    .set_position(&self.buffer, &self.view, new_row, self.cursor.col);
-                self.selection = None;
-            }
-
-            Command::MoveToFileStart => {
-                self.cursor.move_to_file_start(&self.view);
-                self.selection = None;
-            }
-            Command::MoveToFileEnd => {
-                self.cursor.move_to_file_end(&self.buffer, &self.view);
-                self.selection = None;
-            }
-
-            Command::JumpTenthUp => {
-                let total_lines = self.buffer.line_count();
-                let jump_distance = total_lines.max(10) / 10; // 至少跳 1 行
-                self.cursor.row = self.cursor.row.saturating_sub(jump_distance);
-                self.cursor.set_position(
-                    &self.buffer,
-                    &self.view,
-                    self.cursor.row,
-                    self.cursor.col,
-                );
-                self.selection = None;
+        // vi 模式先由模式層轉換按鍵，沒有對應時照常使用快捷鍵
+        #[cfg(feature = "vi")]
+        if let Some(commands) = self.modal_commands(key_event) {
+            for command in commands {
+                self.run_command(command)?;
             }
+            self.needs_redraw = true;
+            return Ok(());
+        }
 
-            Command::JumpTenthDown => {
-                let total_lines = self.buffer.line_count();
-                let jump_distance = total_lines.max(10) / 10;
-                let new_row = self
-                    .cursor
-                    .row
-                    .saturating_add(jump_distance)
-                    .min(total_lines.saturating_sub(1));
-                self.cursor.row = new_row;
-                self.cursor.set_position(
-                    &self.buffer,
-                    &self.view,
-                    self.cursor.row,
-                    self.cursor.col,
-                );
-                self.selection = None;
-            }
+        // 沒有對應命令的按鍵不會改變畫面，不需要重繪
+        let command = handle_key_event(key_event, self.core.selection_mode);
+        let debug = self
+            .key_debug
+            .then(|| Self::describe_key(&key_event, &command))
+            .flatten();
+        if let Some(command) = command {
+            self.run_command(command)?;
+            self.needs_redraw = true;
+        }
+        if let Some(text) = debug {
+            self.message = Some(text);
+            self.needs_redraw = true;
+        }
+        Ok(())
+    }
 
-            // 選擇操作
-            Command::ExtendSelection(direction) => {
-                if self.selection.is_none() {
-                    self.selection = Some(Selection {
-                        start: (self.cursor.row, self.cursor.col),
-                        end: (self.cursor.row, self.cursor.col),
-                    });
-                }
+    /// 執行按鍵的命令，並通知外掛
+    fn run_command(&mut self, command: Command) -> Result<()> {
+        let hooked = (!self.hooks.is_empty()).then(|| command.clone());
+        self.handle_command(command)?;
+        if let Some(command) = hooked {
+            self.command_hooks(&command);
+        }
+        Ok(())
+    }
 
-                match direction {
-                    Direction::Up => self.cursor.move_up(&self.buffer, &self.view),
-                    Direction::Down => self.cursor.move_down(&self.buffer, &self.view),
-                    Direction::Left => self.cursor.move_left(&self.buffer, &self.view),
-                    Direction::Right => self.cursor.move_right(&self.buffer, &self.view),
-                    Direction::Home => self.cursor.move_to_line_start(),
-                    Direction::End => self.cursor.move_to_line_end(&self.buffer, &self.view),
-                    Direction::FileStart => {
-                        self.cursor.move_to_file_start(&self.view);
-                    }
-                    Direction::FileEnd => {
-                        self.cursor.move_to_file_end(&self.buffer, &self.view);
-                    }
-                    Direction::PageUp => {
-                        let effective_rows = self.view.get_effective_screen_rows(self.debug_mode);
-                        let cursor_screen_y =
-                            self.view.get_cursor_screen_y(&self.cursor, &self.buffer);
-                        let (new_row, new_visual_line_index) = self.view.scroll_page(
-                            -1,
-                            cursor_screen_y,
-                            &self.buffer,
-                            effective_rows,
-                        );
-                        self.cursor.row = new_row;
-                        self.cursor.visual_line_index = new_visual_line_index;
-                        self.cursor
-                            .set_position(&self.buffer, &self.view, new_row, self.cursor.col);
-                    }
-                    Direction::PageDown => {
-                        let effective_rows = self.view.get_effective_screen_rows(self.debug_mode);
-                        let cursor_screen_y =
-                            self.view.get_cursor_screen_y(&self.cursor, &self.buffer);
-                        let (new_row, new_visual_line_index) = self.view.scroll_page(
-                            1,
-                            cursor_screen_y,
-                            &self.buffer,
-                            effective_rows,
-                        );
-                        self.cursor.row = new_row;
-                        self.cursor.visual_line_index = new_visual_line_index;
-                        self.cursor
-                            .set_position(&self.buffer, &self.view, new_row, self.cursor.col);
-                    }
-                    Direction::TenthUp => {
-                        let total_lines = self.buffer.line_count();
-                        let jump_distance = total_lines.max(10) / 10;
-                        self.cursor.row = self.cursor.row.saturating_sub(jump_distance);
-                        self.cursor.set_position(
-                            &self.buffer,
-                            &self.view,
-                            self.cursor.row,
-                            self.cursor.col,
-                        );
-                    }
-                    Direction::TenthDown => {
-                        let total_lines = self.buffer.line_count();
-                        let jump_distance = total_lines.max(10) / 10;
-                        let new_row = self
-                            .cursor
-                            .row
-                            .saturating_add(jump_distance)
-                            .min(total_lines.saturating_sub(1));
-                        self.cursor.row = new_row;
-                        self.cursor.set_position(
-                            &self.buffer,
-                            &self.view,
-                            self.cursor.row,
-                            self.cursor.col,
-                        );
-                    }
-                }
+    /// vi 模式（`:set vi`）下按鍵對應的命令；None 表示交給一般的快捷鍵
+    #[cfg(feature = "vi")]
+    fn modal_commands(&mut self, key_event: crossterm::event::KeyEvent) -> Option<Vec<Command>> {
+        let modal = self.modal.as_mut()?;
+        let line_len = self
+            .core
+            .buffer
+            .get_line_content(self.core.cursor.row)
+            .trim_end_matches(['\n', '\r'])
+            .chars()
+            .count();
+        let edges = crate::modal::LineEdges {
+            at_start: self.core.cursor.col == 0,
+            at_end: self.core.cursor.col >= line_len,
+        };
+        modal.handle_key(key_event, edges)
+    }
 
-                if let Some(sel) = &mut self.selection {
-                    sel.end = (self.cursor.row, self.cursor.col);
+    /// 因終止訊號結束：等待背景存檔，把未存檔的修改寫到復原檔，再盡量還原終端
+    ///
+    /// 復原檔在下次開啟同一檔案時提示；沒有檔名的緩衝區無處可寫
+    fn terminate(&mut self, signal: i32) {
+        if crate::autosave::claim_emergency_copy().is_none() {
+            // 監看執行緒已經在寫復原檔，會在寫完後結束程序
+            loop {
+                std::thread::park();
+            }
+        }
+        self.poll_save(true);
+        if let Some(handle) = self.pending_recovery.take() {
+            let _ = handle.join();
+        }
+        let recovery = match self.core.buffer.file_path() {
+            Some(path) if self.core.buffer.is_modified() && !self.output_to_stdout => {
+                crate::autosave::write_emergency_copy(path, &self.core.buffer.text())
+            }
+            _ => None,
+        };
+        // 背景緩衝區的修改也寫入各自的復原檔
+        for hidden in self.buffers.iter().flatten() {
+            if let Some(path) = hidden.buffer.file_path() {
+                if hidden.buffer.is_modified() && !self.output_to_stdout {
+                    crate::autosave::write_emergency_copy(path, &hidden.buffer.text());
                 }
             }
+        }
+        // 結束時不刪除這個復原檔
+        self.recovery_written = None;
+        self.terminated = Some((signal, recovery));
 
-            Command::SelectAll => {
-                let last_line = self.buffer.line_count().saturating_sub(1);
-                let last_col = self
-                    .buffer
-                    .get_line_content(last_line)
-                    .trim_end_matches(['\n', '\r'])
-                    .chars()
-                    .count();
+        let _ = Terminal::exit_raw_mode();
+        let _ = Terminal::show_cursor();
+        let _ = Terminal::reset_cursor_style();
+    }
 
-                self.selection = Some(Selection {
-                    start: (0, 0),
-                    end: (last_line, last_col),
-                });
-                self.cursor.row = last_line;
-                self.cursor.col = last_col;
-            }
+    /// 緩衝區或存檔狀態改變時更新收到終止訊號時使用的緊急副本
+    fn update_emergency_copy(&mut self) {
+        let buffer = &self.core.buffer;
+        let state = (buffer.version(), buffer.is_modified());
+        if self.emergency_copy_state == Some(state) {
+            return;
+        }
+        self.emergency_copy_state = Some(state);
+        let copy = buffer
+            .file_path()
+            .filter(|_| buffer.is_modified() && !self.output_to_stdout)
+            .map(|path| (path.to_path_buf(), buffer.rope_snapshot()));
+        crate::autosave::set_emergency_copy(copy);
+    }
 
-            Command::ClearSelection => {
-                self.selection = None;
-            }
+    /// 收到的終止訊號與寫入的復原檔（正常結束時為 None）
+    pub fn terminated(&self) -> Option<(i32, Option<&Path>)> {
+        self.terminated
+            .as_ref()
+            .map(|(signal, recovery)| (*signal, recovery.as_deref()))
+    }
 
-            Command::ClearMessage => {
-                self.selection = None;
-                self.selection_mode = false; // ESC 關閉選擇模式但保留選擇範圍
-                self.message = None;
+    /// 按鍵除錯訊息：終端送來的按鍵、修飾鍵與對應的命令；內部標記（貼上、縮放、滑鼠）不顯示
+    fn describe_key(key: &crossterm::event::KeyEvent, command: &Option<Command>) -> Option<String> {
+        if matches!(key.code, KeyCode::F(20..=22)) {
+            return None;
+        }
+        let mut text = String::from("Key: ");
+        for (modifier, name) in [
+            (KeyModifiers::CONTROL, "Ctrl+"),
+            (KeyModifiers::ALT, "Alt+"),
+            (KeyModifiers::SHIFT, "Shift+"),
+            (KeyModifiers::SUPER, "Super+"),
+            (KeyModifiers::META, "Meta+"),
+            (KeyModifiers::HYPER, "Hyper+"),
+        ] {
+            if key.modifiers.contains(modifier) {
+                text.push_str(name);
             }
+        }
+        match key.code {
+            KeyCode::Char(ch) => {
+                text.push_str(&format!("'{}' U+{:04X}", ch.escape_debug(), ch as u32))
+            }
+            code => text.push_str(&format!("{:?}", code)),
+        }
+        let command = command
+            .as_ref()
+            .map_or_else(|| "(none)".to_string(), |c| format!("{:?}", c));
+        text.push_str(&format!(" {:?} -> {}", key.kind, command));
+        Some(text)
+    }
 
-            // 選擇模式切換
-            Command::ToggleSelectionMode => {
-                self.selection_mode = !self.selection_mode;
+    /// 這次執行中是否成功存過檔
+    pub fn saved(&self) -> bool {
+        self.saved
+    }
 
-                // 開啟選擇模式時，如果沒有選擇範圍，初始化選擇
-                if self.selection_mode && self.selection.is_none() {
-                    self.selection = Some(Selection {
-                        start: (self.cursor.row, self.cursor.col),
-                        end: (self.cursor.row, self.cursor.col),
-                    });
-                }
+    /// 啟用 filter 模式：結束時由呼叫端將緩衝區寫到 stdout，離開時不再警告未存檔修改
+    pub fn set_output_to_stdout(&mut self, enabled: bool) {
+        self.output_to_stdout = enabled;
+    }
 
-                self.message = Some(format!(
-                    "Selection Mode: {}",
-                    if self.selection_mode { "ON" } else { "OFF" }
-                ));
-            }
+    /// 設定自動存檔方式；改為不寫復原檔時刪除已寫入的復原檔
+    pub fn set_autosave(&mut self, policy: AutosavePolicy) {
+        if policy != AutosavePolicy::Recovery {
+            self.remove_recovery();
+        }
+        self.autosave = policy;
+        self.autosave_change = None;
+        self.autosaved_version = None;
+    }
 
-            // 剪貼板操作
-            Command::Copy => {
-                let text = self.get_copy_text();
-                self.set_clipboard_text(text, true);
-                // 複製後關閉選擇模式並清除選擇範圍
-                self.selection_mode = false;
-                self.selection = None;
-            }
+    /// 執行外部命令（`:!`）前先存檔
+    pub fn set_autowrite(&mut self, enabled: bool) {
+        self.autowrite = enabled;
+    }
 
-            Command::Cut => {
-                let text = self.get_copy_text();
-                self.set_clipboard_text(text, true);
+    /// 以背景色標示光標所在行（None 表示不標示）
+    pub fn set_cursor_line(&mut self, color: Option<crossterm::style::Color>) {
+        self.core.view.cursor_line = color;
+    }
 
-                // 剪切後刪除內容
-                if self.has_selection() {
-                    self.delete_selection();
-                } else {
-                    // 記錄是否在最後一行
-                    let was_last_line = self.cursor.row == self.buffer.line_count() - 1;
+    /// 在指定的欄位（1-based）畫出背景色參考線，空列表表示不顯示；color 為 None 時沿用目前的顏色
+    pub fn set_color_columns(
+        &mut self,
+        columns: Vec<usize>,
+        color: Option<crossterm::style::Color>,
+    ) {
+        self.core.view.color_columns = columns;
+        if let Some(color) = color {
+            self.core.view.color_column_color = color;
+        }
+    }
 
-                    self.buffer.delete_line(self.cursor.row);
-                    self.view.invalidate_cache();
+    fn color_columns_message(&self) -> String {
+        let columns = &self.core.view.color_columns;
+        if columns.is_empty() {
+            return "Color columns: Off".to_string();
+        }
+        let list: Vec<String> = columns.iter().map(|c| c.to_string()).collect();
+        format!("Color columns: {}", list.join(","))
+    }
 
-                    // 如果刪除的是最後一行且不是唯一一行，光標上移
-                    if was_last_line && self.cursor.row > 0 {
-                        self.cursor.row -= 1;
-                    }
+    /// 套用 `KEY[=VALUE]` 形式的選項，與命令列的 `:set` 相同
+    pub fn apply_setting(&mut self, setting: &str) -> Result<()> {
+        let ExCommand::Set { option, value } = crate::ex::parse(&format!("set {}", setting))?
+        else {
+            bail!("Invalid setting: {}", setting);
+        };
+        self.set_option(&option, value.as_deref())?;
+        Ok(())
+    }
 
-                    // 確保光標在有效範圍內
-                    if self.cursor.row >= self.buffer.line_count() && self.buffer.line_count() > 0 {
-                        self.cursor.row = self.buffer.line_count() - 1;
+    /// 依序套用設定檔中的選項
+    ///
+    /// 有問題的行不中斷啟動：第一個錯誤顯示在錯誤訊息列，全部列在 `:checkhealth` 中
+    pub fn load_config(&mut self, path: &Path) {
+        let mut file = ConfigFile {
+            path: path.to_path_buf(),
+            applied: 0,
+            errors: Vec::new(),
+        };
+        match crate::config::load(path) {
+            Ok(settings) => {
+                for (line, setting) in settings {
+                    match self.apply_setting(&setting) {
+                        Ok(()) => file.applied += 1,
+                        Err(e) => file.errors.push(format!("line {}: {:#}", line, e)),
                     }
-
-                    self.cursor.col = 0;
-                    self.cursor.desired_visual_col = 0;
                 }
-
-                // 剪切後關閉選擇模式並清除選擇
-                self.selection_mode = false;
-            }
-
-            Command::Paste => {
-                let text = self.get_clipboard_text(true);
-                self.paste_text(text);
-                self.selection_mode = false; // 貼上後關閉選擇模式
             }
+            Err(e) => file.errors.push(format!("{:#}", e)),
+        }
+        if let Some(error) = file.errors.first() {
+            let more = match file.errors.len() {
+                1 => String::new(),
+                n => format!(" (+{} more, see :checkhealth)", n - 1),
+            };
+            self.error = Some(format!("{}: {}{}", path.display(), error, more));
+        }
+        self.config_file = Some(file);
+    }
 
-            // 內部剪貼板操作（僅使用內部剪貼簿）
-            Command::CopyInternal => {
-                let text = self.get_copy_text();
-                self.set_clipboard_text(text, false);
-                self.selection_mode = false; // 複製後關閉選擇模式
-                self.selection = None; // 複製後清除選擇範圍
-            }
+    /// 設定行號的顯示方式（絕對、相對或混合）
+    pub fn set_line_number_mode(&mut self, mode: LineNumberMode) {
+        self.core.view.line_number_mode = mode;
+    }
 
-            Command::CutInternal => {
-                let text = self.get_copy_text();
-                self.set_clipboard_text(text, false);
+    /// 在頂部固定顯示欄位尺規，並標示指定的欄位（1-based）
+    pub fn set_ruler(&mut self, columns: Vec<usize>) {
+        self.core.view.show_ruler = true;
+        self.core.view.ruler_columns = columns;
+    }
 
-                // 剪切後刪除內容
-                if self.has_selection() {
-                    self.delete_selection();
-                } else {
-                    // 記錄是否在最後一行
-                    let was_last_line = self.cursor.row == self.buffer.line_count() - 1;
+    /// 設定游標樣式；selection 為選擇文字時使用的樣式（None 與一般狀態相同）
+    pub fn set_cursor_styles(
+        &mut self,
+        normal: Option<CursorStyle>,
+        selection: Option<CursorStyle>,
+    ) {
+        self.cursor_style = normal;
+        self.selection_cursor_style = selection;
+    }
 
-                    self.buffer.delete_line(self.cursor.row);
-                    self.view.invalidate_cache();
+    /// 設定東亞模糊寬度字元佔幾欄；Auto 於啟動時測量終端
+    pub fn set_ambiguous_width(&mut self, width: AmbiguousWidth) {
+        match width {
+            AmbiguousWidth::Narrow => crate::utils::set_ambiguous_wide(false),
+            AmbiguousWidth::Wide => crate::utils::set_ambiguous_wide(true),
+            AmbiguousWidth::Auto => self.probe_ambiguous_width = true,
+        }
+    }
 
-                    // 如果刪除的是最後一行且不是唯一一行，光標上移
-                    if was_last_line && self.cursor.row > 0 {
-                        self.cursor.row -= 1;
-                    }
+    /// 取得緩衝區（例如結束後輸出內容）
+    pub fn buffer(&self) -> &RopeBuffer {
+        &self.core.buffer
+    }
 
-                    // 確保光標在有效範圍內
-                    if self.cursor.row >= self.buffer.line_count() && self.buffer.line_count() > 0 {
-                        self.cursor.row = self.buffer.line_count() - 1;
-                    }
+    /// 跳到指定行（1-based），行號超出範圍時返回 false
+    pub fn go_to_line(&mut self, line_num: usize) -> bool {
+        self.core.go_to_line(line_num)
+    }
 
-                    self.cursor.col = 0;
-                    self.cursor.desired_visual_col = 0;
+    /// 等待下一個按鍵
+    ///
+    /// 平時阻塞等待；有背景工作（語法集載入、存檔）時改為輪詢，完成後標記重繪並返回 None
+    fn next_key(&mut self) -> Result<Option<crossterm::event::KeyEvent>> {
+        if !self.has_background_work() {
+            let key = self.terminal.read_key_or_wake()?;
+            // 語言伺服器的讀取執行緒收到訊息時喚醒主迴圈（見 `Terminal::wake`）
+            #[cfg(feature = "lsp")]
+            if self.poll_lsp() {
+                self.needs_redraw = true;
+                #[cfg(debug_assertions)]
+                {
+                    self.idle_frames = 0;
                 }
-                self.selection_mode = false; // 剪切後關閉選擇模式
             }
+            return Ok(key);
+        }
 
-            Command::PasteInternal => {
-                let text = self.get_clipboard_text(false);
-                self.paste_text(text);
-                self.selection_mode = false; // 貼上後關閉選擇模式
-            }
+        // 背景搜尋時不等待，沒有按鍵就繼續找下一段
+        let timeout = if self.search.is_searching() {
+            std::time::Duration::ZERO
+        } else {
+            BACKGROUND_POLL_INTERVAL
+        };
+        let key = self.terminal.poll_key(timeout)?;
+        let searched = key.is_none() && self.step_search();
+        let save_finished = self.poll_save(false) | self.poll_autosave() | self.poll_lint();
+        #[cfg(feature = "lsp")]
+        let save_finished = save_finished | self.poll_lsp();
 
-            // 文件操作
-            Command::Save => {
-                if let Err(e) = self.buffer.save() {
-                    self.message = Some(format!("Save failed: {}", e));
-                } else {
-                    self.message = Some("File saved".to_string());
+        #[cfg(feature = "syntax-highlighting")]
+        let syntax_loaded = self
+            .highlight_engine
+            .as_mut()
+            .is_some_and(|engine| engine.is_loading() && engine.poll_syntax_set());
+        #[cfg(feature = "syntax-highlighting")]
+        if syntax_loaded {
+            self.highlight_cache.clear();
+        }
+        #[cfg(feature = "syntax-highlighting")]
+        let highlighted = self.poll_highlight_worker();
+        #[cfg(not(feature = "syntax-highlighting"))]
+        let (syntax_loaded, highlighted) = (false, false);
+
+        // 拖曳選擇停在邊緣時以固定頻率捲動，不受滑鼠事件多寡影響
+        let mut scrolled = false;
+        if let Some((x, y)) = self.auto_scroll {
+            if self.last_auto_scroll.elapsed() >= AUTO_SCROLL_INTERVAL {
+                self.last_auto_scroll = std::time::Instant::now();
+                scrolled = self.core.auto_scroll(x, y);
+                if !scrolled {
+                    self.auto_scroll = None;
                 }
             }
+        }
 
-            Command::Quit => {
-                if self.buffer.is_modified() {
-                    if self.quit_times > 0 {
-                        // 第二次按 Ctrl+Q，強制退出
-                        self.should_quit = true;
-                    } else {
-                        // 第一次按 Ctrl+Q，顯示警告
-                        self.quit_times = 1;
-                        self.message = Some(
-                            "Unsaved changes! Press Ctrl+Q again to force quit, or Ctrl+W to save"
-                                .to_string(),
-                        );
-                    }
-                } else {
-                    self.should_quit = true;
-                }
+        if save_finished || syntax_loaded || highlighted || scrolled || searched {
+            self.needs_redraw = true;
+            #[cfg(debug_assertions)]
+            {
+                self.idle_frames = 0;
             }
+        }
+        Ok(key)
+    }
 
-            // 視窗調整
-            Command::Resize => {
-                self.view.update_size();
-            }
-
-            // 撤銷/重做
-            Command::Undo => {
-                if let Some(pos) = self.buffer.undo() {
-                    self.view.invalidate_cache();
-                    // 將光標移動到撤銷操作的位置
-                    let row = self.buffer.char_to_line(pos);
-                    let line_start = self.buffer.line_to_char(row);
-                    let col = pos - line_start;
-
-                    self.cursor.row = row;
-                    self.cursor.col = col;
-                    self.cursor.desired_visual_col = col;
-                    self.message = Some("Undo".to_string());
-                } else {
-                    self.message = Some("Nothing to undo".to_string());
+    /// 是否有需要輪詢完成狀態的背景工作
+    fn has_background_work(&self) -> bool {
+        // 語言伺服器只在修改尚未同步時輪詢，收到的訊息由讀取執行緒喚醒主迴圈處理
+        #[cfg(feature = "lsp")]
+        if self.lsp.is_some() && self.lsp_version != Some(self.core.buffer.version()) {
+            return true;
+        }
+        #[cfg(feature = "syntax-highlighting")]
+        if self.highlight_worker.is_busy()
+            || self
+                .highlight_engine
+                .as_ref()
+                .is_some_and(|engine| engine.is_loading())
+        {
+            return true;
+        }
+
+        self.pending_save.is_some()
+            || self.pending_lint.is_some()
+            || self.pending_recovery.is_some()
+            || self.autosave_pending()
+            || self.auto_scroll.is_some()
+            || self.search.is_searching()
+    }
+
+    /// 在背景執行緒存檔：緩衝區以快照交給執行緒，編輯不會被大檔案的寫入阻塞
+    fn start_save(&mut self) {
+        if self.prepare_save(None) {
+            self.write_in_background();
+        }
+    }
+
+    /// 存檔前依設定格式化並呼叫 `pre_save` 掛鉤；返回 false 表示掛鉤取消了存檔
+    ///
+    /// 格式化失敗不阻止存檔，錯誤在存檔完成後顯示
+    fn prepare_save(&mut self, target: Option<&Path>) -> bool {
+        self.save_warning = None;
+        if self.format_on_save {
+            if let Some(command) = self.formatter_command() {
+                if let Err(e) = self.format_range(&command, 0, self.core.buffer.len_chars()) {
+                    self.save_warning = Some(format!("Format failed: {}", e));
                 }
             }
+        }
+        if let Err(e) = self.run_hooks(target, |hook, ctx| hook.pre_save(ctx)) {
+            self.error = Some(format!("Save cancelled by {:#}", e));
+            return false;
+        }
+        true
+    }
+
+    /// 在背景執行緒寫入目前的內容（不呼叫 `pre_save` 掛鉤）
+    fn write_in_background(&mut self) {
+        let snapshot = match self.core.buffer.save_snapshot() {
+            Ok(snapshot) => snapshot,
+            Err(e) => {
+                self.error = Some(format!("Save failed: {}", e));
+                return;
+            }
+        };
 
-            Command::Redo => {
-                if let Some(pos) = self.buffer.redo() {
-                    self.view.invalidate_cache();
-                    // 將光標移動到重做操作的位置
-                    let row = self.buffer.char_to_line(pos);
-                    let line_start = self.buffer.line_to_char(row);
-                    let col = pos - line_start;
+        let (sender, receiver) = mpsc::channel();
+        let version = snapshot.version();
+        std::thread::spawn(move || {
+            let _ = sender.send(snapshot.write());
+        });
+
+        self.pending_save = Some(PendingSave {
+            version,
+            result: receiver,
+        });
+        self.message = Some("Saving...".to_string());
+    }
 
-                    self.cursor.row = row;
-                    self.cursor.col = col;
-                    self.cursor.desired_visual_col = col;
-                    self.message = Some("Redo".to_string());
-                } else {
-                    self.message = Some("Nothing to redo".to_string());
-                }
+    /// 檢查背景存檔是否完成，`wait` 為 true 時阻塞等待；返回是否有存檔剛完成
+    fn poll_save(&mut self, wait: bool) -> bool {
+        let Some(pending) = self.pending_save.as_ref() else {
+            return false;
+        };
+
+        let result = if wait {
+            pending.result.recv().ok()
+        } else {
+            match pending.result.try_recv() {
+                Ok(result) => Some(result),
+                Err(mpsc::TryRecvError::Empty) => return false,
+                Err(mpsc::TryRecvError::Disconnected) => None,
             }
+        };
 
-            // 搜索
-            Command::Find => {
-                // 獲取搜索查詢
-                if let Ok(Some(query)) = crate::dialog::prompt("Search:", self.terminal.size()) {
-                    if !query.is_empty() {
-                        self.search.set_query(query.clone());
-                        self.search.find_matches(&self.buffer);
-
-                        if self.search.match_count() > 0 {
-                            if let Some((row, col)) = self.search.next_match() {
-                                self.cursor.row = row;
-                                self.cursor.col = col;
-                                self.cursor.desired_visual_col = col;
-                                self.message = Some(format!(
-                                    "Found {} matches (F3: next, Shift+F3: prev)",
-                                    self.search.match_count()
-                                ));
-                            }
-                        } else {
-                            self.message = Some(format!("No matches found for '{}'", query));
-                        }
-                    }
+        let version = pending.version;
+        self.pending_save = None;
+        self.line_markers_version = None;
+        self.whitespace_version = None;
+        match result {
+            Some(Ok(())) => {
+                self.core.buffer.finish_save(version);
+                #[cfg(feature = "git")]
+                self.refresh_git();
+                self.message = Some("File saved".to_string());
+                self.error = self.save_warning.take();
+                self.write_undo_file();
+                self.saved = true;
+                self.remove_recovery();
+                if let Err(e) = self.run_hooks(None, |hook, ctx| hook.post_save(ctx)) {
+                    self.error = Some(format!("{:#}", e));
                 }
+                self.start_lint();
             }
+            Some(Err(e)) => self.error = Some(format!("Save failed: {}", e)),
+            None => self.error = Some("Save failed: writer thread exited".to_string()),
+        }
+        true
+    }
 
-            Command::FindNext => {
-                if self.search.match_count() > 0 {
-                    if let Some((row, col)) = self.search.next_match() {
-                        self.cursor.row = row;
-                        self.cursor.col = col;
-                        self.cursor.desired_visual_col = col;
-                        self.message = Some(format!(
-                            "Match {}/{}",
-                            self.search.current_index() + 1,
-                            self.search.match_count()
-                        ));
+    /// 匯集各功能要在畫面上標示的行內樣式範圍（只計算可見的行）
+    ///
+    /// 來源依優先順序：搜尋結果（游標所在的結果使用較醒目的顏色），
+    /// 以及檢查程式與語言伺服器的診斷（依嚴重程度著色並加上底線）
+    fn collect_line_spans(
+        &self,
+        start_row: usize,
+        end_row: usize,
+    ) -> std::collections::HashMap<usize, Vec<LineSpan>> {
+        let mut spans: std::collections::HashMap<usize, Vec<LineSpan>> =
+            std::collections::HashMap::new();
+
+        let query = self.search.query();
+        if self.show_search_matches && !query.is_empty() {
+            let match_style = self.core.view.theme.search_match;
+            let current_style = self.core.view.theme.search_current;
+            let query_chars = query.chars().count();
+
+            // 每次重新在可見行中尋找，編輯後標示位置仍然正確
+            for row in start_row..end_row.min(self.core.buffer.line_count()) {
+                let line = self.core.buffer.get_line_content(row);
+                let line = line.trim_end_matches(['\n', '\r']);
+                let line_start = self.core.buffer.line_to_char(row);
+                for (byte_idx, _) in line.match_indices(query) {
+                    let start = line[..byte_idx].chars().count();
+                    if !self
+                        .search
+                        .in_scope(line_start + start, line_start + start + query_chars)
+                    {
+                        continue;
                     }
-                } else {
-                    self.message = Some("No active search".to_string());
+                    let is_current = row == self.core.cursor.row && start == self.core.cursor.col;
+                    spans.entry(row).or_default().push(LineSpan {
+                        start,
+                        end: start + query_chars,
+                        style: if is_current {
+                            current_style
+                        } else {
+                            match_style
+                        },
+                    });
                 }
             }
+        }
 
-            Command::FindPrev => {
-                if self.search.match_count() > 0 {
-                    if let Some((row, col)) = self.search.prev_match() {
-                        self.cursor.row = row;
-                        self.cursor.col = col;
-                        self.cursor.desired_visual_col = col;
-                        self.message = Some(format!(
-                            "Match {}/{}",
-                            self.search.current_index() + 1,
-                            self.search.match_count()
-                        ));
-                    }
-                } else {
-                    self.message = Some("No active search".to_string());
-                }
+        for diagnostic in &self.diagnostics {
+            if !(start_row..end_row).contains(&diagnostic.row)
+                || diagnostic.row >= self.core.buffer.line_count()
+            {
+                continue;
             }
+            let line = self.core.buffer.get_line_content(diagnostic.row);
+            let (start, end) = diagnostic.char_range(&line);
+            spans.entry(diagnostic.row).or_default().push(LineSpan {
+                start,
+                end,
+                style: SpanStyle {
+                    foreground: Some(diagnostic.marker.color()),
+                    underline: true,
+                    ..SpanStyle::default()
+                },
+            });
+        }
+
+        spans
+    }
+
+    /// 重新讀取 git 分支、暫存狀態與 HEAD 版本（開檔與存檔後呼叫）
+    #[cfg(feature = "git")]
+    fn refresh_git(&mut self) {
+        if self.git.is_none() {
+            self.git = self
+                .core
+                .buffer
+                .file_path()
+                .and_then(crate::git::GitFile::detect);
+        }
+
+        let (status, head) = match &self.git {
+            Some(git) => (git.status(), git.head_contents()),
+            None => (None, None),
+        };
+        self.git_status = status;
+        self.git_head = head.map(|bytes| self.core.buffer.decode_text(&bytes, "HEAD"));
+        self.line_markers_version = None;
+        self.whitespace_version = None;
+    }
+
+    /// 在背景以檢查程式檢查剛存檔的檔案；有語言伺服器時改由伺服器提供診斷
+    fn start_lint(&mut self) {
+        #[cfg(feature = "lsp")]
+        if let Some(client) = self.lsp.as_mut() {
+            client.did_save();
+            return;
+        }
+        let Some(command) = self.linter_command() else {
+            self.set_diagnostics(Vec::new());
+            return;
+        };
+        let Some(path) = self.core.buffer.file_path().map(Path::to_path_buf) else {
+            return;
+        };
+        let (sender, receiver) = mpsc::channel();
+        std::thread::spawn(move || {
+            let result = capture_command(&command, None).map(|output| {
+                // shell 找不到命令時結束碼為 127
+                if output.status.code() == Some(127) {
+                    return None;
+                }
+                let text = format!(
+                    "{}{}",
+                    String::from_utf8_lossy(&output.stdout),
+                    String::from_utf8_lossy(&output.stderr)
+                );
+                Some(crate::lint::parse(&text, &path))
+            });
+            let _ = sender.send(result);
+        });
+        self.pending_lint = Some(receiver);
+    }
+
+    /// 檢查背景的檢查程式是否完成；返回是否有新的結果
+    fn poll_lint(&mut self) -> bool {
+        let Some(receiver) = &self.pending_lint else {
+            return false;
+        };
+        let result = match receiver.try_recv() {
+            Ok(result) => result,
+            Err(mpsc::TryRecvError::Empty) => return false,
+            Err(mpsc::TryRecvError::Disconnected) => Ok(None),
+        };
+        self.pending_lint = None;
+        match result {
+            Ok(diagnostics) => self.set_diagnostics(diagnostics.unwrap_or_default()),
+            Err(e) => self.error = Some(format!("Lint failed: {}", e)),
+        }
+        true
+    }
+
+    /// 依檔案類型啟動語言伺服器（先關閉目前的伺服器），返回伺服器名稱
+    #[cfg(feature = "lsp")]
+    fn start_lsp(&mut self) -> Result<Option<String>> {
+        self.lsp = None;
+        self.set_diagnostics(Vec::new());
+        if !self.lsp_enabled {
+            return Ok(None);
+        }
+        let Some(path) = self.core.buffer.file_path().map(Path::to_path_buf) else {
+            return Ok(None);
+        };
+        let extensions = self.language_extensions();
+        let server = extensions.iter().find_map(|ext| {
+            match self.lsp_programs.get(&ext.to_lowercase()) {
+                // 自訂的伺服器以副檔名作為 languageId
+                Some(command) => Some((ext.to_lowercase(), command.clone())),
+                None => crate::lsp::server_for([ext.as_str()])
+                    .map(|(language, command)| (language.to_string(), command.to_string())),
+            }
+        });
+        let Some((language_id, command)) = server.filter(|(_, c)| !c.trim().is_empty()) else {
+            return Ok(None);
+        };
+
+        let name = command.split_whitespace().next().unwrap_or_default();
+        let client = crate::lsp::LspClient::start(
+            shell_command(&command),
+            name,
+            &path,
+            &language_id,
+            self.core.buffer.text(),
+            Terminal::wake,
+        )
+        .context("LSP")?;
+        self.lsp = Some(client);
+        self.lsp_version = Some(self.core.buffer.version());
+        Ok(Some(name.to_string()))
+    }
+
+    /// 同步修改給語言伺服器並處理收到的訊息；返回是否需要重繪
+    #[cfg(feature = "lsp")]
+    fn poll_lsp(&mut self) -> bool {
+        let version = self.core.buffer.version();
+        let Some(client) = self.lsp.as_mut() else {
+            return false;
+        };
+        if self.lsp_version != Some(version) {
+            client.did_change(self.core.buffer.text());
+            self.lsp_version = Some(version);
+        }
+        let events = client.poll();
+        let changed = !events.is_empty();
+        for event in events {
+            self.handle_lsp_event(event);
+        }
+        changed
+    }
+
+    /// 處理語言伺服器的事件；hover 的結果直接返回
+    #[cfg(feature = "lsp")]
+    fn handle_lsp_event(&mut self, event: crate::lsp::LspEvent) -> Option<Option<String>> {
+        use crate::lsp::LspEvent;
+        match event {
+            LspEvent::Diagnostics(diagnostics) => self.set_diagnostics(diagnostics),
+            LspEvent::Hover(text) => return Some(text),
+            LspEvent::Exited => {
+                if let Some(client) = self.lsp.take() {
+                    self.error = Some(format!("LSP: {} exited", client.name()));
+                }
+                self.set_diagnostics(Vec::new());
+            }
+        }
+        None
+    }
+
+    /// `:hover`：向語言伺服器查詢光標位置的說明，單行顯示在狀態列，多行以全螢幕視窗顯示
+    #[cfg(feature = "lsp")]
+    fn show_hover(&mut self) -> Result<()> {
+        const HOVER_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(3);
+
+        let row = self.core.cursor.row;
+        let line = self.core.buffer.get_line_content(row);
+        let character = crate::lsp::utf16_offset(&line, self.core.cursor.col);
+        self.poll_lsp();
+        let Some(client) = self.lsp.as_mut() else {
+            bail!("No language server; turn one on with :set lsp");
+        };
+        client.hover(row, character);
+
+        // 等待回應，期間收到的診斷照常處理
+        let deadline = std::time::Instant::now() + HOVER_TIMEOUT;
+        let text = loop {
+            let Some(client) = self.lsp.as_mut() else {
+                return Ok(());
+            };
+            let mut hover = None;
+            for event in client.poll() {
+                if let Some(text) = self.handle_lsp_event(event) {
+                    hover = Some(text);
+                }
+            }
+            if let Some(text) = hover {
+                break text;
+            }
+            if std::time::Instant::now() >= deadline {
+                bail!("Hover: no response from language server");
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        };
+
+        let Some(text) = text else {
+            self.message = Some("Hover: nothing here".to_string());
+            return Ok(());
+        };
+        let lines: Vec<String> = text.lines().map(str::to_string).collect();
+        if lines.len() == 1 {
+            self.message = Some(text);
+        } else {
+            crate::dialog::pager("Hover", &lines, self.terminal.size(), |_| None)?;
+            self.core.view.invalidate_cache();
+        }
+        Ok(())
+    }
+
+    #[cfg(not(feature = "lsp"))]
+    fn show_hover(&mut self) -> Result<()> {
+        bail!("This build has no LSP support (build with --features lsp)")
+    }
+
+    fn set_diagnostics(&mut self, diagnostics: Vec<crate::lint::Diagnostic>) {
+        let mut markers = std::collections::HashMap::new();
+        for diagnostic in &diagnostics {
+            // 同一行有多個結果時標示最嚴重的
+            markers
+                .entry(diagnostic.row)
+                .and_modify(|marker: &mut crate::view::DiagnosticMarker| {
+                    *marker = (*marker).min(diagnostic.marker)
+                })
+                .or_insert(diagnostic.marker);
+        }
+        self.core.view.set_diagnostic_markers(markers);
+        self.diagnostics = diagnostics;
+    }
+
+    /// 目前檔案的檢查命令：`lintprg.EXT` 或依檔案類型選擇的預設命令
+    fn linter_command(&self) -> Option<String> {
+        if !self.lint_enabled {
+            return None;
+        }
+        let extensions = self.language_extensions();
+        let command = extensions.iter().find_map(|ext| {
+            match self.lint_programs.get(&ext.to_lowercase()) {
+                Some(command) => Some(command.as_str()),
+                None => crate::lint::linter_for([ext.as_str()]),
+            }
+        })?;
+        if command.trim().is_empty() {
+            return None;
+        }
+        Some(crate::format::expand(
+            command,
+            self.core.buffer.file_path(),
+            extensions.first().map(String::as_str),
+        ))
+    }
+
+    /// 背景存檔進行中時拒絕會寫入或重新載入同一檔案的操作
+    fn save_in_progress(&mut self) -> bool {
+        if self.pending_save.is_some() {
+            self.message = Some("Save in progress, please wait".to_string());
+            true
+        } else {
+            false
+        }
+    }
+
+    /// 重繪整個畫面（捲動、語法高亮與狀態列）
+    fn redraw(&mut self) -> Result<()> {
+        // 閒置檢查：每次輸入之後最多只能重繪一次
+        #[cfg(debug_assertions)]
+        {
+            self.idle_frames += 1;
+            debug_assert!(
+                self.idle_frames <= 1,
+                "redraw without new input ({} frames since last event)",
+                self.idle_frames
+            );
+        }
+
+        let debug_info = if self.debug_mode {
+            Some(self.get_debug_info())
+        } else {
+            None
+        };
+
+        let mut hints = Vec::new();
+        #[cfg(feature = "vi")]
+        hints.extend(self.modal.as_ref().and_then(|modal| modal.indicator()));
+        // 提交訊息：在狀態列顯示摘要行長度與訊息行數
+        if self.git_message {
+            let (summary_chars, lines) = crate::filetype::commit_message_stats(
+                (0..self.core.buffer.line_count())
+                    .map(|row| self.core.buffer.get_line_content(row)),
+                &self.git_comment,
+            );
+            hints.push(format!("Summary: {} chars, {} lines", summary_chars, lines));
+        }
+        #[cfg(feature = "git")]
+        if let Some(status) = &self.git_status {
+            hints.push(status.summary());
+        }
+        // 缺少最後的換行、混用換行符號與行尾空白（`:fix` 修正）
+        if self.whitespace_version != Some(self.core.buffer.version()) {
+            self.whitespace_hint = if self.core.buffer.line_count() <= MAX_LINE_MARKER_LINES {
+                crate::whitespace::check(&self.core.buffer.text()).indicator()
+            } else {
+                None
+            };
+            self.whitespace_version = Some(self.core.buffer.version());
+        }
+        hints.extend(self.whitespace_hint.clone());
+        if !self.diagnostics.is_empty() {
+            hints.push(format!("Lint: {}", self.diagnostics.len()));
+        }
+        // 搜尋進行中（結果有標示時）顯示目前是第幾個結果
+        if self.show_search_matches {
+            if self.core.buffer.line_count() <= MAX_LINE_MARKER_LINES {
+                self.search.refresh(&self.core.buffer);
+            }
+            if self.search.match_count() > 0 || self.search.is_searching() {
+                hints.push(self.search.counter());
+            }
+        }
+        self.core
+            .view
+            .set_status_hint((!hints.is_empty()).then(|| hints.join("  ")));
+
+        // 行號欄修改標記：只在緩衝區改變或存檔後重新比對
+        // 檔案在 git 中有 HEAD 版本時以其為基準，否則與磁碟內容比較
+        if self.core.view.show_line_numbers
+            && self.line_markers_version != Some(self.core.buffer.version())
+        {
+            #[cfg(feature = "git")]
+            let head = self.git_head.as_deref();
+            #[cfg(not(feature = "git"))]
+            let head: Option<&str> = None;
+
+            let markers = if self.core.buffer.line_count() <= MAX_LINE_MARKER_LINES {
+                let saved_text;
+                let baseline = match head {
+                    Some(head) => head,
+                    None => {
+                        saved_text = self.core.buffer.saved_text();
+                        &saved_text
+                    }
+                };
+                crate::diff::line_markers(baseline, &self.core.buffer.text())
+            } else {
+                std::collections::HashMap::new()
+            };
+            self.core.view.set_line_markers(markers);
+            self.line_markers_version = Some(self.core.buffer.version());
+        }
+
+        // 光標所在行有檢查結果時，沒有錯誤就在錯誤訊息列顯示第一則
+        let diagnostic_message = self
+            .diagnostics
+            .iter()
+            .find(|d| d.row == self.core.cursor.row)
+            .map(|d| match d.col {
+                Some(col) => format!("{}:{}: {}", d.row + 1, col, d.message),
+                None => format!("{}: {}", d.row + 1, d.message),
+            });
+        self.core
+            .view
+            .set_error_message(self.error.clone().or(diagnostic_message));
+        self.core.view.tab_line = (self.tab_line && self.buffers.len() > 1).then(|| {
+            let tabs = self
+                .buffers
+                .iter()
+                .map(|hidden| match hidden {
+                    Some(hidden) => (hidden.buffer.file_name(), hidden.buffer.is_modified()),
+                    None => (self.core.buffer.file_name(), self.core.buffer.is_modified()),
+                })
+                .collect();
+            TabLine::new(tabs, self.current_buffer)
+        });
+
+        // ⚠️ 重要：在計算高亮之前先更新 offset_row
+        // 避免跳頁後 highlighted_lines 使用舊的 offset_row
+        let has_debug_ruler = self.debug_mode;
+        self.core
+            .view
+            .scroll_if_needed(&self.core.cursor, &self.core.buffer, has_debug_ruler);
+
+        let line_spans = self.collect_line_spans(
+            self.core.view.offset_row,
+            self.core.view.offset_row + self.core.view.screen_rows,
+        );
+        self.core.view.set_line_spans(line_spans);
+
+        // 獲取語法高亮行
+        #[cfg(feature = "syntax-highlighting")]
+        let highlighted_lines = {
+            if self.highlight_enabled {
+                let start_row = self.core.view.offset_row;
+                let end_row = start_row + self.core.view.screen_rows;
+                self.get_highlighted_lines(start_row, end_row)
+            } else {
+                std::collections::HashMap::new()
+            }
+        };
+
+        // 選擇模式或有選擇範圍時切換游標樣式，只在樣式改變時輸出
+        let selecting = self.core.selection_mode || self.core.selection.is_some();
+        let cursor_style = if selecting {
+            self.selection_cursor_style.or(self.cursor_style)
+        } else {
+            self.cursor_style
+        };
+        if cursor_style != self.applied_cursor_style {
+            Terminal::set_cursor_style(cursor_style)?;
+            self.applied_cursor_style = cursor_style;
+        }
+
+        self.core.view.render(
+            &mut self.terminal,
+            &self.core.buffer,
+            &self.core.cursor,
+            self.core.selection.as_ref(),
+            if self.debug_mode {
+                debug_info.as_deref()
+            } else {
+                self.message.as_deref()
+            },
+            #[cfg(feature = "syntax-highlighting")]
+            Some(&highlighted_lines),
+        )?;
+
+        Ok(())
+    }
+
+    fn handle_command(&mut self, command: Command) -> Result<()> {
+        // Tab 接在單字後面且有候選項時改為補全，否則照常縮排
+        let command = match command {
+            Command::Indent if self.completion_candidates().is_some() => Command::Complete,
+            command => command,
+        };
+
+        // 較遠的跳轉記錄跳轉前的位置，供 Alt+Left 返回
+        let jump_from = matches!(
+            command,
+            Command::Find
+                | Command::FindNext
+                | Command::FindPrev
+                | Command::GoToLine
+                | Command::MoveToFileStart
+                | Command::MoveToFileEnd
+                | Command::JumpToMark(_)
+        )
+        .then_some((self.core.cursor.row, self.core.cursor.col));
+        self.handle_command_inner(command)?;
+        if let Some(from) = jump_from {
+            self.record_jump(from);
+        }
+        Ok(())
+    }
+
+    fn handle_command_inner(&mut self, command: Command) -> Result<()> {
+        match command {
+            Command::ClearMessage => {
+                self.core.selection = None;
+                self.core.selection_mode = false; // ESC 關閉選擇模式但保留選擇範圍
+                self.message = None;
+                self.error = None;
+                self.show_search_matches = false; // 搜尋結果標示也一併關閉，F3 仍可繼續搜尋
+            }
+
+            // 剪貼板操作
+            Command::Copy => {
+                let text = self.core.copy_text();
+                self.set_system_clipboard(text);
+                // 複製後關閉選擇模式並清除選擇範圍
+                self.core.selection_mode = false;
+                self.core.selection = None;
+            }
+
+            Command::Cut => {
+                let text = self.core.copy_text();
+                self.set_system_clipboard(text);
+                // 剪切後刪除內容
+                self.core.cut();
+            }
+
+            Command::Paste => {
+                let text = self.get_system_clipboard();
+                self.core.paste_text(&text);
+                self.core.selection_mode = false; // 貼上後關閉選擇模式
+            }
+
+            // 文件操作
+            Command::Save => {
+                if self.save_in_progress() {
+                    return Ok(());
+                }
+                // 沒有檔案路徑（例如 stdin 輸入）時改為詢問檔名
+                if !self.core.buffer.has_file_path() {
+                    return self.handle_command(Command::SaveAs);
+                }
+                if !self.confirm_lossless_encoding() {
+                    return Ok(());
+                }
+                self.start_save();
+            }
+
+            Command::SaveAs => {
+                if self.save_in_progress() {
+                    return Ok(());
+                }
+                if let Ok(Some(path_str)) = crate::dialog::prompt("Save as:", self.terminal.size())
+                {
+                    let path_str = path_str.trim();
+                    if path_str.is_empty() {
+                        self.message = Some("Save cancelled".to_string());
+                    } else {
+                        self.save_as(Path::new(path_str));
+                    }
+                }
+            }
+
+            Command::QuitBuffer => {
+                if self.resolve_unsaved("Close")? {
+                    self.close_current_buffer();
+                }
+            }
+            Command::QuitAll => self.quit_all()?,
+
+            // 視窗調整
+            Command::Resize => {
+                self.core.view.update_size();
+            }
+
+            Command::Mouse => {
+                if let Some(event) = Terminal::take_mouse_event() {
+                    self.handle_mouse(event)?;
+                }
+            }
+
+            // 搜索
+            Command::Find => {
+                // 有選擇範圍時先選擇只在選擇範圍內或在整個檔案中搜尋
+                let scope = match self.selection_range().filter(|(start, end)| start < end) {
+                    Some(range) => match crate::dialog::choose(
+                        "Search in:",
+                        &[('s', "Selection"), ('f', "Whole file")],
+                        self.terminal.size(),
+                    )? {
+                        Some('s') => Some(range),
+                        Some(_) => None,
+                        None => return Ok(()),
+                    },
+                    None => None,
+                };
+                let title = if scope.is_some() {
+                    "Search in selection:"
+                } else {
+                    "Search:"
+                };
+
+                // 獲取搜索查詢
+                if let Ok(Some(query)) = crate::dialog::prompt(title, self.terminal.size()) {
+                    if !query.is_empty() {
+                        self.search.set_query(query);
+                        self.search.set_scope(scope);
+                        self.search.start(&self.core.buffer);
+                        self.search.step(&self.core.buffer);
+                        self.show_search_matches = true;
+                        self.jump_to_match(true);
+                        if self.search_jump.is_none() && self.search.current_index().is_some() {
+                            self.message = Some(format!(
+                                "{} (F3: next, Shift+F3: prev)",
+                                self.search.counter()
+                            ));
+                        }
+                    }
+                }
+            }
+
+            Command::FindNext => {
+                self.show_search_matches = true;
+                self.search.refresh(&self.core.buffer);
+                self.jump_to_match(true);
+            }
+
+            Command::FindPrev => {
+                self.show_search_matches = true;
+                self.search.refresh(&self.core.buffer);
+                self.jump_to_match(false);
+            }
+
+            Command::ShowMatches => {
+                let from = (self.core.cursor.row, self.core.cursor.col);
+                self.show_matches(None)?;
+                self.record_jump(from);
+            }
+
+            // 視圖控制
+            Command::ToggleLineNumbers => {
+                self.core.view.toggle_line_numbers();
+            }
+            Command::CycleLineNumberMode => {
+                let mode = self.core.view.line_number_mode.next();
+                self.core.view.line_number_mode = mode;
+                self.core.view.show_line_numbers = true;
+                self.message = Some(format!("Line numbers: {}", mode.name()));
+            }
+            Command::ToggleInvisibles => {
+                let show = !self.core.view.show_invisibles;
+                self.core.view.set_show_invisibles(show);
+                self.message = Some(format!("Invisibles: {}", if show { "On" } else { "Off" }));
+            }
+
+            // 跳轉到行
+            Command::GoToLine => {
+                if let Ok(Some(line_str)) =
+                    crate::dialog::prompt("Go to line:", self.terminal.size())
+                {
+                    if let Ok(line_num) = line_str.trim().parse::<usize>() {
+                        if self.go_to_line(line_num) {
+                            self.message = Some(format!("Jumped to line {}", line_num));
+                        } else {
+                            self.error = Some(format!("Invalid line number: {}", line_num));
+                        }
+                    } else {
+                        self.error = Some("Please enter a valid number".to_string());
+                    }
+                }
+            }
+
+            // 編碼切換
+            Command::ChangeEncoding => {
+                if self.save_in_progress() {
+                    return Ok(());
+                }
+                if let Ok(Some(encoding_str)) = crate::dialog::prompt_with_completion(
+                    "Change encoding to (Tab to complete):",
+                    self.terminal.size(),
+                    ENCODING_LABELS,
+                ) {
+                    if let Some(encoding) = Self::parse_encoding(encoding_str.trim()) {
+                        // 檢查是否有檔案路徑（區分已存在檔案和新建檔案）
+                        if self.core.buffer.has_file_path() {
+                            // 已存在的檔案：需要重新載入，有未保存的修改時先確認
+                            if self.confirm_discard("Reload")? {
+                                self.reload_with_encoding(encoding);
+                            }
+                        } else {
+                            // 新建檔案：只設定編碼，不重新載入
+                            self.core.buffer.change_encoding(encoding);
+                            self.message = Some(format!(
+                                "Encoding set to {} (will be used on save)",
+                                encoding.name()
+                            ));
+                        }
+                    } else {
+                        self.error = Some(format!("Unsupported encoding: {}", encoding_str));
+                    }
+                }
+            }
+
+            // 與磁碟檔案比較
+            Command::ShowDiff => match self.diff_against_disk() {
+                Ok(diff) => {
+                    if diff.is_empty() {
+                        self.message = Some("No changes compared to disk".to_string());
+                    } else {
+                        crate::dialog::pager(
+                            "Diff against disk",
+                            &diff,
+                            self.terminal.size(),
+                            diff_line_color,
+                        )?;
+                        self.core.view.invalidate_cache();
+                    }
+                }
+                Err(e) => {
+                    self.error = Some(format!("Diff failed: {}", e));
+                }
+            },
+            Command::ShowStats => self.show_stats(),
+            Command::InspectChar => self.inspect_char(),
+
+            Command::ShowHelp => {
+                let lines: Vec<String> = crate::input::KEY_HELP
+                    .iter()
+                    .map(|line| line.to_string())
+                    .collect();
+                crate::dialog::pager("Help", &lines, self.terminal.size(), |_| None)?;
+                self.core.view.invalidate_cache();
+            }
+
+            // 切換 BOM
+            Command::ToggleBom => {
+                let has_bom = !self.core.buffer.has_bom();
+                self.core.buffer.set_bom(has_bom);
+                self.message = Some(format!(
+                    "BOM: {} (applies on save)",
+                    if has_bom { "On" } else { "Off" }
+                ));
+            }
+
+            // 命令列
+            Command::ShellOutput => {
+                if let Ok(Some(command)) = crate::dialog::prompt("!", self.terminal.size()) {
+                    if !command.trim().is_empty() {
+                        if let Err(e) = self.show_command_output(command.trim()) {
+                            self.error = Some(e.to_string());
+                        }
+                    }
+                }
+            }
+
+            Command::OpenCommandLine => {
+                if let Ok(Some(input)) = crate::dialog::prompt(":", self.terminal.size()) {
+                    if !input.trim().is_empty() {
+                        if let Err(e) = self.run_ex_command(&input) {
+                            self.error = Some(e.to_string());
+                        }
+                    }
+                }
+            }
+
+            // 切換語法高亮
+            #[cfg(feature = "syntax-highlighting")]
+            Command::ToggleSyntaxHighlight => {
+                self.highlight_enabled = !self.highlight_enabled;
+                self.message = Some(format!(
+                    "Syntax Highlight: {}",
+                    if self.highlight_enabled {
+                        "Enabled"
+                    } else {
+                        "Disabled"
+                    }
+                ));
+            }
+
+            #[cfg(feature = "syntax-highlighting")]
+            Command::PickSyntaxTheme => self.pick_syntax_theme()?,
+
+            Command::Complete => self.complete_word()?,
+
+            Command::Mark => self.mark_prefix()?,
+            Command::Count => self.count_prefix()?,
+            // 重複執行；撤銷與重做以外的修改合併為一個撤銷步驟
+            Command::WithCount(count, command) if command.is_repeatable() => {
+                let batch = !matches!(*command, Command::Undo | Command::Redo);
+                if batch {
+                    self.core.buffer.begin_batch();
+                }
+                let result =
+                    (0..count).try_for_each(|_| self.handle_command_inner((*command).clone()));
+                if batch {
+                    self.core.buffer.end_batch();
+                }
+                result?;
+            }
+            Command::WithCount(_, command) => self.handle_command_inner(*command)?,
+            Command::JumpToMark(digit) => self.jump_to_mark(&digit.to_string())?,
+            Command::GoToReference => self.go_to_reference()?,
+            Command::FindFile => self.find_file()?,
+            Command::PrevBuffer => self.cycle_buffer(false),
+            Command::NextBuffer => self.cycle_buffer(true),
+            Command::InsertTimestamp => self.insert_template(Template::Time)?,
+            Command::JumpBack => {
+                let current = (self.core.cursor.row, self.core.cursor.col);
+                match self.jumps.back(current) {
+                    Some((row, col)) => self.go_to_position(row, col),
+                    None => self.message = Some("No earlier jump".to_string()),
+                }
+            }
+            Command::JumpForward => match self.jumps.forward() {
+                Some((row, col)) => self.go_to_position(row, col),
+                None => self.message = Some("No later jump".to_string()),
+            },
+
+            // 編輯、移動與選擇命令交給編輯核心處理
+            command => {
+                #[cfg(feature = "syntax-highlighting")]
+                let before = (
+                    self.core.buffer.version(),
+                    self.core.buffer.line_count(),
+                    self.core.cursor.row,
+                );
+
+                self.core.apply(command);
+                if let Some(message) = self.core.take_message() {
+                    self.message = Some(message);
+                }
+
+                // 行數改變時快取的行號已錯位，清除整個語法高亮快取；否則只需重新高亮光標所在行
+                #[cfg(feature = "syntax-highlighting")]
+                {
+                    let (version, line_count, row) = before;
+                    if self.core.buffer.version() != version {
+                        if self.core.buffer.line_count() != line_count {
+                            self.highlight_cache.clear();
+                        } else {
+                            self.invalidate_highlight_cache(row);
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// 另存新檔（同步寫入），成功後鎖與檔案類型跟著新路徑走
+    fn save_as(&mut self, new_path: &Path) {
+        if !self.confirm_lossless_encoding() {
+            return;
+        }
+        if !self.confirm_foreign_lock(new_path) || !self.prepare_save(Some(new_path)) {
+            return;
+        }
+        match self.core.save_as(new_path) {
+            Ok(ext_changed) => {
+                // 鎖跟著新路徑走（其他實例持有新路徑的鎖時不接手）
+                self.file_lock = FileLock::acquire(new_path).ok();
+                // 副檔名改變時重新檢測檔案類型（語法高亮等）
+                if ext_changed {
+                    self.detect_file_type();
+                }
+                self.line_markers_version = None;
+                self.whitespace_version = None;
+                #[cfg(feature = "git")]
+                {
+                    self.git = None;
+                    self.refresh_git();
+                }
+                self.message = Some(format!("Saved as {}", new_path.display()));
+                self.error = self.save_warning.take();
+                self.write_undo_file();
+                self.saved = true;
+                self.remove_recovery();
+                if let Err(e) = self.run_hooks(None, |hook, ctx| hook.post_save(ctx)) {
+                    self.error = Some(format!("{:#}", e));
+                }
+                self.start_lint();
+            }
+            Err(e) => {
+                self.error = Some(format!("Save failed: {}", e));
+            }
+        }
+    }
+
+    /// 呼叫已註冊的掛鉤；`target` 為另存新檔的路徑，None 時使用目前的檔案路徑
+    ///
+    /// 掛鉤修改緩衝區後重新整理排版與高亮，並把光標留在緩衝區範圍內
+    fn run_hooks(
+        &mut self,
+        target: Option<&Path>,
+        event: impl FnMut(&mut dyn Hook, &mut HookContext) -> Result<()>,
+    ) -> Result<()> {
+        if self.hooks.is_empty() {
+            return Ok(());
+        }
+        let path = target
+            .map(Path::to_path_buf)
+            .or_else(|| self.core.buffer.file_path().map(Path::to_path_buf));
+        let version = self.core.buffer.version();
+        let mut ctx = HookContext::new(&mut self.core, path.as_deref());
+        let result = self.hooks.dispatch(&mut ctx, event);
+        if let Some(message) = ctx.take_message() {
+            self.message = Some(message);
+        }
+
+        if self.core.buffer.version() != version {
+            self.core.view.invalidate_cache();
+            #[cfg(feature = "syntax-highlighting")]
+            self.highlight_cache.clear();
+            self.core.selection = None;
+            self.clamp_cursor_to_buffer();
+            self.needs_redraw = true;
+        }
+        result
+    }
+
+    /// 按鍵命令執行後呼叫 `on_insert` 與 `on_command` 掛鉤
+    fn command_hooks(&mut self, command: &Command) {
+        let mut result = Ok(());
+        if let Command::Insert(ch) = *command {
+            result = self.run_hooks(None, |hook, ctx| hook.on_insert(ctx, ch));
+        }
+        if result.is_ok() {
+            result = self.run_hooks(None, |hook, ctx| hook.on_command(ctx, command));
+        }
+        if let Err(e) = result {
+            self.error = Some(format!("{:#}", e));
+        }
+    }
+
+    /// 執行命令列（`:`）輸入的指令
+    fn run_ex_command(&mut self, input: &str) -> Result<()> {
+        let command = crate::ex::parse(input)?;
+        let jump_from = matches!(
+            command,
+            ExCommand::GoToLine(_)
+                | ExCommand::JumpToMark(_)
+                | ExCommand::Marks
+                | ExCommand::Matches(_)
+        )
+        .then_some((self.core.cursor.row, self.core.cursor.col));
+        self.run_ex(command)?;
+        if let Some(from) = jump_from {
+            self.record_jump(from);
+        }
+        Ok(())
+    }
+
+    fn run_ex(&mut self, command: ExCommand) -> Result<()> {
+        match command {
+            ExCommand::Write(None) => self.handle_command(Command::Save)?,
+            ExCommand::Write(Some(path)) => {
+                if !self.save_in_progress() {
+                    self.save_as(&path);
+                }
+            }
+            ExCommand::WriteQuit => {
+                self.handle_command(Command::Save)?;
+                // 等待存檔完成，失敗或取消時留在編輯器中
+                self.poll_save(true);
+                if !self.core.buffer.is_modified() {
+                    self.close_current_buffer();
+                }
+            }
+            ExCommand::Quit { force } => {
+                self.poll_save(true);
+                if !force && self.core.buffer.is_modified() && !self.output_to_stdout {
+                    bail!("Unsaved changes (use :q! to discard them)");
+                }
+                self.close_current_buffer();
+            }
+            ExCommand::QuitAll { force } => {
+                self.poll_save(true);
+                let modified =
+                    self.modified_hidden_buffers() + usize::from(self.core.buffer.is_modified());
+                if !force && modified > 0 && !self.output_to_stdout {
+                    bail!(
+                        "{} buffer{} unsaved changes (use :qa! to discard them, :wa to save them)",
+                        modified,
+                        if modified == 1 { " has" } else { "s have" }
+                    );
+                }
+                self.should_quit = true;
+            }
+            ExCommand::WriteAll => self.save_all()?,
+            ExCommand::WriteQuitAll => {
+                self.save_all()?;
+                self.should_quit = true;
+            }
+            ExCommand::Edit { path, force } => self.open_file(&path, force)?,
+            ExCommand::Set { option, value } => {
+                self.message = Some(self.set_option(&option, value.as_deref())?);
+            }
+            ExCommand::GoToLine(line) => {
+                // 與 vi 相同，超出範圍時停在第一行或最後一行
+                let last_line = self.core.buffer.line_count().max(1);
+                self.go_to_line(line.clamp(1, last_line));
+            }
+            ExCommand::Substitute {
+                pattern,
+                replacement,
+                global,
+            } => self.substitute(&pattern, &replacement, global)?,
+            ExCommand::Shell(command) => self.run_shell_command(&command)?,
+            ExCommand::ReadShell(command) => self.insert_command_output(&command)?,
+            ExCommand::Filter(command) => self.filter_through_command(&command)?,
+            ExCommand::Format => self.format_buffer()?,
+            ExCommand::Hover => self.show_hover()?,
+            ExCommand::CheckHealth => {
+                let report = self.health_report();
+                crate::dialog::pager(
+                    "Health check",
+                    &report.lines(),
+                    self.terminal.size(),
+                    crate::health::line_color,
+                )?;
+                self.core.view.invalidate_cache();
+                self.message = Some(format!("Health check: {}", report.summary()));
+            }
+            ExCommand::HexView => {
+                let path = self
+                    .core
+                    .buffer
+                    .file_path()
+                    .filter(|path| path.exists())
+                    .context("No file on disk to show")?
+                    .to_path_buf();
+                crate::hexview::show(&path, self.terminal.size())?;
+                self.core.view.invalidate_cache();
+            }
+            ExCommand::Analyze => self.show_analysis()?,
+            ExCommand::Count => self.show_stats(),
+            ExCommand::InspectChar => self.inspect_char(),
+            ExCommand::Suspend => self.suspend()?,
+            ExCommand::Fix(fix) => self.fix_whitespace(fix)?,
+            ExCommand::Mark(name) => self.set_mark(&name),
+            ExCommand::JumpToMark(name) => self.jump_to_mark(&name)?,
+            ExCommand::Marks => self.show_marks()?,
+            ExCommand::Matches(query) => self.show_matches(query)?,
+            ExCommand::Grep(query) => self.grep(query)?,
+            ExCommand::Buffers => self.list_buffers()?,
+            ExCommand::Buffer(number) => {
+                if number == 0 || number > self.buffers.len() {
+                    bail!("No buffer {} ({} open)", number, self.buffers.len());
+                }
+                self.switch_buffer(number - 1);
+            }
+            ExCommand::NextBuffer => self.cycle_buffer(true),
+            ExCommand::PrevBuffer => self.cycle_buffer(false),
+            ExCommand::Insert(template) => self.insert_template(template)?,
+            ExCommand::Align { delimiter, all } => {
+                let changed = self.core.align(&delimiter, all);
+                if changed == 0 {
+                    bail!("Nothing to align on '{}'", delimiter);
+                }
+                #[cfg(feature = "syntax-highlighting")]
+                self.highlight_cache.clear();
+                self.message = Some(format!(
+                    "Aligned {} line{} on '{}'",
+                    changed,
+                    if changed == 1 { "" } else { "s" },
+                    delimiter
+                ));
+            }
+            ExCommand::DeleteMark(name) => {
+                if name == "!" {
+                    let names: Vec<String> = self
+                        .core
+                        .buffer
+                        .marks()
+                        .map(|(n, _)| n.to_string())
+                        .collect();
+                    for name in &names {
+                        self.core.buffer.delete_mark(name);
+                    }
+                    self.message = Some(format!("Deleted {} marks", names.len()));
+                } else if self.core.buffer.delete_mark(&name) {
+                    self.message = Some(format!("Deleted mark {}", name));
+                } else {
+                    bail!("Mark not set: {}", name);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// 選單開啟時重繪編輯區以預覽選取的項目；這些重繪由選單中的按鍵觸發，不計入閒置檢查
+    fn redraw_preview(&mut self) -> Result<()> {
+        #[cfg(debug_assertions)]
+        {
+            self.idle_frames = 0;
+        }
+        self.redraw()?;
+        #[cfg(debug_assertions)]
+        {
+            self.idle_frames = 0;
+        }
+        Ok(())
+    }
+
+    /// `:count` / Alt+S：在訊息列顯示整個檔案（與選擇範圍）的行數、單字數、字符數與存檔後的位元組數
+    fn show_stats(&mut self) {
+        let buffer = &self.core.buffer;
+        let file = Counts::of(buffer.chunks(0, buffer.len_chars()));
+        let bytes = buffer.encoded_file_len();
+        // 位元組數依存檔編碼計算，編碼顯示在狀態列上
+        self.message = Some(match self.selection_range().filter(|(s, e)| s < e) {
+            Some((start, end)) => {
+                let selection = Counts::of(buffer.chunks(start, end));
+                format!(
+                    "Selected: {}/{} lines, {}/{} words, {}/{} chars, {}/{} bytes",
+                    selection.lines,
+                    file.lines,
+                    selection.words,
+                    file.words,
+                    selection.chars,
+                    file.chars,
+                    buffer.encoded_len(start, end),
+                    bytes
+                )
+            }
+            None => format!(
+                "{} lines, {} words, {} chars, {} bytes",
+                file.lines, file.words, file.chars, bytes
+            ),
+        });
+    }
+
+    /// `:char` / Alt+U：在訊息列顯示光標處字元的 Unicode 資訊
+    ///
+    /// 之後緊接的零寬字元（組合符號、變體選擇符、ZWJ）會一起列出，方便看出字元是由哪些碼位組成的
+    fn inspect_char(&mut self) {
+        let line = self.core.buffer.get_line_content(self.core.cursor.row);
+        let mut chars = line.chars().skip(self.core.cursor.col);
+        self.message = Some(match chars.next() {
+            None => "End of file".to_string(),
+            Some(ch) => {
+                let joined: Vec<char> = chars
+                    .take_while(|&c| !c.is_control() && crate::utils::char_width(c) == 0)
+                    .collect();
+                let text =
+                    crate::charinfo::describe(ch, &joined, crate::utils::is_ambiguous_wide());
+                if matches!(ch, '\r' | '\n') {
+                    format!("End of line: {}", text)
+                } else {
+                    text
+                }
+            }
+        });
+    }
+
+    /// `:analyze`：列出重複行與常見單字，選取項目時跳到對應的行；ESC 回到原本的位置
+    fn show_analysis(&mut self) -> Result<()> {
+        let buffer = &self.core.buffer;
+        let analysis = crate::analysis::analyze((0..buffer.line_count()).map(|row| {
+            let line = buffer.get_line_content(row);
+            line.trim_end_matches(['\n', '\r']).to_string()
+        }));
+        let entries = analysis.entries();
+        let labels: Vec<String> = entries.iter().map(|entry| entry.label.clone()).collect();
+        let original = (self.core.cursor.row, self.core.cursor.col);
+        let size = self.terminal.size();
+
+        let choice = crate::dialog::pick("Analysis", &labels, 0, size, &mut |index| {
+            let Some(row) = entries[index].row else {
+                return Ok(());
+            };
+            self.go_to_line(row + 1);
+            self.redraw_preview()
+        })?;
+
+        match choice.and_then(|index| entries[index].row) {
+            Some(row) => {
+                self.go_to_line(row + 1);
+            }
+            None => {
+                let (row, col) = original;
+                self.core
+                    .cursor
+                    .set_position(&self.core.buffer, &self.core.view, row, col);
+            }
+        }
+        self.core.view.invalidate_cache();
+        self.message = Some(analysis.summary());
+        Ok(())
+    }
+
+    /// 光標前的單字開始的欄位與補全候選項；有選擇範圍、光標前不是單字或沒有候選項時返回 None
+    fn completion_candidates(&mut self) -> Option<(usize, Vec<String>)> {
+        if self.core.selection.is_some() {
+            return None;
+        }
+        let content = self.core.buffer.get_line_content(self.core.cursor.row);
+        let line = content.trim_end_matches(['\n', '\r']);
+        let col = self.core.cursor.col;
+        let (start, prefix) = self.word_index.prefix_before(line, col)?;
+        // 光標可能在單字中間，整個單字本身不算候選項
+        let rest = line.chars().skip(col);
+        let current: String = prefix
+            .chars()
+            .chain(rest.take_while(|&ch| self.word_index.is_word_char(ch)))
+            .collect();
+        self.word_index.refresh(&self.core.buffer);
+        let candidates = self.word_index.candidates(&prefix, &current);
+        (!candidates.is_empty()).then_some((start, candidates))
+    }
+
+    /// Ctrl+N / Tab：以緩衝區中出現過的單字補全光標前的單字
+    ///
+    /// 只有一個候選項時直接插入；選單開啟時繼續輸入單字字元或 Backspace 會重新篩選，
+    /// 其他按鍵關閉選單並照常處理
+    fn complete_word(&mut self) -> Result<()> {
+        let mut narrowing = false;
+        loop {
+            let Some((start, candidates)) = self.completion_candidates() else {
+                if !narrowing {
+                    self.message = Some("No completions".to_string());
+                }
+                return Ok(());
+            };
+            if candidates.len() == 1 && !narrowing {
+                self.insert_completion(start, &candidates[0]);
+                return Ok(());
+            }
+
+            self.redraw_preview()?;
+            let anchor = self.core.view.cursor_screen_position();
+            let key = match crate::dialog::popup(&candidates, anchor, self.terminal.size())? {
+                PopupResult::Selected(index) => {
+                    self.insert_completion(start, &candidates[index]);
+                    return Ok(());
+                }
+                PopupResult::Cancelled => return Ok(()),
+                PopupResult::Key(key) => key,
+            };
+            let Some(command) = handle_key_event(key, self.core.selection_mode) else {
+                return Ok(());
+            };
+            narrowing = match command {
+                Command::Insert(ch) => self.word_index.is_word_char(ch),
+                Command::Backspace => true,
+                _ => false,
+            };
+            self.handle_command(command)?;
+            if !narrowing {
+                return Ok(());
+            }
+        }
+    }
+
+    /// 以補全的單字取代光標前的部分單字（一個復原步驟）
+    fn insert_completion(&mut self, start: usize, word: &str) {
+        let row = self.core.cursor.row;
+        let line_start = self.core.buffer.line_to_char(row);
+        self.core
+            .buffer
+            .replace_range(line_start + start, line_start + self.core.cursor.col, word);
+        self.core
+            .buffer
+            .describe_last_edit(format!("complete \"{}\"", word));
+        self.core.view.invalidate_cache();
+        let col = start + word.chars().count();
+        self.core
+            .cursor
+            .set_position(&self.core.buffer, &self.core.view, row, col);
+        #[cfg(feature = "syntax-highlighting")]
+        self.invalidate_highlight_cache(row);
+    }
+
+    /// 滑鼠：左鍵點擊移動光標（Shift+點擊延伸選擇範圍）、拖曳選擇、滾輪捲動畫面
+    ///
+    /// 拖曳到文字區的第一行或最後一行（以及下方的狀態列）時開始自動捲動，直到放開或指標移回文字區
+    fn handle_mouse(&mut self, event: MouseEvent) -> Result<()> {
+        let (x, y) = (event.column, event.row);
+        match event.kind {
+            MouseEventKind::Down(MouseButton::Left) => {
+                let tab = self
+                    .core
+                    .view
+                    .tab_line
+                    .as_ref()
+                    .filter(|_| y == 0)
+                    .and_then(|tab_line| tab_line.tab_at(x as usize, self.core.view.screen_cols));
+                match tab {
+                    Some(index) => self.switch_buffer(index),
+                    None => self
+                        .core
+                        .click(x, y, event.modifiers.contains(KeyModifiers::SHIFT)),
+                }
+            }
+            MouseEventKind::Drag(MouseButton::Left) => {
+                let distance = self.core.drag_to(x, y);
+                if distance == 0 {
+                    self.auto_scroll = None;
+                } else if self.auto_scroll.is_none() {
+                    // 剛到邊緣時立即捲動第一步
+                    self.auto_scroll = Some((x, y));
+                    self.last_auto_scroll = std::time::Instant::now();
+                    if !self.core.auto_scroll(x, y) {
+                        self.auto_scroll = None;
+                    }
+                } else {
+                    self.auto_scroll = Some((x, y));
+                }
+            }
+            MouseEventKind::Up(MouseButton::Left) => {
+                self.core.release();
+                self.auto_scroll = None;
+            }
+            MouseEventKind::ScrollUp | MouseEventKind::ScrollDown => {
+                let command = if event.kind == MouseEventKind::ScrollUp {
+                    Command::ScrollLineUp
+                } else {
+                    Command::ScrollLineDown
+                };
+                for _ in 0..MOUSE_WHEEL_LINES {
+                    self.handle_command_inner(command.clone())?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Ctrl+B：接著按數字在光標處設定書籤，再按一次 Ctrl+B（或 b）列出書籤
+    fn mark_prefix(&mut self) -> Result<()> {
+        self.message = Some("Mark: press 0-9 to set, Ctrl+B to list, Esc to cancel".to_string());
+        self.redraw_preview()?;
+        self.message = None;
+        let key = self.terminal.read_key()?;
+        match key.code {
+            KeyCode::Char(c @ '0'..='9') => self.set_mark(&c.to_string()),
+            KeyCode::Char('b') => self.show_marks()?,
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Ctrl+U：接著輸入的數字為次數，再以該次數執行下一個按鍵的命令；沒有輸入數字時照常執行一次
+    fn count_prefix(&mut self) -> Result<()> {
+        let mut count = 0;
+        loop {
+            self.message = Some(match count {
+                0 => "Count: type a number, then a command (Esc to cancel)".to_string(),
+                count => format!("Count: {}", count),
+            });
+            self.redraw_preview()?;
+            self.message = None;
+            let key = self.terminal.read_key()?;
+            match key.code {
+                KeyCode::Char(c @ '0'..='9') if key.modifiers == KeyModifiers::NONE => {
+                    count = (count * 10 + c as usize - '0' as usize).min(MAX_COUNT);
+                }
+                KeyCode::Esc => return Ok(()),
+                _ => {
+                    let Some(command) = handle_key_event(key, self.core.selection_mode) else {
+                        return Ok(());
+                    };
+                    return match count {
+                        0 => self.handle_command(command),
+                        count => self.handle_command(Command::WithCount(count, Box::new(command))),
+                    };
+                }
+            }
+        }
+    }
+
+    /// 在光標處設定書籤；書籤隨前方文字的插入與刪除移動
+    fn set_mark(&mut self, name: &str) {
+        let pos = self.core.cursor.char_position(&self.core.buffer);
+        self.core.buffer.set_mark(name, pos);
+        self.message = Some(format!(
+            "Mark {} set at line {}",
+            name,
+            self.core.cursor.row + 1
+        ));
+    }
+
+    /// 跳到書籤的位置
+    fn jump_to_mark(&mut self, name: &str) -> Result<()> {
+        let pos = self
+            .core
+            .buffer
+            .mark(name)
+            .with_context(|| format!("Mark not set: {}", name))?;
+        self.go_to_char(pos);
+        self.message = Some(format!("Mark {}: line {}", name, self.core.cursor.row + 1));
+        Ok(())
+    }
+
+    /// 在光標處插入日期時間、檔案路徑、使用者或主機名稱（取代選擇範圍）
+    fn insert_template(&mut self, template: Template) -> Result<()> {
+        let text = match template {
+            Template::Date => LocalTime::now().format(&self.date_format),
+            Template::Time => LocalTime::now().format(&self.time_format),
+            Template::Path | Template::FileName => {
+                let path = self.core.buffer.file_path().context("No file name")?;
+                if template == Template::FileName {
+                    path.file_name()
+                        .unwrap_or(path.as_os_str())
+                        .to_string_lossy()
+                        .into_owned()
+                } else {
+                    std::path::absolute(path)
+                        .unwrap_or_else(|_| path.to_path_buf())
+                        .display()
+                        .to_string()
+                }
+            }
+            Template::User => crate::template::user_name().context("Unknown user name")?,
+            Template::Host => crate::template::host_name().context("Unknown host name")?,
+            Template::UserHost => format!(
+                "{}@{}",
+                crate::template::user_name().context("Unknown user name")?,
+                crate::template::host_name().context("Unknown host name")?
+            ),
+        };
+        if text.is_empty() {
+            bail!("Nothing to insert (empty format)");
+        }
+        self.core.paste_text(&text);
+        self.core.selection_mode = false;
+        Ok(())
+    }
+
+    /// `path` 是否為目前編輯的檔案
+    fn is_current_file(&self, path: &Path) -> bool {
+        self.core.buffer.file_path().is_some_and(|current| {
+            current == path
+                || matches!(
+                    (current.canonicalize(), path.canonicalize()),
+                    (Ok(a), Ok(b)) if a == b
+                )
+        })
+    }
+
+    /// `:grep TEXT`：在工作目錄的所有檔案中尋找並列出結果，Enter 開啟檔案並跳到該處；
+    /// 沒有參數時重新列出上一次的結果
+    fn grep(&mut self, query: Option<String>) -> Result<()> {
+        if let Some(query) = query {
+            let root = std::env::current_dir().context("Cannot read the current directory")?;
+            let matches = crate::grep::search(&root, &query)?;
+            if matches.is_empty() {
+                self.message = Some(format!("No matches found for '{}'", query));
+                return Ok(());
+            }
+            self.grep_results = Some(GrepResults {
+                query,
+                matches,
+                selected: 0,
+            });
+        }
+        let Some(results) = &self.grep_results else {
+            bail!("No previous :grep");
+        };
+
+        let labels: Vec<String> = results
+            .matches
+            .iter()
+            .map(|m| format!("{}:{}: {}", m.path, m.line, m.text.trim()))
+            .collect();
+        let files = results
+            .matches
+            .iter()
+            .map(|m| &m.path)
+            .collect::<std::collections::HashSet<_>>()
+            .len();
+        let title = format!(
+            "'{}': {} match{} in {} file{}",
+            results.query,
+            labels.len(),
+            if labels.len() == 1 { "" } else { "es" },
+            files,
+            if files == 1 { "" } else { "s" }
+        );
+        let selected = results.selected;
+        let choice =
+            crate::dialog::pick(&title, &labels, selected, self.terminal.size(), &mut |_| {
+                Ok(())
+            })?;
+        let Some(index) = choice else {
+            return Ok(());
+        };
+        let Some(results) = &mut self.grep_results else {
+            return Ok(());
+        };
+        results.selected = index;
+        let target = results.matches[index].clone();
+        self.open_location(Path::new(&target.path), target.line - 1, target.col - 1)
+    }
+
+    /// 開啟檔案（已在編輯時不重新開啟）並跳到 (行, 列)
+    fn open_location(&mut self, path: &Path, row: usize, col: usize) -> Result<()> {
+        let from = (self.core.cursor.row, self.core.cursor.col);
+        let same_file = self.is_current_file(path);
+        if !same_file {
+            self.open_file(path, false)?;
+        }
+        self.go_to_position(row, col);
+        if same_file {
+            self.record_jump(from);
+        }
+        self.message = Some(format!("{}:{}:{}", path.display(), row + 1, col + 1));
+        Ok(())
+    }
+
+    /// Ctrl+O：模糊搜尋工作目錄下的檔案（略過 .gitignore 忽略的項目）並開啟
+    fn find_file(&mut self) -> Result<()> {
+        let root = std::env::current_dir().context("Cannot read the current directory")?;
+        let files = crate::finder::index(&root);
+        if files.is_empty() {
+            self.message = Some(format!("No files under {}", root.display()));
+            return Ok(());
+        }
+        let choice = crate::finder::pick(&files, self.terminal.size())?;
+        self.core.view.invalidate_cache();
+        let Some(index) = choice else {
+            return Ok(());
+        };
+        let path = PathBuf::from(&files[index]);
+        if self.is_current_file(&path) {
+            self.message = Some(format!("Already editing {}", path.display()));
+            return Ok(());
+        }
+        self.open_file(&path, false)
+    }
+
+    /// 開啟光標處的檔案參照（`file.rs:42:7`、`file.cpp(42,7)` 或檔案路徑）並跳到該位置
+    ///
+    /// 相對路徑先以目前目錄解析，找不到時再以目前檔案所在的目錄解析
+    fn go_to_reference(&mut self) -> Result<()> {
+        let line = self.core.buffer.get_line_content(self.core.cursor.row);
+        let reference = crate::fileref::reference_at(&line, self.core.cursor.col)
+            .context("No file reference under the cursor")?;
+
+        let base_dir = self
+            .core
+            .buffer
+            .file_path()
+            .and_then(|path| path.parent())
+            .map(Path::to_path_buf);
+        let path = [Some(PathBuf::new()), base_dir]
+            .into_iter()
+            .flatten()
+            .map(|dir| dir.join(&reference.path))
+            .find(|path| path.is_file())
+            .with_context(|| format!("File not found: {}", reference.path))?;
+
+        let same_file = self.is_current_file(&path);
+        let from = (self.core.cursor.row, self.core.cursor.col);
+        if !same_file {
+            self.open_file(&path, false)?;
+        }
+
+        if let Some(line) = reference.line {
+            let row = (line - 1).min(self.core.buffer.line_count().saturating_sub(1));
+            self.go_to_position(row, reference.col.map_or(0, |col| col - 1));
+            let location = match reference.col {
+                Some(col) => format!("{}:{}:{}", path.display(), row + 1, col),
+                None => format!("{}:{}", path.display(), row + 1),
+            };
+            if same_file {
+                self.message = Some(format!("Jumped to {}", location));
+            } else {
+                self.message = Some(format!("Opened {}", location));
+            }
+        } else if same_file {
+            self.message = Some(format!("Already editing {}", path.display()));
+        }
+        if same_file {
+            self.record_jump(from);
+        }
+        Ok(())
+    }
+
+    /// 光標已從 `from` 跳到別處時記錄到跳轉歷史
+    fn record_jump(&mut self, from: (usize, usize)) {
+        if from.0 != self.core.cursor.row || from.1 != self.core.cursor.col {
+            self.jumps.record(from);
+        }
+    }
+
+    /// 回到跳轉歷史中的位置；之後的修改可能讓它超出範圍，此時停在最近的有效位置
+    fn go_to_position(&mut self, row: usize, col: usize) {
+        let row = row.min(self.core.buffer.line_count().saturating_sub(1));
+        let col = col.min(self.core.line_len(row));
+        self.core.selection = None;
+        self.core
+            .cursor
+            .set_position(&self.core.buffer, &self.core.view, row, col);
+    }
+
+    /// 把光標移到字元位置（超出範圍時移到檔尾）
+    fn go_to_char(&mut self, pos: usize) {
+        let buffer = &self.core.buffer;
+        let pos = pos.min(buffer.len_chars());
+        let row = buffer.char_to_line(pos);
+        let col = pos - buffer.line_to_char(row);
+        self.core.selection = None;
+        self.core
+            .cursor
+            .set_position(&self.core.buffer, &self.core.view, row, col);
+    }
+
+    /// `:marks`（或 Ctrl+B 兩次）：列出書籤，選取時預覽位置，Enter 跳過去，ESC 回到原處
+    fn show_marks(&mut self) -> Result<()> {
+        let marks: Vec<(String, usize)> = self
+            .core
+            .buffer
+            .marks()
+            .map(|(name, pos)| (name.to_string(), pos))
+            .collect();
+        if marks.is_empty() {
+            self.message = Some("No marks (Ctrl+B then 0-9 to set one)".to_string());
+            return Ok(());
+        }
+        let labels: Vec<String> = marks
+            .iter()
+            .map(|(name, pos)| {
+                let row = self.core.buffer.char_to_line(*pos);
+                let line = self.core.buffer.get_line_content(row);
+                format!(
+                    "{:>3}  line {:<6} {}",
+                    name,
+                    row + 1,
+                    line.trim_end_matches(['\n', '\r']).trim()
+                )
+            })
+            .collect();
+        let original = self.core.cursor.char_position(&self.core.buffer);
+        let size = self.terminal.size();
+
+        let choice = crate::dialog::pick("Marks", &labels, 0, size, &mut |index| {
+            self.go_to_char(marks[index].1);
+            self.redraw_preview()
+        })?;
+        match choice {
+            Some(index) => self.jump_to_mark(&marks[index].0)?,
+            None => self.go_to_char(original),
+        }
+        Ok(())
+    }
+
+    /// `:fix`：修正缺少的最後換行、混用的換行符號與行尾空白，整批修改只算一個復原步驟
+    fn fix_whitespace(&mut self, fix: Option<Fix>) -> Result<()> {
+        let text = self.core.buffer.text();
+        let issues = crate::whitespace::check(&text);
+        let fixes: Vec<Fix> = match fix {
+            Some(fix) => vec![fix],
+            None => vec![Fix::FinalNewline, Fix::LineEndings, Fix::TrailingWhitespace],
+        }
+        .into_iter()
+        .filter(|&fix| issues.has(fix))
+        .collect();
+        if fixes.is_empty() {
+            self.message = Some("Nothing to fix".to_string());
+            return Ok(());
+        }
+
+        let fixed = crate::whitespace::apply(&text, &fixes);
+        let (start, end, replacement) = crate::whitespace::changed_range(&text, &fixed);
+        self.core.buffer.replace_range(start, end, replacement);
+        self.core.buffer.describe_last_edit("fix whitespace");
+        self.core.view.invalidate_cache();
+        self.core.selection = None;
+        self.core.selection_mode = false;
+
+        // 光標留在原來的行，行尾空白刪除後不超過行尾
+        let row = self.core.cursor.row;
+        let col = self.core.cursor.col.min(
+            self.core
+                .buffer
+                .get_line_content(row)
+                .trim_end_matches(['\n', '\r'])
+                .chars()
+                .count(),
+        );
+        self.core
+            .cursor
+            .set_position(&self.core.buffer, &self.core.view, row, col);
+
+        let done: Vec<String> = fixes
+            .iter()
+            .map(|fix| match fix {
+                Fix::FinalNewline => "final newline".to_string(),
+                Fix::LineEndings => format!(
+                    "line endings to {}",
+                    if issues.line_ending() == "\r\n" {
+                        "CRLF"
+                    } else {
+                        "LF"
+                    }
+                ),
+                Fix::TrailingWhitespace => format!(
+                    "trailing whitespace ({} lines)",
+                    issues.trailing_whitespace_lines
+                ),
+            })
+            .collect();
+        self.message = Some(format!("Fixed: {}", done.join(", ")));
+        Ok(())
+    }
+
+    /// 收集 `:checkhealth` 的各項檢查結果
+    fn health_report(&self) -> crate::health::Report {
+        use crate::health::{self, Check};
+
+        let mut report = health::Report::new();
+
+        // 選項：設定檔中無法套用的行，以及目前選項之間的衝突
+        let mut options = Vec::new();
+        match &self.config_file {
+            None => options.push(Check::info("No configuration file is read")),
+            Some(file) if !file.path.exists() => options.push(Check::info(format!(
+                "No configuration file at {}",
+                file.path.display()
+            ))),
+            Some(file) => {
+                options.push(Check::ok(format!(
+                    "Config: {} ({} settings applied)",
+                    file.path.display(),
+                    file.applied
+                )));
+                for error in &file.errors {
+                    options.push(Check::warn(
+                        format!("Config {}", error),
+                        "Fix or remove the line; it takes the same KEY[=VALUE] as :set",
+                    ));
+                }
+            }
+        }
+        let width = self.core.view.screen_cols;
+        if let Some(&column) = self.core.view.ruler_columns.iter().find(|&&c| c > width) {
+            options.push(Check::warn(
+                format!(
+                    "Ruler column {} is beyond the terminal width ({})",
+                    column, width
+                ),
+                "Change the marked columns with :set rulercolumns=...",
+            ));
+        }
+        if let Some(&column) = self.core.view.color_columns.iter().find(|&&c| c > width) {
+            options.push(Check::warn(
+                format!(
+                    "Color column {} is beyond the terminal width ({})",
+                    column, width
+                ),
+                "Widen the window or change the guides with :set colorcolumn=...",
+            ));
+        }
+        let saves_on_its_own = self.autosave != AutosavePolicy::Off || self.autowrite;
+        match self.core.buffer.file_path() {
+            None if saves_on_its_own => options.push(Check::warn(
+                "autosave/autowrite have no effect on a buffer without a file name",
+                "Give the buffer a name with :w FILE",
+            )),
+            Some(path) if path.metadata().is_ok_and(|m| m.permissions().readonly()) => options
+                .push(Check::warn(
+                    format!("{} is read-only", path.display()),
+                    "Save a copy elsewhere with :w FILE",
+                )),
+            _ => {}
+        }
+        report.section("Options", options);
+
+        report.section("Clipboard", health::clipboard_checks(&self.clipboard));
+
+        #[allow(unused_mut)]
+        let mut commands = vec![health::program_check(
+            SHELL.0,
+            ":! commands",
+            "Shell commands (:!) cannot run until the shell is on PATH",
+        )];
+        #[cfg(feature = "git")]
+        commands.push(health::program_check(
+            "git",
+            "branch and changed-line markers",
+            "Install git to see the branch and changed lines",
+        ));
+        report.section("Commands", commands);
+
+        report.section("Syntax highlighting", self.syntax_checks());
+        let plugins = self.hooks.names();
+        report.section(
+            "Plugins",
+            vec![if plugins.is_empty() {
+                Check::info("No plugins compiled in (see the plugin-* cargo features)")
+            } else {
+                Check::ok(format!("Enabled: {}", plugins.join(", ")))
+            }],
+        );
+        report.section("Terminal", health::terminal_checks(self.terminal.size()));
+        report
+    }
+
+    /// 切換語法高亮主題，已高亮的行以新主題重新計算
+    #[cfg(feature = "syntax-highlighting")]
+    fn set_syntax_theme(&mut self, name: &str) -> Result<()> {
+        match self.highlight_engine.as_mut() {
+            Some(engine) => engine.set_theme(name)?,
+            None => {
+                // 啟動時的主題無法載入，改用新主題建立引擎
+                self.highlight_engine = Some(HighlightEngine::new(
+                    Some(name),
+                    self.highlight_config.true_color,
+                )?);
+                self.detect_file_type();
+            }
+        }
+        self.highlight_config.theme = name.to_string();
+        self.highlight_cache.clear();
+        Ok(())
+    }
+
+    /// Ctrl+T：選擇語法高亮主題，移動時即時預覽；確定後寫入設定檔，取消時恢復原本的主題
+    #[cfg(feature = "syntax-highlighting")]
+    fn pick_syntax_theme(&mut self) -> Result<()> {
+        let mut themes = HighlightEngine::available_themes();
+        themes.sort();
+        let original = self.highlight_config.theme.clone();
+        let current = themes.iter().position(|t| *t == original).unwrap_or(0);
+        let size = self.terminal.size();
+
+        let choice = crate::dialog::pick("Syntax theme", &themes, current, size, &mut |index| {
+            self.set_syntax_theme(&themes[index])?;
+            self.redraw_preview()
+        })?;
+
+        let Some(index) = choice else {
+            self.set_syntax_theme(&original)?;
+            self.message = Some(format!("Theme: {} (unchanged)", original));
+            return Ok(());
+        };
+        let theme = &themes[index];
+        self.set_syntax_theme(theme)?;
+        self.message = Some(match self.config_file.as_ref().map(|file| &file.path) {
+            Some(path) => {
+                crate::config::save_setting(path, "theme", theme)?;
+                format!("Theme: {} (saved to {})", theme, path.display())
+            }
+            None => format!("Theme: {} (not saved: no configuration file)", theme),
+        });
+        Ok(())
+    }
+
+    /// 語法高亮：主題、語法集與目前檔案的語法
+    #[cfg(feature = "syntax-highlighting")]
+    fn syntax_checks(&self) -> Vec<crate::health::Check> {
+        use crate::health::Check;
+
+        if !self.highlight_config.enabled {
+            return vec![Check::info("Syntax highlighting is disabled")];
+        }
+        let Some(engine) = &self.highlight_engine else {
+            return vec![Check::error(
+                format!(
+                    "Theme '{}' could not be loaded",
+                    self.highlight_config.theme
+                ),
+                "Pick a built-in theme with --theme (e.g. base16-ocean.dark)",
+            )];
+        };
+
+        let mut checks = vec![Check::ok(format!("Theme: {}", engine.theme_name()))];
+        if engine.is_loading() {
+            checks.push(Check::info(
+                "Syntax definitions are still loading in the background",
+            ));
+        } else {
+            checks.push(Check::ok(format!(
+                "{} syntax definitions loaded",
+                HighlightEngine::available_syntaxes().len()
+            )));
+            checks.push(match engine.syntax_name() {
+                Some(name) => Check::ok(format!("Syntax for this file: {}", name)),
+                None => Check::info("No syntax matches this file; it is shown as plain text"),
+            });
+        }
+        let assets = crate::highlight::user_assets();
+        if !assets.themes.is_empty() {
+            checks.push(Check::ok(format!(
+                "Custom themes: {}",
+                assets.themes.join(", ")
+            )));
+        }
+        if !assets.syntaxes.is_empty() {
+            checks.push(Check::ok(format!(
+                "Custom syntaxes: {}",
+                assets.syntaxes.join(", ")
+            )));
+        }
+        for error in assets.errors {
+            checks.push(Check::warn(
+                error,
+                "Check the file: .tmTheme files are plist XML, .sublime-syntax files are YAML",
+            ));
+        }
+        if !self.highlight_enabled {
+            checks.push(Check::info("Highlighting is turned off (:set syntax)"));
+        }
+        checks.push(if self.highlight_config.true_color {
+            Check::ok("24-bit color")
+        } else {
+            Check::info("24-bit color not detected, using 256 colors (set COLORTERM=truecolor if supported)")
+        });
+        checks
+    }
+
+    #[cfg(not(feature = "syntax-highlighting"))]
+    fn syntax_checks(&self) -> Vec<crate::health::Check> {
+        vec![crate::health::Check::info(
+            "This build has no syntax highlighting",
+        )]
+    }
+
+    /// 暫停到 shell（`:suspend` 或 SIGTSTP），`fg` 返回後完整重繪
+    fn suspend(&mut self) -> Result<()> {
+        Terminal::suspend()?;
+
+        // 暫停期間終端大小、檔案、儲存庫或游標樣式都可能改變
+        self.core.view.update_size();
+        self.core.view.invalidate_cache();
+        self.applied_cursor_style = None;
+        self.line_markers_version = None;
+        #[cfg(feature = "git")]
+        self.refresh_git();
+        self.needs_redraw = true;
+        Ok(())
+    }
+
+    /// 執行外部命令（`:!CMD`）：暫時離開全螢幕，命令結束後按任意鍵返回
+    ///
+    /// 開啟 autowrite 時先存檔，讓建置或執行命令看到最新的內容
+    fn run_shell_command(&mut self, command: &str) -> Result<()> {
+        if self.output_to_stdout {
+            bail!("Shell commands are not available in filter mode");
+        }
+        if self.autowrite && self.core.buffer.is_modified() && self.core.buffer.has_file_path() {
+            self.poll_save(true);
+            if !self.confirm_lossless_encoding() {
+                return Ok(());
+            }
+            self.start_save();
+            self.poll_save(true);
+            if self.core.buffer.is_modified() {
+                bail!("Save failed, command not run: {}", command);
+            }
+        }
+
+        Terminal::exit_raw_mode()?;
+        let status = run_in_foreground(command);
+        let paused = Terminal::pause("\r\n[Press any key to continue]");
+        Terminal::enter_raw_mode()?;
+        Terminal::clear_screen()?;
+        paused?;
+
+        // 命令可能修改了檔案、儲存庫或游標樣式
+        self.core.view.invalidate_cache();
+        self.applied_cursor_style = None;
+        self.line_markers_version = None;
+        self.whitespace_version = None;
+        #[cfg(feature = "git")]
+        self.refresh_git();
+
+        match status {
+            Ok(status) if status.success() => {
+                self.message = Some(format!("Command finished: {}", command));
+                Ok(())
+            }
+            Ok(status) => bail!("Command failed ({}): {}", status, command),
+            Err(e) => bail!("Cannot run {}: {}", command, e),
+        }
+    }
+
+    /// Alt+!：執行外部命令並以可捲動的視窗顯示標準輸出與標準錯誤
+    fn show_command_output(&mut self, command: &str) -> Result<()> {
+        let output = capture_command(command, None)?;
+        let mut lines: Vec<String> = String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .chain(String::from_utf8_lossy(&output.stderr).lines())
+            .map(str::to_string)
+            .collect();
+        if lines.is_empty() {
+            lines.push("(no output)".to_string());
+        }
+        let title = format!("!{} ({})", command, output.status);
+        crate::dialog::pager(&title, &lines, self.terminal.size(), |_| None)?;
+        self.core.view.invalidate_cache();
+        Ok(())
+    }
+
+    /// `:r !CMD`：在光標處插入外部命令的標準輸出（與貼上相同，取代選擇範圍）
+    fn insert_command_output(&mut self, command: &str) -> Result<()> {
+        let output = capture_command(command, None)?;
+        let text = self.command_output_text(command, &output)?;
+        if text.is_empty() {
+            self.message = Some(format!("No output: {}", command));
+            return Ok(());
+        }
+        self.core.paste_text(&text);
+        self.core
+            .buffer
+            .describe_last_edit(format!("insert output of !{}", command));
+        self.message = Some(format!(
+            "{} from !{}",
+            crate::buffer::describe("Inserted", &text),
+            command
+        ));
+        Ok(())
+    }
+
+    /// `:|CMD`：把選擇範圍（沒有選擇時為整個檔案）送到外部命令的標準輸入，以輸出取代；
+    /// 命令失敗時不修改緩衝區
+    fn filter_through_command(&mut self, command: &str) -> Result<()> {
+        let (start, end) = self
+            .selection_range()
+            .unwrap_or((0, self.core.buffer.len_chars()));
+        let input: String = self
+            .core
+            .buffer
+            .text()
+            .chars()
+            .skip(start)
+            .take(end - start)
+            .collect();
+        let output = capture_command(command, Some(&input))?;
+        let text = self.command_output_text(command, &output)?;
+
+        self.core.buffer.replace_range(start, end, &text);
+        self.core
+            .buffer
+            .describe_last_edit(format!("filter through !{}", command));
+        self.core.view.invalidate_cache();
+        self.core.selection = None;
+        self.core.selection_mode = false;
+        self.clamp_cursor_to_buffer();
+        self.message = Some(format!(
+            "{} through !{}",
+            crate::buffer::describe_lines("Filtered", input.lines().count().max(1)),
+            command
+        ));
+        Ok(())
+    }
+
+    /// 成功時的標準輸出，換行改為檔案使用的換行；失敗時以標準錯誤的第一行作為錯誤訊息
+    fn command_output_text(&self, command: &str, output: &std::process::Output) -> Result<String> {
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            match stderr.lines().find(|line| !line.trim().is_empty()) {
+                Some(line) => bail!("!{} failed ({}): {}", command, output.status, line.trim()),
+                None => bail!("!{} failed ({})", command, output.status),
+            }
+        }
+        let text = String::from_utf8_lossy(&output.stdout).replace("\r\n", "\n");
+        let eol = crate::whitespace::check(&self.core.buffer.text()).line_ending();
+        Ok(if eol == "\r\n" {
+            text.replace('\n', "\r\n")
+        } else {
+            text
+        })
+    }
+
+    /// `:format`：以格式化程式整理選擇範圍（沒有選擇時為整個檔案），整批修改只算一個復原步驟
+    fn format_buffer(&mut self) -> Result<()> {
+        let Some(command) = self.formatter_command() else {
+            bail!("No formatter for this file type; set one with :set formatprg=CMD");
+        };
+        let (start, end) = self
+            .selection_range()
+            .unwrap_or((0, self.core.buffer.len_chars()));
+        let program = command.split_whitespace().next().unwrap_or_default();
+        self.message = Some(if self.format_range(&command, start, end)? {
+            format!("Formatted with {}", program)
+        } else {
+            format!("Already formatted ({})", program)
+        });
+        Ok(())
+    }
+
+    /// 以格式化命令取代 start..end 的文字；返回內容是否改變
+    fn format_range(&mut self, command: &str, start: usize, end: usize) -> Result<bool> {
+        let input: String = self
+            .core
+            .buffer
+            .text()
+            .chars()
+            .skip(start)
+            .take(end - start)
+            .collect();
+        let output = capture_command(command, Some(&input))?;
+        let text = self.command_output_text(command, &output)?;
+        if text == input {
+            return Ok(false);
+        }
+
+        // 只取代實際改變的部分，未改變的行不受影響
+        let (from, to, replacement) = crate::whitespace::changed_range(&input, &text);
+        self.core
+            .buffer
+            .replace_range(start + from, start + to, replacement);
+        let program = command.split_whitespace().next().unwrap_or_default();
+        self.core
+            .buffer
+            .describe_last_edit(format!("format with {}", program));
+        self.core.view.invalidate_cache();
+        #[cfg(feature = "syntax-highlighting")]
+        self.highlight_cache.clear();
+        self.core.selection = None;
+        self.core.selection_mode = false;
+        self.clamp_cursor_to_buffer();
+        Ok(true)
+    }
+
+    /// 目前檔案使用的格式化命令：`formatprg`，或依檔案類型選擇的預設命令
+    fn formatter_command(&self) -> Option<String> {
+        let extensions = self.language_extensions();
+        let command = match &self.format_program {
+            Some(command) => command.as_str(),
+            None => crate::format::formatter_for(extensions.iter().map(String::as_str))?,
+        };
+        Some(crate::format::expand(
+            command,
+            self.core.buffer.file_path(),
+            extensions.first().map(String::as_str),
+        ))
+    }
+
+    /// 目前語言對應的副檔名：手動指定的語言，或檔名與內容推斷的結果
+    fn language_extensions(&self) -> Vec<String> {
+        if let Some(name) = &self.filetype {
+            #[cfg(feature = "syntax-highlighting")]
+            let syntax = self
+                .highlight_engine
+                .as_ref()
+                .map(|engine| engine.syntax_extensions())
+                .unwrap_or_default();
+            #[cfg(not(feature = "syntax-highlighting"))]
+            let syntax: Vec<&str> = Vec::new();
+            let lowercase = name.to_lowercase();
+            return syntax
+                .into_iter()
+                .chain([lowercase.as_str()])
+                .map(str::to_string)
+                .collect();
+        }
+
+        let path = self.core.buffer.file_path();
+        match path.and_then(|p| p.extension()).and_then(|e| e.to_str()) {
+            Some(ext) => vec![ext.to_string()],
+            None if path.is_some_and(crate::filetype::is_git_file) => Vec::new(),
+            None => {
+                let head: String = (0..crate::filetype::SNIFF_LINES
+                    .min(self.core.buffer.line_count()))
+                    .map(|row| self.core.buffer.get_line_content(row))
+                    .collect();
+                crate::filetype::detect_extension_from_content(&head)
+                    .map(str::to_string)
+                    .into_iter()
+                    .collect()
+            }
+        }
+    }
+
+    /// 是否有尚未自動存檔的修改
+    fn autosave_pending(&self) -> bool {
+        let buffer = &self.core.buffer;
+        match self.autosave {
+            AutosavePolicy::Off => false,
+            AutosavePolicy::File if self.output_to_stdout => false,
+            AutosavePolicy::File | AutosavePolicy::Recovery => {
+                buffer.is_modified()
+                    && buffer.has_file_path()
+                    && self.autosaved_version != Some(buffer.version())
+            }
+        }
+    }
+
+    /// 修改後閒置超過 [`AUTOSAVE_DELAY`] 時依設定自動存檔；返回是否需要重繪
+    fn poll_autosave(&mut self) -> bool {
+        let mut changed = false;
+        if self
+            .pending_recovery
+            .as_ref()
+            .is_some_and(|handle| handle.is_finished())
+        {
+            if let Some(Err(e)) = self.pending_recovery.take().and_then(|h| h.join().ok()) {
+                self.error = Some(format!("Recovery save failed: {}", e));
+                changed = true;
+            }
+        }
+
+        if !self.autosave_pending() {
+            return changed;
+        }
+
+        // 版本改變表示還在編輯，重新計算閒置時間
+        let version = self.core.buffer.version();
+        match self.autosave_change {
+            Some((seen, since)) if seen == version => {
+                if since.elapsed() < AUTOSAVE_DELAY {
+                    return changed;
+                }
+            }
+            _ => {
+                self.autosave_change = Some((version, Instant::now()));
+                return changed;
+            }
+        }
+
+        let Some(path) = self.core.buffer.file_path().map(Path::to_path_buf) else {
+            return changed;
+        };
+        match self.autosave {
+            AutosavePolicy::Off => return changed,
+            AutosavePolicy::File => {
+                if self.pending_save.is_some() {
+                    return changed;
+                }
+                // 無法以存檔編碼表示的字元需要使用者確認，不自動寫入原檔
+                if !self.core.buffer.find_unencodable_chars(1).is_empty() {
+                    self.error = Some(
+                        "Autosave skipped: text cannot be encoded in the save encoding".to_string(),
+                    );
+                } else {
+                    // 自動存檔發生在輸入途中，不讓 pre_save 掛鉤修改內容
+                    self.write_in_background();
+                }
+            }
+            AutosavePolicy::Recovery => {
+                if self.pending_recovery.is_some() {
+                    return changed;
+                }
+                // 不覆蓋之前異常結束留下的復原檔（開啟時已提示）
+                if self.recovery_written.as_ref() != Some(&path)
+                    && crate::autosave::existing_recovery(&path).is_some()
+                {
+                    self.autosaved_version = Some(version);
+                    return changed;
+                }
+                let text = self.core.buffer.text();
+                self.recovery_written = Some(path.clone());
+                self.pending_recovery = Some(std::thread::spawn(move || {
+                    crate::autosave::write_recovery(&path, &text)
+                }));
+            }
+        }
+        self.autosaved_version = Some(version);
+        true
+    }
+
+    /// 刪除本實例寫入的復原檔（等待寫入中的復原檔完成後再刪除）
+    fn remove_recovery(&mut self) {
+        if let Some(handle) = self.pending_recovery.take() {
+            let _ = handle.join();
+        }
+        if let Some(path) = self.recovery_written.take() {
+            crate::autosave::remove_recovery(&path);
+        }
+    }
+
+    /// 存檔後寫入撤銷檔（`:set undofile`）；存檔期間又有修改時等下次存檔
+    fn write_undo_file(&mut self) {
+        if !self.undo_file || self.core.buffer.is_modified() {
+            return;
+        }
+        let Some(path) = self.core.buffer.file_path() else {
+            return;
+        };
+        let rope = self.core.buffer.rope_snapshot();
+        if let Err(e) = crate::undofile::write(path, &rope, &self.core.buffer.history_text()) {
+            self.error = Some(format!("{:#}", e));
+        }
+    }
+
+    /// 讀回剛開啟的檔案的撤銷檔（`:set undofile`）；已經有撤銷歷史或修改時不讀取
+    fn read_undo_file(&mut self) {
+        let buffer = &self.core.buffer;
+        if !self.undo_file || buffer.is_modified() || buffer.can_undo() || buffer.can_redo() {
+            return;
+        }
+        let Some(path) = buffer.file_path() else {
+            return;
+        };
+        if let Some(history) = crate::undofile::read(path, &buffer.rope_snapshot()) {
+            self.core.buffer.restore_history(&history);
+        }
+    }
+
+    /// 開啟檔案時提示之前異常結束留下的復原檔
+    fn check_recovery(&mut self) {
+        let recovery = self
+            .core
+            .buffer
+            .file_path()
+            .and_then(crate::autosave::existing_recovery);
+        if let Some(recovery) = recovery {
+            self.error = Some(format!(
+                "Recovery file found: {} (compare with :e, delete it when done)",
+                recovery.display()
+            ));
+        }
+    }
+
+    /// 以指定編碼重新載入檔案（會清除修改與撤銷歷史，呼叫前先以 `confirm_discard` 確認）
+    fn reload_with_encoding(&mut self, encoding: &'static encoding_rs::Encoding) {
+        match self.core.buffer.reload_with_encoding(encoding) {
+            Ok(_) => {
+                // 重新載入成功，重置游標
+                self.core.cursor = Cursor::new();
+                self.core.selection = None;
+                self.core.selection_mode = false;
+                self.core.view.invalidate_cache();
+                self.line_markers_version = None;
+                self.whitespace_version = None;
+                #[cfg(feature = "git")]
+                self.refresh_git();
+                self.message = Some(format!(
+                    "Encoding changed to {} (file reloaded)",
+                    encoding.name()
+                ));
+            }
+            Err(e) => {
+                self.message = Some(format!("Failed to reload file: {}", e));
+            }
+        }
+    }
+
+    /// 磁碟上的檔案與緩衝區內容的 unified diff
+    fn diff_against_disk(&self) -> Result<Vec<String>> {
+        let disk_text = self.core.buffer.read_disk_text()?;
+        let name = self.core.buffer.file_name();
+        Ok(crate::diff::unified_diff(
+            disk_text.as_deref().unwrap_or(""),
+            &self.core.buffer.text(),
+            &format!("{} (disk)", name),
+            &format!("{} (buffer)", name),
+        ))
+    }
+
+    /// 捨棄未存檔修改前的確認；沒有修改時直接返回 true
+    ///
+    /// 使用者可以先檢視會失去的內容（磁碟版本與緩衝區的差異），再決定是否捨棄
+    fn confirm_discard(&mut self, action: &str) -> Result<bool> {
+        if !self.core.buffer.is_modified() {
+            return Ok(true);
+        }
+        let message = format!("{}: unsaved changes will be lost.", action);
+        loop {
+            self.redraw_preview()?;
+            let choice = crate::dialog::choose(
+                &message,
+                &[('y', "Discard"), ('d', "Show diff"), ('n', "Cancel")],
+                self.terminal.size(),
+            )?;
+            match choice {
+                Some('y') => return Ok(true),
+                Some('d') => {
+                    let diff = self.diff_against_disk()?;
+                    crate::dialog::pager(
+                        "Changes that would be lost",
+                        &diff,
+                        self.terminal.size(),
+                        diff_line_color,
+                    )?;
+                    self.core.view.invalidate_cache();
+                }
+                _ => {
+                    self.message = Some(format!("{} cancelled", action));
+                    return Ok(false);
+                }
+            }
+        }
+    }
+
+    /// 已開啟 `path` 的緩衝區位置
+    fn buffer_index_of(&self, path: &Path) -> Option<usize> {
+        let same = |buffer: &RopeBuffer| {
+            buffer.file_path().is_some_and(|open| {
+                open == path
+                    || matches!(
+                        (open.canonicalize(), path.canonicalize()),
+                        (Ok(a), Ok(b)) if a == b
+                    )
+            })
+        };
+        self.buffers.iter().position(|hidden| match hidden {
+            Some(hidden) => same(&hidden.buffer),
+            None => same(&self.core.buffer),
+        })
+    }
+
+    /// 有未存檔修改的背景緩衝區數
+    fn modified_hidden_buffers(&self) -> usize {
+        self.buffers
+            .iter()
+            .flatten()
+            .filter(|hidden| hidden.buffer.is_modified())
+            .count()
+    }
+
+    /// 把目前的緩衝區移到背景，返回它的狀態
+    fn hide_current_buffer(&mut self) -> HiddenBuffer {
+        self.poll_save(true);
+        if let Some(handle) = self.pending_recovery.take() {
+            let _ = handle.join();
+        }
+        self.pending_lint = None;
+        #[cfg(feature = "lsp")]
+        {
+            self.lsp = None;
+        }
+        HiddenBuffer {
+            buffer: std::mem::take(&mut self.core.buffer),
+            cursor: (self.core.cursor.row, self.core.cursor.col),
+            offset_row: self.core.view.offset_row,
+            file_lock: self.file_lock.take(),
+            filetype: self.filetype.take(),
+            jumps: std::mem::take(&mut self.jumps),
+            diagnostics: std::mem::take(&mut self.diagnostics),
+            recovery_written: self.recovery_written.take(),
+        }
+    }
+
+    /// 把背景的緩衝區設為目前的緩衝區，恢復光標與捲動位置
+    fn show_buffer(&mut self, hidden: HiddenBuffer) {
+        self.core.buffer = hidden.buffer;
+        self.core.cursor = Cursor::new();
+        self.core.selection = None;
+        self.core.selection_mode = false;
+        self.core.view.offset_row = hidden.offset_row;
+        self.core.view.invalidate_cache();
+        self.file_lock = hidden.file_lock;
+        self.filetype = hidden.filetype;
+        self.jumps = hidden.jumps;
+        self.recovery_written = hidden.recovery_written;
+        self.line_markers_version = None;
+        self.whitespace_version = None;
+        self.emergency_copy_state = None;
+        self.autosave_change = None;
+        self.autosaved_version = None;
+        // 搜尋結果屬於原本的緩衝區，保留搜尋文字重新尋找
+        let query = self.search.query().to_string();
+        self.search.set_query(query);
+        self.detect_file_type();
+        self.set_diagnostics(hidden.diagnostics);
+        #[cfg(feature = "git")]
+        {
+            self.git = None;
+            self.refresh_git();
+        }
+        self.go_to_position(hidden.cursor.0, hidden.cursor.1);
+        #[cfg(feature = "lsp")]
+        if let Err(e) = self.start_lsp() {
+            self.error = Some(format!("{:#}", e));
+        }
+    }
+
+    /// 切換到第 `index` 個緩衝區
+    fn switch_buffer(&mut self, index: usize) {
+        if index == self.current_buffer {
+            return;
+        }
+        let Some(hidden) = self.buffers.get_mut(index).and_then(Option::take) else {
+            return;
+        };
+        let current = self.hide_current_buffer();
+        self.buffers[self.current_buffer] = Some(current);
+        self.current_buffer = index;
+        self.show_buffer(hidden);
+        self.message = Some(format!(
+            "Buffer {}/{}: {}",
+            index + 1,
+            self.buffers.len(),
+            self.core.buffer.file_name()
+        ));
+    }
+
+    /// Alt+, / Alt+.：切換到上一個或下一個緩衝區（循環）
+    fn cycle_buffer(&mut self, forward: bool) {
+        let count = self.buffers.len();
+        if count < 2 {
+            self.message = Some("Only one buffer is open".to_string());
+            return;
+        }
+        let index = if forward {
+            (self.current_buffer + 1) % count
+        } else {
+            (self.current_buffer + count - 1) % count
+        };
+        self.switch_buffer(index);
+    }
+
+    /// 第 `index` 個緩衝區是否有未存檔修改
+    fn buffer_is_modified(&self, index: usize) -> bool {
+        match self.buffers.get(index) {
+            Some(Some(hidden)) => hidden.buffer.is_modified(),
+            Some(None) => self.core.buffer.is_modified(),
+            None => false,
+        }
+    }
+
+    /// 目前的緩衝區有未存檔修改時詢問存檔、放棄或取消；返回 false 表示取消（或存檔沒有完成）
+    fn resolve_unsaved(&mut self, action: &str) -> Result<bool> {
+        self.poll_save(true);
+        // filter 模式下內容會寫到 stdout，不會遺失修改
+        if !self.core.buffer.is_modified() || self.output_to_stdout {
+            return Ok(true);
+        }
+        self.redraw_preview()?;
+        let message = format!(
+            "{}: {} has unsaved changes.",
+            action,
+            self.core.buffer.file_name()
+        );
+        let choice = crate::dialog::choose(
+            &message,
+            &[('s', "Save"), ('d', "Discard"), ('c', "Cancel")],
+            self.terminal.size(),
+        )?;
+        match choice {
+            Some('s') => {
+                self.handle_command_inner(Command::Save)?;
+                self.poll_save(true);
+                // 存檔失敗或另存新檔時取消：留在這個緩衝區
+                Ok(!self.core.buffer.is_modified())
+            }
+            Some('d') => Ok(true),
+            _ => {
+                self.message = Some(format!("{} cancelled", action));
+                Ok(false)
+            }
+        }
+    }
+
+    /// 關閉目前的緩衝區（不檢查修改），切換到下一個緩衝區；關閉最後一個時離開
+    fn close_current_buffer(&mut self) {
+        if self.buffers.len() == 1 {
+            self.should_quit = true;
+            return;
+        }
+        let name = self.core.buffer.file_name();
+        self.remove_recovery();
+        // 丟棄緩衝區時一併釋放檔案鎖
+        drop(self.hide_current_buffer());
+        self.buffers.remove(self.current_buffer);
+        self.current_buffer = self.current_buffer.min(self.buffers.len() - 1);
+        if let Some(hidden) = self.buffers[self.current_buffer].take() {
+            self.show_buffer(hidden);
+        }
+        self.message = Some(format!("Closed {}", name));
+    }
+
+    /// Alt+Q：逐一詢問有未存檔修改的緩衝區（從目前的開始），全部處理完才離開
+    fn quit_all(&mut self) -> Result<()> {
+        let count = self.buffers.len();
+        let start = self.current_buffer;
+        for index in (0..count).map(|offset| (start + offset) % count) {
+            if !self.buffer_is_modified(index) {
+                continue;
+            }
+            self.switch_buffer(index);
+            if !self.resolve_unsaved("Quit")? {
+                return Ok(());
+            }
+        }
+        self.should_quit = true;
+        Ok(())
+    }
+
+    /// `:wa`：儲存所有有未存檔修改的緩衝區，完成後回到原本的緩衝區
+    fn save_all(&mut self) -> Result<()> {
+        let original = self.current_buffer;
+        let mut saved = 0;
+        for index in 0..self.buffers.len() {
+            if !self.buffer_is_modified(index) {
+                continue;
+            }
+            self.switch_buffer(index);
+            self.handle_command_inner(Command::Save)?;
+            self.poll_save(true);
+            // 存檔失敗或取消時停在這個緩衝區
+            if self.core.buffer.is_modified() {
+                let reason = self.error.take().unwrap_or_else(|| "not saved".to_string());
+                bail!("{}: {}", self.core.buffer.file_name(), reason);
+            }
+            saved += 1;
+        }
+        self.switch_buffer(original);
+        self.message = Some(match saved {
+            0 => "No unsaved changes".to_string(),
+            n => format!("Saved {} file{}", n, if n == 1 { "" } else { "s" }),
+        });
+        Ok(())
+    }
+
+    /// `:ls`：列出開啟的緩衝區，Enter 切換
+    fn list_buffers(&mut self) -> Result<()> {
+        let labels: Vec<String> = self
+            .buffers
+            .iter()
+            .enumerate()
+            .map(|(index, hidden)| {
+                let buffer = hidden.as_ref().map_or(&self.core.buffer, |h| &h.buffer);
+                let path = buffer
+                    .file_path()
+                    .map_or_else(|| buffer.file_name(), |p| p.display().to_string());
+                let modified = if buffer.is_modified() {
+                    " [modified]"
+                } else {
+                    ""
+                };
+                format!("{}: {}{}", index + 1, path, modified)
+            })
+            .collect();
+        let choice = crate::dialog::pick(
+            "Buffers",
+            &labels,
+            self.current_buffer,
+            self.terminal.size(),
+            &mut |_| Ok(()),
+        )?;
+        if let Some(index) = choice {
+            self.switch_buffer(index);
+        }
+        Ok(())
+    }
+
+    /// 開啟檔案：已開啟時切換到它的緩衝區，否則開在新的緩衝區；
+    /// 重新開啟目前的檔案時取代目前的內容，有未存檔修改時需要 force 或確認
+    fn open_file(&mut self, path: &Path, force: bool) -> Result<()> {
+        // 目錄：瀏覽並選擇其中的檔案
+        if path.is_dir() {
+            let file = crate::browser::browse(path, self.terminal.size())?;
+            self.core.view.invalidate_cache();
+            return match file {
+                Some(file) => self.open_file(&file, force),
+                None => Ok(()),
+            };
+        }
+        self.poll_save(true);
+        if let Some(index) = self.buffer_index_of(path) {
+            if index != self.current_buffer {
+                self.switch_buffer(index);
+                return Ok(());
+            }
+        }
+        // 重新開啟目前的檔案，或目前是沒有修改的新檔案時取代目前的緩衝區，否則開在新的緩衝區
+        let replace = self.is_current_file(path)
+            || (!self.core.buffer.is_modified()
+                && self.core.buffer.file_path().is_none_or(|p| !p.exists()));
+        if replace && !force && !self.confirm_discard("Open")? {
+            return Ok(());
+        }
+
+        let encoding_config = EncodingConfig {
+            read_encoding: None,
+            save_encoding: None,
+            bom: None,
+        };
+        let buffer = RopeBuffer::from_file_with_encoding(path, &encoding_config)
+            .with_context(|| format!("Cannot open {}", path.display()))?;
+
+        if replace {
+            // 先釋放舊檔案的鎖，再檢查新檔案是否已被其他實例開啟
+            self.file_lock = None;
+        } else {
+            // 目前的緩衝區移到背景，新的緩衝區排在它後面
+            let hidden = self.hide_current_buffer();
+            self.buffers[self.current_buffer] = Some(hidden);
+            self.current_buffer += 1;
+            self.buffers.insert(self.current_buffer, None);
+        }
+        let conflict = FileLock::existing(path);
+        self.file_lock = FileLock::acquire(path).ok();
+
+        self.core.buffer = buffer;
+        self.core.cursor = Cursor::new();
+        self.core.selection = None;
+        self.core.selection_mode = false;
+        self.core.view.offset_row = 0;
+        self.core.view.invalidate_cache();
+        self.line_markers_version = None;
+        self.whitespace_version = None;
+        self.filetype = None;
+        self.jumps.clear();
+        self.pending_lint = None;
+        self.set_diagnostics(Vec::new());
+        self.detect_file_type();
+        #[cfg(feature = "git")]
+        {
+            self.git = None;
+            self.refresh_git();
+        }
+        self.remove_recovery();
+        self.autosave_change = None;
+        self.autosaved_version = None;
+        self.check_recovery();
+        self.read_undo_file();
+
+        self.message = Some(match conflict {
+            Some(info) => format!(
+                "Opened {} (also open in another wedi, pid {} on {})",
+                path.display(),
+                info.pid,
+                info.hostname
+            ),
+            None => format!("Opened {}", path.display()),
+        });
+        if let Err(e) = self.run_hooks(None, |hook, ctx| hook.on_open(ctx)) {
+            self.error = Some(format!("{:#}", e));
+        }
+        #[cfg(feature = "lsp")]
+        if let Err(e) = self.start_lsp() {
+            self.error = Some(format!("{:#}", e));
+        }
+        Ok(())
+    }
+
+    /// 設定 `:set` 選項，返回要顯示的訊息
+    fn set_option(&mut self, option: &str, value: Option<&str>) -> Result<String> {
+        if matches!(option, "encoding" | "enc") {
+            let label = value.context("Usage: :set encoding=NAME")?;
+            let encoding = encoding_rs::Encoding::for_label(label.as_bytes())
+                .with_context(|| format!("Unsupported encoding: {}", label))?;
+            self.core.buffer.set_save_encoding(encoding);
+            return Ok(format!("Save encoding: {}", encoding.name()));
+        }
+
+        if matches!(option, "ambiwidth" | "ambw") {
+            let width: AmbiguousWidth = value
+                .context("Usage: :set ambiwidth=narrow|wide|auto")?
+                .parse()?;
+            let wide = match width {
+                AmbiguousWidth::Narrow => false,
+                AmbiguousWidth::Wide => true,
+                AmbiguousWidth::Auto => Terminal::probe_ambiguous_wide()?,
+            };
+            crate::utils::set_ambiguous_wide(wide);
+            self.core.view.invalidate_cache();
+            self.core.view.invalidate_frame();
+            return Ok(format!(
+                "Ambiguous width: {}",
+                if wide { "wide" } else { "narrow" }
+            ));
+        }
+
+        if option == "autosave" {
+            let policy: AutosavePolicy = value
+                .context("Usage: :set autosave=off|file|recovery")?
+                .parse()?;
+            self.set_autosave(policy);
+            return Ok(format!("Autosave: {}", policy.name()));
+        }
+
+        if matches!(option, "numbermode" | "nm") {
+            let mode: LineNumberMode = value
+                .context("Usage: :set numbermode=absolute|relative|hybrid")?
+                .parse()?;
+            self.core.view.line_number_mode = mode;
+            return Ok(format!("Line numbers: {}", mode.name()));
+        }
+
+        if matches!(option, "numberstart" | "nus") {
+            self.core.view.line_number_start = match value {
+                Some("0") => 0,
+                Some("1") => 1,
+                _ => bail!("Usage: :set numberstart=0|1"),
+            };
+            self.core.view.invalidate_cache();
+            return Ok(format!(
+                "First line number: {}",
+                self.core.view.line_number_start
+            ));
+        }
+
+        if matches!(option, "textwidth" | "tw") {
+            let width = value.context("Usage: :set textwidth=N (0 to turn off)")?;
+            self.core.text_width = width
+                .parse()
+                .with_context(|| format!("Invalid text width: {}", width))?;
+            self.auto_text_width = false;
+            return Ok(match self.core.text_width {
+                0 => "Text width: Off".to_string(),
+                width => format!("Text width: {} (lines break while typing)", width),
+            });
+        }
 
-            // 視圖控制
-            Command::ToggleLineNumbers => {
-                self.view.toggle_line_numbers();
-            }
-
-            // 註解切換
-            Command::ToggleComment => {
-                if !self.comment_handler.has_comment_style() {
-                    self.message = Some("No comment style for this file type".to_string());
-                } else if self.has_selection() {
-                    // 多行選擇：智能切換註解
-                    if let Some(sel) = self.selection {
-                        let (start_row, _) = sel.start.min(sel.end);
-                        let (end_row, _) = sel.start.max(sel.end);
-
-                        // 檢查是否有任何一行沒有註解
-                        let mut has_uncommented = false;
-                        for row in start_row..=end_row {
-                            let line_content = self.buffer.get_line_content(row);
-                            if !self.comment_handler.is_commented(&line_content) {
-                                has_uncommented = true;
-                                break;
-                            }
-                        }
+        if matches!(option, "esctimeout" | "escdelay") {
+            let millis = value.context("Usage: :set esctimeout=MILLISECONDS (0 to turn off)")?;
+            let millis: u64 = millis
+                .parse()
+                .with_context(|| format!("Invalid escape timeout: {}", millis))?;
+            Terminal::set_escape_timeout(std::time::Duration::from_millis(millis));
+            return Ok(match millis {
+                0 => "Escape timeout: Off (ESC is never combined with the next key)".to_string(),
+                millis => format!("Escape timeout: {} ms", millis),
+            });
+        }
 
-                        // 如果有任何一行沒註解，全部加註解；否則全部取消註解
-                        let should_add_comment = has_uncommented;
-
-                        // 從後往前處理，避免行號變化
-                        for row in (start_row..=end_row).rev() {
-                            let line_content = self.buffer.get_line_content(row);
-
-                            let new_line = if should_add_comment {
-                                // 全部加註解（即使已經有註解的也保持不變）
-                                if self.comment_handler.is_commented(&line_content) {
-                                    Some(line_content.clone())
-                                } else {
-                                    self.comment_handler.add_comment(&line_content)
-                                }
-                            } else {
-                                // 全部取消註解
-                                self.comment_handler.remove_comment(&line_content)
-                            };
-
-                            if let Some(new_line) = new_line {
-                                // 計算行的起始和結束位置
-                                let line_start = self.buffer.line_to_char(row);
-                                let line_end = if row + 1 < self.buffer.line_count() {
-                                    self.buffer.line_to_char(row + 1)
-                                } else {
-                                    self.buffer.len_chars()
-                                };
-
-                                // 刪除舊行（包括換行符）
-                                self.buffer.delete_range(line_start, line_end);
-
-                                // 插入新行（保留換行符）
-                                let new_line_with_newline = if line_content.ends_with('\n')
-                                    || line_content.ends_with("\r\n")
-                                {
-                                    format!("{}\n", new_line.trim_end_matches(['\n', '\r']))
-                                } else {
-                                    new_line.trim_end_matches(['\n', '\r']).to_string()
-                                };
-                                self.buffer.insert(line_start, &new_line_with_newline);
-                            }
-                        }
+        if matches!(option, "maxfps" | "fps") {
+            let fps = value.context("Usage: :set maxfps=N (0 for no limit)")?;
+            let fps: u32 = fps
+                .parse()
+                .with_context(|| format!("Invalid frame rate: {}", fps))?;
+            self.frame_interval = match fps {
+                0 => std::time::Duration::ZERO,
+                fps => std::time::Duration::from_secs(1) / fps,
+            };
+            return Ok(match fps {
+                0 => "Max frame rate: no limit".to_string(),
+                fps => format!("Max frame rate: {} fps", fps),
+            });
+        }
 
-                        self.view.invalidate_cache();
+        if matches!(option, "wrapgutter" | "wg") {
+            let wrap_gutter: WrapGutter = value
+                .context("Usage: :set wrapgutter=blank|dot|number")?
+                .parse()?;
+            self.core.view.set_wrap_gutter(wrap_gutter);
+            return Ok(format!("Wrapped rows: {}", wrap_gutter.name()));
+        }
 
-                        // 保留選擇狀態（不清除選取）
-                        self.cursor.row = start_row;
-                        self.cursor.col = 0;
-                        self.cursor.desired_visual_col = 0;
+        #[cfg(feature = "lsp")]
+        if let Some(extension) = option.strip_prefix("lspprg.") {
+            let command = value.unwrap_or_default().trim();
+            self.lsp_programs
+                .insert(extension.to_lowercase(), command.to_string());
+            return Ok(if command.is_empty() {
+                format!("Language server for .{}: off", extension)
+            } else {
+                format!("Language server for .{}: {}", extension, command)
+            });
+        }
 
-                        let action = if should_add_comment {
-                            "Added"
-                        } else {
-                            "Removed"
-                        };
-                        self.message = Some(format!("{} comments", action));
-                    }
-                } else {
-                    // 單行：直接切換註解
-                    let line_content = self.buffer.get_line_content(self.cursor.row);
-                    if let Some(new_line) = self.comment_handler.toggle_line_comment(&line_content)
-                    {
-                        // 計算行的起始和結束位置
-                        let line_start = self.buffer.line_to_char(self.cursor.row);
-                        let line_end = if self.cursor.row + 1 < self.buffer.line_count() {
-                            self.buffer.line_to_char(self.cursor.row + 1)
-                        } else {
-                            self.buffer.len_chars()
-                        };
+        if let Some(extension) = option.strip_prefix("lintprg.") {
+            let command = value.unwrap_or_default().trim();
+            self.lint_programs
+                .insert(extension.to_lowercase(), command.to_string());
+            return Ok(if command.is_empty() {
+                format!("Linter for .{}: off", extension)
+            } else {
+                format!("Linter for .{}: {}", extension, command)
+            });
+        }
 
-                        // 刪除舊行（包括換行符）
-                        self.buffer.delete_range(line_start, line_end);
+        if matches!(option, "formatprg" | "fp") {
+            self.format_program = value.filter(|v| !v.trim().is_empty()).map(str::to_string);
+            return Ok(match (&self.format_program, self.formatter_command()) {
+                (Some(command), _) => format!("Formatter: {}", command),
+                (None, Some(command)) => format!("Formatter: auto ({})", command),
+                (None, None) => "Formatter: auto (none for this file type)".to_string(),
+            });
+        }
 
-                        // 插入新行（保留換行符）
-                        let new_line_with_newline =
-                            if line_content.ends_with('\n') || line_content.ends_with("\r\n") {
-                                format!("{}\n", new_line.trim_end_matches(['\n', '\r']))
-                            } else {
-                                new_line.trim_end_matches(['\n', '\r']).to_string()
-                            };
-                        self.buffer.insert(line_start, &new_line_with_newline);
+        if matches!(option, "dateformat" | "df" | "timeformat" | "tf") {
+            let format = value.with_context(|| {
+                format!(
+                    "Usage: :set {}=FORMAT (strftime style, e.g. %Y-%m-%d %H:%M)",
+                    option
+                )
+            })?;
+            let (target, name) = if option.starts_with('d') {
+                (&mut self.date_format, "Date format")
+            } else {
+                (&mut self.time_format, "Time format")
+            };
+            *target = format.to_string();
+            return Ok(format!(
+                "{}: {} ({})",
+                name,
+                format,
+                LocalTime::now().format(format)
+            ));
+        }
 
-                        self.view.invalidate_cache();
+        if matches!(option, "cursorlinecolor" | "culc") {
+            let color = value.context("Usage: :set cursorlinecolor=COLOR")?;
+            self.core.view.cursor_line = Some(crate::styled::parse_color(color)?);
+            return Ok(format!("Cursor line color: {}", color));
+        }
 
-                        self.message = Some("Toggled comment".to_string());
-                    }
-                }
-            }
+        if matches!(option, "colorcolumn" | "cc") && value.is_some() {
+            let columns = crate::view::parse_columns(value.unwrap_or_default())?;
+            self.set_color_columns(columns, None);
+            return Ok(self.color_columns_message());
+        }
 
-            // 縮排（Tab 鍵）
-            Command::Indent => {
-                if self.has_selection() {
-                    // 多行選擇：對每行添加 4 個空格
-                    if let Some(sel) = self.selection {
-                        let (start_row, _) = sel.start.min(sel.end);
-                        let (end_row, _) = sel.start.max(sel.end);
+        if matches!(option, "colorcolumncolor" | "ccc") {
+            let color = value.context("Usage: :set colorcolumncolor=COLOR")?;
+            self.core.view.color_column_color = crate::styled::parse_color(color)?;
+            return Ok(format!("Color column color: {}", color));
+        }
 
-                        // 從後往前處理，避免行號變化
-                        for row in (start_row..=end_row).rev() {
-                            let line_start = self.buffer.line_to_char(row);
-                            self.buffer.insert(line_start, "    ");
-                        }
+        // `syntax` 本身是開關，其他的值視為語言名稱
+        if matches!(option, "filetype" | "ft")
+            || (option == "syntax"
+                && value.is_some_and(|v| crate::ex::parse_flag(option, Some(v)).is_err()))
+        {
+            return self.set_filetype(value);
+        }
 
-                        self.view.invalidate_cache();
+        #[cfg(feature = "syntax-highlighting")]
+        if option == "theme" {
+            let name = value.context("Usage: :set theme=NAME (see Ctrl+T)")?;
+            self.set_syntax_theme(name)?;
+            return Ok(format!("Theme: {}", name));
+        }
 
-                        // 保留選擇狀態
-                        self.cursor.row = start_row;
-                        self.cursor.col = 0;
-                        self.cursor.desired_visual_col = 0;
-                    }
-                } else {
-                    // 單行：在光標位置插入 4 個空格
-                    let pos = self.cursor.char_position(&self.buffer);
-                    self.buffer.insert(pos, "    ");
-                    self.view.invalidate_cache();
-                    self.cursor.col += 4;
-                    self.cursor.desired_visual_col = self.cursor.col;
-                }
-            }
-
-            // 退位（Shift+Tab 鍵）
-            Command::Unindent => {
-                if self.has_selection() {
-                    // 多行選擇：對每行刪除最多 4 個前導空格
-                    if let Some(sel) = self.selection {
-                        let (start_row, _) = sel.start.min(sel.end);
-                        let (end_row, _) = sel.start.max(sel.end);
-
-                        // 從後往前處理，避免行號變化
-                        for row in (start_row..=end_row).rev() {
-                            let line_content = self.buffer.get_line_content(row);
-                            let spaces_to_remove = line_content
-                                .chars()
-                                .take_while(|&c| c == ' ')
-                                .take(4)
-                                .count();
-
-                            if spaces_to_remove > 0 {
-                                let line_start = self.buffer.line_to_char(row);
-                                self.buffer
-                                    .delete_range(line_start, line_start + spaces_to_remove);
-                            }
-                        }
+        if matches!(option, "uitheme" | "uit") {
+            let name = value.context("Usage: :set uitheme=NAME")?;
+            self.core.view.theme = crate::theme::UiTheme::named(name).with_context(|| {
+                format!(
+                    "Unknown UI theme: {} (use {})",
+                    name,
+                    crate::theme::UI_THEMES.join(", ")
+                )
+            })?;
+            return Ok(format!("UI theme: {}", name));
+        }
+
+        if let Some(element) = option.strip_prefix("ui.") {
+            let style = value.context("Usage: :set ui.ELEMENT=FG[,BG]")?;
+            self.core.view.theme.set_element(element, style)?;
+            return Ok(format!("UI {}: {}", element, style));
+        }
 
-                        self.view.invalidate_cache();
+        if matches!(option, "rulercolumns" | "rc") {
+            let columns = value.context("Usage: :set rulercolumns=7,73")?;
+            self.set_ruler(crate::view::parse_columns(columns)?);
+            let columns = &self.core.view.ruler_columns;
+            return Ok(if columns.is_empty() {
+                "Ruler: On".to_string()
+            } else {
+                let list: Vec<String> = columns.iter().map(|c| c.to_string()).collect();
+                format!("Ruler columns: {}", list.join(","))
+            });
+        }
 
-                        // 保留選擇狀態
-                        self.cursor.row = start_row;
-                        self.cursor.col = 0;
-                        self.cursor.desired_visual_col = 0;
-                    }
+        let (name, on) = crate::ex::parse_flag(option, value)?;
+        let label = if on { "On" } else { "Off" };
+        match name {
+            "number" | "nu" => {
+                self.core.view.show_line_numbers = on;
+                self.core.view.invalidate_cache();
+                Ok(format!("Line numbers: {}", label))
+            }
+            // 與 vi 相同：number 與 relativenumber 同時開啟時為混合模式
+            "relativenumber" | "rnu" => {
+                self.core.view.line_number_mode = if on {
+                    LineNumberMode::Hybrid
                 } else {
-                    // 單行：刪除光標前最多 4 個空格
-                    let line_content = self.buffer.get_line_content(self.cursor.row);
-                    let before_cursor: String =
-                        line_content.chars().take(self.cursor.col).collect();
-                    let spaces_to_remove = before_cursor
-                        .chars()
-                        .rev()
-                        .take_while(|&c| c == ' ')
-                        .take(4)
-                        .count();
-
-                    if spaces_to_remove > 0 {
-                        let line_start = self.buffer.line_to_char(self.cursor.row);
-                        let delete_start = line_start + self.cursor.col - spaces_to_remove;
-                        self.buffer
-                            .delete_range(delete_start, delete_start + spaces_to_remove);
-                        self.view.invalidate_cache();
-                        self.cursor.col -= spaces_to_remove;
-                        self.cursor.desired_visual_col = self.cursor.col;
-                    }
+                    LineNumberMode::Absolute
+                };
+                Ok(format!(
+                    "Line numbers: {}",
+                    self.core.view.line_number_mode.name()
+                ))
+            }
+            "hexnumber" | "hexnu" => {
+                self.core.view.hex_line_numbers = on;
+                self.core.view.invalidate_cache();
+                Ok(format!("Hex line numbers: {}", label))
+            }
+            "colorcolumn" | "cc" => {
+                let columns = match (on, self.core.view.color_columns.is_empty()) {
+                    (true, true) => vec![DEFAULT_COLOR_COLUMN],
+                    (true, false) => self.core.view.color_columns.clone(),
+                    (false, _) => Vec::new(),
+                };
+                self.set_color_columns(columns, None);
+                Ok(self.color_columns_message())
+            }
+            "list" | "invisibles" => {
+                self.core.view.set_show_invisibles(on);
+                Ok(format!("Invisibles: {}", label))
+            }
+            "cursorline" | "cul" => {
+                self.core.view.cursor_line = on.then_some(DEFAULT_CURSOR_LINE_COLOR);
+                Ok(format!("Cursor line: {}", label))
+            }
+            "mouse" => {
+                Terminal::set_mouse_capture(on)?;
+                Ok(format!("Mouse: {}", label))
+            }
+            "stickyheader" | "sticky" => {
+                self.core.view.sticky_header = on;
+                Ok(format!("Sticky header: {}", label))
+            }
+            "pasteindent" | "pi" => {
+                self.core.paste_indent = on;
+                Ok(format!("Re-indent pasted blocks: {}", label))
+            }
+            "keydebug" | "kd" => {
+                self.key_debug = on;
+                Ok(format!("Key debug: {}", label))
+            }
+            "autowrite" | "aw" => {
+                self.autowrite = on;
+                Ok(format!("Save before shell commands: {}", label))
+            }
+            "lint" => {
+                self.lint_enabled = on;
+                if on {
+                    self.start_lint();
+                } else {
+                    self.pending_lint = None;
+                    self.set_diagnostics(Vec::new());
                 }
+                Ok(format!("Lint on save: {}", label))
+            }
+            "formatonsave" | "fos" => {
+                self.format_on_save = on;
+                Ok(format!("Format on save: {}", label))
+            }
+            "ruler" => {
+                self.core.view.show_ruler = on;
+                Ok(format!("Ruler: {}", label))
+            }
+            "tabline" | "tal" => {
+                self.tab_line = on;
+                Ok(format!("Tab line: {}", label))
+            }
+            #[cfg(feature = "vi")]
+            "vi" => {
+                self.modal = on.then(crate::modal::Modal::default);
+                Ok(format!("vi mode: {}", label))
+            }
+            "undofile" | "udf" => {
+                self.undo_file = on;
+                // 在設定檔或 --set 中開啟時讀回啟動時開啟的檔案的撤銷檔
+                self.read_undo_file();
+                Ok(format!("Undo file: {}", label))
+            }
+            "bom" => {
+                self.core.buffer.set_bom(on);
+                Ok(format!("BOM: {} (applies on save)", label))
+            }
+            #[cfg(feature = "lsp")]
+            "lsp" => {
+                self.lsp_enabled = on;
+                Ok(match self.start_lsp()? {
+                    Some(name) => format!("LSP: {}", name),
+                    None if on => "LSP: On (no server for this file type)".to_string(),
+                    None => "LSP: Off".to_string(),
+                })
             }
+            #[cfg(feature = "syntax-highlighting")]
+            "syntax" => {
+                self.highlight_enabled = on;
+                Ok(format!("Syntax Highlight: {}", label))
+            }
+            _ => bail!("Unknown option: {}", option),
+        }
+    }
 
-            // 跳轉到行
-            Command::GoToLine => {
-                if let Ok(Some(line_str)) =
-                    crate::dialog::prompt("Go to line:", self.terminal.size())
-                {
-                    if let Ok(line_num) = line_str.trim().parse::<usize>() {
-                        if line_num > 0 && line_num <= self.buffer.line_count() {
-                            self.cursor.row = line_num - 1;
-                            self.cursor.col = 0;
-                            self.cursor.desired_visual_col = 0;
-                            self.message = Some(format!("Jumped to line {}", line_num));
-                        } else {
-                            self.message = Some(format!("Invalid line number: {}", line_num));
-                        }
-                    } else {
-                        self.message = Some("Please enter a valid number".to_string());
-                    }
-                }
+    /// 跳到下一個（或上一個）搜尋結果
+    ///
+    /// 還在尋找且目前找到的結果不夠決定位置時（還沒有結果，或往前時需要繞到最後一個），
+    /// 記下方向，由背景搜尋找到後再跳
+    fn jump_to_match(&mut self, forward: bool) {
+        self.search_jump = None;
+        let searching = self.search.is_searching();
+        let target = if forward {
+            self.search.next_match()
+        } else if !searching || self.search.current_index().is_some_and(|index| index > 0) {
+            self.search.prev_match()
+        } else {
+            None
+        };
+        if let Some((row, col)) = target {
+            // 結果的列是字元索引；由 set_position 換算寬字元與自動換行後的視覺位置
+            self.core
+                .cursor
+                .set_position(&self.core.buffer, &self.core.view, row, col);
+            self.message = Some(self.search.counter());
+        } else if self.search.query().is_empty() {
+            self.message = Some("No active search".to_string());
+        } else if searching {
+            self.search_jump = Some(forward);
+            self.message = Some(self.search.counter());
+        } else {
+            self.message = Some(format!("No matches found for '{}'", self.search.query()));
+        }
+    }
+
+    /// 背景搜尋繼續找下一段，完成等待中的跳轉；返回是否需要重繪
+    fn step_search(&mut self) -> bool {
+        if !self.search.is_searching() {
+            return false;
+        }
+        self.search.step(&self.core.buffer);
+        match self.search_jump {
+            Some(true) if self.search.match_count() > 0 => self.jump_to_match(true),
+            Some(forward) if !self.search.is_searching() => self.jump_to_match(forward),
+            _ => {}
+        }
+        true
+    }
+
+    /// 列出所有搜尋結果（行號與該行內容），移動時預覽、Enter 跳過去，`r` 取代列出的全部結果
+    ///
+    /// 沒有指定文字時使用上一次的搜尋（包括只在選擇範圍內搜尋），沒有搜尋過則先詢問
+    fn show_matches(&mut self, query: Option<String>) -> Result<()> {
+        let size = self.terminal.size();
+        let query = match query {
+            Some(query) => Some(query),
+            None if self.search.query().is_empty() => crate::dialog::prompt("Search:", size)?,
+            None => None,
+        };
+        match query {
+            Some(query) if query.is_empty() => return Ok(()),
+            Some(query) => {
+                self.search.set_query(query);
+                self.search.start(&self.core.buffer);
+                self.search.step(&self.core.buffer);
             }
+            None => self.search.refresh(&self.core.buffer),
+        }
+        let query = self.search.query().to_string();
+        if query.is_empty() {
+            return Ok(());
+        }
+        self.show_search_matches = true;
+        if let Some(progress) = self.search.progress() {
+            self.message = Some(format!(
+                "Still searching ({}%), list the matches when it finishes",
+                progress
+            ));
+            return Ok(());
+        }
+        if self.search.match_count() == 0 {
+            self.message = Some(format!("No matches found for '{}'", query));
+            return Ok(());
+        }
 
-            // 編碼切換
-            Command::ChangeEncoding => {
-                if let Ok(Some(encoding_str)) =
-                    crate::dialog::prompt("Change encoding to:", self.terminal.size())
-                {
-                    if let Some(encoding) = Self::parse_encoding(&encoding_str) {
-                        // 檢查是否有檔案路徑（區分已存在檔案和新建檔案）
-                        if self.buffer.has_file_path() {
-                            // 已存在的檔案：需要重新載入
-                            if self.buffer.is_modified() {
-                                // 有未保存的修改，顯示確認對話框
-                                if let Ok(confirmed) = crate::dialog::confirm(
-                                    "Unsaved changes will be lost. Continue?",
-                                    self.terminal.size(),
-                                ) {
-                                    if confirmed {
-                                        match self.buffer.reload_with_encoding(encoding) {
-                                            Ok(_) => {
-                                                // 重新載入成功，重置游標
-                                                self.cursor.row = 0;
-                                                self.cursor.col = 0;
-                                                self.cursor.desired_visual_col = 0;
-                                                self.cursor.visual_line_index = 0;
-                                                self.view.invalidate_cache();
-                                                self.message = Some(format!(
-                                                    "Encoding changed to {} (file reloaded)",
-                                                    encoding.name()
-                                                ));
-                                            }
-                                            Err(e) => {
-                                                self.message =
-                                                    Some(format!("Failed to reload file: {}", e));
-                                            }
-                                        }
-                                    }
-                                }
-                            } else {
-                                // 沒有未保存的修改，直接重新載入
-                                match self.buffer.reload_with_encoding(encoding) {
-                                    Ok(_) => {
-                                        self.cursor.row = 0;
-                                        self.cursor.col = 0;
-                                        self.cursor.desired_visual_col = 0;
-                                        self.cursor.visual_line_index = 0;
-                                        self.view.invalidate_cache();
-                                        self.message = Some(format!(
-                                            "Encoding changed to {} (file reloaded)",
-                                            encoding.name()
-                                        ));
-                                    }
-                                    Err(e) => {
-                                        self.message =
-                                            Some(format!("Failed to reload file: {}", e));
-                                    }
-                                }
-                            }
-                        } else {
-                            // 新建檔案：只設定編碼，不重新載入
-                            self.buffer.change_encoding(encoding);
-                            self.message = Some(format!(
-                                "Encoding set to {} (will be used on save)",
-                                encoding.name()
-                            ));
-                        }
-                    } else {
-                        self.message = Some(format!("Unsupported encoding: {}", encoding_str));
-                    }
+        // 結果的字元位置與列表中的「行號: 內容」
+        let width = self.core.buffer.line_count().to_string().len();
+        let positions = self.search.matches().to_vec();
+        let labels: Vec<String> = positions
+            .iter()
+            .map(|&(row, _)| {
+                let line = self.core.buffer.get_line_content(row);
+                let line = line.trim_end_matches(['\n', '\r']);
+                format!("{:>width$}: {}", row + 1, line.trim_start())
+            })
+            .collect();
+
+        let original = (self.core.cursor.row, self.core.cursor.col);
+        let original_selection = self.core.selection;
+        let selected = self.search.current_index().unwrap_or(0);
+        let title = format!("Matches for '{}'", query);
+        let choice = crate::dialog::pick_with_keys(
+            &title,
+            &labels,
+            selected,
+            &[('r', "Replace all")],
+            size,
+            &mut |index| {
+                let (row, col) = positions[index];
+                self.go_to_position(row, col);
+                self.redraw_preview()
+            },
+        )?;
+
+        match choice {
+            Some(Picked::Item(index)) => {
+                let (row, col) = positions[index];
+                self.go_to_position(row, col);
+                self.search.select(index);
+                self.message = Some(self.search.counter());
+            }
+            Some(Picked::Key(_, _)) => {
+                self.go_to_position(original.0, original.1);
+                let prompt = format!(
+                    "Replace {} match{} of '{}' with:",
+                    positions.len(),
+                    if positions.len() == 1 { "" } else { "es" },
+                    query
+                );
+                if let Some(replacement) = crate::dialog::prompt(&prompt, size)? {
+                    self.replace_matches(&positions, &query, &replacement);
                 }
             }
-
-            // 切換語法高亮
-            #[cfg(feature = "syntax-highlighting")]
-            Command::ToggleSyntaxHighlight => {
-                self.highlight_enabled = !self.highlight_enabled;
-                self.message = Some(format!(
-                    "Syntax Highlight: {}",
-                    if self.highlight_enabled { "Enabled" } else { "Disabled" }
-                ));
+            None => {
+                self.go_to_position(original.0, original.1);
+                self.core.selection = original_selection;
             }
         }
-
         Ok(())
     }
 
-    fn has_selection(&self) -> bool {
-        self.selection.is_some()
+    /// 把 (行, 列) 位置上的 `query` 全部取代為 `replacement`，整批修改只算一個復原步驟
+    fn replace_matches(&mut self, positions: &[(usize, usize)], query: &str, replacement: &str) {
+        let (Some(&(first_row, first_col)), Some(&(last_row, last_col))) =
+            (positions.first(), positions.last())
+        else {
+            return;
+        };
+        let query_chars = query.chars().count();
+        let start = self.core.buffer.line_to_char(first_row) + first_col;
+        let end = self.core.buffer.line_to_char(last_row) + last_col + query_chars;
+
+        let text: Vec<char> = self
+            .core
+            .buffer
+            .text()
+            .chars()
+            .skip(start)
+            .take(end - start)
+            .collect();
+        let mut replaced = String::new();
+        let mut copied = 0;
+        for &(row, col) in positions {
+            let pos = self.core.buffer.line_to_char(row) + col - start;
+            replaced.extend(&text[copied..pos]);
+            replaced.push_str(replacement);
+            copied = pos + query_chars;
+        }
+        replaced.extend(&text[copied..]);
+
+        self.core.buffer.replace_range(start, end, &replaced);
+        let count = positions.len();
+        self.core.buffer.describe_last_edit(format!(
+            "replace {} occurrence{}",
+            count,
+            if count == 1 { "" } else { "s" }
+        ));
+        self.core.view.invalidate_cache();
+        #[cfg(feature = "syntax-highlighting")]
+        self.highlight_cache.clear();
+        self.core.selection = None;
+        self.core.selection_mode = false;
+        let (row, col) = (self.core.cursor.row, self.core.cursor.col);
+        self.go_to_position(row, col);
+        self.message = Some(format!(
+            "{} substitution{}",
+            count,
+            if count == 1 { "" } else { "s" }
+        ));
+    }
+
+    /// 選擇範圍的字元位置 (起點, 終點)
+    fn selection_range(&self) -> Option<(usize, usize)> {
+        let sel = self.core.selection?;
+        let (start_row, start_col) = sel.start.min(sel.end);
+        let (end_row, end_col) = sel.start.max(sel.end);
+        Some((
+            self.core.buffer.line_to_char(start_row) + start_col,
+            self.core.buffer.line_to_char(end_row) + end_col,
+        ))
     }
 
-    /// 獲取要複製/剪切的文本
-    /// 如果有選擇範圍，返回選擇的文本；否則返回當前整行（帶換行符）
-    fn get_copy_text(&self) -> String {
-        if self.has_selection() {
-            self.get_selected_text()
+    /// 以字面文字取代選擇範圍（沒有選擇時為整個檔案）中的內容
+    ///
+    /// 空的搜尋文字沿用上一次 Ctrl+F 的搜尋內容
+    fn substitute(&mut self, pattern: &str, replacement: &str, global: bool) -> Result<()> {
+        let pattern = if pattern.is_empty() {
+            self.search.query().to_string()
         } else {
-            // 複製當前整行（完整內容，包括尾部空格和換行符）
-            let line_text = self.buffer.get_line_full(self.cursor.row);
-            // 確保以換行符結尾（用於識別整行貼上）
-            if line_text.ends_with('\n') {
-                line_text
-            } else {
-                format!("{}\n", line_text)
-            }
+            pattern.to_string()
+        };
+        if pattern.is_empty() {
+            bail!("No previous search pattern");
+        }
+
+        let (start, end) = self
+            .selection_range()
+            .unwrap_or((0, self.core.buffer.len_chars()));
+
+        let text: String = self
+            .core
+            .buffer
+            .text()
+            .chars()
+            .skip(start)
+            .take(end - start)
+            .collect();
+        let (replaced, count) = crate::ex::substitute(&text, &pattern, replacement, global);
+        if count == 0 {
+            bail!("Pattern not found: {}", pattern);
         }
+
+        self.core.buffer.replace_range(start, end, &replaced);
+        self.core.buffer.describe_last_edit(format!(
+            "replace {} occurrence{}",
+            count,
+            if count == 1 { "" } else { "s" }
+        ));
+        self.core.view.invalidate_cache();
+        self.core.selection = None;
+        self.core.selection_mode = false;
+
+        self.clamp_cursor_to_buffer();
+
+        self.message = Some(format!(
+            "{} substitution{}",
+            count,
+            if count == 1 { "" } else { "s" }
+        ));
+        Ok(())
+    }
+
+    /// 取代大段文字後，光標留在原來的行，超出範圍時移到最後一行
+    fn clamp_cursor_to_buffer(&mut self) {
+        let row = self
+            .core
+            .cursor
+            .row
+            .min(self.core.buffer.line_count().saturating_sub(1));
+        let col = self.core.cursor.col.min(
+            self.core
+                .buffer
+                .get_line_content(row)
+                .trim_end_matches(['\n', '\r'])
+                .chars()
+                .count(),
+        );
+        self.core
+            .cursor
+            .set_position(&self.core.buffer, &self.core.view, row, col);
     }
 
-    /// 設置剪貼簿內容
-    /// use_system: true 表示使用系統剪貼簿，false 表示僅使用內部剪貼簿
-    fn set_clipboard_text(&mut self, text: String, use_system: bool) {
-        if use_system {
-            // 嘗試系統剪貼簿，失敗則回退到內部剪貼簿
-            if self.clipboard.set_text(&text).is_err() && !self.clipboard.is_available() {
-                self.message = Some("Copied (internal clipboard)".to_string());
+    /// 另存新檔的目標已被其他實例開啟時詢問是否仍要覆寫；返回 false 表示取消
+    fn confirm_foreign_lock(&mut self, path: &Path) -> bool {
+        let Some(info) = FileLock::existing(path) else {
+            return true;
+        };
+        let message = format!(
+            "{} is open{} in another wedi (pid {} on {}).",
+            path.display(),
+            if info.modified {
+                " with unsaved changes"
+            } else {
+                ""
+            },
+            info.pid,
+            info.hostname
+        );
+        match crate::dialog::choose(
+            &message,
+            &[('o', "Overwrite"), ('c', "Cancel")],
+            self.terminal.size(),
+        ) {
+            Ok(Some('o')) => true,
+            _ => {
+                self.message = Some("Save cancelled".to_string());
+                false
             }
-            self.internal_clipboard = text; // 同步到內部剪貼簿
-        } else {
-            // 僅使用內部剪貼簿
-            self.internal_clipboard = text;
-            self.message = Some("Copied (internal clipboard)".to_string());
         }
     }
 
-    /// 獲取剪貼簿內容
-    /// use_system: true 表示優先使用系統剪貼簿，false 表示僅使用內部剪貼簿
-    fn get_clipboard_text(&mut self, use_system: bool) -> String {
-        if use_system {
-            // 嘗試從系統剪貼簿獲取，失敗則使用內部剪貼簿
-            self.clipboard.get_text().unwrap_or_else(|_| {
-                if self.internal_clipboard.is_empty() {
-                    if !self.clipboard.is_available() {
-                        self.message = Some("Nothing to paste (internal clipboard)".to_string());
-                    }
-                    String::new()
-                } else {
-                    self.internal_clipboard.clone()
+    /// 處理開啟時發現的檔案鎖衝突，並為本實例建立鎖
+    ///
+    /// 另一個實例有未存檔修改時詢問是否仍要開啟；否則只顯示提示訊息。
+    /// 仍在執行的實例的鎖不會被覆蓋，本實例這時不持有鎖
+    fn resolve_lock_conflict(&mut self) -> Result<()> {
+        if let Some(info) = self.lock_conflict.take() {
+            if info.modified {
+                let message = format!(
+                    "File is open with unsaved changes in another wedi (pid {} on {}).",
+                    info.pid, info.hostname
+                );
+                let choice = crate::dialog::choose(
+                    &message,
+                    &[('o', "Open anyway"), ('q', "Quit")],
+                    self.terminal.size(),
+                )?;
+                if choice != Some('o') {
+                    self.should_quit = true;
+                    return Ok(());
                 }
-            })
-        } else {
-            // 僅使用內部剪貼簿
-            if self.internal_clipboard.is_empty() {
-                self.message = Some("Nothing to paste (internal clipboard)".to_string());
-                String::new()
             } else {
-                self.internal_clipboard.clone()
+                self.message = Some(format!(
+                    "Also open in another wedi (pid {} on {})",
+                    info.pid, info.hostname
+                ));
             }
         }
-    }
 
-    /// 執行貼上操作
-    fn paste_text(&mut self, text: String) {
-        if text.is_empty() {
-            return;
+        if let Some(path) = self.core.buffer.file_path() {
+            self.file_lock = FileLock::acquire(path).ok();
         }
+        self.check_recovery();
 
-        if self.has_selection() {
-            self.delete_selection();
-        }
+        Ok(())
+    }
 
-        // 檢查是否為整行貼上（文字以換行結尾）
-        let is_whole_line = text.ends_with('\n');
+    /// 根據目前檔案路徑重新檢測註解風格與語法高亮
+    ///
+    /// 沒有副檔名的檔案（或 stdin 輸入）會改用開頭內容推斷（shebang、XML、JSON、YAML、Makefile）
+    fn detect_file_type(&mut self) {
+        let path = self.core.buffer.file_path().map(|p| p.to_path_buf());
+
+        // git 提交訊息與 rebase 待辦清單沒有副檔名，但不做內容推斷，維持預設的 # 註解
+        self.git_message = path.as_deref().is_some_and(crate::filetype::is_git_message);
+        let is_git_file = path.as_deref().is_some_and(crate::filetype::is_git_file);
+
+        let content_ext = if !is_git_file && path.as_ref().is_none_or(|p| p.extension().is_none()) {
+            let head: String = (0..crate::filetype::SNIFF_LINES.min(self.core.buffer.line_count()))
+                .map(|row| self.core.buffer.get_line_content(row))
+                .collect();
+            crate::filetype::detect_extension_from_content(&head)
+        } else {
+            None
+        };
 
-        if is_whole_line {
-            // 整行貼上：在光標所在行的開始處插入
-            let line_start = self.buffer.line_to_char(self.cursor.row);
-            self.buffer.insert(line_start, &text);
-            self.view.invalidate_cache();
+        if let Some(path) = &path {
+            self.core.comment_handler.detect_from_path(path);
+        }
+        if let Some(ext) = content_ext {
+            self.core.comment_handler.detect_from_extension(Some(ext));
+        }
+        if is_git_file {
+            self.git_comment = self.detect_git_comment(path.as_deref());
+            self.core.comment_handler.set_line_prefix(&self.git_comment);
+        }
 
-            // 計算插入了多少行
-            let inserted_lines = text.chars().filter(|&c| c == '\n').count();
+        // 提交訊息依 git 的慣例在 72 欄換行（已設定 textwidth 時不覆寫）
+        if self.git_message && self.core.text_width == 0 {
+            self.core.text_width = crate::filetype::GIT_MESSAGE_WIDTH;
+            self.auto_text_width = true;
+        } else if !self.git_message && self.auto_text_width {
+            self.core.text_width = 0;
+            self.auto_text_width = false;
+        }
+        let path_ext = path
+            .as_deref()
+            .and_then(|p| p.extension())
+            .and_then(|ext| ext.to_str());
+        self.word_index
+            .set_language(path_ext.into_iter().chain(content_ext));
 
-            // 光標移動到被擠下去的原行首
-            self.cursor.row += inserted_lines;
-            self.cursor.col = 0;
-            self.cursor.desired_visual_col = 0;
-        } else {
-            // 普通貼上：在光標位置插入
-            let pos = self.cursor.char_position(&self.buffer);
-            self.buffer.insert(pos, &text);
-            self.view.invalidate_cache();
-            // 移動到貼上內容末尾
-            for ch in text.chars() {
-                if ch == '\n' {
-                    self.cursor.row += 1;
-                    self.cursor.col = 0;
-                } else {
-                    self.cursor.col += 1;
+        #[cfg(feature = "syntax-highlighting")]
+        {
+            if let Some(engine) = self.highlight_engine.as_mut() {
+                engine.set_file(path.as_deref());
+                if let Some(ext) = content_ext {
+                    engine.set_fallback_extension(ext);
                 }
             }
-            self.cursor.desired_visual_col = self.cursor.col;
+            self.highlight_cache.clear();
+        }
+
+        // 手動指定的語言優先於檢測結果（名稱在指定時已檢查過）
+        if let Some(name) = self.filetype.clone() {
+            let _ = self.apply_filetype(&name);
         }
     }
 
-    fn get_selected_text(&self) -> String {
-        if let Some(sel) = self.selection {
-            let (start_row, start_col) = sel.start.min(sel.end);
-            let (end_row, end_col) = sel.start.max(sel.end);
+    /// git 檔案的註解前綴：`core.commentChar`，未設定或為 `auto` 時從 git 寫入的說明文字推斷
+    fn detect_git_comment(&self, path: Option<&Path>) -> String {
+        #[cfg(feature = "git")]
+        if let Some(prefix) = path
+            .and_then(|p| p.parent())
+            .map(|dir| match dir.as_os_str().is_empty() {
+                true => Path::new("."),
+                false => dir,
+            })
+            .and_then(crate::git::comment_char)
+        {
+            return prefix;
+        }
+        #[cfg(not(feature = "git"))]
+        let _ = path;
 
-            let mut text = String::new();
+        crate::filetype::detect_git_comment_char(
+            (0..self.core.buffer.line_count()).map(|row| self.core.buffer.get_line_content(row)),
+        )
+        .unwrap_or('#')
+        .to_string()
+    }
 
-            for row in start_row..=end_row {
-                let line = self.buffer.get_line_content(row);
-                let line = line.trim_end_matches(['\n', '\r']);
+    /// 套用手動指定的語言（語法高亮與註解風格），返回顯示用的語言名稱
+    ///
+    /// 註解風格取自語法對應的副檔名，沒有語法高亮時把名稱當作副檔名
+    fn apply_filetype(&mut self, name: &str) -> Result<String> {
+        #[cfg(feature = "syntax-highlighting")]
+        if let Some(engine) = self.highlight_engine.as_mut() {
+            engine.set_syntax(name)?;
+            let lowercase = name.to_lowercase();
+            let extensions = engine.syntax_extensions();
+            self.core
+                .comment_handler
+                .detect_from_extensions(extensions.iter().copied().chain([lowercase.as_str()]));
+            self.word_index
+                .set_language(extensions.into_iter().chain([lowercase.as_str()]));
+            self.highlight_cache.clear();
+            return Ok(engine.syntax_name().unwrap_or(name).to_string());
+        }
+        let lowercase = name.to_lowercase();
+        self.core
+            .comment_handler
+            .detect_from_extension(Some(&lowercase));
+        self.word_index.set_language([lowercase.as_str()]);
+        Ok(name.to_string())
+    }
 
-                if row == start_row && row == end_row {
-                    // 單行選擇
-                    let chars: Vec<char> = line.chars().collect();
-                    text.push_str(
-                        &chars[start_col..end_col.min(chars.len())]
-                            .iter()
-                            .collect::<String>(),
-                    );
-                } else if row == start_row {
-                    // 第一行
-                    let chars: Vec<char> = line.chars().collect();
-                    text.push_str(&chars[start_col..].iter().collect::<String>());
-                    text.push('\n');
-                } else if row == end_row {
-                    // 最後一行
-                    let chars: Vec<char> = line.chars().collect();
-                    text.push_str(&chars[..end_col.min(chars.len())].iter().collect::<String>());
-                } else {
-                    // 中間行
-                    text.push_str(line);
-                    text.push('\n');
-                }
+    /// `:set filetype=NAME`：指定目前緩衝區的語言；`auto` 或空值恢復自動檢測，沒有值時開啟選單
+    fn set_filetype(&mut self, value: Option<&str>) -> Result<String> {
+        match value {
+            Some("" | "auto") => {
+                self.filetype = None;
+                self.detect_file_type();
+                Ok("Filetype: auto".to_string())
             }
+            Some(name) => {
+                let shown = self.apply_filetype(name)?;
+                self.filetype = Some(name.to_string());
+                Ok(format!("Filetype: {}", shown))
+            }
+            None => self.pick_filetype(),
+        }
+    }
 
-            text
-        } else {
-            String::new()
+    /// 從語法清單選擇語言，移動時即時預覽；取消時恢復原本的設定
+    #[cfg(feature = "syntax-highlighting")]
+    fn pick_filetype(&mut self) -> Result<String> {
+        let mut syntaxes = HighlightEngine::available_syntaxes();
+        syntaxes.sort_by_key(|name| name.to_lowercase());
+        let current = self
+            .highlight_engine
+            .as_ref()
+            .and_then(|engine| engine.syntax_name())
+            .and_then(|name| syntaxes.iter().position(|s| s == name))
+            .unwrap_or(0);
+        let size = self.terminal.size();
+
+        let choice = crate::dialog::pick("Filetype", &syntaxes, current, size, &mut |index| {
+            self.apply_filetype(&syntaxes[index])?;
+            self.redraw_preview()
+        })?;
+
+        match choice {
+            Some(index) => self.set_filetype(Some(&syntaxes[index])),
+            None => {
+                self.detect_file_type();
+                Ok("Filetype: unchanged".to_string())
+            }
         }
     }
 
-    fn delete_selection(&mut self) {
-        if let Some(sel) = self.selection {
-            let (start_row, start_col) = sel.start.min(sel.end);
-            let (end_row, end_col) = sel.start.max(sel.end);
+    #[cfg(not(feature = "syntax-highlighting"))]
+    fn pick_filetype(&mut self) -> Result<String> {
+        bail!("Usage: :set filetype=EXTENSION")
+    }
 
-            let start_pos = self.buffer.line_to_char(start_row) + start_col;
-            let end_pos = self.buffer.line_to_char(end_row) + end_col;
+    /// 存檔前檢查是否有字符無法以存檔編碼表示
+    ///
+    /// 有問題時列出前幾個字符及行號，讓使用者選擇改用 UTF-8、照常存檔或取消。
+    /// 返回 false 表示取消存檔
+    fn confirm_lossless_encoding(&mut self) -> bool {
+        const MAX_REPORTED: usize = 5;
+
+        let problems = self.core.buffer.find_unencodable_chars(MAX_REPORTED);
+        if problems.is_empty() {
+            return true;
+        }
 
-            self.buffer.delete_range(start_pos, end_pos);
-            self.view.invalidate_cache();
+        let listed = problems
+            .iter()
+            .map(|(row, ch)| format!("'{}' (line {})", ch, row + 1))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let message = format!(
+            "{} cannot encode {}{}.",
+            self.core.buffer.save_encoding().name(),
+            listed,
+            if problems.len() >= MAX_REPORTED {
+                ", ..."
+            } else {
+                ""
+            }
+        );
+
+        match crate::dialog::choose(
+            &message,
+            &[
+                ('u', "Save as UTF-8"),
+                ('s', "Save anyway"),
+                ('c', "Cancel"),
+            ],
+            self.terminal.size(),
+        ) {
+            Ok(Some('u')) => {
+                self.core.buffer.set_save_encoding(encoding_rs::UTF_8);
+                true
+            }
+            Ok(Some('s')) => true,
+            _ => {
+                self.message = Some("Save cancelled".to_string());
+                false
+            }
+        }
+    }
 
-            self.cursor
-                .set_position(&self.buffer, &self.view, start_row, start_col);
-            self.selection = None;
+    /// 設置系統剪貼簿內容，失敗時回退到內部剪貼簿（兩者保持同步）
+    fn set_system_clipboard(&mut self, text: String) {
+        if self.clipboard.set_text(&text).is_err() && !self.clipboard.is_available() {
+            self.message = Some("Copied (internal clipboard)".to_string());
         }
+        self.core.set_clipboard(text); // 同步到內部剪貼簿
+    }
+
+    /// 獲取剪貼簿內容：優先使用系統剪貼簿，失敗則使用內部剪貼簿
+    fn get_system_clipboard(&mut self) -> String {
+        self.clipboard.get_text().unwrap_or_else(|_| {
+            if self.core.clipboard.is_empty() {
+                if !self.clipboard.is_available() {
+                    self.message = Some("Nothing to paste (internal clipboard)".to_string());
+                }
+                String::new()
+            } else {
+                self.core.clipboard.clone()
+            }
+        })
     }
 
     fn get_debug_info(&self) -> String {
-        let total_lines = self.buffer.line_count();
-        let screen_rows = self.view.screen_rows;
-        let logical_row = self.cursor.row;
-        let logical_col = self.cursor.col;
-        let visual_line_index = self.cursor.visual_line_index;
+        let total_lines = self.core.buffer.line_count();
+        let screen_rows = self.core.view.screen_rows;
+        let logical_row = self.core.cursor.row;
+        let logical_col = self.core.cursor.col;
+        let visual_line_index = self.core.cursor.visual_line_index;
 
         // 計算可用列寬度
-        let available_width = self.view.get_available_width(&self.buffer);
+        let available_width = self.core.view.get_available_width(&self.core.buffer);
 
         // 計算當前行的視覺列位置和總字符數
         let (
@@ -1261,16 +4650,20 @@ impl Editor {
             line_visual_width,
             total_visual_lines,
             current_visual_line_width,
-        ) = if let Some(line) = self.buffer.line(logical_row) {
+        ) = if let Some(line) = self.core.buffer.line(logical_row) {
             let line_str = line.to_string();
             let line_str = line_str.trim_end_matches(['\n', '\r']);
-            let visual_col = self.view.logical_col_to_visual_col(line_str, logical_col);
+            let visual_col = self
+                .core
+                .view
+                .logical_col_to_visual_col(line_str, logical_col);
             let char_count = line_str.chars().count();
 
             // 計算在當前視覺行內的列位置
             let visual_lines = self
+                .core
                 .view
-                .calculate_visual_lines_for_row(&self.buffer, logical_row);
+                .calculate_visual_lines_for_row(&self.core.buffer, logical_row);
             let total_visual_lines = visual_lines.len();
             let mut accumulated = 0;
             for line in visual_lines
@@ -1303,8 +4696,8 @@ impl Editor {
         };
 
         // 計算選取的邏輯字數和顯示寬度
-        let (selection_char_count, selection_visual_width) = if self.selection.is_some() {
-            let selected_text = self.get_selected_text();
+        let (selection_char_count, selection_visual_width) = if self.core.selection.is_some() {
+            let selected_text = self.core.selected_text();
             let char_count = selected_text.chars().count();
             let visual_width = visual_width(&selected_text);
             (char_count, visual_width)
@@ -1337,12 +4730,18 @@ impl Editor {
     /// - 大檔案跳轉首頁：從第 0 行開始
     /// - 大檔案跳轉尾頁：只處理可見區域（犧牲少量正確性換取性能）
     /// - 大檔案中間位置：從 start_row - BUFFER 開始
+    ///
+    /// 內容與開始狀態都與快取相符的行直接沿用快取，不需重新解析；
+    /// 因此單字元編輯後只會重新高亮修改的行，以及語法狀態因此改變的後續行
+    ///
+    /// 重新高亮只在時間預算內進行，來不及的行（以及可見區域之後預先準備的行）
+    /// 交給背景執行緒，完成前以純文字顯示，捲動大檔案時不會卡頓
     #[cfg(feature = "syntax-highlighting")]
     pub fn get_highlighted_lines(
         &mut self,
         start_row: usize,
         end_row: usize,
-    ) -> std::collections::HashMap<usize, String> {
+    ) -> std::collections::HashMap<usize, StyledLine> {
         use crate::highlight::CachedLine;
 
         let mut result = std::collections::HashMap::new();
@@ -1361,8 +4760,10 @@ impl Editor {
         const BUFFER_LINES: usize = 100; // 緩衝範圍
         const SMALL_FILE_THRESHOLD: usize = 500; // 小檔案閾值
         const LARGE_FILE_JUMP_THRESHOLD: usize = 1000; // 大檔案跳轉閾值
+        const LOOKAHEAD_LINES: usize = 200; // 背景預先高亮可見區域之後的行數
+        const HIGHLIGHT_BUDGET: std::time::Duration = std::time::Duration::from_millis(4); // 每次重繪的高亮時間
 
-        let total_lines = self.buffer.line_count();
+        let total_lines = self.core.buffer.line_count();
         let is_small_file = total_lines <= SMALL_FILE_THRESHOLD;
         let is_near_start = start_row < BUFFER_LINES;
 
@@ -1381,9 +4782,12 @@ impl Editor {
             start_row.saturating_sub(BUFFER_LINES) // 大檔案中間位置，從緩衝區開始
         };
 
+        let last_row = end_row.min(total_lines.saturating_sub(1));
+        let deadline = Instant::now() + HIGHLIGHT_BUDGET;
+
         // 循序處理（維護跨行狀態）
-        for row in process_start..=end_row.min(total_lines.saturating_sub(1)) {
-            let line_text = match self.buffer.line(row) {
+        for row in process_start..=last_row {
+            let line_text = match self.core.buffer.line(row) {
                 Some(line) => {
                     // syntect 需要換行符才能正確解析語法狀態
                     let mut text = line.to_string();
@@ -1395,40 +4799,96 @@ impl Editor {
                 None => continue,
             };
 
-            // 檢查快取
-            if self.highlight_cache.is_valid(row, &line_text) {
+            // 內容與開始狀態相符時沿用快取，並以快取的結束狀態接續下一行
+            if let Some(cached) =
+                self.highlight_cache
+                    .get_valid(row, &line_text, highlighter.state())
+            {
                 if row >= start_row {
-                    // 在可見區域內，使用快取
-                    if let Some(cached) = self.highlight_cache.get(row) {
-                        result.insert(row, cached.highlighted.clone());
-                    }
+                    result.insert(row, cached.highlighted.clone());
                 }
-                // 即使不在可見區域，也要處理這一行以維護狀態
-                let _ = highlighter.highlight_line(&line_text);
-            } else {
-                // 快取失效，重新高亮
-                // 注意：engine.rs 已在 token 層級處理換行符，此處無需 trim
-                let highlighted = highlighter.highlight_line(&line_text);
+                let end_state = cached.end_state.clone();
+                highlighter.set_state(end_state);
+                continue;
+            }
 
-                // 更新快取
-                self.highlight_cache.insert(
+            // 超出時間預算：從這一行起交給背景執行緒，其餘的行先以純文字顯示
+            if Instant::now() >= deadline {
+                self.highlight_worker.request(
+                    self.highlight_cache.generation(),
+                    self.core.buffer.version(),
+                    self.core.buffer.rope_snapshot(),
                     row,
-                    CachedLine {
-                        text: line_text,
-                        highlighted: highlighted.clone(),
-                    },
+                    end_row + LOOKAHEAD_LINES,
+                    highlighter,
                 );
+                return result;
+            }
 
-                // 如果在可見區域，加入結果
-                if row >= start_row {
-                    result.insert(row, highlighted);
-                }
+            // 快取失效，重新高亮
+            // 注意：engine.rs 已在 token 層級處理換行符，此處無需 trim
+            let start_state = highlighter.state().clone();
+            let highlighted = highlighter.highlight_line(&line_text);
+
+            // 更新快取
+            self.highlight_cache.insert(
+                row,
+                CachedLine {
+                    text: line_text,
+                    highlighted: highlighted.clone(),
+                    start_state,
+                    end_state: highlighter.state().clone(),
+                },
+            );
+
+            // 如果在可見區域，加入結果
+            if row >= start_row {
+                result.insert(row, highlighted);
+            }
+        }
+
+        // 在背景預先高亮可見區域之後的行，向下捲動時可直接使用快取
+        let next_row = last_row + 1;
+        if let Some(line) = self.core.buffer.line(next_row) {
+            let mut text = line.to_string();
+            if !text.ends_with('\n') {
+                text.push('\n');
+            }
+            if self
+                .highlight_cache
+                .get_valid(next_row, &text, highlighter.state())
+                .is_none()
+            {
+                self.highlight_worker.request(
+                    self.highlight_cache.generation(),
+                    self.core.buffer.version(),
+                    self.core.buffer.rope_snapshot(),
+                    next_row,
+                    end_row + LOOKAHEAD_LINES,
+                    highlighter,
+                );
             }
         }
 
         result
     }
 
+    /// 將背景高亮完成的行寫入快取，返回是否需要重繪
+    #[cfg(feature = "syntax-highlighting")]
+    fn poll_highlight_worker(&mut self) -> bool {
+        let mut updated = false;
+        for batch in self.highlight_worker.poll() {
+            // 主題或語法已改變的結果直接丟棄
+            if batch.generation != self.highlight_cache.generation() {
+                continue;
+            }
+            for (row, cached) in batch.lines {
+                self.highlight_cache.insert(row, cached);
+                updated = true;
+            }
+        }
+        updated && self.highlight_enabled
+    }
 
     /// 使語法高亮快取失效（編輯操作後調用）
     #[cfg(feature = "syntax-highlighting")]
@@ -1452,3 +4912,105 @@ impl Editor {
         }
     }
 }
+
+/// diff 每行的顏色：新增為綠色、刪除為紅色、區塊標頭為青色
+fn diff_line_color(line: &str) -> Option<crossterm::style::Color> {
+    use crossterm::style::Color;
+
+    if line.starts_with("+++") || line.starts_with("---") {
+        Some(Color::White)
+    } else if line.starts_with('+') {
+        Some(Color::Green)
+    } else if line.starts_with('-') {
+        Some(Color::Red)
+    } else if line.starts_with("@@") {
+        Some(Color::Cyan)
+    } else {
+        None
+    }
+}
+
+// `:!` 使用的系統 shell 與傳入命令的參數
+#[cfg(windows)]
+const SHELL: (&str, &str) = ("cmd", "/C");
+#[cfg(not(windows))]
+const SHELL: (&str, &str) = ("sh", "-c");
+
+/// 收到終止訊號後等待主迴圈結束的時間
+const TERMINATE_GRACE: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// 啟動等待終止訊號的執行緒
+///
+/// 終端掛斷（SIGHUP）後 crossterm 會不斷重試讀取終端，主迴圈無法醒來處理訊號；
+/// 主迴圈沒有在時限內開始結束時，由這個執行緒把緊急副本寫成復原檔後結束程序
+fn spawn_termination_watchdog() {
+    use std::io::Write;
+
+    std::thread::spawn(|| {
+        let Some(signal) = Terminal::wait_for_termination() else {
+            return;
+        };
+        std::thread::sleep(TERMINATE_GRACE);
+        let Some(copy) = crate::autosave::claim_emergency_copy() else {
+            // 主迴圈已在處理
+            return;
+        };
+        let recovery = copy.and_then(|(path, rope)| {
+            crate::autosave::write_emergency_copy(&path, &rope.to_string())
+        });
+        let _ = Terminal::exit_raw_mode();
+        let _ = Terminal::show_cursor();
+        if let Some(message) =
+            crate::autosave::termination_message(signal, recovery.as_deref(), false)
+        {
+            // 終端已掛斷時 stderr 無法寫入，eprintln! 會 panic
+            let _ = writeln!(std::io::stderr(), "{}", message);
+        }
+        std::process::exit(128 + signal);
+    });
+}
+
+/// 以系統 shell 執行命令
+fn shell_command(command: &str) -> std::process::Command {
+    let (shell, flag) = SHELL;
+    let mut process = std::process::Command::new(shell);
+    process.args([flag, command]);
+    process
+}
+
+/// 在前景以系統 shell 執行命令並等待結束；期間 Ctrl+C 只中斷命令，不會結束編輯器
+fn run_in_foreground(command: &str) -> std::io::Result<std::process::ExitStatus> {
+    let _shield = Terminal::shield_interrupts();
+    shell_command(command).status()
+}
+
+/// 以系統 shell 執行命令並擷取標準輸出與標準錯誤；`input` 寫到命令的標準輸入，
+/// None 時標準輸入為空，避免命令等待終端輸入
+fn capture_command(command: &str, input: Option<&str>) -> Result<std::process::Output> {
+    use std::io::Write;
+    use std::process::Stdio;
+
+    let mut child = shell_command(command)
+        .stdin(if input.is_some() {
+            Stdio::piped()
+        } else {
+            Stdio::null()
+        })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Cannot run {}", command))?;
+    // 另一個執行緒寫入，避免輸出填滿管線時雙方互相等待
+    let writer = input.zip(child.stdin.take()).map(|(input, mut stdin)| {
+        let input = input.to_string();
+        std::thread::spawn(move || stdin.write_all(input.as_bytes()))
+    });
+    let output = child
+        .wait_with_output()
+        .with_context(|| format!("Cannot run {}", command))?;
+    if let Some(writer) = writer {
+        // 命令沒有讀完輸入就結束（例如 head）不算錯誤
+        let _ = writer.join();
+    }
+    Ok(output)
+}
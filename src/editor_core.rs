@@ -0,0 +1,1758 @@
+// 無畫面的編輯核心
+//
+// 緩衝區、光標、選擇範圍與復原記錄，不依賴終端。
+// 終端編輯器把編輯與移動命令交給這裡處理；其他工具與整合測試也可以直接以 Command 驅動編輯邏輯
+
+use crate::backend::TerminalBackend;
+use crate::buffer::{describe, describe_lines, RopeBuffer};
+use crate::comment::CommentHandler;
+use crate::cursor::Cursor;
+use crate::input::{handle_key_event, Command, Direction};
+use crate::utils::{next_grapheme_boundary, prev_grapheme_boundary, visual_width};
+use crate::view::{Selection, View};
+use anyhow::Result;
+use crossterm::event::KeyEvent;
+use std::path::Path;
+
+/// 拖曳選擇自動捲動時每一步最多捲動的行數
+const MAX_AUTO_SCROLL: isize = 3;
+
+/// 未指定大小時使用的終端大小（欄, 列）
+const DEFAULT_SIZE: (u16, u16) = (80, 24);
+
+/// 無畫面的編輯器：以 [`EditorCore::apply`] 套用編輯、移動與選擇命令
+///
+/// ```
+/// use wedi::{Command, EditorCore, RopeBuffer};
+///
+/// let mut core = EditorCore::new(RopeBuffer::new());
+/// core.apply(Command::Insert('h'));
+/// core.apply(Command::Insert('i'));
+/// assert_eq!(core.text(), "hi");
+/// core.apply(Command::Undo);
+/// assert_eq!(core.text(), "h");
+/// ```
+pub struct EditorCore {
+    pub(crate) buffer: RopeBuffer,
+    pub(crate) cursor: Cursor,
+    pub(crate) view: View, // 排版（自動換行）資訊，上下移動與翻頁以視覺行為單位
+    pub(crate) selection: Option<Selection>,
+    pub(crate) selection_mode: bool, // Ctrl+S 選擇模式開關
+    pub(crate) clipboard: String,    // 內部剪貼簿
+    pub(crate) comment_handler: CommentHandler,
+    pub(crate) ruler: bool, // 頂部是否顯示欄位尺規（可用行數少一行，影響翻頁距離）
+    pub(crate) text_width: usize, // 輸入超過此欄位時在前一個空白處自動斷行（0 表示關閉）
+    pub(crate) paste_indent: bool, // 貼上多行內容時依光標處的縮排調整每一行
+    drag_anchor: Option<(usize, usize)>, // 滑鼠按下的位置，拖曳時作為選擇範圍的起點
+    message: Option<String>, // 上一個命令產生的狀態訊息
+}
+
+impl EditorCore {
+    /// 以預設大小（80x24）建立編輯核心
+    #[allow(dead_code)]
+    pub fn new(buffer: RopeBuffer) -> Self {
+        Self::with_size(buffer, DEFAULT_SIZE.0, DEFAULT_SIZE.1)
+    }
+
+    /// 以指定的終端大小（包含狀態列）建立編輯核心，影響自動換行與翻頁
+    pub fn with_size(buffer: RopeBuffer, cols: u16, rows: u16) -> Self {
+        Self::with_view(buffer, View::with_size(cols, rows))
+    }
+
+    /// 以建構器設定大小、自動換行與註解等選項
+    #[allow(dead_code)]
+    pub fn builder() -> EditorCoreBuilder {
+        EditorCoreBuilder::default()
+    }
+
+    pub(crate) fn with_view(buffer: RopeBuffer, view: View) -> Self {
+        let mut comment_handler = CommentHandler::new();
+        if let Some(path) = buffer.file_path() {
+            comment_handler.detect_from_path(path);
+        }
+
+        Self {
+            buffer,
+            cursor: Cursor::new(),
+            view,
+            selection: None,
+            selection_mode: false,
+            clipboard: String::new(),
+            comment_handler,
+            ruler: false,
+            text_width: 0,
+            paste_indent: false,
+            drag_anchor: None,
+            message: None,
+        }
+    }
+
+    /// 套用命令，返回 false 表示命令需要終端或檔案操作（存檔、搜尋對話框等），未處理
+    pub fn apply(&mut self, command: Command) -> bool {
+        match command {
+            // 字符輸入
+            Command::Insert(ch) => {
+                if self.has_selection() {
+                    self.delete_selection();
+                }
+
+                let pos = self.cursor.char_position(&self.buffer);
+                self.buffer.insert_char(pos, ch);
+
+                // 優化：僅失效當前行（除非是換行符，需要重建整個緩存）
+                if ch == '\n' {
+                    self.view.invalidate_cache(); // 換行影響多行佈局
+                    self.cursor.row += 1;
+                    self.cursor.reset_to_line_start();
+                } else {
+                    self.view.invalidate_line(self.cursor.row); // 僅失效當前行
+                    self.cursor.set_position(
+                        &self.buffer,
+                        &self.view,
+                        self.cursor.row,
+                        self.cursor.col + 1,
+                    );
+                }
+
+                self.selection = None;
+                self.selection_mode = false; // 輸入後關閉選擇模式
+
+                // 與 vi 的 textwidth 相同，只在輸入非空白字元時斷行
+                if !ch.is_whitespace() {
+                    while self.hard_wrap_line() {}
+                }
+            }
+
+            // 刪除操作
+            Command::Backspace => {
+                if self.has_selection() {
+                    self.delete_selection();
+                } else if self.cursor.col > 0 {
+                    // 行內刪除：刪除整個字素叢集（emoji 序列、組合字元）
+                    let line = self.buffer.get_line_content(self.cursor.row);
+                    let new_col = prev_grapheme_boundary(&line, self.cursor.col);
+                    let line_start = self.buffer.line_to_char(self.cursor.row);
+                    self.buffer
+                        .delete_range(line_start + new_col, line_start + self.cursor.col);
+                    self.view.invalidate_line(self.cursor.row); // 僅失效當前行
+                    self.cursor
+                        .set_position(&self.buffer, &self.view, self.cursor.row, new_col);
+                } else if self.cursor.row > 0 {
+                    // 刪除換行符，合併到上一行
+                    let new_row = self.cursor.row - 1;
+                    let prev_line_len = self.line_len(new_row);
+
+                    let pos = self.buffer.line_to_char(new_row) + prev_line_len;
+                    self.buffer.delete_char(pos);
+                    self.view.invalidate_cache(); // 行合併影響多行
+
+                    self.cursor
+                        .set_position(&self.buffer, &self.view, new_row, prev_line_len);
+                }
+                self.selection_mode = false; // 刪除後關閉選擇模式
+            }
+
+            Command::Delete => {
+                if self.has_selection() {
+                    self.delete_selection();
+                } else {
+                    let pos = self.cursor.char_position(&self.buffer);
+                    let at_line_end = self.cursor.col >= self.line_len(self.cursor.row);
+
+                    if at_line_end {
+                        self.buffer.delete_char(pos);
+                    } else {
+                        // 刪除整個字素叢集
+                        let line = self.buffer.get_line_content(self.cursor.row);
+                        let end = next_grapheme_boundary(&line, self.cursor.col);
+                        self.buffer.delete_range(pos, pos + end - self.cursor.col);
+                    }
+
+                    // 優化：如果在行尾刪除（會合併下一行），需要完全失效；否則僅失效當前行
+                    if at_line_end {
+                        self.view.invalidate_cache(); // 行合併影響多行
+                    } else {
+                        self.view.invalidate_line(self.cursor.row); // 僅失效當前行
+                    }
+                }
+                self.selection_mode = false; // 刪除後關閉選擇模式
+            }
+
+            Command::DeleteLine => {
+                if self.has_selection() {
+                    self.delete_selection();
+                } else {
+                    self.delete_current_line();
+                }
+                self.selection_mode = false; // 刪除後關閉選擇模式
+            }
+
+            // 光標移動
+            Command::MoveUp => self.move_cursor(Direction::Up),
+            Command::MoveDown => self.move_cursor(Direction::Down),
+            Command::MoveLeft => self.move_cursor(Direction::Left),
+            Command::MoveRight => self.move_cursor(Direction::Right),
+            Command::MoveHome => self.move_cursor(Direction::Home),
+            Command::MoveEnd => self.move_cursor(Direction::End),
+            Command::PageUp => self.move_cursor(Direction::PageUp),
+            Command::PageDown => self.move_cursor(Direction::PageDown),
+            Command::MoveToFileStart => self.move_cursor(Direction::FileStart),
+            Command::MoveParagraphUp => self.move_cursor(Direction::ParagraphUp),
+            Command::MoveParagraphDown => self.move_cursor(Direction::ParagraphDown),
+            Command::MoveBlockStart => self.move_cursor(Direction::BlockStart),
+            Command::MoveBlockEnd => self.move_cursor(Direction::BlockEnd),
+            Command::MoveToFileEnd => self.move_cursor(Direction::FileEnd),
+            Command::JumpTenthUp => self.move_cursor(Direction::TenthUp),
+            Command::JumpTenthDown => self.move_cursor(Direction::TenthDown),
+            Command::ScrollLineUp => self.scroll_view(-1),
+            Command::ScrollLineDown => self.scroll_view(1),
+            Command::ScrollHalfPageUp => self.scroll_view(-self.half_page()),
+            Command::ScrollHalfPageDown => self.scroll_view(self.half_page()),
+            Command::CenterCursor => {
+                let effective_rows = self.view.get_effective_screen_rows(self.ruler);
+                self.view
+                    .center_on_row(self.cursor.row, &self.buffer, effective_rows);
+            }
+
+            // 選擇操作
+            Command::ExtendSelection(direction) => {
+                if self.selection.is_none() {
+                    self.selection = Some(Selection {
+                        start: (self.cursor.row, self.cursor.col),
+                        end: (self.cursor.row, self.cursor.col),
+                    });
+                }
+
+                self.step_cursor(direction);
+
+                if let Some(sel) = &mut self.selection {
+                    sel.end = (self.cursor.row, self.cursor.col);
+                }
+            }
+
+            Command::SelectAll => {
+                let last_line = self.buffer.line_count().saturating_sub(1);
+                let last_col = self.line_len(last_line);
+
+                self.selection = Some(Selection {
+                    start: (0, 0),
+                    end: (last_line, last_col),
+                });
+                self.cursor.row = last_line;
+                self.cursor.col = last_col;
+            }
+
+            Command::ClearSelection => {
+                self.selection = None;
+            }
+
+            // 選擇模式切換
+            Command::ToggleSelectionMode => {
+                self.selection_mode = !self.selection_mode;
+
+                // 開啟選擇模式時，如果沒有選擇範圍，初始化選擇
+                if self.selection_mode && self.selection.is_none() {
+                    self.selection = Some(Selection {
+                        start: (self.cursor.row, self.cursor.col),
+                        end: (self.cursor.row, self.cursor.col),
+                    });
+                }
+
+                self.message = Some(format!(
+                    "Selection Mode: {}",
+                    if self.selection_mode { "ON" } else { "OFF" }
+                ));
+            }
+
+            // 內部剪貼板操作（僅使用內部剪貼簿）
+            Command::CopyInternal => {
+                self.clipboard = self.copy_text();
+                self.message = Some("Copied (internal clipboard)".to_string());
+                self.selection_mode = false; // 複製後關閉選擇模式
+                self.selection = None; // 複製後清除選擇範圍
+            }
+
+            Command::CutInternal => {
+                self.clipboard = self.copy_text();
+                self.message = Some("Copied (internal clipboard)".to_string());
+                self.cut();
+            }
+
+            Command::PasteInternal => {
+                if self.clipboard.is_empty() {
+                    self.message = Some("Nothing to paste (internal clipboard)".to_string());
+                } else {
+                    let text = self.clipboard.clone();
+                    self.paste_text(&text);
+                }
+                self.selection_mode = false; // 貼上後關閉選擇模式
+            }
+
+            Command::PasteInternalBelow => {
+                if self.clipboard.is_empty() {
+                    self.message = Some("Nothing to paste (internal clipboard)".to_string());
+                } else {
+                    let text = self.clipboard.clone();
+                    self.paste_below(&text);
+                }
+                self.selection_mode = false;
+            }
+
+            // 撤銷/重做
+            Command::Undo => match self.buffer.undo() {
+                Some(pos) => {
+                    self.move_to_char(pos);
+                    self.message = Some(match self.buffer.last_undone_description() {
+                        Some(description) => format!("Undo: {}", description),
+                        None => "Undo".to_string(),
+                    });
+                }
+                None => self.message = Some("Nothing to undo".to_string()),
+            },
+
+            Command::Redo => match self.buffer.redo() {
+                Some(pos) => {
+                    self.move_to_char(pos);
+                    self.message = Some(match self.buffer.last_done_description() {
+                        Some(description) => format!("Redo: {}", description),
+                        None => "Redo".to_string(),
+                    });
+                }
+                None => self.message = Some("Nothing to redo".to_string()),
+            },
+
+            // 註解切換
+            Command::ToggleComment => self.toggle_comment(),
+
+            // 縮排（Tab 鍵）
+            Command::Indent => {
+                if let Some(sel) = self.selection {
+                    // 多行選擇：對每行添加 4 個空格
+                    let (start_row, _) = sel.start.min(sel.end);
+                    let (end_row, _) = sel.start.max(sel.end);
+
+                    rewrite_lines(&mut self.buffer, start_row, end_row, |line| {
+                        Some(format!("    {}", line))
+                    });
+                    self.buffer
+                        .describe_last_edit(describe_lines("indent", end_row - start_row + 1));
+                    self.view.invalidate_cache();
+
+                    // 保留選擇狀態
+                    self.cursor.row = start_row;
+                    self.cursor.col = 0;
+                    self.cursor.desired_visual_col = 0;
+                } else {
+                    // 單行：在光標位置插入 4 個空格
+                    let pos = self.cursor.char_position(&self.buffer);
+                    self.buffer.insert(pos, "    ");
+                    self.buffer.describe_last_edit("indent");
+                    self.view.invalidate_cache();
+                    self.cursor.col += 4;
+                    self.cursor.desired_visual_col = self.cursor.col;
+                }
+            }
+
+            // 退位（Shift+Tab 鍵）
+            Command::Unindent => {
+                if let Some(sel) = self.selection {
+                    // 多行選擇：對每行刪除最多 4 個前導空格
+                    let (start_row, _) = sel.start.min(sel.end);
+                    let (end_row, _) = sel.start.max(sel.end);
+
+                    let changed = rewrite_lines(&mut self.buffer, start_row, end_row, |line| {
+                        let spaces_to_remove =
+                            line.chars().take_while(|&c| c == ' ').take(4).count();
+                        Some(line[spaces_to_remove..].to_string())
+                    });
+                    self.buffer
+                        .describe_last_edit(describe_lines("unindent", changed));
+                    self.view.invalidate_cache();
+
+                    // 保留選擇狀態
+                    self.cursor.row = start_row;
+                    self.cursor.col = 0;
+                    self.cursor.desired_visual_col = 0;
+                } else {
+                    // 單行：刪除光標前最多 4 個空格
+                    let line_content = self.buffer.get_line_content(self.cursor.row);
+                    let before_cursor: String =
+                        line_content.chars().take(self.cursor.col).collect();
+                    let spaces_to_remove = before_cursor
+                        .chars()
+                        .rev()
+                        .take_while(|&c| c == ' ')
+                        .take(4)
+                        .count();
+
+                    if spaces_to_remove > 0 {
+                        let line_start = self.buffer.line_to_char(self.cursor.row);
+                        let delete_start = line_start + self.cursor.col - spaces_to_remove;
+                        self.buffer
+                            .delete_range(delete_start, delete_start + spaces_to_remove);
+                        self.buffer.describe_last_edit("unindent");
+                        self.view.invalidate_cache();
+                        self.cursor.col -= spaces_to_remove;
+                        self.cursor.desired_visual_col = self.cursor.col;
+                    }
+                }
+            }
+
+            // 重複執行；撤銷與重做以外的修改合併為一個撤銷步驟
+            Command::WithCount(count, command) => {
+                if !command.is_repeatable() {
+                    return self.apply(*command);
+                }
+                let batch = !matches!(*command, Command::Undo | Command::Redo);
+                if batch {
+                    self.buffer.begin_batch();
+                }
+                let mut handled = true;
+                for _ in 0..count {
+                    if !self.apply((*command).clone()) {
+                        handled = false;
+                        break;
+                    }
+                }
+                if batch {
+                    self.buffer.end_batch();
+                }
+                return handled;
+            }
+
+            // 需要終端、系統剪貼簿或檔案的命令
+            _ => return false,
+        }
+
+        true
+    }
+
+    /// 緩衝區
+    #[allow(dead_code)]
+    pub fn buffer(&self) -> &RopeBuffer {
+        &self.buffer
+    }
+
+    /// 緩衝區（可修改；直接修改後光標可能超出範圍，需自行調整）
+    #[allow(dead_code)]
+    pub fn buffer_mut(&mut self) -> &mut RopeBuffer {
+        &mut self.buffer
+    }
+
+    /// 緩衝區的完整內容
+    #[allow(dead_code)]
+    pub fn text(&self) -> String {
+        self.buffer.text()
+    }
+
+    /// 光標位置
+    #[allow(dead_code)]
+    pub fn cursor(&self) -> &Cursor {
+        &self.cursor
+    }
+
+    /// 目前的選擇範圍
+    #[allow(dead_code)]
+    pub fn selection(&self) -> Option<Selection> {
+        self.selection
+    }
+
+    /// 是否處於選擇模式（Ctrl+S）
+    #[allow(dead_code)]
+    pub fn is_selection_mode(&self) -> bool {
+        self.selection_mode
+    }
+
+    /// 內部剪貼簿的內容
+    #[allow(dead_code)]
+    pub fn clipboard(&self) -> &str {
+        &self.clipboard
+    }
+
+    /// 設定內部剪貼簿的內容（例如同步系統剪貼簿）
+    pub fn set_clipboard(&mut self, text: String) {
+        self.clipboard = text;
+    }
+
+    /// 取得並清除上一個命令產生的狀態訊息
+    pub fn take_message(&mut self) -> Option<String> {
+        self.message.take()
+    }
+
+    /// 變更終端大小（包含狀態列）
+    #[allow(dead_code)]
+    pub fn resize(&mut self, cols: u16, rows: u16) {
+        self.view.resize(cols, rows);
+    }
+
+    /// 以終端編輯器的按鍵配置套用按鍵，返回是否已處理
+    #[allow(dead_code)]
+    pub fn handle_key(&mut self, key: KeyEvent) -> bool {
+        match handle_key_event(key, self.selection_mode) {
+            Some(command) => self.apply(command),
+            None => false,
+        }
+    }
+
+    /// 將目前的畫面渲染到終端後端（例如測試用的 [`crate::MemoryTerminal`]），大小不同時先調整
+    #[allow(dead_code)]
+    pub fn render(&mut self, backend: &mut impl TerminalBackend) -> Result<()> {
+        let (cols, rows) = backend.size();
+        if (self.view.screen_cols, self.view.screen_rows)
+            != (cols as usize, rows.saturating_sub(1) as usize)
+        {
+            self.view.resize(cols, rows);
+        }
+        self.view.render(
+            backend,
+            &self.buffer,
+            &self.cursor,
+            self.selection.as_ref(),
+            self.message.as_deref(),
+            #[cfg(feature = "syntax-highlighting")]
+            None,
+        )
+    }
+
+    /// 另存新檔；副檔名改變時依新路徑重新檢測註解風格
+    ///
+    /// 返回副檔名是否改變，呼叫端據此更新語法高亮等其他依檔案類型決定的設定
+    pub fn save_as(&mut self, path: &Path) -> Result<bool> {
+        let old_ext = self
+            .buffer
+            .file_path()
+            .and_then(|p| p.extension())
+            .map(|e| e.to_os_string());
+        self.buffer.save_as(path)?;
+        let changed = path.extension() != old_ext.as_deref();
+        if changed {
+            self.comment_handler.detect_from_path(path);
+        }
+        Ok(changed)
+    }
+
+    /// 跳到指定行（1-based），行號超出範圍時返回 false
+    pub fn go_to_line(&mut self, line_num: usize) -> bool {
+        if line_num == 0 || line_num > self.buffer.line_count() {
+            return false;
+        }
+        self.cursor.row = line_num - 1;
+        self.cursor.col = 0;
+        self.cursor.desired_visual_col = 0;
+        true
+    }
+
+    pub(crate) fn has_selection(&self) -> bool {
+        self.selection.is_some()
+    }
+
+    /// 獲取要複製/剪切的文本
+    /// 如果有選擇範圍，返回選擇的文本；否則返回當前整行（帶換行符）
+    pub fn copy_text(&self) -> String {
+        if self.has_selection() {
+            self.selected_text()
+        } else {
+            // 複製當前整行（完整內容，包括尾部空格和換行符）
+            let line_text = self.buffer.get_line_full(self.cursor.row);
+            // 確保以換行符結尾（用於識別整行貼上）
+            if line_text.ends_with('\n') {
+                line_text
+            } else {
+                format!("{}\n", line_text)
+            }
+        }
+    }
+
+    /// 選擇範圍的文本（沒有選擇時為空字串）
+    pub fn selected_text(&self) -> String {
+        if let Some(sel) = self.selection {
+            let (start_row, start_col) = sel.start.min(sel.end);
+            let (end_row, end_col) = sel.start.max(sel.end);
+
+            let mut text = String::new();
+
+            for row in start_row..=end_row {
+                let line = self.buffer.get_line_content(row);
+                let line = line.trim_end_matches(['\n', '\r']);
+
+                if row == start_row && row == end_row {
+                    // 單行選擇
+                    let chars: Vec<char> = line.chars().collect();
+                    text.push_str(
+                        &chars[start_col..end_col.min(chars.len())]
+                            .iter()
+                            .collect::<String>(),
+                    );
+                } else if row == start_row {
+                    // 第一行
+                    let chars: Vec<char> = line.chars().collect();
+                    text.push_str(&chars[start_col..].iter().collect::<String>());
+                    text.push('\n');
+                } else if row == end_row {
+                    // 最後一行
+                    let chars: Vec<char> = line.chars().collect();
+                    text.push_str(&chars[..end_col.min(chars.len())].iter().collect::<String>());
+                } else {
+                    // 中間行
+                    text.push_str(line);
+                    text.push('\n');
+                }
+            }
+
+            text
+        } else {
+            String::new()
+        }
+    }
+
+    /// 剪下：刪除選擇範圍，沒有選擇時刪除當前整行
+    pub(crate) fn cut(&mut self) {
+        let text = self.copy_text();
+        if self.has_selection() {
+            self.delete_selection();
+        } else {
+            self.delete_current_line();
+        }
+        self.buffer.describe_last_edit(describe("cut", &text));
+        // 剪切後關閉選擇模式
+        self.selection_mode = false;
+    }
+
+    /// 在光標位置貼上文字（取代選擇範圍）
+    ///
+    /// 以換行結尾的文字視為整行，插入在當前行之前
+    pub fn paste_text(&mut self, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+
+        if self.has_selection() {
+            self.delete_selection();
+        }
+
+        // 檢查是否為整行貼上（文字以換行結尾）
+        let is_whole_line = text.ends_with('\n');
+        let reindented;
+        let text = match self.paste_indent_target(is_whole_line, text) {
+            Some((indent, strip_first)) => {
+                reindented = reindent_block(text, &indent, strip_first);
+                reindented.as_str()
+            }
+            None => text,
+        };
+
+        if is_whole_line {
+            // 整行貼上：在光標所在行的開始處插入
+            let line_start = self.buffer.line_to_char(self.cursor.row);
+            self.buffer.insert(line_start, text);
+            self.buffer.describe_last_edit(describe("paste", text));
+            self.view.invalidate_cache();
+
+            // 計算插入了多少行
+            let inserted_lines = text.chars().filter(|&c| c == '\n').count();
+
+            // 光標移動到被擠下去的原行首
+            self.cursor.row += inserted_lines;
+            self.cursor.col = 0;
+            self.cursor.desired_visual_col = 0;
+        } else {
+            // 普通貼上：在光標位置插入
+            let pos = self.cursor.char_position(&self.buffer);
+            self.buffer.insert(pos, text);
+            self.buffer.describe_last_edit(describe("paste", text));
+            self.view.invalidate_cache();
+            // 移動到貼上內容末尾
+            for ch in text.chars() {
+                if ch == '\n' {
+                    self.cursor.row += 1;
+                    self.cursor.col = 0;
+                } else {
+                    self.cursor.col += 1;
+                }
+            }
+            self.cursor.desired_visual_col = self.cursor.col;
+        }
+    }
+
+    /// 把整行的文字貼在光標所在行之後，光標停在貼上的第一行
+    ///
+    /// 光標在最後一行時後面沒有行首可以插入，改在檔案結尾補上換行再貼上（不多留結尾的換行）
+    pub fn paste_below(&mut self, text: &str) {
+        let below = self.cursor.row + 1;
+        if below < self.buffer.line_count() {
+            self.cursor.row = below;
+            self.cursor.col = 0;
+            self.paste_text(text);
+        } else {
+            let pasted = format!("\n{}", text.strip_suffix('\n').unwrap_or(text));
+            self.buffer.insert(self.buffer.len_chars(), &pasted);
+            self.buffer.describe_last_edit(describe("paste", text));
+            self.view.invalidate_cache();
+        }
+        self.cursor.row = below;
+        self.cursor.col = 0;
+        self.cursor.desired_visual_col = 0;
+    }
+
+    pub(crate) fn delete_selection(&mut self) {
+        if let Some(sel) = self.selection {
+            let (start_row, start_col) = sel.start.min(sel.end);
+            let (end_row, end_col) = sel.start.max(sel.end);
+
+            let start_pos = self.buffer.line_to_char(start_row) + start_col;
+            let end_pos = self.buffer.line_to_char(end_row) + end_col;
+
+            self.buffer.delete_range(start_pos, end_pos);
+            self.view.invalidate_cache();
+
+            self.cursor
+                .set_position(&self.buffer, &self.view, start_row, start_col);
+            self.selection = None;
+        }
+    }
+
+    /// 刪除光標所在的整行，光標移到行首
+    fn delete_current_line(&mut self) {
+        // 記錄是否在最後一行
+        let was_last_line = self.cursor.row == self.buffer.line_count() - 1;
+
+        self.buffer.delete_line(self.cursor.row);
+        self.view.invalidate_cache();
+
+        // 如果刪除的是最後一行且不是唯一一行，光標上移
+        if was_last_line && self.cursor.row > 0 {
+            self.cursor.row -= 1;
+        }
+
+        // 確保光標在有效範圍內
+        if self.cursor.row >= self.buffer.line_count() && self.buffer.line_count() > 0 {
+            self.cursor.row = self.buffer.line_count() - 1;
+        }
+
+        self.cursor.reset_to_line_start();
+    }
+
+    /// 移動光標並清除選擇範圍
+    fn move_cursor(&mut self, direction: Direction) {
+        self.step_cursor(direction);
+        self.selection = None;
+    }
+
+    /// 依方向移動光標（不改變選擇範圍）
+    fn step_cursor(&mut self, direction: Direction) {
+        match direction {
+            Direction::Up => self.cursor.move_up(&self.buffer, &self.view),
+            Direction::Down => self.cursor.move_down(&self.buffer, &self.view),
+            Direction::Left => self.cursor.move_left(&self.buffer, &self.view),
+            Direction::Right => self.cursor.move_right(&self.buffer, &self.view),
+            Direction::Home => self.cursor.move_to_line_start(),
+            Direction::End => self.cursor.move_to_line_end(&self.buffer, &self.view),
+            Direction::FileStart => self.cursor.move_to_file_start(&self.view),
+            Direction::FileEnd => self.cursor.move_to_file_end(&self.buffer, &self.view),
+            Direction::ParagraphUp => self.cursor.move_paragraph_up(&self.buffer, &self.view),
+            Direction::ParagraphDown => self.cursor.move_paragraph_down(&self.buffer, &self.view),
+            Direction::BlockStart => self.cursor.move_to_block_start(&self.buffer, &self.view),
+            Direction::BlockEnd => self.cursor.move_to_block_end(&self.buffer, &self.view),
+            Direction::PageUp => self.scroll_page(-1),
+            Direction::PageDown => self.scroll_page(1),
+            Direction::TenthUp => {
+                let total_lines = self.buffer.line_count();
+                let jump_distance = total_lines.max(10) / 10; // 至少跳 1 行
+                self.cursor.row = self.cursor.row.saturating_sub(jump_distance);
+                self.cursor.set_position(
+                    &self.buffer,
+                    &self.view,
+                    self.cursor.row,
+                    self.cursor.col,
+                );
+            }
+            Direction::TenthDown => {
+                let total_lines = self.buffer.line_count();
+                let jump_distance = total_lines.max(10) / 10;
+                let new_row = self
+                    .cursor
+                    .row
+                    .saturating_add(jump_distance)
+                    .min(total_lines.saturating_sub(1));
+                self.cursor.row = new_row;
+                self.cursor.set_position(
+                    &self.buffer,
+                    &self.view,
+                    self.cursor.row,
+                    self.cursor.col,
+                );
+            }
+        }
+    }
+
+    /// 翻頁並維持光標在螢幕上的位置
+    fn scroll_page(&mut self, direction: isize) {
+        let effective_rows = self.view.get_effective_screen_rows(self.ruler);
+        // 記錄光標在屏幕上的 Y 位置
+        let cursor_screen_y = self.view.get_cursor_screen_y(&self.cursor, &self.buffer);
+        let (new_row, new_visual_line_index) =
+            self.view
+                .scroll_page(direction, cursor_screen_y, &self.buffer, effective_rows);
+        // 更新光標位置
+        self.cursor.row = new_row;
+        self.cursor.visual_line_index = new_visual_line_index;
+        self.cursor
+            .set_position(&self.buffer, &self.view, new_row, self.cursor.col);
+    }
+
+    /// 半個畫面的行數（至少一行）
+    fn half_page(&self) -> isize {
+        (self.view.get_effective_screen_rows(self.ruler) / 2).max(1) as isize
+    }
+
+    /// 捲動畫面而不移動光標；光標離開畫面時移到畫面最上或最下一行（保持欄位）
+    fn scroll_view(&mut self, delta: isize) {
+        if !self.view.scroll_lines(delta, &self.buffer) {
+            return;
+        }
+        let effective_rows = self.view.get_effective_screen_rows(self.ruler);
+        let (first, last) = self.view.visible_row_range(&self.buffer, effective_rows);
+        let row = self.cursor.row.clamp(first, last);
+        if row != self.cursor.row {
+            self.cursor.move_to_line(&self.buffer, &self.view, row);
+            self.selection = None;
+        }
+    }
+
+    /// 讓選擇範圍內各行（沒有選擇時為光標所在的段落）的 `delimiter` 對齊，整批修改只算一個復原步驟
+    ///
+    /// `all` 時對齊每一個分隔符號，否則只對齊每行的第一個；返回改變的行數
+    pub fn align(&mut self, delimiter: &str, all: bool) -> usize {
+        let (start_row, end_row) = match self.selection {
+            Some(sel) => (sel.start.min(sel.end).0, sel.start.max(sel.end).0),
+            None => {
+                let is_blank = |row: usize| self.buffer.get_line_content(row).trim().is_empty();
+                if is_blank(self.cursor.row) {
+                    return 0;
+                }
+                let start = (0..self.cursor.row)
+                    .rev()
+                    .find(|&row| is_blank(row))
+                    .map_or(0, |row| row + 1);
+                let end = (self.cursor.row + 1..self.buffer.line_count())
+                    .find(|&row| is_blank(row))
+                    .map_or(self.buffer.line_count() - 1, |row| row - 1);
+                (start, end)
+            }
+        };
+
+        let contents: Vec<String> = (start_row..=end_row)
+            .map(|row| {
+                let line = self.buffer.get_line_content(row);
+                line.trim_end_matches(['\n', '\r']).to_string()
+            })
+            .collect();
+        let lines: Vec<&str> = contents.iter().map(String::as_str).collect();
+        let mut aligned = align_lines(&lines, delimiter, all).into_iter();
+        let changed = rewrite_lines(&mut self.buffer, start_row, end_row, |_| aligned.next());
+        if changed > 0 {
+            self.buffer
+                .describe_last_edit(describe_lines("align", changed));
+            self.view.invalidate_cache();
+            let col = self.cursor.col.min(self.line_len(self.cursor.row));
+            self.cursor
+                .set_position(&self.buffer, &self.view, self.cursor.row, col);
+            if let Some(mut sel) = self.selection {
+                sel.start.1 = sel.start.1.min(self.line_len(sel.start.0));
+                sel.end.1 = sel.end.1.min(self.line_len(sel.end.0));
+                self.selection = Some(sel);
+            }
+        }
+        changed
+    }
+
+    /// 滑鼠在終端座標 (x, y) 按下：移動光標並清除選擇範圍；點在文字區以外時不處理
+    ///
+    /// `extend` 為 true（Shift+點擊）時從原本的選擇範圍起點（或光標）延伸選擇範圍
+    pub fn click(&mut self, x: u16, y: u16, extend: bool) {
+        let Some(text_y) = (y as usize).checked_sub(self.text_top()) else {
+            return;
+        };
+        if text_y >= self.view.get_effective_screen_rows(self.ruler) {
+            return;
+        }
+        if extend {
+            let anchor = self
+                .selection
+                .map_or((self.cursor.row, self.cursor.col), |sel| sel.start);
+            self.drag_anchor = Some(anchor);
+            self.drag_to(x, y);
+            return;
+        }
+        let (row, col) = self
+            .view
+            .position_at_screen(x as usize, text_y, &self.buffer);
+        self.cursor.set_position(&self.buffer, &self.view, row, col);
+        self.selection = None;
+        self.selection_mode = false;
+        self.drag_anchor = Some((row, col));
+    }
+
+    /// 拖曳到終端座標 (x, y)：把選擇範圍從按下的位置延伸到指標處（超出文字區時為最上或最下一行）
+    ///
+    /// 返回指標超出文字區上緣或下緣的行數（上方為負數，第一行與最後一行算 1），
+    /// 呼叫端依此以固定頻率呼叫 [`EditorCore::auto_scroll`]；沒有按下時返回 0
+    pub fn drag_to(&mut self, x: u16, y: u16) -> isize {
+        let Some(anchor) = self.drag_anchor else {
+            return 0;
+        };
+        let top = self.text_top();
+        let rows = self.view.get_effective_screen_rows(self.ruler);
+        let text_y = (y as usize).saturating_sub(top).min(rows.saturating_sub(1));
+        let (row, col) = self
+            .view
+            .position_at_screen(x as usize, text_y, &self.buffer);
+        self.cursor.set_position(&self.buffer, &self.view, row, col);
+        self.selection = Some(Selection {
+            start: anchor,
+            end: (row, col),
+        });
+
+        let y = y as isize;
+        let (top, bottom) = (top as isize, (top + rows) as isize - 1);
+        if y <= top && self.view.offset_row > 0 {
+            y - top - 1
+        } else if y >= bottom {
+            y - bottom + 1
+        } else {
+            0
+        }
+    }
+
+    /// 拖曳時指標停在文字區邊緣或以外：往該方向捲動一步並延伸選擇範圍
+    ///
+    /// 離邊緣越遠捲得越快（每步最多 `MAX_AUTO_SCROLL` 行）；返回 false 表示已捲到檔首或檔尾
+    pub fn auto_scroll(&mut self, x: u16, y: u16) -> bool {
+        let distance = self.drag_to(x, y);
+        if distance == 0 {
+            return false;
+        }
+        if distance > 0 {
+            // 最後一行已在畫面中時不再往下捲
+            let rows = self.view.get_effective_screen_rows(self.ruler);
+            let (_, last) = self.view.visible_row_range(&self.buffer, rows);
+            if last + 1 >= self.buffer.line_count() {
+                return false;
+            }
+        }
+        let delta = distance.clamp(-MAX_AUTO_SCROLL, MAX_AUTO_SCROLL);
+        if !self.view.scroll_lines(delta, &self.buffer) {
+            return false;
+        }
+        self.drag_to(x, y);
+        true
+    }
+
+    /// 放開滑鼠按鍵：結束拖曳，沒有拖出範圍時不留下空的選擇範圍
+    pub fn release(&mut self) {
+        self.drag_anchor = None;
+        if self.selection.is_some_and(|sel| sel.start == sel.end) {
+            self.selection = None;
+        }
+    }
+
+    /// 文字區第一行的終端列（分頁列與欄位尺規佔用頂端）
+    fn text_top(&self) -> usize {
+        self.view.tab_line_rows() + usize::from(self.ruler || self.view.show_ruler)
+    }
+
+    /// 將光標移動到字元位置（撤銷/重做後）
+    fn move_to_char(&mut self, pos: usize) {
+        self.view.invalidate_cache();
+        let row = self.buffer.char_to_line(pos);
+        let col = pos - self.buffer.line_to_char(row);
+
+        self.cursor.row = row;
+        self.cursor.col = col;
+        self.cursor.desired_visual_col = col;
+    }
+
+    /// 切換註解：有選擇範圍時切換所有選擇的行，否則切換當前行
+    fn toggle_comment(&mut self) {
+        if !self.comment_handler.has_comment_style() {
+            self.message = Some("No comment style for this file type".to_string());
+            return;
+        }
+
+        let Some(sel) = self.selection else {
+            // 單行：直接切換註解
+            let row = self.cursor.row;
+            let commented = self
+                .comment_handler
+                .is_commented(&self.buffer.get_line_content(row));
+            let handler = &self.comment_handler;
+            let changed = rewrite_lines(&mut self.buffer, row, row, |line| {
+                handler.toggle_line_comment(line)
+            });
+            if changed > 0 {
+                self.view.invalidate_cache();
+                let verb = if commented { "uncomment" } else { "comment" };
+                self.buffer.describe_last_edit(describe_lines(verb, 1));
+                self.message = Some("Toggled comment".to_string());
+            }
+            return;
+        };
+
+        // 多行選擇：智能切換註解
+        let (start_row, _) = sel.start.min(sel.end);
+        let (end_row, _) = sel.start.max(sel.end);
+
+        // 如果有任何一行沒註解，全部加註解；否則全部取消註解
+        let should_add_comment = (start_row..=end_row).any(|row| {
+            !self
+                .comment_handler
+                .is_commented(&self.buffer.get_line_content(row))
+        });
+
+        let handler = &self.comment_handler;
+        let changed = rewrite_lines(&mut self.buffer, start_row, end_row, |line| {
+            if should_add_comment {
+                // 全部加註解（即使已經有註解的也保持不變）
+                if handler.is_commented(line) {
+                    None
+                } else {
+                    handler.add_comment(line)
+                }
+            } else {
+                // 全部取消註解
+                handler.remove_comment(line)
+            }
+        });
+        self.view.invalidate_cache();
+
+        // 保留選擇狀態（不清除選取）
+        self.cursor.row = start_row;
+        self.cursor.col = 0;
+        self.cursor.desired_visual_col = 0;
+
+        let (action, verb) = if should_add_comment {
+            ("Added", "comment")
+        } else {
+            ("Removed", "uncomment")
+        };
+        self.buffer
+            .describe_last_edit(describe_lines(verb, changed));
+        self.message = Some(format!("{} comments", action));
+    }
+
+    /// 貼上時的目標縮排；返回 (縮排, 第一行是否去掉原本的縮排)，不調整時返回 None
+    ///
+    /// 整行貼上對齊光標所在行（空白行時往上找最近的非空白行）；一般貼上只在光標位於
+    /// 行首縮排內時調整，此時光標前的空白就是第一行的縮排
+    fn paste_indent_target(&self, is_whole_line: bool, text: &str) -> Option<(String, bool)> {
+        if !self.paste_indent || !text.contains('\n') {
+            return None;
+        }
+        let leading = |line: &str| -> String {
+            line.chars()
+                .take_while(|&ch| ch == ' ' || ch == '\t')
+                .collect()
+        };
+        if is_whole_line {
+            let indent = (0..=self.cursor.row)
+                .rev()
+                .map(|row| self.buffer.get_line_content(row))
+                .find(|line| !line.trim().is_empty())
+                .map(|line| leading(&line))
+                .unwrap_or_default();
+            return Some((indent, false));
+        }
+        let content = self.buffer.get_line_content(self.cursor.row);
+        let before: String = content.chars().take(self.cursor.col).collect();
+        before
+            .chars()
+            .all(|ch| ch == ' ' || ch == '\t')
+            .then_some((before, true))
+    }
+
+    /// 自動斷行：光標超過 `text_width` 時，把最後一個放得下的空白換成換行
+    ///
+    /// 新行沿用原本的縮排；註解行（例如 `// `）在新行加上同樣的註解前綴。
+    /// 整個斷行是一個復原步驟；沒有可斷的位置（例如一個很長的字）時返回 false
+    fn hard_wrap_line(&mut self) -> bool {
+        if self.text_width == 0 {
+            return false;
+        }
+        let row = self.cursor.row;
+        let content = self.buffer.get_line_content(row);
+        let line = content.trim_end_matches(['\n', '\r']);
+        let col = self.cursor.col;
+        if self.view.logical_col_to_visual_col(line, col) <= self.text_width {
+            return false;
+        }
+
+        // 縮排與註解前綴不能斷開，新行以同樣的內容開頭
+        let chars: Vec<char> = line.chars().collect();
+        let is_blank = |ch: char| ch == ' ' || ch == '\t';
+        let indent = chars.iter().take_while(|&&ch| is_blank(ch)).count();
+        let mut lead = indent;
+        let mut continuation: String = chars[..indent].iter().collect();
+        if let Some(prefix) = self.comment_handler.line_prefix() {
+            let rest: String = chars[indent..].iter().collect();
+            if rest.starts_with(prefix) {
+                lead += prefix.chars().count();
+                while lead < chars.len() && is_blank(chars[lead]) {
+                    lead += 1;
+                }
+                continuation.push_str(prefix);
+                continuation.push(' ');
+            }
+        }
+
+        // 從光標往前找第一個前方文字放得下的空白
+        let Some(blank) = (lead..col.min(chars.len())).rev().find(|&i| {
+            is_blank(chars[i]) && self.view.logical_col_to_visual_col(line, i) <= self.text_width
+        }) else {
+            return false;
+        };
+        let mut start = blank;
+        while start > lead && is_blank(chars[start - 1]) {
+            start -= 1;
+        }
+        let mut end = blank;
+        while end < chars.len() && is_blank(chars[end]) {
+            end += 1;
+        }
+        if start == lead {
+            return false;
+        }
+
+        let line_start = self.buffer.line_to_char(row);
+        self.buffer.replace_range(
+            line_start + start,
+            line_start + end,
+            &format!("\n{}", continuation),
+        );
+        self.buffer.describe_last_edit("wrap line");
+        self.view.invalidate_cache();
+        let new_col = continuation.chars().count() + col.saturating_sub(end);
+        self.cursor
+            .set_position(&self.buffer, &self.view, row + 1, new_col);
+        true
+    }
+
+    /// 行的字元數（不含換行符）
+    pub(crate) fn line_len(&self, row: usize) -> usize {
+        self.buffer
+            .get_line_content(row)
+            .trim_end_matches(['\n', '\r'])
+            .chars()
+            .count()
+    }
+}
+
+/// [`EditorCore`] 的建構器
+///
+/// ```
+/// use wedi::{Command, EditorCore, RopeBuffer};
+///
+/// let mut buffer = RopeBuffer::new();
+/// buffer.insert(0, "let x = 1;\n");
+/// let mut core = EditorCore::builder()
+///     .buffer(buffer)
+///     .size(100, 30)
+///     .comment_prefix("//")
+///     .build();
+/// core.apply(Command::ToggleComment);
+/// assert_eq!(core.text(), "// let x = 1;\n");
+/// ```
+#[allow(dead_code)]
+#[derive(Default)]
+pub struct EditorCoreBuilder {
+    buffer: Option<RopeBuffer>,
+    size: Option<(u16, u16)>,
+    comment_prefix: Option<String>,
+    text_width: usize,
+    paste_indent: bool,
+    ruler: bool,
+}
+
+#[allow(dead_code)]
+impl EditorCoreBuilder {
+    /// 要編輯的緩衝區（預設為空白緩衝區）；有檔案路徑時依副檔名決定註解風格
+    pub fn buffer(mut self, buffer: RopeBuffer) -> Self {
+        self.buffer = Some(buffer);
+        self
+    }
+
+    /// 終端大小（包含狀態列，預設 80x24），影響自動換行與翻頁
+    pub fn size(mut self, cols: u16, rows: u16) -> Self {
+        self.size = Some((cols, rows));
+        self
+    }
+
+    /// 行註解前綴，取代依副檔名偵測的註解風格
+    pub fn comment_prefix(mut self, prefix: &str) -> Self {
+        self.comment_prefix = Some(prefix.to_string());
+        self
+    }
+
+    /// 輸入超過此欄位時在前一個空白處自動斷行（0 表示關閉，預設）
+    pub fn text_width(mut self, width: usize) -> Self {
+        self.text_width = width;
+        self
+    }
+
+    /// 貼上多行內容時是否依光標處的縮排調整每一行（預設關閉）
+    pub fn paste_indent(mut self, enabled: bool) -> Self {
+        self.paste_indent = enabled;
+        self
+    }
+
+    /// 頂部是否顯示欄位尺規（可用行數少一行，預設關閉）
+    pub fn ruler(mut self, enabled: bool) -> Self {
+        self.ruler = enabled;
+        self
+    }
+
+    pub fn build(self) -> EditorCore {
+        let (cols, rows) = self.size.unwrap_or(DEFAULT_SIZE);
+        let mut core = EditorCore::with_size(self.buffer.unwrap_or_default(), cols, rows);
+        if let Some(prefix) = &self.comment_prefix {
+            core.comment_handler.set_line_prefix(prefix);
+        }
+        core.text_width = self.text_width;
+        core.paste_indent = self.paste_indent;
+        core.ruler = self.ruler;
+        core
+    }
+}
+
+/// 以 `rewrite` 改寫 start_row..=end_row 的每一行（不含換行符，返回 None 表示不變），
+/// 保留原本的換行符；整批修改只算一個復原步驟，返回改變的行數
+fn rewrite_lines(
+    buffer: &mut RopeBuffer,
+    start_row: usize,
+    end_row: usize,
+    mut rewrite: impl FnMut(&str) -> Option<String>,
+) -> usize {
+    let mut changed = 0;
+    let mut text = String::new();
+    for row in start_row..=end_row {
+        let line = buffer.get_line_content(row);
+        let content = line.trim_end_matches(['\n', '\r']);
+        match rewrite(content) {
+            Some(new_line) if new_line != content => {
+                text.push_str(&new_line);
+                changed += 1;
+            }
+            _ => text.push_str(content),
+        }
+        text.push_str(&line[content.len()..]);
+    }
+    if changed > 0 {
+        let start = buffer.line_to_char(start_row);
+        let end = buffer.line_to_char(end_row + 1);
+        buffer.replace_range(start, end, &text);
+    }
+    changed
+}
+
+/// 讓各行的 `delimiter` 對齊在同一欄（`all` 時對齊每一個，像表格一樣分欄）
+///
+/// `,`、`:`、`;` 接在前一個欄位後面，空白補在分隔符號之後（`key:   value`）；
+/// 其他分隔符號前後各留一個空格，空白補在前面（`key   = value`）。
+/// 沒有分隔符號的行不變；返回對應每一行的新內容
+fn align_lines(lines: &[&str], delimiter: &str, all: bool) -> Vec<String> {
+    let attaches_left = matches!(delimiter, "," | ":" | ";");
+    let fields: Vec<Vec<&str>> = lines
+        .iter()
+        .map(|line| {
+            let mut fields: Vec<&str> = if all {
+                line.split(delimiter).collect()
+            } else {
+                line.splitn(2, delimiter).collect()
+            };
+            let last = fields.len() - 1;
+            for (i, field) in fields.iter_mut().enumerate() {
+                // 第一欄保留縮排，最後一欄保留行尾
+                *field = match (i, i == last) {
+                    (0, true) => field,
+                    (0, false) => field.trim_end(),
+                    (_, true) => field.trim_start(),
+                    _ => field.trim(),
+                };
+            }
+            fields
+        })
+        .collect();
+
+    // 每一欄（不含各行的最後一欄）的最大顯示寬度
+    let mut widths: Vec<usize> = Vec::new();
+    for line in &fields {
+        for (i, field) in line.iter().enumerate().take(line.len() - 1) {
+            if widths.len() <= i {
+                widths.push(0);
+            }
+            widths[i] = widths[i].max(visual_width(field));
+        }
+    }
+
+    fields
+        .iter()
+        .zip(lines)
+        .map(|(line, original)| {
+            if line.len() == 1 {
+                return original.to_string();
+            }
+            let mut out = String::new();
+            for (i, field) in line.iter().enumerate() {
+                let Some(&width) = widths.get(i).filter(|_| i + 1 < line.len()) else {
+                    out.push_str(field);
+                    break;
+                };
+                // 行尾的空欄位之後不留空白
+                let trailing = i + 2 == line.len() && line[i + 1].is_empty();
+                let padding = " ".repeat(width - visual_width(field));
+                if attaches_left {
+                    out.push_str(field);
+                    out.push_str(delimiter);
+                    if !trailing {
+                        out.push_str(&padding);
+                    }
+                } else {
+                    out.push_str(&format!("{}{} {}", field, padding, delimiter));
+                }
+                if !trailing {
+                    out.push(' ');
+                }
+            }
+            out
+        })
+        .collect()
+}
+
+/// 把多行文字整體移到 `indent` 的縮排層級，保留各行之間的相對縮排
+///
+/// 基準是最淺的非空白行；`strip_first` 時第一行接在既有的縮排之後，
+/// 只去掉它原本的縮排（沒有縮排的第一行不列入基準，它多半是從行中間開始複製的）
+fn reindent_block(text: &str, indent: &str, strip_first: bool) -> String {
+    let leading = |line: &str| line.len() - line.trim_start_matches([' ', '\t']).len();
+    let is_blank = |line: &str| line.trim().is_empty();
+    let base = text
+        .split_inclusive('\n')
+        .enumerate()
+        .filter(|&(i, line)| !(is_blank(line) || (i == 0 && strip_first && leading(line) == 0)))
+        .map(|(_, line)| &line[..leading(line)])
+        .min_by_key(|ws| ws.len())
+        .unwrap_or("");
+
+    let mut result = String::with_capacity(text.len());
+    for (i, line) in text.split_inclusive('\n').enumerate() {
+        let ws = &line[..leading(line)];
+        let rest = &line[ws.len()..];
+        if i == 0 && strip_first {
+            result.push_str(rest);
+        } else if is_blank(line) {
+            result.push_str(line);
+        } else {
+            // 與基準不同的縮排字元（Tab 與空格混用）時，只保留超出基準長度的部分
+            let extra = ws
+                .strip_prefix(base)
+                .unwrap_or(&ws[base.len().min(ws.len())..]);
+            result.push_str(indent);
+            result.push_str(extra);
+            result.push_str(rest);
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn core_with(text: &str) -> EditorCore {
+        let mut core = EditorCore::new(RopeBuffer::new());
+        core.paste_text(text);
+        core.apply(Command::MoveToFileStart);
+        core
+    }
+
+    #[test]
+    fn test_insert_and_undo() {
+        let mut core = EditorCore::new(RopeBuffer::new());
+        for ch in "ab\nc".chars() {
+            assert!(core.apply(Command::Insert(ch)));
+        }
+        assert_eq!(core.text(), "ab\nc");
+        assert_eq!((core.cursor().row, core.cursor().col), (1, 1));
+
+        core.apply(Command::Backspace);
+        core.apply(Command::Backspace);
+        assert_eq!(core.text(), "ab");
+        core.apply(Command::Undo);
+        assert_eq!(core.text(), "ab\n");
+        assert_eq!(
+            core.take_message().as_deref(),
+            Some("Undo: delete 1 line break")
+        );
+        core.apply(Command::Redo);
+        assert_eq!(
+            core.take_message().as_deref(),
+            Some("Redo: delete 1 line break")
+        );
+    }
+
+    #[test]
+    fn test_paragraph_and_block_motions() {
+        let mut core = core_with("a\nb\n\n\nc\nd\n\ne");
+        core.apply(Command::MoveParagraphDown);
+        assert_eq!(core.cursor().row, 2);
+        core.apply(Command::MoveParagraphDown);
+        assert_eq!(core.cursor().row, 6);
+        core.apply(Command::MoveParagraphDown);
+        assert_eq!((core.cursor().row, core.cursor().col), (7, 1));
+        core.apply(Command::MoveParagraphUp);
+        assert_eq!(core.cursor().row, 6);
+        core.apply(Command::MoveParagraphUp);
+        assert_eq!(core.cursor().row, 3);
+        core.apply(Command::ExtendSelection(Direction::ParagraphUp));
+        assert_eq!(core.cursor().row, 0);
+        assert!(core.has_selection());
+
+        let mut core = core_with("fn a() {\n    x;\n    if y {\n        z;\n    }\n\n    w;\n}\n");
+        core.apply(Command::MoveDown);
+        core.apply(Command::MoveDown);
+        core.apply(Command::MoveBlockEnd);
+        assert_eq!((core.cursor().row, core.cursor().col), (6, 4));
+        // 已在區塊結尾：移到外層的下一行
+        core.apply(Command::MoveBlockEnd);
+        assert_eq!((core.cursor().row, core.cursor().col), (7, 0));
+        core.apply(Command::MoveUp);
+        core.apply(Command::MoveBlockStart);
+        assert_eq!((core.cursor().row, core.cursor().col), (1, 4));
+        core.apply(Command::MoveBlockStart);
+        assert_eq!((core.cursor().row, core.cursor().col), (0, 0));
+    }
+
+    #[test]
+    fn test_scroll_keeps_cursor_on_screen() {
+        let text: String = (1..=100).map(|n| format!("line {}\n", n)).collect();
+        let mut core = EditorCore::with_size(RopeBuffer::new(), 80, 11); // 10 行文字
+        core.paste_text(&text);
+        core.apply(Command::MoveToFileStart);
+
+        core.apply(Command::ScrollLineDown);
+        assert_eq!((core.view.offset_row, core.cursor().row), (1, 1));
+        core.apply(Command::ScrollLineUp);
+        assert_eq!((core.view.offset_row, core.cursor().row), (0, 1));
+        core.apply(Command::ScrollHalfPageDown);
+        core.apply(Command::ScrollHalfPageDown);
+        assert_eq!((core.view.offset_row, core.cursor().row), (10, 10));
+        core.apply(Command::ScrollHalfPageUp);
+        assert_eq!((core.view.offset_row, core.cursor().row), (5, 10));
+        core.apply(Command::ScrollHalfPageUp);
+        assert_eq!((core.view.offset_row, core.cursor().row), (0, 9));
+
+        for _ in 0..40 {
+            core.apply(Command::MoveDown);
+        }
+        core.apply(Command::CenterCursor);
+        assert_eq!((core.view.offset_row, core.cursor().row), (44, 49));
+    }
+
+    #[test]
+    fn test_mouse_drag_selects_and_auto_scrolls() {
+        let text: String = (1..=100).map(|n| format!("line {}\n", n)).collect();
+        let mut core = EditorCore::with_size(RopeBuffer::new(), 80, 11); // 10 行文字
+        core.paste_text(&text);
+        core.apply(Command::MoveToFileStart);
+        core.view.show_line_numbers = false;
+
+        core.click(2, 3, false);
+        assert_eq!((core.cursor().row, core.cursor().col), (3, 2));
+        assert!(core.selection().is_none());
+        assert_eq!(core.drag_to(4, 5), 0);
+        assert_eq!(core.selection().unwrap().end, (5, 4));
+
+        // 拖到狀態列：選擇範圍到最後一行，離下緣 2 行
+        assert_eq!(core.drag_to(0, 10), 2);
+        assert_eq!(core.selection().unwrap().end, (9, 0));
+        assert!(core.auto_scroll(0, 10));
+        assert_eq!(core.view.offset_row, 2);
+        let sel = core.selection().unwrap();
+        assert_eq!((sel.start, sel.end), ((3, 2), (11, 0)));
+
+        // 捲到最後一行出現在畫面中為止
+        while core.auto_scroll(0, 9) {}
+        assert_eq!(core.view.offset_row, 91);
+        assert_eq!(core.selection().unwrap().end, (100, 0));
+
+        // 往上拖到第一行時往回捲；放開後選擇範圍保留
+        assert_eq!(core.drag_to(3, 0), -1);
+        assert!(core.auto_scroll(3, 0));
+        assert_eq!(core.view.offset_row, 90);
+        core.release();
+        assert_eq!(core.selection().unwrap().end, (90, 3));
+        assert_eq!(core.drag_to(0, 0), 0);
+
+        // 單純點擊不留下選擇範圍；Shift+點擊從原本的起點延伸
+        core.click(1, 1, false);
+        core.release();
+        assert!(core.selection().is_none());
+        core.click(5, 4, true);
+        core.release();
+        assert_eq!(core.selection().unwrap().start, (91, 1));
+        assert_eq!(core.selection().unwrap().end, (94, 5));
+    }
+
+    #[test]
+    fn test_text_width_breaks_at_previous_blank() {
+        let mut core = EditorCore::new(RopeBuffer::new());
+        core.text_width = 10;
+        for ch in "  one two three".chars() {
+            core.apply(Command::Insert(ch));
+        }
+        assert_eq!(core.text(), "  one two\n  three");
+        assert_eq!((core.cursor().row, core.cursor().col), (1, 7));
+        // 輸入 t 時超過第 10 欄而斷行；斷行本身是一個復原步驟
+        for _ in 0.."hree".len() + 1 {
+            core.apply(Command::Undo);
+        }
+        assert_eq!(core.text(), "  one two t");
+
+        // 註解行在新行延續註解前綴；一個長字沒有可斷的位置
+        let mut core = EditorCore::new(RopeBuffer::new());
+        core.comment_handler.detect_from_extension(Some("rs"));
+        core.text_width = 12;
+        for ch in "// alpha beta gamma\nabcdefghijklmno".chars() {
+            core.apply(Command::Insert(ch));
+        }
+        assert_eq!(core.text(), "// alpha\n// beta\n// gamma\nabcdefghijklmno");
+
+        // 不認得的檔案類型不把 # 當成註解（例如 Markdown 標題）
+        let mut core = EditorCore::new(RopeBuffer::new());
+        core.comment_handler.detect_from_extension(Some("md"));
+        core.text_width = 8;
+        for ch in "# Title words".chars() {
+            core.apply(Command::Insert(ch));
+        }
+        assert_eq!(core.text(), "# Title\nwords");
+    }
+
+    #[test]
+    fn test_save_as_redetects_comment_style() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("script.py");
+        std::fs::write(&path, "let x = 1;\n").unwrap();
+        let buffer = RopeBuffer::from_file_with_encoding(&path, &Default::default()).unwrap();
+        let mut core = EditorCore::new(buffer);
+        assert_eq!(core.comment_handler.line_prefix(), Some("#"));
+
+        // 副檔名改變：改用新檔案類型的註解風格
+        assert!(core.save_as(&dir.path().join("main.rs")).unwrap());
+        core.apply(Command::ToggleComment);
+        assert_eq!(core.text(), "// let x = 1;\n");
+        assert!(dir.path().join("main.rs").exists());
+
+        // 副檔名相同：不需要重新檢測
+        assert!(!core.save_as(&dir.path().join("copy.rs")).unwrap());
+        assert_eq!(
+            core.buffer().file_path(),
+            Some(dir.path().join("copy.rs").as_path())
+        );
+    }
+
+    #[test]
+    fn test_paste_indent_shifts_block() {
+        // 整行貼上：對齊光標所在行的縮排，保留相對縮排與空白行
+        let mut core = core_with("fn main() {\n    let x = 1;\n}\n");
+        core.paste_indent = true;
+        core.apply(Command::MoveDown);
+        core.paste_text("if a {\n    b();\n\n}\n");
+        assert_eq!(
+            core.text(),
+            "fn main() {\n    if a {\n        b();\n\n    }\n    let x = 1;\n}\n"
+        );
+        assert_eq!(core.cursor().row, 5);
+        core.apply(Command::Undo);
+        assert_eq!(core.text(), "fn main() {\n    let x = 1;\n}\n");
+
+        // 光標在縮排內：第一行接在既有縮排後，其餘各行依最淺的一行移動
+        let mut core = core_with("        \n");
+        core.paste_indent = true;
+        core.apply(Command::MoveEnd);
+        core.paste_text("if a {\n        b();\n    }");
+        assert_eq!(core.text(), "        if a {\n            b();\n        }\n");
+        assert_eq!((core.cursor().row, core.cursor().col), (2, 9));
+
+        // 光標在文字之後或關閉選項時照原樣貼上
+        let mut core = core_with("x = \n");
+        core.paste_indent = true;
+        core.apply(Command::MoveEnd);
+        core.paste_text("[\n  1,\n]");
+        assert_eq!(core.text(), "x = [\n  1,\n]\n");
+        assert_eq!(reindent_block("a\n\tb\n", "  ", false), "  a\n  \tb\n");
+    }
+
+    #[test]
+    fn test_selection_and_internal_clipboard() {
+        let mut core = core_with("hello world\nsecond\n");
+        for _ in 0..5 {
+            core.apply(Command::ExtendSelection(Direction::Right));
+        }
+        assert_eq!(core.selected_text(), "hello");
+
+        core.apply(Command::CutInternal);
+        assert_eq!(core.clipboard(), "hello");
+        assert_eq!(core.text(), " world\nsecond\n");
+
+        core.apply(Command::MoveEnd);
+        core.apply(Command::PasteInternal);
+        assert_eq!(core.text(), " worldhello\nsecond\n");
+
+        // 沒有選擇時剪下整行
+        core.apply(Command::MoveDown);
+        core.apply(Command::CutInternal);
+        assert_eq!(core.text(), " worldhello\n");
+        assert_eq!(core.clipboard(), "second\n");
+    }
+
+    #[test]
+    fn test_grapheme_clusters_move_and_delete_as_one() {
+        let family = "👨\u{200D}👩\u{200D}👧";
+        let mut core = core_with(&format!("a{}🇹🇼e\u{301}\u{1100}\u{1161}b\n", family));
+
+        // 向右一次跨過整個叢集
+        let mut cols = Vec::new();
+        for _ in 0..6 {
+            core.apply(Command::MoveRight);
+            cols.push(core.cursor().col);
+        }
+        assert_eq!(cols, vec![1, 6, 8, 10, 12, 13]);
+        core.apply(Command::MoveLeft);
+        assert_eq!(core.cursor().col, 12);
+
+        // Backspace 刪除整個韓文字母組合，Delete 刪除整個 emoji 序列
+        core.apply(Command::Backspace);
+        assert_eq!(core.text(), format!("a{}🇹🇼e\u{301}b\n", family));
+        core.apply(Command::MoveHome);
+        core.apply(Command::MoveRight);
+        core.apply(Command::Delete);
+        assert_eq!(core.text(), "a🇹🇼e\u{301}b\n");
+
+        // 選擇範圍的邊界也落在叢集之間
+        core.apply(Command::ExtendSelection(Direction::Right));
+        core.apply(Command::ExtendSelection(Direction::Right));
+        assert_eq!(core.selected_text(), "🇹🇼e\u{301}");
+
+        // 上下移動不會停在叢集中間
+        let mut core = core_with("abc\ne\u{301}e\u{301}x\n");
+        core.apply(Command::MoveRight);
+        core.apply(Command::MoveDown);
+        assert_eq!((core.cursor().row, core.cursor().col), (1, 2));
+        core.apply(Command::MoveUp);
+        core.apply(Command::MoveRight);
+        core.apply(Command::MoveDown);
+        assert_eq!((core.cursor().row, core.cursor().col), (1, 4));
+    }
+
+    #[test]
+    fn test_align_lines_on_delimiter() {
+        let lines = ["  a=1", "  long_key = 2", "# comment", "  b =x=y"];
+        assert_eq!(
+            align_lines(&lines, "=", false),
+            [
+                "  a        = 1",
+                "  long_key = 2",
+                "# comment",
+                "  b        = x=y"
+            ]
+        );
+        assert_eq!(
+            align_lines(&["name: wedi", "version:  1", "x:"], ":", false),
+            ["name:    wedi", "version: 1", "x:"]
+        );
+        assert_eq!(
+            align_lines(&["a,bb,c", "ccc,d,eeee", "中文,x"], ",", true),
+            ["a,    bb, c", "ccc,  d,  eeee", "中文, x"]
+        );
+    }
+
+    #[test]
+    fn test_align_selection_is_one_undo_step() {
+        let mut core = core_with("x=1\nlonger=2\n\nother=3\n");
+        core.align("=", false);
+        assert_eq!(core.text(), "x      = 1\nlonger = 2\n\nother=3\n");
+        core.apply(Command::Undo);
+        assert_eq!(core.text(), "x=1\nlonger=2\n\nother=3\n");
+
+        core.apply(Command::ExtendSelection(Direction::Down));
+        core.apply(Command::ExtendSelection(Direction::Down));
+        core.apply(Command::ExtendSelection(Direction::Down));
+        assert_eq!(core.align("=", false), 3);
+        assert_eq!(core.text(), "x      = 1\nlonger = 2\n\nother  = 3\n");
+    }
+
+    #[test]
+    fn test_indent_and_unsupported_commands() {
+        let mut core = core_with("a\nb");
+        core.apply(Command::SelectAll);
+        core.apply(Command::Indent);
+        assert_eq!(core.text(), "    a\n    b");
+        core.apply(Command::Unindent);
+        assert_eq!(core.text(), "a\nb");
+        // 整批縮排只算一個復原步驟，訊息說明復原了什麼
+        core.apply(Command::Undo);
+        assert_eq!(core.text(), "    a\n    b");
+        assert_eq!(
+            core.take_message().as_deref(),
+            Some("Undo: unindent 2 lines")
+        );
+        core.apply(Command::Undo);
+        assert_eq!(core.text(), "a\nb");
+
+        // 需要終端或檔案的命令不處理
+        assert!(!core.apply(Command::Save));
+        assert!(!core.apply(Command::Find));
+    }
+
+    #[test]
+    fn test_count_repeats_command() {
+        let mut core = core_with("1\n2\n3\n4\n5\n");
+        core.apply(Command::WithCount(2, Box::new(Command::MoveDown)));
+        assert_eq!(core.cursor().row, 2);
+        assert!(core.apply(Command::WithCount(2, Box::new(Command::DeleteLine))));
+        assert_eq!(core.text(), "1\n2\n5\n");
+        core.apply(Command::WithCount(3, Box::new(Command::Insert('x'))));
+        assert_eq!(core.text(), "1\n2\nxxx5\n");
+
+        // 每次重複的修改合併為一個撤銷步驟；撤銷本身照次數重複
+        core.apply(Command::Undo);
+        assert_eq!(core.text(), "1\n2\n5\n");
+        core.apply(Command::Undo);
+        assert_eq!(core.take_message().as_deref(), Some("Undo: delete 2 lines"));
+        core.apply(Command::WithCount(2, Box::new(Command::Redo)));
+        assert_eq!(core.text(), "1\n2\nxxx5\n");
+
+        assert!(!core.apply(Command::WithCount(2, Box::new(Command::Save))));
+    }
+
+    #[test]
+    fn test_text_input_with_modifiers() {
+        use crossterm::event::{KeyCode, KeyModifiers};
+
+        let key = |c: char, m: KeyModifiers| KeyEvent::new(KeyCode::Char(c), m);
+        let mut core = EditorCore::new(RopeBuffer::new());
+        // 輸入法帶著 Alt/Shift 送出的文字，含 BMP 以外的字元
+        assert!(core.handle_key(key('中', KeyModifiers::ALT)));
+        assert!(core.handle_key(key('文', KeyModifiers::SHIFT | KeyModifiers::ALT)));
+        assert!(core.handle_key(key('😀', KeyModifiers::ALT)));
+        // AltGr（Ctrl+Alt）組出的符號
+        assert!(core.handle_key(key('@', KeyModifiers::CONTROL | KeyModifiers::ALT)));
+        assert!(core.handle_key(key('€', KeyModifiers::CONTROL | KeyModifiers::ALT)));
+        assert_eq!(core.text(), "中文😀@€");
+
+        // Ctrl 組合與未配置的 Alt+字母不是文字
+        assert!(!core.handle_key(key('中', KeyModifiers::CONTROL)));
+        assert!(!core.handle_key(key('q', KeyModifiers::ALT)));
+        assert!(!core.handle_key(key('q', KeyModifiers::CONTROL | KeyModifiers::ALT)));
+        assert_eq!(core.text(), "中文😀@€");
+    }
+}
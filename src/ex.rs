@@ -0,0 +1,477 @@
+// 命令列（ex 模式）
+//
+// 解析 `:` 命令列輸入的指令，例如 `:w`、`:wq`、`:q!`、`:e FILE`、`:set number=off`、
+// `:42`、`:s/foo/bar/g`、`:!make`、`:checkhealth`、`:analyze`、`:mark a`、`:insert date`，由編輯器對應到既有的操作執行
+
+use crate::template::Template;
+use crate::whitespace::Fix;
+use anyhow::{bail, Result};
+use std::path::PathBuf;
+
+/// 命令列指令
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExCommand {
+    /// `:w [FILE]` 存檔（指定檔名時另存新檔）
+    Write(Option<PathBuf>),
+    /// `:wq` / `:x` 存檔後關閉目前的緩衝區（最後一個時離開）
+    WriteQuit,
+    /// `:q` 關閉目前的緩衝區（最後一個時離開），`:q!` 放棄修改
+    Quit { force: bool },
+    /// `:qa` 關閉所有緩衝區並離開，`:qa!` 放棄所有修改
+    QuitAll { force: bool },
+    /// `:wa` 儲存所有有修改的緩衝區
+    WriteAll,
+    /// `:wqa` / `:xa` 儲存所有緩衝區後離開
+    WriteQuitAll,
+    /// `:e[!] FILE` 開啟另一個檔案，`!` 放棄目前的修改
+    Edit { path: PathBuf, force: bool },
+    /// `:set option[=value]`
+    Set {
+        option: String,
+        value: Option<String>,
+    },
+    /// `:NUM` 跳到指定行（1-based）
+    GoToLine(usize),
+    /// `:s/pattern/replacement/[g]` 取代文字（有選擇範圍時只處理選擇範圍）
+    Substitute {
+        pattern: String,
+        replacement: String,
+        global: bool,
+    },
+    /// `:!CMD` 以系統 shell 執行外部命令（例如建置或執行程式）
+    Shell(String),
+    /// `:r !CMD` 在光標處插入外部命令的輸出
+    ReadShell(String),
+    /// `:|CMD` 以外部命令過濾選擇範圍（沒有選擇時為整個檔案），輸出取代原本的文字
+    Filter(String),
+    /// `:format` 以格式化程式整理選擇範圍（沒有選擇時為整個檔案）
+    Format,
+    /// `:hover` 向語言伺服器查詢光標位置的說明
+    Hover,
+    /// `:checkhealth` 檢查執行環境並顯示報告
+    CheckHealth,
+    /// `:hexview` 以十六進位顯示磁碟上的檔案內容
+    HexView,
+    /// `:analyze` 列出重複行與常見單字
+    Analyze,
+    /// `:count` 計算行數、單字數、字符數與位元組數
+    Count,
+    /// `:char` 顯示光標處字元的 Unicode 資訊
+    InspectChar,
+    /// `:suspend` 暫停到 shell，`fg` 返回
+    Suspend,
+    /// `:fix [eol|endings|trailing]` 修正換行與行尾空白（沒有參數時修正全部）
+    Fix(Option<Fix>),
+    /// `:mark NAME` 在光標處設定書籤
+    Mark(String),
+    /// `:'NAME` 跳到書籤
+    JumpToMark(String),
+    /// `:marks` 列出書籤
+    Marks,
+    /// `:delmarks NAME`（`!` 刪除全部）
+    DeleteMark(String),
+    /// `:matches [TEXT]` 列出所有搜尋結果（沒有參數時使用上一次的搜尋）
+    Matches(Option<String>),
+    /// `:grep [TEXT]` 在工作目錄的所有檔案中尋找（沒有參數時重新列出上一次的結果）
+    Grep(Option<String>),
+    /// `:ls` 列出開啟的緩衝區
+    Buffers,
+    /// `:b N` 切換到第 N 個緩衝區
+    Buffer(usize),
+    /// `:bn` 下一個緩衝區
+    NextBuffer,
+    /// `:bp` 上一個緩衝區
+    PrevBuffer,
+    /// `:align[!] [DELIM]` 對齊選擇範圍各行的分隔符號（預設 `=`），`!` 對齊每一個
+    Align { delimiter: String, all: bool },
+    /// `:insert WHAT` 在光標處插入日期、時間、檔案路徑、使用者或主機名稱
+    Insert(Template),
+}
+
+/// 解析命令列輸入（可含開頭的 `:`）
+pub fn parse(input: &str) -> Result<ExCommand> {
+    let input = input.trim();
+    let input = input.strip_prefix(':').unwrap_or(input).trim_start();
+    if input.is_empty() {
+        bail!("Empty command");
+    }
+
+    if let Ok(line) = input.parse::<usize>() {
+        return Ok(ExCommand::GoToLine(line));
+    }
+
+    if let Some(command) = input.strip_prefix('!') {
+        let command = command.trim();
+        if command.is_empty() {
+            bail!("Usage: :!COMMAND");
+        }
+        return Ok(ExCommand::Shell(command.to_string()));
+    }
+
+    if let Some(command) = input.strip_prefix('|') {
+        let command = command.trim();
+        if command.is_empty() {
+            bail!("Usage: :|COMMAND");
+        }
+        return Ok(ExCommand::Filter(command.to_string()));
+    }
+
+    // vi 也接受不加空白的 `:r!CMD`
+    if let Some(rest) = input
+        .strip_prefix("read")
+        .or_else(|| input.strip_prefix('r'))
+    {
+        if let Some(command) = rest.trim_start().strip_prefix('!') {
+            let command = command.trim();
+            if command.is_empty() {
+                bail!("Usage: :r !COMMAND");
+            }
+            return Ok(ExCommand::ReadShell(command.to_string()));
+        }
+    }
+
+    if let Some(name) = input.strip_prefix('\'') {
+        let name = name.trim();
+        if name.is_empty() {
+            bail!("Usage: :'NAME");
+        }
+        return Ok(ExCommand::JumpToMark(name.to_string()));
+    }
+
+    // 取代指令的分隔符號可以是任何標點，例如 :s#a/b#c#
+    if let Some(rest) = input.strip_prefix('s') {
+        if rest.starts_with(|c: char| c.is_ascii_punctuation() && c != '!') {
+            return parse_substitute(rest);
+        }
+    }
+
+    let (name, arg) = match input.find(char::is_whitespace) {
+        Some(pos) => (&input[..pos], input[pos..].trim()),
+        None => (input, ""),
+    };
+
+    match (name, arg) {
+        ("w" | "write", "") => Ok(ExCommand::Write(None)),
+        ("w" | "write", path) => Ok(ExCommand::Write(Some(PathBuf::from(path)))),
+        ("wq" | "x" | "exit", "") => Ok(ExCommand::WriteQuit),
+        ("wa" | "wall", "") => Ok(ExCommand::WriteAll),
+        ("wqa" | "wqall" | "xa" | "xall", "") => Ok(ExCommand::WriteQuitAll),
+        ("qa" | "qall", "") => Ok(ExCommand::QuitAll { force: false }),
+        ("qa!" | "qall!", "") => Ok(ExCommand::QuitAll { force: true }),
+        ("q" | "quit", "") => Ok(ExCommand::Quit { force: false }),
+        ("q!" | "quit!", "") => Ok(ExCommand::Quit { force: true }),
+        ("e" | "edit" | "e!" | "edit!", "") => bail!("Usage: :e FILE"),
+        ("e" | "edit", path) => Ok(ExCommand::Edit {
+            path: PathBuf::from(path),
+            force: false,
+        }),
+        ("e!" | "edit!", path) => Ok(ExCommand::Edit {
+            path: PathBuf::from(path),
+            force: true,
+        }),
+        ("format" | "fmt", "") => Ok(ExCommand::Format),
+        ("hover", "") => Ok(ExCommand::Hover),
+        ("checkhealth" | "che" | "health", "") => Ok(ExCommand::CheckHealth),
+        ("hexview" | "hex", "") => Ok(ExCommand::HexView),
+        ("analyze" | "analyse" | "stats", "") => Ok(ExCommand::Analyze),
+        ("count" | "wc", "") => Ok(ExCommand::Count),
+        ("char" | "ascii" | "as", "") => Ok(ExCommand::InspectChar),
+        ("suspend" | "sus" | "stop" | "st", "") => Ok(ExCommand::Suspend),
+        ("fix", "") => Ok(ExCommand::Fix(None)),
+        ("fix", fix) => Ok(ExCommand::Fix(Some(fix.parse()?))),
+        ("mark" | "ma" | "k", "") => bail!("Usage: :mark NAME"),
+        ("mark" | "ma" | "k", name) => Ok(ExCommand::Mark(name.to_string())),
+        ("marks", "") => Ok(ExCommand::Marks),
+        ("align" | "al" | "align!" | "al!", delimiter) => Ok(ExCommand::Align {
+            delimiter: if delimiter.is_empty() { "=" } else { delimiter }.to_string(),
+            all: name.ends_with('!'),
+        }),
+        ("insert" | "ins", "") => bail!("Usage: :insert date|time|path|file|user|host|user@host"),
+        ("insert" | "ins", what) => Ok(ExCommand::Insert(what.parse()?)),
+        ("matches" | "mat", "") => Ok(ExCommand::Matches(None)),
+        ("matches" | "mat", text) => Ok(ExCommand::Matches(Some(text.to_string()))),
+        ("grep" | "gr", "") => Ok(ExCommand::Grep(None)),
+        ("ls" | "buffers", "") => Ok(ExCommand::Buffers),
+        ("b" | "buffer", "") => bail!("Usage: :b N (:ls lists the buffers)"),
+        ("b" | "buffer", number) => match number.parse() {
+            Ok(number) => Ok(ExCommand::Buffer(number)),
+            Err(_) => bail!("Invalid buffer number: {}", number),
+        },
+        ("bn" | "bnext", "") => Ok(ExCommand::NextBuffer),
+        ("bp" | "bprevious" | "bprev", "") => Ok(ExCommand::PrevBuffer),
+        ("grep" | "gr", text) => Ok(ExCommand::Grep(Some(text.to_string()))),
+        ("delmarks" | "delm", "") => bail!("Usage: :delmarks NAME (! for all)"),
+        ("delmarks" | "delm", name) => Ok(ExCommand::DeleteMark(name.to_string())),
+        ("delmarks!" | "delm!", "") => Ok(ExCommand::DeleteMark("!".to_string())),
+        ("set" | "se", "") => bail!("Usage: :set option[=value]"),
+        ("set" | "se", setting) => {
+            let (option, value) = match setting.split_once('=') {
+                Some((option, value)) => (option.trim(), Some(value.trim().to_string())),
+                None => (setting, None),
+            };
+            Ok(ExCommand::Set {
+                option: option.to_string(),
+                value,
+            })
+        }
+        _ => bail!("Unknown command: {}", input),
+    }
+}
+
+/// 解析 `/pattern/replacement/flags`（`rest` 以分隔符號開頭）
+fn parse_substitute(rest: &str) -> Result<ExCommand> {
+    let mut chars = rest.chars();
+    let Some(delimiter) = chars.next() else {
+        bail!("Usage: :s/pattern/replacement/[g]");
+    };
+
+    // 依分隔符號切成欄位，`\` 加分隔符號表示字面上的分隔符號
+    let mut fields = vec![String::new()];
+    let mut escaped = false;
+    for ch in chars {
+        let field_count = fields.len();
+        let field = fields.last_mut().expect("fields is never empty");
+        if escaped {
+            if ch != delimiter && ch != '\\' {
+                field.push('\\');
+            }
+            field.push(ch);
+            escaped = false;
+        } else if ch == '\\' {
+            escaped = true;
+        } else if ch == delimiter && field_count < 3 {
+            fields.push(String::new());
+        } else {
+            field.push(ch);
+        }
+    }
+    if escaped {
+        fields.last_mut().expect("fields is never empty").push('\\');
+    }
+
+    let mut fields = fields.into_iter();
+    let pattern = fields.next().unwrap_or_default();
+    let replacement = fields.next().unwrap_or_default();
+    let flags = fields.next().unwrap_or_default();
+
+    let mut global = false;
+    for flag in flags.trim().chars() {
+        match flag {
+            'g' => global = true,
+            _ => bail!("Unknown flag: {}", flag),
+        }
+    }
+
+    Ok(ExCommand::Substitute {
+        pattern,
+        replacement,
+        global,
+    })
+}
+
+/// 解析開關選項：`name`、`noname` 或 `name=on/off`，返回 (選項名稱, 開關)
+pub fn parse_flag<'a>(option: &'a str, value: Option<&str>) -> Result<(&'a str, bool)> {
+    match value {
+        Some(value) => {
+            let on = match value.to_lowercase().as_str() {
+                "on" | "true" | "yes" | "1" => true,
+                "off" | "false" | "no" | "0" => false,
+                _ => bail!("Invalid value for {}: {}", option, value),
+            };
+            Ok((option, on))
+        }
+        None => match option.strip_prefix("no") {
+            Some(name) if !name.is_empty() => Ok((name, false)),
+            _ => Ok((option, true)),
+        },
+    }
+}
+
+/// 以字面文字取代，返回 (取代後的文字, 取代次數)
+///
+/// 沒有 global 時與 vi 相同，每行只取代第一個符合的位置
+pub fn substitute(text: &str, pattern: &str, replacement: &str, global: bool) -> (String, usize) {
+    if pattern.is_empty() {
+        return (text.to_string(), 0);
+    }
+
+    let mut result = String::with_capacity(text.len());
+    let mut count = 0;
+    for line in text.split_inclusive('\n') {
+        if global {
+            count += line.matches(pattern).count();
+            result.push_str(&line.replace(pattern, replacement));
+        } else if let Some(pos) = line.find(pattern) {
+            count += 1;
+            result.push_str(&line[..pos]);
+            result.push_str(replacement);
+            result.push_str(&line[pos + pattern.len()..]);
+        } else {
+            result.push_str(line);
+        }
+    }
+
+    (result, count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_commands() {
+        assert_eq!(parse(":w").unwrap(), ExCommand::Write(None));
+        assert_eq!(
+            parse("w out.txt").unwrap(),
+            ExCommand::Write(Some(PathBuf::from("out.txt")))
+        );
+        assert_eq!(parse(":wq").unwrap(), ExCommand::WriteQuit);
+        assert_eq!(parse("q").unwrap(), ExCommand::Quit { force: false });
+        assert_eq!(parse(":qa!").unwrap(), ExCommand::QuitAll { force: true });
+        assert_eq!(parse(":wa").unwrap(), ExCommand::WriteAll);
+        assert_eq!(parse(":xa").unwrap(), ExCommand::WriteQuitAll);
+        assert_eq!(parse(":q!").unwrap(), ExCommand::Quit { force: true });
+        assert_eq!(
+            parse(":e! notes.md").unwrap(),
+            ExCommand::Edit {
+                path: PathBuf::from("notes.md"),
+                force: true
+            }
+        );
+        assert_eq!(parse(":42").unwrap(), ExCommand::GoToLine(42));
+        assert_eq!(parse(":stats").unwrap(), ExCommand::Analyze);
+        assert_eq!(
+            parse(":fix trailing").unwrap(),
+            ExCommand::Fix(Some(Fix::TrailingWhitespace))
+        );
+        assert!(parse(":fix tabs").is_err());
+        assert_eq!(
+            parse(":set number=off").unwrap(),
+            ExCommand::Set {
+                option: "number".to_string(),
+                value: Some("off".to_string())
+            }
+        );
+        assert_eq!(
+            parse(":!cargo build --release").unwrap(),
+            ExCommand::Shell("cargo build --release".to_string())
+        );
+        assert_eq!(
+            parse(":r !date").unwrap(),
+            ExCommand::ReadShell("date".to_string())
+        );
+        assert_eq!(
+            parse(":read!git log -1").unwrap(),
+            ExCommand::ReadShell("git log -1".to_string())
+        );
+        assert_eq!(
+            parse(":| sort -u").unwrap(),
+            ExCommand::Filter("sort -u".to_string())
+        );
+        assert!(parse(":r !").is_err());
+        assert!(parse(":|").is_err());
+        assert_eq!(parse(":mark a").unwrap(), ExCommand::Mark("a".to_string()));
+        assert_eq!(
+            parse(":'a").unwrap(),
+            ExCommand::JumpToMark("a".to_string())
+        );
+        assert_eq!(
+            parse(":delm!").unwrap(),
+            ExCommand::DeleteMark("!".to_string())
+        );
+        assert_eq!(parse(":matches").unwrap(), ExCommand::Matches(None));
+        assert_eq!(
+            parse(":align").unwrap(),
+            ExCommand::Align {
+                delimiter: "=".to_string(),
+                all: false
+            }
+        );
+        assert_eq!(
+            parse(":al! |").unwrap(),
+            ExCommand::Align {
+                delimiter: "|".to_string(),
+                all: true
+            }
+        );
+        assert_eq!(
+            parse(":mat port = 80").unwrap(),
+            ExCommand::Matches(Some("port = 80".to_string()))
+        );
+        assert_eq!(
+            parse(":insert timestamp").unwrap(),
+            ExCommand::Insert(Template::Time)
+        );
+        assert_eq!(
+            parse(":ins user@host").unwrap(),
+            ExCommand::Insert(Template::UserHost)
+        );
+        assert_eq!(parse(":wc").unwrap(), ExCommand::Count);
+        assert_eq!(parse(":ascii").unwrap(), ExCommand::InspectChar);
+        assert_eq!(parse(":stop").unwrap(), ExCommand::Suspend);
+        assert_eq!(parse(":fmt").unwrap(), ExCommand::Format);
+        assert_eq!(parse(":hover").unwrap(), ExCommand::Hover);
+        assert_eq!(parse(":grep").unwrap(), ExCommand::Grep(None));
+        assert_eq!(parse(":ls").unwrap(), ExCommand::Buffers);
+        assert_eq!(parse(":b 2").unwrap(), ExCommand::Buffer(2));
+        assert!(parse(":b two").is_err());
+        assert_eq!(parse(":bn").unwrap(), ExCommand::NextBuffer);
+        assert_eq!(
+            parse(":gr fn main").unwrap(),
+            ExCommand::Grep(Some("fn main".to_string()))
+        );
+        assert!(parse(":insert").is_err());
+        assert!(parse(":insert weather").is_err());
+        assert!(parse(":mark").is_err());
+        assert!(parse(":").is_err());
+        assert!(parse(":!").is_err());
+        assert!(parse(":e").is_err());
+        assert!(parse(":frobnicate").is_err());
+    }
+
+    #[test]
+    fn test_parse_substitute() {
+        assert_eq!(
+            parse(":s/foo/bar/g").unwrap(),
+            ExCommand::Substitute {
+                pattern: "foo".to_string(),
+                replacement: "bar".to_string(),
+                global: true
+            }
+        );
+        // 其他分隔符號與跳脫的分隔符號
+        assert_eq!(
+            parse(":s#a/b#c\\#d").unwrap(),
+            ExCommand::Substitute {
+                pattern: "a/b".to_string(),
+                replacement: "c#d".to_string(),
+                global: false
+            }
+        );
+        assert!(parse(":s/a/b/x").is_err());
+        // `set` 不是取代指令
+        assert!(matches!(parse(":set nu"), Ok(ExCommand::Set { .. })));
+    }
+
+    #[test]
+    fn test_parse_flag() {
+        assert_eq!(parse_flag("number", None).unwrap(), ("number", true));
+        assert_eq!(parse_flag("nonumber", None).unwrap(), ("number", false));
+        assert_eq!(parse_flag("bom", Some("off")).unwrap(), ("bom", false));
+        assert!(parse_flag("bom", Some("maybe")).is_err());
+    }
+
+    #[test]
+    fn test_substitute() {
+        let text = "foo foo\nbar\nfoo\n";
+        assert_eq!(
+            substitute(text, "foo", "x", false),
+            ("x foo\nbar\nx\n".to_string(), 2)
+        );
+        assert_eq!(
+            substitute(text, "foo", "x", true),
+            ("x x\nbar\nx\n".to_string(), 3)
+        );
+        assert_eq!(substitute(text, "", "x", true).1, 0);
+    }
+}
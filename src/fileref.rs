@@ -0,0 +1,139 @@
+// 檔案位置參照
+//
+// 解析編譯器、測試與 grep 輸出中常見的 `path/file.rs:42:7`、`file.rs:42`、
+// `file.cpp(42,7)` 與單純的檔案路徑，讓光標所在的參照可以直接開啟並跳到該位置
+
+/// 一個檔案位置參照；行與列從 1 開始
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileRef {
+    pub path: String,
+    pub line: Option<usize>,
+    pub col: Option<usize>,
+}
+
+/// 不屬於參照的字元（空白以外）
+fn is_delimiter(ch: char) -> bool {
+    ch.is_whitespace() || matches!(ch, '"' | '\'' | '`' | '<' | '>' | '|')
+}
+
+/// 找出第 `col` 個字元所在的參照；光標位於參照之後的空白上時也算
+pub fn reference_at(line: &str, col: usize) -> Option<FileRef> {
+    let chars: Vec<char> = line.chars().collect();
+    let mut col = col.min(chars.len());
+    if col == chars.len() || is_delimiter(chars[col]) {
+        // 光標在行尾或分隔字元上：改用前一個字元所在的參照
+        col = col.checked_sub(1).filter(|&c| !is_delimiter(chars[c]))?;
+    }
+    let start = chars[..col]
+        .iter()
+        .rposition(|&ch| is_delimiter(ch))
+        .map_or(0, |i| i + 1);
+    let end = chars[col..]
+        .iter()
+        .position(|&ch| is_delimiter(ch))
+        .map_or(chars.len(), |i| col + i);
+    let token: String = chars[start..end].iter().collect();
+    parse(&token)
+}
+
+/// 解析一個不含空白的參照
+pub fn parse(token: &str) -> Option<FileRef> {
+    let token = token
+        .trim_start_matches(['(', '[', '{'])
+        .trim_end_matches(['.', ',', ';', ':', ']', '}']);
+
+    // MSVC 與 C# 的 `file.cpp(42,7)`
+    if let Some((path, rest)) = token.strip_suffix(')').and_then(|t| t.rsplit_once('(')) {
+        let (line, col) = match rest.split_once(',') {
+            Some((line, col)) => (line, Some(col)),
+            None => (rest, None),
+        };
+        if let Ok(line) = line.parse() {
+            return file_ref(path, Some(line), col.and_then(|c| c.parse().ok()));
+        }
+    }
+    let token = token.trim_end_matches(')');
+
+    // `file:42:7`、`file:42`；從右邊拆，讓 Windows 的磁碟代號 `C:` 留在路徑中
+    let mut numbers = Vec::new();
+    let mut path = token;
+    while numbers.len() < 2 {
+        match path.rsplit_once(':') {
+            Some((rest, number)) if !number.is_empty() => match number.parse::<usize>() {
+                Ok(n) => {
+                    numbers.push(n);
+                    path = rest;
+                }
+                Err(_) => break,
+            },
+            _ => break,
+        }
+    }
+    numbers.reverse();
+    file_ref(path, numbers.first().copied(), numbers.get(1).copied())
+}
+
+fn file_ref(path: &str, line: Option<usize>, col: Option<usize>) -> Option<FileRef> {
+    // 至少要看起來像路徑：含有目錄分隔字元或副檔名
+    let looks_like_path = path.contains(['/', '\\']) || path.contains('.');
+    (looks_like_path && !path.ends_with(['/', '\\']) && !path.contains("://")).then(|| FileRef {
+        path: path.to_string(),
+        line: line.filter(|&n| n > 0),
+        col: col.filter(|&n| n > 0),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn r(path: &str, line: Option<usize>, col: Option<usize>) -> Option<FileRef> {
+        Some(FileRef {
+            path: path.to_string(),
+            line,
+            col,
+        })
+    }
+
+    #[test]
+    fn test_parse_reference_forms() {
+        assert_eq!(
+            parse("src/main.rs:42:7"),
+            r("src/main.rs", Some(42), Some(7))
+        );
+        assert_eq!(parse("src/main.rs:42:"), r("src/main.rs", Some(42), None));
+        assert_eq!(parse("(lib.rs:3)"), r("lib.rs", Some(3), None));
+        assert_eq!(parse("foo.cpp(12,5):"), r("foo.cpp", Some(12), Some(5)));
+        assert_eq!(parse("foo.cs(12)"), r("foo.cs", Some(12), None));
+        assert_eq!(
+            parse(r"C:\src\main.rs:3:1"),
+            r(r"C:\src\main.rs", Some(3), Some(1))
+        );
+        assert_eq!(parse("README.md"), r("README.md", None, None));
+        assert_eq!(parse("hello"), None);
+        assert_eq!(parse("https://example.com/a.rs"), None);
+    }
+
+    #[test]
+    fn test_reference_under_cursor() {
+        let line = "  --> src/editor.rs:120:9 here";
+        assert_eq!(
+            reference_at(line, 8),
+            r("src/editor.rs", Some(120), Some(9))
+        );
+        assert_eq!(
+            reference_at(line, 22),
+            r("src/editor.rs", Some(120), Some(9))
+        );
+        // 緊接在參照之後
+        assert_eq!(
+            reference_at(line, 25),
+            r("src/editor.rs", Some(120), Some(9))
+        );
+        assert_eq!(reference_at(line, 1), None);
+        assert_eq!(
+            reference_at("error: \"a/b.txt\" missing", 10),
+            r("a/b.txt", None, None)
+        );
+    }
+}
@@ -0,0 +1,311 @@
+// 檔案類型檢測（依內容推斷）
+//
+// 用於沒有副檔名的檔案：從前幾行內容推斷檔案類型，
+// 返回對應的副檔名，讓註解風格與語法高亮共用同一套副檔名對照
+
+use std::path::Path;
+
+/// 內容檢測時最多檢查的行數
+pub const SNIFF_LINES: usize = 20;
+
+/// git 開啟編輯器時使用的提交訊息檔名
+const GIT_MESSAGE_FILES: &[&str] = &["COMMIT_EDITMSG", "MERGE_MSG", "TAG_EDITMSG", "SQUASH_MSG"];
+
+/// `git commit -v` 在此行（前面加上註解字元）之後附上 diff，不屬於提交訊息
+const GIT_SCISSORS: &str = " ------------------------ >8 ------------------------";
+
+/// `core.commentChar=auto` 時 git 從這些字元中挑選註解字元
+const GIT_COMMENT_CANDIDATES: &str = "#;@!$%^&|:";
+
+/// 提交訊息的建議行寬；沒有設定 textwidth 時用於自動換行
+pub const GIT_MESSAGE_WIDTH: usize = 72;
+
+/// 是否為 git 提交訊息檔（COMMIT_EDITMSG 等）
+pub fn is_git_message(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|n| n.to_str())
+        .is_some_and(|name| GIT_MESSAGE_FILES.contains(&name))
+}
+
+/// 是否為 git 產生、以 `#` 為註解的檔案（提交訊息或 rebase 待辦清單）
+pub fn is_git_file(path: &Path) -> bool {
+    is_git_message(path) || path.file_name().is_some_and(|n| n == "git-rebase-todo")
+}
+
+/// 從 git 產生的說明文字推斷註解字元
+///
+/// git 在檔案結尾附上以註解字元開頭的說明，取最後一個以候選字元加空白開頭
+/// （或只有該字元）的行；找不到時返回 None
+pub fn detect_git_comment_char<I, S>(lines: I) -> Option<char>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    let mut found = None;
+    for line in lines {
+        let line = line.as_ref().trim_end_matches(['\n', '\r']);
+        let mut chars = line.chars();
+        if let Some(ch) = chars
+            .next()
+            .filter(|&ch| GIT_COMMENT_CANDIDATES.contains(ch))
+        {
+            if matches!(chars.next(), None | Some(' ')) {
+                found = Some(ch);
+            }
+        }
+    }
+    found
+}
+
+/// 統計提交訊息：返回 (摘要行字元數, 訊息行數)
+///
+/// 與 git 一致：忽略以 `comment` 開頭的註解行、開頭與結尾的空行，並在 scissors 行停止
+pub fn commit_message_stats<I, S>(lines: I, comment: &str) -> (usize, usize)
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<str>,
+{
+    let mut summary_chars = None;
+    let mut line_count = 0;
+    let mut pending_blank = 0;
+
+    for line in lines {
+        let line = line.as_ref().trim_end_matches(['\n', '\r']);
+        if line.strip_prefix(comment) == Some(GIT_SCISSORS) {
+            break;
+        }
+        if line.starts_with(comment) {
+            continue;
+        }
+        if line.trim().is_empty() {
+            // 空行只在之後還有內容時才計入
+            if summary_chars.is_some() {
+                pending_blank += 1;
+            }
+            continue;
+        }
+
+        if summary_chars.is_none() {
+            summary_chars = Some(line.trim_end().chars().count());
+        }
+        line_count += pending_blank + 1;
+        pending_blank = 0;
+    }
+
+    (summary_chars.unwrap_or(0), line_count)
+}
+
+/// 從檔案開頭內容推斷檔案類型，返回等效副檔名
+///
+/// 檢測順序：shebang > XML 宣告 > JSON > YAML 文件標記 > Makefile 規則
+pub fn detect_extension_from_content(content: &str) -> Option<&'static str> {
+    let first_line = content.lines().next()?;
+
+    if let Some(ext) = detect_from_shebang(first_line) {
+        return Some(ext);
+    }
+
+    let trimmed = content.trim_start_matches('\u{feff}').trim_start();
+
+    if trimmed.starts_with("<?xml") {
+        return Some("xml");
+    }
+
+    if trimmed.starts_with('{') {
+        return Some("json");
+    }
+
+    let first_line = trimmed.lines().next().unwrap_or("").trim_end();
+    // 只認單獨一行的 `---`；`--- a/file` 是 unified diff 的檔頭
+    if first_line == "---" || first_line.starts_with("%YAML") {
+        return Some("yaml");
+    }
+
+    if looks_like_makefile(content) {
+        return Some("mk");
+    }
+
+    None
+}
+
+/// 從 shebang 行推斷直譯器對應的副檔名
+fn detect_from_shebang(line: &str) -> Option<&'static str> {
+    let rest = line.strip_prefix("#!")?.trim();
+    let mut parts = rest.split_whitespace();
+    let mut program = parts.next()?.rsplit('/').next()?;
+
+    // #!/usr/bin/env [-S] python3
+    if program == "env" {
+        program = parts.find(|p| !p.starts_with('-'))?;
+    }
+
+    // 去掉版本號，例如 python3.11 -> python
+    let name = program.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+
+    match name {
+        "sh" | "bash" | "zsh" | "ksh" | "dash" | "ash" => Some("sh"),
+        "python" | "pypy" => Some("py"),
+        "node" | "nodejs" | "deno" => Some("js"),
+        "ruby" => Some("rb"),
+        "perl" => Some("pl"),
+        "lua" | "luajit" => Some("lua"),
+        "php" => Some("php"),
+        "pwsh" | "powershell" => Some("ps1"),
+        "Rscript" => Some("r"),
+        _ => None,
+    }
+}
+
+/// 判斷內容是否像 Makefile（目標規則後接 Tab 縮排的指令，或常見指示詞）
+fn looks_like_makefile(content: &str) -> bool {
+    let mut prev_is_rule = false;
+
+    for line in content.lines().take(SNIFF_LINES) {
+        if line.starts_with(".PHONY:") || line.starts_with("include ") {
+            return true;
+        }
+
+        if prev_is_rule && line.starts_with('\t') {
+            return true;
+        }
+
+        prev_is_rule = is_make_rule(line);
+    }
+
+    false
+}
+
+/// 判斷一行是否為 `target: deps` 形式的規則（排除 `:=` 賦值與 YAML 鍵值）
+fn is_make_rule(line: &str) -> bool {
+    if line.starts_with(char::is_whitespace) || line.starts_with('#') {
+        return false;
+    }
+
+    let Some(colon) = line.find(':') else {
+        return false;
+    };
+
+    let (target, rest) = line.split_at(colon);
+    if target.is_empty() || rest.starts_with(":=") || rest.starts_with("::=") {
+        return false;
+    }
+
+    target.chars().all(|c| {
+        c.is_alphanumeric() || matches!(c, '_' | '-' | '.' | '/' | '%' | '$' | '(' | ')' | ' ')
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shebang_detection() {
+        assert_eq!(
+            detect_extension_from_content("#!/bin/bash\necho hi"),
+            Some("sh")
+        );
+        assert_eq!(
+            detect_extension_from_content("#!/usr/bin/env python3\nprint(1)"),
+            Some("py")
+        );
+        assert_eq!(
+            detect_extension_from_content("#!/usr/bin/env -S node --flag\n"),
+            Some("js")
+        );
+        assert_eq!(detect_extension_from_content("#!/usr/bin/unknown\n"), None);
+    }
+
+    #[test]
+    fn test_markup_and_data_detection() {
+        assert_eq!(
+            detect_extension_from_content("<?xml version=\"1.0\"?>\n<a/>"),
+            Some("xml")
+        );
+        assert_eq!(
+            detect_extension_from_content("\n  {\"key\": 1}\n"),
+            Some("json")
+        );
+        assert_eq!(
+            detect_extension_from_content("---\nkey: value\n"),
+            Some("yaml")
+        );
+        assert_eq!(
+            detect_extension_from_content("%YAML 1.2\n---\n"),
+            Some("yaml")
+        );
+    }
+
+    #[test]
+    fn test_diff_is_not_yaml() {
+        let diff = "--- a/src/main.rs\n+++ b/src/main.rs\n@@ -1,2 +1,2 @@\n-key: old\n+key: new\n";
+        assert_eq!(detect_extension_from_content(diff), None);
+        assert_eq!(
+            detect_extension_from_content("--- old.txt\t2024-01-01\n+++ new.txt\t2024-01-02\n"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_makefile_detection() {
+        assert_eq!(
+            detect_extension_from_content("all: build\n\tcargo build\n"),
+            Some("mk")
+        );
+        assert_eq!(
+            detect_extension_from_content("CC := gcc\n.PHONY: all\n"),
+            Some("mk")
+        );
+        // YAML 風格的鍵值不應被誤判
+        assert_eq!(
+            detect_extension_from_content("name: test\nvalue: 1\n"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_git_files() {
+        assert!(is_git_message(Path::new(".git/COMMIT_EDITMSG")));
+        assert!(is_git_file(Path::new(".git/rebase-merge/git-rebase-todo")));
+        assert!(!is_git_message(Path::new(
+            ".git/rebase-merge/git-rebase-todo"
+        )));
+        assert!(!is_git_file(Path::new("COMMIT_EDITMSG.txt")));
+    }
+
+    #[test]
+    fn test_commit_message_stats() {
+        let message = [
+            "",
+            "Fix crash when saving\n",
+            "\n",
+            "Details here.\n",
+            "\n",
+            "# Please enter the commit message\n",
+            "# ------------------------ >8 ------------------------\n",
+            "diff --git a/x b/x\n",
+        ];
+        assert_eq!(commit_message_stats(message, "#"), (21, 3));
+        assert_eq!(commit_message_stats(["# only comments"], "#"), (0, 0));
+        assert_eq!(detect_git_comment_char(message), Some('#'));
+    }
+
+    #[test]
+    fn test_custom_comment_char() {
+        let message = [
+            "# Heading in the message\n",
+            "\n",
+            "; Please enter the commit message\n",
+            ";\n",
+        ];
+        assert_eq!(detect_git_comment_char(message), Some(';'));
+        assert_eq!(commit_message_stats(message, ";"), (24, 1));
+        assert_eq!(detect_git_comment_char(["Just text"]), None);
+    }
+
+    #[test]
+    fn test_plain_text() {
+        assert_eq!(detect_extension_from_content("Hello world\n"), None);
+        assert_eq!(detect_extension_from_content(""), None);
+    }
+}
@@ -0,0 +1,423 @@
+// 模糊檔案搜尋（Ctrl+O）
+//
+// 列出工作目錄下的檔案（略過 .git 與 .gitignore 忽略的項目），
+// 輸入時以模糊比對篩選：查詢的字元依序出現在路徑中即符合，連續、位於單字開頭或檔名中的字元分數較高
+
+use crate::terminal::Terminal;
+use crate::utils::{char_width, visual_width};
+use anyhow::Result;
+use crossterm::{
+    cursor,
+    event::{self, Event, KeyCode, KeyEventKind, KeyModifiers},
+    queue,
+    style::{self, Color},
+    terminal::{self, ClearType},
+};
+use std::path::Path;
+
+/// 最多列出的檔案數，避免在很大的目錄（例如家目錄）中花太久時間
+const MAX_FILES: usize = 100_000;
+
+/// .gitignore 中的一條規則
+#[derive(Debug)]
+struct IgnoreRule {
+    base: String, // .gitignore 所在的目錄（相對於根目錄，根目錄為空字串）
+    pattern: String,
+    negated: bool,  // `!pattern`：重新包含
+    dir_only: bool, // `pattern/`：只符合目錄
+    anchored: bool, // 含有 `/`：相對於 base 比對整個路徑，否則只比對名稱
+}
+
+impl IgnoreRule {
+    fn parse(base: &str, line: &str) -> Option<Self> {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let (negated, line) = match line.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let (dir_only, line) = match line.strip_suffix('/') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let anchored = line.contains('/');
+        Some(Self {
+            base: base.to_string(),
+            pattern: line.trim_start_matches('/').to_string(),
+            negated,
+            dir_only,
+            anchored,
+        })
+    }
+
+    /// `path` 為相對於根目錄、以 `/` 分隔的路徑
+    fn matches(&self, path: &str, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        let relative = if self.base.is_empty() {
+            path
+        } else {
+            match path
+                .strip_prefix(self.base.as_str())
+                .and_then(|rest| rest.strip_prefix('/'))
+            {
+                Some(rest) => rest,
+                None => return false,
+            }
+        };
+        if self.anchored {
+            glob_match(&self.pattern, relative)
+        } else {
+            let name = relative.rsplit('/').next().unwrap_or(relative);
+            glob_match(&self.pattern, name)
+        }
+    }
+}
+
+/// 簡單的 glob：`*` 與 `?` 不跨越 `/`，`**` 符合任意路徑
+fn glob_match(pattern: &str, text: &str) -> bool {
+    fn matches(pattern: &[char], text: &[char]) -> bool {
+        match pattern {
+            [] => text.is_empty(),
+            ['*', '*', rest @ ..] => {
+                let rest = rest.strip_prefix(&['/']).unwrap_or(rest);
+                (0..=text.len()).any(|i| matches(rest, &text[i..]))
+            }
+            ['*', rest @ ..] => (0..=text.len())
+                .take_while(|&i| i == 0 || text[i - 1] != '/')
+                .any(|i| matches(rest, &text[i..])),
+            ['?', rest @ ..] => {
+                text.first().is_some_and(|&ch| ch != '/') && matches(rest, &text[1..])
+            }
+            [ch, rest @ ..] => text.first() == Some(ch) && matches(rest, &text[1..]),
+        }
+    }
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    matches(&pattern, &text)
+}
+
+/// 列出 `root` 下的檔案（相對路徑，以 `/` 分隔並排序）
+pub fn index(root: &Path) -> Vec<String> {
+    let mut rules: Vec<IgnoreRule> = Vec::new();
+    let mut files = Vec::new();
+    let mut dirs = vec![String::new()];
+    while let Some(dir) = dirs.pop() {
+        let path = root.join(&dir);
+        if let Ok(text) = std::fs::read_to_string(path.join(".gitignore")) {
+            rules.extend(
+                text.lines()
+                    .filter_map(|line| IgnoreRule::parse(&dir, line)),
+            );
+        }
+        let Ok(entries) = std::fs::read_dir(&path) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            // 版本庫目錄與 wedi 自己的鎖定檔、復原檔
+            if name == ".git" || name.ends_with(".wedi-lock") || name.ends_with(".wedi-recovery") {
+                continue;
+            }
+            let relative = if dir.is_empty() {
+                name
+            } else {
+                format!("{}/{}", dir, name)
+            };
+            // 不跟隨指向目錄的符號連結，避免循環
+            let Ok(file_type) = entry.file_type() else {
+                continue;
+            };
+            let is_dir = file_type.is_dir();
+            // 最後一條符合的規則決定是否忽略
+            let ignored = rules
+                .iter()
+                .rev()
+                .find(|rule| rule.matches(&relative, is_dir))
+                .is_some_and(|rule| !rule.negated);
+            if ignored {
+                continue;
+            }
+            if is_dir {
+                dirs.push(relative);
+            } else {
+                files.push(relative);
+                if files.len() >= MAX_FILES {
+                    dirs.clear();
+                    break;
+                }
+            }
+        }
+    }
+    files.sort();
+    files
+}
+
+/// 模糊比對（不分大小寫）：返回分數與符合的字元位置，不符合時返回 None
+///
+/// 從尾端往前比對，讓符合的字元盡量落在檔名中
+pub fn fuzzy_match(candidate: &str, query: &str) -> Option<(i64, Vec<usize>)> {
+    let chars: Vec<char> = candidate.chars().collect();
+    let mut positions = Vec::with_capacity(query.chars().count());
+    let mut end = chars.len();
+    for q in query.chars().rev().filter(|ch| !ch.is_whitespace()) {
+        let q = q.to_lowercase().next().unwrap_or(q);
+        let index = chars[..end]
+            .iter()
+            .rposition(|ch| ch.to_lowercase().next() == Some(q))?;
+        positions.push(index);
+        end = index;
+    }
+    positions.reverse();
+
+    let basename_start = chars.iter().rposition(|&ch| ch == '/').map_or(0, |i| i + 1);
+    let mut score = 0i64;
+    for (i, &index) in positions.iter().enumerate() {
+        score += 1;
+        if i > 0 && positions[i - 1] + 1 == index {
+            score += 5;
+        }
+        let boundary = index == 0
+            || matches!(chars[index - 1], '/' | '_' | '-' | '.' | ' ')
+            || chars[index - 1].is_lowercase() && chars[index].is_uppercase();
+        if boundary {
+            score += 8;
+        }
+        if index >= basename_start {
+            score += 2;
+        }
+    }
+    // 第一個符合的字元之後的間隔越大分數越低
+    if let (Some(first), Some(last)) = (positions.first(), positions.last()) {
+        score -= ((last - first + 1) - positions.len()) as i64;
+    }
+    Some((score, positions))
+}
+
+/// 篩選並依分數排序（同分時路徑短的在前），返回項目索引與符合的字元位置
+pub fn filter(items: &[String], query: &str) -> Vec<(usize, Vec<usize>)> {
+    if query.trim().is_empty() {
+        return (0..items.len()).map(|index| (index, Vec::new())).collect();
+    }
+    let mut matches: Vec<(i64, usize, Vec<usize>)> = items
+        .iter()
+        .enumerate()
+        .filter_map(|(index, item)| {
+            fuzzy_match(item, query).map(|(score, positions)| (score, index, positions))
+        })
+        .collect();
+    matches.sort_by_key(|(score, index, _)| (-score, items[*index].len(), *index));
+    matches
+        .into_iter()
+        .map(|(_, index, positions)| (index, positions))
+        .collect()
+}
+
+/// 在畫面下方顯示搜尋框與結果，返回選擇的項目索引；ESC 返回 None
+///
+/// 輸入文字篩選，上下鍵（或 Ctrl+P/Ctrl+N）、PageUp/PageDown 移動選取項目，Enter 確定
+pub fn pick(items: &[String], terminal_size: (u16, u16)) -> Result<Option<usize>> {
+    let (cols, rows) = terminal_size;
+    let cols = cols as usize;
+    // 標題、輸入行與結果佔畫面下半部（不含狀態列）
+    let height = (rows as usize / 2).saturating_sub(2).max(1);
+    let title_row = (rows as usize).saturating_sub(height + 3);
+    let input_row = title_row + 1;
+
+    let mut query = String::new();
+    let mut results = filter(items, &query);
+    let mut selected = 0;
+    let mut top = 0;
+
+    loop {
+        if selected < top {
+            top = selected;
+        } else if selected >= top + height {
+            top = selected + 1 - height;
+        }
+
+        // 選單會覆寫編輯區，下一次重繪必須完整輸出
+        Terminal::invalidate_screen();
+        Terminal::begin_frame()?;
+        let header = format!(" Open file ({}/{})", results.len(), items.len());
+        let input = format!(" > {}", query);
+        queue!(
+            Terminal::output(),
+            cursor::MoveTo(0, title_row as u16),
+            style::SetBackgroundColor(Color::DarkBlue),
+            style::SetForegroundColor(Color::White),
+            style::Print(format!("{:<cols$}", header)),
+            cursor::MoveTo(0, input_row as u16),
+            style::SetBackgroundColor(Color::Black),
+            style::Print(&input),
+            terminal::Clear(ClearType::UntilNewLine),
+            style::ResetColor,
+        )?;
+        for offset in 0..height {
+            let screen_row = (input_row + 1 + offset) as u16;
+            let (foreground, background) = if top + offset == selected {
+                (Color::Black, Color::Cyan)
+            } else {
+                (Color::White, Color::DarkGrey)
+            };
+            queue!(
+                Terminal::output(),
+                cursor::MoveTo(0, screen_row),
+                style::SetBackgroundColor(background),
+                style::SetForegroundColor(foreground),
+            )?;
+            let mut width = 0;
+            if let Some((index, positions)) = results.get(top + offset) {
+                queue!(Terminal::output(), style::Print("   "))?;
+                width = 3;
+                for (ch, highlight) in
+                    visible_chars(&items[*index], positions, cols.saturating_sub(3))
+                {
+                    if highlight {
+                        queue!(Terminal::output(), style::SetForegroundColor(Color::Yellow))?;
+                    }
+                    queue!(Terminal::output(), style::Print(ch))?;
+                    if highlight {
+                        queue!(Terminal::output(), style::SetForegroundColor(foreground))?;
+                    }
+                    width += char_width(ch);
+                }
+            }
+            queue!(
+                Terminal::output(),
+                style::Print(" ".repeat(cols.saturating_sub(width))),
+                style::ResetColor,
+            )?;
+        }
+        let cursor_col = visual_width(&input).min(cols.saturating_sub(1));
+        Terminal::end_frame(Some((cursor_col as u16, input_row as u16)))?;
+
+        let Event::Key(key_event) = event::read()? else {
+            continue;
+        };
+        if key_event.kind != KeyEventKind::Press && key_event.kind != KeyEventKind::Repeat {
+            continue;
+        }
+        let last = results.len().saturating_sub(1);
+        let ctrl = key_event.modifiers.contains(KeyModifiers::CONTROL);
+        match key_event.code {
+            KeyCode::Esc => return Ok(None),
+            KeyCode::Enter => return Ok(results.get(selected).map(|(index, _)| *index)),
+            KeyCode::Up => selected = selected.saturating_sub(1),
+            KeyCode::Char('p') if ctrl => selected = selected.saturating_sub(1),
+            KeyCode::Down => selected = (selected + 1).min(last),
+            KeyCode::Char('n') if ctrl => selected = (selected + 1).min(last),
+            KeyCode::PageUp => selected = selected.saturating_sub(height),
+            KeyCode::PageDown => selected = (selected + height).min(last),
+            KeyCode::Backspace if query.pop().is_some() => {
+                results = filter(items, &query);
+                selected = 0;
+            }
+            KeyCode::Char(ch) if !ctrl => {
+                query.push(ch);
+                results = filter(items, &query);
+                selected = 0;
+            }
+            _ => {}
+        }
+    }
+}
+
+/// 寬度不夠時從開頭省略（保留檔名），返回 (字元, 是否符合)
+fn visible_chars(text: &str, positions: &[usize], width: usize) -> Vec<(char, bool)> {
+    let chars: Vec<(char, bool)> = text
+        .chars()
+        .enumerate()
+        .map(|(i, ch)| (ch, positions.contains(&i)))
+        .collect();
+    if visual_width(text) <= width {
+        return chars;
+    }
+    // 從尾端往前取，保留一格給省略號
+    let mut used = 1;
+    let mut start = chars.len();
+    while start > 0 && used + char_width(chars[start - 1].0) <= width {
+        start -= 1;
+        used += char_width(chars[start].0);
+    }
+    let mut visible = vec![('…', false)];
+    visible.extend_from_slice(&chars[start..]);
+    visible
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gitignore_rules() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        for path in [
+            "src/main.rs",
+            "src/gen/out.rs",
+            "target/debug/wedi",
+            "docs/a.log",
+            "docs/keep.log",
+            "sub/build/x.txt",
+            "sub/notes.txt",
+            "sub/.notes.txt.wedi-lock",
+            ".git/HEAD",
+        ] {
+            let path = root.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "").unwrap();
+        }
+        std::fs::write(
+            root.join(".gitignore"),
+            "/target/\n*.log\n!keep.log\nsrc/gen\n",
+        )
+        .unwrap();
+        std::fs::write(root.join("sub/.gitignore"), "build/\n").unwrap();
+
+        assert_eq!(
+            index(root),
+            [
+                ".gitignore",
+                "docs/keep.log",
+                "src/main.rs",
+                "sub/.gitignore",
+                "sub/notes.txt"
+            ]
+        );
+        assert!(glob_match("a/**/b", "a/x/y/b"));
+        assert!(glob_match("a/**/b", "a/b"));
+        assert!(!glob_match("*.rs", "src/main.rs"));
+    }
+
+    #[test]
+    fn test_fuzzy_ranking() {
+        let items: Vec<String> = [
+            "src/domain/main.rs",
+            "src/editor.rs",
+            "README.md",
+            "src/ex.rs",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+        let ranked = |query: &str| -> Vec<&str> {
+            filter(&items, query)
+                .into_iter()
+                .map(|(index, _)| items[index].as_str())
+                .collect()
+        };
+        assert_eq!(ranked("edr"), ["src/editor.rs"]);
+        assert_eq!(ranked("ed"), ["src/editor.rs", "README.md"]);
+        assert_eq!(ranked("MAIN")[0], "src/domain/main.rs");
+        assert_eq!(ranked("").len(), 4);
+        assert!(ranked("zzz").is_empty());
+
+        // 從尾端比對，符合的字元落在檔名中
+        let (_, positions) = fuzzy_match("src/domain/main.rs", "main").unwrap();
+        assert_eq!(positions, [11, 12, 13, 14]);
+    }
+}
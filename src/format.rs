@@ -0,0 +1,96 @@
+// 外部格式化程式
+//
+// 依檔案類型的副檔名選擇預設的格式化命令；命令從標準輸入讀取、輸出到標準輸出，
+// 由 `:format` 或存檔時（formatonsave）執行，`:set formatprg=CMD` 可以覆寫
+
+use std::path::Path;
+
+/// (副檔名, 命令)；命令中的 `{file}` 代換為檔案路徑，讓格式化程式依路徑選擇解析器與設定檔
+const FORMATTERS: &[(&[&str], &str)] = &[
+    (&["rs"], "rustfmt --edition 2021"),
+    (&["py", "pyi"], "black --quiet -"),
+    (&["sh", "bash", "zsh"], "shfmt -filename {file}"),
+    (&["go"], "gofmt"),
+    (
+        &["c", "h", "cc", "cpp", "cxx", "hpp", "hh", "m", "mm"],
+        "clang-format --assume-filename={file}",
+    ),
+    (
+        &[
+            "js", "jsx", "mjs", "cjs", "ts", "tsx", "json", "css", "scss", "less", "html", "vue",
+            "md", "markdown", "yaml", "yml", "graphql",
+        ],
+        "prettier --stdin-filepath {file}",
+    ),
+    (&["toml"], "taplo fmt -"),
+    (&["lua"], "stylua -"),
+    (&["zig"], "zig fmt --stdin"),
+];
+
+/// 依序以副檔名尋找預設的格式化命令（`{file}` 尚未代換）
+pub fn formatter_for<'a>(extensions: impl IntoIterator<Item = &'a str>) -> Option<&'static str> {
+    extensions.into_iter().find_map(|ext| {
+        let ext = ext.to_lowercase();
+        FORMATTERS
+            .iter()
+            .find(|(exts, _)| exts.contains(&ext.as_str()))
+            .map(|(_, command)| *command)
+    })
+}
+
+/// 把命令中的 `{file}` 代換為檔案路徑；沒有檔名時使用 `untitled.EXT`
+pub fn expand(command: &str, path: Option<&Path>, extension: Option<&str>) -> String {
+    if !command.contains("{file}") {
+        return command.to_string();
+    }
+    let file = match (path, extension) {
+        (Some(path), _) => path.to_string_lossy().into_owned(),
+        (None, Some(ext)) => format!("untitled.{}", ext),
+        (None, None) => "untitled".to_string(),
+    };
+    command.replace("{file}", &shell_quote(&file))
+}
+
+/// 讓路徑在 shell 中作為單一參數
+#[cfg(not(windows))]
+fn shell_quote(text: &str) -> String {
+    let plain = |ch: char| ch.is_ascii_alphanumeric() || "/._-+:,@".contains(ch);
+    if !text.is_empty() && text.chars().all(plain) {
+        text.to_string()
+    } else {
+        format!("'{}'", text.replace('\'', r"'\''"))
+    }
+}
+
+#[cfg(windows)]
+fn shell_quote(text: &str) -> String {
+    format!("\"{}\"", text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_formatter_lookup_and_expansion() {
+        assert_eq!(formatter_for(["rs"]), Some("rustfmt --edition 2021"));
+        assert_eq!(formatter_for(["txt", "PY"]), Some("black --quiet -"));
+        assert_eq!(formatter_for(["txt"]), None);
+
+        let prettier = formatter_for(["ts"]).unwrap();
+        assert_eq!(
+            expand(prettier, Some(Path::new("src/app.ts")), Some("ts")),
+            "prettier --stdin-filepath src/app.ts"
+        );
+        assert_eq!(
+            expand(prettier, None, Some("json")),
+            "prettier --stdin-filepath untitled.json"
+        );
+        #[cfg(not(windows))]
+        assert_eq!(
+            expand(prettier, Some(Path::new("my app's/x.ts")), None),
+            r"prettier --stdin-filepath 'my app'\''s/x.ts'"
+        );
+        assert_eq!(expand("gofmt", None, None), "gofmt");
+    }
+}
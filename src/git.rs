@@ -0,0 +1,182 @@
+// git 整合
+//
+// 透過執行 `git` 指令取得檔案所在儲存庫的分支、暫存狀態與 HEAD 版本內容，
+// 用於狀態列與行號欄的修改標記。找不到 git 或檔案不在儲存庫中時靜默停用。
+
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// 檔案在儲存庫中的狀態
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GitStatus {
+    pub branch: String,
+    pub staged: bool,    // 有已暫存（git add）的修改
+    pub unstaged: bool,  // 工作目錄中有未暫存的修改
+    pub untracked: bool, // 檔案尚未加入版本控制
+}
+
+impl GitStatus {
+    /// 狀態列顯示的文字，例如 `git:main (staged, unstaged)`
+    pub fn summary(&self) -> String {
+        let mut states = Vec::new();
+        if self.untracked {
+            states.push("untracked");
+        }
+        if self.staged {
+            states.push("staged");
+        }
+        if self.unstaged {
+            states.push("unstaged");
+        }
+
+        if states.is_empty() {
+            format!("git:{}", self.branch)
+        } else {
+            format!("git:{} ({})", self.branch, states.join(", "))
+        }
+    }
+}
+
+/// 位於 git 工作目錄中的檔案
+pub struct GitFile {
+    dir: PathBuf,       // 檔案所在目錄（作為 git -C 的參數）
+    file_name: PathBuf, // 相對於 dir 的檔名
+}
+
+impl GitFile {
+    /// 檢查檔案是否位於 git 工作目錄中
+    pub fn detect(path: &Path) -> Option<Self> {
+        let file_name = PathBuf::from(path.file_name()?);
+        let dir = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+
+        let output = run_git(&dir, &["rev-parse", "--is-inside-work-tree"])?;
+        (output.trim() == "true").then_some(Self { dir, file_name })
+    }
+
+    /// 取得分支名稱與檔案的暫存狀態
+    pub fn status(&self) -> Option<GitStatus> {
+        let output = run_git(
+            &self.dir,
+            &[
+                OsStr::new("status"),
+                OsStr::new("--porcelain=v1"),
+                OsStr::new("--branch"),
+                OsStr::new("--"),
+                self.file_name.as_os_str(),
+            ],
+        )?;
+        let mut status = parse_status(&output)?;
+
+        // 分離的 HEAD 改以縮寫的提交雜湊顯示
+        if status.branch.is_empty() {
+            status.branch = run_git(&self.dir, &["rev-parse", "--short", "HEAD"])
+                .map(|hash| hash.trim().to_string())
+                .unwrap_or_else(|| "HEAD".to_string());
+        }
+
+        Some(status)
+    }
+
+    /// 讀取檔案在 HEAD 中的內容（未追蹤或尚無提交時返回 None）
+    pub fn head_contents(&self) -> Option<Vec<u8>> {
+        let spec = format!("HEAD:./{}", self.file_name.to_string_lossy());
+        run_git_bytes(&self.dir, &["show", spec.as_str()])
+    }
+}
+
+/// 讀取 `core.commentChar`；未設定或為 `auto` 時返回 None
+///
+/// `dir` 可以是工作目錄或 `.git` 目錄（提交訊息檔所在處）
+pub fn comment_char(dir: &Path) -> Option<String> {
+    let value = run_git(dir, &["config", "--get", "core.commentChar"])?;
+    let value = value.trim_end_matches(['\n', '\r']);
+    (!value.is_empty() && value != "auto").then(|| value.to_string())
+}
+
+/// 解析 `git status --porcelain=v1 --branch` 的輸出
+///
+/// 分離的 HEAD 返回空的分支名稱
+fn parse_status(output: &str) -> Option<GitStatus> {
+    let mut lines = output.lines();
+    let header = lines.next()?.strip_prefix("## ")?;
+
+    let branch = if header.starts_with("HEAD (no branch)") {
+        String::new()
+    } else {
+        let name = header
+            .strip_prefix("No commits yet on ")
+            .or_else(|| header.strip_prefix("Initial commit on "))
+            .unwrap_or(header);
+        let end = name.find("...").or_else(|| name.find(' '));
+        end.map_or(name, |end| &name[..end]).to_string()
+    };
+
+    let mut status = GitStatus {
+        branch,
+        staged: false,
+        unstaged: false,
+        untracked: false,
+    };
+
+    for line in lines {
+        let mut codes = line.chars();
+        let (Some(index), Some(worktree)) = (codes.next(), codes.next()) else {
+            continue;
+        };
+        if index == '?' {
+            status.untracked = true;
+            continue;
+        }
+        status.staged |= index != ' ';
+        status.unstaged |= worktree != ' ';
+    }
+
+    Some(status)
+}
+
+/// 執行 git 並返回標準輸出（失敗時返回 None）
+fn run_git<S: AsRef<OsStr>>(dir: &Path, args: &[S]) -> Option<String> {
+    run_git_bytes(dir, args).map(|bytes| String::from_utf8_lossy(&bytes).into_owned())
+}
+
+fn run_git_bytes<S: AsRef<OsStr>>(dir: &Path, args: &[S]) -> Option<Vec<u8>> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    output.status.success().then_some(output.stdout)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_status() {
+        let status = parse_status("## main...origin/main [ahead 1]\nMM src/main.rs\n").unwrap();
+        assert_eq!(status.branch, "main");
+        assert!(status.staged && status.unstaged && !status.untracked);
+        assert_eq!(status.summary(), "git:main (staged, unstaged)");
+
+        let status = parse_status("## feature/x\n").unwrap();
+        assert_eq!(status.summary(), "git:feature/x");
+
+        let status = parse_status("## No commits yet on master\n?? notes.txt\n").unwrap();
+        assert_eq!(status.branch, "master");
+        assert!(status.untracked && !status.staged);
+
+        let status = parse_status("## HEAD (no branch)\nA  new.rs\n").unwrap();
+        assert!(status.branch.is_empty());
+        assert!(status.staged && !status.unstaged);
+
+        assert_eq!(parse_status(""), None);
+    }
+}
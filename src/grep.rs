@@ -0,0 +1,147 @@
+// 在工作目錄的所有檔案中尋找文字（`:grep`）
+//
+// 有安裝 ripgrep 時使用 `rg --vimgrep`，否則以 [`crate::finder::index`] 列出的檔案
+// （同樣略過 .gitignore 忽略的項目）逐一尋找；兩者都是不分正規表示式的純文字比對
+
+use anyhow::{bail, Context, Result};
+use std::path::Path;
+
+/// 最多列出的結果數
+const MAX_MATCHES: usize = 5_000;
+
+/// 內建搜尋略過超過此大小的檔案
+const MAX_FILE_SIZE: u64 = 10 * 1024 * 1024;
+
+/// 一個搜尋結果；`line` 與 `col`（字元）從 1 開始
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GrepMatch {
+    pub path: String,
+    pub line: usize,
+    pub col: usize,
+    pub text: String,
+}
+
+/// 在 `root` 下尋找 `query`，依路徑、行、列排序
+pub fn search(root: &Path, query: &str) -> Result<Vec<GrepMatch>> {
+    let mut matches = match ripgrep(root, query)? {
+        Some(matches) => matches,
+        None => search_files(root, query),
+    };
+    matches.sort_by(|a, b| (&a.path, a.line, a.col).cmp(&(&b.path, b.line, b.col)));
+    matches.truncate(MAX_MATCHES);
+    Ok(matches)
+}
+
+/// 以 ripgrep 搜尋；沒有安裝時返回 None
+fn ripgrep(root: &Path, query: &str) -> Result<Option<Vec<GrepMatch>>> {
+    let output = match std::process::Command::new("rg")
+        .args(["--vimgrep", "--fixed-strings", "--color", "never", "--"])
+        .args([query, "."])
+        .current_dir(root)
+        .stdin(std::process::Stdio::null())
+        .output()
+    {
+        Ok(output) => output,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).context("Cannot run rg"),
+    };
+    // 結束碼 1 表示沒有結果
+    if !output.status.success() && output.status.code() != Some(1) {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        bail!("rg: {}", stderr.lines().next().unwrap_or("failed"));
+    }
+    Ok(Some(parse_vimgrep(&String::from_utf8_lossy(
+        &output.stdout,
+    ))))
+}
+
+/// 解析 `path:line:col:text`（col 為位元組位置）
+fn parse_vimgrep(output: &str) -> Vec<GrepMatch> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut parts = line.splitn(4, ':');
+            let path = parts.next()?;
+            let line = parts.next()?.parse().ok()?;
+            let byte_col: usize = parts.next()?.parse().ok()?;
+            let text = parts.next()?;
+            let col = text
+                .get(..byte_col.saturating_sub(1))
+                .map_or(byte_col, |prefix| prefix.chars().count() + 1);
+            Some(GrepMatch {
+                path: path.trim_start_matches("./").to_string(),
+                line,
+                col,
+                text: text.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// 內建搜尋：略過過大與二進位檔案
+fn search_files(root: &Path, query: &str) -> Vec<GrepMatch> {
+    let mut matches = Vec::new();
+    for path in crate::finder::index(root) {
+        let full_path = root.join(&path);
+        if std::fs::metadata(&full_path).map_or(true, |m| m.len() > MAX_FILE_SIZE) {
+            continue;
+        }
+        let Ok(bytes) = std::fs::read(&full_path) else {
+            continue;
+        };
+        if bytes.iter().take(8192).any(|&b| b == 0) {
+            continue;
+        }
+        let text = String::from_utf8_lossy(&bytes);
+        for (index, line) in text.lines().enumerate() {
+            for (byte_col, _) in line.match_indices(query) {
+                matches.push(GrepMatch {
+                    path: path.clone(),
+                    line: index + 1,
+                    col: line[..byte_col].chars().count() + 1,
+                    text: line.to_string(),
+                });
+            }
+        }
+        if matches.len() >= MAX_MATCHES {
+            break;
+        }
+    }
+    matches
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_search_files_and_parse_vimgrep() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        std::fs::create_dir(root.join("src")).unwrap();
+        std::fs::write(
+            root.join("src/main.rs"),
+            "fn main() {\n    // 設定 port = 80\n}\n",
+        )
+        .unwrap();
+        std::fs::write(root.join("ignored.txt"), "port").unwrap();
+        std::fs::write(root.join("data.bin"), b"port\0\0").unwrap();
+        std::fs::write(root.join(".gitignore"), "ignored.txt\n").unwrap();
+
+        assert_eq!(
+            search_files(root, "port"),
+            [GrepMatch {
+                path: "src/main.rs".to_string(),
+                line: 2,
+                col: 11,
+                text: "    // 設定 port = 80".to_string(),
+            }]
+        );
+
+        // rg 的列是位元組位置
+        assert_eq!(
+            parse_vimgrep("src/main.rs:2:15:    // 設定 port = 80\n"),
+            search_files(root, "port")
+        );
+    }
+}
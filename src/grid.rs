@@ -0,0 +1,309 @@
+// 畫面格
+//
+// View 先把一幀畫進 Grid（每格一個字元與樣式），再與上一幀比較，
+// 只輸出有改變的格子：光標移動 + 樣式切換 + 字元，行尾空白以清除到行尾取代
+
+use crate::styled::SpanStyle;
+use crate::utils::char_width;
+use crossterm::{cursor, style, terminal, Command as _};
+
+// 未改變的格子少於此數時直接重印，不移動光標
+const SHORT_GAP: usize = 4;
+
+/// 畫面上的一格
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cell {
+    pub ch: char,             // 寬字元的第二格為 '\0'
+    pub combining: Vec<char>, // 附加在字元上的零寬字元（例如組合用重音符號）
+    pub style: SpanStyle,
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        Self {
+            ch: ' ',
+            combining: Vec::new(),
+            style: SpanStyle::default(),
+        }
+    }
+}
+
+impl Cell {
+    /// 寬字元的第二格
+    fn is_continuation(&self) -> bool {
+        self.ch == '\0'
+    }
+}
+
+/// 一幀畫面
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Grid {
+    cols: usize,
+    rows: usize,
+    cells: Vec<Cell>,
+}
+
+#[allow(dead_code)]
+impl Grid {
+    pub fn new(cols: usize, rows: usize) -> Self {
+        Self {
+            cols,
+            rows,
+            cells: vec![Cell::default(); cols * rows],
+        }
+    }
+
+    pub fn size(&self) -> (usize, usize) {
+        (self.cols, self.rows)
+    }
+
+    pub fn cell(&self, x: usize, y: usize) -> Option<&Cell> {
+        (x < self.cols && y < self.rows).then(|| &self.cells[y * self.cols + x])
+    }
+
+    fn row(&self, y: usize) -> &[Cell] {
+        &self.cells[y * self.cols..(y + 1) * self.cols]
+    }
+
+    /// 在 (x, y) 放一個字元，返回下一格的 x；超出畫面的部分捨棄
+    ///
+    /// 零寬字元附加在前一格；控制字元以 `?` 顯示，避免移動終端的游標
+    pub fn put_char(&mut self, x: usize, y: usize, ch: char, style: SpanStyle) -> usize {
+        if y >= self.rows {
+            return x;
+        }
+        let ch = if ch.is_control() { '?' } else { ch };
+        let width = char_width(ch);
+
+        if width == 0 {
+            if x > 0 && x <= self.cols {
+                let mut owner = y * self.cols + x - 1;
+                if self.cells[owner].is_continuation() && x >= 2 {
+                    owner -= 1;
+                }
+                self.cells[owner].combining.push(ch);
+            }
+            return x;
+        }
+        if x + width > self.cols {
+            // 放不下的寬字元以空白填滿剩餘的格子
+            if x < self.cols {
+                self.cells[y * self.cols + x] = Cell {
+                    style,
+                    ..Cell::default()
+                };
+            }
+            return self.cols;
+        }
+
+        let index = y * self.cols + x;
+        self.cells[index] = Cell {
+            ch,
+            combining: Vec::new(),
+            style,
+        };
+        if width == 2 {
+            self.cells[index + 1] = Cell {
+                ch: '\0',
+                combining: Vec::new(),
+                style,
+            };
+        }
+        x + width
+    }
+
+    /// 從 (x, y) 開始放一段文字，返回下一格的 x
+    pub fn put_str(&mut self, mut x: usize, y: usize, text: &str, style: SpanStyle) -> usize {
+        for ch in text.chars() {
+            x = self.put_char(x, y, ch, style);
+        }
+        x
+    }
+
+    /// 輸出從 `previous` 變成這一幀所需的控制碼（previous 為 None 或大小不同時完整輸出）
+    pub fn diff(&self, previous: Option<&Grid>) -> String {
+        let previous = previous.filter(|p| p.size() == self.size());
+        let mut out = Output::default();
+
+        for y in 0..self.rows {
+            let row = self.row(y);
+            let old_row = previous.map(|p| p.row(y));
+            let changed = |x: usize| old_row.is_none_or(|old| old[x] != row[x]);
+
+            // 行尾的空白以清除到行尾輸出
+            let content_end = row
+                .iter()
+                .rposition(|cell| *cell != Cell::default())
+                .map_or(0, |x| x + 1);
+
+            let mut x = 0;
+            while x < content_end {
+                if !changed(x) {
+                    // 與下一個改變的格子距離很近時直接重印，比移動光標的控制碼短
+                    let near_change = out.position == Some((x, y))
+                        && (x + 1..content_end.min(x + SHORT_GAP)).any(&changed);
+                    if !near_change {
+                        x += 1;
+                        continue;
+                    }
+                }
+
+                // 改到寬字元的任一半都要重印整個字元（終端不能只畫半個寬字元）
+                let mut start = x;
+                let old_continuation = old_row.is_some_and(|old| old[x].is_continuation());
+                if (row[x].is_continuation() || old_continuation)
+                    && x > 0
+                    && out.position != Some((x, y))
+                {
+                    start = x - 1;
+                    if row[start].is_continuation() && start > 0 {
+                        start -= 1;
+                    }
+                }
+
+                let mut next = start;
+                while next <= x {
+                    next = out.print(next, y, &row[next]);
+                }
+                x = next;
+            }
+
+            let old_has_tail =
+                old_row.is_none_or(|old| old[content_end..].iter().any(|c| *c != Cell::default()));
+            if content_end < self.cols && old_has_tail {
+                out.move_to(content_end, y);
+                out.set_style(SpanStyle::default());
+                let _ =
+                    terminal::Clear(terminal::ClearType::UntilNewLine).write_ansi(&mut out.text);
+            }
+        }
+
+        // 結束時還原為預設樣式，之後的輸出（對話框等）不受影響
+        if out.style.is_some_and(|style| style != SpanStyle::default()) {
+            out.set_style(SpanStyle::default());
+        }
+        out.text
+    }
+}
+
+/// 產生輸出時追蹤終端的游標位置與目前樣式，省略多餘的控制碼
+#[derive(Default)]
+struct Output {
+    text: String,
+    position: Option<(usize, usize)>,
+    style: Option<SpanStyle>, // None 表示不確定終端目前的樣式
+}
+
+impl Output {
+    fn move_to(&mut self, x: usize, y: usize) {
+        if self.position != Some((x, y)) {
+            let _ = cursor::MoveTo(x as u16, y as u16).write_ansi(&mut self.text);
+            self.position = Some((x, y));
+        }
+    }
+
+    fn set_style(&mut self, style: SpanStyle) {
+        let current = match self.style {
+            Some(current) => current,
+            None => {
+                let _ = style::SetAttribute(style::Attribute::Reset).write_ansi(&mut self.text);
+                SpanStyle::default()
+            }
+        };
+        style.transition_from(&current, &mut self.text);
+        self.style = Some(style);
+    }
+
+    /// 輸出 (x, y) 的格子，返回下一個要處理的 x
+    fn print(&mut self, x: usize, y: usize, cell: &Cell) -> usize {
+        if cell.is_continuation() {
+            // 前半已經輸出（或不存在），後半不需要輸出
+            return x + 1;
+        }
+        self.move_to(x, y);
+        self.set_style(cell.style);
+        self.text.push(cell.ch);
+        self.text.extend(cell.combining.iter());
+
+        let width = char_width(cell.ch);
+        self.position = Some((x + width, y));
+        x + width
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossterm::style::Color;
+
+    fn move_to(x: u16, y: u16) -> String {
+        let mut s = String::new();
+        let _ = cursor::MoveTo(x, y).write_ansi(&mut s);
+        s
+    }
+
+    #[test]
+    fn test_diff_outputs_only_changed_cells() {
+        let mut old = Grid::new(10, 2);
+        old.put_str(0, 0, "hello", SpanStyle::default());
+        old.put_str(0, 1, "world", SpanStyle::default());
+
+        let mut new = old.clone();
+        new.put_char(1, 0, 'a', SpanStyle::default());
+        assert_eq!(new.diff(Some(&old)), format!("{}\x1b[0ma", move_to(1, 0)));
+        assert_eq!(new.diff(Some(&new)), "");
+
+        // 連續改變的格子不需要重新移動光標
+        let mut new = old.clone();
+        new.put_str(0, 1, "wxyld", SpanStyle::default());
+        assert_eq!(new.diff(Some(&old)), format!("{}\x1b[0mxy", move_to(1, 1)));
+
+        // 中間只隔少數未改變的格子時一起重印
+        let mut new = old.clone();
+        new.put_str(0, 0, "Hello", SpanStyle::default());
+        new.put_char(3, 0, 'L', SpanStyle::default());
+        assert_eq!(
+            new.diff(Some(&old)),
+            format!("{}\x1b[0mHelL", move_to(0, 0))
+        );
+    }
+
+    #[test]
+    fn test_diff_styles_and_trailing_clear() {
+        let mut old = Grid::new(10, 1);
+        old.put_str(0, 0, "abcdef", SpanStyle::default());
+
+        let red = SpanStyle {
+            foreground: Some(Color::Red),
+            ..SpanStyle::default()
+        };
+        let mut new = Grid::new(10, 1);
+        new.put_str(0, 0, "ab", red);
+        let expected = format!("{}\x1b[0m\x1b[38;5;9mab\x1b[39m\x1b[K", move_to(0, 0));
+        assert_eq!(new.diff(Some(&old)), expected);
+
+        // 沒有上一幀時完整輸出，空白行也要清除
+        let blank = Grid::new(4, 2);
+        let expected = format!("{}\x1b[0m\x1b[K{}\x1b[K", move_to(0, 0), move_to(0, 1));
+        assert_eq!(blank.diff(None), expected);
+        assert_eq!(blank.diff(Some(&blank)), "");
+    }
+
+    #[test]
+    fn test_diff_reprints_whole_wide_char() {
+        let mut old = Grid::new(6, 1);
+        old.put_str(0, 0, "a中b", SpanStyle::default());
+
+        // 原本寬字元的後半被改成窄字元：前半也要重印
+        let mut new = Grid::new(6, 1);
+        new.put_str(0, 0, "a中b", SpanStyle::default());
+        new.put_char(1, 0, 'x', SpanStyle::default());
+        new.put_char(2, 0, 'y', SpanStyle::default());
+        assert_eq!(new.diff(Some(&old)), format!("{}\x1b[0mxy", move_to(1, 0)));
+
+        // 寬字元放不下時以空白填滿
+        let mut grid = Grid::new(3, 1);
+        assert_eq!(grid.put_str(0, 0, "ab中", SpanStyle::default()), 3);
+        assert_eq!(grid.cell(2, 0), Some(&Cell::default()));
+    }
+}
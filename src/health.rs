@@ -0,0 +1,310 @@
+// 健康檢查
+//
+// `:checkhealth` 檢查執行環境：目前的選項、系統剪貼簿、外部命令、語法高亮與終端，
+// 每個問題附上處理方式。編輯器收集各項結果，這裡提供共用的檢查與報告格式
+
+use crate::clipboard::ClipboardManager;
+use crossterm::style::Color;
+use std::env;
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+
+/// 檢查結果的等級
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Ok,
+    Info,
+    Warn,
+    Error,
+}
+
+impl Status {
+    fn label(self) -> &'static str {
+        match self {
+            Self::Ok => "OK",
+            Self::Info => "INFO",
+            Self::Warn => "WARN",
+            Self::Error => "ERROR",
+        }
+    }
+}
+
+/// 一項檢查結果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Check {
+    pub status: Status,
+    pub message: String,
+    pub advice: Option<String>, // 處理方式（WARN / ERROR）
+}
+
+impl Check {
+    pub fn ok(message: impl Into<String>) -> Self {
+        Self::new(Status::Ok, message.into(), None)
+    }
+
+    pub fn info(message: impl Into<String>) -> Self {
+        Self::new(Status::Info, message.into(), None)
+    }
+
+    pub fn warn(message: impl Into<String>, advice: impl Into<String>) -> Self {
+        Self::new(Status::Warn, message.into(), Some(advice.into()))
+    }
+
+    #[allow(dead_code)]
+    pub fn error(message: impl Into<String>, advice: impl Into<String>) -> Self {
+        Self::new(Status::Error, message.into(), Some(advice.into()))
+    }
+
+    fn new(status: Status, message: String, advice: Option<String>) -> Self {
+        Self {
+            status,
+            message,
+            advice,
+        }
+    }
+}
+
+/// 整份報告，依區段分組
+#[derive(Debug, Default)]
+pub struct Report {
+    sections: Vec<(String, Vec<Check>)>,
+}
+
+impl Report {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn section(&mut self, name: &str, checks: Vec<Check>) {
+        self.sections.push((name.to_string(), checks));
+    }
+
+    /// 指定等級的檢查數量
+    pub fn count(&self, status: Status) -> usize {
+        self.sections
+            .iter()
+            .flat_map(|(_, checks)| checks)
+            .filter(|check| check.status == status)
+            .count()
+    }
+
+    /// 一行摘要，例如 `1 error, 2 warnings`
+    pub fn summary(&self) -> String {
+        let errors = self.count(Status::Error);
+        let warnings = self.count(Status::Warn);
+        if errors == 0 && warnings == 0 {
+            return "All checks passed".to_string();
+        }
+        let plural = |n: usize| if n == 1 { "" } else { "s" };
+        format!(
+            "{} error{}, {} warning{}",
+            errors,
+            plural(errors),
+            warnings,
+            plural(warnings)
+        )
+    }
+
+    /// 報告的文字行（在 pager 中顯示）
+    pub fn lines(&self) -> Vec<String> {
+        let mut lines = vec![
+            format!("wedi {}: {}", env!("CARGO_PKG_VERSION"), self.summary()),
+            String::new(),
+        ];
+        for (name, checks) in &self.sections {
+            lines.push(name.clone());
+            for check in checks {
+                lines.push(format!("  {:<6}{}", check.status.label(), check.message));
+                if let Some(advice) = &check.advice {
+                    lines.push(format!("        -> {}", advice));
+                }
+            }
+            lines.push(String::new());
+        }
+        lines.pop();
+        lines
+    }
+}
+
+/// pager 中每行的顏色：區段標題與各等級的標籤
+pub fn line_color(line: &str) -> Option<Color> {
+    if !line.starts_with(' ') {
+        return (!line.is_empty()).then_some(Color::Cyan);
+    }
+    match line.split_whitespace().next() {
+        Some("OK") => Some(Color::Green),
+        Some("WARN") => Some(Color::Yellow),
+        Some("ERROR") => Some(Color::Red),
+        _ => None,
+    }
+}
+
+/// 在 PATH 中尋找可執行檔
+pub fn find_program(program: &str) -> Option<PathBuf> {
+    find_in(program, &env::var_os("PATH")?)
+}
+
+fn find_in(program: &str, path_var: &OsStr) -> Option<PathBuf> {
+    #[cfg(windows)]
+    let extensions: Vec<String> = env::var("PATHEXT")
+        .unwrap_or_else(|_| ".EXE;.CMD;.BAT;.COM".to_string())
+        .split(';')
+        .map(|ext| ext.to_string())
+        .collect();
+
+    env::split_paths(path_var).find_map(|dir| {
+        let candidate = dir.join(program);
+        if is_executable(&candidate) {
+            return Some(candidate);
+        }
+        #[cfg(windows)]
+        for ext in &extensions {
+            let candidate = dir.join(format!("{}{}", program, ext));
+            if candidate.is_file() {
+                return Some(candidate);
+            }
+        }
+        None
+    })
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    path.metadata()
+        .is_ok_and(|meta| meta.is_file() && meta.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+/// 檢查外部程式是否在 PATH 中；找不到時相關功能停用，以 WARN 回報
+pub fn program_check(program: &str, purpose: &str, advice: &str) -> Check {
+    match find_program(program) {
+        Some(path) => Check::ok(format!("{} ({}): {}", program, purpose, path.display())),
+        None => Check::warn(
+            format!("{} not found on PATH ({})", program, purpose),
+            advice,
+        ),
+    }
+}
+
+/// 系統剪貼簿：外部程式是否存在
+pub fn clipboard_checks(clipboard: &ClipboardManager) -> Vec<Check> {
+    if cfg!(windows) {
+        return vec![Check::ok("Using the Windows clipboard API")];
+    }
+    if !cfg!(feature = "system-clipboard") {
+        return vec![Check::warn(
+            "System clipboard support is disabled in this build",
+            "Copy and paste use the internal clipboard; rebuild with the system-clipboard feature",
+        )];
+    }
+
+    let programs = clipboard.programs();
+    if programs.is_empty() {
+        return vec![Check::warn(
+            "No display server found (WAYLAND_DISPLAY and DISPLAY are unset)",
+            "Copy and paste use the internal clipboard; run wedi inside a graphical session to share the system clipboard",
+        )];
+    }
+    programs
+        .into_iter()
+        .map(|program| {
+            let package = match program {
+                "wl-copy" | "wl-paste" => "Install the wl-clipboard package",
+                "xclip" => "Install the xclip package",
+                _ => "Make sure the program is on PATH",
+            };
+            program_check(program, "system clipboard", package)
+        })
+        .collect()
+}
+
+/// 終端：大小與 TERM
+pub fn terminal_checks(size: (u16, u16)) -> Vec<Check> {
+    let (cols, rows) = size;
+    let mut checks = Vec::new();
+    if cols < 40 || rows < 5 {
+        checks.push(Check::warn(
+            format!("Terminal is very small ({}x{})", cols, rows),
+            "Enlarge the window; the status bar and dialogs need at least 40x5",
+        ));
+    } else {
+        checks.push(Check::ok(format!("Size: {}x{}", cols, rows)));
+    }
+
+    match env::var("TERM") {
+        Ok(term) if term == "dumb" => checks.push(Check::warn(
+            "TERM is dumb",
+            "Set TERM to your terminal's type (e.g. xterm-256color)",
+        )),
+        Ok(term) => checks.push(Check::ok(format!("TERM: {}", term))),
+        Err(_) if cfg!(windows) => {}
+        Err(_) => checks.push(Check::info("TERM is not set")),
+    }
+    checks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report_lines_and_summary() {
+        let mut report = Report::new();
+        report.section(
+            "Commands",
+            vec![
+                Check::ok("sh: /bin/sh"),
+                Check::warn("git not found on PATH", "Install git"),
+            ],
+        );
+        report.section(
+            "Syntax",
+            vec![Check::error("Theme 'x' not found", "Use --theme")],
+        );
+
+        assert_eq!(report.summary(), "1 error, 1 warning");
+        let lines = report.lines();
+        assert!(lines[0].ends_with(": 1 error, 1 warning"));
+        assert_eq!(
+            lines[2..],
+            [
+                "Commands",
+                "  OK    sh: /bin/sh",
+                "  WARN  git not found on PATH",
+                "        -> Install git",
+                "",
+                "Syntax",
+                "  ERROR Theme 'x' not found",
+                "        -> Use --theme",
+            ]
+        );
+        assert_eq!(line_color(&lines[2]), Some(Color::Cyan));
+        assert_eq!(line_color(&lines[4]), Some(Color::Yellow));
+        assert_eq!(line_color(&lines[5]), None);
+
+        let mut clean = Report::new();
+        clean.section("Terminal", vec![Check::info("TERM is not set")]);
+        assert_eq!(clean.summary(), "All checks passed");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_find_in_path_requires_executable() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::TempDir::new().unwrap();
+        let tool = dir.path().join("tool");
+        std::fs::write(&tool, "#!/bin/sh\n").unwrap();
+        let path_var = env::join_paths([Path::new("/nonexistent"), dir.path()]).unwrap();
+        assert_eq!(find_in("tool", &path_var), None);
+
+        std::fs::set_permissions(&tool, std::fs::Permissions::from_mode(0o755)).unwrap();
+        assert_eq!(find_in("tool", &path_var), Some(tool));
+        assert_eq!(find_in("missing", &path_var), None);
+    }
+}
@@ -0,0 +1,167 @@
+// 二進位檔案偵測與十六進位檢視
+//
+// 開啟檔案前先檢查開頭的內容：含有 NUL 位元組或大量控制字元的檔案視為二進位檔。
+// 解碼成文字只會得到亂碼（而且可能是一整行數 MB 的內容），改為提供唯讀的十六進位檢視，
+// 每行顯示位移、16 個位元組的十六進位值與對應的 ASCII 字元
+
+use crate::terminal::Terminal;
+use anyhow::{Context, Result};
+use crossterm::style::Color;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+/// 偵測時讀取的開頭長度
+const SNIFF_LEN: u64 = 8192;
+
+/// 十六進位檢視最多讀取的長度（超過時只顯示開頭）
+const MAX_BYTES: u64 = 16 * 1024 * 1024;
+
+const BYTES_PER_LINE: usize = 16;
+
+/// 內容是否像二進位資料：含有 NUL，或超過一成是文字檔中少見的控制字元
+pub fn looks_binary(bytes: &[u8]) -> bool {
+    // 有 BOM 的是文字檔（UTF-16 的 ASCII 字元含有 NUL）
+    if [&[0xEF, 0xBB, 0xBF][..], &[0xFF, 0xFE], &[0xFE, 0xFF]]
+        .iter()
+        .any(|bom| bytes.starts_with(bom))
+    {
+        return false;
+    }
+    if bytes.contains(&0) {
+        return true;
+    }
+    // Tab、換行、換頁與 ESC（ANSI 色彩碼）在文字檔中很常見
+    let control = bytes
+        .iter()
+        .filter(|&&b| (b < 0x20 && !matches!(b, b'\t' | b'\n' | b'\r' | 0x0c | 0x1b)) || b == 0x7f)
+        .count();
+    control * 10 > bytes.len()
+}
+
+/// 檢查檔案開頭是否為二進位資料；檔案不存在或無法讀取時返回 false，由開啟檔案時回報錯誤
+pub fn is_binary_file(path: &Path) -> bool {
+    let mut head = Vec::new();
+    File::open(path)
+        .and_then(|file| file.take(SNIFF_LEN).read_to_end(&mut head))
+        .is_ok_and(|_| looks_binary(&head))
+}
+
+/// 十六進位檢視的各行，例如
+/// `00000000  7f 45 4c 46 02 01 01 00  00 00 00 00 00 00 00 00  |.ELF............|`
+pub fn dump_lines(bytes: &[u8]) -> Vec<String> {
+    bytes
+        .chunks(BYTES_PER_LINE)
+        .enumerate()
+        .map(|(index, chunk)| {
+            let mut hex = String::new();
+            for i in 0..BYTES_PER_LINE {
+                if i == BYTES_PER_LINE / 2 {
+                    hex.push(' ');
+                }
+                match chunk.get(i) {
+                    Some(byte) => hex.push_str(&format!("{:02x} ", byte)),
+                    None => hex.push_str("   "),
+                }
+            }
+            let ascii: String = chunk
+                .iter()
+                .map(|&b| {
+                    if b.is_ascii_graphic() || b == b' ' {
+                        b as char
+                    } else {
+                        '.'
+                    }
+                })
+                .collect();
+            format!("{:08x}  {} |{}|", index * BYTES_PER_LINE, hex, ascii)
+        })
+        .collect()
+}
+
+/// pager 中每行的顏色：只顯示開頭時的提示行
+pub fn line_color(line: &str) -> Option<Color> {
+    line.starts_with("...").then_some(Color::Yellow)
+}
+
+/// 以十六進位顯示磁碟上的檔案內容（唯讀，按 ESC 或 q 關閉）
+pub fn show(path: &Path, terminal_size: (u16, u16)) -> Result<()> {
+    let file =
+        File::open(path).with_context(|| format!("Failed to read file: {}", path.display()))?;
+    let total = file.metadata()?.len();
+    let mut bytes = Vec::new();
+    file.take(MAX_BYTES)
+        .read_to_end(&mut bytes)
+        .with_context(|| format!("Failed to read file: {}", path.display()))?;
+
+    let mut lines = dump_lines(&bytes);
+    if (bytes.len() as u64) < total {
+        lines.push(format!(
+            "... showing the first {} of {} bytes",
+            bytes.len(),
+            total
+        ));
+    }
+    let title = format!("Hex view (read-only): {} ({} bytes)", path.display(), total);
+    crate::dialog::pager(&title, &lines, terminal_size, line_color)
+}
+
+/// 開啟二進位檔案前詢問使用者：十六進位檢視、仍以文字編輯或離開
+///
+/// `ask` 為 false 時（`--hex`）直接進入十六進位檢視。返回 true 表示繼續以文字開啟
+pub fn open_binary(path: &Path, ask: bool) -> Result<bool> {
+    // terminal 離開作用域時恢復終端設定
+    let terminal = Terminal::new()?;
+    Terminal::enter_raw_mode()?;
+    let choice = if ask {
+        let name = path.file_name().unwrap_or(path.as_os_str());
+        let message = format!("{} looks like a binary file.", name.to_string_lossy());
+        crate::dialog::choose(
+            &message,
+            &[('h', "Hex view"), ('e', "Edit as text"), ('q', "Quit")],
+            terminal.size(),
+        )?
+    } else {
+        Some('h')
+    };
+    match choice {
+        Some('h') => show(path, terminal.size()).map(|_| false),
+        Some('e') => Ok(true),
+        _ => Ok(false),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_looks_binary() {
+        assert!(looks_binary(b"\x7fELF\x02\x01\x01\x00"));
+        assert!(looks_binary(&[0x01, 0x02, 0x03, b'a', b'b', b'c', b'd']));
+        assert!(!looks_binary(
+            b"fn main() {\r\n\tprintln!(\"\x1b[1mhi\");\n}\n"
+        ));
+        assert!(!looks_binary(b""));
+        // UTF-16LE 的 "hi"：有 BOM 時雖然含 NUL 仍是文字
+        assert!(!looks_binary(&[0xFF, 0xFE, b'h', 0, b'i', 0]));
+        // 非 ASCII 的位元組（其他編碼的文字）不算控制字元
+        assert!(!looks_binary("中文內容".as_bytes()));
+    }
+
+    #[test]
+    fn test_dump_lines() {
+        let bytes: Vec<u8> = (0x3e..0x52).collect();
+        assert_eq!(
+            dump_lines(&bytes),
+            vec![
+                "00000000  3e 3f 40 41 42 43 44 45  46 47 48 49 4a 4b 4c 4d  |>?@ABCDEFGHIJKLM|",
+                "00000010  4e 4f 50 51                                       |NOPQ|",
+            ]
+        );
+        assert_eq!(
+            dump_lines(b"a\0\n"),
+            vec!["00000000  61 00 0a                                          |a..|"]
+        );
+    }
+}
@@ -1,19 +1,26 @@
-//! 語法高亮快取系統（簡化版本）
+//! 語法高亮快取系統
 //!
-//! 由於 syntect 的 ParseState 是私有的，我們只快取已高亮的字串
+//! 快取每行的高亮結果以及該行開始、結束時的語法狀態，
+//! 編輯後只需重新高亮狀態或內容改變的行
 
+use super::engine::LineState;
+use crate::styled::StyledLine;
 use std::collections::HashMap;
 
 /// 單行的高亮快取項目
 ///
-/// ⚠️ 注意：不包含 ParseState，因為 syntect 的 ParseState 是私有的
-/// 快取失效策略：修改任何一行時，使該行及之後所有行失效
+/// 快取有效條件：文字相同，且開始狀態等於上一行目前的結束狀態。
+/// 因此修改一行只需使該行失效，之後的行會在狀態改變時自動重新高亮
 #[derive(Clone, Debug)]
 pub struct CachedLine {
     /// 原始文字內容（用於驗證快取是否有效）
     pub text: String,
-    /// 高亮後的 ANSI 字串
-    pub highlighted: String,
+    /// 高亮後帶樣式的文字
+    pub highlighted: StyledLine,
+    /// 該行開始時的語法狀態
+    pub start_state: LineState,
+    /// 該行結束時的語法狀態（下一行的開始狀態）
+    pub end_state: LineState,
 }
 
 /// 語法狀態快取（用於優化效能）
@@ -22,6 +29,8 @@ pub struct HighlightCache {
     lines: HashMap<usize, CachedLine>,
     /// 快取大小限制
     max_size: usize,
+    /// 每次清除後遞增；背景高亮的結果只在世代相同時寫入
+    generation: u64,
 }
 
 impl HighlightCache {
@@ -35,20 +44,31 @@ impl HighlightCache {
         Self {
             lines: HashMap::with_capacity(max_size.min(1000)),
             max_size,
+            generation: 0,
         }
     }
 
+    /// 目前的世代（主題、語法或行號改變而清除快取時遞增）
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
     /// 取得快取的行
+    #[allow(dead_code)]
     pub fn get(&self, line_idx: usize) -> Option<&CachedLine> {
         self.lines.get(&line_idx)
     }
 
-    /// 檢查行是否已快取且內容相同
-    pub fn is_valid(&self, line_idx: usize, text: &str) -> bool {
+    /// 取得內容與開始狀態都相符的快取行
+    pub fn get_valid(
+        &self,
+        line_idx: usize,
+        text: &str,
+        start_state: &LineState,
+    ) -> Option<&CachedLine> {
         self.lines
             .get(&line_idx)
-            .map(|cached| cached.text == text)
-            .unwrap_or(false)
+            .filter(|cached| cached.text == text && &cached.start_state == start_state)
     }
 
     /// 插入快取項目
@@ -63,7 +83,6 @@ impl HighlightCache {
     }
 
     /// 使指定行失效
-    #[allow(dead_code)]
     pub fn invalidate(&mut self, line_idx: usize) {
         self.lines.remove(&line_idx);
     }
@@ -77,8 +96,7 @@ impl HighlightCache {
     }
 
     /// 使從指定行開始的所有行失效
-    ///
-    /// ⚠️ 這是因為語法狀態可能影響後續所有行（如多行註解）
+    #[allow(dead_code)]
     pub fn invalidate_from(&mut self, line_idx: usize) {
         self.lines.retain(|&idx, _| idx < line_idx);
     }
@@ -87,9 +105,10 @@ impl HighlightCache {
     pub fn invalidate_from_edit(&mut self, line_idx: usize, edit_type: EditType) {
         match edit_type {
             EditType::CharInsert | EditType::CharDelete => {
-                // 字元級編輯：使當前行及之後所有行失效
-                // （因為可能影響語法狀態，例如開始/結束多行註解）
-                self.invalidate_from(line_idx);
+                // 字元級編輯：只使當前行失效
+                // 若語法狀態因此改變（例如開始/結束多行註解），後續行的開始狀態不再相符，
+                // 會在下次高亮時依序重新計算
+                self.invalidate(line_idx);
             }
             EditType::LineInsert | EditType::LineDelete | EditType::MultiLineEdit => {
                 // 行級編輯：清除所有快取（行號改變）
@@ -101,6 +120,7 @@ impl HighlightCache {
     /// 清除所有快取
     pub fn clear(&mut self) {
         self.lines.clear();
+        self.generation += 1;
     }
 
     /// 取得快取統計資訊
@@ -161,29 +181,38 @@ pub enum EditType {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::highlight::HighlightEngine;
+    use std::path::Path;
+
+    fn cached_line(text: &str) -> CachedLine {
+        let mut engine = HighlightEngine::new(None, true).unwrap();
+        engine.set_file(Some(Path::new("test.txt")));
+        let state = engine.create_highlighter().unwrap().state().clone();
+        CachedLine {
+            text: text.to_string(),
+            highlighted: StyledLine::new(),
+            start_state: state.clone(),
+            end_state: state,
+        }
+    }
 
     #[test]
     fn test_cache_basic() {
         let mut cache = HighlightCache::new();
 
-        let cached = CachedLine {
-            text: "test".to_string(),
-            highlighted: "\x1b[0mtest\x1b[0m".to_string(),
-        };
+        let cached = cached_line("test");
+        let state = cached.start_state.clone();
 
-        cache.insert(0, cached.clone());
-        assert!(cache.is_valid(0, "test"));
-        assert!(!cache.is_valid(0, "different"));
+        cache.insert(0, cached);
+        assert!(cache.get_valid(0, "test", &state).is_some());
+        assert!(cache.get_valid(0, "different", &state).is_none());
     }
 
     #[test]
     fn test_cache_invalidation() {
         let mut cache = HighlightCache::new();
 
-        let cached = CachedLine {
-            text: "test".to_string(),
-            highlighted: String::new(),
-        };
+        let cached = cached_line("test");
 
         cache.insert(0, cached.clone());
         cache.insert(1, cached.clone());
@@ -204,10 +233,7 @@ mod tests {
     fn test_smart_invalidation() {
         let mut cache = HighlightCache::new();
 
-        let cached = CachedLine {
-            text: "test".to_string(),
-            highlighted: String::new(),
-        };
+        let cached = cached_line("test");
 
         // 建立 10 行快取
         for i in 0..10 {
@@ -216,22 +242,20 @@ mod tests {
 
         assert_eq!(cache.len(), 10);
 
-        // 字元編輯：使第 5 行及之後失效
+        // 字元編輯：只使第 5 行失效，之後的行由開始狀態判斷是否需要重新高亮
         cache.invalidate_from_edit(5, EditType::CharInsert);
 
-        assert_eq!(cache.len(), 5);
+        assert_eq!(cache.len(), 9);
         assert!(cache.get(4).is_some());
         assert!(cache.get(5).is_none());
+        assert!(cache.get(6).is_some());
     }
 
     #[test]
     fn test_line_edit_clears_all() {
         let mut cache = HighlightCache::new();
 
-        let cached = CachedLine {
-            text: "test".to_string(),
-            highlighted: String::new(),
-        };
+        let cached = cached_line("test");
 
         for i in 0..10 {
             cache.insert(i, cached.clone());
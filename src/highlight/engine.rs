@@ -5,15 +5,21 @@
 //!
 //! 實現特點：
 //! - Token 層級過濾換行符（避免 Linux 終端殘影問題）
-//! - 優化 ANSI 碼生成（只在顏色變化時輸出，減少輸出大小）
+//! - 輸出結構化的 StyledLine，相鄰同色的 token 合併為一段，由 View 合成樣式後輸出
+//! - 設定目錄下 `themes/*.tmTheme` 與 `syntaxes/*.sublime-syntax` 在載入時合併到內建的主題與語法
 
+use crate::styled::{SpanStyle, StyledLine};
 use anyhow::{Context, Result};
-use once_cell::sync::Lazy;
-use std::fmt::Write;
-use std::path::Path;
-use syntect::easy::HighlightLines;
-use syntect::highlighting::{Color, Style, Theme, ThemeSet};
-use syntect::parsing::{SyntaxReference, SyntaxSet};
+use crossterm::style::Color;
+use once_cell::sync::{Lazy, OnceCell};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use syntect::highlighting::{
+    HighlightIterator, HighlightState, Highlighter, Style, Theme, ThemeSet,
+};
+use syntect::parsing::{ParseState, ScopeStack, SyntaxDefinition, SyntaxReference, SyntaxSet};
 
 /// 嵌入的語法集（來自 bat 專案）
 ///
@@ -22,16 +28,167 @@ use syntect::parsing::{SyntaxReference, SyntaxSet};
 /// 包含 219 種語法定義，原始來源為 Sublime Text packages (MIT License)
 const SERIALIZED_SYNTAX_SET: &[u8] = include_bytes!("../../assets/syntaxes.bin");
 
-/// 全域語法集（延遲載入）
-static SYNTAX_SET: Lazy<SyntaxSet> =
-    Lazy::new(|| load_syntax_set().expect("Failed to load embedded syntax set"));
+/// 全域語法集（延遲載入，可由背景執行緒預先載入）
+static SYNTAX_SET: OnceCell<SyntaxSet> = OnceCell::new();
 
-/// 全域主題集（使用 syntect 內建主題）
-static THEME_SET: Lazy<ThemeSet> = Lazy::new(ThemeSet::load_defaults);
+/// 是否已啟動背景載入
+static PRELOAD_STARTED: AtomicBool = AtomicBool::new(false);
 
-/// 載入語法集（未壓縮版本）
+/// 全域主題集（syntect 內建主題加上使用者的主題）
+static THEME_SET: Lazy<ThemeSet> = Lazy::new(|| {
+    let mut themes = ThemeSet::load_defaults();
+    if let Some(dir) = user_dir("themes") {
+        let loaded = add_user_themes(&mut themes, &dir);
+        record_user_assets(loaded, |assets| &mut assets.themes);
+    }
+    themes
+});
+
+/// 使用者自訂的主題與語法（顯示在 `:checkhealth`）
+static USER_ASSETS: Mutex<UserAssets> = Mutex::new(UserAssets {
+    themes: Vec::new(),
+    syntaxes: Vec::new(),
+    errors: Vec::new(),
+});
+
+/// 從設定目錄載入的主題與語法名稱，以及無法載入的檔案
+#[derive(Debug, Clone, Default)]
+pub struct UserAssets {
+    pub themes: Vec<String>,
+    pub syntaxes: Vec<String>,
+    pub errors: Vec<String>,
+}
+
+/// 目前已載入的自訂主題與語法（語法集在背景載入完成前只有主題）
+pub fn user_assets() -> UserAssets {
+    USER_ASSETS
+        .lock()
+        .map(|assets| assets.clone())
+        .unwrap_or_default()
+}
+
+/// 設定目錄下存放自訂主題或語法的子目錄
+fn user_dir(name: &str) -> Option<PathBuf> {
+    crate::config::config_dir().map(|dir| dir.join(name))
+}
+
+fn record_user_assets(
+    (names, errors): (Vec<String>, Vec<String>),
+    field: fn(&mut UserAssets) -> &mut Vec<String>,
+) {
+    if let Ok(mut assets) = USER_ASSETS.lock() {
+        field(&mut assets).extend(names);
+        assets.errors.extend(errors);
+    }
+}
+
+/// 目錄中指定副檔名的檔案（依檔名排序）；目錄不存在時為空
+fn files_with_extension(dir: &Path, extension: &str) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut files: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.is_file()
+                && path
+                    .extension()
+                    .is_some_and(|ext| ext.eq_ignore_ascii_case(extension))
+        })
+        .collect();
+    files.sort();
+    files
+}
+
+/// 把目錄中的 `.tmTheme` 加入主題集，主題名稱為檔名（同名時取代內建主題）
+///
+/// 返回 (載入的主題名稱, 錯誤訊息)
+fn add_user_themes(themes: &mut ThemeSet, dir: &Path) -> (Vec<String>, Vec<String>) {
+    let mut names = Vec::new();
+    let mut errors = Vec::new();
+    for path in files_with_extension(dir, "tmTheme") {
+        let name = path
+            .file_stem()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned();
+        match ThemeSet::get_theme(&path) {
+            Ok(theme) => {
+                themes.themes.insert(name.clone(), theme);
+                names.push(name);
+            }
+            Err(e) => errors.push(format!("{}: {}", path.display(), e)),
+        }
+    }
+    (names, errors)
+}
+
+/// 把目錄中的 `.sublime-syntax` 加入語法集；副檔名與內建語法重複時優先使用自訂的語法
+///
+/// 沒有自訂語法時直接返回原本的語法集（重新連結所有語法較費時）
+fn add_user_syntaxes(set: SyntaxSet, dir: &Path) -> (SyntaxSet, Vec<String>, Vec<String>) {
+    let files = files_with_extension(dir, "sublime-syntax");
+    let mut names = Vec::new();
+    let mut errors = Vec::new();
+    let mut definitions = Vec::new();
+    for path in files {
+        let fallback_name = path.file_stem().map(|stem| stem.to_string_lossy());
+        let definition = fs::read_to_string(&path)
+            .map_err(anyhow::Error::from)
+            .and_then(|text| {
+                SyntaxDefinition::load_from_str(&text, true, fallback_name.as_deref())
+                    .map_err(anyhow::Error::from)
+            });
+        match definition {
+            Ok(definition) => {
+                names.push(definition.name.clone());
+                definitions.push(definition);
+            }
+            Err(e) => errors.push(format!("{}: {}", path.display(), e)),
+        }
+    }
+    if definitions.is_empty() {
+        return (set, names, errors);
+    }
+    let mut builder = set.into_builder();
+    for definition in definitions {
+        builder.add(definition);
+    }
+    (builder.build(), names, errors)
+}
+
+/// 載入語法集（未壓縮版本），加上使用者的語法
 fn load_syntax_set() -> Result<SyntaxSet> {
-    bincode::deserialize(SERIALIZED_SYNTAX_SET).context("Failed to deserialize syntax set")
+    let set: SyntaxSet =
+        bincode::deserialize(SERIALIZED_SYNTAX_SET).context("Failed to deserialize syntax set")?;
+    let Some(dir) = user_dir("syntaxes") else {
+        return Ok(set);
+    };
+    let (set, names, errors) = add_user_syntaxes(set, &dir);
+    record_user_assets((names, errors), |assets| &mut assets.syntaxes);
+    Ok(set)
+}
+
+/// 取得語法集（尚未載入時會阻塞直到載入完成）
+fn syntax_set() -> &'static SyntaxSet {
+    SYNTAX_SET.get_or_init(|| load_syntax_set().expect("Failed to load embedded syntax set"))
+}
+
+/// 在背景執行緒反序列化語法集，讓檔案可以先以純文字顯示
+///
+/// 之後對語法的查詢會延後到載入完成，見 [`HighlightEngine::poll_syntax_set`]
+pub fn preload_syntax_set() {
+    if PRELOAD_STARTED.swap(true, Ordering::SeqCst) || SYNTAX_SET.get().is_some() {
+        return;
+    }
+    std::thread::spawn(|| {
+        syntax_set();
+    });
+}
+
+/// 語法集是否可以立即使用（未啟動背景載入時會同步載入）
+fn syntax_set_available() -> bool {
+    SYNTAX_SET.get().is_some() || !PRELOAD_STARTED.load(Ordering::SeqCst)
 }
 
 /// 語法高亮引擎
@@ -39,6 +196,9 @@ pub struct HighlightEngine {
     theme: Theme,
     current_syntax: Option<&'static SyntaxReference>,
     true_color: bool,
+    file_path: Option<PathBuf>,               // 用於檢測語法的檔案路徑
+    fallback_extension: Option<&'static str>, // 路徑無法判斷時使用的等效副檔名
+    resolved: bool,                           // 語法是否已依目前路徑檢測完成
 }
 
 impl HighlightEngine {
@@ -55,12 +215,66 @@ impl HighlightEngine {
             theme,
             current_syntax: None,
             true_color,
+            file_path: None,
+            fallback_extension: None,
+            resolved: true,
         })
     }
 
+    /// 切換主題（名稱見 [`HighlightEngine::available_themes`]）
+    pub fn set_theme(&mut self, theme_name: &str) -> Result<()> {
+        self.theme = THEME_SET
+            .themes
+            .get(theme_name)
+            .with_context(|| format!("Theme '{}' not found", theme_name))?
+            .clone();
+        Ok(())
+    }
+
     /// 設定當前檔案類型（從路徑檢測）
     pub fn set_file(&mut self, file_path: Option<&Path>) {
-        self.current_syntax = self.detect_syntax_from_path(file_path);
+        self.file_path = file_path.map(Path::to_path_buf);
+        self.fallback_extension = None;
+        self.resolve_syntax();
+    }
+
+    /// 設定路徑無法判斷語法時使用的等效副檔名（用於內容檢測的結果）
+    pub fn set_fallback_extension(&mut self, extension: &'static str) {
+        self.fallback_extension = Some(extension);
+        self.resolve_syntax();
+    }
+
+    /// 依路徑與等效副檔名檢測語法；語法集仍在背景載入時延後處理
+    fn resolve_syntax(&mut self) {
+        if !syntax_set_available() {
+            self.current_syntax = None;
+            self.resolved = false;
+            return;
+        }
+
+        self.current_syntax = self
+            .detect_syntax_from_path(self.file_path.as_deref())
+            .or_else(|| {
+                self.fallback_extension
+                    .and_then(|ext| syntax_set().find_syntax_by_extension(ext))
+            });
+        self.resolved = true;
+    }
+
+    /// 語法集是否仍在背景載入中（此時不會產生高亮）
+    pub fn is_loading(&self) -> bool {
+        !self.resolved
+    }
+
+    /// 檢查背景載入是否完成，完成時補上語法檢測
+    ///
+    /// 返回 true 表示語法剛剛可用，呼叫端需要重繪
+    pub fn poll_syntax_set(&mut self) -> bool {
+        if self.resolved || SYNTAX_SET.get().is_none() {
+            return false;
+        }
+        self.resolve_syntax();
+        true
     }
 
     /// 從檔案路徑檢測語法
@@ -72,14 +286,19 @@ impl HighlightEngine {
 
         // 1. 從副檔名檢測
         if let Some(ext) = path.extension().and_then(|e| e.to_str()) {
-            if let Some(syntax) = SYNTAX_SET.find_syntax_by_extension(ext) {
+            if let Some(syntax) = syntax_set().find_syntax_by_extension(ext) {
                 return Some(syntax);
             }
         }
 
-        // 2. 從檔名檢測（例如 Makefile, Dockerfile）
+        // 2. 從檔名檢測（例如 Makefile, Dockerfile, COMMIT_EDITMSG, .gitignore）
         if let Some(name) = path.file_name().and_then(|n| n.to_str()) {
-            if let Some(syntax) = SYNTAX_SET.find_syntax_by_name(name) {
+            if let Some(syntax) = syntax_set().find_syntax_by_name(name) {
+                return Some(syntax);
+            }
+
+            // 語法定義中也以完整檔名登記（例如 Git Commit 的 COMMIT_EDITMSG）
+            if let Some(syntax) = syntax_set().find_syntax_by_extension(name) {
                 return Some(syntax);
             }
 
@@ -103,10 +322,10 @@ impl HighlightEngine {
                     | "bash_profile"
             ) {
                 // 嘗試找 Bash 或 Shell Script 語法
-                if let Some(syntax) = SYNTAX_SET
+                if let Some(syntax) = syntax_set()
                     .find_syntax_by_name("Bash")
-                    .or_else(|| SYNTAX_SET.find_syntax_by_name("Shell Script (Bash)"))
-                    .or_else(|| SYNTAX_SET.find_syntax_by_extension("sh"))
+                    .or_else(|| syntax_set().find_syntax_by_name("Shell Script (Bash)"))
+                    .or_else(|| syntax_set().find_syntax_by_extension("sh"))
                 {
                     return Some(syntax);
                 }
@@ -115,12 +334,12 @@ impl HighlightEngine {
             // 其他特殊檔名
             match name_lower.as_str() {
                 "makefile" | "gnumakefile" => {
-                    if let Some(syntax) = SYNTAX_SET.find_syntax_by_name("Makefile") {
+                    if let Some(syntax) = syntax_set().find_syntax_by_name("Makefile") {
                         return Some(syntax);
                     }
                 }
                 "dockerfile" => {
-                    if let Some(syntax) = SYNTAX_SET.find_syntax_by_name("Dockerfile") {
+                    if let Some(syntax) = syntax_set().find_syntax_by_name("Dockerfile") {
                         return Some(syntax);
                     }
                 }
@@ -131,12 +350,31 @@ impl HighlightEngine {
         None
     }
 
+    /// 直接指定語法（語法名稱或副檔名，不分大小寫），取代路徑檢測的結果
+    pub fn set_syntax(&mut self, token: &str) -> Result<()> {
+        let syntax = syntax_set()
+            .find_syntax_by_token(token)
+            .with_context(|| format!("Unknown syntax: {}", token))?;
+        self.file_path = None;
+        self.fallback_extension = None;
+        self.current_syntax = Some(syntax);
+        self.resolved = true;
+        Ok(())
+    }
+
+    /// 目前語法對應的副檔名（用於推斷手動指定語言時的註解風格）
+    pub fn syntax_extensions(&self) -> Vec<&'static str> {
+        self.current_syntax
+            .map(|syntax| syntax.file_extensions.iter().map(String::as_str).collect())
+            .unwrap_or_default()
+    }
+
     /// 從內容檢測語法（shebang）
     #[allow(dead_code)]
     pub fn detect_syntax_from_content(&self, content: &str) -> Option<&'static SyntaxReference> {
         if let Some(first_line) = content.lines().next() {
             if first_line.starts_with("#!") {
-                return SYNTAX_SET.find_syntax_by_first_line(first_line);
+                return syntax_set().find_syntax_by_first_line(first_line);
             }
         }
         None
@@ -181,7 +419,7 @@ impl HighlightEngine {
     /// 取得可用語法清單
     #[allow(dead_code)]
     pub fn available_syntaxes() -> Vec<String> {
-        SYNTAX_SET
+        syntax_set()
             .syntaxes()
             .iter()
             .map(|s| s.name.clone())
@@ -189,16 +427,37 @@ impl HighlightEngine {
     }
 }
 
+/// 行與行之間的語法狀態（解析狀態 + 高亮狀態）
+///
+/// 快取每行結束時的狀態，編輯後只需從修改的行重新高亮，
+/// 直到狀態與舊快取一致為止
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LineState {
+    parse: ParseState,
+    highlight: HighlightState,
+}
+
+impl LineState {
+    /// 檔案開頭的初始狀態
+    fn new(syntax: &SyntaxReference, highlighter: &Highlighter) -> Self {
+        Self {
+            parse: ParseState::new(syntax),
+            highlight: HighlightState::new(highlighter, ScopeStack::new()),
+        }
+    }
+}
+
 /// 逐行高亮器（維護內部語法狀態）
 ///
-/// ⚠️ 重要：HighlightLines 內部維護 ParseState，
-/// 必須循序處理行才能正確處理跨行語法（如多行註解）
+/// ⚠️ 重要：內部維護 ParseState，必須循序處理行才能正確處理跨行語法（如多行註解）；
+/// 可透過 `state`/`set_state` 從快取的狀態接續高亮
 ///
 /// 優化策略：
-/// - Token 層級過濾換行符（而非輸出層級），確保 ANSI 碼完整性
-/// - 只在顏色變化時輸出色碼，減少輸出大小約 30-50%
+/// - Token 層級過濾換行符（而非輸出層級）
+/// - 相鄰同色的 token 合併為一段，減少輸出的色碼
 pub struct LineHighlighter {
-    inner: HighlightLines<'static>,
+    highlighter: Highlighter<'static>,
+    state: LineState,
     true_color: bool,
 }
 
@@ -207,79 +466,92 @@ impl LineHighlighter {
         // 將 theme 洩漏到 'static 生命週期（接受小量記憶體洩漏以換取簡單性）
         // 這是安全的，因為 theme 數量很少（只有幾個主題）
         let theme_static: &'static Theme = Box::leak(Box::new(theme));
+        let highlighter = Highlighter::new(theme_static);
+        let state = LineState::new(syntax, &highlighter);
 
         Self {
-            inner: HighlightLines::new(syntax, theme_static),
+            highlighter,
+            state,
             true_color,
         }
     }
 
-    /// 高亮單行，返回 ANSI 色碼字串
+    /// 目前的語法狀態（即上一行結束時的狀態）
+    pub fn state(&self) -> &LineState {
+        &self.state
+    }
+
+    /// 從指定狀態接續高亮
+    pub fn set_state(&mut self, state: LineState) {
+        self.state = state;
+    }
+
+    /// 高亮單行，返回帶樣式的文字（不含換行符）
     ///
     /// 實現特點：
     /// - 在 token 層級過濾換行符，避免終端殘影
-    /// - 優化 ANSI 碼生成，只在顏色變化時輸出
+    /// - 前景色依終端能力轉換為真彩色或 256 色
     ///
     /// ⚠️ 錯誤處理策略：
     /// - 如果高亮失敗，自動降級為純文字（不崩潰）
     /// - 這確保編輯器在語法錯誤時仍可正常使用
-    pub fn highlight_line(&mut self, line: &str) -> String {
-        match self.inner.highlight_line(line, &SYNTAX_SET) {
-            Ok(ranges) => self.ranges_to_ansi_optimized(&ranges),
+    pub fn highlight_line(&mut self, line: &str) -> StyledLine {
+        let ops = self.state.parse.parse_line(line, syntax_set());
+        match ops {
+            Ok(ops) => {
+                let ranges: Vec<(Style, &str)> = HighlightIterator::new(
+                    &mut self.state.highlight,
+                    &ops,
+                    line,
+                    &self.highlighter,
+                )
+                .collect();
+                self.ranges_to_styled_line(&ranges)
+            }
             Err(e) => {
                 // 降級為純文字，不影響編輯器運作
                 if cfg!(debug_assertions) {
                     eprintln!("[WARN] Syntax highlighting failed: {}", e);
                 }
                 // 過濾換行符
-                strip_line_endings(line)
+                StyledLine::plain(strip_line_endings(line))
             }
         }
     }
 
-    /// 優化的 ANSI 碼生成（方案 A + C）
+    /// 將 syntect 的 token 轉換為 StyledLine
     ///
     /// 特點：
     /// 1. Token 層級過濾換行符（修復 Linux 殘影問題）
-    /// 2. 只在顏色變化時輸出色碼（減少輸出大小）
-    /// 3. 統一處理真彩色和 256 色模式
-    fn ranges_to_ansi_optimized(&self, ranges: &[(Style, &str)]) -> String {
-        let mut output = String::with_capacity(256); // 預分配以減少重分配
-        let mut last_color: Option<Color> = None;
+    /// 2. 統一處理真彩色和 256 色模式
+    fn ranges_to_styled_line(&self, ranges: &[(Style, &str)]) -> StyledLine {
+        let mut line = StyledLine::new();
 
         for (style, text) in ranges {
             // 在 token 層級過濾控制字符（關鍵修復）
             let clean = strip_line_endings(text);
-            if clean.is_empty() {
-                continue;
-            }
-
             let fg = style.foreground;
-
-            // 只在顏色變化時輸出色碼（效能優化）
-            let color_changed = last_color.is_none_or(|last| {
-                last.r != fg.r || last.g != fg.g || last.b != fg.b
-            });
-
-            if color_changed {
-                if self.true_color {
-                    let _ = write!(output, "\x1b[38;2;{};{};{}m", fg.r, fg.g, fg.b);
-                } else {
-                    let code = ansi_colours::ansi256_from_rgb((fg.r, fg.g, fg.b));
-                    let _ = write!(output, "\x1b[38;5;{}m", code);
+            let color = if self.true_color {
+                Color::Rgb {
+                    r: fg.r,
+                    g: fg.g,
+                    b: fg.b,
                 }
-                last_color = Some(fg);
-            }
-
-            output.push_str(&clean);
+            } else {
+                Color::AnsiValue(ansi_colours::ansi256_from_rgb((fg.r, fg.g, fg.b)))
+            };
+
+            // 相同顏色的 token 由 push 合併
+            line.push(
+                clean,
+                SpanStyle {
+                    foreground: Some(color),
+                    ..SpanStyle::default()
+                },
+            );
         }
 
-        // 只在有輸出色碼時才需要 reset
-        if last_color.is_some() && !output.is_empty() {
-            output.push_str("\x1b[0m");
-        }
-
-        output
+        line
     }
 }
 
@@ -376,6 +648,11 @@ mod tests {
     fn test_engine_creation() {
         let engine = HighlightEngine::new(None, true);
         assert!(engine.is_ok());
+
+        let mut engine = engine.unwrap();
+        engine.set_theme("InspiredGitHub").unwrap();
+        assert_eq!(engine.theme_name(), "GitHub");
+        assert!(engine.set_theme("no-such-theme").is_err());
     }
 
     #[test]
@@ -387,8 +664,61 @@ mod tests {
 
         let mut highlighter = engine.create_highlighter().unwrap();
         let result = highlighter.highlight_line("fn main() {}");
-        assert!(!result.is_empty());
-        assert!(result.contains("fn"));
+        assert_eq!(result.text(), "fn main() {}");
+        assert!(result.spans.len() > 1);
+    }
+
+    #[test]
+    fn test_set_syntax_by_name_or_extension() {
+        let mut engine = HighlightEngine::new(None, true).unwrap();
+        engine.set_file(Some(Path::new("notes.txt")));
+        engine.set_syntax("py").unwrap();
+        assert_eq!(engine.syntax_name(), Some("Python"));
+        engine.set_syntax("rust").unwrap();
+        assert_eq!(engine.syntax_name(), Some("Rust"));
+        assert!(engine.syntax_extensions().contains(&"rs"));
+        assert!(engine.set_syntax("no-such-syntax").is_err());
+    }
+
+    #[test]
+    fn test_user_themes_and_syntaxes() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let theme = r#"<?xml version="1.0" encoding="UTF-8"?>
+<plist version="1.0"><dict>
+<key>name</key><string>Mine</string>
+<key>settings</key><array>
+<dict><key>settings</key><dict><key>foreground</key><string>#112233</string></dict></dict>
+<dict><key>scope</key><string>keyword</string>
+<key>settings</key><dict><key>foreground</key><string>#FF0000</string></dict></dict>
+</array></dict></plist>"#;
+        fs::write(dir.path().join("mine.tmTheme"), theme).unwrap();
+        fs::write(dir.path().join("broken.tmTheme"), "not a plist").unwrap();
+        fs::write(dir.path().join("notes.txt"), "ignored").unwrap();
+
+        let mut themes = ThemeSet::load_defaults();
+        let (names, errors) = add_user_themes(&mut themes, dir.path());
+        assert_eq!(names, vec!["mine"]);
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].contains("broken.tmTheme"));
+        assert!(themes.themes.contains_key("mine"));
+
+        // 自訂的 DSL：副檔名 .wdsl，`when` 為關鍵字
+        let syntax = "%YAML 1.2\n---\nname: WediDSL\nfile_extensions: [wdsl]\nscope: source.wdsl\n\
+                      contexts:\n  main:\n    - match: '\\bwhen\\b'\n      scope: keyword.control.wdsl\n";
+        fs::write(dir.path().join("wdsl.sublime-syntax"), syntax).unwrap();
+        let set: SyntaxSet = bincode::deserialize(SERIALIZED_SYNTAX_SET).unwrap();
+        let builtin_count = set.syntaxes().len();
+        let (set, names, errors) = add_user_syntaxes(set, dir.path());
+        assert_eq!(names, vec!["WediDSL"]);
+        assert!(errors.is_empty());
+        assert_eq!(set.syntaxes().len(), builtin_count + 1);
+        assert_eq!(
+            set.find_syntax_by_extension("wdsl")
+                .map(|s| s.name.as_str()),
+            Some("WediDSL")
+        );
+        // 內建語法仍然可用
+        assert!(set.find_syntax_by_extension("rs").is_some());
     }
 
     #[test]
@@ -403,10 +733,58 @@ mod tests {
         let line2 = highlighter.highlight_line("   middle");
         let line3 = highlighter.highlight_line("   end */");
 
-        // 所有行都應該有 ANSI 色碼
-        assert!(line1.contains("\x1b["));
-        assert!(line2.contains("\x1b["));
-        assert!(line3.contains("\x1b["));
+        // 整段註解使用同一個顏色
+        for line in [&line1, &line2, &line3] {
+            assert!(line.spans[0].style.foreground.is_some());
+        }
+        assert_eq!(line1.spans[0].style, line2.spans[0].style);
+        assert_eq!(line2.spans[0].style, line3.spans[0].style);
+    }
+
+    #[test]
+    fn test_resume_from_state() {
+        let mut engine = HighlightEngine::new(None, true).unwrap();
+        engine.set_file(Some(Path::new("test.rs")));
+
+        let mut highlighter = engine.create_highlighter().unwrap();
+        highlighter.highlight_line("/* start\n");
+        let after_first = highlighter.state().clone();
+        let expected = highlighter.highlight_line("   end */ fn x() {}\n");
+
+        // 從快取的狀態接續，結果與循序高亮相同
+        let mut resumed = engine.create_highlighter().unwrap();
+        assert_ne!(resumed.state(), &after_first);
+        resumed.set_state(after_first);
+        assert_eq!(resumed.highlight_line("   end */ fn x() {}\n"), expected);
+        assert_eq!(resumed.state(), highlighter.state());
+    }
+
+    #[test]
+    fn test_git_file_syntax() {
+        let mut engine = HighlightEngine::new(None, true).unwrap();
+        engine.set_file(Some(Path::new(".git/COMMIT_EDITMSG")));
+        assert_eq!(engine.syntax_name(), Some("Git Commit"));
+        engine.set_file(Some(Path::new(".git/rebase-merge/git-rebase-todo")));
+        assert_eq!(engine.syntax_name(), Some("Git Rebase Todo"));
+    }
+
+    #[test]
+    fn test_syntax_by_content_extension() {
+        let mut engine = HighlightEngine::new(None, true).unwrap();
+        for ext in ["sh", "py", "js", "xml", "json", "yaml", "mk"] {
+            engine.set_file(Some(Path::new("script")));
+            engine.set_fallback_extension(ext);
+            assert!(engine.is_enabled(), "No syntax for {}", ext);
+        }
+
+        // 路徑能判斷時優先使用路徑
+        engine.set_file(Some(Path::new("main.rs")));
+        engine.set_fallback_extension("py");
+        assert_eq!(engine.syntax_name(), Some("Rust"));
+
+        engine.set_file(Some(Path::new("script")));
+        engine.set_fallback_extension("no-such-ext");
+        assert!(!engine.is_enabled());
     }
 
     #[test]
@@ -423,7 +801,7 @@ mod tests {
         let mut highlighter = engine.create_highlighter().unwrap();
         // 即使是畸形的輸入也應該回傳純文字，不崩潰
         let result = highlighter.highlight_line("畸形語法 {{{");
-        assert!(!result.is_empty());
+        assert_eq!(result.text(), "畸形語法 {{{");
     }
 
     #[test]
@@ -445,31 +823,42 @@ mod tests {
         let mut highlighter = engine.create_highlighter().unwrap();
 
         // 測試帶換行符的輸入
-        let result = highlighter.highlight_line("fn main() {}\n");
+        let result = highlighter.highlight_line("fn main() {}\n").text();
         assert!(!result.contains('\n'), "Output should not contain newline");
-        assert!(!result.contains('\r'), "Output should not contain carriage return");
+        assert!(
+            !result.contains('\r'),
+            "Output should not contain carriage return"
+        );
 
         // 測試 Windows 換行符
-        let result2 = highlighter.highlight_line("let x = 1;\r\n");
+        let result2 = highlighter.highlight_line("let x = 1;\r\n").text();
         assert!(!result2.contains('\n'), "Output should not contain newline");
-        assert!(!result2.contains('\r'), "Output should not contain carriage return");
+        assert!(
+            !result2.contains('\r'),
+            "Output should not contain carriage return"
+        );
     }
 
     #[test]
-    fn test_optimized_ansi_output() {
-        // 測試 ANSI 碼優化：連續相同顏色的 token 只輸出一次色碼
+    fn test_adjacent_tokens_merged() {
+        // 連續相同顏色的 token 合併為一段
         let mut engine = HighlightEngine::new(None, true).unwrap();
         engine.set_file(Some(Path::new("test.rs")));
 
         let mut highlighter = engine.create_highlighter().unwrap();
         let result = highlighter.highlight_line("fn main() {}");
 
-        // 應該只有一個 reset code（在最後）
-        let reset_count = result.matches("\x1b[0m").count();
-        assert_eq!(reset_count, 1, "Should have exactly one reset code at the end");
+        assert!(result
+            .spans
+            .windows(2)
+            .all(|pair| pair[0].style != pair[1].style));
+        assert!(result.spans.iter().all(|span| !span.text.is_empty()));
 
-        // 確保輸出以 reset code 結尾
-        assert!(result.ends_with("\x1b[0m"), "Output should end with reset code");
+        // 轉換為 ANSI 時以還原前景色結尾
+        assert!(
+            result.to_ansi().ends_with("\x1b[39m"),
+            "Output should end with reset code"
+        );
     }
 
     #[test]
@@ -481,15 +870,11 @@ mod tests {
         let mut highlighter = engine.create_highlighter().unwrap();
         let result = highlighter.highlight_line("fn main() {}");
 
-        // 應該使用 256 色格式 \x1b[38;5;XXXm
-        assert!(
-            result.contains("\x1b[38;5;"),
-            "Should use 256-color format"
-        );
-        // 不應該使用真彩色格式
-        assert!(
-            !result.contains("\x1b[38;2;"),
-            "Should not use true-color format"
-        );
+        // 應該使用 256 色，不應該使用真彩色
+        assert!(result
+            .spans
+            .iter()
+            .all(|span| matches!(span.style.foreground, Some(Color::AnsiValue(_)))));
+        assert!(result.to_ansi().contains("\x1b[38;5;"));
     }
 }
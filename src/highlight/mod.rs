@@ -5,12 +5,16 @@
 mod cache;
 #[cfg(feature = "syntax-highlighting")]
 mod engine;
+#[cfg(feature = "syntax-highlighting")]
+mod worker;
 
 // 導出公開 API
 #[cfg(feature = "syntax-highlighting")]
 pub use cache::{CachedLine, EditType, HighlightCache};
 #[cfg(feature = "syntax-highlighting")]
-pub use engine::{supports_true_color, HighlightEngine};
+pub use engine::{preload_syntax_set, supports_true_color, user_assets, HighlightEngine};
+#[cfg(feature = "syntax-highlighting")]
+pub use worker::HighlightWorker;
 
 /// 語法高亮設定
 #[cfg(feature = "syntax-highlighting")]
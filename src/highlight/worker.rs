@@ -0,0 +1,250 @@
+//! 背景高亮執行緒
+//!
+//! 畫面只高亮時間預算內來得及的行，其餘（包括光標以下預先準備的行）交給背景執行緒，
+//! 結果分批送回並寫入 `HighlightCache`；尚未高亮的行先以純文字顯示
+
+use super::cache::CachedLine;
+use super::engine::LineHighlighter;
+use ropey::Rope;
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+
+/// 每批送回的行數
+const BATCH_LINES: usize = 64;
+
+/// 一次高亮工作：從 `from` 行開始（以 highlighter 目前的狀態接續）到 `to` 行（不含）
+struct Job {
+    key: JobKey,
+    rope: Rope,
+    from: usize,
+    to: usize,
+    highlighter: LineHighlighter,
+}
+
+// SAFETY: 語法狀態中含有 oniguruma 的比對結果（原始指標），因此不是自動 Send；
+// 這些記憶體完全由所屬的值擁有、沒有共用，整個值在執行緒之間轉移所有權是安全的
+unsafe impl Send for Job {}
+
+/// 用於避免重複送出相同的工作
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct JobKey {
+    generation: u64,
+    version: u64,
+    from: usize,
+}
+
+/// 背景執行緒送回的一批高亮結果
+pub struct HighlightBatch {
+    /// 送出工作時快取的世代；與目前不同時（主題或語法已改變）應丟棄
+    pub generation: u64,
+    pub lines: Vec<(usize, CachedLine)>,
+    key: JobKey,
+    done: bool,
+}
+
+// SAFETY: 同 `Job`，快取行的語法狀態由這一批結果獨佔
+unsafe impl Send for HighlightBatch {}
+
+/// 背景高亮執行緒的控制端（第一次送出工作時才建立執行緒）
+#[derive(Default)]
+pub struct HighlightWorker {
+    channel: Option<(Sender<Job>, Receiver<HighlightBatch>)>,
+    pending: Option<JobKey>,
+}
+
+impl HighlightWorker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 是否有尚未完成的工作
+    pub fn is_busy(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    /// 送出高亮工作；較新的工作會取代尚未完成的舊工作
+    ///
+    /// `generation` 為快取的世代、`version` 為緩衝區版本，
+    /// 與進行中的工作相同時不重複送出
+    pub fn request(
+        &mut self,
+        generation: u64,
+        version: u64,
+        rope: Rope,
+        from: usize,
+        to: usize,
+        highlighter: LineHighlighter,
+    ) {
+        let key = JobKey {
+            generation,
+            version,
+            from,
+        };
+        if self.pending == Some(key) || from >= to {
+            return;
+        }
+        let (jobs, _) = self.channel.get_or_insert_with(spawn);
+        let job = Job {
+            key,
+            rope,
+            from,
+            to,
+            highlighter,
+        };
+        if jobs.send(job).is_ok() {
+            self.pending = Some(key);
+        } else {
+            // 執行緒已結束（例如高亮時 panic），下次再重新建立
+            self.channel = None;
+            self.pending = None;
+        }
+    }
+
+    /// 取出目前已完成的結果（不阻塞）
+    pub fn poll(&mut self) -> Vec<HighlightBatch> {
+        let mut batches = Vec::new();
+        let Some((_, results)) = &self.channel else {
+            return batches;
+        };
+        loop {
+            match results.try_recv() {
+                Ok(batch) => {
+                    if batch.done && self.pending == Some(batch.key) {
+                        self.pending = None;
+                    }
+                    batches.push(batch);
+                }
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.channel = None;
+                    self.pending = None;
+                    break;
+                }
+            }
+        }
+        batches
+    }
+}
+
+fn spawn() -> (Sender<Job>, Receiver<HighlightBatch>) {
+    let (job_sender, jobs) = mpsc::channel();
+    let (result_sender, results) = mpsc::channel();
+    std::thread::spawn(move || run(jobs, result_sender));
+    (job_sender, results)
+}
+
+/// 執行緒主迴圈；控制端釋放後結束
+fn run(jobs: Receiver<Job>, results: Sender<HighlightBatch>) {
+    let Ok(mut job) = jobs.recv() else {
+        return;
+    };
+    'jobs: loop {
+        // 只處理最新的工作
+        while let Ok(newer) = jobs.try_recv() {
+            job = newer;
+        }
+
+        let last = job.to.min(job.rope.len_lines());
+        let mut lines = Vec::with_capacity(BATCH_LINES);
+        for row in job.from..last {
+            match jobs.try_recv() {
+                Ok(newer) => {
+                    job = newer;
+                    continue 'jobs;
+                }
+                Err(TryRecvError::Disconnected) => return,
+                Err(TryRecvError::Empty) => {}
+            }
+
+            // syntect 需要換行符才能正確解析語法狀態
+            let mut text = job.rope.line(row).to_string();
+            if !text.ends_with('\n') {
+                text.push('\n');
+            }
+            let start_state = job.highlighter.state().clone();
+            let highlighted = job.highlighter.highlight_line(&text);
+            lines.push((
+                row,
+                CachedLine {
+                    text,
+                    highlighted,
+                    start_state,
+                    end_state: job.highlighter.state().clone(),
+                },
+            ));
+
+            if lines.len() >= BATCH_LINES {
+                let batch = HighlightBatch {
+                    generation: job.key.generation,
+                    lines: std::mem::replace(&mut lines, Vec::with_capacity(BATCH_LINES)),
+                    key: job.key,
+                    done: false,
+                };
+                if results.send(batch).is_err() {
+                    return;
+                }
+            }
+        }
+
+        let batch = HighlightBatch {
+            generation: job.key.generation,
+            lines,
+            key: job.key,
+            done: true,
+        };
+        if results.send(batch).is_err() {
+            return;
+        }
+        match jobs.recv() {
+            Ok(next) => job = next,
+            Err(_) => return,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::highlight::HighlightEngine;
+    use std::path::Path;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_worker_matches_sequential_highlighting() {
+        let mut engine = HighlightEngine::new(None, true).unwrap();
+        engine.set_file(Some(Path::new("test.rs")));
+        let text = "/* a\nmulti-line */\nfn main() {\n    let x = \"s\";\n}\n";
+        let rope = Rope::from_str(text);
+
+        // 前兩行在前景高亮，其餘交給背景執行緒接續
+        let mut highlighter = engine.create_highlighter().unwrap();
+        let mut expected = Vec::new();
+        for line in text.split_inclusive('\n') {
+            expected.push(highlighter.highlight_line(line));
+        }
+        let mut highlighter = engine.create_highlighter().unwrap();
+        for line in text.split_inclusive('\n').take(2) {
+            highlighter.highlight_line(line);
+        }
+
+        let mut worker = HighlightWorker::new();
+        worker.request(1, 1, rope.clone(), 2, 100, highlighter);
+        assert!(worker.is_busy());
+
+        let mut lines = Vec::new();
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while worker.is_busy() && Instant::now() < deadline {
+            for batch in worker.poll() {
+                assert_eq!(batch.generation, 1);
+                lines.extend(batch.lines);
+            }
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        assert!(!worker.is_busy());
+
+        let rows: Vec<usize> = lines.iter().map(|(row, _)| *row).collect();
+        assert_eq!(rows, vec![2, 3, 4, 5]);
+        for (row, cached) in &lines[..3] {
+            assert_eq!(cached.highlighted, expected[*row]);
+        }
+    }
+}
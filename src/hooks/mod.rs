@@ -0,0 +1,192 @@
+// 事件掛鉤
+//
+// 外掛實作 Hook，在開啟、存檔、輸入與執行命令時收到通知，並可透過 EditorCore 修改緩衝區。
+// 外掛在編譯時加入，各自放在 cargo feature 之後；`builtin()` 依啟用的 feature 註冊
+
+#[cfg(feature = "plugin-trim-whitespace")]
+mod trim_whitespace;
+
+use crate::editor_core::EditorCore;
+use crate::input::Command;
+use anyhow::Result;
+use std::path::Path;
+
+/// 掛鉤收到的內容
+#[allow(dead_code)]
+pub struct HookContext<'a> {
+    /// 編輯核心；修改緩衝區後編輯器會重新整理畫面
+    pub core: &'a mut EditorCore,
+    /// 目前的檔案路徑（另存新檔時為新的路徑）
+    pub path: Option<&'a Path>,
+    message: Option<String>,
+}
+
+impl<'a> HookContext<'a> {
+    pub fn new(core: &'a mut EditorCore, path: Option<&'a Path>) -> Self {
+        Self {
+            core,
+            path,
+            message: None,
+        }
+    }
+
+    /// 在狀態列顯示訊息
+    #[allow(dead_code)]
+    pub fn message(&mut self, message: impl Into<String>) {
+        self.message = Some(message.into());
+    }
+
+    pub fn take_message(&mut self) -> Option<String> {
+        self.message.take()
+    }
+}
+
+/// 編輯器事件的掛鉤；每個事件都有不做任何事的預設實作，外掛只需實作需要的事件
+///
+/// 返回錯誤時顯示在錯誤訊息列；`pre_save` 返回錯誤會取消存檔
+pub trait Hook {
+    /// 外掛名稱（顯示在錯誤訊息與 `:checkhealth`）
+    fn name(&self) -> &'static str;
+
+    /// 開啟檔案後（包括啟動時）
+    fn on_open(&mut self, _ctx: &mut HookContext) -> Result<()> {
+        Ok(())
+    }
+
+    /// 寫入檔案之前；可以修改緩衝區，修改的內容會一起寫入
+    fn pre_save(&mut self, _ctx: &mut HookContext) -> Result<()> {
+        Ok(())
+    }
+
+    /// 檔案寫入成功之後
+    fn post_save(&mut self, _ctx: &mut HookContext) -> Result<()> {
+        Ok(())
+    }
+
+    /// 輸入一個字元之後
+    fn on_insert(&mut self, _ctx: &mut HookContext, _ch: char) -> Result<()> {
+        Ok(())
+    }
+
+    /// 按鍵對應的命令執行之後（輸入字元時在 `on_insert` 之後）
+    fn on_command(&mut self, _ctx: &mut HookContext, _command: &Command) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// 已註冊的掛鉤，依註冊順序呼叫
+#[derive(Default)]
+pub struct HookRegistry {
+    hooks: Vec<Box<dyn Hook>>,
+}
+
+impl HookRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 依啟用的 feature 註冊內建的外掛
+    pub fn builtin() -> Self {
+        #[allow(unused_mut)]
+        let mut registry = Self::new();
+        #[cfg(feature = "plugin-trim-whitespace")]
+        registry.register(Box::new(trim_whitespace::TrimWhitespace));
+        registry
+    }
+
+    #[allow(dead_code)]
+    pub fn register(&mut self, hook: Box<dyn Hook>) {
+        self.hooks.push(hook);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hooks.is_empty()
+    }
+
+    /// 已註冊的外掛名稱
+    pub fn names(&self) -> Vec<&'static str> {
+        self.hooks.iter().map(|hook| hook.name()).collect()
+    }
+
+    /// 依序對每個掛鉤呼叫 `event`，遇到第一個錯誤就停止並在錯誤前加上外掛名稱
+    pub fn dispatch(
+        &mut self,
+        ctx: &mut HookContext,
+        mut event: impl FnMut(&mut dyn Hook, &mut HookContext) -> Result<()>,
+    ) -> Result<()> {
+        for hook in &mut self.hooks {
+            let name = hook.name();
+            event(hook.as_mut(), ctx).map_err(|e| e.context(name))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::buffer::RopeBuffer;
+    use anyhow::bail;
+
+    /// 記錄收到的事件；`fail_save` 時拒絕存檔
+    struct Recorder {
+        events: std::rc::Rc<std::cell::RefCell<Vec<String>>>,
+        fail_save: bool,
+    }
+
+    impl Hook for Recorder {
+        fn name(&self) -> &'static str {
+            "recorder"
+        }
+
+        fn pre_save(&mut self, ctx: &mut HookContext) -> Result<()> {
+            self.events.borrow_mut().push("pre_save".to_string());
+            if self.fail_save {
+                bail!("lint failed");
+            }
+            ctx.core.buffer_mut().insert(0, "#");
+            ctx.message("saved by recorder");
+            Ok(())
+        }
+
+        fn on_insert(&mut self, _ctx: &mut HookContext, ch: char) -> Result<()> {
+            self.events.borrow_mut().push(format!("insert {}", ch));
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_dispatch_in_order_and_stop_on_error() {
+        let events = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+        let mut registry = HookRegistry::new();
+        registry.register(Box::new(Recorder {
+            events: events.clone(),
+            fail_save: false,
+        }));
+        registry.register(Box::new(Recorder {
+            events: events.clone(),
+            fail_save: true,
+        }));
+        registry.register(Box::new(Recorder {
+            events: events.clone(),
+            fail_save: false,
+        }));
+        assert_eq!(registry.names(), ["recorder"; 3]);
+
+        let mut core = EditorCore::new(RopeBuffer::new());
+        let mut ctx = HookContext::new(&mut core, None);
+        registry
+            .dispatch(&mut ctx, |hook, ctx| hook.on_insert(ctx, 'x'))
+            .unwrap();
+        assert_eq!(events.borrow().len(), 3);
+
+        // 第二個外掛拒絕存檔，第三個不再被呼叫；第一個的修改保留
+        let error = registry
+            .dispatch(&mut ctx, |hook, ctx| hook.pre_save(ctx))
+            .unwrap_err();
+        assert_eq!(format!("{:#}", error), "recorder: lint failed");
+        assert_eq!(events.borrow().len(), 5);
+        assert_eq!(ctx.take_message().as_deref(), Some("saved by recorder"));
+        assert_eq!(core.text(), "#");
+    }
+}
@@ -0,0 +1,31 @@
+// 存檔前刪除行尾空白（feature `plugin-trim-whitespace`）
+
+use super::{Hook, HookContext};
+use crate::whitespace::{self, Fix};
+use anyhow::Result;
+
+pub struct TrimWhitespace;
+
+impl Hook for TrimWhitespace {
+    fn name(&self) -> &'static str {
+        "trim-whitespace"
+    }
+
+    fn pre_save(&mut self, ctx: &mut HookContext) -> Result<()> {
+        let text = ctx.core.text();
+        let issues = whitespace::check(&text);
+        if !issues.has(Fix::TrailingWhitespace) {
+            return Ok(());
+        }
+
+        let fixed = whitespace::apply(&text, &[Fix::TrailingWhitespace]);
+        let (start, end, replacement) = whitespace::changed_range(&text, &fixed);
+        let buffer = ctx.core.buffer_mut();
+        buffer.replace_range(start, end, replacement);
+        buffer.describe_last_edit(format!(
+            "trim trailing whitespace ({} lines)",
+            issues.trailing_whitespace_lines
+        ));
+        Ok(())
+    }
+}
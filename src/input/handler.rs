@@ -1,5 +1,7 @@
+/// 移動方向（延伸選擇範圍等命令使用）
 #[allow(dead_code)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
 pub enum Direction {
     Up,
     Down,
@@ -13,10 +15,16 @@ pub enum Direction {
     TenthDown, // 跳躍 1/10 文件向下
     FileStart,
     FileEnd,
+    ParagraphUp,   // 上一個空白行分隔的段落
+    ParagraphDown, // 下一個段落
+    BlockStart,    // 目前縮排區塊的第一行
+    BlockEnd,      // 目前縮排區塊的最後一行
 }
 
+/// 編輯器命令；按鍵經由快捷鍵對應轉換為命令，[`EditorCore::apply`](crate::EditorCore::apply) 套用其中的編輯與移動命令
 #[allow(dead_code)]
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
 pub enum Command {
     // 字符輸入
     Insert(char),
@@ -35,8 +43,12 @@ pub enum Command {
     MoveEnd,  // End： 跳到行尾
     PageUp,
     PageDown,
-    MoveToFileStart, // Ctrl+Up: 跳到第一行
-    MoveToFileEnd,   // Ctrl+Down: 跳到最後一行
+    MoveToFileStart,   // Ctrl+Up: 跳到第一行
+    MoveToFileEnd,     // Ctrl+Down: 跳到最後一行
+    MoveParagraphUp,   // Alt+Up / Alt+{: 上一個段落
+    MoveParagraphDown, // Alt+Down / Alt+}: 下一個段落
+    MoveBlockStart,    // Alt+Home: 縮排區塊開頭
+    MoveBlockEnd,      // Alt+End: 縮排區塊結尾
     // MoveToLineStart, // Ctrl+Left: 跳到行首
     // MoveToLineEnd,   // Ctrl+Right: 跳到行尾
 
@@ -44,16 +56,22 @@ pub enum Command {
     Copy,
     Cut,
     Paste,
-    CopyInternal,  // 使用內部剪貼簿複製
-    CutInternal,   // 使用內部剪貼簿剪切
-    PasteInternal, // 使用內部剪貼簿貼上
+    CopyInternal,       // 使用內部剪貼簿複製
+    CutInternal,        // 使用內部剪貼簿剪切
+    PasteInternal,      // 使用內部剪貼簿貼上
+    PasteInternalBelow, // 內部剪貼簿的整行內容貼在光標所在行之後（vi 的 p）
 
     // 視窗調整
     Resize,
 
+    // 滑鼠事件（`:set mouse`），事件內容由 Terminal::take_mouse_event 取得
+    Mouse,
+
     // 文件操作
     Save,
-    Quit,
+    SaveAs,
+    QuitBuffer, // Ctrl+Q: 關閉目前的緩衝區（最後一個時離開）
+    QuitAll,    // Alt+Q: 關閉所有緩衝區並離開
 
     // 撤銷/重做
     Undo,
@@ -63,9 +81,12 @@ pub enum Command {
     Find,
     FindNext,
     FindPrev,
+    ShowMatches, // Alt+F: 列出所有搜尋結果
 
     // 視圖控制
     ToggleLineNumbers,
+    CycleLineNumberMode,
+    ToggleInvisibles,
 
     // 註解切換
     ToggleComment,
@@ -74,6 +95,20 @@ pub enum Command {
     Indent,
     Unindent,
 
+    // 插入目前的日期時間（Alt+T，格式由 `:set timeformat` 設定）
+    InsertTimestamp,
+
+    // 單字補全（Ctrl+N；Tab 接在單字後面時由編輯器改為補全）
+    Complete,
+
+    // 書籤
+    Mark,             // Ctrl+B: 接著按數字設定書籤，再按一次 Ctrl+B 列出書籤
+    JumpToMark(char), // Alt+數字: 跳到書籤
+
+    // 跳轉歷史
+    JumpBack,    // Alt+Left: 回到上一個跳轉前的位置
+    JumpForward, // Alt+Right: 前進到下一個位置
+
     // 選擇操作
     SelectAll,
     ExtendSelection(Direction),
@@ -82,6 +117,14 @@ pub enum Command {
 
     // 跳轉
     GoToLine,
+    GoToReference, // Alt+G: 開啟光標處的 `file:line:col` 參照
+    FindFile,      // Ctrl+O: 模糊搜尋工作目錄中的檔案並開啟
+    PrevBuffer,    // Alt+,: 上一個緩衝區
+    NextBuffer,    // Alt+.: 下一個緩衝區
+
+    // 次數
+    Count,                          // Ctrl+U: 接著輸入的數字為下一個命令的執行次數
+    WithCount(usize, Box<Command>), // 執行命令 N 次
 
     // 清除訊息
     ClearMessage,
@@ -92,11 +135,79 @@ pub enum Command {
     // 編碼切換
     ChangeEncoding,
 
+    // BOM 切換
+    ToggleBom,
+
+    // 顯示與磁碟檔案的差異
+    ShowDiff,
+
+    // 行數、單字數、字符數與位元組數（Alt+S；有選擇範圍時也顯示選擇範圍的）
+    ShowStats,
+
+    // 光標處字元的碼位、UTF-8 位元組、名稱與寬度（Alt+U）
+    InspectChar,
+
+    // 命令列（:w、:q、:s/// 等）
+    OpenCommandLine,
+
+    // 執行外部命令並在可捲動的視窗中顯示輸出（Alt+!）
+    ShellOutput,
+
+    // 按鍵說明視窗
+    ShowHelp,
+
     // 文件 1/10 跳躍
     JumpTenthUp,
     JumpTenthDown,
 
+    // 捲動畫面（不移動光標，只在光標離開畫面時把它留在畫面邊緣）
+    ScrollLineUp,       // Alt+Y
+    ScrollLineDown,     // Alt+E
+    ScrollHalfPageUp,   // Alt+PageUp
+    ScrollHalfPageDown, // Alt+PageDown
+    CenterCursor,       // Alt+Z: 把光標所在行捲到畫面中間
+
     // 語法高亮模式切換
     #[cfg(feature = "syntax-highlighting")]
     ToggleSyntaxHighlight,
+    // 語法高亮主題選單
+    #[cfg(feature = "syntax-highlighting")]
+    PickSyntaxTheme,
+}
+
+impl Command {
+    /// 加上次數時重複執行的命令：移動、捲動、編輯、貼上與撤銷；其他命令只執行一次
+    pub fn is_repeatable(&self) -> bool {
+        matches!(
+            self,
+            Command::Insert(_)
+                | Command::Delete
+                | Command::Backspace
+                | Command::DeleteLine
+                | Command::MoveUp
+                | Command::MoveDown
+                | Command::MoveLeft
+                | Command::MoveRight
+                | Command::PageUp
+                | Command::PageDown
+                | Command::MoveParagraphUp
+                | Command::MoveParagraphDown
+                | Command::Paste
+                | Command::PasteInternal
+                | Command::PasteInternalBelow
+                | Command::Undo
+                | Command::Redo
+                | Command::FindNext
+                | Command::FindPrev
+                | Command::Indent
+                | Command::Unindent
+                | Command::ExtendSelection(_)
+                | Command::JumpBack
+                | Command::JumpForward
+                | Command::ScrollLineUp
+                | Command::ScrollLineDown
+                | Command::ScrollHalfPageUp
+                | Command::ScrollHalfPageDown
+        )
+    }
 }
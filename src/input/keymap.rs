@@ -62,6 +62,20 @@ pub fn handle_key_event(event: KeyEvent, selection_mode: bool) -> Option<Command
             (KeyCode::End, KeyModifiers::CONTROL) => {
                 return Some(Command::ExtendSelection(Direction::FileEnd))
             }
+
+            // 段落與縮排區塊移動
+            (KeyCode::Up, KeyModifiers::ALT) => {
+                return Some(Command::ExtendSelection(Direction::ParagraphUp))
+            }
+            (KeyCode::Down, KeyModifiers::ALT) => {
+                return Some(Command::ExtendSelection(Direction::ParagraphDown))
+            }
+            (KeyCode::Home, KeyModifiers::ALT) => {
+                return Some(Command::ExtendSelection(Direction::BlockStart))
+            }
+            (KeyCode::End, KeyModifiers::ALT) => {
+                return Some(Command::ExtendSelection(Direction::BlockEnd))
+            }
             _ => {} // 其他按鍵繼續正常處理
         }
     }
@@ -81,6 +95,8 @@ pub fn handle_key_event(event: KeyEvent, selection_mode: bool) -> Option<Command
         (KeyCode::Up, KeyModifiers::CONTROL) => Some(Command::MoveToFileStart),
         (KeyCode::Down, KeyModifiers::CONTROL) => Some(Command::MoveToFileEnd),
         (KeyCode::Left, KeyModifiers::CONTROL) => Some(Command::MoveHome),
+        (KeyCode::Left, KeyModifiers::ALT) => Some(Command::JumpBack),
+        (KeyCode::Right, KeyModifiers::ALT) => Some(Command::JumpForward),
         (KeyCode::Right, KeyModifiers::CONTROL) => Some(Command::MoveEnd),
         // 替代按鍵:Ctrl+Home/End
         (KeyCode::Home, KeyModifiers::CONTROL) => Some(Command::MoveToFileStart),
@@ -89,6 +105,23 @@ pub fn handle_key_event(event: KeyEvent, selection_mode: bool) -> Option<Command
         (KeyCode::PageUp, KeyModifiers::CONTROL) => Some(Command::JumpTenthUp),
         (KeyCode::PageDown, KeyModifiers::CONTROL) => Some(Command::JumpTenthDown),
 
+        // Alt 段落與縮排區塊移動
+        (KeyCode::Up, KeyModifiers::ALT) => Some(Command::MoveParagraphUp),
+        (KeyCode::Down, KeyModifiers::ALT) => Some(Command::MoveParagraphDown),
+        (KeyCode::Char('{'), m) if m.contains(KeyModifiers::ALT) => Some(Command::MoveParagraphUp),
+        (KeyCode::Char('}'), m) if m.contains(KeyModifiers::ALT) => {
+            Some(Command::MoveParagraphDown)
+        }
+        (KeyCode::Home, KeyModifiers::ALT) => Some(Command::MoveBlockStart),
+        (KeyCode::End, KeyModifiers::ALT) => Some(Command::MoveBlockEnd),
+
+        // 捲動畫面而不移動光標
+        (KeyCode::Char('e'), KeyModifiers::ALT) => Some(Command::ScrollLineDown),
+        (KeyCode::Char('y'), KeyModifiers::ALT) => Some(Command::ScrollLineUp),
+        (KeyCode::PageUp, KeyModifiers::ALT) => Some(Command::ScrollHalfPageUp),
+        (KeyCode::PageDown, KeyModifiers::ALT) => Some(Command::ScrollHalfPageDown),
+        (KeyCode::Char('z'), KeyModifiers::ALT) => Some(Command::CenterCursor),
+
         // 選擇模式移動
         (KeyCode::Up, KeyModifiers::SHIFT) => Some(Command::ExtendSelection(Direction::Up)),
         (KeyCode::Down, KeyModifiers::SHIFT) => Some(Command::ExtendSelection(Direction::Down)),
@@ -101,6 +134,20 @@ pub fn handle_key_event(event: KeyEvent, selection_mode: bool) -> Option<Command
             Some(Command::ExtendSelection(Direction::PageDown))
         }
 
+        // Alt+Shift 選擇到段落或縮排區塊的邊界
+        (KeyCode::Up, m) if m.contains(KeyModifiers::ALT) && m.contains(KeyModifiers::SHIFT) => {
+            Some(Command::ExtendSelection(Direction::ParagraphUp))
+        }
+        (KeyCode::Down, m) if m.contains(KeyModifiers::ALT) && m.contains(KeyModifiers::SHIFT) => {
+            Some(Command::ExtendSelection(Direction::ParagraphDown))
+        }
+        (KeyCode::Home, m) if m.contains(KeyModifiers::ALT) && m.contains(KeyModifiers::SHIFT) => {
+            Some(Command::ExtendSelection(Direction::BlockStart))
+        }
+        (KeyCode::End, m) if m.contains(KeyModifiers::ALT) && m.contains(KeyModifiers::SHIFT) => {
+            Some(Command::ExtendSelection(Direction::BlockEnd))
+        }
+
         // Ctrl+Shift 快速選擇
         (KeyCode::Left, m)
             if m.contains(KeyModifiers::CONTROL) && m.contains(KeyModifiers::SHIFT) =>
@@ -157,21 +204,47 @@ pub fn handle_key_event(event: KeyEvent, selection_mode: bool) -> Option<Command
 
         // Ctrl 組合鍵
         (KeyCode::Char('w'), KeyModifiers::CONTROL) => Some(Command::Save),
-        (KeyCode::Char('q'), KeyModifiers::CONTROL) => Some(Command::Quit),
+        (KeyCode::Char('w'), KeyModifiers::ALT) => Some(Command::SaveAs),
+        (KeyCode::Char('q'), KeyModifiers::CONTROL) => Some(Command::QuitBuffer),
+        (KeyCode::Char('q'), KeyModifiers::ALT) => Some(Command::QuitAll),
         (KeyCode::Char('z'), KeyModifiers::CONTROL) => Some(Command::Undo),
         (KeyCode::Char('y'), KeyModifiers::CONTROL) => Some(Command::Redo),
         (KeyCode::Char('f'), KeyModifiers::CONTROL) => Some(Command::Find),
         (KeyCode::Char('l'), KeyModifiers::CONTROL) => Some(Command::ToggleLineNumbers),
+        (KeyCode::Char('l'), KeyModifiers::ALT) => Some(Command::CycleLineNumberMode),
+        (KeyCode::Char('i'), KeyModifiers::ALT) => Some(Command::ToggleInvisibles),
         (KeyCode::Char('g'), KeyModifiers::CONTROL) => Some(Command::GoToLine),
+        (KeyCode::Char('n'), KeyModifiers::CONTROL) => Some(Command::Complete),
         (KeyCode::Char('a'), KeyModifiers::CONTROL) => Some(Command::SelectAll),
         (KeyCode::Char('d'), KeyModifiers::CONTROL) => Some(Command::DeleteLine),
         (KeyCode::Char('\\'), KeyModifiers::CONTROL) => Some(Command::ToggleComment),
         (KeyCode::Char('/'), KeyModifiers::CONTROL) => Some(Command::ToggleComment),
         (KeyCode::Char('k'), KeyModifiers::CONTROL) => Some(Command::ToggleComment),
         (KeyCode::Char('e'), KeyModifiers::CONTROL) => Some(Command::ChangeEncoding),
+        (KeyCode::Char('b'), KeyModifiers::ALT) => Some(Command::ToggleBom),
+        (KeyCode::Char('d'), KeyModifiers::ALT) => Some(Command::ShowDiff),
+        (KeyCode::Char('s'), KeyModifiers::ALT) => Some(Command::ShowStats),
+        (KeyCode::Char('u'), KeyModifiers::ALT) => Some(Command::InspectChar),
+        (KeyCode::Char('g'), KeyModifiers::ALT) => Some(Command::GoToReference),
+        (KeyCode::Char('o'), KeyModifiers::CONTROL) => Some(Command::FindFile),
+        (KeyCode::Char(','), KeyModifiers::ALT) => Some(Command::PrevBuffer),
+        (KeyCode::Char('.'), KeyModifiers::ALT) => Some(Command::NextBuffer),
+        (KeyCode::Char('t'), KeyModifiers::ALT) => Some(Command::InsertTimestamp),
+        (KeyCode::Char('b'), KeyModifiers::CONTROL) => Some(Command::Mark),
+        (KeyCode::Char('u'), KeyModifiers::CONTROL) => Some(Command::Count),
+        (KeyCode::Char(c @ '0'..='9'), KeyModifiers::ALT) => Some(Command::JumpToMark(c)),
+        // Alt+; 或 Alt+: 開啟命令列（部分終端的 Alt+: 會同時帶有 Shift）
+        (KeyCode::Char(';' | ':'), m) if m.contains(KeyModifiers::ALT) => {
+            Some(Command::OpenCommandLine)
+        }
+        // Alt+! 執行外部命令並顯示輸出（終端無法送出 Ctrl+!）
+        (KeyCode::Char('!'), m) if m.contains(KeyModifiers::ALT) => Some(Command::ShellOutput),
         // Ctrl+H: 切換語法高亮模式
         #[cfg(feature = "syntax-highlighting")]
         (KeyCode::Char('h'), KeyModifiers::CONTROL) => Some(Command::ToggleSyntaxHighlight),
+        // Ctrl+T: 選擇語法高亮主題（即時預覽）
+        #[cfg(feature = "syntax-highlighting")]
+        (KeyCode::Char('t'), KeyModifiers::CONTROL) => Some(Command::PickSyntaxTheme),
 
         // 剪貼板操作
         (KeyCode::Char('c'), KeyModifiers::CONTROL) => Some(Command::Copy),
@@ -184,14 +257,149 @@ pub fn handle_key_event(event: KeyEvent, selection_mode: bool) -> Option<Command
         // (KeyCode::F(20), KeyModifiers::NONE) => Some(Command::SelectAll),
         // F21 用於視窗大小調整事件
         (KeyCode::F(21), KeyModifiers::NONE) => Some(Command::Resize),
+        // F22 是滑鼠事件的標記
+        (KeyCode::F(22), KeyModifiers::NONE) => Some(Command::Mouse),
 
         // ESC 清除選擇和訊息
         (KeyCode::Esc, _) => Some(Command::ClearMessage),
 
+        // F1 按鍵說明
+        (KeyCode::F(1), KeyModifiers::NONE) => Some(Command::ShowHelp),
+
         // F3/F4 搜索導航
         (KeyCode::F(3), KeyModifiers::NONE) => Some(Command::FindNext),
         (KeyCode::F(4), KeyModifiers::NONE) => Some(Command::FindPrev),
+        (KeyCode::Char('f'), KeyModifiers::ALT) => Some(Command::ShowMatches),
+
+        // 沒有對應命令、但看起來是文字的字元（輸入法或 AltGr 帶著修飾鍵送出）
+        (KeyCode::Char(c), m) if is_text_input(c, m) => Some(Command::Insert(c)),
 
         _ => None,
     }
 }
+
+/// 帶著修飾鍵的字元是否應該當成文字輸入
+///
+/// 部分輸入法與終端在組字時會把 Shift/Alt 狀態一起送出（例如中日韓文字與 emoji 帶著 ALT），
+/// Windows 的 AltGr 則回報為 Ctrl+Alt。非 ASCII 字元只要沒有單獨按下 Ctrl 就是輸入；
+/// ASCII 字元只在 AltGr 組合下接受符號與空白，避免 Ctrl+Alt+字母被當成文字
+fn is_text_input(c: char, modifiers: KeyModifiers) -> bool {
+    if c.is_control() {
+        return false;
+    }
+    let alt_gr = modifiers.contains(KeyModifiers::CONTROL | KeyModifiers::ALT);
+    if !c.is_ascii() {
+        return alt_gr || !modifiers.contains(KeyModifiers::CONTROL);
+    }
+    alt_gr && !c.is_ascii_alphanumeric()
+}
+
+/// 按鍵說明（`--help` 與 F1 說明視窗共用）
+#[allow(dead_code)]
+pub const KEY_HELP: &[&str] = &[
+    "  Basic Editing:",
+    "    Ctrl+W              Save file",
+    "    Alt+W               Save file as (re-detects file type)",
+    "    Ctrl+Q              Close the file (quit when it is the last one)",
+    "    Alt+Q               Quit, asking about each file with unsaved changes",
+    "    Ctrl+Z              Undo (:set undofile keeps the history across sessions)",
+    "    Ctrl+Y              Redo",
+    "    Backspace           Delete character before cursor or selected text",
+    "    Delete              Delete character under cursor or selected text",
+    "    Ctrl+D              Delete current line or selected lines",
+    "    Ctrl+U, NUM, key    Repeat the key NUM times (move, delete, indent, paste...)",
+    "    Tab                 Indent (insert 4 spaces or indent selected lines)",
+    "    Shift+Tab           Unindent (remove up to 4 leading spaces)",
+    "    Ctrl+N              Complete the word before the cursor (also Tab after a word)",
+    "    F1                  Show this help (/ to search, n/N for next/previous)",
+    "",
+    "  Navigation:",
+    "    Arrow Keys          Move cursor",
+    "    Ctrl+Left/Home      Move to line start",
+    "    Ctrl+Right/End      Move to line end",
+    "    Ctrl+Up/Ctrl+Home   Move to first line",
+    "    Ctrl+Down/Ctrl+End  Move to last line",
+    "    Page Up/Down        Scroll page up/down",
+    "    Ctrl+PageUp/Down    Jump 1/10 of file",
+    "    Alt+E / Alt+Y       Scroll down/up one line without moving the cursor",
+    "    Alt+PageUp/Down     Scroll half a page without moving the cursor",
+    "    Alt+Z               Scroll the cursor line to the middle of the screen",
+    "    Alt+Up/Down         Previous/next paragraph (also Alt+{ and Alt+})",
+    "    Alt+Home/End        Start/end of the indentation block (again: outer block)",
+    "    Ctrl+G              Go to line number",
+    "    Alt+G               Open the file:line:col reference under the cursor",
+    "    Ctrl+O              Find a file under the current directory and open it",
+    "    Alt+, / Alt+.       Switch to the previous / next open buffer",
+    "    Ctrl+B, 0-9         Set a numbered mark (Ctrl+B twice lists marks)",
+    "    Alt+0-9             Jump to a numbered mark",
+    "    Alt+Left/Right      Go back/forward through jumps (search, go to line, marks)",
+    "",
+    "  Selection:",
+    "    Ctrl+S              Toggle selection mode (for terminals without Shift support)",
+    "    Shift+Arrows        Select text",
+    "    Shift+Ctrl+Arrows   Quick select to line/file boundaries",
+    "    Shift+Home/End      Select to line boundaries",
+    "    Shift+Ctrl+Home/End Quick select to file boundaries",
+    "    Shift+PgUp/Dn       Select page up/down",
+    "    Alt+Shift+Up/Down   Select to previous/next paragraph",
+    "    Alt+Shift+Home/End  Select to start/end of the indentation block",
+    "    Ctrl+A              Select all",
+    "    ESC                 Clear selection and messages",
+    "",
+    "  Clipboard:",
+    "    Ctrl+C              Copy (selection or current line)",
+    "    Ctrl+X              Cut (selection or current line)",
+    "    Ctrl+V              Paste",
+    "    Alt+C               Internal Copy (selection or current line)",
+    "    Alt+X               Internal Cut (selection or current line)",
+    "    Alt+V               Internal Paste",
+    "    Alt+T               Insert the current date and time (:set timeformat)",
+    "",
+    "  Search:",
+    "    Ctrl+F              Find text",
+    "    F3                  Find next match",
+    "    F4                  Find previous match",
+    "    Alt+F               List all matches (Enter: jump, r: replace all)",
+    "",
+    "  Code:",
+    "    Ctrl+/ \\ K         Toggle line comment",
+    "    Ctrl+L              Toggle line numbers",
+    "    Alt+L               Cycle line numbers: absolute, relative, hybrid",
+    "    Alt+I               Show invisibles (tabs, spaces, control characters)",
+    #[cfg(feature = "syntax-highlighting")]
+    "    Ctrl+H              Toggle syntax highlight (Disabled/Fast/Accurate)",
+    #[cfg(feature = "syntax-highlighting")]
+    "    Ctrl+T              Pick the syntax theme (live preview, saved to the config file)",
+    "",
+    "  Encoding:",
+    "    Ctrl+E              Change file encoding (utf-8, gbk, big5, shift-jis, etc.)",
+    "    Alt+B               Toggle byte order mark (BOM) on save",
+    "    Alt+D               Show diff between buffer and file on disk",
+    "    Alt+S               Count lines, words, chars and bytes (file and selection)",
+    "    Alt+U               Show code point, UTF-8 bytes, name and width of the character",
+    "",
+    "  Command Line:",
+    "    Alt+; or Alt+:      Open the command line",
+    "    :w [FILE]  :wq  :q  :q!  :e[!] FILE|DIR  :NUM  :set OPTION[=VALUE]",
+    "    :wa  :qa  :qa!  :wqa Save all files, quit all (! discards changes), both",
+    "    :ls  :b N  :bn  :bp List open buffers, switch to buffer N, next, previous",
+    "    :s/foo/bar/[g]      Replace text in the selection or the whole file",
+    "    :matches [TEXT]     List all matches (Enter: jump, r: replace all)",
+    "    :grep [TEXT]        Search all files under the current directory",
+    "    :align[!] [DELIM]   Align selected lines on DELIM (default =; ! every one)",
+    "    :insert date|time|path|file|user|host|user@host   Insert at the cursor",
+    "    :!COMMAND           Run a shell command",
+    "    Alt+!               Run a shell command and show its output (scrollable)",
+    "    :r !COMMAND         Insert the output of a command at the cursor",
+    "    :|COMMAND           Filter the selection (or the file) through a command",
+    "    :suspend            Suspend to the shell (Unix); fg returns",
+    "    :checkhealth        Check clipboard, shell, git, theme and terminal setup",
+    "    :hexview            Show the file on disk in a read-only hex view",
+    "    :analyze            List duplicate lines and frequent words; jump to them",
+    "    :count              Count lines, words, chars and bytes (like wc)",
+    "    :char               Inspect the character under the cursor (same as Alt+U)",
+    "    :fix [eol|endings|trailing]  Fix final newline, mixed line endings, trailing spaces",
+    "    :format (:fmt)      Run the file type's formatter (:set formatprg=CMD)",
+    "    :hover              Show language server hover text (:set lsp)",
+    "    :mark NAME  :'NAME  :marks  :delmarks NAME|!   Set, jump to, list, delete marks",
+];
@@ -4,4 +4,4 @@ mod keymap;
 #[allow(unused_imports)]
 pub use handler::{Command, Direction};
 #[allow(unused_imports)]
-pub use keymap::handle_key_event;
+pub use keymap::{handle_key_event, KEY_HELP};
@@ -0,0 +1,97 @@
+// 跳轉歷史
+//
+// 搜尋、跳到指定行、檔首/檔尾與書籤等較遠的移動會記錄跳轉前的位置，
+// Alt+Left / Alt+Right 像瀏覽器的上一頁/下一頁一樣在這些位置之間來回
+
+/// 最多保留的位置數
+const MAX_JUMPS: usize = 100;
+
+/// 跳轉歷史；位置為 (行, 列)
+#[derive(Debug, Default)]
+pub struct JumpList {
+    entries: Vec<(usize, usize)>,
+    index: usize, // 目前在歷史中的位置；等於 entries.len() 表示位於最新的位置之後
+}
+
+impl JumpList {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 記錄跳轉前的位置；在歷史中間跳轉時丟棄之後（可以「前進」）的位置
+    pub fn record(&mut self, pos: (usize, usize)) {
+        self.entries.truncate(self.index);
+        if self.entries.last() != Some(&pos) {
+            if self.entries.len() >= MAX_JUMPS {
+                self.entries.remove(0);
+            }
+            self.entries.push(pos);
+        }
+        self.index = self.entries.len();
+    }
+
+    /// 回到上一個位置；`current` 是目前的光標位置，從最新的位置返回時記錄下來以便再前進
+    pub fn back(&mut self, current: (usize, usize)) -> Option<(usize, usize)> {
+        if self.index == self.entries.len() {
+            self.record(current);
+            self.index = self.entries.len() - 1;
+        }
+        // 跳過與目前位置相同的項目（例如已在最後記錄的位置上）
+        while self.index > 0 {
+            self.index -= 1;
+            if self.entries[self.index] != current {
+                return Some(self.entries[self.index]);
+            }
+        }
+        None
+    }
+
+    /// 前進到下一個位置（之前以 `back` 離開的位置）
+    pub fn forward(&mut self) -> Option<(usize, usize)> {
+        if self.index + 1 < self.entries.len() {
+            self.index += 1;
+            Some(self.entries[self.index])
+        } else {
+            None
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.index = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_back_and_forward() {
+        let mut jumps = JumpList::new();
+        jumps.record((0, 0));
+        jumps.record((10, 2));
+        // 目前在 (50, 0)
+        assert_eq!(jumps.back((50, 0)), Some((10, 2)));
+        assert_eq!(jumps.back((10, 2)), Some((0, 0)));
+        assert_eq!(jumps.back((0, 0)), None);
+        assert_eq!(jumps.forward(), Some((10, 2)));
+        assert_eq!(jumps.forward(), Some((50, 0)));
+        assert_eq!(jumps.forward(), None);
+
+        // 回到中間後再跳轉：之後的位置被丟棄
+        assert_eq!(jumps.back((50, 0)), Some((10, 2)));
+        jumps.record((10, 2));
+        assert_eq!(jumps.forward(), None);
+        assert_eq!(jumps.back((30, 0)), Some((10, 2)));
+    }
+
+    #[test]
+    fn test_back_skips_current_position() {
+        let mut jumps = JumpList::new();
+        jumps.record((3, 0));
+        jumps.record((7, 0));
+        assert_eq!(jumps.back((7, 0)), Some((3, 0)));
+        assert_eq!(jumps.forward(), Some((7, 0)));
+    }
+}
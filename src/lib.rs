@@ -1,22 +1,59 @@
 //! wedi - 輕量級跨平台終端文字編輯器
+//!
+//! 函式庫提供編輯器使用的編輯元件，供其他工具重用：
+//!
+//! - [`RopeBuffer`]：含復原記錄與編碼處理（[`EncodingConfig`]）的文字緩衝區
+//! - [`EditorCore`]：無畫面的編輯核心，以 [`Command`] 驅動編輯、移動與選擇（[`Selection`]），
+//!   可用 [`EditorCore::builder`] 設定
+//! - [`Search`]：在緩衝區中尋找文字
+//! - [`CommentHandler`]：依檔案類型切換行註解
+//! - [`TerminalBackend`] 與 [`MemoryTerminal`]：渲染畫面的終端介面與測試用的記憶體終端
+//!
+//! 只有這裡重新導出的項目屬於公開 API，遵循語意化版本；
+//! [`Command`]、[`Direction`] 與 [`EncodingConfig`] 標記為 `#[non_exhaustive]`，之後新增命令或選項不算破壞相容性
+//!
+//! ```
+//! use wedi::{EditorCore, RopeBuffer, Search};
+//!
+//! let mut buffer = RopeBuffer::new();
+//! buffer.insert(0, "foo bar foo\n");
+//! let core = EditorCore::builder().buffer(buffer).build();
+//!
+//! let mut search = Search::new();
+//! search.set_query("foo".to_string());
+//! search.find_matches(core.buffer());
+//! assert_eq!(search.matches(), &[(0, 0), (0, 8)]);
+//! ```
 
 // 導出公開模組
 #[cfg(feature = "syntax-highlighting")]
 pub mod highlight;
 
 // 內部模組（供 lib 編譯）
+mod backend;
 mod buffer;
 mod clipboard;
 mod comment;
 mod config;
 mod cursor;
 mod dialog;
+mod editor_core;
+mod grid;
 mod input;
 mod search;
+mod styled;
 mod terminal;
+mod theme;
 mod utils;
 mod view;
 
-// 重新導出常用類型（供 examples 使用）
-pub use buffer::RopeBuffer;
+// 重新導出公開 API（供其他工具、examples 與整合測試使用）
+pub use backend::{Frame, MemoryTerminal, TerminalBackend};
+pub use buffer::{EncodingConfig, RopeBuffer};
+pub use comment::CommentHandler;
 pub use cursor::Cursor;
+pub use editor_core::{EditorCore, EditorCoreBuilder};
+pub use input::{Command, Direction};
+pub use search::Search;
+pub use styled::{Span, SpanStyle, StyledLine};
+pub use view::Selection;
@@ -0,0 +1,199 @@
+// 外部檢查程式（linter）
+//
+// 存檔後在背景以檔案類型對應的命令檢查磁碟上的檔案，解析輸出中的 `file:line:col: message`，
+// 在行號欄標示有問題的行；`:set lintprg.EXT=CMD` 可以覆寫或（留空時）停用某種檔案類型的檢查
+
+use crate::view::DiagnosticMarker;
+use std::path::Path;
+
+/// (副檔名, 命令)；`{file}` 代換為檔案路徑
+const LINTERS: &[(&[&str], &str)] = &[
+    (&["sh", "bash"], "shellcheck -f gcc {file}"),
+    (&["yaml", "yml"], "yamllint -f parsable {file}"),
+    (&["json"], "jq empty {file}"),
+];
+
+/// 一則檢查結果；`row` 從 0 開始，`col` 從 1 開始
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub row: usize,
+    pub col: Option<usize>,
+    pub end_col: Option<usize>, // 範圍結束的欄位（1-based，不含）；None 時標到該處的字結尾
+    pub marker: DiagnosticMarker,
+    pub message: String,
+}
+
+impl Diagnostic {
+    /// 在 `line` 中標示的字元範圍 `[start, end)`（0-based）；沒有欄位時標示整行的文字
+    pub fn char_range(&self, line: &str) -> (usize, usize) {
+        let chars: Vec<char> = line.trim_end_matches(['\n', '\r']).chars().collect();
+        let Some(col) = self.col else {
+            let start = chars.iter().take_while(|c| c.is_whitespace()).count();
+            return (start, chars.len().max(start));
+        };
+        let start = col.saturating_sub(1).min(chars.len());
+        let end = match self.end_col {
+            Some(end) => end.saturating_sub(1).min(chars.len()),
+            None => {
+                start
+                    + chars[start..]
+                        .iter()
+                        .take_while(|c| !c.is_whitespace())
+                        .count()
+            }
+        };
+        // 範圍是空的（例如指向行尾）時至少標示一個字元的位置
+        (start, end.max(start + 1))
+    }
+}
+
+/// 依序以副檔名尋找預設的檢查命令（`{file}` 尚未代換）
+pub fn linter_for<'a>(extensions: impl IntoIterator<Item = &'a str>) -> Option<&'static str> {
+    extensions.into_iter().find_map(|ext| {
+        let ext = ext.to_lowercase();
+        LINTERS
+            .iter()
+            .find(|(exts, _)| exts.contains(&ext.as_str()))
+            .map(|(_, command)| *command)
+    })
+}
+
+/// 解析檢查程式的輸出，只保留屬於 `path` 的結果，依行號排序
+pub fn parse(output: &str, path: &Path) -> Vec<Diagnostic> {
+    let mut diagnostics: Vec<Diagnostic> = output
+        .lines()
+        .filter_map(|line| parse_line(line.trim_end(), path))
+        .collect();
+    diagnostics.sort_by_key(|d| (d.row, d.marker, d.col));
+    diagnostics
+}
+
+/// `file:line[:col]: message`，或 jq 的 `... at line N, column M`
+fn parse_line(line: &str, path: &Path) -> Option<Diagnostic> {
+    let mut parts = line.splitn(4, ':');
+    let file = parts.next()?;
+    if let Some(row) = parts.next().and_then(|n| n.trim().parse::<usize>().ok()) {
+        if Path::new(file.trim()).file_name() != path.file_name() {
+            return None;
+        }
+        let rest: Vec<&str> = parts.collect();
+        let (col, message) = match rest.as_slice() {
+            [col, message] if col.trim().parse::<usize>().is_ok() => {
+                (col.trim().parse().ok(), message.to_string())
+            }
+            _ => (None, rest.join(":")),
+        };
+        return Some(diagnostic(row, col, message.trim()));
+    }
+
+    let (message, position) = line.rsplit_once(" at line ")?;
+    let (row, col) = position.split_once(", column ")?;
+    Some(diagnostic(
+        row.parse().ok()?,
+        col.parse().ok(),
+        message.trim_start_matches("jq: error: "),
+    ))
+}
+
+fn diagnostic(line: usize, col: Option<usize>, message: &str) -> Diagnostic {
+    // 以訊息開頭的嚴重程度分類，其餘視為錯誤
+    let level = message
+        .trim_start_matches('[')
+        .split([':', ']', ' '])
+        .next()
+        .unwrap_or_default()
+        .to_lowercase();
+    let marker = match level.as_str() {
+        "warning" | "note" | "info" | "style" => DiagnosticMarker::Warning,
+        _ => DiagnosticMarker::Error,
+    };
+    Diagnostic {
+        row: line.saturating_sub(1),
+        col,
+        end_col: None,
+        marker,
+        message: message.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_linter_output() {
+        let path = Path::new("/tmp/project/run.sh");
+        let output = "\
+/tmp/project/run.sh:7:6: note: Double quote to prevent globbing. [SC2086]
+/tmp/project/run.sh:3:1: error: Couldn't parse this function. [SC1073]
+other.sh:1:1: error: not this file
+";
+        assert_eq!(
+            parse(output, path),
+            vec![
+                Diagnostic {
+                    row: 2,
+                    col: Some(1),
+                    end_col: None,
+                    marker: DiagnosticMarker::Error,
+                    message: "error: Couldn't parse this function. [SC1073]".to_string(),
+                },
+                Diagnostic {
+                    row: 6,
+                    col: Some(6),
+                    end_col: None,
+                    marker: DiagnosticMarker::Warning,
+                    message: "note: Double quote to prevent globbing. [SC2086]".to_string(),
+                },
+            ]
+        );
+
+        // yamllint -f parsable
+        let yaml = parse(
+            "ci.yml:1:1: [warning] missing document start \"---\" (document-start)\n",
+            Path::new("ci.yml"),
+        );
+        assert_eq!(
+            (yaml[0].row, yaml[0].marker),
+            (0, DiagnosticMarker::Warning)
+        );
+
+        // jq 的語法錯誤沒有檔名
+        let json = parse(
+            "jq: error (at <unknown>): x\nparse error: Expected separator between values at line 4, column 2\n",
+            Path::new("data.json"),
+        );
+        assert_eq!(json.len(), 1);
+        assert_eq!((json[0].row, json[0].col), (3, Some(2)));
+        assert_eq!(
+            json[0].message,
+            "parse error: Expected separator between values"
+        );
+
+        assert_eq!(
+            linter_for(["txt", "YML"]),
+            Some("yamllint -f parsable {file}")
+        );
+        assert_eq!(linter_for(["rs"]), None);
+    }
+
+    #[test]
+    fn test_diagnostic_char_range() {
+        let at = |col, end_col| Diagnostic {
+            row: 0,
+            col,
+            end_col,
+            marker: DiagnosticMarker::Error,
+            message: String::new(),
+        };
+        let line = "  echo $name done\n";
+        // 沒有結束欄位時標到字結尾
+        assert_eq!(at(Some(8), None).char_range(line), (7, 12));
+        assert_eq!(at(Some(3), Some(7)).char_range(line), (2, 6));
+        // 沒有欄位時標示整行的文字
+        assert_eq!(at(None, None).char_range(line), (2, 17));
+        // 指向行尾或超出行尾
+        assert_eq!(at(Some(18), None).char_range(line), (17, 18));
+        assert_eq!(at(Some(3), Some(usize::MAX)).char_range(line), (2, 17));
+    }
+}
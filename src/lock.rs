@@ -0,0 +1,288 @@
+// 檔案鎖（advisory lock）
+//
+// 開啟檔案時在同目錄建立 `.檔名.wedi-lock` 標記檔，記錄 PID、主機名稱與
+// 是否有未存檔修改，讓另一個 wedi 實例開啟同一檔案時能提出警告。
+// 這只是提示用的標記，不會阻止其他程式寫入檔案。
+
+use anyhow::Result;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// 另一個實例留下的鎖資訊
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LockInfo {
+    pub pid: u32,
+    pub hostname: String,
+    pub modified: bool,
+}
+
+impl LockInfo {
+    fn current(modified: bool) -> Self {
+        Self {
+            pid: std::process::id(),
+            hostname: hostname(),
+            modified,
+        }
+    }
+
+    fn parse(content: &str) -> Option<Self> {
+        let mut lines = content.lines();
+        let pid = lines.next()?.trim().parse().ok()?;
+        let hostname = lines.next()?.trim().to_string();
+        let modified = lines.next()?.trim() == "1";
+        Some(Self {
+            pid,
+            hostname,
+            modified,
+        })
+    }
+
+    fn serialize(&self) -> String {
+        format!(
+            "{}\n{}\n{}\n",
+            self.pid,
+            self.hostname,
+            if self.modified { 1 } else { 0 }
+        )
+    }
+
+    /// 是否為本實例寫入的鎖；共用主機或 NFS 上其他主機的程序可能有相同的 PID，所以也比對主機名稱
+    fn is_own(&self) -> bool {
+        self.pid == std::process::id() && self.hostname == hostname()
+    }
+
+    /// 判斷持有鎖的程序是否仍在執行
+    ///
+    /// 只有同一台主機上的程序能確認是否已結束；其他主機或無法查詢時保守地視為仍在執行
+    fn is_alive(&self) -> bool {
+        if self.hostname != hostname() {
+            return true;
+        }
+        process_exists(self.pid)
+    }
+}
+
+/// 查詢程序是否存在：送出訊號 0，EPERM 表示程序存在但屬於其他使用者
+#[cfg(unix)]
+fn process_exists(pid: u32) -> bool {
+    let Ok(pid) = libc::pid_t::try_from(pid) else {
+        return true;
+    };
+    if unsafe { libc::kill(pid, 0) } == 0 {
+        return true;
+    }
+    std::io::Error::last_os_error().raw_os_error() != Some(libc::ESRCH)
+}
+
+/// 查詢程序是否存在：開不了程序且不是權限不足時表示已結束
+#[cfg(windows)]
+fn process_exists(pid: u32) -> bool {
+    use winapi::shared::winerror::ERROR_ACCESS_DENIED;
+    use winapi::um::handleapi::CloseHandle;
+    use winapi::um::minwinbase::STILL_ACTIVE;
+    use winapi::um::processthreadsapi::{GetExitCodeProcess, OpenProcess};
+    use winapi::um::winnt::PROCESS_QUERY_LIMITED_INFORMATION;
+
+    unsafe {
+        let process = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, pid);
+        if process.is_null() {
+            return std::io::Error::last_os_error().raw_os_error()
+                == Some(ERROR_ACCESS_DENIED as i32);
+        }
+        let mut code = 0;
+        let ok = GetExitCodeProcess(process, &mut code);
+        CloseHandle(process);
+        ok == 0 || code == STILL_ACTIVE
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+fn process_exists(_pid: u32) -> bool {
+    true
+}
+
+/// 本實例持有的檔案鎖，Drop 時自動移除
+pub struct FileLock {
+    lock_path: PathBuf,
+    modified: bool,
+}
+
+impl FileLock {
+    /// 取得檔案對應的鎖檔路徑
+    pub fn lock_path_for(file_path: &Path) -> Option<PathBuf> {
+        let name = file_path.file_name()?.to_str()?;
+        let dir = file_path.parent().unwrap_or(Path::new(""));
+        Some(dir.join(format!(".{}.wedi-lock", name)))
+    }
+
+    /// 讀取其他仍在執行的實例留下的鎖（過期的鎖會被忽略）
+    pub fn existing(file_path: &Path) -> Option<LockInfo> {
+        let lock_path = Self::lock_path_for(file_path)?;
+        let content = fs::read_to_string(lock_path).ok()?;
+        let info = LockInfo::parse(&content)?;
+
+        if info.is_own() {
+            return None;
+        }
+
+        info.is_alive().then_some(info)
+    }
+
+    /// 建立鎖檔；其他仍在執行的實例持有鎖時不覆蓋（保留它的未存檔修改狀態），返回錯誤
+    pub fn acquire(file_path: &Path) -> Result<Self> {
+        let lock_path = Self::lock_path_for(file_path)
+            .ok_or_else(|| anyhow::anyhow!("Invalid file path: {}", file_path.display()))?;
+        if let Some(info) = Self::existing(file_path) {
+            anyhow::bail!(
+                "Locked by another wedi (pid {} on {})",
+                info.pid,
+                info.hostname
+            );
+        }
+        fs::write(&lock_path, LockInfo::current(false).serialize())?;
+        Ok(Self {
+            lock_path,
+            modified: false,
+        })
+    }
+
+    /// 更新鎖檔中的「未存檔修改」狀態（狀態未改變時不寫檔）
+    pub fn set_modified(&mut self, modified: bool) {
+        if self.modified == modified {
+            return;
+        }
+        self.modified = modified;
+        let _ = fs::write(&self.lock_path, LockInfo::current(modified).serialize());
+    }
+}
+
+impl Drop for FileLock {
+    fn drop(&mut self) {
+        // 只移除自己寫入的鎖，避免刪掉後來接手的實例的鎖
+        let owned = fs::read_to_string(&self.lock_path)
+            .ok()
+            .and_then(|content| LockInfo::parse(&content))
+            .is_some_and(|info| info.is_own());
+        if owned {
+            let _ = fs::remove_file(&self.lock_path);
+        }
+    }
+}
+
+/// 取得主機名稱
+fn hostname() -> String {
+    std::env::var("HOSTNAME")
+        .or_else(|_| std::env::var("COMPUTERNAME"))
+        .ok()
+        .or_else(|| {
+            fs::read_to_string("/etc/hostname")
+                .ok()
+                .map(|s| s.trim().to_string())
+        })
+        .filter(|s| !s.is_empty())
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_lock_info_roundtrip() {
+        let info = LockInfo {
+            pid: 42,
+            hostname: "server".to_string(),
+            modified: true,
+        };
+        assert_eq!(LockInfo::parse(&info.serialize()), Some(info));
+        assert_eq!(LockInfo::parse("garbage"), None);
+    }
+
+    #[test]
+    fn test_acquire_and_release() {
+        let temp_dir = TempDir::new().unwrap();
+        let file_path = temp_dir.path().join("notes.txt");
+        let lock_path = FileLock::lock_path_for(&file_path).unwrap();
+        assert_eq!(lock_path, temp_dir.path().join(".notes.txt.wedi-lock"));
+
+        let mut lock = FileLock::acquire(&file_path).unwrap();
+        assert!(lock_path.exists());
+
+        // 自己持有的鎖不算衝突
+        assert_eq!(FileLock::existing(&file_path), None);
+
+        lock.set_modified(true);
+        let content = fs::read_to_string(&lock_path).unwrap();
+        assert!(LockInfo::parse(&content).unwrap().modified);
+
+        drop(lock);
+        assert!(!lock_path.exists());
+    }
+
+    #[test]
+    fn test_foreign_lock_detected() {
+        let temp_dir = TempDir::new().unwrap();
+        let file_path = temp_dir.path().join("config.yaml");
+        let lock_path = FileLock::lock_path_for(&file_path).unwrap();
+
+        // 其他主機的鎖無法確認是否過期，視為有效
+        let foreign = LockInfo {
+            pid: 1,
+            hostname: "another-host".to_string(),
+            modified: true,
+        };
+        fs::write(&lock_path, foreign.serialize()).unwrap();
+        assert_eq!(FileLock::existing(&file_path), Some(foreign.clone()));
+
+        // 不覆蓋其他實例的鎖
+        assert!(FileLock::acquire(&file_path).is_err());
+        assert_eq!(FileLock::existing(&file_path), Some(foreign));
+    }
+
+    #[test]
+    fn test_dead_process_lock_is_stale() {
+        let temp_dir = TempDir::new().unwrap();
+        let file_path = temp_dir.path().join("crashed.txt");
+        let lock_path = FileLock::lock_path_for(&file_path).unwrap();
+
+        // 同一台主機上已結束的程序留下的鎖（例如當掉後）
+        let mut child = std::process::Command::new(std::env::current_exe().unwrap())
+            .arg("--list")
+            .stdout(std::process::Stdio::null())
+            .spawn()
+            .unwrap();
+        let pid = child.id();
+        child.wait().unwrap();
+        let stale = LockInfo {
+            pid,
+            hostname: hostname(),
+            modified: true,
+        };
+        fs::write(&lock_path, stale.serialize()).unwrap();
+        assert_eq!(FileLock::existing(&file_path), None);
+
+        let _lock = FileLock::acquire(&file_path).unwrap();
+        assert!(LockInfo::parse(&fs::read_to_string(&lock_path).unwrap())
+            .unwrap()
+            .is_own());
+    }
+
+    #[test]
+    fn test_same_pid_on_another_host_not_removed() {
+        let temp_dir = TempDir::new().unwrap();
+        let file_path = temp_dir.path().join("shared.txt");
+        let lock_path = FileLock::lock_path_for(&file_path).unwrap();
+
+        // 其他主機上 PID 相同的程序接手了鎖
+        let lock = FileLock::acquire(&file_path).unwrap();
+        let other_host = LockInfo {
+            pid: std::process::id(),
+            hostname: "another-host".to_string(),
+            modified: false,
+        };
+        fs::write(&lock_path, other_host.serialize()).unwrap();
+        drop(lock);
+        assert!(lock_path.exists());
+    }
+}
@@ -0,0 +1,551 @@
+// 精簡的 LSP 客戶端（feature `lsp`）
+//
+// 依檔案類型啟動語言伺服器，以整份文字同步（didOpen/didChange/didSave），
+// 接收 publishDiagnostics 顯示在行號欄，並支援 hover 查詢。
+// 伺服器的輸出由背景執行緒讀取、輸入由另一個執行緒寫入，編輯不會被伺服器阻塞；
+// 收到需要處理的訊息時由讀取執行緒喚醒主迴圈，閒置時不必輪詢
+
+use crate::lint::Diagnostic;
+use crate::view::DiagnosticMarker;
+use anyhow::{bail, Context, Result};
+use serde_json::{json, Value};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc::{self, Receiver, Sender, TryRecvError};
+
+/// (副檔名, languageId, 命令)
+const SERVERS: &[(&[&str], &str, &str)] = &[
+    (&["rs"], "rust", "rust-analyzer"),
+    (&["py", "pyi"], "python", "pylsp"),
+    (&["go"], "go", "gopls"),
+    (&["c", "h"], "c", "clangd"),
+    (&["cc", "cpp", "cxx", "hpp", "hh"], "cpp", "clangd"),
+    (
+        &["ts", "tsx"],
+        "typescript",
+        "typescript-language-server --stdio",
+    ),
+    (
+        &["js", "jsx", "mjs", "cjs"],
+        "javascript",
+        "typescript-language-server --stdio",
+    ),
+    (&["sh", "bash"], "shellscript", "bash-language-server start"),
+    (&["lua"], "lua", "lua-language-server"),
+    (&["zig"], "zig", "zls"),
+];
+
+/// 尋找專案根目錄時辨識的檔案
+const ROOT_MARKERS: &[&str] = &[
+    ".git",
+    "Cargo.toml",
+    "package.json",
+    "go.mod",
+    "pyproject.toml",
+    "setup.py",
+    "compile_commands.json",
+];
+
+/// 依序以副檔名尋找預設的語言伺服器，返回 (languageId, 命令)
+pub fn server_for<'a>(
+    extensions: impl IntoIterator<Item = &'a str>,
+) -> Option<(&'static str, &'static str)> {
+    extensions.into_iter().find_map(|ext| {
+        let ext = ext.to_lowercase();
+        SERVERS
+            .iter()
+            .find(|(exts, _, _)| exts.contains(&ext.as_str()))
+            .map(|(_, language, command)| (*language, *command))
+    })
+}
+
+/// 客戶端從伺服器收到的事件
+#[derive(Debug, PartialEq)]
+pub enum LspEvent {
+    /// 目前檔案的診斷結果（依行號排序）
+    Diagnostics(Vec<Diagnostic>),
+    /// hover 的結果；None 表示該位置沒有資訊
+    Hover(Option<String>),
+    /// 伺服器已結束
+    Exited,
+}
+
+/// 與一個語言伺服器的連線，只同步一個檔案
+pub struct LspClient {
+    name: String,
+    child: Option<Child>,
+    outgoing: Option<Sender<Value>>,
+    incoming: Receiver<Value>,
+    uri: String,
+    next_id: u64,
+    /// initialize 的請求 id；收到回應前送出的通知先排隊
+    initialize_id: Option<u64>,
+    queued: Vec<Value>,
+    hover_id: Option<u64>,
+    version: i64,
+}
+
+impl LspClient {
+    /// 啟動語言伺服器並開啟檔案
+    ///
+    /// 讀取執行緒收到診斷、回應或伺服器的請求後呼叫 `wake`（例如 [`Terminal::wake`]），
+    /// 進度等其他通知留到下次 `poll` 再處理
+    ///
+    /// [`Terminal::wake`]: crate::terminal::Terminal::wake
+    pub fn start(
+        mut command: Command,
+        name: &str,
+        path: &Path,
+        language_id: &str,
+        text: String,
+        wake: impl Fn() + Send + 'static,
+    ) -> Result<Self> {
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .with_context(|| format!("Cannot start {}", name))?;
+        let stdin = child.stdin.take().context("No stdin")?;
+        let stdout = child.stdout.take().context("No stdout")?;
+
+        let (outgoing, writer_queue) = mpsc::channel::<Value>();
+        std::thread::spawn(move || {
+            let mut stdin = stdin;
+            for message in writer_queue {
+                if write_message(&mut stdin, &message).is_err() {
+                    break;
+                }
+            }
+        });
+        let (reader_queue, incoming) = mpsc::channel();
+        std::thread::spawn(move || {
+            let mut stdout = BufReader::new(stdout);
+            while let Ok(Some(message)) = read_message(&mut stdout) {
+                let urgent = needs_attention(&message);
+                if reader_queue.send(message).is_err() {
+                    return;
+                }
+                if urgent {
+                    wake();
+                }
+            }
+            // 伺服器結束，讓主迴圈發現連線中斷
+            drop(reader_queue);
+            wake();
+        });
+
+        let root = project_root(path);
+        let mut client = Self {
+            name: name.to_string(),
+            child: Some(child),
+            outgoing: Some(outgoing),
+            incoming,
+            uri: file_uri(path),
+            next_id: 1,
+            initialize_id: None,
+            queued: Vec::new(),
+            hover_id: None,
+            version: 1,
+        };
+        let id = client.request(
+            "initialize",
+            json!({
+                "processId": std::process::id(),
+                "rootUri": file_uri(&root),
+                "workspaceFolders": [{
+                    "uri": file_uri(&root),
+                    "name": root.file_name().map(|n| n.to_string_lossy()).unwrap_or_default(),
+                }],
+                "capabilities": {
+                    "textDocument": {
+                        "synchronization": { "didSave": true },
+                        "publishDiagnostics": {},
+                        "hover": { "contentFormat": ["plaintext", "markdown"] },
+                    },
+                },
+            }),
+        );
+        client.initialize_id = Some(id);
+        client.notify(
+            "textDocument/didOpen",
+            json!({
+                "textDocument": {
+                    "uri": client.uri,
+                    "languageId": language_id,
+                    "version": client.version,
+                    "text": text,
+                },
+            }),
+        );
+        Ok(client)
+    }
+
+    /// 伺服器命令的名稱（第一個字）
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// 以整份文字通知檔案內容改變
+    pub fn did_change(&mut self, text: String) {
+        self.version += 1;
+        self.notify(
+            "textDocument/didChange",
+            json!({
+                "textDocument": { "uri": self.uri, "version": self.version },
+                "contentChanges": [{ "text": text }],
+            }),
+        );
+    }
+
+    pub fn did_save(&mut self) {
+        self.notify(
+            "textDocument/didSave",
+            json!({ "textDocument": { "uri": self.uri } }),
+        );
+    }
+
+    /// 查詢 `row` 行第 `character` 個 UTF-16 單位的說明，結果由 `poll` 以 `LspEvent::Hover` 返回
+    pub fn hover(&mut self, row: usize, character: usize) {
+        let id = self.request(
+            "textDocument/hover",
+            json!({
+                "textDocument": { "uri": self.uri },
+                "position": { "line": row, "character": character },
+            }),
+        );
+        self.hover_id = Some(id);
+    }
+
+    /// 處理目前已收到的訊息（不阻塞）
+    pub fn poll(&mut self) -> Vec<LspEvent> {
+        let mut events = Vec::new();
+        loop {
+            match self.incoming.try_recv() {
+                Ok(message) => events.extend(self.handle(message)),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    events.push(LspEvent::Exited);
+                    break;
+                }
+            }
+        }
+        events
+    }
+
+    fn handle(&mut self, message: Value) -> Option<LspEvent> {
+        let id = message.get("id").cloned();
+        match message.get("method").and_then(Value::as_str) {
+            Some("textDocument/publishDiagnostics") => {
+                let params = message.get("params")?;
+                if params.get("uri").and_then(Value::as_str) != Some(self.uri.as_str()) {
+                    return None;
+                }
+                Some(LspEvent::Diagnostics(parse_diagnostics(params)))
+            }
+            // 伺服器送來的請求都需要回應，否則有些伺服器會一直等待
+            Some(method) => {
+                let id = id?;
+                let result = if method == "workspace/configuration" {
+                    let items = message["params"]["items"].as_array().map_or(0, Vec::len);
+                    Value::Array(vec![Value::Null; items])
+                } else {
+                    Value::Null
+                };
+                self.send(json!({ "jsonrpc": "2.0", "id": id, "result": result }));
+                None
+            }
+            None => {
+                let id = id.as_ref().and_then(Value::as_u64)?;
+                if self.initialize_id == Some(id) {
+                    self.initialize_id = None;
+                    self.send(json!({ "jsonrpc": "2.0", "method": "initialized", "params": {} }));
+                    for message in std::mem::take(&mut self.queued) {
+                        self.send(message);
+                    }
+                    None
+                } else if self.hover_id == Some(id) {
+                    self.hover_id = None;
+                    Some(LspEvent::Hover(hover_text(&message["result"])))
+                } else {
+                    None
+                }
+            }
+        }
+    }
+
+    fn request(&mut self, method: &str, params: Value) -> u64 {
+        let id = self.next_id;
+        self.next_id += 1;
+        let message = json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params });
+        if method == "initialize" {
+            self.send(message);
+        } else {
+            self.send_when_ready(message);
+        }
+        id
+    }
+
+    fn notify(&mut self, method: &str, params: Value) {
+        self.send_when_ready(json!({ "jsonrpc": "2.0", "method": method, "params": params }));
+    }
+
+    fn send_when_ready(&mut self, message: Value) {
+        if self.initialize_id.is_some() {
+            self.queued.push(message);
+        } else {
+            self.send(message);
+        }
+    }
+
+    fn send(&self, message: Value) {
+        if let Some(outgoing) = &self.outgoing {
+            let _ = outgoing.send(message);
+        }
+    }
+}
+
+impl Drop for LspClient {
+    /// 要求伺服器結束；關閉輸入後由背景執行緒回收行程
+    fn drop(&mut self) {
+        if self.initialize_id.is_none() {
+            let id = self.next_id;
+            self.send(json!({ "jsonrpc": "2.0", "id": id, "method": "shutdown" }));
+            self.send(json!({ "jsonrpc": "2.0", "method": "exit" }));
+        }
+        self.outgoing = None;
+        if let Some(mut child) = self.child.take() {
+            std::thread::spawn(move || {
+                std::thread::sleep(std::time::Duration::from_secs(2));
+                if !matches!(child.try_wait(), Ok(Some(_))) {
+                    let _ = child.kill();
+                    let _ = child.wait();
+                }
+            });
+        }
+    }
+}
+
+/// 是否需要喚醒主迴圈處理：回應、伺服器的請求與診斷
+fn needs_attention(message: &Value) -> bool {
+    message.get("id").is_some()
+        || message.get("method").and_then(Value::as_str) == Some("textDocument/publishDiagnostics")
+}
+
+/// 以 `Content-Length` 標頭寫出一則訊息
+fn write_message(writer: &mut impl Write, message: &Value) -> std::io::Result<()> {
+    let body = message.to_string();
+    write!(writer, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    writer.flush()
+}
+
+/// 讀取一則訊息；輸出結束時返回 None
+fn read_message(reader: &mut impl BufRead) -> Result<Option<Value>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("Content-Length") {
+                length = Some(value.trim().parse::<usize>()?);
+            }
+        }
+    }
+    let Some(length) = length else {
+        bail!("Missing Content-Length");
+    };
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    Ok(Some(serde_json::from_slice(&body)?))
+}
+
+fn parse_diagnostics(params: &Value) -> Vec<Diagnostic> {
+    let mut diagnostics: Vec<Diagnostic> = params["diagnostics"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|diagnostic| {
+            let start = &diagnostic["range"]["start"];
+            let end = &diagnostic["range"]["end"];
+            let message = diagnostic["message"].as_str()?.lines().next()?;
+            let message = match diagnostic["source"].as_str() {
+                Some(source) => format!("{}: {}", source, message),
+                None => message.to_string(),
+            };
+            Some(Diagnostic {
+                row: start["line"].as_u64()? as usize,
+                col: start["character"].as_u64().map(|c| c as usize + 1),
+                // 跨行的範圍標到行尾
+                end_col: if end["line"] == start["line"] {
+                    end["character"].as_u64().map(|c| c as usize + 1)
+                } else {
+                    Some(usize::MAX)
+                },
+                // 1 = Error，其餘（Warning、Information、Hint）以警告顯示
+                marker: match diagnostic["severity"].as_u64() {
+                    Some(1) | None => DiagnosticMarker::Error,
+                    Some(_) => DiagnosticMarker::Warning,
+                },
+                message,
+            })
+        })
+        .collect();
+    diagnostics.sort_by_key(|d| (d.row, d.marker, d.col));
+    diagnostics
+}
+
+/// hover 結果的文字：MarkupContent、MarkedString 或其陣列
+fn hover_text(result: &Value) -> Option<String> {
+    fn text(contents: &Value) -> Option<String> {
+        match contents {
+            Value::String(text) => Some(text.clone()),
+            Value::Array(items) => {
+                let parts: Vec<String> = items.iter().filter_map(text).collect();
+                Some(parts.join("\n\n"))
+            }
+            Value::Object(object) => object.get("value").and_then(text),
+            _ => None,
+        }
+    }
+    text(result.get("contents")?)
+        .map(|text| text.trim().to_string())
+        .filter(|text| !text.is_empty())
+}
+
+/// 光標欄位（字元）轉為 LSP 使用的 UTF-16 位移
+pub fn utf16_offset(line: &str, col: usize) -> usize {
+    line.chars().take(col).map(char::len_utf16).sum()
+}
+
+/// 往上尋找含有專案標記的目錄，找不到時使用檔案所在的目錄
+fn project_root(path: &Path) -> PathBuf {
+    let path = absolute(path);
+    let dir = path.parent().unwrap_or(&path);
+    dir.ancestors()
+        .find(|dir| ROOT_MARKERS.iter().any(|marker| dir.join(marker).exists()))
+        .unwrap_or(dir)
+        .to_path_buf()
+}
+
+fn absolute(path: &Path) -> PathBuf {
+    std::fs::canonicalize(path).unwrap_or_else(|_| {
+        std::env::current_dir()
+            .map(|dir| dir.join(path))
+            .unwrap_or_else(|_| path.to_path_buf())
+    })
+}
+
+/// 檔案路徑轉為 `file://` URI
+fn file_uri(path: &Path) -> String {
+    let path = absolute(path).to_string_lossy().replace('\\', "/");
+    // Windows 的路徑（C:/...）前面需要多一個 /
+    let path = path.trim_start_matches("//?/");
+    let mut uri = String::from(if path.starts_with('/') {
+        "file://"
+    } else {
+        "file:///"
+    });
+    for byte in path.bytes() {
+        if byte.is_ascii_alphanumeric() || b"/-._~:".contains(&byte) {
+            uri.push(byte as char);
+        } else {
+            uri.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    uri
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_message_framing() {
+        let mut bytes = Vec::new();
+        write_message(&mut bytes, &json!({ "id": 1, "result": "é" })).unwrap();
+        write_message(&mut bytes, &json!({ "method": "exit" })).unwrap();
+        assert!(bytes.starts_with(b"Content-Length: 22\r\n\r\n"));
+
+        let mut reader = std::io::Cursor::new(bytes);
+        assert_eq!(
+            read_message(&mut reader).unwrap(),
+            Some(json!({ "id": 1, "result": "é" }))
+        );
+        assert_eq!(
+            read_message(&mut reader).unwrap(),
+            Some(json!({ "method": "exit" }))
+        );
+        assert_eq!(read_message(&mut reader).unwrap(), None);
+    }
+
+    #[test]
+    fn test_diagnostics_and_hover() {
+        let params = json!({
+            "uri": "file:///a.rs",
+            "diagnostics": [
+                {
+                    "range": { "start": { "line": 4, "character": 2 }, "end": { "line": 4, "character": 3 } },
+                    "severity": 2,
+                    "message": "unused variable\nsecond line",
+                    "source": "rustc",
+                },
+                {
+                    "range": { "start": { "line": 1, "character": 0 }, "end": { "line": 1, "character": 1 } },
+                    "severity": 1,
+                    "message": "mismatched types",
+                },
+            ],
+        });
+        let diagnostics = parse_diagnostics(&params);
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(
+            diagnostics[0],
+            Diagnostic {
+                row: 1,
+                col: Some(1),
+                end_col: Some(2),
+                marker: DiagnosticMarker::Error,
+                message: "mismatched types".to_string(),
+            }
+        );
+        assert_eq!(diagnostics[1].marker, DiagnosticMarker::Warning);
+        assert_eq!(diagnostics[1].message, "rustc: unused variable");
+
+        let markup =
+            json!({ "contents": { "kind": "markdown", "value": "```rust\nfn main()\n```" } });
+        assert_eq!(
+            hover_text(&markup).as_deref(),
+            Some("```rust\nfn main()\n```")
+        );
+        let marked = json!({ "contents": ["a", { "language": "rust", "value": "b" }] });
+        assert_eq!(hover_text(&marked).as_deref(), Some("a\n\nb"));
+        assert_eq!(hover_text(&Value::Null), None);
+
+        assert!(needs_attention(&json!({ "id": 1, "result": null })));
+        assert!(needs_attention(
+            &json!({ "method": "textDocument/publishDiagnostics", "params": params })
+        ));
+        assert!(!needs_attention(
+            &json!({ "method": "$/progress", "params": {} })
+        ));
+
+        assert_eq!(utf16_offset("a😀b", 2), 3);
+        assert_eq!(server_for(["txt", "RS"]), Some(("rust", "rust-analyzer")));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_file_uri_escapes_path() {
+        assert_eq!(
+            file_uri(Path::new("/tmp/my dir/a#1.rs")),
+            "file:///tmp/my%20dir/a%231.rs"
+        );
+    }
+}
@@ -1,26 +1,62 @@
+mod analysis;
+mod autosave;
+mod backend;
+mod browser;
 mod buffer;
+mod charinfo;
+mod cli;
 mod clipboard;
 mod comment;
+mod completion;
 mod config;
 mod cursor;
 mod dialog;
+mod diff;
 mod editor;
+mod editor_core;
+mod ex;
+mod fileref;
+mod filetype;
+mod finder;
+mod format;
+#[cfg(feature = "git")]
+mod git;
+mod grep;
+mod grid;
+mod health;
+mod hexview;
 mod highlight;
+mod hooks;
 mod input;
+mod jumplist;
+mod lint;
+mod lock;
+#[cfg(feature = "lsp")]
+mod lsp;
+#[cfg(feature = "vi")]
+mod modal;
 mod search;
+mod styled;
+mod template;
 mod terminal;
+mod theme;
+mod undofile;
 mod utils;
 mod view;
+mod whitespace;
 
-use anyhow::Result;
-use buffer::EncodingConfig;
+use anyhow::{Context, Result};
+use buffer::{EncodingConfig, RopeBuffer};
+use cli::{Cli, Subcommand};
 use editor::Editor;
 use pico_args::Arguments;
+use std::io::{IsTerminal, Read, Write};
 use std::path::PathBuf;
 
 fn parse_encoding(
     from_encoding: Option<&str>,
     to_encoding: Option<&str>,
+    bom: Option<bool>,
 ) -> Result<EncodingConfig> {
     // 解析讀取編碼
     let read_encoding = if let Some(enc_str) = from_encoding {
@@ -45,6 +81,7 @@ fn parse_encoding(
     Ok(EncodingConfig {
         read_encoding,
         save_encoding,
+        bom,
     })
 }
 
@@ -69,7 +106,11 @@ fn parse_single_encoding(enc_str: &str) -> Result<&'static encoding_rs::Encoding
             if let Some(enc) = encoding_rs::Encoding::for_label(enc_str.as_bytes()) {
                 Ok(enc)
             } else {
-                anyhow::bail!("Unsupported encoding: {}", enc_str);
+                anyhow::bail!(
+                    "Unsupported encoding: {}\n\nKnown encodings: {}",
+                    enc_str,
+                    editor::ENCODING_LABELS.join(", ")
+                );
             }
         }
     }
@@ -81,6 +122,23 @@ struct Args {
     debug: bool,
     from_encoding: Option<String>,
     to_encoding: Option<String>,
+    bom: Option<bool>,
+    stdout: bool,
+    hex: bool,
+    line: Option<usize>,
+    cursor: Option<terminal::CursorStyle>,
+    selection_cursor: Option<terminal::CursorStyle>,
+    ambiguous_width: Option<utils::AmbiguousWidth>,
+    ruler: Option<Vec<usize>>,
+    line_numbers: Option<view::LineNumberMode>,
+    cursor_line: Option<crossterm::style::Color>,
+    color_columns: Option<Vec<usize>>,
+    color_column_color: Option<crossterm::style::Color>,
+    autosave: Option<autosave::AutosavePolicy>,
+    autowrite: bool,
+    must_save: bool,         // 沒有存檔就離開時以 exit_code::DISCARDED 結束
+    settings: Vec<String>,   // --set KEY=VALUE，依序以 :set 套用
+    config: Option<PathBuf>, // 設定檔（None 表示不讀取）
     #[cfg(feature = "syntax-highlighting")]
     theme: Option<String>,
     #[cfg(feature = "syntax-highlighting")]
@@ -89,9 +147,7 @@ struct Args {
 }
 
 impl Args {
-    fn parse() -> Result<Self> {
-        let mut pargs = Arguments::from_env();
-
+    fn parse(mut pargs: Arguments, settings: Vec<String>) -> Result<Self> {
         // 檢查是否有 --help
         if pargs.contains(["-h", "--help"]) {
             Self::print_help();
@@ -113,6 +169,62 @@ impl Args {
 
         let debug = pargs.contains("--debug");
 
+        // 設定檔：--config 指定路徑，--no-config 不讀取
+        let config_path: Option<PathBuf> = pargs.opt_value_from_os_str("--config", |s| {
+            Ok::<_, std::convert::Infallible>(PathBuf::from(s))
+        })?;
+        let config = if pargs.contains("--no-config") {
+            None
+        } else {
+            config_path.or_else(config::path)
+        };
+
+        // -o/--stdout：filter 模式，結束時將內容寫到 stdout
+        let stdout = pargs.contains(["-o", "--stdout"]);
+
+        // --hex：以唯讀的十六進位檢視開啟檔案
+        let hex = pargs.contains("--hex");
+
+        // 游標樣式：block、bar、underline，加上 -blink 表示閃爍
+        let cursor = pargs.opt_value_from_str("--cursor")?;
+        let selection_cursor = pargs.opt_value_from_str("--selection-cursor")?;
+
+        // 東亞模糊寬度字元：narrow、wide 或 auto（啟動時測量終端）
+        let ambiguous_width = pargs.opt_value_from_str("--ambiguous-width")?;
+
+        // 行號：absolute、relative（與光標的距離）或 hybrid（光標行為絕對行號）
+        let line_numbers = pargs.opt_value_from_str("--line-numbers")?;
+
+        // --cursor-line 標示光標所在行，--cursor-line-color 另外指定背景色
+        let cursor_line_color =
+            pargs.opt_value_from_fn("--cursor-line-color", styled::parse_color)?;
+        let cursor_line = if pargs.contains("--cursor-line") {
+            Some(cursor_line_color.unwrap_or(editor::DEFAULT_CURSOR_LINE_COLOR))
+        } else {
+            cursor_line_color
+        };
+
+        // 欄位參考線（例如行長限制 80,100）與參考線的背景色
+        let color_columns = pargs.opt_value_from_fn("--color-columns", view::parse_columns)?;
+        let color_column_color =
+            pargs.opt_value_from_fn("--color-column-color", styled::parse_color)?;
+
+        // 自動存檔：off、file（寫回原檔）或 recovery（只寫復原檔）
+        let autosave = pargs.opt_value_from_str("--autosave")?;
+        // --autowrite：執行外部命令（:!）前先存檔
+        let autowrite = pargs.contains("--autowrite");
+
+        // --must-save：沒有存檔就離開視為放棄（例如作為 git 的 $EDITOR 時中止提交）
+        let must_save = pargs.contains("--must-save");
+
+        // --ruler 顯示欄位尺規，--ruler-columns 另外標示指定的欄位
+        let ruler_columns = pargs.opt_value_from_fn("--ruler-columns", view::parse_columns)?;
+        let ruler = if pargs.contains("--ruler") {
+            Some(ruler_columns.unwrap_or_default())
+        } else {
+            ruler_columns
+        };
+
         // 解析主題參數
         #[cfg(feature = "syntax-highlighting")]
         let theme = pargs.opt_value_from_str("--theme")?;
@@ -130,12 +242,31 @@ impl Args {
             .opt_value_from_str(["-t", "--to-encoding"])?
             .or(encoding);
 
-        let file = pargs
-            .free_from_str()
-            .unwrap_or_else(|_| PathBuf::from("Untitled"));
+        // --bom/--no-bom 強制存檔時加上或移除 BOM，未指定時沿用原檔
+        let bom = match (pargs.contains("--bom"), pargs.contains("--no-bom")) {
+            (true, true) => anyhow::bail!("--bom and --no-bom cannot be used together"),
+            (true, false) => Some(true),
+            (false, true) => Some(false),
+            (false, false) => None,
+        };
+
+        // 其餘參數：`+N`（開啟後跳到第 N 行，與 vi 相同，方便作為 git core.editor）與檔名
+        let mut file = None;
+        let mut line = None;
+        let mut remaining = Vec::new();
+        for arg in pargs.finish() {
+            let text = arg.to_string_lossy();
+            if let Some(num) = text.strip_prefix('+').and_then(|n| n.parse::<usize>().ok()) {
+                line = Some(num);
+            } else if file.is_none() {
+                file = Some(PathBuf::from(arg));
+            } else {
+                remaining.push(arg);
+            }
+        }
+        let file = file.unwrap_or_else(|| PathBuf::from("Untitled"));
 
         // 檢查未處理的參數
-        let remaining = pargs.finish();
         if !remaining.is_empty() {
             eprintln!("Warning: unused arguments {:?}", remaining);
         }
@@ -145,6 +276,23 @@ impl Args {
             debug,
             from_encoding,
             to_encoding,
+            bom,
+            stdout,
+            hex,
+            line,
+            cursor,
+            selection_cursor,
+            ambiguous_width,
+            ruler,
+            line_numbers,
+            cursor_line,
+            color_columns,
+            color_column_color,
+            autosave,
+            autowrite,
+            must_save,
+            settings,
+            config,
             #[cfg(feature = "syntax-highlighting")]
             theme,
             #[cfg(feature = "syntax-highlighting")]
@@ -179,9 +327,35 @@ impl Args {
         println!("wedi - A easy-to-use text editor");
         println!();
         println!("USAGE:");
-        println!("    wedi [OPTIONS] [FILE]");
+        println!("    wedi [edit] [OPTIONS] [+LINE] [FILE]");
+        println!(
+            "    wedi [OPTIONS] DIRECTORY           Browse a directory and pick a file to open"
+        );
+        println!(
+            "    command | wedi [OPTIONS] -         Edit piped input (asks for a filename on save)"
+        );
+        println!("    wedi [--set KEY=VALUE]... <SUBCOMMAND> [ARGS]");
+        println!();
+        println!("SUBCOMMANDS:");
+        println!("    edit                               Open the editor (the default)");
+        println!("    diff                               Print a unified diff of two files");
+        println!("    convert                            Re-encode files (also convert-encoding)");
+        #[cfg(feature = "syntax-highlighting")]
+        println!("    highlight                          Print a file with syntax highlighting (ANSI or HTML;");
+        #[cfg(feature = "syntax-highlighting")]
+        println!("                                       also highlight-export)");
+        println!("    Run `wedi <SUBCOMMAND> --help` for the subcommand's options.");
         println!();
         println!("OPTIONS:");
+        println!(
+            "    --set KEY[=VALUE]                  Set an editor option as with :set (repeatable,"
+        );
+        println!(
+            "                                       e.g. --set colorcolumn=80 --set cursorline)"
+        );
+        println!("    --config <FILE>                    Read options from FILE (default: $WEDI_CONFIG or");
+        println!("                                       ~/.config/wedi/config; %APPDATA%\\wedi\\config on Windows)");
+        println!("    --no-config                        Do not read a configuration file");
         println!("    -h, --help                         Show this help message");
         println!("    -v, --version                      Show version information");
         println!("    --debug                            Enable debug mode");
@@ -191,6 +365,34 @@ impl Args {
             "    -f, --from-encoding <ENCODING>     Encoding for reading files (overrides -e)"
         );
         println!("    -t, --to-encoding <ENCODING>       Encoding for saving files (overrides -e)");
+        println!("    -o, --stdout                       Write the buffer to stdout on quit (filter mode)");
+        println!("    --hex                              Open the file in a read-only hex view");
+        println!("                                       (offered automatically for binary files)");
+        println!("    --bom, --no-bom                    Add or strip the byte order mark on save");
+        println!("                                       (default: keep the file's original BOM)");
+        println!("    --cursor <STYLE>                   Cursor style: block, bar or underline;");
+        println!("                                       add -blink to blink (e.g. bar-blink)");
+        println!("    --selection-cursor <STYLE>         Cursor style while selecting text");
+        println!("    --ambiguous-width <WIDTH>          Width of East Asian ambiguous characters (e.g. ±):");
+        println!("                                       narrow (default), wide, or auto to probe the terminal");
+        println!("    --line-numbers <MODE>              Line numbers: absolute (default), relative or hybrid");
+        println!("    --cursor-line                      Highlight the line containing the cursor");
+        println!("    --cursor-line-color <COLOR>        Cursor line background: a name (e.g. dark_blue),");
+        println!(
+            "                                       #rrggbb or a 256-color index (default: 236)"
+        );
+        println!("    --color-columns <COLS>             Draw column guides (e.g. 80,120)");
+        println!("    --color-column-color <COLOR>       Column guide background (default: 52)");
+        println!("    --autosave <POLICY>                Save after 2 seconds of inactivity: off (default),");
+        println!("                                       file (write the file) or recovery (write a recovery copy)");
+        println!("    --autowrite                        Save before running :!COMMAND");
+        println!(
+            "    --must-save                        Exit with status 1 unless the file was saved"
+        );
+        println!("    --ruler                            Show a column ruler above the text");
+        println!(
+            "    --ruler-columns <COLS>             Show the ruler and mark columns (e.g. 7,73)"
+        );
         #[cfg(feature = "syntax-highlighting")]
         println!("    --theme <THEME>                    Set syntax highlighting theme");
         #[cfg(feature = "syntax-highlighting")]
@@ -198,62 +400,16 @@ impl Args {
         println!();
         println!("KEYBOARD SHORTCUTS:");
         println!();
-        println!("  Basic Editing:");
-        println!("    Ctrl+W              Save file");
-        println!("    Ctrl+Q              Quit (press twice if modified)");
-        println!("    Ctrl+Z              Undo");
-        println!("    Ctrl+Y              Redo");
-        println!("    Backspace           Delete character before cursor or selected text");
-        println!("    Delete              Delete character under cursor or selected text");
-        println!("    Ctrl+D              Delete current line or selected lines");
-        println!("    Tab                 Indent (insert 4 spaces or indent selected lines)");
-        println!("    Shift+Tab           Unindent (remove up to 4 leading spaces)");
-        println!();
-        println!("  Navigation:");
-        println!("    Arrow Keys          Move cursor");
-        println!("    Ctrl+Left/Home      Move to line start");
-        println!("    Ctrl+Right/End      Move to line end");
-        println!("    Ctrl+Up/Ctrl+Home   Move to first line");
-        println!("    Ctrl+Down/Ctrl+End  Move to last line");
-        println!("    Page Up/Down        Scroll page up/down");
-        println!("    Ctrl+PageUp/Down    Jump 1/10 of file");
-        println!("    Ctrl+G              Go to line number");
-        println!();
-        println!("  Selection:");
-        println!(
-            "    Ctrl+S              Toggle selection mode (for terminals without Shift support)"
-        );
-        println!("    Shift+Arrows        Select text");
-        println!("    Shift+Ctrl+Arrows   Quick select to line/file boundaries");
-        println!("    Shift+Home/End      Select to line boundaries");
-        println!("    Shift+Ctrl+Home/End Quick select to file boundaries");
-        println!("    Shift+PgUp/Dn       Select page up/down");
-        println!("    Ctrl+A              Select all");
-        println!("    ESC                 Clear selection and messages");
-        println!();
-        println!("  Clipboard:");
-        println!("    Ctrl+C              Copy (selection or current line)");
-        println!("    Ctrl+X              Cut (selection or current line)");
-        println!("    Ctrl+V              Paste");
-        println!("    Alt+C               Internal Copy (selection or current line)");
-        println!("    Alt+X               Internal Cut (selection or current line)");
-        println!("    Alt+V               Internal Paste");
-        println!();
-        println!("  Search:");
-        println!("    Ctrl+F              Find text");
-        println!("    F3                  Find next match");
-        println!("    F4                  Find previous match");
-        println!();
-        println!("  Code:");
-        println!("    Ctrl+/ \\ K         Toggle line comment");
-        println!("    Ctrl+L              Toggle line numbers");
-        #[cfg(feature = "syntax-highlighting")]
-        println!("    Ctrl+H              Toggle syntax highlight (Disabled/Fast/Accurate)");
+        for line in input::KEY_HELP {
+            println!("{}", line);
+        }
         println!();
-        println!("  Encoding:");
-        println!(
-            "    Ctrl+E              Change file encoding (utf-8, gbk, big5, shift-jis, etc.)"
-        );
+        println!("EXIT STATUS:");
+        println!("    0  Quit with everything saved (or nothing changed)");
+        println!("    1  Quit with unsaved changes discarded (with --must-save: without saving)");
+        println!("    2  The file could not be opened");
+        println!("    3  Unsupported or invalid encoding");
+        println!("    4  Other errors (invalid options, terminal or I/O errors)");
         println!();
         println!("SUPPORTED COMMENT STYLES:");
         println!("  //  - Rust, C/C++, Java, JavaScript, TypeScript, Go, C#");
@@ -264,9 +420,46 @@ impl Args {
     }
 }
 
+/// `wedi edit` 的結束碼，讓包裝 wedi 的腳本（例如作為 git 的 `$EDITOR`）判斷結果
+mod exit_code {
+    /// 已存檔或沒有修改
+    pub const OK: i32 = 0;
+    /// 放棄未存檔的修改離開；`--must-save` 時沒有存檔就離開也是
+    pub const DISCARDED: i32 = 1;
+    /// 無法開啟檔案
+    pub const OPEN_FAILED: i32 = 2;
+    /// 不支援或無效的編碼
+    pub const ENCODING: i32 = 3;
+    /// 其他錯誤（命令列選項、終端、I/O 等）
+    pub const ERROR: i32 = 4;
+}
+
+/// 以指定的結束碼回報錯誤
+fn fail(code: i32, error: anyhow::Error) -> i32 {
+    eprintln!("Error: {:?}", error);
+    code
+}
+
 fn main() -> Result<()> {
-    let args = Args::parse()?;
+    let cli = Cli::from_env()?;
+    let code = match cli.subcommand {
+        Subcommand::Edit => match Args::parse(cli.args, cli.settings) {
+            Ok(args) => edit(args).unwrap_or_else(|e| fail(exit_code::ERROR, e)),
+            Err(e) => fail(exit_code::ERROR, e),
+        },
+        Subcommand::Diff => cli::run_diff(cli)?,
+        Subcommand::ConvertEncoding => cli::run_convert_encoding(cli)?,
+        #[cfg(feature = "syntax-highlighting")]
+        Subcommand::HighlightExport => cli::run_highlight_export(cli)?,
+    };
+    if code != 0 {
+        std::process::exit(code);
+    }
+    Ok(())
+}
 
+/// `wedi edit`：開啟編輯器，返回結束碼（見 [`exit_code`]）
+fn edit(mut args: Args) -> Result<i32> {
     // 設置全局調試模式（支持 release 版本通過 --debug 參數啟用）
     utils::set_debug_mode(args.debug);
 
@@ -274,8 +467,14 @@ fn main() -> Result<()> {
     debug_log!("Starting wedi with file: {:?}", args.file);
     debug_log!("Debug mode enabled");
 
-    let encoding_config =
-        parse_encoding(args.from_encoding.as_deref(), args.to_encoding.as_deref())?;
+    let encoding_config = match parse_encoding(
+        args.from_encoding.as_deref(),
+        args.to_encoding.as_deref(),
+        args.bom,
+    ) {
+        Ok(config) => config,
+        Err(e) => return Ok(fail(exit_code::ENCODING, e)),
+    };
 
     debug_log!(
         "Read encoding: {:?}",
@@ -286,24 +485,144 @@ fn main() -> Result<()> {
         encoding_config.save_encoding.map(|e| e.name())
     );
 
+    // filter 模式：stdout 保留給最終內容，畫面改畫到控制終端
+    if args.stdout {
+        terminal::Terminal::redirect_output_to_tty()?;
+    }
+
+    // 目錄：先瀏覽並選擇要開啟的檔案
+    if args.file.is_dir() {
+        match browser::open_directory(&args.file)? {
+            Some(path) => args.file = path,
+            None => return Ok(exit_code::OK),
+        }
+    }
+
+    // 二進位檔案解碼成文字只會得到亂碼，先詢問是否改用十六進位檢視
+    // （指定讀取編碼時視為使用者確定是文字檔）
+    let is_file = args.file.as_os_str() != "-";
+    if is_file && (args.hex || args.from_encoding.is_none() && hexview::is_binary_file(&args.file))
+    {
+        if args.hex && !args.file.exists() {
+            let error = anyhow::anyhow!("No such file: {}", args.file.display());
+            return Ok(fail(exit_code::OPEN_FAILED, error));
+        }
+        if !hexview::open_binary(&args.file, !args.hex)? {
+            return Ok(exit_code::OK);
+        }
+    }
+
     // 創建並運行編輯器
-    let mut editor = Editor::new(
-        Some(&args.file),
-        args.debug,
-        &encoding_config,
-        #[cfg(feature = "syntax-highlighting")]
-        args.theme.as_deref(),
-    )?;
+    let editor = if args.file.as_os_str() == "-" {
+        // 從 stdin 讀入內容；之後的鍵盤輸入由 crossterm 改從 /dev/tty（Windows 為 CONIN$）讀取
+        let mut stdin = std::io::stdin();
+        if stdin.is_terminal() {
+            let error = anyhow::anyhow!("stdin is a terminal; pipe content into `wedi -`");
+            return Ok(fail(exit_code::OPEN_FAILED, error));
+        }
+        let mut bytes = Vec::new();
+        if let Err(e) = stdin.read_to_end(&mut bytes) {
+            let error = anyhow::Error::new(e).context("Cannot read stdin");
+            return Ok(fail(exit_code::OPEN_FAILED, error));
+        }
+
+        Editor::with_buffer(
+            RopeBuffer::from_bytes_with_encoding(&bytes, &encoding_config),
+            args.debug,
+            #[cfg(feature = "syntax-highlighting")]
+            args.theme.as_deref(),
+        )
+    } else {
+        Editor::new(
+            Some(&args.file),
+            args.debug,
+            &encoding_config,
+            #[cfg(feature = "syntax-highlighting")]
+            args.theme.as_deref(),
+        )
+    };
+    let mut editor = match editor {
+        Ok(editor) => editor,
+        Err(e) => return Ok(fail(exit_code::OPEN_FAILED, e)),
+    };
 
     // 設置 panic hook 以確保終端正常恢復
     let original_hook = std::panic::take_hook();
     std::panic::set_hook(Box::new(move |panic_info| {
         let _ = terminal::Terminal::exit_raw_mode();
         let _ = terminal::Terminal::show_cursor();
+        let _ = terminal::Terminal::reset_cursor_style();
         original_hook(panic_info);
     }));
 
+    // 設定檔先套用，命令列的選項與 --set 再覆蓋
+    if let Some(path) = &args.config {
+        editor.load_config(path);
+    }
+    #[cfg(feature = "syntax-highlighting")]
+    if let Some(theme) = &args.theme {
+        editor
+            .apply_setting(&format!("theme={}", theme))
+            .context("--theme")?;
+    }
+    editor.set_output_to_stdout(args.stdout);
+    editor.set_cursor_styles(args.cursor, args.selection_cursor);
+    if let Some(width) = args.ambiguous_width {
+        editor.set_ambiguous_width(width);
+    }
+    if let Some(mode) = args.line_numbers {
+        editor.set_line_number_mode(mode);
+    }
+    if args.cursor_line.is_some() {
+        editor.set_cursor_line(args.cursor_line);
+    }
+    if args.color_columns.is_some() || args.color_column_color.is_some() {
+        let columns = args.color_columns.unwrap_or_default();
+        editor.set_color_columns(columns, args.color_column_color);
+    }
+    if let Some(policy) = args.autosave {
+        editor.set_autosave(policy);
+    }
+    if args.autowrite {
+        editor.set_autowrite(true);
+    }
+    if let Some(columns) = args.ruler {
+        editor.set_ruler(columns);
+    }
+    for setting in &args.settings {
+        editor
+            .apply_setting(setting)
+            .with_context(|| format!("--set {}", setting))?;
+    }
+    if let Some(line) = args.line {
+        // 超出範圍時與 vi 相同，停在最後一行
+        let last_line = editor.buffer().line_count().max(1);
+        editor.go_to_line(line.clamp(1, last_line));
+    }
     editor.run()?;
 
-    Ok(())
+    // 被 SIGTERM/SIGHUP 等訊號結束：與 shell 相同以 128 + 訊號編號結束
+    if let Some((signal, recovery)) = editor.terminated() {
+        let modified = editor.buffer().is_modified();
+        if let Some(message) = autosave::termination_message(signal, recovery, modified) {
+            // 終端已掛斷時 stderr 無法寫入，eprintln! 會 panic
+            let _ = writeln!(std::io::stderr(), "{}", message);
+        }
+        return Ok(128 + signal);
+    }
+
+    if args.stdout {
+        // filter 模式下內容寫到 stdout，不會遺失修改
+        let mut stdout = std::io::stdout().lock();
+        stdout.write_all(&editor.buffer().encode_contents("stdout"))?;
+        stdout.flush()?;
+        return Ok(exit_code::OK);
+    }
+
+    let discarded = editor.buffer().is_modified() || (args.must_save && !editor.saved());
+    Ok(if discarded {
+        exit_code::DISCARDED
+    } else {
+        exit_code::OK
+    })
 }
@@ -0,0 +1,511 @@
+// vi 風格的模式輸入（`:set vi`，需要 `vi` 功能）
+//
+// 一般與選擇模式的按鍵轉換為既有的命令：hjkl 移動、dd/yy/p 以內部剪貼簿刪除、複製與貼上、
+// i/a/o 進入插入模式、v/V 進入選擇模式。插入模式除了 Esc 之外照常使用快捷鍵；
+// 兩種模式下沒有對應的 Ctrl/Alt 組合鍵與功能鍵也交給一般的快捷鍵（Ctrl+W 存檔等）
+
+use crate::input::{Command, Direction};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+/// 目前的模式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Mode {
+    #[default]
+    Normal,
+    Insert,
+    Visual,
+}
+
+/// 光標是否在行首或行尾；h、l 與 a 在行的邊緣不移動，避免跨到相鄰的行
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LineEdges {
+    pub at_start: bool,
+    pub at_end: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct Modal {
+    mode: Mode,
+    count: usize,          // 輸入中的次數（0 表示沒有）
+    pending: Option<char>, // 等待第二個按鍵的命令（d、y、c、g、z、>、<、'）
+    linewise: bool,        // 內部剪貼簿的內容是否為整行（決定 p 貼在下一行或光標後）
+}
+
+impl Modal {
+    /// 狀態列顯示的模式與輸入中的次數、命令；一般模式且沒有輸入中的按鍵時為 None
+    pub fn indicator(&self) -> Option<String> {
+        let mut keys = String::new();
+        if self.count > 0 {
+            keys.push_str(&self.count.to_string());
+        }
+        keys.extend(self.pending);
+        let mode = match self.mode {
+            Mode::Normal => None,
+            Mode::Insert => Some("-- INSERT --"),
+            Mode::Visual => Some("-- VISUAL --"),
+        };
+        match (mode, keys.is_empty()) {
+            (None, true) => None,
+            (None, false) => Some(keys),
+            (Some(mode), true) => Some(mode.to_string()),
+            (Some(mode), false) => Some(format!("{} {}", mode, keys)),
+        }
+    }
+
+    /// 把按鍵轉換為命令；None 表示交給一般的快捷鍵對應
+    pub fn handle_key(&mut self, key: KeyEvent, edges: LineEdges) -> Option<Vec<Command>> {
+        // 內部標記（貼上、縮放、滑鼠）照常處理，不打斷輸入中的命令
+        if matches!(key.code, KeyCode::F(20..=22)) {
+            return None;
+        }
+        if self.mode == Mode::Insert {
+            if key.code != KeyCode::Esc {
+                return None;
+            }
+            self.mode = Mode::Normal;
+            // 與 vi 相同，回到一般模式時光標退回最後輸入的字元上
+            if edges.at_start {
+                return Some(vec![Command::ClearMessage]);
+            }
+            return Some(vec![Command::ClearMessage, Command::MoveLeft]);
+        }
+
+        let modifiers = key.modifiers - KeyModifiers::SHIFT;
+        if modifiers == KeyModifiers::CONTROL {
+            let KeyCode::Char(c) = key.code else {
+                return None;
+            };
+            let command = match c {
+                'r' => Command::Redo,
+                'f' => Command::PageDown,
+                'b' => Command::PageUp,
+                'd' => Command::ScrollHalfPageDown,
+                'u' => Command::ScrollHalfPageUp,
+                'e' => Command::ScrollLineDown,
+                'y' => Command::ScrollLineUp,
+                _ => return None,
+            };
+            let count = self.take_count();
+            return Some(vec![repeat(count, command)]);
+        }
+        if !modifiers.is_empty() {
+            return None;
+        }
+
+        if let Some(first) = self.pending.take() {
+            let count = self.take_count();
+            return Some(self.operator(first, key.code, count));
+        }
+        match key.code {
+            KeyCode::Char(c @ '1'..='9') => return Some(self.push_digit(c)),
+            KeyCode::Char('0') if self.count > 0 => return Some(self.push_digit('0')),
+            _ => {}
+        }
+
+        let given = self.count > 0;
+        let count = self.take_count();
+        if let Some(commands) = self.motion(key.code, count, given, edges) {
+            return Some(commands);
+        }
+        match self.mode {
+            Mode::Visual => self.visual_key(key.code),
+            _ => self.normal_key(key.code, count, edges),
+        }
+    }
+
+    fn push_digit(&mut self, digit: char) -> Vec<Command> {
+        let value = digit as usize - '0' as usize;
+        self.count = (self.count * 10 + value).min(10_000);
+        Vec::new()
+    }
+
+    /// 取出輸入的次數（沒有輸入時為 1）
+    fn take_count(&mut self) -> usize {
+        std::mem::take(&mut self.count).max(1)
+    }
+
+    /// 移動光標；選擇模式中延伸選擇範圍。`given` 表示有輸入次數
+    fn motion(
+        &self,
+        code: KeyCode,
+        count: usize,
+        given: bool,
+        edges: LineEdges,
+    ) -> Option<Vec<Command>> {
+        let direction = match code {
+            KeyCode::Char('h') | KeyCode::Left | KeyCode::Backspace => {
+                if edges.at_start && count == 1 {
+                    return Some(Vec::new());
+                }
+                Direction::Left
+            }
+            KeyCode::Char('l') | KeyCode::Right | KeyCode::Char(' ') => {
+                if edges.at_end && count == 1 {
+                    return Some(Vec::new());
+                }
+                Direction::Right
+            }
+            KeyCode::Char('j') | KeyCode::Down => Direction::Down,
+            KeyCode::Char('k') | KeyCode::Up => Direction::Up,
+            KeyCode::Char('0' | '^') | KeyCode::Home => Direction::Home,
+            KeyCode::Char('$') | KeyCode::End => Direction::End,
+            KeyCode::Char('{') => Direction::ParagraphUp,
+            KeyCode::Char('}') => Direction::ParagraphDown,
+            KeyCode::PageUp => Direction::PageUp,
+            KeyCode::PageDown => Direction::PageDown,
+            // 有次數時 G 跳到該行
+            KeyCode::Char('G') if given => {
+                return Some(self.go_to_line(count));
+            }
+            KeyCode::Char('G') => Direction::FileEnd,
+            KeyCode::Enter => {
+                return Some(vec![
+                    repeat(count, self.step(Direction::Down)),
+                    self.step(Direction::Home),
+                ]);
+            }
+            _ => return None,
+        };
+        Some(vec![repeat(count, self.step(direction))])
+    }
+
+    /// 一次移動：一般模式移動光標，選擇模式延伸選擇範圍
+    fn step(&self, direction: Direction) -> Command {
+        if self.mode == Mode::Visual {
+            return Command::ExtendSelection(direction);
+        }
+        match direction {
+            Direction::Left => Command::MoveLeft,
+            Direction::Right => Command::MoveRight,
+            Direction::Up => Command::MoveUp,
+            Direction::Down => Command::MoveDown,
+            Direction::Home => Command::MoveHome,
+            Direction::End => Command::MoveEnd,
+            Direction::ParagraphUp => Command::MoveParagraphUp,
+            Direction::ParagraphDown => Command::MoveParagraphDown,
+            Direction::PageUp => Command::PageUp,
+            Direction::PageDown => Command::PageDown,
+            Direction::FileStart => Command::MoveToFileStart,
+            _ => Command::MoveToFileEnd,
+        }
+    }
+
+    /// 跳到第 `line` 行（1-based）
+    fn go_to_line(&self, line: usize) -> Vec<Command> {
+        let mut commands = vec![self.step(Direction::FileStart)];
+        if line > 1 {
+            commands.push(repeat(line - 1, self.step(Direction::Down)));
+        }
+        commands
+    }
+
+    fn normal_key(
+        &mut self,
+        code: KeyCode,
+        count: usize,
+        edges: LineEdges,
+    ) -> Option<Vec<Command>> {
+        let KeyCode::Char(c) = code else {
+            return match code {
+                KeyCode::Esc => Some(vec![Command::ClearMessage]),
+                // 不輸入文字
+                KeyCode::Tab | KeyCode::BackTab => Some(Vec::new()),
+                _ => None,
+            };
+        };
+        let commands = match c {
+            'i' => self.insert(Vec::new()),
+            'a' if edges.at_end => self.insert(Vec::new()),
+            'a' => self.insert(vec![Command::MoveRight]),
+            'A' => self.insert(vec![Command::MoveEnd]),
+            'I' => self.insert(vec![Command::MoveHome]),
+            'o' => self.insert(vec![Command::MoveEnd, Command::Insert('\n')]),
+            'O' => self.insert(vec![
+                Command::MoveHome,
+                Command::Insert('\n'),
+                Command::MoveUp,
+            ]),
+            // 光標在行尾（例如 $ 之後）時刪除最後一個字元
+            'x' if edges.at_end && edges.at_start => Vec::new(),
+            'x' if edges.at_end => vec![Command::Backspace],
+            'x' => vec![repeat(count, Command::Delete)],
+            'X' if edges.at_start => Vec::new(),
+            'X' => vec![repeat(count, Command::Backspace)],
+            's' => {
+                let commands = vec![repeat(count, Command::Delete)];
+                self.insert(commands)
+            }
+            'D' => self.cut_to_line_end(),
+            'C' => {
+                let commands = self.cut_to_line_end();
+                self.insert(commands)
+            }
+            'S' => self.change_line(),
+            'p' if self.linewise => vec![repeat(count, Command::PasteInternalBelow)],
+            'p' if edges.at_end => vec![repeat(count, Command::PasteInternal)],
+            'p' => vec![Command::MoveRight, repeat(count, Command::PasteInternal)],
+            'P' => vec![repeat(count, Command::PasteInternal)],
+            'u' => vec![repeat(count, Command::Undo)],
+            'n' => vec![repeat(count, Command::FindNext)],
+            'N' => vec![repeat(count, Command::FindPrev)],
+            '/' | '?' => vec![Command::Find],
+            ':' => vec![Command::OpenCommandLine],
+            'm' => vec![Command::Mark],
+            'v' => {
+                self.mode = Mode::Visual;
+                if edges.at_end {
+                    Vec::new()
+                } else {
+                    vec![Command::ExtendSelection(Direction::Right)]
+                }
+            }
+            'V' => {
+                self.mode = Mode::Visual;
+                vec![Command::MoveHome, Command::ExtendSelection(Direction::Down)]
+            }
+            'd' | 'y' | 'c' | 'g' | 'z' | '>' | '<' | '\'' | '`' => {
+                self.pending = Some(c);
+                // 第二個按鍵使用同樣的次數
+                self.count = if count > 1 { count } else { 0 };
+                Vec::new()
+            }
+            // 其他字元不輸入文字
+            _ => Vec::new(),
+        };
+        Some(commands)
+    }
+
+    /// 選擇模式：對選擇範圍複製、刪除、縮排或貼上
+    fn visual_key(&mut self, code: KeyCode) -> Option<Vec<Command>> {
+        let commands = match code {
+            KeyCode::Esc | KeyCode::Char('v' | 'V') => vec![Command::ClearMessage],
+            KeyCode::Char('y') => {
+                self.linewise = false;
+                vec![Command::CopyInternal]
+            }
+            KeyCode::Char('d' | 'x') => {
+                self.linewise = false;
+                vec![Command::CutInternal]
+            }
+            KeyCode::Char('c') => {
+                self.linewise = false;
+                return Some(self.insert(vec![Command::CutInternal]));
+            }
+            KeyCode::Char('p') => vec![Command::PasteInternal],
+            KeyCode::Char('>') => vec![Command::Indent],
+            KeyCode::Char('<') => vec![Command::Unindent],
+            KeyCode::Char(':') => vec![Command::OpenCommandLine],
+            KeyCode::Char(_) | KeyCode::Tab | KeyCode::BackTab => return Some(Vec::new()),
+            _ => return None,
+        };
+        self.mode = Mode::Normal;
+        Some(commands)
+    }
+
+    /// 第二個按鍵：dd、yy、cc、d$、c$、gg、zz、>>、<<、'0-9
+    fn operator(&mut self, first: char, code: KeyCode, count: usize) -> Vec<Command> {
+        let KeyCode::Char(second) = code else {
+            return Vec::new();
+        };
+        match (first, second) {
+            ('d', 'd') => {
+                self.linewise = true;
+                self.with_lines(count, Command::CutInternal)
+            }
+            ('y', 'y') => {
+                self.linewise = true;
+                let mut commands = self.with_lines(count, Command::CopyInternal);
+                // 複製後光標留在原本的行
+                if count > 1 {
+                    commands.push(repeat(count, Command::MoveUp));
+                }
+                commands
+            }
+            ('c', 'c') => self.change_line(),
+            ('d', '$') => self.cut_to_line_end(),
+            ('c', '$') => {
+                let commands = self.cut_to_line_end();
+                self.insert(commands)
+            }
+            ('g', 'g') if count > 1 => self.go_to_line(count),
+            ('g', 'g') => vec![Command::MoveToFileStart],
+            ('z', 'z') => vec![Command::CenterCursor],
+            ('>', '>') => vec![Command::MoveHome, Command::Indent],
+            ('<', '<') => vec![Command::Unindent],
+            ('\'' | '`', mark @ '0'..='9') => vec![Command::JumpToMark(mark)],
+            _ => Vec::new(),
+        }
+    }
+
+    /// 對目前開始的 `count` 行執行 `command`（內部剪貼簿的複製或剪下）
+    fn with_lines(&self, count: usize, command: Command) -> Vec<Command> {
+        if count == 1 {
+            // 沒有選擇範圍時複製與剪下整行
+            return vec![command];
+        }
+        vec![
+            Command::MoveHome,
+            repeat(count, Command::ExtendSelection(Direction::Down)),
+            command,
+        ]
+    }
+
+    fn cut_to_line_end(&mut self) -> Vec<Command> {
+        self.linewise = false;
+        vec![
+            Command::ExtendSelection(Direction::End),
+            Command::CutInternal,
+        ]
+    }
+
+    /// 刪除整行的內容（保留換行）並進入插入模式
+    fn change_line(&mut self) -> Vec<Command> {
+        self.linewise = false;
+        self.insert(vec![
+            Command::MoveHome,
+            Command::ExtendSelection(Direction::End),
+            Command::CutInternal,
+        ])
+    }
+
+    /// 執行 `commands` 後進入插入模式
+    fn insert(&mut self, commands: Vec<Command>) -> Vec<Command> {
+        self.mode = Mode::Insert;
+        commands
+    }
+}
+
+/// 執行 `count` 次
+fn repeat(count: usize, command: Command) -> Command {
+    if count == 1 {
+        command
+    } else {
+        Command::WithCount(count, Box::new(command))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(modal: &mut Modal, keys: &str) -> Vec<Command> {
+        keys.chars()
+            .flat_map(|c| {
+                let key = KeyEvent::new(KeyCode::Char(c), KeyModifiers::NONE);
+                modal.handle_key(key, LineEdges::default()).unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_normal_mode_keys() {
+        let mut modal = Modal::default();
+        assert_eq!(keys(&mut modal, "j"), [Command::MoveDown]);
+        assert_eq!(
+            keys(&mut modal, "3j"),
+            [Command::WithCount(3, Box::new(Command::MoveDown))]
+        );
+        assert_eq!(keys(&mut modal, "0"), [Command::MoveHome]);
+        assert_eq!(keys(&mut modal, "dd"), [Command::CutInternal]);
+        assert_eq!(keys(&mut modal, "2"), []);
+        assert_eq!(modal.indicator().as_deref(), Some("2"));
+        assert_eq!(
+            keys(&mut modal, "yy"),
+            [
+                Command::MoveHome,
+                Command::WithCount(2, Box::new(Command::ExtendSelection(Direction::Down))),
+                Command::CopyInternal,
+                Command::WithCount(2, Box::new(Command::MoveUp)),
+            ]
+        );
+        // 整行的內容貼在下一行
+        assert_eq!(keys(&mut modal, "p"), [Command::PasteInternalBelow]);
+        assert_eq!(
+            keys(&mut modal, "12G"),
+            [
+                Command::MoveToFileStart,
+                Command::WithCount(11, Box::new(Command::MoveDown))
+            ]
+        );
+        assert_eq!(keys(&mut modal, "G"), [Command::MoveToFileEnd]);
+        assert_eq!(keys(&mut modal, "'3"), [Command::JumpToMark('3')]);
+        // 等待第二個按鍵時收到縮放標記
+        assert_eq!(keys(&mut modal, "d"), []);
+        let resize = KeyEvent::new(KeyCode::F(21), KeyModifiers::NONE);
+        assert_eq!(modal.handle_key(resize, LineEdges::default()), None);
+        assert_eq!(keys(&mut modal, "d"), [Command::CutInternal]);
+        // 沒有對應的字元不輸入文字；Ctrl 組合鍵交給一般的快捷鍵
+        assert_eq!(keys(&mut modal, "q"), []);
+        let save = KeyEvent::new(KeyCode::Char('w'), KeyModifiers::CONTROL);
+        assert_eq!(modal.handle_key(save, LineEdges::default()), None);
+    }
+
+    #[test]
+    fn test_insert_and_visual_modes() {
+        let mut modal = Modal::default();
+        assert_eq!(
+            keys(&mut modal, "o"),
+            [Command::MoveEnd, Command::Insert('\n')]
+        );
+        assert_eq!(modal.indicator().as_deref(), Some("-- INSERT --"));
+        let key = KeyEvent::new(KeyCode::Char('x'), KeyModifiers::NONE);
+        assert_eq!(modal.handle_key(key, LineEdges::default()), None);
+        let esc = KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE);
+        assert_eq!(
+            modal.handle_key(esc, LineEdges::default()),
+            Some(vec![Command::ClearMessage, Command::MoveLeft])
+        );
+
+        let at_end = LineEdges {
+            at_start: false,
+            at_end: true,
+        };
+        let a = KeyEvent::new(KeyCode::Char('a'), KeyModifiers::NONE);
+        assert_eq!(modal.handle_key(a, at_end), Some(Vec::new()));
+        modal.handle_key(esc, LineEdges::default());
+
+        assert_eq!(
+            keys(&mut modal, "vj"),
+            [
+                Command::ExtendSelection(Direction::Right),
+                Command::ExtendSelection(Direction::Down)
+            ]
+        );
+        assert_eq!(modal.indicator().as_deref(), Some("-- VISUAL --"));
+        assert_eq!(keys(&mut modal, "y"), [Command::CopyInternal]);
+        assert_eq!(modal.indicator(), None);
+        // 選擇範圍複製的內容貼在光標後
+        assert_eq!(
+            keys(&mut modal, "p"),
+            [Command::MoveRight, Command::PasteInternal]
+        );
+    }
+
+    #[test]
+    fn test_paste_line_below_last_line() {
+        use crate::buffer::RopeBuffer;
+        use crate::editor_core::EditorCore;
+
+        let mut core = EditorCore::new(RopeBuffer::new());
+        core.paste_text("first\nlast");
+        let mut modal = Modal::default();
+        let mut run = |core: &mut EditorCore, input: &str| {
+            for command in keys(&mut modal, input) {
+                core.apply(command);
+            }
+        };
+
+        // 最後一行沒有換行：yyp 貼在它之後
+        run(&mut core, "yyp");
+        assert_eq!(core.text(), "first\nlast\nlast");
+        assert_eq!(core.cursor().row, 2);
+        core.apply(Command::Undo);
+        assert_eq!(core.text(), "first\nlast");
+
+        run(&mut core, "ggyyGp");
+        assert_eq!(core.text(), "first\nlast\nfirst");
+        run(&mut core, "ggp");
+        assert_eq!(core.text(), "first\nfirst\nlast\nfirst");
+        assert_eq!(core.cursor().row, 1);
+    }
+}
@@ -1,13 +1,26 @@
 // 搜索功能
-// 這個模組將在後續階段實現
+//
+// 結果分段尋找：每次 [`Search::step`] 最多檢查 SEARCH_CHUNK_LINES 行，
+// 編輯器在按鍵之間繼續下一段，大檔案搜尋時仍能即時回應按鍵
 
 use crate::buffer::RopeBuffer;
+use std::ops::Range;
 
+/// 每一段最多檢查的行數
+const SEARCH_CHUNK_LINES: usize = 20_000;
+
+/// 在緩衝區中尋找文字：[`Search::find_matches`] 一次找完，
+/// 或以 [`Search::start`]/[`Search::step`] 分段尋找
 #[allow(dead_code)]
 pub struct Search {
     query: String,
-    matches: Vec<(usize, usize)>, // (line, col) pairs
-    current_match: usize,
+    matches: Vec<(usize, usize)>,   // (line, col) pairs
+    current_match: Option<usize>,   // 目前所在的結果；None 表示還沒跳到任何結果
+    version: Option<u64>,           // matches 對應的緩衝區版本
+    scope: Option<(usize, usize)>,  // 只在這個字元範圍內尋找（搜尋選擇範圍）
+    pending: Option<Range<usize>>,  // 還沒檢查的行；None 表示已找完
+    lines: Range<usize>,            // 這次搜尋的行範圍（計算進度）
+    resume: Option<(usize, usize)>, // 重新尋找後，目前的結果改為此位置或之後的第一個結果
 }
 
 #[allow(dead_code)]
@@ -16,68 +29,213 @@ impl Search {
         Self {
             query: String::new(),
             matches: Vec::new(),
-            current_match: 0,
+            current_match: None,
+            version: None,
+            scope: None,
+            pending: None,
+            lines: 0..0,
+            resume: None,
         }
     }
 
     pub fn set_query(&mut self, query: String) {
         self.query = query;
         self.matches.clear();
-        self.current_match = 0;
+        self.current_match = None;
+        self.version = None;
+        self.scope = None;
+        self.pending = None;
+        self.resume = None;
+    }
+
+    /// 限制搜尋範圍為字元位置 start..end（None 為整個檔案），之後需重新 find_matches
+    pub fn set_scope(&mut self, scope: Option<(usize, usize)>) {
+        self.scope = scope;
+        self.version = None;
+    }
+
+    pub fn scope(&self) -> Option<(usize, usize)> {
+        self.scope
+    }
+
+    /// 字元位置 start..end 的結果是否在搜尋範圍內
+    pub fn in_scope(&self, start: usize, end: usize) -> bool {
+        self.scope
+            .is_none_or(|(scope_start, scope_end)| start >= scope_start && end <= scope_end)
     }
 
+    /// 尋找所有結果（一次找完）
     pub fn find_matches(&mut self, buffer: &RopeBuffer) {
+        self.start(buffer);
+        while self.step(buffer) {}
+    }
+
+    /// 開始重新尋找，之後以 [`Search::step`] 分段進行
+    pub fn start(&mut self, buffer: &RopeBuffer) {
         self.matches.clear();
+        self.current_match = None;
+        self.version = Some(buffer.version());
 
-        if self.query.is_empty() {
-            return;
+        self.lines = match self.scope {
+            Some((start, end)) => {
+                buffer.char_to_line(start)..(buffer.char_to_line(end) + 1).min(buffer.line_count())
+            }
+            None => 0..buffer.line_count(),
+        };
+        self.pending = (!self.query.is_empty()).then(|| self.lines.clone());
+        if self.pending.is_none() {
+            self.resume = None;
+        }
+    }
+
+    /// 繼續尋找下一段；返回 true 表示還沒找完。緩衝區在搜尋途中改變時從頭開始
+    pub fn step(&mut self, buffer: &RopeBuffer) -> bool {
+        if self.pending.is_some() && self.version != Some(buffer.version()) {
+            self.resume = self.resume.or(self.current());
+            self.start(buffer);
+        }
+        let Some(pending) = self.pending.clone() else {
+            return false;
+        };
+
+        let query_chars = self.query.chars().count();
+        let end = pending.end.min(pending.start + SEARCH_CHUNK_LINES);
+        let found = self.matches.len();
+        // 跨越多個 rope 區塊的行才複製到共用的暫存字串，其餘直接在區塊上比對
+        let mut scratch = String::new();
+        for line_idx in pending.start..end {
+            let Some(slice) = buffer.line(line_idx) else {
+                break;
+            };
+            let line = match slice.as_str() {
+                Some(text) => text,
+                None => {
+                    scratch.clear();
+                    slice.chunks().for_each(|chunk| scratch.push_str(chunk));
+                    &scratch
+                }
+            };
+            let line = line.trim_end_matches(['\n', '\r']);
+            let line_start = buffer.line_to_char(line_idx);
+
+            // 列以字元計算（與光標相同），從上一個結果接著數
+            let (mut col, mut counted) = (0, 0);
+            for (byte_idx, _) in line.match_indices(self.query.as_str()) {
+                col += line[counted..byte_idx].chars().count();
+                counted = byte_idx;
+                if self.in_scope(line_start + col, line_start + col + query_chars) {
+                    self.matches.push((line_idx, col));
+                }
+            }
         }
 
-        for line_idx in 0..buffer.line_count() {
-            let line_content = buffer.get_line_content(line_idx);
-            let line_content = line_content.trim_end_matches(['\n', '\r']);
-
-            let mut start = 0;
-            while let Some(pos) = line_content[start..].find(&self.query) {
-                let actual_pos = start + pos;
-                self.matches.push((line_idx, actual_pos));
-                // 使用查詢字符串的字節長度來避免 UTF-8 字符邊界錯誤
-                // 這樣可以正確處理中文等多字節字符
-                start = actual_pos + self.query.len();
+        // 重新尋找前所在的結果：找到原位置或之後的第一個結果時恢復
+        if let Some(position) = self.resume {
+            if let Some(offset) = self.matches[found..].iter().position(|&m| m >= position) {
+                self.current_match = Some(found + offset);
+                self.resume = None;
             }
         }
+
+        self.pending = (end < pending.end).then_some(end..pending.end);
+        if self.pending.is_none() {
+            self.resume = None;
+        }
+        self.pending.is_some()
+    }
+
+    /// 是否還在尋找中
+    pub fn is_searching(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    /// 尋找進度（百分比），找完時為 None
+    pub fn progress(&self) -> Option<usize> {
+        let pending = self.pending.as_ref()?;
+        let total = self.lines.len().max(1);
+        Some((pending.start - self.lines.start) * 100 / total)
+    }
+
+    fn current(&self) -> Option<(usize, usize)> {
+        self.current_match.map(|index| self.matches[index])
+    }
+
+    /// 緩衝區改變後重新開始尋找並先找一段；目前的結果改為原位置或之後的第一個結果
+    pub fn refresh(&mut self, buffer: &RopeBuffer) {
+        if self.version == Some(buffer.version()) {
+            return;
+        }
+        self.resume = self.resume.or(self.current());
+        self.start(buffer);
+        self.step(buffer);
     }
 
+    /// 下一個結果；還沒跳到任何結果時為第一個，最後一個之後回到第一個
     pub fn next_match(&mut self) -> Option<(usize, usize)> {
         if self.matches.is_empty() {
             return None;
         }
 
-        self.current_match = (self.current_match + 1) % self.matches.len();
-        Some(self.matches[self.current_match])
+        let index = self
+            .current_match
+            .map_or(0, |index| (index + 1) % self.matches.len());
+        self.current_match = Some(index);
+        Some(self.matches[index])
     }
 
+    /// 上一個結果；還沒跳到任何結果時為最後一個，第一個之前回到最後一個
     pub fn prev_match(&mut self) -> Option<(usize, usize)> {
         if self.matches.is_empty() {
             return None;
         }
 
-        if self.current_match == 0 {
-            self.current_match = self.matches.len() - 1;
-        } else {
-            self.current_match -= 1;
+        let index = match self.current_match {
+            Some(index) if index > 0 => index - 1,
+            _ => self.matches.len() - 1,
+        };
+        self.current_match = Some(index);
+        Some(self.matches[index])
+    }
+
+    /// 所有結果 (行, 列)；列為字元索引
+    pub fn matches(&self) -> &[(usize, usize)] {
+        &self.matches
+    }
+
+    /// 把第 `index` 個結果設為目前的結果（例如從結果列表跳過去）
+    pub fn select(&mut self, index: usize) {
+        if index < self.matches.len() {
+            self.current_match = Some(index);
         }
+    }
 
-        Some(self.matches[self.current_match])
+    pub fn query(&self) -> &str {
+        &self.query
     }
 
     pub fn match_count(&self) -> usize {
         self.matches.len()
     }
 
-    pub fn current_index(&self) -> usize {
+    /// 目前所在結果的索引（從 0 開始）
+    pub fn current_index(&self) -> Option<usize> {
         self.current_match
     }
+
+    /// 「第 i 個，共 n 個」的顯示文字；還沒跳到任何結果時只顯示總數
+    pub fn counter(&self) -> String {
+        let mut counter = match self.current_match {
+            Some(index) => format!("Match {}/{}", index + 1, self.matches.len()),
+            None => format!("{} matches", self.matches.len()),
+        };
+        if self.scope.is_some() {
+            counter.push_str(" in selection");
+        }
+        if let Some(progress) = self.progress() {
+            counter.push_str(&format!(" (searching {}%)", progress));
+        }
+        counter
+    }
 }
 
 impl Default for Search {
@@ -85,3 +243,125 @@ impl Default for Search {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn buffer_with(text: &str) -> RopeBuffer {
+        let mut buffer = RopeBuffer::new();
+        buffer.insert(0, text);
+        buffer
+    }
+
+    #[test]
+    fn test_match_index_starts_at_first_match() {
+        let buffer = buffer_with("a x\nx b x\n");
+        let mut search = Search::new();
+        search.set_query("x".to_string());
+        search.find_matches(&buffer);
+        assert_eq!(search.counter(), "3 matches");
+
+        assert_eq!(search.next_match(), Some((0, 2)));
+        assert_eq!(search.counter(), "Match 1/3");
+        search.next_match();
+        assert_eq!(search.next_match(), Some((1, 4)));
+        assert_eq!(search.counter(), "Match 3/3");
+        assert_eq!(search.next_match(), Some((0, 2)));
+        assert_eq!(search.prev_match(), Some((1, 4)));
+        assert_eq!(search.current_index(), Some(2));
+
+        // 從頭往回找是最後一個
+        search.find_matches(&buffer);
+        assert_eq!(search.prev_match(), Some((1, 4)));
+    }
+
+    #[test]
+    fn test_scope_limits_matches_to_selection() {
+        let buffer = buffer_with("port=1\n[db]\nport=2\nhost=port\n");
+        let mut search = Search::new();
+        search.set_query("port".to_string());
+        // 第三行開頭到第四行 "host=po" 為止：最後一個 port 只有一部分在範圍內
+        let start = buffer.line_to_char(2);
+        search.set_scope(Some((start, buffer.line_to_char(3) + 7)));
+        search.find_matches(&buffer);
+        assert_eq!(search.match_count(), 1);
+        assert_eq!(search.next_match(), Some((2, 0)));
+        assert_eq!(search.counter(), "Match 1/1 in selection");
+
+        // 新的搜尋回到整個檔案
+        search.set_query("port".to_string());
+        search.find_matches(&buffer);
+        assert_eq!(search.match_count(), 3);
+    }
+
+    #[test]
+    fn test_search_runs_in_chunks() {
+        let text: String = (0..50_000)
+            .map(|n| if n % 1000 == 0 { "hit\n" } else { "miss\n" })
+            .collect();
+        let buffer = buffer_with(&text);
+        let mut search = Search::new();
+        search.set_query("hit".to_string());
+        search.start(&buffer);
+        assert!(search.step(&buffer));
+        assert_eq!(search.match_count(), 20);
+        assert_eq!(search.progress(), Some(39));
+        assert_eq!(search.counter(), "20 matches (searching 39%)");
+        // 找到的結果可以先跳過去
+        assert_eq!(search.next_match(), Some((0, 0)));
+
+        while search.step(&buffer) {}
+        assert!(!search.is_searching());
+        assert_eq!(search.counter(), "Match 1/50");
+    }
+
+    #[test]
+    fn test_columns_are_char_indices() {
+        let buffer = buffer_with("中文 key 與 key\r\n");
+        let mut search = Search::new();
+        search.set_query("key".to_string());
+        search.find_matches(&buffer);
+        assert_eq!(search.matches(), &[(0, 3), (0, 9)]);
+    }
+
+    #[test]
+    fn test_multibyte_matches_land_on_the_query() {
+        let text = "第一行：設定值 = 1\n🙂 emoji 設定值\n設定值設定值\n";
+        let buffer = buffer_with(text);
+        let mut search = Search::new();
+        search.set_query("設定值".to_string());
+        search.find_matches(&buffer);
+        assert_eq!(search.matches(), &[(0, 4), (1, 8), (2, 0), (2, 3)]);
+
+        // 每個結果的字元位置上確實是搜尋文字
+        let chars: Vec<char> = buffer.text().chars().collect();
+        for &(row, col) in search.matches() {
+            let start = buffer.line_to_char(row) + col;
+            let found: String = chars[start..start + 3].iter().collect();
+            assert_eq!(found, "設定值");
+        }
+
+        // 範圍以字元計算：只包含第二行
+        let start = buffer.line_to_char(1);
+        search.set_scope(Some((start, buffer.line_to_char(2))));
+        search.find_matches(&buffer);
+        assert_eq!(search.matches(), &[(1, 8)]);
+    }
+
+    #[test]
+    fn test_refresh_keeps_position_after_edit() {
+        let mut buffer = buffer_with("x x x\n");
+        let mut search = Search::new();
+        search.set_query("x".to_string());
+        search.find_matches(&buffer);
+        search.next_match();
+        search.next_match(); // (0, 2)
+
+        // 刪除第一個 x：原位置上的結果變成第 1 個
+        buffer.delete_range(0, 2);
+        search.refresh(&buffer);
+        assert_eq!(search.counter(), "Match 2/2");
+        assert_eq!(search.next_match(), Some((0, 0)));
+    }
+}
@@ -0,0 +1,195 @@
+// 帶樣式的文字
+//
+// 語法高亮輸出結構化的 StyledLine（文字片段 + 樣式），而不是含 ANSI 色碼的字串；
+// View 再把語法樣式與搜尋結果等行內範圍的樣式合成後輸出
+
+use anyhow::{bail, Result};
+use crossterm::style::{self, Attribute, Color};
+use crossterm::Command as _;
+
+/// 文字的樣式，未設定的欄位沿用底下的樣式
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SpanStyle {
+    pub foreground: Option<Color>,
+    pub background: Option<Color>,
+    pub underline: bool,
+    pub reverse: bool, // 反白（選擇範圍）
+}
+
+impl SpanStyle {
+    /// 將 `overlay` 疊在此樣式上：overlay 有設定的欄位優先
+    pub fn patch(self, overlay: SpanStyle) -> SpanStyle {
+        SpanStyle {
+            foreground: overlay.foreground.or(self.foreground),
+            background: overlay.background.or(self.background),
+            underline: self.underline || overlay.underline,
+            reverse: self.reverse || overlay.reverse,
+        }
+    }
+
+    /// 從 `from` 切換到此樣式所需的控制碼（只輸出有改變的部分）
+    pub(crate) fn transition_from(&self, from: &SpanStyle, output: &mut String) {
+        if self.foreground != from.foreground {
+            let color = self.foreground.unwrap_or(Color::Reset);
+            let _ = style::SetForegroundColor(color).write_ansi(output);
+        }
+        if self.background != from.background {
+            let color = self.background.unwrap_or(Color::Reset);
+            let _ = style::SetBackgroundColor(color).write_ansi(output);
+        }
+        if self.underline != from.underline {
+            let attribute = if self.underline {
+                Attribute::Underlined
+            } else {
+                Attribute::NoUnderline
+            };
+            let _ = style::SetAttribute(attribute).write_ansi(output);
+        }
+        if self.reverse != from.reverse {
+            let attribute = if self.reverse {
+                Attribute::Reverse
+            } else {
+                Attribute::NoReverse
+            };
+            let _ = style::SetAttribute(attribute).write_ansi(output);
+        }
+    }
+}
+
+/// 解析顏色：顏色名稱（例如 `dark_grey`）、`#rrggbb` 或 256 色編號（0-255）
+#[allow(dead_code)]
+pub fn parse_color(text: &str) -> Result<Color> {
+    let text = text.trim();
+    if let Some(hex) = text.strip_prefix('#') {
+        let value = u32::from_str_radix(hex, 16).ok().filter(|_| hex.len() == 6);
+        let Some(value) = value else {
+            bail!("Invalid color: {}", text);
+        };
+        return Ok(Color::Rgb {
+            r: (value >> 16) as u8,
+            g: (value >> 8) as u8,
+            b: value as u8,
+        });
+    }
+    if let Ok(index) = text.parse::<u8>() {
+        return Ok(Color::AnsiValue(index));
+    }
+    Color::try_from(text.replace('-', "_").as_str())
+        .map_err(|_| anyhow::anyhow!("Invalid color: {} (use a name, #rrggbb or 0-255)", text))
+}
+
+/// 套用同一樣式的一段文字
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Span {
+    pub text: String,
+    pub style: SpanStyle,
+}
+
+/// 一行帶樣式的文字（不含換行符）
+#[allow(dead_code)]
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct StyledLine {
+    pub spans: Vec<Span>,
+}
+
+#[allow(dead_code)]
+impl StyledLine {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 沒有樣式的一行
+    pub fn plain(text: impl AsRef<str>) -> Self {
+        let mut line = Self::new();
+        line.push(text, SpanStyle::default());
+        line
+    }
+
+    /// 加入一段文字；與前一段樣式相同時合併
+    pub fn push(&mut self, text: impl AsRef<str>, style: SpanStyle) {
+        let text = text.as_ref();
+        if text.is_empty() {
+            return;
+        }
+        match self.spans.last_mut() {
+            Some(last) if last.style == style => last.text.push_str(text),
+            _ => self.spans.push(Span {
+                text: text.to_string(),
+                style,
+            }),
+        }
+    }
+
+    /// 去掉樣式後的文字
+    pub fn text(&self) -> String {
+        self.spans.iter().map(|span| span.text.as_str()).collect()
+    }
+
+    /// 逐字元列出文字與樣式
+    pub fn chars(&self) -> impl Iterator<Item = (char, SpanStyle)> + '_ {
+        self.spans
+            .iter()
+            .flat_map(|span| span.text.chars().map(move |ch| (ch, span.style)))
+    }
+
+    /// 轉換為含 ANSI 控制碼的字串（例如直接輸出到終端）
+    pub fn to_ansi(&self) -> String {
+        let mut output = String::new();
+        let mut current = SpanStyle::default();
+        for span in &self.spans {
+            span.style.transition_from(&current, &mut output);
+            output.push_str(&span.text);
+            current = span.style;
+        }
+        SpanStyle::default().transition_from(&current, &mut output);
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_push_merges_and_patch_overrides() {
+        let red = SpanStyle {
+            foreground: Some(Color::Red),
+            ..SpanStyle::default()
+        };
+        let mut line = StyledLine::new();
+        line.push("fn", red);
+        line.push(" ", red);
+        line.push("", SpanStyle::default());
+        line.push("main", SpanStyle::default());
+        assert_eq!(line.spans.len(), 2);
+        assert_eq!(line.text(), "fn main");
+        assert_eq!(line.chars().nth(1), Some(('n', red)));
+
+        let search = SpanStyle {
+            background: Some(Color::Yellow),
+            underline: true,
+            ..SpanStyle::default()
+        };
+        let patched = red.patch(search);
+        assert_eq!(patched.foreground, Some(Color::Red));
+        assert_eq!(patched.background, Some(Color::Yellow));
+        assert!(patched.underline);
+    }
+
+    #[test]
+    fn test_parse_color() {
+        assert_eq!(parse_color("dark-grey").unwrap(), Color::DarkGrey);
+        assert_eq!(parse_color("236").unwrap(), Color::AnsiValue(236));
+        assert_eq!(
+            parse_color("#1e2a3B").unwrap(),
+            Color::Rgb {
+                r: 0x1e,
+                g: 0x2a,
+                b: 0x3b
+            }
+        );
+        assert!(parse_color("#123").is_err());
+        assert!(parse_color("mauve").is_err());
+    }
+}
@@ -0,0 +1,297 @@
+// 插入範本
+//
+// 在光標處插入目前的日期時間（strftime 風格的格式）、檔案路徑、使用者與主機名稱，
+// 方便在更新記錄或維運手冊中留下記錄。時間以本地時區表示，不需要額外的日期時間套件
+
+use anyhow::{bail, Result};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// `:insert date` 的預設格式
+pub const DEFAULT_DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// `:insert time` 與 Alt+T 的預設格式
+pub const DEFAULT_TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+const WEEKDAYS: [&str; 7] = [
+    "Sunday",
+    "Monday",
+    "Tuesday",
+    "Wednesday",
+    "Thursday",
+    "Friday",
+    "Saturday",
+];
+
+const MONTHS: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+
+/// 本地時間
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LocalTime {
+    pub year: i64,
+    pub month: u32,  // 1-12
+    pub day: u32,    // 1-31
+    pub hour: u32,   // 0-23
+    pub minute: u32, // 0-59
+    pub second: u32, // 0-60
+    pub offset: i64, // 與 UTC 的差距（秒）
+    pub unix: i64,   // Unix 時間（秒）
+}
+
+impl LocalTime {
+    /// 從 Unix 時間與時區差距建立
+    pub fn from_unix(unix: i64, offset: i64) -> Self {
+        let local = unix + offset;
+        let (year, month, day) = civil_from_days(local.div_euclid(86_400));
+        let seconds = local.rem_euclid(86_400) as u32;
+        Self {
+            year,
+            month,
+            day,
+            hour: seconds / 3600,
+            minute: seconds / 60 % 60,
+            second: seconds % 60,
+            offset,
+            unix,
+        }
+    }
+
+    /// 目前的本地時間
+    pub fn now() -> Self {
+        let unix = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs() as i64);
+        Self::from_unix(unix, local_offset(unix))
+    }
+
+    /// 星期（0 為星期日）
+    fn weekday(&self) -> usize {
+        // 1970-01-01 是星期四
+        (days_from_civil(self.year, self.month, self.day) + 4).rem_euclid(7) as usize
+    }
+
+    /// 一年中的第幾天（1 開始）
+    fn day_of_year(&self) -> i64 {
+        days_from_civil(self.year, self.month, self.day) - days_from_civil(self.year, 1, 1) + 1
+    }
+
+    /// 依 strftime 風格的格式輸出
+    ///
+    /// 支援 `%Y %y %m %d %e %H %I %M %S %p %j %a %A %b %B %z %s %F %T %R %%`；其他 `%` 組合原樣保留
+    pub fn format(&self, format: &str) -> String {
+        let mut out = String::new();
+        let mut chars = format.chars();
+        while let Some(ch) = chars.next() {
+            if ch != '%' {
+                out.push(ch);
+                continue;
+            }
+            let Some(spec) = chars.next() else {
+                out.push('%');
+                break;
+            };
+            let text = match spec {
+                'Y' => self.year.to_string(),
+                'y' => format!("{:02}", self.year.rem_euclid(100)),
+                'm' => format!("{:02}", self.month),
+                'd' => format!("{:02}", self.day),
+                'e' => format!("{:>2}", self.day),
+                'H' => format!("{:02}", self.hour),
+                'I' => format!("{:02}", (self.hour + 11) % 12 + 1),
+                'M' => format!("{:02}", self.minute),
+                'S' => format!("{:02}", self.second),
+                'p' => if self.hour < 12 { "AM" } else { "PM" }.to_string(),
+                'j' => format!("{:03}", self.day_of_year()),
+                'a' => WEEKDAYS[self.weekday()][..3].to_string(),
+                'A' => WEEKDAYS[self.weekday()].to_string(),
+                'b' => MONTHS[self.month as usize - 1][..3].to_string(),
+                'B' => MONTHS[self.month as usize - 1].to_string(),
+                'z' => {
+                    let sign = if self.offset < 0 { '-' } else { '+' };
+                    let minutes = self.offset.abs() / 60;
+                    format!("{}{:02}{:02}", sign, minutes / 60, minutes % 60)
+                }
+                's' => self.unix.to_string(),
+                'F' => self.format("%Y-%m-%d"),
+                'T' => self.format("%H:%M:%S"),
+                'R' => self.format("%H:%M"),
+                '%' => "%".to_string(),
+                other => format!("%{}", other),
+            };
+            out.push_str(&text);
+        }
+        out
+    }
+}
+
+/// 公曆日期對應的 1970-01-01 起的天數
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month = month as i64;
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day as i64 - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// 1970-01-01 起的天數對應的公曆日期 (年, 月, 日)
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// 本地時區在 `unix` 時的 UTC 差距（秒）
+#[cfg(unix)]
+fn local_offset(unix: i64) -> i64 {
+    let time = unix as libc::time_t;
+    let mut tm = std::mem::MaybeUninit::<libc::tm>::uninit();
+    if unsafe { libc::localtime_r(&time, tm.as_mut_ptr()) }.is_null() {
+        return 0;
+    }
+    // 32 位元平台上 `c_long` 是 i32
+    let offset: libc::c_long = unsafe { tm.assume_init() }.tm_gmtoff;
+    offset as i64
+}
+
+/// 本地時區的 UTC 差距（秒）：本地時鐘與 UTC 時間的差，取整到分鐘
+#[cfg(windows)]
+fn local_offset(unix: i64) -> i64 {
+    use winapi::um::minwinbase::SYSTEMTIME;
+    use winapi::um::sysinfoapi::GetLocalTime;
+
+    let mut st: SYSTEMTIME = unsafe { std::mem::zeroed() };
+    unsafe { GetLocalTime(&mut st) };
+    let local = days_from_civil(st.wYear as i64, st.wMonth as u32, st.wDay as u32) * 86_400
+        + st.wHour as i64 * 3600
+        + st.wMinute as i64 * 60
+        + st.wSecond as i64;
+    ((local - unix) as f64 / 60.0).round() as i64 * 60
+}
+
+#[cfg(not(any(unix, windows)))]
+fn local_offset(_unix: i64) -> i64 {
+    0
+}
+
+/// 目前的使用者名稱；環境變數沒有設定時（例如在容器或 cron 中）從帳號資料庫查詢
+pub fn user_name() -> Option<String> {
+    let name = ["USER", "USERNAME", "LOGNAME"]
+        .iter()
+        .find_map(|name| std::env::var(name).ok().filter(|v| !v.is_empty()));
+    #[cfg(unix)]
+    let name = name.or_else(|| {
+        let passwd = unsafe { libc::getpwuid(libc::geteuid()) };
+        if passwd.is_null() {
+            return None;
+        }
+        let pw_name = unsafe { std::ffi::CStr::from_ptr((*passwd).pw_name) };
+        Some(pw_name.to_string_lossy().into_owned()).filter(|n| !n.is_empty())
+    });
+    name
+}
+
+/// 主機名稱
+pub fn host_name() -> Option<String> {
+    #[cfg(unix)]
+    {
+        let mut name = [0u8; 256];
+        if unsafe { libc::gethostname(name.as_mut_ptr().cast(), name.len()) } == 0 {
+            let len = name.iter().position(|&b| b == 0).unwrap_or(name.len());
+            let host = String::from_utf8_lossy(&name[..len]).into_owned();
+            if !host.is_empty() {
+                return Some(host);
+            }
+        }
+    }
+    ["HOSTNAME", "COMPUTERNAME"]
+        .iter()
+        .find_map(|name| std::env::var(name).ok().filter(|v| !v.is_empty()))
+}
+
+/// `:insert` 可以插入的內容
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Template {
+    Date,     // 日期（`dateformat`）
+    Time,     // 日期與時間（`timeformat`）
+    Path,     // 檔案的完整路徑
+    FileName, // 檔名
+    User,     // 使用者名稱
+    Host,     // 主機名稱
+    UserHost, // user@host
+}
+
+impl std::str::FromStr for Template {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s {
+            "date" => Self::Date,
+            "time" | "datetime" | "timestamp" => Self::Time,
+            "path" => Self::Path,
+            "file" | "filename" | "name" => Self::FileName,
+            "user" => Self::User,
+            "host" | "hostname" => Self::Host,
+            "user@host" | "login" => Self::UserHost,
+            _ => bail!(
+                "Unknown template: {} (expected date, time, path, file, user, host or user@host)",
+                s
+            ),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_civil_date_round_trip() {
+        for days in [-719_468, -1, 0, 11_016, 19_782, 20_000, 2_932_896] {
+            let (year, month, day) = civil_from_days(days);
+            assert_eq!(days_from_civil(year, month, day), days);
+        }
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(19_782), (2024, 2, 29));
+    }
+
+    #[test]
+    fn test_format_time() {
+        // 2024-02-29 13:05:09 UTC，時區 +08:00
+        let time = LocalTime::from_unix(1_709_211_909, 8 * 3600);
+        assert_eq!(time.format("%F %T"), "2024-02-29 21:05:09");
+        assert_eq!(
+            time.format("%a %b %e %I:%M %p %z"),
+            "Thu Feb 29 09:05 PM +0800"
+        );
+        assert_eq!(
+            time.format("%A %B %j %y %% %q"),
+            "Thursday February 060 24 % %q"
+        );
+        assert_eq!(time.format("%s"), "1709211909");
+
+        let time = LocalTime::from_unix(0, -(5 * 3600 + 30 * 60));
+        assert_eq!(time.format("%Y-%m-%d %R %z"), "1969-12-31 18:30 -0530");
+    }
+}
@@ -1,11 +1,214 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use crossterm::{
     cursor,
-    event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
-    execute,
+    event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, MouseEvent},
+    execute, queue, style,
     terminal::{self, ClearType},
 };
-use std::io::{self, Write};
+use once_cell::sync::OnceCell;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::sync::atomic::{AtomicBool, AtomicI32, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// 畫面輸出改寫到控制終端時使用的檔案（見 [`Terminal::redirect_output_to_tty`]）
+static TTY_OUTPUT: OnceCell<Mutex<BufWriter<File>>> = OnceCell::new();
+
+/// 畫面是否被編輯器以外的內容覆寫（清除畫面、對話框），下一次渲染需完整重繪
+static SCREEN_INVALIDATED: AtomicBool = AtomicBool::new(false);
+
+/// 是否曾改變游標樣式（結束時需還原為使用者預設）
+static CURSOR_STYLE_CHANGED: AtomicBool = AtomicBool::new(false);
+
+/// 是否擷取滑鼠事件（`:set mouse`）；離開與重新進入 raw mode 時跟著關閉與恢復
+static MOUSE_CAPTURE: AtomicBool = AtomicBool::new(false);
+
+/// 最近一個滑鼠事件，以 F22 標記按鍵通知編輯器後由 [`Terminal::take_mouse_event`] 取得
+static MOUSE_EVENT: Mutex<Option<MouseEvent>> = Mutex::new(None);
+
+/// 收到 SIGTSTP（例如 `kill -TSTP`）後等待主迴圈還原終端再暫停
+#[cfg(unix)]
+static SUSPEND_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// 收到的終止訊號，0 表示沒有
+#[cfg(unix)]
+static TERMINATE_SIGNAL: AtomicI32 = AtomicI32::new(0);
+
+/// 通知終止訊號的管道（讀取端, 寫入端），-1 表示還沒建立；
+/// 訊號處理函式只能做少數安全的操作，寫入管道讓等待的執行緒不需輪詢就能醒來
+#[cfg(unix)]
+static SIGNAL_PIPE: [AtomicI32; 2] = [AtomicI32::new(-1), AtomicI32::new(-1)];
+
+/// 其他執行緒要求等待按鍵的主迴圈醒來（見 [`Terminal::wake`]）
+static WAKE_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// 喚醒等待按鍵的主迴圈的管道（讀取端, 寫入端），-1 表示還沒建立；
+/// 主迴圈同時等待終端輸入與這個管道（見 [`Terminal::read_key_or_wake`]）
+#[cfg(unix)]
+static WAKE_PIPE: [AtomicI32; 2] = [AtomicI32::new(-1), AtomicI32::new(-1)];
+
+/// 收到 SIGWINCH 後還沒回報給主迴圈的 Resize
+#[cfg(unix)]
+static RESIZE_PENDING: AtomicBool = AtomicBool::new(false);
+
+/// ESC 之後等待後續按鍵的預設時間（毫秒）
+const DEFAULT_ESCAPE_TIMEOUT_MS: u64 = 50;
+
+/// ESC 之後等待後續按鍵的時間（毫秒，`:set esctimeout`），0 表示不合併
+static ESCAPE_TIMEOUT_MS: AtomicU64 = AtomicU64::new(DEFAULT_ESCAPE_TIMEOUT_MS);
+
+/// 等待 ESC 後續按鍵時讀到、但不能與 ESC 合併的按鍵，留給下一次讀取
+static PENDING_KEY: Mutex<Option<KeyEvent>> = Mutex::new(None);
+
+/// 設定訊號處理函式；系統呼叫被打斷時自動重新開始。返回原本的設定
+#[cfg(unix)]
+fn set_signal_handler(signal: libc::c_int, handler: libc::sighandler_t) -> libc::sigaction {
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        let mut previous: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = handler;
+        action.sa_flags = libc::SA_RESTART;
+        libc::sigemptyset(&mut action.sa_mask);
+        libc::sigaction(signal, &action, &mut previous);
+        previous
+    }
+}
+
+/// 寫入喚醒管道；只做訊號處理函式中也安全的操作
+#[cfg(unix)]
+fn write_wake_pipe() {
+    let fd = WAKE_PIPE[1].load(Ordering::Relaxed);
+    if fd >= 0 {
+        unsafe { libc::write(fd, [0u8].as_ptr().cast(), 1) };
+    }
+}
+
+/// 等待終端輸入或喚醒管道可讀（被訊號打斷時也返回），並清空管道；無法等待時返回 false
+#[cfg(unix)]
+fn wait_for_input() -> bool {
+    use std::io::IsTerminal;
+    use std::os::unix::io::AsRawFd;
+
+    static TTY_INPUT: OnceCell<Option<File>> = OnceCell::new();
+
+    let wake = WAKE_PIPE[0].load(Ordering::Relaxed);
+    if wake < 0 {
+        return false;
+    }
+    // 與 crossterm 相同：stdin 不是終端時（例如管道輸入）改用 /dev/tty
+    let input = if io::stdin().is_terminal() {
+        libc::STDIN_FILENO
+    } else {
+        match TTY_INPUT.get_or_init(|| File::open("/dev/tty").ok()) {
+            Some(tty) => tty.as_raw_fd(),
+            None => return false,
+        }
+    };
+    let mut fds = [input, wake].map(|fd| libc::pollfd {
+        fd,
+        events: libc::POLLIN,
+        revents: 0,
+    });
+    if unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, -1) } < 0
+        && io::Error::last_os_error().kind() != io::ErrorKind::Interrupted
+    {
+        return false;
+    }
+    let mut buffer = [0u8; 64];
+    while unsafe { libc::read(wake, buffer.as_mut_ptr().cast(), buffer.len()) } > 0 {}
+    true
+}
+
+/// ESC 之後緊接著的按鍵轉成 Alt 組合；ESC、已帶 Alt 的按鍵與內部標記（貼上、縮放、滑鼠）不合併
+fn with_alt(key_event: KeyEvent) -> Option<KeyEvent> {
+    if matches!(key_event.code, KeyCode::Esc | KeyCode::F(20..=22))
+        || key_event.modifiers.contains(KeyModifiers::ALT)
+    {
+        return None;
+    }
+    Some(KeyEvent {
+        modifiers: key_event.modifiers | KeyModifiers::ALT,
+        ..key_event
+    })
+}
+
+/// 游標形狀
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CursorShape {
+    Block,
+    Bar,
+    Underline,
+}
+
+/// 游標樣式：形狀與是否閃爍
+///
+/// 命令列寫法為 `block`、`bar`、`underline`，加上 `-blink` 後綴表示閃爍（例如 `bar-blink`）
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CursorStyle {
+    pub shape: CursorShape,
+    pub blink: bool,
+}
+
+impl CursorStyle {
+    #[allow(dead_code)]
+    fn command(self) -> cursor::SetCursorStyle {
+        use cursor::SetCursorStyle::*;
+        match (self.shape, self.blink) {
+            (CursorShape::Block, true) => BlinkingBlock,
+            (CursorShape::Block, false) => SteadyBlock,
+            (CursorShape::Bar, true) => BlinkingBar,
+            (CursorShape::Bar, false) => SteadyBar,
+            (CursorShape::Underline, true) => BlinkingUnderScore,
+            (CursorShape::Underline, false) => SteadyUnderScore,
+        }
+    }
+}
+
+impl std::str::FromStr for CursorStyle {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let lower = s.to_lowercase();
+        let (name, blink) = match lower.strip_suffix("-blink") {
+            Some(name) => (name, true),
+            None => (lower.as_str(), false),
+        };
+        let shape = match name {
+            "block" => CursorShape::Block,
+            "bar" | "beam" => CursorShape::Bar,
+            "underline" | "underscore" => CursorShape::Underline,
+            _ => anyhow::bail!(
+                "Unknown cursor style: {} (expected block, bar or underline, optionally with -blink)",
+                s
+            ),
+        };
+        Ok(Self { shape, blink })
+    }
+}
+
+/// 編輯器畫面的輸出目標
+///
+/// 平時寫到 stdout；stdout 被用於輸出內容（--stdout）時改寫到控制終端
+pub struct Output;
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match TTY_OUTPUT.get() {
+            Some(tty) => tty.lock().unwrap_or_else(|e| e.into_inner()).write(buf),
+            None => io::stdout().write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match TTY_OUTPUT.get() {
+            Some(tty) => tty.lock().unwrap_or_else(|e| e.into_inner()).flush(),
+            None => io::stdout().flush(),
+        }
+    }
+}
 
 pub struct Terminal {
     size: (u16, u16),
@@ -17,23 +220,402 @@ impl Terminal {
         Ok(Self { size })
     }
 
+    /// 取得畫面輸出
+    pub fn output() -> Output {
+        Output
+    }
+
+    /// 將畫面輸出改寫到控制終端，讓 stdout 保留給最終的緩衝區內容
+    #[allow(dead_code)]
+    pub fn redirect_output_to_tty() -> Result<()> {
+        #[cfg(windows)]
+        const TTY_PATH: &str = "CONOUT$";
+        #[cfg(not(windows))]
+        const TTY_PATH: &str = "/dev/tty";
+
+        let tty = OpenOptions::new()
+            .write(true)
+            .open(TTY_PATH)
+            .with_context(|| format!("Failed to open {}", TTY_PATH))?;
+        let _ = TTY_OUTPUT.set(Mutex::new(BufWriter::new(tty)));
+        Ok(())
+    }
+
     pub fn enter_raw_mode() -> Result<()> {
         terminal::enable_raw_mode()?;
-        execute!(io::stdout(), terminal::EnterAlternateScreen)?;
+        execute!(Self::output(), terminal::EnterAlternateScreen)?;
+        if MOUSE_CAPTURE.load(Ordering::Relaxed) {
+            execute!(Self::output(), event::EnableMouseCapture)?;
+        }
         Ok(())
     }
 
     pub fn exit_raw_mode() -> Result<()> {
-        execute!(io::stdout(), terminal::LeaveAlternateScreen)?;
+        if MOUSE_CAPTURE.load(Ordering::Relaxed) {
+            execute!(Self::output(), event::DisableMouseCapture)?;
+        }
+        execute!(Self::output(), terminal::LeaveAlternateScreen)?;
         terminal::disable_raw_mode()?;
         Ok(())
     }
 
+    /// 接管工作控制與終止訊號（Unix）
+    ///
+    /// SIGTSTP 不能直接停下程序，否則終端停在 raw mode 與替代畫面、shell 變得無法使用；
+    /// 改為記下請求，由主迴圈還原終端後再暫停（見 [`Terminal::suspend`]）。
+    /// SIGTERM 與 SIGHUP 同樣只記下訊號，由主迴圈保留未存檔的修改、還原終端後結束；
+    /// SIGINT 不攔截，raw mode 下 Ctrl+C 本來就是按鍵（執行子程序時見 [`Terminal::shield_interrupts`]）。
+    /// 處理函式都送出 SIGWINCH，讓等待按鍵的主迴圈收到 Resize 事件而醒來；
+    /// 被 SIGSTOP 停下後繼續執行時（SIGCONT）畫面可能已被 shell 覆寫，標記為需要完整重繪
+    #[allow(dead_code)]
+    pub fn install_signal_handlers() {
+        #[cfg(unix)]
+        {
+            extern "C" fn on_stop(_: libc::c_int) {
+                SUSPEND_REQUESTED.store(true, Ordering::Relaxed);
+                unsafe { libc::raise(libc::SIGWINCH) };
+            }
+            extern "C" fn on_continue(_: libc::c_int) {
+                SCREEN_INVALIDATED.store(true, Ordering::Relaxed);
+                unsafe { libc::raise(libc::SIGWINCH) };
+            }
+            extern "C" fn on_terminate(signal: libc::c_int) {
+                TERMINATE_SIGNAL.store(signal, Ordering::Relaxed);
+                let fd = SIGNAL_PIPE[1].load(Ordering::Relaxed);
+                if fd >= 0 {
+                    unsafe { libc::write(fd, [0u8].as_ptr().cast(), 1) };
+                }
+                unsafe { libc::raise(libc::SIGWINCH) };
+            }
+            if SIGNAL_PIPE[0].load(Ordering::Relaxed) < 0 {
+                let mut fds = [-1; 2];
+                if unsafe { libc::pipe(fds.as_mut_ptr()) } == 0 {
+                    for (slot, fd) in SIGNAL_PIPE.iter().zip(fds) {
+                        // 不讓外部命令繼承
+                        unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) };
+                        slot.store(fd, Ordering::Relaxed);
+                    }
+                }
+            }
+            if WAKE_PIPE[0].load(Ordering::Relaxed) < 0 {
+                let mut fds = [-1; 2];
+                if unsafe { libc::pipe(fds.as_mut_ptr()) } == 0 {
+                    for (slot, fd) in WAKE_PIPE.iter().zip(fds) {
+                        // 不阻塞：管道滿了表示已有待處理的喚醒
+                        unsafe {
+                            libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC);
+                            libc::fcntl(fd, libc::F_SETFL, libc::O_NONBLOCK);
+                        }
+                        slot.store(fd, Ordering::Relaxed);
+                    }
+                    // 經由 signal-hook 與 crossterm 共用 SIGWINCH，在管道上等待時視窗大小改變也會醒來
+                    let _ = unsafe {
+                        signal_hook_registry::register(libc::SIGWINCH, || {
+                            RESIZE_PENDING.store(true, Ordering::Relaxed);
+                            write_wake_pipe();
+                        })
+                    };
+                }
+            }
+            for signal in [libc::SIGTERM, libc::SIGHUP] {
+                set_signal_handler(
+                    signal,
+                    on_terminate as extern "C" fn(libc::c_int) as libc::sighandler_t,
+                );
+            }
+            set_signal_handler(
+                libc::SIGTSTP,
+                on_stop as extern "C" fn(libc::c_int) as libc::sighandler_t,
+            );
+            set_signal_handler(
+                libc::SIGCONT,
+                on_continue as extern "C" fn(libc::c_int) as libc::sighandler_t,
+            );
+        }
+    }
+
+    /// 收到的終止訊號（SIGTERM、SIGHUP）
+    #[allow(dead_code)]
+    pub fn termination_signal() -> Option<i32> {
+        #[cfg(unix)]
+        return Some(TERMINATE_SIGNAL.load(Ordering::Relaxed)).filter(|&signal| signal != 0);
+        #[cfg(not(unix))]
+        None
+    }
+
+    /// 等待終止訊號並返回訊號編號（在另一個執行緒中呼叫）；無法等待時返回 None
+    #[allow(dead_code)]
+    pub fn wait_for_termination() -> Option<i32> {
+        #[cfg(unix)]
+        {
+            let fd = SIGNAL_PIPE[0].load(Ordering::Relaxed);
+            if fd < 0 {
+                return None;
+            }
+            let mut byte = 0u8;
+            loop {
+                let read = unsafe { libc::read(fd, (&mut byte as *mut u8).cast(), 1) };
+                if read == 1 {
+                    return Self::termination_signal();
+                }
+                if read == 0 || io::Error::last_os_error().kind() != io::ErrorKind::Interrupted {
+                    return None;
+                }
+            }
+        }
+        #[cfg(not(unix))]
+        None
+    }
+
+    /// 讓在 [`Terminal::read_key_or_wake`] 等待的主迴圈醒來（可在其他執行緒呼叫）
+    ///
+    /// Unix 寫入喚醒管道；Windows 在主控台輸入佇列放入一個焦點事件，讓 crossterm 的讀取返回
+    #[allow(dead_code)]
+    pub fn wake() {
+        WAKE_REQUESTED.store(true, Ordering::Relaxed);
+        #[cfg(unix)]
+        write_wake_pipe();
+        #[cfg(windows)]
+        unsafe {
+            use winapi::um::processenv::GetStdHandle;
+            use winapi::um::winbase::STD_INPUT_HANDLE;
+            use winapi::um::wincon::{WriteConsoleInputW, FOCUS_EVENT, INPUT_RECORD};
+
+            let mut record: INPUT_RECORD = std::mem::zeroed();
+            record.EventType = FOCUS_EVENT;
+            record.Event.FocusEvent_mut().bSetFocus = 1;
+            let mut written = 0;
+            WriteConsoleInputW(GetStdHandle(STD_INPUT_HANDLE), &record, 1, &mut written);
+        }
+    }
+
+    /// 在前景執行子程序期間不讓 Ctrl+C 結束編輯器；返回的值 drop 時還原 SIGINT 的設定
+    ///
+    /// 離開 raw mode 後 Ctrl+C 會對整個前景程序群組送出 SIGINT。以空的處理函式接住而不是忽略：
+    /// 忽略的設定會被子程序繼承，處理函式則在 exec 時恢復為預設動作，子程序仍然可以被中斷
+    #[allow(dead_code)]
+    pub fn shield_interrupts() -> InterruptShield {
+        #[cfg(unix)]
+        {
+            extern "C" fn on_interrupt(_: libc::c_int) {}
+            InterruptShield {
+                previous: set_signal_handler(
+                    libc::SIGINT,
+                    on_interrupt as extern "C" fn(libc::c_int) as libc::sighandler_t,
+                ),
+            }
+        }
+        #[cfg(not(unix))]
+        InterruptShield {}
+    }
+
+    /// 取得並重置暫停請求（收到 SIGTSTP）
+    #[allow(dead_code)]
+    pub fn take_suspend_request() -> bool {
+        #[cfg(unix)]
+        return SUSPEND_REQUESTED.swap(false, Ordering::Relaxed);
+        #[cfg(not(unix))]
+        false
+    }
+
+    /// 暫停到 shell：還原終端後停下程序，`fg` 繼續時重新進入 raw mode 與替代畫面
+    ///
+    /// 返回後畫面已清除，呼叫端需要完整重繪
+    #[allow(dead_code)]
+    pub fn suspend() -> Result<()> {
+        #[cfg(unix)]
+        {
+            Self::exit_raw_mode()?;
+            Self::reset_cursor_style()?;
+            // 以預設動作停下程序；不是由支援工作控制的 shell 啟動時（孤兒程序群組）訊號會被忽略
+            set_signal_handler(libc::SIGTSTP, libc::SIG_DFL);
+            unsafe { libc::raise(libc::SIGTSTP) };
+            Self::install_signal_handlers();
+            SUSPEND_REQUESTED.store(false, Ordering::Relaxed);
+            Self::enter_raw_mode()?;
+            Self::clear_screen()
+        }
+        #[cfg(not(unix))]
+        anyhow::bail!("Suspend is not supported on this platform")
+    }
+
+    /// 開啟或關閉滑鼠擷取；開啟時終端不再自行處理滑鼠選取文字
+    #[allow(dead_code)]
+    pub fn set_mouse_capture(enabled: bool) -> Result<()> {
+        if MOUSE_CAPTURE.swap(enabled, Ordering::Relaxed) != enabled
+            && terminal::is_raw_mode_enabled()?
+        {
+            if enabled {
+                execute!(Self::output(), event::EnableMouseCapture)?;
+            } else {
+                execute!(Self::output(), event::DisableMouseCapture)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// 設定 ESC 之後等待後續按鍵的時間；0 表示 ESC 一律單獨處理
+    #[allow(dead_code)]
+    pub fn set_escape_timeout(timeout: Duration) {
+        ESCAPE_TIMEOUT_MS.store(timeout.as_millis() as u64, Ordering::Relaxed);
+    }
+
+    /// ESC 之後等待後續按鍵的時間
+    #[allow(dead_code)]
+    pub fn escape_timeout() -> Duration {
+        Duration::from_millis(ESCAPE_TIMEOUT_MS.load(Ordering::Relaxed))
+    }
+
+    /// 取出 F22 標記對應的滑鼠事件
+    #[allow(dead_code)]
+    pub fn take_mouse_event() -> Option<MouseEvent> {
+        MOUSE_EVENT.lock().unwrap_or_else(|e| e.into_inner()).take()
+    }
+
+    /// 離開全螢幕時顯示提示並等待任意鍵（例如外部命令執行完畢後讓使用者看輸出）
+    pub fn pause(prompt: &str) -> Result<()> {
+        let mut output = Self::output();
+        write!(output, "{}", prompt)?;
+        output.flush()?;
+        terminal::enable_raw_mode()?;
+        let key = Self::read_key();
+        terminal::disable_raw_mode()?;
+        key.map(|_| ())
+    }
+
+    /// 確認終端仍處於 raw mode
+    ///
+    /// 子程序（格式化工具、執行命令、暫停後恢復）可能把終端改回一般模式，
+    /// 導致按鍵要等到 Enter 才送出、編輯器看似凍結。發現時重新進入 raw mode 並清除畫面，
+    /// 返回 true 表示呼叫端需要完整重繪
+    pub fn ensure_raw_mode() -> Result<bool> {
+        if !terminal::is_raw_mode_enabled()? || !Self::is_line_buffered() {
+            return Ok(false);
+        }
+
+        // crossterm 仍記錄為 raw mode，需先還原為原始設定再重新進入
+        terminal::disable_raw_mode()?;
+        terminal::enable_raw_mode()?;
+        execute!(
+            Self::output(),
+            terminal::EnterAlternateScreen,
+            terminal::Clear(ClearType::All)
+        )?;
+        if MOUSE_CAPTURE.load(Ordering::Relaxed) {
+            execute!(Self::output(), event::EnableMouseCapture)?;
+        }
+        Ok(true)
+    }
+
+    /// 終端輸入是否為一般（行緩衝、回顯）模式
+    #[cfg(unix)]
+    fn is_line_buffered() -> bool {
+        use std::io::IsTerminal;
+        use std::os::unix::io::AsRawFd;
+
+        // 與 crossterm 相同：stdin 不是終端時（例如管道輸入）改用 /dev/tty
+        let tty;
+        let fd = if io::stdin().is_terminal() {
+            libc::STDIN_FILENO
+        } else {
+            match File::open("/dev/tty") {
+                Ok(file) => {
+                    tty = file;
+                    tty.as_raw_fd()
+                }
+                Err(_) => return false,
+            }
+        };
+
+        let mut termios = std::mem::MaybeUninit::<libc::termios>::uninit();
+        if unsafe { libc::tcgetattr(fd, termios.as_mut_ptr()) } != 0 {
+            return false;
+        }
+        let termios = unsafe { termios.assume_init() };
+        termios.c_lflag & (libc::ICANON | libc::ECHO) != 0
+    }
+
+    /// 終端輸入是否為一般（行緩衝、回顯）模式
+    #[cfg(windows)]
+    fn is_line_buffered() -> bool {
+        use winapi::um::consoleapi::GetConsoleMode;
+        use winapi::um::processenv::GetStdHandle;
+        use winapi::um::winbase::STD_INPUT_HANDLE;
+        use winapi::um::wincon::{ENABLE_ECHO_INPUT, ENABLE_LINE_INPUT};
+
+        let mut mode = 0;
+        unsafe {
+            let handle = GetStdHandle(STD_INPUT_HANDLE);
+            if GetConsoleMode(handle, &mut mode) == 0 {
+                return false;
+            }
+        }
+        mode & (ENABLE_LINE_INPUT | ENABLE_ECHO_INPUT) != 0
+    }
+
     pub fn clear_screen() -> Result<()> {
-        execute!(io::stdout(), terminal::Clear(ClearType::All))?;
+        execute!(Self::output(), terminal::Clear(ClearType::All))?;
+        Self::invalidate_screen();
+        Ok(())
+    }
+
+    /// 開始輸出一幀：進入同步輸出並隱藏游標
+    ///
+    /// 支援同步輸出（BSU/ESU）的終端會等到 [`Terminal::end_frame`] 才一次更新畫面，
+    /// 避免快速捲動時出現撕裂；不支援的終端會忽略這個序列
+    pub fn begin_frame() -> Result<()> {
+        queue!(
+            Self::output(),
+            terminal::BeginSynchronizedUpdate,
+            cursor::Hide
+        )?;
+        Ok(())
+    }
+
+    /// 結束一幀：游標移到最終位置後只顯示一次（None 保持隱藏），結束同步輸出並送出
+    pub fn end_frame(cursor_position: Option<(u16, u16)>) -> Result<()> {
+        let mut output = Self::output();
+        if let Some((x, y)) = cursor_position {
+            queue!(output, cursor::MoveTo(x, y), cursor::Show)?;
+        }
+        queue!(output, terminal::EndSynchronizedUpdate)?;
+        output.flush()?;
         Ok(())
     }
 
+    /// 測量終端把東亞模糊寬度字元顯示為幾欄，返回 true 表示兩欄（需在 raw mode 中呼叫）
+    ///
+    /// 在左上角輸出 `±` 後以游標位置回報（DSR）讀取游標位置；終端不回應時返回錯誤
+    #[allow(dead_code)]
+    pub fn probe_ambiguous_wide() -> Result<bool> {
+        // 游標位置回報固定寫到 stdout，畫面改寫到控制終端時無法測量
+        if TTY_OUTPUT.get().is_some() {
+            anyhow::bail!("Cannot probe the terminal while stdout is redirected");
+        }
+        execute!(
+            Self::output(),
+            cursor::MoveTo(0, 0),
+            style::Print('\u{00B1}')
+        )?;
+        let position = cursor::position();
+        execute!(
+            Self::output(),
+            cursor::MoveTo(0, 0),
+            terminal::Clear(ClearType::CurrentLine)
+        )?;
+        Ok(position?.0 >= 2)
+    }
+
+    /// 標記畫面已被覆寫，下一次渲染不沿用上一幀的內容
+    pub fn invalidate_screen() {
+        SCREEN_INVALIDATED.store(true, Ordering::Relaxed);
+    }
+
+    /// 取得並重置畫面覆寫標記
+    pub fn take_screen_invalidated() -> bool {
+        SCREEN_INVALIDATED.swap(false, Ordering::Relaxed)
+    }
+
     pub fn size(&self) -> (u16, u16) {
         self.size
     }
@@ -46,61 +628,251 @@ impl Terminal {
 
     #[allow(dead_code)]
     pub fn flush() -> Result<()> {
-        io::stdout().flush()?;
+        Self::output().flush()?;
         Ok(())
     }
 
     pub fn read_key() -> Result<KeyEvent> {
+        if let Some(key_event) = Self::take_pending_key() {
+            return Ok(key_event);
+        }
+        if Self::termination_signal().is_some() {
+            return Ok(Self::terminating_key());
+        }
         loop {
-            let event = event::read()?;
-
-            match event {
-                Event::Key(key_event) => {
-                    // 處理正常的 Press 和 Repeat 事件
-                    if key_event.kind == KeyEventKind::Press
-                        || key_event.kind == KeyEventKind::Repeat
-                    {
-                        return Ok(key_event);
-                    }
-                }
-                Event::Resize(_cols, _rows) => {
-                    // 視窗大小改變,返回特殊標記
-                    return Ok(KeyEvent::new(KeyCode::F(21), KeyModifiers::NONE));
+            if let Some(key_event) = Self::translate_event(event::read()?) {
+                return Self::resolve_escape(key_event);
+            }
+        }
+    }
+
+    /// 等待按鍵，或直到其他執行緒以 [`Terminal::wake`] 喚醒時返回 None
+    #[allow(dead_code)]
+    pub fn read_key_or_wake() -> Result<Option<KeyEvent>> {
+        loop {
+            if let Some(key_event) = Self::poll_key(Duration::ZERO)? {
+                return Ok(Some(key_event));
+            }
+            if WAKE_REQUESTED.swap(false, Ordering::Relaxed) {
+                return Ok(None);
+            }
+            #[cfg(unix)]
+            {
+                // 管道可能比 crossterm 先收到 SIGWINCH，這時自行回報視窗大小改變
+                if RESIZE_PENDING.swap(false, Ordering::Relaxed) {
+                    return Ok(Some(KeyEvent::new(KeyCode::F(21), KeyModifiers::NONE)));
                 }
-                Event::Paste(_text) => {
-                    // Windows Terminal 的 Ctrl+V 觸發 Paste 事件
-                    // 返回一個特殊按鍵標記,攜帶文本長度信息
-                    // 實際文本需要從剪貼簿讀取
-                    return Ok(KeyEvent::new(KeyCode::F(20), KeyModifiers::NONE));
+                if !wait_for_input() {
+                    return Self::read_key().map(Some);
                 }
-                _ => {
-                    // 忽略其他事件（鼠標、調整大小等）
+            }
+            // 喚醒時收到的焦點事件轉換後是 None，回到迴圈開頭檢查喚醒請求
+            #[cfg(not(unix))]
+            if let Some(key_event) = Self::translate_event(event::read()?) {
+                return Self::resolve_escape(key_event).map(Some);
+            }
+        }
+    }
+
+    /// 在 timeout 內等待按鍵，逾時返回 None
+    #[allow(dead_code)]
+    pub fn poll_key(timeout: Duration) -> Result<Option<KeyEvent>> {
+        if let Some(key_event) = Self::take_pending_key() {
+            return Ok(Some(key_event));
+        }
+        if Self::termination_signal().is_some() {
+            return Ok(Some(Self::terminating_key()));
+        }
+        let deadline = Instant::now() + timeout;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if !event::poll(remaining)? {
+                return Ok(None);
+            }
+            if let Some(key_event) = Self::translate_event(event::read()?) {
+                return Self::resolve_escape(key_event).map(Some);
+            }
+        }
+    }
+
+    /// 收到終止訊號後每次讀取都返回 ESC，讓開著的對話框一層層取消，回到主迴圈結束
+    fn terminating_key() -> KeyEvent {
+        KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE)
+    }
+
+    fn take_pending_key() -> Option<KeyEvent> {
+        PENDING_KEY.lock().unwrap_or_else(|e| e.into_inner()).take()
+    }
+
+    /// 單獨的 ESC：在逾時內等待下一個按鍵
+    ///
+    /// 有些終端（以及 SSH、tmux 等中間層把序列拆開送出時）將 Alt+鍵 送成 ESC 加上該鍵，
+    /// 若兩者分開到達，crossterm 會回報成兩個按鍵。逾時內緊接著到達的按鍵合併為 Alt 組合；
+    /// 逾時或接著的是不能合併的事件時，ESC 照常單獨處理（清除訊息、取消對話框）
+    fn resolve_escape(key_event: KeyEvent) -> Result<KeyEvent> {
+        let timeout = Self::escape_timeout();
+        if key_event.code != KeyCode::Esc
+            || key_event.modifiers != KeyModifiers::NONE
+            || timeout.is_zero()
+        {
+            return Ok(key_event);
+        }
+        let deadline = Instant::now() + timeout;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if !event::poll(remaining)? {
+                return Ok(key_event);
+            }
+            let Some(next) = Self::translate_event(event::read()?) else {
+                continue;
+            };
+            return Ok(match with_alt(next) {
+                Some(combined) => combined,
+                None => {
+                    *PENDING_KEY.lock().unwrap_or_else(|e| e.into_inner()) = Some(next);
+                    key_event
                 }
+            });
+        }
+    }
+
+    /// 將終端事件轉換為按鍵，不需處理的事件返回 None
+    fn translate_event(event: Event) -> Option<KeyEvent> {
+        match event {
+            // 處理正常的 Press 和 Repeat 事件
+            Event::Key(key_event)
+                if key_event.kind == KeyEventKind::Press
+                    || key_event.kind == KeyEventKind::Repeat =>
+            {
+                Some(key_event)
+            }
+            Event::Resize(_cols, _rows) => {
+                // 視窗大小改變,返回特殊標記
+                #[cfg(unix)]
+                RESIZE_PENDING.store(false, Ordering::Relaxed);
+                Some(KeyEvent::new(KeyCode::F(21), KeyModifiers::NONE))
+            }
+            Event::Paste(_text) => {
+                // Windows Terminal 的 Ctrl+V 觸發 Paste 事件
+                // 返回一個特殊按鍵標記,攜帶文本長度信息
+                // 實際文本需要從剪貼簿讀取
+                Some(KeyEvent::new(KeyCode::F(20), KeyModifiers::NONE))
+            }
+            Event::Mouse(mouse_event) if MOUSE_CAPTURE.load(Ordering::Relaxed) => {
+                // 滑鼠事件放在 MOUSE_EVENT，返回 F22 標記
+                *MOUSE_EVENT.lock().unwrap_or_else(|e| e.into_inner()) = Some(mouse_event);
+                Some(KeyEvent::new(KeyCode::F(22), KeyModifiers::NONE))
+            }
+            _ => {
+                // 忽略其他事件（未開啟擷取時的滑鼠、焦點等）
+                None
             }
         }
     }
 
     #[allow(dead_code)]
     pub fn set_cursor_position(x: u16, y: u16) -> Result<()> {
-        execute!(io::stdout(), cursor::MoveTo(x, y))?;
+        execute!(Self::output(), cursor::MoveTo(x, y))?;
         Ok(())
     }
 
     #[allow(dead_code)]
     pub fn hide_cursor() -> Result<()> {
-        execute!(io::stdout(), cursor::Hide)?;
+        execute!(Self::output(), cursor::Hide)?;
         Ok(())
     }
 
     pub fn show_cursor() -> Result<()> {
-        execute!(io::stdout(), cursor::Show)?;
+        execute!(Self::output(), cursor::Show)?;
+        Ok(())
+    }
+
+    /// 設定游標樣式，None 還原為終端的使用者預設樣式（隨下一幀一起送出）
+    #[allow(dead_code)]
+    pub fn set_cursor_style(style: Option<CursorStyle>) -> Result<()> {
+        match style {
+            Some(style) => {
+                queue!(Self::output(), style.command())?;
+                CURSOR_STYLE_CHANGED.store(true, Ordering::Relaxed);
+            }
+            None => Self::reset_cursor_style()?,
+        }
+        Ok(())
+    }
+
+    /// 曾改變游標樣式時還原為使用者預設（結束或 panic 時呼叫）
+    pub fn reset_cursor_style() -> Result<()> {
+        if CURSOR_STYLE_CHANGED.swap(false, Ordering::Relaxed) {
+            execute!(Self::output(), cursor::SetCursorStyle::DefaultUserShape)?;
+        }
         Ok(())
     }
 }
 
+/// [`Terminal::shield_interrupts`] 的結果，drop 時還原 SIGINT 原本的設定
+pub struct InterruptShield {
+    #[cfg(unix)]
+    previous: libc::sigaction,
+}
+
+impl Drop for InterruptShield {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        unsafe {
+            libc::sigaction(libc::SIGINT, &self.previous, std::ptr::null_mut());
+        }
+    }
+}
+
 impl Drop for Terminal {
     fn drop(&mut self) {
         let _ = Self::exit_raw_mode();
         let _ = Self::show_cursor();
+        let _ = Self::reset_cursor_style();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_escape_prefix_becomes_alt() {
+        let key = |code, modifiers| KeyEvent::new(code, modifiers);
+        assert_eq!(
+            with_alt(key(KeyCode::Char('f'), KeyModifiers::NONE)),
+            Some(key(KeyCode::Char('f'), KeyModifiers::ALT))
+        );
+        assert_eq!(
+            with_alt(key(KeyCode::Char('{'), KeyModifiers::SHIFT)),
+            Some(key(
+                KeyCode::Char('{'),
+                KeyModifiers::SHIFT | KeyModifiers::ALT
+            ))
+        );
+        assert_eq!(
+            with_alt(key(KeyCode::Up, KeyModifiers::NONE)),
+            Some(key(KeyCode::Up, KeyModifiers::ALT))
+        );
+        // 連按兩次 ESC、已是 Alt 組合與內部標記維持原樣
+        assert_eq!(with_alt(key(KeyCode::Esc, KeyModifiers::NONE)), None);
+        assert_eq!(with_alt(key(KeyCode::Char('f'), KeyModifiers::ALT)), None);
+        assert_eq!(with_alt(key(KeyCode::F(22), KeyModifiers::NONE)), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_shell_command_survives_interrupt() {
+        use std::os::unix::process::ExitStatusExt;
+
+        // 模擬 `:!` 執行期間按下 Ctrl+C：編輯器與命令都收到 SIGINT
+        let shield = Terminal::shield_interrupts();
+        let status = std::process::Command::new("sh")
+            .args(["-c", "kill -INT $PPID; kill -INT $$; sleep 5"])
+            .status()
+            .unwrap();
+        drop(shield);
+        // 命令被中斷，測試程序（編輯器）仍在執行
+        assert_eq!(status.signal(), Some(libc::SIGINT));
     }
 }
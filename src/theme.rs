@@ -0,0 +1,200 @@
+// 介面配色
+//
+// 狀態列、訊息、行號、選擇範圍與搜尋結果的顏色（語法高亮的顏色由 syntect 主題決定）。
+// 內建幾組配色，每個元素也可以用 `:set ui.ELEMENT=FG[,BG]` 或設定檔個別調整
+
+use crate::styled::{parse_color, SpanStyle};
+use anyhow::{bail, Result};
+use crossterm::style::Color;
+
+/// 內建的介面配色名稱
+#[allow(dead_code)]
+pub const UI_THEMES: &[&str] = &["default", "light", "high-contrast"];
+
+/// 可以個別調整的介面元素
+#[allow(dead_code)]
+pub const UI_ELEMENTS: &[&str] = &[
+    "statusbar",
+    "message",
+    "error",
+    "linenumber",
+    "selection",
+    "search",
+    "searchcurrent",
+    "stickyheader",
+];
+
+/// 介面各元素的樣式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UiTheme {
+    pub status_bar: SpanStyle,
+    pub message: SpanStyle, // 狀態列顯示訊息時
+    pub error: SpanStyle,   // 狀態列上方的錯誤訊息列
+    pub line_number: SpanStyle,
+    pub selection: SpanStyle,
+    pub search_match: SpanStyle,
+    pub search_current: SpanStyle, // 游標所在的搜尋結果
+    pub sticky_header: SpanStyle,  // 固定在頂端的外層標題行
+}
+
+fn colors(foreground: Option<Color>, background: Option<Color>) -> SpanStyle {
+    SpanStyle {
+        foreground,
+        background,
+        ..SpanStyle::default()
+    }
+}
+
+impl UiTheme {
+    /// 內建配色；名稱不存在時返回 None
+    pub fn named(name: &str) -> Option<Self> {
+        let theme = match name {
+            "default" => Self {
+                status_bar: colors(Some(Color::White), Some(Color::DarkGrey)),
+                message: colors(Some(Color::White), Some(Color::DarkGrey)),
+                error: colors(Some(Color::White), Some(Color::DarkRed)),
+                line_number: colors(Some(Color::DarkGrey), None),
+                selection: SpanStyle {
+                    reverse: true,
+                    ..SpanStyle::default()
+                },
+                search_match: colors(None, Some(Color::DarkYellow)),
+                search_current: colors(Some(Color::Black), Some(Color::Yellow)),
+                sticky_header: SpanStyle {
+                    underline: true,
+                    ..colors(None, Some(Color::AnsiValue(236)))
+                },
+            },
+            // 淺色背景的終端：深色文字與淡色背景（256 色）
+            "light" => Self {
+                status_bar: colors(Some(Color::Black), Some(Color::AnsiValue(252))),
+                message: colors(Some(Color::Black), Some(Color::AnsiValue(229))),
+                error: colors(Some(Color::White), Some(Color::AnsiValue(160))),
+                line_number: colors(Some(Color::AnsiValue(245)), None),
+                selection: colors(None, Some(Color::AnsiValue(153))),
+                search_match: colors(None, Some(Color::AnsiValue(223))),
+                search_current: colors(Some(Color::Black), Some(Color::AnsiValue(214))),
+                sticky_header: SpanStyle {
+                    underline: true,
+                    ..colors(None, Some(Color::AnsiValue(254)))
+                },
+            },
+            // 只用基本 16 色並加大對比
+            "high-contrast" => Self {
+                status_bar: colors(Some(Color::Black), Some(Color::White)),
+                message: colors(Some(Color::Black), Some(Color::Yellow)),
+                error: colors(Some(Color::Yellow), Some(Color::Red)),
+                line_number: colors(Some(Color::White), None),
+                selection: colors(Some(Color::Black), Some(Color::Cyan)),
+                search_match: colors(Some(Color::Black), Some(Color::Green)),
+                search_current: colors(Some(Color::Black), Some(Color::Magenta)),
+                sticky_header: SpanStyle {
+                    underline: true,
+                    ..colors(Some(Color::White), None)
+                },
+            },
+            _ => return None,
+        };
+        Some(theme)
+    }
+
+    /// 調整單一元素的樣式（元素名稱見 [`UI_ELEMENTS`]，值的格式見 [`parse_style`]）
+    #[allow(dead_code)]
+    pub fn set_element(&mut self, element: &str, value: &str) -> Result<()> {
+        let style = parse_style(value)?;
+        let target = match element {
+            "statusbar" => &mut self.status_bar,
+            "message" => &mut self.message,
+            "error" => &mut self.error,
+            "linenumber" => &mut self.line_number,
+            "selection" => &mut self.selection,
+            "search" => &mut self.search_match,
+            "searchcurrent" => &mut self.search_current,
+            "stickyheader" => &mut self.sticky_header,
+            _ => bail!(
+                "Unknown UI element: {} (use {})",
+                element,
+                UI_ELEMENTS.join(", ")
+            ),
+        };
+        *target = style;
+        Ok(())
+    }
+}
+
+impl Default for UiTheme {
+    fn default() -> Self {
+        Self::named("default").expect("default UI theme")
+    }
+}
+
+/// 解析元素樣式：`FG[,BG]` 加上可選的 `reverse`、`underline`
+///
+/// 顏色格式與 [`parse_color`] 相同，留空表示沿用文字本身的顏色，
+/// 例如 `white,dark_blue`、`,#303030`、`reverse`
+#[allow(dead_code)]
+pub fn parse_style(value: &str) -> Result<SpanStyle> {
+    let mut style = SpanStyle::default();
+    let mut colors = 0;
+    for part in value.split(',').map(str::trim) {
+        match part {
+            "reverse" => style.reverse = true,
+            "underline" => style.underline = true,
+            _ => {
+                let color = if part.is_empty() || part == "none" {
+                    None
+                } else {
+                    Some(parse_color(part)?)
+                };
+                match colors {
+                    0 => style.foreground = color,
+                    1 => style.background = color,
+                    _ => bail!(
+                        "Invalid style: {} (use FG[,BG][,reverse][,underline])",
+                        value
+                    ),
+                }
+                colors += 1;
+            }
+        }
+    }
+    Ok(style)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_style_and_set_element() {
+        assert_eq!(
+            parse_style("white,dark_blue").unwrap(),
+            colors(Some(Color::White), Some(Color::DarkBlue))
+        );
+        assert_eq!(
+            parse_style(",#303030").unwrap(),
+            colors(
+                None,
+                Some(Color::Rgb {
+                    r: 0x30,
+                    g: 0x30,
+                    b: 0x30
+                })
+            )
+        );
+        let reverse = parse_style("reverse").unwrap();
+        assert!(reverse.reverse && reverse.foreground.is_none());
+        assert!(parse_style("red,blue,green").is_err());
+        assert!(parse_style("nope").is_err());
+
+        let mut theme = UiTheme::default();
+        theme.set_element("linenumber", "yellow").unwrap();
+        assert_eq!(theme.line_number, colors(Some(Color::Yellow), None));
+        assert!(theme.set_element("gutter", "yellow").is_err());
+
+        for name in UI_THEMES {
+            assert!(UiTheme::named(name).is_some(), "{}", name);
+        }
+        assert!(UiTheme::named("solarized").is_none());
+    }
+}
@@ -0,0 +1,137 @@
+// 撤銷檔（`:set undofile`）
+//
+// 存檔後把撤銷歷史寫到狀態目錄的 `undo/` 中，檔名為檔案路徑的雜湊；之後開啟同一個檔案、
+// 且內容與當時存檔的內容相同（比對內容雜湊）時讀回，可以撤銷上次存檔之前的修改。
+// 撤銷檔含有刪除過的文字，所以預設關閉，並只讓自己讀取
+
+use anyhow::{Context, Result};
+use ropey::Rope;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// 撤銷檔的第一行（格式版本）
+const HEADER: &str = "wedi-undo 1";
+
+/// 撤銷檔所在目錄
+pub fn undo_dir() -> Option<PathBuf> {
+    crate::config::state_dir().map(|dir| dir.join("undo"))
+}
+
+/// 寫入 `file_path` 的撤銷檔；`rope` 是剛存檔的內容，`history` 是撤銷歷史的文字格式
+pub fn write(file_path: &Path, rope: &Rope, history: &str) -> Result<()> {
+    let dir = undo_dir().context("Cannot find the undo directory")?;
+    write_in(&dir, file_path, rope, history)
+}
+
+/// 讀取 `file_path` 的撤銷歷史；沒有撤銷檔或內容與 `rope` 不同時返回 None
+pub fn read(file_path: &Path, rope: &Rope) -> Option<String> {
+    read_in(&undo_dir()?, file_path, rope)
+}
+
+fn write_in(dir: &Path, file_path: &Path, rope: &Rope, history: &str) -> Result<()> {
+    let key = key(file_path);
+    let path = undo_path(dir, &key);
+    let mut builder = fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+    builder
+        .create(dir)
+        .with_context(|| format!("Cannot create {}", dir.display()))?;
+
+    let text = format!(
+        "{}\n{}\n{:016x}\n{}",
+        HEADER,
+        key,
+        hash(rope.chunks().map(str::as_bytes)),
+        history
+    );
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options
+        .open(&path)
+        .with_context(|| format!("Cannot write {}", path.display()))?;
+    std::io::Write::write_all(&mut file, text.as_bytes())
+        .with_context(|| format!("Cannot write {}", path.display()))
+}
+
+fn read_in(dir: &Path, file_path: &Path, rope: &Rope) -> Option<String> {
+    let key = key(file_path);
+    let path = undo_path(dir, &key);
+    let text = fs::read_to_string(path).ok()?;
+    let mut lines = text.splitn(4, '\n');
+    let content_hash = format!("{:016x}", hash(rope.chunks().map(str::as_bytes)));
+    // 路徑雜湊相同的其他檔案，或存檔後在其他地方修改過的檔案
+    if lines.next()? != HEADER || lines.next()? != key || lines.next()? != content_hash {
+        return None;
+    }
+    Some(lines.next().unwrap_or_default().to_string())
+}
+
+/// 撤銷檔以檔案的絕對路徑區分
+fn key(file_path: &Path) -> String {
+    fs::canonicalize(file_path)
+        .unwrap_or_else(|_| file_path.to_path_buf())
+        .to_string_lossy()
+        .replace('\n', "?")
+}
+
+/// 撤銷檔路徑：檔名為檔案路徑的雜湊
+fn undo_path(dir: &Path, key: &str) -> PathBuf {
+    dir.join(format!("{:016x}.undo", hash([key.as_bytes()])))
+}
+
+/// FNV-1a 64 位元雜湊（不同版本與平台的結果相同）
+fn hash<'a>(chunks: impl IntoIterator<Item = &'a [u8]>) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in chunks.into_iter().flatten() {
+        hash ^= u64::from(*byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_undo_file_matches_path_and_content() {
+        let dir = tempfile::tempdir().unwrap();
+        let undo_dir = dir.path().join("undo");
+        let file = dir.path().join("notes.txt");
+        fs::write(&file, "hello\n").unwrap();
+        let rope = Rope::from_str("hello\n");
+
+        write_in(
+            &undo_dir,
+            &file,
+            &rope,
+            "u\tinsert \"hello\"\ti\t0\thello\n",
+        )
+        .unwrap();
+        assert_eq!(
+            read_in(&undo_dir, &file, &rope).as_deref(),
+            Some("u\tinsert \"hello\"\ti\t0\thello\n")
+        );
+        // 存檔後在其他地方修改過
+        assert_eq!(read_in(&undo_dir, &file, &Rope::from_str("hello!\n")), None);
+        // 其他檔案
+        assert_eq!(
+            read_in(&undo_dir, &dir.path().join("other.txt"), &rope),
+            None
+        );
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let undo_file = fs::read_dir(&undo_dir).unwrap().next().unwrap().unwrap();
+            let mode = undo_file.metadata().unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        assert_eq!(hash([b"a".as_slice()]), 0xaf63_dc4c_8601_ec8c);
+    }
+}
@@ -23,7 +23,7 @@ impl LineWrapper {
         let mut current_width = 0;
 
         for ch in line.chars() {
-            let char_width = unicode_width::UnicodeWidthChar::width(ch).unwrap_or(1);
+            let char_width = crate::utils::char_width(ch);
 
             if current_width + char_width > self.max_width {
                 wrapped.push(current.clone());
@@ -4,11 +4,57 @@ mod line_wrapper;
 pub use line_wrapper::LineWrapper;
 
 use std::sync::atomic::{AtomicBool, Ordering};
+use unicode_segmentation::UnicodeSegmentation;
 use unicode_width::UnicodeWidthChar;
 
 /// 全局調試模式標誌，支持運行時通過 --debug 參數啟用
 static DEBUG_MODE: AtomicBool = AtomicBool::new(false);
 
+/// 東亞模糊寬度字元（例如 ±、§、部分 CJK 標點）是否佔兩欄
+static AMBIGUOUS_WIDE: AtomicBool = AtomicBool::new(false);
+
+/// 東亞模糊寬度字元的處理方式
+///
+/// 終端對這些字元顯示為一欄或兩欄並不一致，與終端不同時光標會對不齊
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AmbiguousWidth {
+    /// 佔一欄（大多數西文環境的終端）
+    Narrow,
+    /// 佔兩欄（CJK 環境的終端）
+    Wide,
+    /// 啟動時以游標位置回報測量終端的實際寬度
+    Auto,
+}
+
+impl std::str::FromStr for AmbiguousWidth {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s.to_lowercase().as_str() {
+            "narrow" | "single" | "1" => Ok(Self::Narrow),
+            "wide" | "double" | "2" => Ok(Self::Wide),
+            "auto" => Ok(Self::Auto),
+            _ => anyhow::bail!(
+                "Unknown ambiguous width: {} (expected narrow, wide or auto)",
+                s
+            ),
+        }
+    }
+}
+
+/// 設定模糊寬度字元是否佔兩欄
+#[allow(dead_code)]
+pub fn set_ambiguous_wide(wide: bool) {
+    AMBIGUOUS_WIDE.store(wide, Ordering::Relaxed);
+}
+
+/// 模糊寬度字元是否佔兩欄
+#[allow(dead_code)]
+pub fn is_ambiguous_wide() -> bool {
+    AMBIGUOUS_WIDE.load(Ordering::Relaxed)
+}
+
 /// 設置調試模式
 #[allow(dead_code)]
 pub fn set_debug_mode(enabled: bool) {
@@ -37,13 +83,116 @@ macro_rules! debug_log {
 /// 計算字符串的視覺寬度（考慮寬字元）
 /// 中文字元等寬字元會正確計算為 2，ASCII 字元計算為 1
 pub fn visual_width(s: &str) -> usize {
-    s.chars()
-        .map(|ch| UnicodeWidthChar::width(ch).unwrap_or(1))
-        .sum()
+    s.chars().map(char_width).sum()
 }
 
-/// 計算單個字符的視覺寬度
-#[allow(dead_code)]
+/// 計算單個字符的視覺寬度（模糊寬度字元依 [`set_ambiguous_wide`] 的設定）
+///
+/// 組合符號、ZWJ 與變體選擇符等零寬字元為 0，畫在前一個字元的格子上；
+/// 控制字元沒有定義寬度，以 1 計算（畫面上一律以 `^X` 或 `?` 等替代符號顯示）
 pub fn char_width(ch: char) -> usize {
-    UnicodeWidthChar::width(ch).unwrap_or(1)
+    char_width_with(ch, is_ambiguous_wide())
+}
+
+/// 計算單個字符的視覺寬度，`ambiguous_wide` 決定模糊寬度字元佔幾欄
+fn char_width_with(ch: char, ambiguous_wide: bool) -> usize {
+    let width = if ambiguous_wide {
+        UnicodeWidthChar::width_cjk(ch)
+    } else {
+        UnicodeWidthChar::width(ch)
+    };
+    width.unwrap_or(1)
+}
+
+/// 一行中各個字素叢集（extended grapheme cluster）的起點（字元索引），最後附上行的字元數
+///
+/// emoji 的 ZWJ 序列、國旗、膚色修飾與組合字元在畫面上是一個字，光標移動與刪除以叢集為單位
+fn grapheme_boundaries(line: &str) -> impl Iterator<Item = usize> + '_ {
+    let mut col = 0;
+    std::iter::once(0).chain(line.graphemes(true).map(move |g| {
+        col += g.chars().count();
+        col
+    }))
+}
+
+/// `col` 之前最近的字素叢集邊界；`col` 為 0 時返回 0
+pub fn prev_grapheme_boundary(line: &str, col: usize) -> usize {
+    grapheme_boundaries(line)
+        .take_while(|&b| b < col)
+        .last()
+        .unwrap_or(0)
+}
+
+/// `col` 之後最近的字素叢集邊界；已在行尾時返回行的字元數
+pub fn next_grapheme_boundary(line: &str, col: usize) -> usize {
+    let mut last = 0;
+    for b in grapheme_boundaries(line) {
+        if b > col {
+            return b;
+        }
+        last = b;
+    }
+    last.max(col)
+}
+
+/// 不大於 `col` 的字素叢集邊界（落在叢集中間時退回叢集開頭）
+pub fn floor_grapheme_boundary(line: &str, col: usize) -> usize {
+    grapheme_boundaries(line)
+        .take_while(|&b| b <= col)
+        .last()
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ambiguous_width() {
+        assert_eq!(char_width_with('±', false), 1);
+        assert_eq!(char_width_with('±', true), 2);
+        // 明確的窄字元與寬字元不受設定影響
+        assert_eq!(char_width_with('a', true), 1);
+        assert_eq!(char_width_with('中', false), 2);
+        assert_eq!(
+            "wide".parse::<AmbiguousWidth>().unwrap(),
+            AmbiguousWidth::Wide
+        );
+        assert!("medium".parse::<AmbiguousWidth>().is_err());
+    }
+
+    #[test]
+    fn test_zero_width_characters() {
+        for ch in [
+            '\u{301}', '\u{200B}', '\u{200D}', '\u{FE0F}', '\u{FEFF}', '\u{1161}',
+        ] {
+            assert_eq!(char_width_with(ch, false), 0, "U+{:04X}", ch as u32);
+            assert_eq!(char_width_with(ch, true), 0, "U+{:04X}", ch as u32);
+        }
+        assert_eq!(visual_width("e\u{301}\u{302}x"), 2);
+        assert_eq!(char_width_with('\x1b', false), 1);
+    }
+
+    #[test]
+    fn test_grapheme_boundaries() {
+        // 國旗、ZWJ 家庭 emoji、膚色修飾、組合字元與韓文字母（初聲+中聲+終聲）
+        let line = "a🇹🇼👨\u{200D}👩\u{200D}👧👍🏽e\u{301}\u{1100}\u{1161}\u{11A8}z";
+        let expected = [0, 1, 3, 8, 10, 12, 15, 16];
+        let boundaries: Vec<usize> = grapheme_boundaries(line).collect();
+        assert_eq!(boundaries, expected);
+
+        for pair in expected.windows(2) {
+            assert_eq!(next_grapheme_boundary(line, pair[0]), pair[1]);
+            assert_eq!(prev_grapheme_boundary(line, pair[1]), pair[0]);
+            // 在叢集中間
+            for col in pair[0] + 1..pair[1] {
+                assert_eq!(floor_grapheme_boundary(line, col), pair[0]);
+                assert_eq!(next_grapheme_boundary(line, col), pair[1]);
+                assert_eq!(prev_grapheme_boundary(line, col), pair[0]);
+            }
+        }
+        assert_eq!(prev_grapheme_boundary(line, 0), 0);
+        assert_eq!(next_grapheme_boundary(line, 16), 16);
+        assert_eq!(next_grapheme_boundary("", 0), 0);
+    }
 }
@@ -1,17 +1,18 @@
+use crate::backend::TerminalBackend;
 use crate::buffer::RopeBuffer;
 use crate::cursor::Cursor;
+use crate::grid::Grid;
+use crate::styled::{SpanStyle, StyledLine};
 use crate::terminal::Terminal;
-use crate::utils::visual_width;
+use crate::theme::UiTheme;
+use crate::utils::{char_width, visual_width};
 use anyhow::Result;
-use crossterm::{
-    cursor, execute, queue,
-    style::{self, Attribute, Color},
-};
-use std::io::{self, Write};
-use unicode_width::UnicodeWidthChar;
+use crossterm::style::Color;
+use std::collections::HashMap;
 
 // 視圖配置常量
 const TAB_WIDTH: usize = 4; // Tab 寬度（空格數）
+const MAX_STICKY_SCAN: usize = 5000; // 尋找外層標題行時最多往上（與往下）檢查的行數
 const CACHE_MULTIPLIER: usize = 3; // 緩存大小倍數（螢幕行數 × 倍數）
 
 #[derive(Clone, Debug)]
@@ -22,10 +23,17 @@ pub struct LineLayout {
     pub visual_height: usize,
     /// logical_col -> visual_col（整行累計視覺座標）
     pub logical_to_visual: Vec<usize>,
+    /// 以替代符號顯示的字元所佔的視覺欄位範圍 [start, end)
+    pub invisibles: Vec<(usize, usize, Invisible)>,
 }
 
 impl LineLayout {
-    pub fn new(buffer: &RopeBuffer, row: usize, available_width: usize) -> Option<Self> {
+    pub fn new(
+        buffer: &RopeBuffer,
+        row: usize,
+        available_width: usize,
+        show_invisibles: bool,
+    ) -> Option<Self> {
         let line = buffer.line(row)?;
         let mut line_str = line.to_string();
         // 去掉結尾換行符
@@ -33,34 +41,142 @@ impl LineLayout {
             line_str.pop();
         }
 
-        let (displayed_line, logical_to_visual) = expand_tabs_and_build_map(&line_str);
-        let visual_lines = wrap_line(&displayed_line, available_width);
+        let expanded = expand_tabs_and_build_map(&line_str, show_invisibles);
+        let visual_lines = wrap_line(&expanded.displayed, available_width);
         let visual_height = visual_lines.len();
 
         Some(LineLayout {
             visual_lines,
             visual_height,
-            logical_to_visual,
+            logical_to_visual: expanded.logical_to_visual,
+            invisibles: expanded.invisibles,
         })
     }
+
+    /// 空行（或超出範圍的行）
+    fn empty() -> Self {
+        LineLayout {
+            visual_lines: vec![String::new()],
+            visual_height: 1,
+            logical_to_visual: vec![0],
+            invisibles: Vec::new(),
+        }
+    }
+}
+
+/// 以替代符號顯示的字元種類
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Invisible {
+    /// 顯示不可見字元時的 Tab（→）與空白（·）
+    Whitespace,
+    /// 控制字元（^X）與其他不可列印字元（<U+XXXX>）
+    Control,
+}
+
+impl Invisible {
+    fn style(self) -> SpanStyle {
+        let color = match self {
+            Invisible::Whitespace => Color::DarkGrey,
+            Invisible::Control => Color::Red,
+        };
+        SpanStyle {
+            foreground: Some(color),
+            ..SpanStyle::default()
+        }
+    }
 }
 
-fn expand_tabs_and_build_map(line: &str) -> (String, Vec<usize>) {
+/// 字元的替代顯示文字；None 表示照原樣顯示（Tab 未顯示不可見字元時展開為空白）
+///
+/// 控制字元直接輸出會移動終端的游標或改變狀態，因此一律以 `^X` 或 `<U+XXXX>` 顯示；
+/// 零寬的格式字元（零寬空白、雙向控制等）只在顯示不可見字元時標出
+fn placeholder(ch: char, show_invisibles: bool) -> Option<(String, Invisible)> {
+    let text = match ch {
+        '\t' if show_invisibles => {
+            let padding = TAB_WIDTH.saturating_sub(char_width('→'));
+            (format!("→{}", " ".repeat(padding)), Invisible::Whitespace)
+        }
+        ' ' if show_invisibles => ("·".to_string(), Invisible::Whitespace),
+        '\t' => return None,
+        '\0'..='\x1f' => (
+            format!("^{}", char::from(ch as u8 + 0x40)),
+            Invisible::Control,
+        ),
+        '\x7f' => ("^?".to_string(), Invisible::Control),
+        _ if ch.is_control() || (show_invisibles && is_invisible_format(ch)) => {
+            (format!("<U+{:04X}>", ch as u32), Invisible::Control)
+        }
+        _ => return None,
+    };
+    Some(text)
+}
+
+/// 不佔寬度、畫面上看不出來的格式字元
+fn is_invisible_format(ch: char) -> bool {
+    matches!(
+        ch,
+        '\u{00AD}'
+            | '\u{200B}'..='\u{200F}'
+            | '\u{202A}'..='\u{202E}'
+            | '\u{2060}'..='\u{2064}'
+            | '\u{2066}'..='\u{2069}'
+            | '\u{FEFF}'
+    )
+}
+
+/// 沒有基底字元可以依附的組合符號（位於行首）前面補上的虛線圓圈
+///
+/// 零寬字元畫在前一格上；行首的組合符號若直接輸出會疊到行號欄，因此和 Unicode 的慣例一樣畫在 ◌ 上
+const ORPHAN_MARK_BASE: char = '\u{25CC}';
+
+/// 視覺欄位 `visual_col` 上的邏輯列：視覺座標不大於它的最後一個字元
+///
+/// 零寬字元與前一個字元位於同一欄，因此會越過它們，停在下一個佔寬度的字元上
+fn logical_col_at(logical_to_visual: &[usize], visual_col: usize) -> usize {
+    logical_to_visual
+        .iter()
+        .rposition(|&vcol| vcol <= visual_col)
+        .unwrap_or(0)
+}
+
+/// 展開 Tab 與替代符號後的一行
+struct ExpandedLine {
+    displayed: String,
+    logical_to_visual: Vec<usize>,
+    invisibles: Vec<(usize, usize, Invisible)>,
+}
+
+fn expand_tabs_and_build_map(line: &str, show_invisibles: bool) -> ExpandedLine {
     let mut displayed = String::new();
     let mut logical_to_visual = Vec::new();
+    let mut invisibles = Vec::new();
     let mut visual_col = 0;
 
-    for ch in line.chars() {
+    for (idx, ch) in line.chars().enumerate() {
         // 記錄「這個 logical_col 對應的視覺座標」
         logical_to_visual.push(visual_col);
 
-        if ch == '\t' {
+        if idx == 0
+            && ch != '\t'
+            && char_width(ch) == 0
+            && placeholder(ch, show_invisibles).is_none()
+        {
+            displayed.push(ORPHAN_MARK_BASE);
+            displayed.push(ch);
+            invisibles.push((visual_col, visual_col + 1, Invisible::Whitespace));
+            visual_col += 1;
+        } else if let Some((text, kind)) = placeholder(ch, show_invisibles) {
+            let width = visual_width(&text);
+            displayed.push_str(&text);
+            invisibles.push((visual_col, visual_col + width, kind));
+            visual_col += width;
+        } else if ch == '\t' {
             for _ in 0..TAB_WIDTH {
                 displayed.push(' ');
             }
             visual_col += TAB_WIDTH;
         } else {
-            let w = UnicodeWidthChar::width(ch).unwrap_or(1);
+            let w = char_width(ch);
             displayed.push(ch);
             visual_col += w;
         }
@@ -69,58 +185,373 @@ fn expand_tabs_and_build_map(line: &str) -> (String, Vec<usize>) {
     // 尾端一個 mapping，讓「行尾」也有對應視覺座標
     logical_to_visual.push(visual_col);
 
-    (displayed, logical_to_visual)
+    ExpandedLine {
+        displayed,
+        logical_to_visual,
+        invisibles,
+    }
+}
+
+/// 行號欄中標示與磁碟版本差異的標記
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineMarker {
+    /// 新增的行
+    Added,
+    /// 修改過的行
+    Modified,
+    /// 此行之前有被刪除的行
+    Deleted,
 }
 
+impl LineMarker {
+    fn symbol(self) -> (char, Color) {
+        match self {
+            LineMarker::Added => ('+', Color::Green),
+            LineMarker::Modified => ('~', Color::Yellow),
+            LineMarker::Deleted => ('-', Color::Red),
+        }
+    }
+}
+
+/// 檢查程式（linter）結果的嚴重程度，以行號的顏色與問題處的底線顏色顯示
 #[allow(dead_code)]
-fn calculate_hash(line: &str) -> u64 {
-    use std::collections::hash_map::DefaultHasher;
-    use std::hash::{Hash, Hasher};
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum DiagnosticMarker {
+    Error,
+    Warning,
+}
 
-    let mut hasher = DefaultHasher::new();
-    line.hash(&mut hasher);
-    hasher.finish()
+impl DiagnosticMarker {
+    pub fn color(self) -> Color {
+        match self {
+            DiagnosticMarker::Error => Color::Red,
+            DiagnosticMarker::Warning => Color::Yellow,
+        }
+    }
+}
+
+/// 頂端的分頁列：依序列出開啟的檔案，目前的檔案反白，已修改的檔案名稱後加上 `*`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TabLine {
+    tabs: Vec<(String, bool)>, // (名稱, 是否已修改)
+    current: usize,
 }
 
-#[derive(Debug, Clone, Copy)]
+impl TabLine {
+    pub fn new(tabs: Vec<(String, bool)>, current: usize) -> Self {
+        Self { tabs, current }
+    }
+
+    /// 各分頁的 (索引, 起始欄, 標籤)；放不下時從讓目前的分頁可見的位置開始
+    fn layout(&self, width: usize) -> Vec<(usize, usize, String)> {
+        let labels: Vec<String> = self
+            .tabs
+            .iter()
+            .map(|(name, modified)| format!(" {}{} ", name, if *modified { "*" } else { "" }))
+            .collect();
+        let Some(current) = labels.get(self.current) else {
+            return Vec::new();
+        };
+        let mut first = self.current;
+        let mut used = visual_width(current);
+        while first > 0 && used + visual_width(&labels[first - 1]) <= width {
+            first -= 1;
+            used += visual_width(&labels[first]);
+        }
+
+        let mut x = 0;
+        let mut layout = Vec::new();
+        for (index, label) in labels.into_iter().enumerate().skip(first) {
+            if x >= width {
+                break;
+            }
+            let label_width = visual_width(&label);
+            layout.push((index, x, label));
+            x += label_width;
+        }
+        layout
+    }
+
+    /// 螢幕欄位 `x` 上的分頁
+    pub fn tab_at(&self, x: usize, width: usize) -> Option<usize> {
+        self.layout(width)
+            .into_iter()
+            .find(|(_, start, label)| (*start..start + visual_width(label)).contains(&x))
+            .map(|(index, _, _)| index)
+    }
+
+    fn render(&self, grid: &mut Grid, row: usize, width: usize, theme: &UiTheme) {
+        let mut x = 0;
+        for (index, start, label) in self.layout(width) {
+            let style = if index == self.current {
+                SpanStyle {
+                    reverse: true,
+                    ..theme.status_bar
+                }
+            } else {
+                theme.status_bar
+            };
+            x = grid.put_str(start, row, &label, style);
+        }
+        for x in x..width {
+            grid.put_char(x, row, ' ', theme.status_bar);
+        }
+    }
+}
+
+/// 行號的顯示方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LineNumberMode {
+    /// 絕對行號
+    #[default]
+    Absolute,
+    /// 與光標所在行的距離（光標行顯示 0）
+    Relative,
+    /// 光標行顯示絕對行號，其他行顯示距離
+    Hybrid,
+}
+
+impl std::str::FromStr for LineNumberMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "absolute" | "abs" => Ok(Self::Absolute),
+            "relative" | "rel" => Ok(Self::Relative),
+            "hybrid" => Ok(Self::Hybrid),
+            _ => anyhow::bail!(
+                "Invalid line number mode: {} (use absolute, relative or hybrid)",
+                s
+            ),
+        }
+    }
+}
+
+#[allow(dead_code)]
+impl LineNumberMode {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Absolute => "absolute",
+            Self::Relative => "relative",
+            Self::Hybrid => "hybrid",
+        }
+    }
+
+    /// 下一種顯示方式（絕對 → 相對 → 混合 → 絕對）
+    pub fn next(self) -> Self {
+        match self {
+            Self::Absolute => Self::Relative,
+            Self::Relative => Self::Hybrid,
+            Self::Hybrid => Self::Absolute,
+        }
+    }
+
+    /// 邏輯行 `row` 在光標位於 `cursor_row` 時顯示的行號；`start` 是第一行的行號
+    fn number(self, row: usize, cursor_row: usize, start: usize) -> usize {
+        match self {
+            Self::Absolute => row + start,
+            Self::Hybrid if row == cursor_row => row + start,
+            Self::Relative | Self::Hybrid => row.abs_diff(cursor_row),
+        }
+    }
+}
+
+/// 折行後續視覺行的行號欄
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WrapGutter {
+    /// 留白
+    #[default]
+    Blank,
+    /// 顯示 `·`
+    Dot,
+    /// 顯示從檔案開頭算起的視覺行號（每個換行後的視覺行都計數）
+    Number,
+}
+
+impl std::str::FromStr for WrapGutter {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "blank" | "none" => Ok(Self::Blank),
+            "dot" => Ok(Self::Dot),
+            "number" | "visual" => Ok(Self::Number),
+            _ => anyhow::bail!("Invalid wrap gutter: {} (use blank, dot or number)", s),
+        }
+    }
+}
+
+#[allow(dead_code)]
+impl WrapGutter {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Blank => "blank",
+            Self::Dot => "dot",
+            Self::Number => "number",
+        }
+    }
+}
+
+/// 套用在邏輯行 `[start, end)` 字元欄位上的樣式
+///
+/// 搜尋結果、診斷、拼字檢查等功能都以此描述要標示的範圍，由 View 統一渲染；
+/// 範圍重疊時以列表中較前面的為準
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineSpan {
+    pub start: usize,
+    pub end: usize,
+    pub style: SpanStyle,
+}
+
+/// 選擇範圍，起點與終點都是 (行, 列)，終點可以在起點之前
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Selection {
     pub start: (usize, usize), // (row, col)
     pub end: (usize, usize),   // (row, col)
 }
 
+/// 視覺行索引：從 `base` 行起連續數行的換行高度前綴和
+///
+/// `prefix[i]` 為 `base..base + i` 行的視覺行總數，任兩行之間的視覺距離為 O(1)，
+/// 依螢幕位置找行為 O(log n)。只在需要時向下延伸；編輯時從修改的行截斷，
+/// 大範圍變更或寬度改變時整個重建
+#[derive(Debug, Default)]
+struct VisualIndex {
+    base: usize,
+    width: usize, // 建立索引時的可用寬度
+    prefix: Vec<usize>,
+}
+
+impl VisualIndex {
+    fn clear(&mut self) {
+        self.prefix.clear();
+    }
+
+    /// 第一個尚未計算高度的行
+    fn end(&self) -> usize {
+        self.base + self.prefix.len().saturating_sub(1)
+    }
+
+    /// 捨棄 `row` 以後的高度
+    fn truncate(&mut self, row: usize) {
+        if row < self.base {
+            self.clear();
+        } else if row < self.end() {
+            self.prefix.truncate(row - self.base + 1);
+        }
+    }
+
+    /// 從 `base` 到 `row` 之前的視覺行數（`row` 必須在索引範圍內）
+    fn rows_before(&self, row: usize) -> usize {
+        self.prefix[row - self.base]
+    }
+}
+
 pub struct View {
     pub offset_row: usize, // 視窗頂部顯示的行號（邏輯行）
     pub show_line_numbers: bool,
+    pub line_number_mode: LineNumberMode,
+    pub line_number_start: usize, // 第一行顯示的行號（`:set numberstart`，預設 1）
+    pub hex_line_numbers: bool,   // 以十六進位顯示行號（`:set hexnumber`）
+    wrap_gutter: WrapGutter,      // 折行後續視覺行的行號欄顯示方式
+    pub show_invisibles: bool,    // 以 → 與 · 顯示 Tab 與空白，並標出零寬的格式字元
+    pub cursor_line: Option<Color>, // 光標所在行的背景色（None 表示不標示）
+    pub show_ruler: bool,         // 頂部固定顯示欄位尺規
+    pub sticky_header: bool,      // 捲到區塊中間時在頂端顯示外層的標題行（函式、區段）
+    pub tab_line: Option<TabLine>, // 頂端的分頁列（None 表示不顯示）
+    pub ruler_columns: Vec<usize>, // 尺規上標示的欄位（1-based，例如固定欄位格式的欄位邊界）
+    pub color_columns: Vec<usize>, // 以背景色標示的欄位參考線（1-based，例如行長限制 80）
+    pub color_column_color: Color,
+    pub theme: UiTheme, // 狀態列、行號、選擇範圍等介面元素的顏色
     pub screen_rows: usize,
     pub screen_cols: usize,
     // 行快取：從 offset_row 起往下的數行
     line_layout_cache: Vec<Option<LineLayout>>,
+    status_hint: Option<String>, // 狀態列額外資訊（例如提交訊息摘要長度）
+    error_message: Option<String>, // 錯誤訊息，顯示在狀態列上方額外的一行，不被一般訊息覆蓋
+    line_markers: HashMap<usize, LineMarker>, // 行號欄的修改標記（邏輯行 -> 標記）
+    diagnostic_markers: HashMap<usize, DiagnosticMarker>, // 檢查程式回報問題的行（邏輯行 -> 嚴重程度）
+    line_spans: HashMap<usize, Vec<LineSpan>>,            // 行內樣式範圍（邏輯行 -> 範圍）
+    // 上一幀的畫面格，渲染時只輸出與它不同的格子；None 表示下一次完整重繪
+    previous_frame: Option<Grid>,
+    cursor_screen: (u16, u16), // 上一幀終端光標的位置，補全選單畫在它旁邊
+    visual_index: VisualIndex,
 }
 
 impl View {
     pub fn new(terminal: &Terminal) -> Self {
         let (cols, rows) = terminal.size();
+        Self::with_size(cols, rows)
+    }
+
+    /// 以指定的終端大小建立視圖（不需要終端，例如無畫面的 [`crate::EditorCore`]）
+    pub fn with_size(cols: u16, rows: u16) -> Self {
         let screen_rows = rows.saturating_sub(1) as usize; // 減去狀態欄
         let cache_size = screen_rows.max(1) * CACHE_MULTIPLIER;
 
         Self {
             offset_row: 0,
             show_line_numbers: true,
+            line_number_mode: LineNumberMode::Absolute,
+            line_number_start: 1,
+            hex_line_numbers: false,
+            wrap_gutter: WrapGutter::Blank,
+            show_invisibles: false,
+            cursor_line: None,
+            show_ruler: false,
+            sticky_header: false,
+            tab_line: None,
+            ruler_columns: Vec::new(),
+            color_columns: Vec::new(),
+            color_column_color: DEFAULT_COLOR_COLUMN_COLOR,
+            theme: UiTheme::default(),
             screen_rows,
             screen_cols: cols as usize,
             line_layout_cache: vec![None; cache_size],
+            status_hint: None,
+            error_message: None,
+            line_markers: HashMap::new(),
+            diagnostic_markers: HashMap::new(),
+            line_spans: HashMap::new(),
+            previous_frame: None,
+            cursor_screen: (0, 0),
+            visual_index: VisualIndex::default(),
         }
     }
 
     /// 完全清空緩存（用於大範圍變更或視窗調整）
     pub fn invalidate_cache(&mut self) {
+        self.reset_layout_cache();
+        self.visual_index.clear();
+    }
+
+    /// 清空行快取（捲動後快取的起點改變，但各行高度不變）
+    fn reset_layout_cache(&mut self) {
         let cache_size = self.screen_rows.max(1) * CACHE_MULTIPLIER;
         self.line_layout_cache.clear();
         self.line_layout_cache.resize(cache_size, None);
     }
 
+    /// 行快取的起點向下移動 `rows` 行
+    fn shift_layout_cache(&mut self, rows: usize) {
+        if rows >= self.line_layout_cache.len() {
+            self.reset_layout_cache();
+        } else {
+            self.line_layout_cache.drain(..rows);
+            self.line_layout_cache
+                .resize(self.line_layout_cache.len() + rows, None);
+        }
+    }
+
+    /// 捨棄上一幀的記錄，下一次渲染完整重繪所有行（畫面被其他內容覆寫時使用）
+    pub fn invalidate_frame(&mut self) {
+        self.previous_frame = None;
+    }
+
     /// 部分失效：僅清除指定邏輯行的緩存（用於單行編輯）
     pub fn invalidate_line(&mut self, logical_row: usize) {
+        self.visual_index.truncate(logical_row);
         if logical_row < self.offset_row {
             return; // 不在可見範圍內，無需清除
         }
@@ -141,45 +572,65 @@ impl View {
 
     #[allow(dead_code)]
     pub fn update_size(&mut self) {
-        let size = crossterm::terminal::size().unwrap_or((80, 24));
-        let new_screen_rows = size.1.saturating_sub(1) as usize;
-        let new_screen_cols = size.0 as usize;
+        let (cols, rows) = crossterm::terminal::size().unwrap_or((80, 24));
+        self.resize(cols, rows);
+    }
+
+    /// 設定終端大小（包含狀態列），大小改變時使排版快取失效
+    pub fn resize(&mut self, cols: u16, rows: u16) {
+        let new_screen_rows = rows.saturating_sub(1) as usize;
+        let new_screen_cols = cols as usize;
 
         if self.screen_rows != new_screen_rows || self.screen_cols != new_screen_cols {
             self.screen_rows = new_screen_rows;
             self.screen_cols = new_screen_cols;
             self.invalidate_cache(); // 寬度或高度改變時使快取失效
         }
+        self.invalidate_frame();
     }
 
     pub fn render(
         &mut self,
+        backend: &mut impl TerminalBackend,
         buffer: &RopeBuffer,
         cursor: &Cursor,
         selection: Option<&Selection>,
         message: Option<&str>,
         #[cfg(feature = "syntax-highlighting")] highlighted_lines: Option<
-            &std::collections::HashMap<usize, String>,
+            &HashMap<usize, StyledLine>,
         >,
     ) -> Result<()> {
-        let has_debug_ruler = message.is_some_and(|m| m.starts_with("DEBUG"));
+        let has_ruler = self.show_ruler || message.is_some_and(|m| m.starts_with("DEBUG"));
 
-        self.scroll_if_needed(cursor, buffer, has_debug_ruler);
+        self.scroll_if_needed(cursor, buffer, has_ruler);
 
-        let mut stdout = io::stdout();
+        // 對話框或清除畫面覆寫過的內容與上一幀不同，必須完整重繪
+        if backend.take_screen_invalidated() {
+            self.invalidate_frame();
+        }
 
-        execute!(stdout, cursor::Hide)?;
-        execute!(stdout, cursor::MoveTo(0, 0))?;
+        // 先把整幀畫進畫面格，再與上一幀比較，只輸出改變的格子
+        let mut grid = Grid::new(self.screen_cols, self.screen_rows + 1);
 
-        let ruler_offset = if has_debug_ruler {
-            self.render_column_ruler(&mut stdout, buffer)?;
-            1
+        let tab_rows = self.tab_line_rows();
+        if let Some(tab_line) = &self.tab_line {
+            tab_line.render(&mut grid, 0, self.screen_cols, &self.theme);
+        }
+        let ruler_offset = if has_ruler {
+            self.render_column_ruler(&mut grid, buffer, tab_rows);
+            tab_rows + 1
         } else {
-            0
+            tab_rows
         };
 
         let line_num_width = self.calculate_line_number_width(buffer);
         let available_width = self.get_available_width(buffer);
+        let text_x = if self.show_line_numbers {
+            line_num_width
+        } else {
+            0
+        };
+        let dim = self.theme.line_number;
 
         // 計算選擇範圍（轉換為視覺列）
         let sel_visual_range = selection.map(|sel| {
@@ -215,15 +666,37 @@ impl View {
 
         let mut screen_row = ruler_offset;
         let mut file_row = self.offset_row;
+        // 視窗頂部之前的視覺行數（折行的後續行顯示視覺行號時才需要）
+        let mut visual_row = if self.show_line_numbers && self.wrap_gutter == WrapGutter::Number {
+            self.visual_rows_between(buffer, 0, self.offset_row)
+        } else {
+            0
+        };
 
-        while screen_row < self.screen_rows && file_row < buffer.line_count() {
-            queue!(stdout, cursor::MoveTo(0, screen_row as u16))?;
+        // 有錯誤訊息時狀態列上方多佔一行
+        let text_rows = self.text_rows();
 
+        while screen_row < text_rows && file_row < buffer.line_count() {
             if self.show_line_numbers {
-                let line_num = format!("{:>width$} ", file_row + 1, width = line_num_width - 1);
-                queue!(stdout, style::SetForegroundColor(Color::DarkGrey))?;
-                queue!(stdout, style::Print(&line_num))?;
-                queue!(stdout, style::ResetColor)?;
+                let number = self.line_number_label(file_row, cursor.row);
+                let line_num = format!("{:>width$}", number, width = line_num_width - 1);
+                let style = match self.diagnostic_markers.get(&file_row) {
+                    Some(marker) => SpanStyle {
+                        foreground: Some(marker.color()),
+                        ..dim
+                    },
+                    None => dim,
+                };
+                let x = grid.put_str(0, screen_row, &line_num, style);
+                // 行號右側的空格用來顯示修改標記
+                if let Some(marker) = self.line_markers.get(&file_row) {
+                    let (symbol, color) = marker.symbol();
+                    let style = SpanStyle {
+                        foreground: Some(color),
+                        ..SpanStyle::default()
+                    };
+                    grid.put_char(x, screen_row, symbol, style);
+                }
             }
 
             let cache_index = file_row.saturating_sub(self.offset_row);
@@ -235,168 +708,261 @@ impl View {
 
             let layout = if let Some(layout) = layout_opt {
                 layout
-            } else if let Some(new_layout) = LineLayout::new(buffer, file_row, available_width) {
+            } else if let Some(new_layout) =
+                LineLayout::new(buffer, file_row, available_width, self.show_invisibles)
+            {
                 if cache_index < self.line_layout_cache.len() {
                     self.line_layout_cache[cache_index] = Some(new_layout.clone());
                 }
                 new_layout
             } else {
                 // 空行或超出範圍
-                LineLayout {
-                    visual_lines: vec![String::new()],
-                    visual_height: 1,
-                    logical_to_visual: vec![0],
-                }
+                LineLayout::empty()
             };
 
-            for (visual_idx, visual_line) in layout.visual_lines.iter().enumerate() {
-                if screen_row >= self.screen_rows {
-                    break;
-                }
+            #[cfg(feature = "syntax-highlighting")]
+            let syntax = highlighted_lines.and_then(|h| h.get(&file_row));
+            #[cfg(not(feature = "syntax-highlighting"))]
+            let syntax = None;
+            let styles = self.line_styles(file_row, &layout, syntax, sel_visual_range);
+            // 光標所在行（含換行後的每個視覺行）以背景色標示到行尾，行內範圍自己的背景色優先
+            let line_background = self.cursor_line.filter(|_| file_row == cursor.row);
 
+            // 逐視覺行輸出，樣式以整個邏輯行的視覺欄位查詢
+            let mut visual_col = 0;
+            for (visual_idx, visual_line) in layout.visual_lines.iter().enumerate() {
                 if visual_idx > 0 {
                     screen_row += 1;
-                    if screen_row >= self.screen_rows {
-                        break;
-                    }
-                    queue!(stdout, cursor::MoveTo(0, screen_row as u16))?;
-
-                    if self.show_line_numbers {
-                        for _ in 0..line_num_width {
-                            queue!(stdout, style::Print(" "))?;
-                        }
-                    }
                 }
-
-                // 渲染視覺行，支持 selection 高亮和語法高亮
-
-                // 檢查是否有語法高亮（無選擇時）
-                #[cfg(feature = "syntax-highlighting")]
-                let use_syntax_highlight = selection.is_none()
-                    && visual_idx == 0  // 只在第一個 visual line 使用（簡化處理）
-                    && highlighted_lines.and_then(|h| h.get(&file_row)).is_some();
-
-                #[cfg(not(feature = "syntax-highlighting"))]
-                let use_syntax_highlight = false;
-
-                if let Some(((start_row, start_col), (end_row, end_col))) = sel_visual_range {
-                    if file_row >= start_row && file_row <= end_row {
-                        // 這一行有選擇，需要逐字符渲染
-                        // 計算這個visual_line在整個邏輯行中的視覺起始位置
-                        let visual_line_start: usize = layout
-                            .visual_lines
-                            .iter()
-                            .take(visual_idx)
-                            .map(|line| visual_width(line))
-                            .sum();
-
-                        let chars: Vec<char> = visual_line.chars().collect();
-                        let mut current_visual_pos = visual_line_start;
-
-                        for &ch in chars.iter() {
-                            let ch_width = UnicodeWidthChar::width(ch).unwrap_or(1);
-
-                            // 判斷這個字符是否在選擇範圍內
-                            let is_selected = if file_row == start_row && file_row == end_row {
-                                // 選擇在同一行
-                                current_visual_pos >= start_col && current_visual_pos < end_col
-                            } else if file_row == start_row {
-                                // 選擇起始行
-                                current_visual_pos >= start_col
-                            } else if file_row == end_row {
-                                // 選擇結束行
-                                current_visual_pos < end_col
+                if screen_row >= text_rows {
+                    break;
+                }
+                if visual_idx > 0 && self.show_line_numbers {
+                    let label = match self.wrap_gutter {
+                        WrapGutter::Blank => None,
+                        WrapGutter::Dot => Some("·".to_string()),
+                        WrapGutter::Number => {
+                            // 寬度不夠時改顯示 ·
+                            let number = self.format_line_number(
+                                visual_row + visual_idx + self.line_number_start,
+                            );
+                            Some(if number.len() < line_num_width {
+                                number
                             } else {
-                                // 選擇中間的行，全選
-                                true
-                            };
-
-                            if is_selected {
-                                queue!(stdout, style::SetAttribute(Attribute::Reverse))?;
-                            }
-                            queue!(stdout, style::Print(ch))?;
-                            if is_selected {
-                                queue!(stdout, style::SetAttribute(Attribute::NoReverse))?;
-                            }
-
-                            current_visual_pos += ch_width;
+                                "·".to_string()
+                            })
                         }
-                    } else {
-                        // 這一行沒有選擇，直接打印
-                        queue!(stdout, style::Print(visual_line))?;
+                    };
+                    if let Some(label) = label {
+                        let label = format!("{:>width$}", label, width = line_num_width - 1);
+                        grid.put_str(0, screen_row, &label, dim);
                     }
-                } else {
-                    // 沒有選擇
-                    if use_syntax_highlight {
-                        // 使用語法高亮
-                        #[cfg(feature = "syntax-highlighting")]
-                        if let Some(highlighted) = highlighted_lines.and_then(|h| h.get(&file_row))
-                        {
-                            // 輸出高亮後的文字（包含 ANSI 色碼）
-                            queue!(stdout, style::Print(highlighted))?;
-                        } else {
-                            // 降級為純文字
-                            queue!(stdout, style::Print(visual_line))?;
-                        }
+                }
 
-                        #[cfg(not(feature = "syntax-highlighting"))]
-                        queue!(stdout, style::Print(visual_line))?;
-                    } else {
-                        // 純文字渲染
-                        queue!(stdout, style::Print(visual_line))?;
-                    }
+                // 欄位參考線只畫在每個邏輯行的第一個視覺行，換行後的部分已超過該欄
+                let guides: &[usize] = if visual_idx == 0 {
+                    &self.color_columns
+                } else {
+                    &[]
+                };
+                let background = |x: usize| {
+                    guides
+                        .contains(&(x + 1 - text_x))
+                        .then_some(self.color_column_color)
+                        .or(line_background)
+                };
+
+                let mut x = text_x;
+                for ch in visual_line.chars() {
+                    let mut style = styles.get(visual_col).copied().unwrap_or_default();
+                    style.background = style.background.or(background(x));
+                    x = grid.put_char(x, screen_row, ch, style);
+                    visual_col += char_width(ch);
                 }
 
-                queue!(
-                    stdout,
-                    crossterm::terminal::Clear(crossterm::terminal::ClearType::UntilNewLine)
-                )?;
+                // 行尾之後：光標行填滿到畫面右側，參考線只填到最後一條參考線
+                let fill_end = if line_background.is_some() {
+                    self.screen_cols
+                } else {
+                    guides.last().map_or(0, |&column| text_x + column)
+                };
+                while x < fill_end.min(self.screen_cols) {
+                    let style = SpanStyle {
+                        background: background(x),
+                        ..SpanStyle::default()
+                    };
+                    x = grid.put_char(x, screen_row, ' ', style);
+                }
             }
 
             screen_row += 1;
             file_row += 1;
+            visual_row += layout.visual_height.max(1);
         }
 
         // 畫底部的 ~ 行
-        while screen_row < self.screen_rows {
-            queue!(stdout, cursor::MoveTo(0, screen_row as u16))?;
-            queue!(stdout, style::SetForegroundColor(Color::DarkGrey))?;
-            queue!(stdout, style::Print("~"))?;
-            queue!(stdout, style::ResetColor)?;
-            queue!(
-                stdout,
-                crossterm::terminal::Clear(crossterm::terminal::ClearType::UntilNewLine)
-            )?;
+        while screen_row < text_rows {
+            grid.put_char(0, screen_row, '~', dim);
             screen_row += 1;
         }
 
-        self.render_status_bar(buffer, selection.is_some(), message, cursor)?;
+        // 外層標題行蓋在第一個文字行上；光標在那一行時不顯示，避免遮住光標
+        let cursor_on_top = cursor.row == self.offset_row && cursor.visual_line_index == 0;
+        if self.sticky_header && !cursor_on_top {
+            if let Some(header_row) = self.sticky_header_row(buffer) {
+                self.render_sticky_header(&mut grid, buffer, header_row, ruler_offset, cursor);
+            }
+        }
+
+        if let Some(error) = &self.error_message {
+            self.render_error_row(&mut grid, text_rows, error);
+        }
+
+        self.render_status_bar(&mut grid, buffer, selection.is_some(), message, cursor);
+
+        // 整幀輸出期間隱藏游標，最後只在最終位置顯示一次
+        let output = grid.diff(self.previous_frame.as_ref());
+        self.previous_frame = Some(grid);
+        backend.begin_frame()?;
+        backend.write_all(output.as_bytes())?;
 
         // 移動終端光標到當前cursor位置
-        let ruler_offset = if has_debug_ruler { 1 } else { 0 };
         let (cursor_x, cursor_y) = self.get_cursor_visual_position(cursor, buffer);
         let cursor_y = cursor_y + ruler_offset;
-        execute!(stdout, cursor::MoveTo(cursor_x as u16, cursor_y as u16))?;
-
-        execute!(stdout, cursor::Show)?;
-        stdout.flush()?;
+        self.cursor_screen = (cursor_x as u16, cursor_y as u16);
+        backend.end_frame(Some(self.cursor_screen))?;
         Ok(())
     }
 
-    pub fn scroll_if_needed(
-        &mut self,
-        cursor: &Cursor,
+    /// 畫面頂端的內容所屬的外層標題行：往上找第一個縮排比頂端第一個非空白行小的行
+    ///
+    /// 頂端的行沒有縮排、標題行仍在畫面中，或往上 [`MAX_STICKY_SCAN`] 行內找不到時返回 None
+    pub fn sticky_header_row(&self, buffer: &RopeBuffer) -> Option<usize> {
+        let indent_of = |row: usize| -> Option<usize> {
+            let line = buffer.line(row)?.to_string();
+            let line = line.trim_end_matches(['\n', '\r']);
+            let indent = line.len() - line.trim_start().len();
+            (indent < line.len()).then(|| self.logical_col_to_visual_col(line, indent))
+        };
+
+        let top = (self.offset_row..buffer.line_count().min(self.offset_row + MAX_STICKY_SCAN))
+            .find_map(indent_of)?;
+        (self.offset_row.saturating_sub(MAX_STICKY_SCAN)..self.offset_row)
+            .rev()
+            .find(|&row| indent_of(row).is_some_and(|indent| indent < top))
+    }
+
+    /// 在第一個文字行畫出標題行（行號與截斷到一行的內容）
+    fn render_sticky_header(
+        &self,
+        grid: &mut Grid,
         buffer: &RopeBuffer,
-        has_debug_ruler: bool,
+        row: usize,
+        screen_row: usize,
+        cursor: &Cursor,
     ) {
+        let style = self.theme.sticky_header;
+        let mut x = 0;
+        if self.show_line_numbers {
+            let width = self.calculate_line_number_width(buffer);
+            let number = self.line_number_label(row, cursor.row);
+            let label = format!("{:>width$} ", number, width = width - 1);
+            x = grid.put_str(0, screen_row, &label, self.theme.line_number.patch(style));
+        }
+        let line = buffer.line(row).map(|l| l.to_string()).unwrap_or_default();
+        let line = line.trim_end_matches(['\n', '\r']);
+        for ch in line.chars() {
+            let ch = if ch == '\t' { ' ' } else { ch };
+            if x + char_width(ch) > self.screen_cols {
+                break;
+            }
+            x = grid.put_char(x, screen_row, ch, style);
+        }
+        while x < self.screen_cols {
+            x = grid.put_char(x, screen_row, ' ', style);
+        }
+    }
+
+    /// 上一次渲染時終端光標的螢幕位置 (x, y)
+    #[allow(dead_code)]
+    pub fn cursor_screen_position(&self) -> (u16, u16) {
+        self.cursor_screen
+    }
+
+    /// 計算邏輯行每個視覺欄位的樣式
+    ///
+    /// 以語法高亮為底，疊上行內範圍的樣式（範圍位置為邏輯欄位），最後疊上選擇範圍的樣式
+    fn line_styles(
+        &self,
+        file_row: usize,
+        layout: &LineLayout,
+        syntax: Option<&StyledLine>,
+        selection: Option<((usize, usize), (usize, usize))>,
+    ) -> Vec<SpanStyle> {
+        let to_visual = |col: usize| {
+            layout
+                .logical_to_visual
+                .get(col)
+                .or(layout.logical_to_visual.last())
+                .copied()
+                .unwrap_or(0)
+        };
+        let width = to_visual(usize::MAX);
+        let mut styles = vec![SpanStyle::default(); width];
+
+        if let Some(syntax) = syntax {
+            for (col, (_, style)) in syntax.chars().enumerate() {
+                if col + 1 >= layout.logical_to_visual.len() {
+                    break;
+                }
+                styles[to_visual(col)..to_visual(col + 1)].fill(style);
+            }
+        }
+
+        // 替代符號以固定的顏色顯示，與一般文字區分
+        for &(start, end, kind) in &layout.invisibles {
+            for style in styles.iter_mut().take(end).skip(start) {
+                *style = style.patch(kind.style());
+            }
+        }
+
+        if let Some(spans) = self.line_spans.get(&file_row) {
+            let visual_spans: Vec<(usize, usize, SpanStyle)> = spans
+                .iter()
+                .map(|span| (to_visual(span.start), to_visual(span.end), span.style))
+                .collect();
+            for (col, style) in styles.iter_mut().enumerate() {
+                if let Some(&(_, _, overlay)) = visual_spans
+                    .iter()
+                    .find(|(start, end, _)| *start <= col && col < *end)
+                {
+                    *style = style.patch(overlay);
+                }
+            }
+        }
+
+        if let Some(((start_row, start_col), (end_row, end_col))) = selection {
+            if (start_row..=end_row).contains(&file_row) {
+                let start = if file_row == start_row { start_col } else { 0 };
+                let end = if file_row == end_row { end_col } else { width };
+                for style in styles.iter_mut().take(end).skip(start) {
+                    *style = style.patch(self.theme.selection);
+                }
+            }
+        }
+
+        styles
+    }
+
+    pub fn scroll_if_needed(&mut self, cursor: &Cursor, buffer: &RopeBuffer, has_ruler: bool) {
         // 向上滾動
         if cursor.row < self.offset_row {
             self.offset_row = cursor.row;
-            self.invalidate_cache();
+            self.reset_layout_cache();
             return;
         }
 
-        let effective_rows = self.get_effective_screen_rows(has_debug_ruler);
+        let effective_rows = self.get_effective_screen_rows(has_ruler);
 
         // 大幅跳轉優化：如果跳轉距離超過 3 個螢幕高度，直接設置 offset_row
         // 這避免了計算中間所有行的視覺高度，大幅提升大文件跳轉性能
@@ -407,70 +973,80 @@ impl View {
             // 將 offset_row 設置為讓光標位於螢幕中間偏上的位置
             // 這樣用戶可以看到光標上下文，體驗更好
             self.offset_row = cursor.row.saturating_sub(effective_rows / 3);
-            self.invalidate_cache();
+            self.reset_layout_cache();
             return;
         }
 
         // 計算目前 offset_row ~ cursor.row 的視覺高度
-        let mut visual_offset = 0;
-        let available_width = self.get_available_width(buffer);
-
-        for row in self.offset_row..=cursor.row {
-            let cache_index = row.saturating_sub(self.offset_row);
-            if let Some(Some(layout)) = self.line_layout_cache.get(cache_index) {
-                visual_offset += layout.visual_height;
-            } else if let Some(layout) = LineLayout::new(buffer, row, available_width) {
-                visual_offset += layout.visual_height;
-                if cache_index < self.line_layout_cache.len() {
-                    self.line_layout_cache[cache_index] = Some(layout);
-                }
-            }
-        }
+        let visual_offset = self.visual_rows_between(buffer, self.offset_row, cursor.row + 1);
 
         // 如果沒超出螢幕，就不用動
         if visual_offset < effective_rows {
             return;
         }
 
-        // 向下推 offset_row，每次扣掉最上面那一行的視覺高度
-        while self.offset_row < cursor.row && visual_offset >= effective_rows {
-            let top_layout_opt = self
-                .line_layout_cache
-                .first()
-                .and_then(|l| l.as_ref())
-                .cloned();
+        // 向下推 offset_row：找出第一個讓 offset_row ~ cursor.row 的高度小於螢幕行數的行
+        // （光標行本身比螢幕高時停在光標行）
+        let index = &self.visual_index;
+        let limit = index.rows_before(cursor.row + 1) - effective_rows;
+        let candidates = &index.prefix[self.offset_row - index.base..=cursor.row - index.base];
+        let new_offset =
+            (self.offset_row + candidates.partition_point(|&rows| rows <= limit)).min(cursor.row);
 
-            if let Some(layout) = top_layout_opt {
-                visual_offset = visual_offset.saturating_sub(layout.visual_height);
-            } else if let Some(layout) = LineLayout::new(buffer, self.offset_row, available_width) {
-                visual_offset = visual_offset.saturating_sub(layout.visual_height);
-                if !self.line_layout_cache.is_empty() {
-                    self.line_layout_cache[0] = Some(layout);
-                }
-            }
+        self.shift_layout_cache(new_offset - self.offset_row);
+        self.offset_row = new_offset;
+    }
 
-            self.offset_row += 1;
+    /// 指定邏輯行的視覺行數（優先使用行快取）
+    fn row_height(&self, buffer: &RopeBuffer, row: usize, available_width: usize) -> usize {
+        let cache_index = row.wrapping_sub(self.offset_row);
+        if let Some(Some(layout)) = self.line_layout_cache.get(cache_index) {
+            return layout.visual_height;
+        }
+        LineLayout::new(buffer, row, available_width, self.show_invisibles)
+            .map_or(1, |layout| layout.visual_height)
+    }
 
-            if !self.line_layout_cache.is_empty() {
-                self.line_layout_cache.remove(0);
-                self.line_layout_cache.push(None);
-            }
+    /// 確保視覺行索引涵蓋 `start..end` 行：不足時向下延伸，
+    /// 起點不在索引範圍內或寬度改變時從 `start` 重建
+    fn ensure_visual_index(&mut self, buffer: &RopeBuffer, start: usize, end: usize) {
+        let available_width = self.get_available_width(buffer);
+        let index = &mut self.visual_index;
+        if index.prefix.is_empty()
+            || index.width != available_width
+            || start < index.base
+            || start > index.end()
+        {
+            index.base = start;
+            index.width = available_width;
+            index.prefix = vec![0];
+        }
+
+        while self.visual_index.end() < end {
+            let row = self.visual_index.end();
+            let height = self.row_height(buffer, row, available_width);
+            let total = self.visual_index.rows_before(row) + height;
+            self.visual_index.prefix.push(total);
         }
     }
 
+    /// `start..end` 邏輯行的視覺行總數
+    fn visual_rows_between(&mut self, buffer: &RopeBuffer, start: usize, end: usize) -> usize {
+        if end <= start {
+            return 0;
+        }
+        self.ensure_visual_index(buffer, start, end);
+        self.visual_index.rows_before(end) - self.visual_index.rows_before(start)
+    }
+
     fn render_status_bar(
         &self,
+        grid: &mut Grid,
         buffer: &RopeBuffer,
         selection_mode: bool,
         message: Option<&str>,
         cursor: &Cursor,
-    ) -> Result<()> {
-        let mut stdout = io::stdout();
-        queue!(stdout, cursor::MoveTo(0, self.screen_rows as u16))?;
-
-        queue!(stdout, style::SetBackgroundColor(Color::DarkGrey))?;
-        queue!(stdout, style::SetForegroundColor(Color::White))?;
-
+    ) {
         let modified = if buffer.is_modified() {
             " [modified]"
         } else {
@@ -484,16 +1060,23 @@ impl View {
             ""
         };
 
+        let hint = self
+            .status_hint
+            .as_deref()
+            .map(|h| format!("  {}", h))
+            .unwrap_or_default();
+
         let status = if let Some(msg) = message {
             format!(" {}{}{}  - {}", filename, modified, mode_indicator, msg)
         } else {
             format!(
-                " {}{}{}  Line {}/{}  Ctrl+W:Save Ctrl+Q:Quit",
+                " {}{}{}  Line {}/{}{}  Ctrl+W:Save Ctrl+Q:Quit",
                 filename,
                 modified,
                 mode_indicator,
                 cursor.row + 1,
-                buffer.line_count()
+                buffer.line_count(),
+                hint
             )
         };
 
@@ -504,7 +1087,7 @@ impl View {
             let mut result = String::new();
             let mut current_width = 0;
             for ch in status.chars() {
-                let ch_width = UnicodeWidthChar::width(ch).unwrap_or(1);
+                let ch_width = char_width(ch);
                 if current_width + ch_width > self.screen_cols {
                     break;
                 }
@@ -514,20 +1097,100 @@ impl View {
             result
         };
 
-        queue!(stdout, style::Print(status))?;
-        queue!(stdout, style::ResetColor)?;
+        let style = if message.is_some() {
+            self.theme.message
+        } else {
+            self.theme.status_bar
+        };
+        grid.put_str(0, self.screen_rows, &status, style);
+    }
 
-        Ok(())
+    /// 在 `row` 輸出填滿整行的錯誤訊息列
+    fn render_error_row(&self, grid: &mut Grid, row: usize, error: &str) {
+        let style = self.theme.error;
+        let x = grid.put_str(0, row, &format!(" {}", error), style);
+        for x in x..self.screen_cols {
+            grid.put_char(x, row, ' ', style);
+        }
+    }
+
+    /// 設定錯誤訊息（None 表示清除）；有錯誤訊息時文字區少一行
+    #[allow(dead_code)]
+    pub fn set_error_message(&mut self, error: Option<String>) {
+        self.error_message = error;
+    }
+
+    /// 文字區（含欄位尺規）的螢幕行數：扣除錯誤訊息列
+    fn text_rows(&self) -> usize {
+        let error_rows = usize::from(self.error_message.is_some());
+        self.screen_rows.saturating_sub(error_rows)
+    }
+
+    /// 設定狀態列在行號後顯示的額外資訊
+    #[allow(dead_code)]
+    pub fn set_status_hint(&mut self, hint: Option<String>) {
+        self.status_hint = hint;
+    }
+
+    /// 設定行內樣式範圍（取代先前設定的所有範圍）
+    #[allow(dead_code)]
+    pub fn set_line_spans(&mut self, spans: HashMap<usize, Vec<LineSpan>>) {
+        self.line_spans = spans;
+    }
+
+    /// 設定行號欄的修改標記
+    #[allow(dead_code)]
+    pub fn set_line_markers(&mut self, markers: HashMap<usize, LineMarker>) {
+        self.line_markers = markers;
+    }
+
+    /// 設定行號欄的檢查結果標記
+    #[allow(dead_code)]
+    pub fn set_diagnostic_markers(&mut self, markers: HashMap<usize, DiagnosticMarker>) {
+        self.diagnostic_markers = markers;
     }
 
     pub fn toggle_line_numbers(&mut self) {
         self.show_line_numbers = !self.show_line_numbers;
     }
 
+    pub fn set_show_invisibles(&mut self, show: bool) {
+        self.show_invisibles = show;
+        // 替代符號改變行寬，快取的折行結果失效
+        self.invalidate_cache();
+    }
+
+    /// 設定折行後續視覺行的行號欄；顯示視覺行號時行號欄較寬，折行結果失效
+    #[allow(dead_code)]
+    pub fn set_wrap_gutter(&mut self, wrap_gutter: WrapGutter) {
+        self.wrap_gutter = wrap_gutter;
+        self.invalidate_cache();
+    }
+
+    /// 邏輯行 `row` 的行號文字（依顯示方式、起始行號與進位）
+    fn line_number_label(&self, row: usize, cursor_row: usize) -> String {
+        let number = self
+            .line_number_mode
+            .number(row, cursor_row, self.line_number_start);
+        self.format_line_number(number)
+    }
+
+    /// 以目前的進位（`:set hexnumber`）格式化行號
+    fn format_line_number(&self, number: usize) -> String {
+        if self.hex_line_numbers {
+            format!("{:x}", number)
+        } else {
+            number.to_string()
+        }
+    }
+
     /// 計算行號寬度（包含右側空格）
     fn calculate_line_number_width(&self, buffer: &RopeBuffer) -> usize {
         if self.show_line_numbers {
-            buffer.line_count().to_string().len() + 1
+            // 視覺行號比邏輯行號大，多留一位數
+            let extra = usize::from(self.wrap_gutter == WrapGutter::Number);
+            let last = buffer.line_count().saturating_sub(1) + self.line_number_start;
+            self.format_line_number(last).len() + 1 + extra
         } else {
             0
         }
@@ -560,29 +1223,22 @@ impl View {
             line.pop();
         }
 
-        let (displayed_line, _) = expand_tabs_and_build_map(&line);
-        wrap_line(&displayed_line, available_width)
+        let expanded = expand_tabs_and_build_map(&line, self.show_invisibles);
+        wrap_line(&expanded.displayed, available_width)
     }
 
     /// 將邏輯列轉換為視覺列（考慮 Tab 展開和字符寬度）
     pub fn logical_col_to_visual_col(&self, line: &str, logical_col: usize) -> usize {
-        // 這個函式目前只拿到一行字串，不知道 row，無法用 cache。
-        // 保留原來的行為：直接掃一遍。
-        let mut visual_col = 0;
-        for (idx, ch) in line.chars().enumerate() {
-            if idx >= logical_col {
-                break;
-            }
-            if ch == '\t' {
-                visual_col += TAB_WIDTH;
-            } else {
-                visual_col += UnicodeWidthChar::width(ch).unwrap_or(1);
-            }
-        }
-        visual_col
+        // 這個函式目前只拿到一行字串，不知道 row，無法用 cache，直接展開一次
+        let line = line.trim_end_matches(['\n', '\r']);
+        let map = expand_tabs_and_build_map(line, self.show_invisibles).logical_to_visual;
+        map[logical_col.min(map.len() - 1)]
     }
 
     /// 從視覺行索引和視覺列轉換為邏輯列
+    ///
+    /// 視覺列超過該視覺行的結尾時（包括寬字元換到下一行後留下的空格），
+    /// 除了最後一個視覺行停在行尾外，都停在這個視覺行的最後一個字元上，不會跑到下一個視覺行
     pub fn visual_to_logical_col(
         &self,
         buffer: &RopeBuffer,
@@ -590,148 +1246,102 @@ impl View {
         visual_line_index: usize,
         visual_col: usize,
     ) -> usize {
-        // 優先使用快取（如果該行目前在視窗 cache 內）
+        // 優先使用快取（如果該行目前在視窗 cache 內），不在 cache 範圍時重新展開
         let cache_index = row.saturating_sub(self.offset_row);
-        if let Some(Some(layout)) = self.line_layout_cache.get(cache_index) {
-            if visual_line_index >= layout.visual_lines.len() {
-                return 0;
-            }
-
-            // 計算前面視覺行的總視覺寬度
-            let mut accumulated_width = 0;
-            for line in layout.visual_lines.iter().take(visual_line_index) {
-                accumulated_width += visual_width(line);
-            }
-
-            // 加上當前視覺行內的列位置
-            let col_in_visual =
-                visual_col.min(visual_width(&layout.visual_lines[visual_line_index]));
-            let visual_col_total = accumulated_width + col_in_visual;
-
-            // 在 logical_to_visual 中尋找「視覺座標 >= visual_col_total」的最小 logical_col
-            let mut logical_col = 0;
-            for (idx, &vcol) in layout.logical_to_visual.iter().enumerate() {
-                if vcol > visual_col_total {
-                    break;
-                }
-                logical_col = idx;
+        let computed;
+        let (visual_lines, logical_to_visual) = match self
+            .line_layout_cache
+            .get(cache_index)
+            .and_then(|l| l.as_ref())
+        {
+            Some(layout) => (&layout.visual_lines, &layout.logical_to_visual),
+            None => {
+                let Some(line) = buffer.line(row) else {
+                    return 0;
+                };
+                let line = line.to_string();
+                let line = line.trim_end_matches(['\n', '\r']);
+                let expanded = expand_tabs_and_build_map(line, self.show_invisibles);
+                computed = (
+                    wrap_line(&expanded.displayed, self.get_available_width(buffer)),
+                    expanded.logical_to_visual,
+                );
+                (&computed.0, &computed.1)
             }
-            return logical_col;
-        }
-
-        // 若不在 cache 範圍，退回原本的計算方式（慢但安全）
-        let visual_lines = self.calculate_visual_lines_for_row(buffer, row);
-
+        };
         if visual_line_index >= visual_lines.len() {
             return 0;
         }
 
         // 計算前面視覺行的總視覺寬度
-        let mut accumulated_width = 0;
-        for line in visual_lines.iter().take(visual_line_index) {
-            accumulated_width += visual_width(line);
-        }
-
-        let col_in_visual = visual_col.min(visual_width(&visual_lines[visual_line_index]));
-        let visual_col_total = accumulated_width + col_in_visual;
-
-        if let Some(line) = buffer.line(row) {
-            let mut line_str = line.to_string();
-            while matches!(line_str.chars().last(), Some('\n' | '\r')) {
-                line_str.pop();
-            }
-
-            let mut logical_col = 0;
-            let mut current_visual = 0;
-
-            for ch in line_str.chars() {
-                if current_visual >= visual_col_total {
-                    break;
-                }
-
-                if ch == '\t' {
-                    current_visual += TAB_WIDTH;
-                } else {
-                    current_visual += UnicodeWidthChar::width(ch).unwrap_or(1);
-                }
-
-                logical_col += 1;
-            }
-
-            logical_col
+        let accumulated_width: usize = visual_lines
+            .iter()
+            .take(visual_line_index)
+            .map(|line| visual_width(line))
+            .sum();
+        let row_width = visual_width(&visual_lines[visual_line_index]);
+        let last_col = if visual_line_index + 1 == visual_lines.len() {
+            row_width
         } else {
-            0
-        }
+            row_width.saturating_sub(1)
+        };
+        logical_col_at(
+            logical_to_visual,
+            accumulated_width + visual_col.min(last_col),
+        )
     }
 
-    /// 實際可用於顯示文本的螢幕行數（扣除 debug 標尺）
-    pub fn get_effective_screen_rows(&self, has_debug_ruler: bool) -> usize {
-        if has_debug_ruler {
-            self.screen_rows.saturating_sub(1)
-        } else {
-            self.screen_rows
-        }
+    /// 實際可用於顯示文本的螢幕行數（扣除分頁列與欄位尺規）
+    pub fn get_effective_screen_rows(&self, has_ruler: bool) -> usize {
+        let top_rows = self.tab_line_rows() + usize::from(has_ruler || self.show_ruler);
+        self.text_rows().saturating_sub(top_rows)
+    }
+
+    /// 分頁列佔用的螢幕行數
+    pub fn tab_line_rows(&self) -> usize {
+        usize::from(self.tab_line.is_some())
     }
 
     /// 計算光標在屏幕上的視覺 Y 位置（從 offset_row 開始計算）
     ///
     /// 返回：屏幕上的視覺行號（0-based）
-    pub fn get_cursor_screen_y(&self, cursor: &Cursor, buffer: &RopeBuffer) -> usize {
-        let mut screen_y = 0;
-        let available_width = self.get_available_width(buffer);
-
-        // 從 offset_row 累計到 cursor.row 的視覺行數
-        for row in self.offset_row..cursor.row {
-            let cache_index = row.saturating_sub(self.offset_row);
-            let height = if let Some(Some(layout)) = self.line_layout_cache.get(cache_index) {
-                layout.visual_height
-            } else if let Some(layout) = LineLayout::new(buffer, row, available_width) {
-                layout.visual_height
-            } else {
-                1
-            };
-            screen_y += height;
-        }
-
-        // 加上光標在當前行內的視覺行偏移
-        screen_y + cursor.visual_line_index
+    pub fn get_cursor_screen_y(&mut self, cursor: &Cursor, buffer: &RopeBuffer) -> usize {
+        // 從 offset_row 累計到 cursor.row 的視覺行數，加上光標在當前行內的視覺行偏移
+        self.visual_rows_between(buffer, self.offset_row, cursor.row) + cursor.visual_line_index
     }
 
     /// 根據屏幕 Y 位置找到對應的邏輯行和視覺行索引
     ///
     /// 返回：(邏輯行號, 視覺行索引)
     pub fn get_row_at_screen_y(
-        &self,
+        &mut self,
         target_screen_y: usize,
         buffer: &RopeBuffer,
     ) -> (usize, usize) {
-        let mut screen_y = 0;
-        let mut row = self.offset_row;
-        let available_width = self.get_available_width(buffer);
         let max_row = buffer.line_count().saturating_sub(1);
+        if self.offset_row > max_row {
+            return (max_row, 0);
+        }
 
-        while row <= max_row {
-            let cache_index = row.saturating_sub(self.offset_row);
-            let height = if let Some(Some(layout)) = self.line_layout_cache.get(cache_index) {
-                layout.visual_height
-            } else if let Some(layout) = LineLayout::new(buffer, row, available_width) {
-                layout.visual_height
-            } else {
-                1
-            };
-
-            if screen_y + height > target_screen_y {
-                // 目標位置在這一行內
-                let visual_line_index = target_screen_y.saturating_sub(screen_y);
-                return (row, visual_line_index.min(height.saturating_sub(1)));
-            }
-
-            screen_y += height;
-            row += 1;
+        // 每行至少一個視覺行，目標位置一定在 offset_row 之後 target_screen_y + 1 行以內
+        let end = (self.offset_row + target_screen_y + 1).min(max_row + 1);
+        self.ensure_visual_index(buffer, self.offset_row, end);
+
+        let index = &self.visual_index;
+        let top = index.rows_before(self.offset_row);
+        let rows = &index.prefix[self.offset_row - index.base + 1..=end - index.base];
+        let found = rows.partition_point(|&rows| rows - top <= target_screen_y);
+        if found == rows.len() {
+            // 超出文件末尾，返回最後一行
+            return (max_row, 0);
         }
 
-        // 超出文件末尾，返回最後一行
-        (max_row, 0)
+        // 目標位置在這一行內
+        let row = self.offset_row + found;
+        let screen_y = index.rows_before(row) - top;
+        let height = index.rows_before(row + 1) - index.rows_before(row);
+        let visual_line_index = target_screen_y - screen_y;
+        (row, visual_line_index.min(height.saturating_sub(1)))
     }
 
     /// 翻頁：滾動 offset_row 並返回新的光標位置
@@ -761,12 +1371,13 @@ impl View {
 
             // 累計足夠的視覺行來滾動一頁
             while new_offset <= max_row && visual_count < effective_rows {
-                let height =
-                    if let Some(layout) = LineLayout::new(buffer, new_offset, available_width) {
-                        layout.visual_height
-                    } else {
-                        1
-                    };
+                let height = if let Some(layout) =
+                    LineLayout::new(buffer, new_offset, available_width, self.show_invisibles)
+                {
+                    layout.visual_height
+                } else {
+                    1
+                };
                 visual_count += height;
                 new_offset += 1;
             }
@@ -776,13 +1387,16 @@ impl View {
             let mut visual_from_end = 0;
             while last_page_offset > 0 && visual_from_end < effective_rows {
                 last_page_offset -= 1;
-                let height =
-                    if let Some(layout) = LineLayout::new(buffer, last_page_offset, available_width)
-                    {
-                        layout.visual_height
-                    } else {
-                        1
-                    };
+                let height = if let Some(layout) = LineLayout::new(
+                    buffer,
+                    last_page_offset,
+                    available_width,
+                    self.show_invisibles,
+                ) {
+                    layout.visual_height
+                } else {
+                    1
+                };
                 visual_from_end += height;
             }
             if visual_from_end < effective_rows {
@@ -813,65 +1427,110 @@ impl View {
             // 累計足夠的視覺行來滾動一頁
             while new_offset > 0 && visual_count < effective_rows {
                 new_offset -= 1;
-                let height =
-                    if let Some(layout) = LineLayout::new(buffer, new_offset, available_width) {
-                        layout.visual_height
-                    } else {
-                        1
-                    };
+                let height = if let Some(layout) =
+                    LineLayout::new(buffer, new_offset, available_width, self.show_invisibles)
+                {
+                    layout.visual_height
+                } else {
+                    1
+                };
                 visual_count += height;
             }
 
             self.offset_row = new_offset;
         }
 
-        self.invalidate_cache();
+        self.reset_layout_cache();
 
         // 根據保持的屏幕 Y 位置計算新的光標行
         self.get_row_at_screen_y(cursor_screen_y, buffer)
     }
 
+    /// 捲動畫面 `delta` 行（正數向下），不移動光標；返回是否有捲動
+    ///
+    /// 最多捲到最後一行位於畫面頂端
+    pub fn scroll_lines(&mut self, delta: isize, buffer: &RopeBuffer) -> bool {
+        let max_row = buffer.line_count().saturating_sub(1);
+        let new_offset = self.offset_row.saturating_add_signed(delta).min(max_row);
+        if new_offset == self.offset_row {
+            return false;
+        }
+        if new_offset > self.offset_row {
+            self.shift_layout_cache(new_offset - self.offset_row);
+        } else {
+            self.reset_layout_cache();
+        }
+        self.offset_row = new_offset;
+        true
+    }
+
+    /// 捲動畫面讓 `row` 位於畫面中間（接近檔首時停在第一行）
+    pub fn center_on_row(&mut self, row: usize, buffer: &RopeBuffer, effective_rows: usize) {
+        let half = effective_rows / 2;
+        // 每行至少一個視覺行，新的頂端一定在 row - half 之後
+        let start = row.saturating_sub(half);
+        self.ensure_visual_index(buffer, start, row + 1);
+        let index = &self.visual_index;
+        let new_offset = (start..=row)
+            .find(|&offset| index.rows_before(row) - index.rows_before(offset) <= half)
+            .unwrap_or(row);
+        if new_offset != self.offset_row {
+            self.offset_row = new_offset;
+            self.reset_layout_cache();
+        }
+    }
+
+    /// 文字區第 `text_y` 個螢幕行、終端第 `x` 欄對應的緩衝區位置 (行, 列)
+    ///
+    /// 點在行號上時為該視覺行的開頭，超過行尾時為行尾
+    pub fn position_at_screen(
+        &mut self,
+        x: usize,
+        text_y: usize,
+        buffer: &RopeBuffer,
+    ) -> (usize, usize) {
+        let (row, visual_line_index) = self.get_row_at_screen_y(text_y, buffer);
+        let visual_col = x.saturating_sub(self.calculate_line_number_width(buffer));
+        let col = self.visual_to_logical_col(buffer, row, visual_line_index, visual_col);
+        // 點在組合字元或 emoji 序列中間時放在叢集開頭
+        let line = buffer.get_line_content(row);
+        (row, crate::utils::floor_grapheme_boundary(&line, col))
+    }
+
+    /// 畫面上完整顯示的第一行與最後一行（邏輯行）
+    pub fn visible_row_range(
+        &mut self,
+        buffer: &RopeBuffer,
+        effective_rows: usize,
+    ) -> (usize, usize) {
+        let (row, visual_line_index) =
+            self.get_row_at_screen_y(effective_rows.saturating_sub(1), buffer);
+        // 最後一行只顯示了一部分時不算
+        let height = self.visual_rows_between(buffer, row, row + 1);
+        let last = if visual_line_index + 1 < height && row > self.offset_row {
+            row - 1
+        } else {
+            row
+        };
+        (self.offset_row, last)
+    }
+
     /// 獲取cursor的視覺位置（螢幕座標）
     pub fn get_cursor_visual_position(
-        &self,
+        &mut self,
         cursor: &Cursor,
         buffer: &RopeBuffer,
     ) -> (usize, usize) {
         let line_num_width = self.calculate_line_number_width(buffer);
 
         // 計算cursor所在的螢幕行
-        let mut screen_y = 0;
-        let mut file_row = self.offset_row;
-
-        while file_row < cursor.row && screen_y < self.screen_rows {
-            let cache_index = file_row.saturating_sub(self.offset_row);
-            let layout_opt = self
-                .line_layout_cache
-                .get(cache_index)
-                .and_then(|l| l.as_ref())
-                .cloned();
-
-            let layout = if let Some(layout) = layout_opt {
-                layout
-            } else {
-                LineLayout::new(buffer, file_row, self.get_available_width(buffer)).unwrap_or_else(
-                    || LineLayout {
-                        visual_lines: vec![String::new()],
-                        visual_height: 1,
-                        logical_to_visual: vec![0],
-                    },
-                )
-            };
-
-            screen_y += layout.visual_height;
-            file_row += 1;
-        }
+        let mut screen_y = self.visual_rows_between(buffer, self.offset_row, cursor.row);
 
         // 添加cursor行內的視覺行偏移
         screen_y += cursor.visual_line_index;
 
         // 如果超出螢幕，返回最後一行
-        let screen_y = screen_y.min(self.screen_rows.saturating_sub(1));
+        let screen_y = screen_y.min(self.text_rows().saturating_sub(1));
 
         // 計算cursor在視覺行內的x位置
         let visual_lines = self.calculate_visual_lines_for_row(buffer, cursor.row);
@@ -903,28 +1562,53 @@ impl View {
     }
 
     /// 渲染列標尺（顯示列位置個位數字）
-    fn render_column_ruler(&self, stdout: &mut io::Stdout, buffer: &RopeBuffer) -> Result<()> {
-        queue!(stdout, cursor::MoveTo(0, 0))?;
-        queue!(stdout, style::SetForegroundColor(Color::DarkGrey))?;
-
+    fn render_column_ruler(&self, grid: &mut Grid, buffer: &RopeBuffer, row: usize) {
         let line_num_width = self.calculate_line_number_width(buffer);
-
-        for _ in 0..line_num_width {
-            queue!(stdout, style::Print(" "))?;
-        }
-
         let available_cols = self
             .screen_cols
             .saturating_sub(line_num_width)
             .saturating_sub(1);
+        let style = self.theme.line_number;
+        // 標示的欄位以反白顯示，仍保留數字方便對照
+        let marked = SpanStyle {
+            foreground: Some(Color::Yellow),
+            reverse: true,
+            ..SpanStyle::default()
+        };
+
+        let mut x = line_num_width;
         for col in 0..available_cols {
-            let digit = col % 10;
-            queue!(stdout, style::Print(digit))?;
+            let digit = char::from(b'0' + (col % 10) as u8);
+            let style = if self.ruler_columns.contains(&(col + 1)) {
+                marked
+            } else {
+                style
+            };
+            x = grid.put_char(x, row, digit, style);
         }
+    }
+}
 
-        queue!(stdout, style::ResetColor)?;
-        Ok(())
+/// 欄位參考線的預設背景色（256 色中的暗紅色）
+pub const DEFAULT_COLOR_COLUMN_COLOR: Color = Color::AnsiValue(52);
+
+/// 解析欄位列表（以逗號分隔的 1-based 欄位，例如 `7,73`），用於尺規與欄位參考線
+#[allow(dead_code)]
+pub fn parse_columns(text: &str) -> Result<Vec<usize>> {
+    let mut columns = Vec::new();
+    for part in text
+        .split(',')
+        .map(str::trim)
+        .filter(|part| !part.is_empty())
+    {
+        match part.parse::<usize>() {
+            Ok(column) if column > 0 => columns.push(column),
+            _ => anyhow::bail!("Invalid column: {}", part),
+        }
     }
+    columns.sort_unstable();
+    columns.dedup();
+    Ok(columns)
 }
 
 /// 將行按可用寬度切分成多個視覺行（共用）
@@ -938,16 +1622,16 @@ fn wrap_line(line: &str, max_width: usize) -> Vec<String> {
     let mut current_width = 0;
 
     for ch in line.chars() {
-        let char_width = UnicodeWidthChar::width(ch).unwrap_or(1);
+        let ch_width = char_width(ch);
 
-        if current_width + char_width > max_width && !current_line.is_empty() {
+        if current_width + ch_width > max_width && !current_line.is_empty() {
             result.push(current_line);
             current_line = String::new();
             current_width = 0;
         }
 
         current_line.push(ch);
-        current_width += char_width;
+        current_width += ch_width;
     }
 
     if !current_line.is_empty() {
@@ -960,3 +1644,421 @@ fn wrap_line(line: &str, max_width: usize) -> Vec<String> {
 
     result
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tab_line_layout_and_hit_testing() {
+        let tabs = vec![
+            ("main.rs".to_string(), false),
+            ("editor.rs".to_string(), true),
+            ("view.rs".to_string(), false),
+        ];
+        // " main.rs " 9 欄、" editor.rs* " 12 欄、" view.rs " 9 欄
+        let tab_line = TabLine::new(tabs.clone(), 1);
+        assert_eq!(tab_line.tab_at(0, 80), Some(0));
+        assert_eq!(tab_line.tab_at(9, 80), Some(1));
+        assert_eq!(tab_line.tab_at(21, 80), Some(2));
+        assert_eq!(tab_line.tab_at(30, 80), None);
+
+        // 放不下時從讓目前的分頁可見的位置開始
+        let tab_line = TabLine::new(tabs, 2);
+        assert_eq!(tab_line.tab_at(0, 22), Some(1));
+        assert_eq!(tab_line.tab_at(12, 22), Some(2));
+
+        let mut grid = Grid::new(22, 1);
+        tab_line.render(&mut grid, 0, 22, &UiTheme::default());
+        let text: String = (0..22).map(|x| grid.cell(x, 0).unwrap().ch).collect();
+        assert_eq!(text, " editor.rs*  view.rs  ");
+        assert!(grid.cell(13, 0).unwrap().style.reverse);
+    }
+
+    #[test]
+    fn test_line_styles_layers_syntax_spans_and_selection() {
+        let mut view = View::with_size(40, 10);
+        let expanded = expand_tabs_and_build_map("a\tb", false);
+        let layout = LineLayout {
+            visual_lines: vec![expanded.displayed],
+            visual_height: 1,
+            logical_to_visual: expanded.logical_to_visual,
+            invisibles: expanded.invisibles,
+        };
+
+        let red = SpanStyle {
+            foreground: Some(Color::Red),
+            ..SpanStyle::default()
+        };
+        let green = SpanStyle {
+            foreground: Some(Color::Green),
+            ..SpanStyle::default()
+        };
+        let mut syntax = StyledLine::new();
+        syntax.push("a", red);
+        syntax.push("\t", SpanStyle::default());
+        syntax.push("b", green);
+
+        let underline = SpanStyle {
+            underline: true,
+            ..SpanStyle::default()
+        };
+        view.set_line_spans(HashMap::from([(
+            0,
+            vec![LineSpan {
+                start: 2,
+                end: 3,
+                style: underline,
+            }],
+        )]));
+
+        // Tab 展開為 4 格；範圍與語法樣式以視覺欄位對齊，選擇範圍加上反白
+        let styles = view.line_styles(0, &layout, Some(&syntax), Some(((0, 0), (0, 1))));
+        assert_eq!(styles.len(), 6);
+        assert_eq!(
+            styles[0],
+            SpanStyle {
+                reverse: true,
+                ..red
+            }
+        );
+        assert!(styles[1..5].iter().all(|s| *s == SpanStyle::default()));
+        assert_eq!(styles[5], green.patch(underline));
+
+        // 其他行不受選擇與範圍影響
+        let styles = view.line_styles(1, &layout, None, Some(((0, 0), (0, 1))));
+        assert!(styles.iter().all(|s| *s == SpanStyle::default()));
+    }
+
+    #[test]
+    fn test_visual_index_matches_layout() {
+        // 可用寬度 16（20 欄 - 行號 3 欄 - 1）
+        let mut buffer = RopeBuffer::new();
+        let text: String = (0..40)
+            .map(|i| format!("{}\n", "x".repeat(i % 25)))
+            .collect();
+        buffer.insert(0, &text);
+        let mut view = View::with_size(20, 9);
+        let width = view.get_available_width(&buffer);
+        let height = |buffer: &RopeBuffer, row: usize| {
+            LineLayout::new(buffer, row, width, false).map_or(1, |layout| layout.visual_height)
+        };
+
+        let mut cursor = Cursor::new();
+        for row in 0..40 {
+            cursor.row = row;
+            view.scroll_if_needed(&cursor, &buffer, false);
+            let expected: usize = (view.offset_row..row).map(|r| height(&buffer, r)).sum();
+            assert_eq!(view.get_cursor_screen_y(&cursor, &buffer), expected);
+            // 光標行可見，且再往上一行就放不下（光標下方保留一行）
+            assert!(expected + height(&buffer, row) < view.screen_rows || view.offset_row == row);
+            if view.offset_row > 0 {
+                assert!(
+                    expected + height(&buffer, view.offset_row - 1) + height(&buffer, row)
+                        >= view.screen_rows
+                );
+            }
+        }
+
+        // 編輯使行變長後，索引從修改的行重新計算
+        let row = view.offset_row;
+        let before = height(&buffer, row);
+        buffer.insert(buffer.line_to_char(row), &"y".repeat(30));
+        view.invalidate_line(row);
+        cursor.row = row + 1;
+        let expected = height(&buffer, row);
+        assert!(expected > before);
+        assert_eq!(view.get_cursor_screen_y(&cursor, &buffer), expected);
+        assert_eq!(view.get_row_at_screen_y(expected, &buffer), (row + 1, 0));
+        assert_eq!(view.get_row_at_screen_y(2, &buffer), (row, 2));
+    }
+
+    #[test]
+    fn test_cursor_line_background_covers_wrapped_rows() {
+        // 可用寬度 9（12 欄 - 行號 2 欄 - 1）：第 2 行換成兩個視覺行
+        let mut buffer = RopeBuffer::new();
+        buffer.insert(0, "one\nabcdefghijk\nthree\n");
+        let mut view = View::with_size(12, 6);
+        view.cursor_line = Some(Color::AnsiValue(236));
+        let mut cursor = Cursor::new();
+        cursor.row = 1;
+        let mut term = crate::backend::MemoryTerminal::new(12, 6);
+        #[cfg(feature = "syntax-highlighting")]
+        view.render(&mut term, &buffer, &cursor, None, None, None)
+            .unwrap();
+        #[cfg(not(feature = "syntax-highlighting"))]
+        view.render(&mut term, &buffer, &cursor, None, None)
+            .unwrap();
+
+        let grid = view.previous_frame.as_ref().unwrap();
+        let background = |x, y| grid.cell(x, y).unwrap().style.background;
+        for y in 1..=2 {
+            // 行號欄不標示，文字與行尾的空白都標示
+            assert_eq!(background(0, y), None);
+            assert_eq!(background(3, y), Some(Color::AnsiValue(236)));
+            assert_eq!(background(11, y), Some(Color::AnsiValue(236)));
+        }
+        assert_eq!(background(3, 0), None);
+        assert_eq!(background(3, 3), None);
+    }
+
+    #[test]
+    fn test_color_columns_mark_first_visual_row() {
+        let mut buffer = RopeBuffer::new();
+        buffer.insert(0, "ab\nabcdefghijk\n");
+        let mut view = View::with_size(12, 6);
+        view.color_columns = parse_columns("2,5").unwrap();
+        let mut term = crate::backend::MemoryTerminal::new(12, 6);
+        #[cfg(feature = "syntax-highlighting")]
+        view.render(&mut term, &buffer, &Cursor::new(), None, None, None)
+            .unwrap();
+        #[cfg(not(feature = "syntax-highlighting"))]
+        view.render(&mut term, &buffer, &Cursor::new(), None, None)
+            .unwrap();
+
+        let grid = view.previous_frame.as_ref().unwrap();
+        let background = |x, y| grid.cell(x, y).unwrap().style.background;
+        let guide = Some(DEFAULT_COLOR_COLUMN_COLOR);
+        // 文字從 x = 2 開始（行號 2 欄）：欄位 2 與 5 分別在 x = 3 與 6
+        for y in [0, 1, 3] {
+            assert_eq!(background(3, y), guide);
+            assert_eq!(background(6, y), guide);
+            assert_eq!(background(4, y), None);
+            assert_eq!(background(7, y), None);
+        }
+        // 短行的參考線畫在行尾之後的空白上，換行後的視覺行與 ~ 行沒有參考線
+        assert_eq!(grid.cell(6, 0).unwrap().ch, ' ');
+        assert_eq!(background(3, 2), None);
+        assert_eq!(background(3, 4), None);
+    }
+
+    #[test]
+    fn test_wrap_gutter_modes() {
+        let mut buffer = RopeBuffer::new();
+        buffer.insert(0, "abcdefghijklmnopqrst\nx\nabcdefghijklm\n");
+        let gutter_with = |buffer: &RopeBuffer, cursor: &Cursor, setup: &dyn Fn(&mut View)| {
+            let mut view = View::with_size(12, 8);
+            setup(&mut view);
+            let mut term = crate::backend::MemoryTerminal::new(12, 8);
+            #[cfg(feature = "syntax-highlighting")]
+            view.render(&mut term, buffer, cursor, None, None, None)
+                .unwrap();
+            #[cfg(not(feature = "syntax-highlighting"))]
+            view.render(&mut term, buffer, cursor, None, None).unwrap();
+            let grid = view.previous_frame.as_ref().unwrap();
+            (0..6)
+                .map(|y| {
+                    (0..2)
+                        .map(|x| grid.cell(x, y).unwrap().ch)
+                        .collect::<String>()
+                })
+                .collect::<Vec<_>>()
+        };
+        let gutter = |wrap_gutter: WrapGutter| {
+            gutter_with(&buffer, &Cursor::new(), &|view: &mut View| {
+                view.set_wrap_gutter(wrap_gutter)
+            })
+        };
+        // 可用寬度 9（12 欄 - 行號 2 欄 - 1）：第 1 行佔 3 個視覺行，第 3 行佔 2 個
+        assert_eq!(
+            gutter(WrapGutter::Blank),
+            ["1 ", "  ", "  ", "2 ", "3 ", "  "]
+        );
+        assert_eq!(
+            gutter(WrapGutter::Dot),
+            ["1 ", "· ", "· ", "2 ", "3 ", "· "]
+        );
+        // 顯示視覺行號時行號欄多一位（可用寬度 8）：第 1 行佔 3 個視覺行
+        assert_eq!(
+            gutter(WrapGutter::Number),
+            [" 1", " 2", " 3", " 2", " 3", " 6"]
+        );
+
+        // 視覺行號也依起始行號與進位顯示
+        let zero_based = gutter_with(&buffer, &Cursor::new(), &|view: &mut View| {
+            view.set_wrap_gutter(WrapGutter::Number);
+            view.line_number_start = 0;
+        });
+        assert_eq!(zero_based, [" 0", " 1", " 2", " 1", " 2", " 5"]);
+        let mut long = RopeBuffer::new();
+        long.insert(0, &format!("{}abcdefghijklm\n", "x\n".repeat(9)));
+        let mut cursor = Cursor::new();
+        cursor.row = 8;
+        let hex = gutter_with(&long, &cursor, &|view: &mut View| {
+            view.set_wrap_gutter(WrapGutter::Number);
+            view.hex_line_numbers = true;
+            view.offset_row = 8;
+        });
+        assert_eq!(&hex[..3], [" 9", " a", " b"]);
+    }
+
+    #[test]
+    fn test_zero_width_characters_in_layout() {
+        // 組合符號不佔欄位，與前一個字元位於同一欄
+        let accent = expand_tabs_and_build_map("e\u{301}x", false);
+        assert_eq!(accent.displayed, "e\u{301}x");
+        assert_eq!(accent.logical_to_visual, vec![0, 1, 1, 2]);
+        assert_eq!(logical_col_at(&accent.logical_to_visual, 1), 2);
+        assert_eq!(logical_col_at(&accent.logical_to_visual, 0), 0);
+
+        // 行首沒有基底字元的組合符號畫在 ◌ 上
+        let orphan = expand_tabs_and_build_map("\u{301}ab", false);
+        assert_eq!(orphan.displayed, "\u{25CC}\u{301}ab");
+        assert_eq!(orphan.logical_to_visual, vec![0, 1, 2, 3]);
+        assert_eq!(orphan.invisibles, vec![(0, 1, Invisible::Whitespace)]);
+
+        // 換行時零寬字元跟著前一個字元，不會成為下一個視覺行的開頭
+        assert_eq!(wrap_line("abc\u{301}d", 3), vec!["abc\u{301}", "d"]);
+        assert_eq!(wrap_line("ab中\u{200D}x", 3), vec!["ab", "中\u{200D}x"]);
+
+        // 有無快取的換算結果一致
+        let mut buffer = RopeBuffer::new();
+        buffer.insert(0, "e\u{301}xy\n\u{301}z\n");
+        let mut view = View::with_size(20, 6);
+        assert_eq!(view.logical_col_to_visual_col("e\u{301}xy", 2), 1);
+        assert_eq!(view.visual_to_logical_col(&buffer, 0, 0, 1), 2);
+        assert_eq!(view.visual_to_logical_col(&buffer, 1, 0, 1), 1);
+
+        let cursor = Cursor::new();
+        let mut term = crate::backend::MemoryTerminal::new(20, 6);
+        #[cfg(feature = "syntax-highlighting")]
+        view.render(&mut term, &buffer, &cursor, None, None, None)
+            .unwrap();
+        #[cfg(not(feature = "syntax-highlighting"))]
+        view.render(&mut term, &buffer, &cursor, None, None)
+            .unwrap();
+        assert_eq!(view.visual_to_logical_col(&buffer, 0, 0, 1), 2);
+        assert_eq!(view.visual_to_logical_col(&buffer, 1, 0, 1), 1);
+
+        // 組合符號疊在文字上，不會疊到行號欄
+        let grid = view.previous_frame.as_ref().unwrap();
+        let text_x = view.calculate_line_number_width(&buffer);
+        for x in 0..text_x {
+            assert!(grid.cell(x, 1).unwrap().combining.is_empty());
+        }
+        let base = grid.cell(text_x, 1).unwrap();
+        assert_eq!(
+            (base.ch, base.combining.as_slice()),
+            ('\u{25CC}', &['\u{301}'][..])
+        );
+        let e = grid.cell(text_x, 0).unwrap();
+        assert_eq!((e.ch, e.combining.as_slice()), ('e', &['\u{301}'][..]));
+    }
+
+    #[test]
+    fn test_wide_character_at_wrap_column() {
+        // 可用寬度 9（12 欄 - 行號 2 欄 - 1）：「中」放不下第一個視覺行的最後一欄，換到下一行
+        let mut buffer = RopeBuffer::new();
+        buffer.insert(0, "abcdefgh中xy\nabcdefghij\n");
+        let mut view = View::with_size(12, 6);
+        let text_x = view.calculate_line_number_width(&buffer);
+        assert_eq!(
+            view.calculate_visual_lines_for_row(&buffer, 0),
+            vec!["abcdefgh", "中xy"]
+        );
+
+        let render = |view: &mut View, cursor: &Cursor, selection: Option<&Selection>| {
+            let mut term = crate::backend::MemoryTerminal::new(12, 6);
+            #[cfg(feature = "syntax-highlighting")]
+            view.render(&mut term, &buffer, cursor, selection, None, None)
+                .unwrap();
+            #[cfg(not(feature = "syntax-highlighting"))]
+            view.render(&mut term, &buffer, cursor, selection, None)
+                .unwrap();
+        };
+
+        // 光標在「中」上：畫在第二個視覺行的開頭
+        let mut cursor = Cursor::new();
+        cursor.set_position(&buffer, &view, 0, 8);
+        assert_eq!(cursor.visual_line_index, 1);
+        assert_eq!(
+            view.get_cursor_visual_position(&cursor, &buffer),
+            (text_x, 1)
+        );
+
+        // 從下一行往上移到第 8 欄（第一個視覺行換行留下的空格）：停在同一視覺行的最後一個字元
+        for cached in [false, true] {
+            if cached {
+                render(&mut view, &cursor, None);
+            }
+            assert_eq!(view.visual_to_logical_col(&buffer, 0, 0, 8), 7);
+            assert_eq!(view.visual_to_logical_col(&buffer, 0, 0, 20), 7);
+            assert_eq!(view.visual_to_logical_col(&buffer, 0, 1, 0), 8);
+            assert_eq!(view.visual_to_logical_col(&buffer, 0, 1, 1), 8);
+            assert_eq!(view.visual_to_logical_col(&buffer, 0, 1, 2), 9);
+            assert_eq!(view.visual_to_logical_col(&buffer, 0, 1, 9), 11);
+        }
+        cursor.set_position(&buffer, &view, 1, 8);
+        cursor.move_up(&buffer, &view);
+        cursor.move_up(&buffer, &view);
+        assert_eq!(
+            (cursor.row, cursor.col, cursor.visual_line_index),
+            (0, 7, 0)
+        );
+        assert_eq!(
+            view.get_cursor_visual_position(&cursor, &buffer),
+            (text_x + 7, 0)
+        );
+
+        // 選擇 g、h、中：兩個視覺行上標示的格子與字元一致，換行留下的空格不標示
+        let selection = Selection {
+            start: (0, 6),
+            end: (0, 9),
+        };
+        render(&mut view, &cursor, Some(&selection));
+        let grid = view.previous_frame.as_ref().unwrap();
+        let selected =
+            |x: usize, y: usize| grid.cell(text_x + x, y).unwrap().style == view.theme.selection;
+        let row0: Vec<bool> = (0..9).map(|x| selected(x, 0)).collect();
+        assert_eq!(
+            row0,
+            [false, false, false, false, false, false, true, true, false]
+        );
+        let row1: Vec<bool> = (0..4).map(|x| selected(x, 1)).collect();
+        assert_eq!(row1, [true, true, false, false]);
+        assert_eq!(grid.cell(text_x, 1).unwrap().ch, '中');
+    }
+
+    #[test]
+    fn test_invisibles_placeholders_and_mapping() {
+        // 控制字元一律以替代符號顯示，不會原樣輸出到終端
+        let hidden = expand_tabs_and_build_map("a\tb \x1b\u{85}\u{200b}", false);
+        assert_eq!(hidden.displayed, "a    b ^[<U+0085>\u{200b}");
+        assert_eq!(hidden.logical_to_visual, vec![0, 1, 5, 6, 7, 9, 17, 17]);
+        assert_eq!(
+            hidden.invisibles,
+            vec![(7, 9, Invisible::Control), (9, 17, Invisible::Control)]
+        );
+
+        let shown = expand_tabs_and_build_map("a\tb \x7f\u{200b}", true);
+        assert_eq!(shown.displayed, "a→   b·^?<U+200B>");
+        assert_eq!(shown.logical_to_visual, vec![0, 1, 5, 6, 7, 9, 17]);
+        assert_eq!(
+            shown.invisibles,
+            vec![
+                (1, 5, Invisible::Whitespace),
+                (6, 7, Invisible::Whitespace),
+                (7, 9, Invisible::Control),
+                (9, 17, Invisible::Control),
+            ]
+        );
+
+        let mut view = View::with_size(40, 10);
+        view.set_show_invisibles(true);
+        assert_eq!(view.logical_col_to_visual_col("a\tb \x7f\u{200b}", 5), 9);
+        let styles = view.line_styles(
+            0,
+            &LineLayout {
+                visual_lines: vec![shown.displayed],
+                visual_height: 1,
+                logical_to_visual: shown.logical_to_visual,
+                invisibles: shown.invisibles,
+            },
+            None,
+            None,
+        );
+        assert_eq!(styles[0].foreground, None);
+        assert_eq!(styles[2].foreground, Some(Color::DarkGrey));
+        assert_eq!(styles[8].foreground, Some(Color::Red));
+    }
+}
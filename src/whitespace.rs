@@ -0,0 +1,209 @@
+// 行尾與空白檢查
+//
+// 狀態列提示檔案缺少最後的換行、混用 LF 與 CRLF，或有行尾空白；
+// `:fix` 一次修正整個緩衝區（只算一個復原步驟）
+
+use anyhow::{bail, Result};
+
+/// 可以修正的問題
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fix {
+    /// 檔尾補上換行
+    FinalNewline,
+    /// 統一為較常用的換行符號
+    LineEndings,
+    /// 刪除行尾的空白與 Tab
+    TrailingWhitespace,
+}
+
+impl std::str::FromStr for Fix {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "eol" | "newline" => Ok(Fix::FinalNewline),
+            "endings" | "lineendings" | "crlf" => Ok(Fix::LineEndings),
+            "trailing" | "whitespace" | "ws" => Ok(Fix::TrailingWhitespace),
+            _ => bail!("Unknown fix: {} (use eol, endings or trailing)", s),
+        }
+    }
+}
+
+/// 緩衝區內容的檢查結果
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Issues {
+    pub missing_final_newline: bool,
+    pub lf_lines: usize,
+    pub crlf_lines: usize,
+    pub trailing_whitespace_lines: usize,
+}
+
+impl Issues {
+    /// 同時有 LF 與 CRLF 結尾的行
+    pub fn mixed_line_endings(&self) -> bool {
+        self.lf_lines > 0 && self.crlf_lines > 0
+    }
+
+    /// 較常用的換行符號（相同時取 LF）
+    pub fn line_ending(&self) -> &'static str {
+        if self.crlf_lines > self.lf_lines {
+            "\r\n"
+        } else {
+            "\n"
+        }
+    }
+
+    pub fn has(&self, fix: Fix) -> bool {
+        match fix {
+            Fix::FinalNewline => self.missing_final_newline,
+            Fix::LineEndings => self.mixed_line_endings(),
+            Fix::TrailingWhitespace => self.trailing_whitespace_lines > 0,
+        }
+    }
+
+    /// 狀態列提示，例如 `[noeol] [mixed EOL] [trailing ws: 3]`；沒有問題時返回 None
+    pub fn indicator(&self) -> Option<String> {
+        let mut parts = Vec::new();
+        if self.missing_final_newline {
+            parts.push("[noeol]".to_string());
+        }
+        if self.mixed_line_endings() {
+            parts.push("[mixed EOL]".to_string());
+        }
+        if self.trailing_whitespace_lines > 0 {
+            parts.push(format!("[trailing ws: {}]", self.trailing_whitespace_lines));
+        }
+        (!parts.is_empty()).then(|| parts.join(" "))
+    }
+}
+
+/// 檢查換行與行尾空白
+pub fn check(text: &str) -> Issues {
+    let mut issues = Issues {
+        missing_final_newline: !text.is_empty() && !text.ends_with('\n'),
+        ..Issues::default()
+    };
+    for line in text.split_inclusive('\n') {
+        let content = match line.strip_suffix('\n') {
+            Some(content) => match content.strip_suffix('\r') {
+                Some(content) => {
+                    issues.crlf_lines += 1;
+                    content
+                }
+                None => {
+                    issues.lf_lines += 1;
+                    content
+                }
+            },
+            None => line,
+        };
+        if content.ends_with([' ', '\t']) {
+            issues.trailing_whitespace_lines += 1;
+        }
+    }
+    issues
+}
+
+/// 修正指定的問題，返回修正後的內容
+pub fn apply(text: &str, fixes: &[Fix]) -> String {
+    let issues = check(text);
+    let eol = issues.line_ending();
+    let mut result = String::with_capacity(text.len() + eol.len());
+    for line in text.split_inclusive('\n') {
+        let (content, ending) = match line.strip_suffix('\n') {
+            Some(content) => match content.strip_suffix('\r') {
+                Some(content) => (content, "\r\n"),
+                None => (content, "\n"),
+            },
+            None => (line, ""),
+        };
+        if fixes.contains(&Fix::TrailingWhitespace) {
+            result.push_str(content.trim_end_matches([' ', '\t']));
+        } else {
+            result.push_str(content);
+        }
+        if ending.is_empty() {
+            continue;
+        }
+        result.push_str(if fixes.contains(&Fix::LineEndings) {
+            eol
+        } else {
+            ending
+        });
+    }
+    if fixes.contains(&Fix::FinalNewline) && issues.missing_final_newline {
+        result.push_str(eol);
+    }
+    result
+}
+
+/// 新舊內容不同的範圍（以字元計）：返回 (開始, 舊內容的結束, 新內容中取代的部分)
+pub fn changed_range<'a>(old: &str, new: &'a str) -> (usize, usize, &'a str) {
+    let prefix: usize = old
+        .chars()
+        .zip(new.chars())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let old_rest: Vec<char> = old.chars().skip(prefix).collect();
+    let new_start = new.char_indices().nth(prefix).map_or(new.len(), |(i, _)| i);
+    let new_rest = &new[new_start..];
+    let suffix = old_rest
+        .iter()
+        .rev()
+        .zip(new_rest.chars().rev())
+        .take_while(|(a, b)| **a == *b)
+        .count();
+    let new_end = new_rest
+        .char_indices()
+        .nth(new_rest.chars().count() - suffix)
+        .map_or(new_rest.len(), |(i, _)| i);
+    (
+        prefix,
+        prefix + old_rest.len() - suffix,
+        &new_rest[..new_end],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_and_indicator() {
+        let issues = check("a \nb\r\nc\r\nd\t");
+        assert!(issues.missing_final_newline);
+        assert_eq!((issues.lf_lines, issues.crlf_lines), (1, 2));
+        assert!(issues.mixed_line_endings());
+        assert_eq!(issues.line_ending(), "\r\n");
+        assert_eq!(issues.trailing_whitespace_lines, 2);
+        assert_eq!(
+            issues.indicator().as_deref(),
+            Some("[noeol] [mixed EOL] [trailing ws: 2]")
+        );
+
+        assert_eq!(check("clean\ntext\n").indicator(), None);
+        assert_eq!(check("").indicator(), None);
+    }
+
+    #[test]
+    fn test_apply_fixes() {
+        let text = "a \nb\r\nc\r\nd\t";
+        assert_eq!(apply(text, &[Fix::TrailingWhitespace]), "a\nb\r\nc\r\nd");
+        assert_eq!(apply(text, &[Fix::LineEndings]), "a \r\nb\r\nc\r\nd\t");
+        assert_eq!(apply(text, &[Fix::FinalNewline]), "a \nb\r\nc\r\nd\t\r\n");
+        let all = [Fix::FinalNewline, Fix::LineEndings, Fix::TrailingWhitespace];
+        let fixed = apply(text, &all);
+        assert_eq!(fixed, "a\r\nb\r\nc\r\nd\r\n");
+        assert_eq!(check(&fixed).indicator(), None);
+        assert_eq!("ws".parse::<Fix>().unwrap(), Fix::TrailingWhitespace);
+        assert!("tabs".parse::<Fix>().is_err());
+    }
+
+    #[test]
+    fn test_changed_range() {
+        assert_eq!(changed_range("中a  \nb\n", "中a\nb\n"), (2, 4, ""));
+        assert_eq!(changed_range("x\ny", "x\ny\n"), (3, 3, "\n"));
+        assert_eq!(changed_range("a\nb\n", "a\r\nb\r\n"), (1, 3, "\r\nb\r"));
+        assert_eq!(changed_range("same", "same"), (4, 4, ""));
+    }
+}